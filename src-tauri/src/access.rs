@@ -0,0 +1,129 @@
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use rusqlite::{params, Connection};
+
+use crate::errors::CommandError;
+use crate::types::AllowedLocation;
+use crate::util::{capture_docx_path, now_ms, path_display};
+use crate::CommandResult;
+
+/// Folders commands may read from or write into: every registered root plus
+/// the locations the user allow-listed.
+fn permitted_locations(connection: &Connection) -> CommandResult<Vec<PathBuf>> {
+    let mut statement = connection
+        .prepare_cached("SELECT path FROM roots UNION SELECT path FROM allowed_locations")
+        .map_err(|error| {
+            CommandError::database(format!(
+                "Could not prepare permitted locations query: {error}"
+            ))
+        })?;
+    let rows = statement
+        .query_map([], |row| row.get::<_, String>(0))
+        .map_err(|error| {
+            CommandError::database(format!("Could not run permitted locations query: {error}"))
+        })?;
+    let mut locations = Vec::new();
+    for row in rows {
+        locations.push(PathBuf::from(row.map_err(|error| {
+            CommandError::database(format!("Could not parse permitted location row: {error}"))
+        })?));
+    }
+    Ok(locations)
+}
+
+/// Canonicalizes `path` through its deepest existing ancestor, so a target
+/// that is about to be created resolves too. Symlinks in the existing part
+/// are followed; a missing part may only hold plain names.
+fn resolve(path: &Path) -> Option<PathBuf> {
+    let mut existing = path;
+    let mut missing = Vec::<OsString>::new();
+    loop {
+        if let Ok(canonical) = fs::canonicalize(existing) {
+            let mut resolved = canonical;
+            for name in missing.iter().rev() {
+                resolved.push(name);
+            }
+            return Some(resolved);
+        }
+        missing.push(existing.file_name()?.to_os_string());
+        existing = existing.parent()?;
+    }
+}
+
+/// Resolves `path` and rejects it unless it lies inside a registered root or
+/// an allow-listed location. Callers should use the returned path, not the
+/// one they passed in, so a symlink swapped in afterwards cannot redirect it.
+pub(crate) fn guard_path(connection: &Connection, path: &Path) -> CommandResult<PathBuf> {
+    let resolved = resolve(path).ok_or_else(|| CommandError::access_denied(path))?;
+    let permitted = permitted_locations(connection)?
+        .iter()
+        .any(|location| resolved.starts_with(location));
+    if !permitted {
+        return Err(CommandError::access_denied(path));
+    }
+    Ok(resolved)
+}
+
+/// The absolute path of a capture target, checked with `guard_path`.
+pub(crate) fn guarded_capture_path(
+    connection: &Connection,
+    root: &Path,
+    target_relative_path: &str,
+) -> CommandResult<PathBuf> {
+    guard_path(connection, &capture_docx_path(root, target_relative_path))
+}
+
+pub(crate) fn load_allowed_locations(
+    connection: &Connection,
+) -> CommandResult<Vec<AllowedLocation>> {
+    let mut statement = connection
+        .prepare("SELECT path, added_at_ms FROM allowed_locations ORDER BY path")
+        .map_err(|error| {
+            CommandError::database(format!(
+                "Could not prepare allowed locations query: {error}"
+            ))
+        })?;
+    let rows = statement
+        .query_map([], |row| {
+            Ok(AllowedLocation {
+                path: row.get(0)?,
+                added_at_ms: row.get(1)?,
+            })
+        })
+        .map_err(|error| {
+            CommandError::database(format!("Could not run allowed locations query: {error}"))
+        })?;
+    let mut locations = Vec::new();
+    for row in rows {
+        locations.push(row.map_err(|error| {
+            CommandError::database(format!("Could not parse allowed location row: {error}"))
+        })?);
+    }
+    Ok(locations)
+}
+
+pub(crate) fn store_allowed_location(connection: &Connection, folder: &Path) -> CommandResult<()> {
+    connection
+        .execute(
+            "INSERT INTO allowed_locations(path, added_at_ms) VALUES(?1, ?2) ON CONFLICT(path) DO NOTHING",
+            params![path_display(folder), now_ms()],
+        )
+        .map_err(|error| {
+            CommandError::database(format!("Could not store allowed location: {error}"))
+        })?;
+    Ok(())
+}
+
+pub(crate) fn delete_allowed_location(connection: &Connection, path: &str) -> CommandResult<()> {
+    connection
+        .execute(
+            "DELETE FROM allowed_locations WHERE path = ?1",
+            params![path],
+        )
+        .map_err(|error| {
+            CommandError::database(format!("Could not remove allowed location: {error}"))
+        })?;
+    Ok(())
+}
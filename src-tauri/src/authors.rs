@@ -0,0 +1,405 @@
+use std::path::PathBuf;
+
+use rayon::prelude::*;
+use rusqlite::{params, Connection, OptionalExtension};
+
+use crate::docx_parse::parse_docx_paragraphs;
+use crate::errors::CommandError;
+use crate::operations::OperationHandle;
+use crate::search::normalize_for_search;
+use crate::shards::{index_schemas, schema_for_file};
+use crate::text_cache::load_file_paragraphs;
+use crate::types::{AuthorEntry, IndexSettings};
+use crate::util::{
+    author_candidates_from_text, extract_author_candidates, file_name_from_relative,
+    first_year_token, now_ms,
+};
+use crate::CommandResult;
+
+/// Files handled per batch in the author phase, so cancellation is noticed
+/// between batches.
+const AUTHOR_PHASE_BATCH_SIZE: usize = 64;
+
+/// Characters that end the names at the start of an author line: the
+/// bracketed qualifications, the comma before them, or a quoted title.
+/// Apostrophes are left alone for names like O'Brien; a `'19` year stops at
+/// its digits.
+const NAME_PART_TERMINATORS: [char; 8] = ['[', '(', '{', ',', ':', ';', '"', '“'];
+
+/// A name longer than this is the start of a sentence, not an author.
+const MAX_NAME_WORDS: usize = 4;
+
+/// Authors returned by one browse or autocomplete query.
+const AUTHOR_LIST_LIMIT: i64 = 200;
+
+/// The structured part of an author line. `surnames` joins co-authors with
+/// ` & ` in the order written and is empty when the line does not open with
+/// names.
+pub(crate) struct AuthorName {
+    pub surnames: String,
+    pub year: Option<i64>,
+}
+
+/// Reads the surnames and year from a line such as `Smith and Jones 19
+/// [John Smith, ..., 2019]`: names run up to the first digit or bracket,
+/// `and`, `&` and `/` separate co-authors and `et al.` ends the list.
+pub(crate) fn parse_author_line(text: &str) -> AuthorName {
+    let year = first_year_token(text);
+    let end = text
+        .find(|character: char| {
+            character.is_ascii_digit() || NAME_PART_TERMINATORS.contains(&character)
+        })
+        .unwrap_or(text.len());
+    let name_part = text[..end].replace('/', " / ");
+
+    let mut surnames = Vec::new();
+    let mut name_words = Vec::new();
+    let mut words = name_part.split_whitespace().peekable();
+    loop {
+        let word = words.next();
+        let ends_name = match word {
+            None => true,
+            Some(word) => {
+                let lower = word.to_lowercase();
+                let et_al = lower == "et"
+                    && words
+                        .peek()
+                        .is_some_and(|next| next.to_lowercase().starts_with("al"));
+                if et_al || matches!(lower.as_str(), "and" | "&" | "/") {
+                    true
+                } else {
+                    name_words.push(word);
+                    false
+                }
+            }
+        };
+        if !ends_name {
+            continue;
+        }
+        if name_words.len() > MAX_NAME_WORDS {
+            surnames.clear();
+            break;
+        }
+        if let Some(surname) = name_words
+            .last()
+            .map(|word| word.trim_matches(|character: char| !character.is_alphanumeric()))
+            .filter(|surname| surname.chars().next().is_some_and(char::is_uppercase))
+        {
+            surnames.push(surname.to_string());
+        }
+        name_words.clear();
+        if word.is_none_or(|word| word.eq_ignore_ascii_case("et")) {
+            break;
+        }
+    }
+
+    AuthorName {
+        surnames: surnames.join(" & "),
+        year,
+    }
+}
+
+pub(crate) fn load_index_settings(connection: &Connection) -> CommandResult<IndexSettings> {
+    let extract_authors = connection
+        .query_row(
+            "SELECT extract_authors FROM index_settings WHERE id = 1",
+            [],
+            |row| row.get::<_, i64>(0),
+        )
+        .optional()
+        .map_err(|error| CommandError::database(format!("Could not load index settings: {error}")))?
+        .map(|value| value != 0)
+        .unwrap_or(true);
+    Ok(IndexSettings { extract_authors })
+}
+
+pub(crate) fn store_index_settings(
+    connection: &Connection,
+    settings: &IndexSettings,
+) -> CommandResult<()> {
+    connection
+        .execute(
+            "INSERT INTO index_settings(id, extract_authors, updated_at_ms) VALUES(1, ?1, ?2)
+             ON CONFLICT(id) DO UPDATE SET
+               extract_authors = excluded.extract_authors,
+               updated_at_ms = excluded.updated_at_ms",
+            params![i64::from(settings.extract_authors), now_ms()],
+        )
+        .map_err(|error| {
+            CommandError::database(format!("Could not save index settings: {error}"))
+        })?;
+    Ok(())
+}
+
+/// Drops every stored author row and marks all files as not yet extracted,
+/// for when author search is switched off.
+pub(crate) fn clear_all_authors(connection: &Connection) -> CommandResult<()> {
+    for schema in index_schemas(connection)? {
+        connection
+            .execute_batch(&format!(
+                "DELETE FROM {schema}.authors; UPDATE {schema}.files SET authors_hash = '';"
+            ))
+            .map_err(|error| {
+                CommandError::database(format!("Could not clear author rows: {error}"))
+            })?;
+    }
+    Ok(())
+}
+
+/// Replaces a file's author rows and records the file hash they were taken
+/// from. An empty `authors_hash` leaves the file pending for the author phase.
+pub(crate) fn replace_file_authors(
+    connection: &Connection,
+    file_id: i64,
+    relative_path: &str,
+    authors: &[(i64, String)],
+    authors_hash: &str,
+) -> CommandResult<()> {
+    let schema = schema_for_file(file_id);
+    connection
+        .execute(
+            &format!("DELETE FROM {schema}.authors WHERE file_id = ?1"),
+            params![file_id],
+        )
+        .map_err(|error| {
+            CommandError::database(format!(
+                "Could not clear old author rows for '{relative_path}': {error}"
+            ))
+        })?;
+
+    let file_name = file_name_from_relative(relative_path);
+    let mut insert_author = connection
+        .prepare_cached(&format!(
+            "INSERT INTO {schema}.authors(file_id, author_order, text, normalized, file_name, relative_path, surnames, year)
+             VALUES(?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)"
+        ))
+        .map_err(|error| {
+            CommandError::database(format!("Could not prepare author insert: {error}"))
+        })?;
+    for (author_order, author_text) in authors {
+        let name = parse_author_line(author_text);
+        insert_author
+            .execute(params![
+                file_id,
+                author_order,
+                author_text,
+                normalize_for_search(author_text),
+                file_name.as_str(),
+                relative_path,
+                name.surnames,
+                name.year
+            ])
+            .map_err(|error| {
+                CommandError::database(format!(
+                    "Could not insert author metadata for '{relative_path}': {error}"
+                ))
+            })?;
+    }
+
+    connection
+        .execute(
+            &format!("UPDATE {schema}.files SET authors_hash = ?1 WHERE id = ?2"),
+            params![authors_hash, file_id],
+        )
+        .map_err(|error| {
+            CommandError::database(format!(
+                "Could not record author extraction for '{relative_path}': {error}"
+            ))
+        })?;
+    Ok(())
+}
+
+struct PendingAuthorFile {
+    id: i64,
+    relative_path: String,
+    absolute_path: PathBuf,
+    file_hash: String,
+}
+
+/// Extracts authors for every file in the root whose authors were taken from
+/// an older version of the file (or never taken). Text comes from the
+/// compressed text cache when it is current; other files are re-parsed.
+/// Author rows stored before names were parsed get their surnames and year
+/// here too. Returns how many files were brought up to date.
+pub(crate) fn run_author_phase(
+    connection: &Connection,
+    root_id: i64,
+    operation: &OperationHandle,
+) -> CommandResult<usize> {
+    let mut statement = connection
+        .prepare(
+            "SELECT id, relative_path, absolute_path, file_hash FROM files
+             WHERE root_id = ?1 AND authors_hash <> file_hash
+             ORDER BY relative_path",
+        )
+        .map_err(|error| {
+            CommandError::database(format!("Could not prepare pending author query: {error}"))
+        })?;
+    let rows = statement
+        .query_map(params![root_id], |row| {
+            Ok(PendingAuthorFile {
+                id: row.get(0)?,
+                relative_path: row.get(1)?,
+                absolute_path: PathBuf::from(row.get::<_, String>(2)?),
+                file_hash: row.get(3)?,
+            })
+        })
+        .map_err(|error| {
+            CommandError::database(format!("Could not run pending author query: {error}"))
+        })?;
+    let mut pending = Vec::new();
+    for row in rows {
+        pending.push(row.map_err(|error| {
+            CommandError::database(format!("Could not parse pending author row: {error}"))
+        })?);
+    }
+
+    for batch in pending.chunks(AUTHOR_PHASE_BATCH_SIZE) {
+        operation.ensure_not_cancelled()?;
+        let mut cached_texts = Vec::with_capacity(batch.len());
+        for file in batch {
+            cached_texts.push(load_file_paragraphs(connection, file.id)?);
+        }
+
+        let extracted =
+            batch
+                .par_iter()
+                .zip(cached_texts.into_par_iter())
+                .map(|(file, cached)| match cached {
+                    Some(texts) => author_candidates_from_text(texts.iter().enumerate().map(
+                        |(index, text)| (i64::try_from(index).unwrap_or(0) + 1, text.as_str()),
+                    )),
+                    None => parse_docx_paragraphs(&file.absolute_path)
+                        .map(|paragraphs| extract_author_candidates(&paragraphs))
+                        .unwrap_or_default(),
+                })
+                .collect::<Vec<Vec<(i64, String)>>>();
+
+        for (file, authors) in batch.iter().zip(extracted) {
+            replace_file_authors(
+                connection,
+                file.id,
+                &file.relative_path,
+                &authors,
+                &file.file_hash,
+            )?;
+        }
+    }
+
+    operation.ensure_not_cancelled()?;
+    parse_pending_author_names(connection, root_id)?;
+    Ok(pending.len())
+}
+
+/// Fills in surnames and year for the root's author rows that predate them.
+fn parse_pending_author_names(connection: &Connection, root_id: i64) -> CommandResult<()> {
+    let mut statement = connection
+        .prepare(
+            "SELECT a.id, a.file_id, a.text FROM authors a
+             JOIN files f ON f.id = a.file_id
+             WHERE f.root_id = ?1 AND a.surnames IS NULL",
+        )
+        .map_err(|error| {
+            CommandError::database(format!("Could not prepare author name query: {error}"))
+        })?;
+    let rows = statement
+        .query_map(params![root_id], |row| {
+            Ok((
+                row.get::<_, i64>(0)?,
+                row.get::<_, i64>(1)?,
+                row.get::<_, String>(2)?,
+            ))
+        })
+        .map_err(|error| {
+            CommandError::database(format!("Could not run author name query: {error}"))
+        })?;
+    let mut pending = Vec::new();
+    for row in rows {
+        pending.push(row.map_err(|error| {
+            CommandError::database(format!("Could not parse author name row: {error}"))
+        })?);
+    }
+
+    for (author_id, file_id, text) in pending {
+        let name = parse_author_line(&text);
+        connection
+            .prepare_cached(&format!(
+                "UPDATE {}.authors SET surnames = ?1, year = ?2 WHERE id = ?3",
+                schema_for_file(file_id)
+            ))
+            .and_then(|mut statement| {
+                statement.execute(params![name.surnames, name.year, author_id])
+            })
+            .map_err(|error| {
+                CommandError::database(format!("Could not store author name: {error}"))
+            })?;
+    }
+    Ok(())
+}
+
+/// Escapes `%`, `_` and `\` for a `LIKE ... ESCAPE '\'` pattern.
+fn like_literal(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_")
+}
+
+/// Distinct authors in a root by card count, for browsing and autocomplete.
+/// Authors are grouped by their surnames regardless of case; `prefix` matches
+/// the start of any co-author's surname.
+pub(crate) fn list_root_authors(
+    connection: &Connection,
+    root_id: i64,
+    prefix: &str,
+) -> CommandResult<Vec<AuthorEntry>> {
+    let prefix = like_literal(&prefix.trim().to_lowercase());
+    let mut statement = connection
+        .prepare_cached(
+            r"
+            SELECT
+              MIN(a.surnames),
+              COUNT(*),
+              COUNT(DISTINCT a.file_id),
+              MIN(a.year),
+              MAX(a.year)
+            FROM authors a
+            JOIN files f ON f.id = a.file_id
+            WHERE f.root_id = ?1
+              AND a.surnames <> ''
+              AND (lower(a.surnames) LIKE ?2 ESCAPE '\' OR lower(a.surnames) LIKE ?3 ESCAPE '\')
+            GROUP BY lower(a.surnames)
+            ORDER BY COUNT(*) DESC, lower(a.surnames)
+            LIMIT ?4
+            ",
+        )
+        .map_err(|error| {
+            CommandError::database(format!("Could not prepare author list query: {error}"))
+        })?;
+    let rows = statement
+        .query_map(
+            params![
+                root_id,
+                format!("{prefix}%"),
+                format!("% & {prefix}%"),
+                AUTHOR_LIST_LIMIT
+            ],
+            |row| {
+                Ok(AuthorEntry {
+                    surnames: row.get(0)?,
+                    card_count: row.get(1)?,
+                    file_count: row.get(2)?,
+                    first_year: row.get(3)?,
+                    last_year: row.get(4)?,
+                })
+            },
+        )
+        .map_err(|error| CommandError::database(format!("Could not list authors: {error}")))?;
+    let mut authors = Vec::new();
+    for row in rows {
+        authors.push(row.map_err(|error| {
+            CommandError::database(format!("Could not parse author list row: {error}"))
+        })?);
+    }
+    Ok(authors)
+}
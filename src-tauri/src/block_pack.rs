@@ -0,0 +1,566 @@
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{Read, Write};
+use std::path::{Component, Path, PathBuf};
+
+use rusqlite::{params, Connection};
+use zip::ZipArchive;
+
+use crate::access::guarded_capture_path;
+use crate::capture_writer::flush_capture_target;
+use crate::errors::CommandError;
+use crate::operations::OperationHandle;
+use crate::shared_roots::ensure_root_identity;
+use crate::tags::add_tag;
+use crate::types::{
+    BlockPackAuthor, BlockPackFile, BlockPackHeading, BlockPackManifest, BlockPackSelection,
+    BlockPackTag,
+};
+use crate::util::{
+    epoch_ms, fast_file_hash, file_name_from_relative, now_ms, path_display, relative_path,
+};
+use crate::validation::normalize_capture_target_path;
+use crate::CommandResult;
+
+pub(crate) const BLOCK_PACK_VERSION: i64 = 1;
+pub(crate) const BLOCK_PACK_EXTENSION: &str = "bfpack";
+const MANIFEST_ENTRY: &str = "manifest.json";
+const FILES_PREFIX: &str = "files/";
+
+/// A file going into a pack. Capture targets that were never indexed have no
+/// `file_id`, so they carry no headings or authors in the manifest.
+struct PackSource {
+    file_id: Option<i64>,
+    modified_ms: i64,
+    absolute_path: String,
+    file_hash: String,
+    tags: Vec<BlockPackTag>,
+}
+
+fn selected_file_rows(
+    connection: &Connection,
+    root_id: i64,
+    selection: &BlockPackSelection,
+) -> CommandResult<BTreeMap<String, PackSource>> {
+    let mut statement = connection
+        .prepare(
+            "SELECT id, relative_path, absolute_path, modified_ms, file_hash FROM files WHERE root_id = ?1",
+        )
+        .map_err(|error| {
+            CommandError::database(format!("Could not prepare block pack file query: {error}"))
+        })?;
+    let rows = statement
+        .query_map(params![root_id], |row| {
+            Ok((
+                row.get::<_, i64>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, String>(2)?,
+                row.get::<_, i64>(3)?,
+                row.get::<_, String>(4)?,
+            ))
+        })
+        .map_err(|error| {
+            CommandError::database(format!("Could not run block pack file query: {error}"))
+        })?;
+
+    let folder_prefixes = selection
+        .folder_paths
+        .iter()
+        .map(|folder| folder.trim_matches('/').to_string())
+        .collect::<Vec<String>>();
+
+    let mut selected = BTreeMap::new();
+    for row in rows {
+        let (file_id, relative_path, absolute_path, modified_ms, file_hash) =
+            row.map_err(|error| {
+                CommandError::database(format!("Could not parse block pack file row: {error}"))
+            })?;
+        let in_folder = folder_prefixes.iter().any(|folder| {
+            folder.is_empty()
+                || relative_path
+                    .strip_prefix(folder.as_str())
+                    .map(|rest| rest.starts_with('/'))
+                    .unwrap_or(false)
+        });
+        if in_folder || selection.file_ids.contains(&file_id) {
+            selected.insert(
+                relative_path,
+                PackSource {
+                    file_id: Some(file_id),
+                    modified_ms,
+                    absolute_path,
+                    file_hash,
+                    tags: Vec::new(),
+                },
+            );
+        }
+    }
+    Ok(selected)
+}
+
+fn file_headings(connection: &Connection, file_id: i64) -> CommandResult<Vec<BlockPackHeading>> {
+    let mut statement = connection
+        .prepare(
+            "SELECT heading_order, level, text FROM headings WHERE file_id = ?1 ORDER BY heading_order",
+        )
+        .map_err(|error| {
+            CommandError::database(format!("Could not prepare block pack heading query: {error}"))
+        })?;
+    let rows = statement
+        .query_map(params![file_id], |row| {
+            Ok(BlockPackHeading {
+                order: row.get(0)?,
+                level: row.get(1)?,
+                text: row.get(2)?,
+            })
+        })
+        .map_err(|error| {
+            CommandError::database(format!("Could not run block pack heading query: {error}"))
+        })?;
+    let mut headings = Vec::new();
+    for row in rows {
+        headings.push(row.map_err(|error| {
+            CommandError::database(format!("Could not parse block pack heading: {error}"))
+        })?);
+    }
+    Ok(headings)
+}
+
+fn file_authors(connection: &Connection, file_id: i64) -> CommandResult<Vec<BlockPackAuthor>> {
+    let mut statement = connection
+        .prepare("SELECT author_order, text FROM authors WHERE file_id = ?1 ORDER BY author_order")
+        .map_err(|error| {
+            CommandError::database(format!(
+                "Could not prepare block pack author query: {error}"
+            ))
+        })?;
+    let rows = statement
+        .query_map(params![file_id], |row| {
+            Ok(BlockPackAuthor {
+                order: row.get(0)?,
+                text: row.get(1)?,
+            })
+        })
+        .map_err(|error| {
+            CommandError::database(format!("Could not run block pack author query: {error}"))
+        })?;
+    let mut authors = Vec::new();
+    for row in rows {
+        authors.push(row.map_err(|error| {
+            CommandError::database(format!("Could not parse block pack author: {error}"))
+        })?);
+    }
+    Ok(authors)
+}
+
+/// Tags on a file and its headings. Tags are keyed by path rather than file
+/// id, so a capture target that was never indexed can still carry them.
+fn file_tags(
+    connection: &Connection,
+    root_id: i64,
+    relative_path: &str,
+) -> CommandResult<Vec<BlockPackTag>> {
+    let mut statement = connection
+        .prepare(
+            "SELECT heading_normalized, heading_text, tag FROM tags
+             WHERE root_id = ?1 AND relative_path = ?2
+             ORDER BY heading_normalized, tag COLLATE NOCASE",
+        )
+        .map_err(|error| {
+            CommandError::database(format!("Could not prepare block pack tag query: {error}"))
+        })?;
+    let rows = statement
+        .query_map(params![root_id, relative_path], |row| {
+            Ok(BlockPackTag {
+                heading_normalized: row.get(0)?,
+                heading_text: row.get(1)?,
+                tag: row.get(2)?,
+            })
+        })
+        .map_err(|error| {
+            CommandError::database(format!("Could not run block pack tag query: {error}"))
+        })?;
+    let mut tags = Vec::new();
+    for row in rows {
+        tags.push(row.map_err(|error| {
+            CommandError::database(format!("Could not parse block pack tag: {error}"))
+        })?);
+    }
+    Ok(tags)
+}
+
+/// Adds a capture target to the selection, returning its path in the pack.
+fn select_capture_target(
+    connection: &Connection,
+    selected: &mut BTreeMap<String, PackSource>,
+    root_path: &str,
+    target_path: &str,
+) -> CommandResult<String> {
+    let target_relative_path = normalize_capture_target_path(Some(target_path))?;
+    if Path::new(&target_relative_path).is_absolute() {
+        return Err(CommandError::validation(
+            "Only capture targets inside the root can be packed.",
+        ));
+    }
+    let relative_path = target_relative_path.replace('\\', "/");
+    if selected.contains_key(&relative_path) {
+        return Ok(relative_path);
+    }
+
+    let absolute_path =
+        guarded_capture_path(connection, Path::new(root_path), &target_relative_path)?;
+    flush_capture_target(&absolute_path)?;
+    let metadata = fs::metadata(&absolute_path).map_err(|_| {
+        CommandError::io(format!(
+            "Capture target '{relative_path}' does not exist yet."
+        ))
+    })?;
+    let modified_ms = metadata
+        .modified()
+        .map(epoch_ms)
+        .unwrap_or_else(|_| now_ms());
+    selected.insert(
+        relative_path.clone(),
+        PackSource {
+            file_id: None,
+            modified_ms,
+            file_hash: fast_file_hash(&absolute_path)?,
+            absolute_path: path_display(&absolute_path),
+            tags: Vec::new(),
+        },
+    );
+    Ok(relative_path)
+}
+
+pub(crate) fn write_block_pack(
+    connection: &Connection,
+    root_id: i64,
+    root_path: &str,
+    selection: &BlockPackSelection,
+    output_path: &Path,
+    operation: &mut OperationHandle,
+) -> CommandResult<BlockPackManifest> {
+    let mut selected = selected_file_rows(connection, root_id, selection)?;
+    if selected.is_empty() {
+        return Err(CommandError::validation(
+            "Block pack selection does not match any indexed files.",
+        ));
+    }
+    let capture_target = selection
+        .capture_target
+        .as_deref()
+        .map(str::trim)
+        .filter(|target| !target.is_empty())
+        .map(|target| select_capture_target(connection, &mut selected, root_path, target))
+        .transpose()?;
+    let source_root_uid = ensure_root_identity(connection, root_id, Path::new(root_path))?;
+    for (relative_path, source) in &mut selected {
+        source.tags = file_tags(connection, root_id, relative_path)?;
+    }
+
+    if let Some(parent) = output_path.parent() {
+        fs::create_dir_all(parent).map_err(|error| {
+            CommandError::io(format!(
+                "Could not create block pack folder '{}': {error}",
+                path_display(parent)
+            ))
+        })?;
+    }
+    let output = File::create(output_path).map_err(|error| {
+        CommandError::io(format!(
+            "Could not create block pack '{}': {error}",
+            path_display(output_path)
+        ))
+    })?;
+    let result = write_pack_entries(
+        connection,
+        zip::ZipWriter::new(output),
+        root_path,
+        source_root_uid,
+        capture_target,
+        selected,
+        operation,
+    );
+    if result.is_err() {
+        let _ = fs::remove_file(output_path);
+    }
+    result
+}
+
+fn write_pack_entries(
+    connection: &Connection,
+    mut writer: zip::ZipWriter<File>,
+    root_path: &str,
+    source_root_uid: String,
+    capture_target: Option<String>,
+    selected: BTreeMap<String, PackSource>,
+    operation: &mut OperationHandle,
+) -> CommandResult<BlockPackManifest> {
+    let options = zip::write::SimpleFileOptions::default()
+        .compression_method(zip::CompressionMethod::Deflated);
+
+    let total = selected.len();
+    let mut files = Vec::with_capacity(total);
+    for (index, (relative_path, source)) in selected.into_iter().enumerate() {
+        operation.ensure_not_cancelled()?;
+        operation.update("packing", index, Some(total), Some(relative_path.clone()));
+        let bytes = fs::read(&source.absolute_path).map_err(|error| {
+            CommandError::io(format!(
+                "Could not read '{}' for block pack: {error}",
+                source.absolute_path
+            ))
+        })?;
+        let entry_name = format!("{FILES_PREFIX}{relative_path}");
+        writer
+            .start_file(entry_name.as_str(), options)
+            .map_err(|error| {
+                CommandError::io(format!(
+                    "Could not add '{entry_name}' to block pack: {error}"
+                ))
+            })?;
+        writer.write_all(&bytes).map_err(|error| {
+            CommandError::io(format!(
+                "Could not write '{entry_name}' to block pack: {error}"
+            ))
+        })?;
+
+        let (headings, authors) = match source.file_id {
+            Some(file_id) => (
+                file_headings(connection, file_id)?,
+                file_authors(connection, file_id)?,
+            ),
+            None => (Vec::new(), Vec::new()),
+        };
+        files.push(BlockPackFile {
+            file_name: file_name_from_relative(&relative_path),
+            headings,
+            authors,
+            tags: source.tags,
+            relative_path,
+            modified_ms: source.modified_ms,
+            file_hash: source.file_hash,
+        });
+    }
+
+    let manifest = BlockPackManifest {
+        version: BLOCK_PACK_VERSION,
+        exported_at_ms: now_ms(),
+        source_root_path: root_path.to_string(),
+        source_root_uid: Some(source_root_uid),
+        capture_target,
+        files,
+    };
+    let manifest_raw = serde_json::to_vec_pretty(&manifest).map_err(|error| {
+        CommandError::parse(format!("Could not serialize block pack manifest: {error}"))
+    })?;
+    writer
+        .start_file(MANIFEST_ENTRY, options)
+        .map_err(|error| CommandError::io(format!("Could not add block pack manifest: {error}")))?;
+    writer.write_all(&manifest_raw).map_err(|error| {
+        CommandError::io(format!("Could not write block pack manifest: {error}"))
+    })?;
+    writer
+        .finish()
+        .map_err(|error| CommandError::io(format!("Could not finish block pack: {error}")))?;
+
+    Ok(manifest)
+}
+
+pub(crate) fn safe_relative_path(relative_path: &str) -> Option<PathBuf> {
+    let mut safe = PathBuf::new();
+    for component in Path::new(relative_path).components() {
+        match component {
+            Component::Normal(part) => safe.push(part),
+            Component::CurDir => {}
+            _ => return None,
+        }
+    }
+    (!safe.as_os_str().is_empty()).then_some(safe)
+}
+
+fn imported_copy_path(destination: &Path) -> PathBuf {
+    let stem = destination
+        .file_stem()
+        .map(|value| value.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let extension = destination
+        .extension()
+        .map(|value| value.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "docx".to_string());
+    let mut attempt = 1_u32;
+    loop {
+        let suffix = if attempt == 1 {
+            "imported".to_string()
+        } else {
+            format!("imported {attempt}")
+        };
+        let candidate = destination.with_file_name(format!("{stem} [{suffix}].{extension}"));
+        if !candidate.exists() {
+            return candidate;
+        }
+        attempt += 1;
+    }
+}
+
+pub(crate) fn read_block_pack_manifest(pack_path: &Path) -> CommandResult<BlockPackManifest> {
+    let file = File::open(pack_path).map_err(|error| {
+        CommandError::io(format!(
+            "Could not open '{}': {error}",
+            path_display(pack_path)
+        ))
+    })?;
+    let mut archive = ZipArchive::new(file).map_err(|error| {
+        CommandError::parse(format!(
+            "Could not read '{}': {error}",
+            path_display(pack_path)
+        ))
+    })?;
+    let mut raw = String::new();
+    archive
+        .by_name(MANIFEST_ENTRY)
+        .map_err(|error| {
+            CommandError::parse(format!("Block pack is missing its manifest: {error}"))
+        })?
+        .read_to_string(&mut raw)
+        .map_err(|error| {
+            CommandError::io(format!("Could not read block pack manifest: {error}"))
+        })?;
+    let manifest = serde_json::from_str::<BlockPackManifest>(&raw).map_err(|error| {
+        CommandError::parse(format!("Could not parse block pack manifest: {error}"))
+    })?;
+    if manifest.version > BLOCK_PACK_VERSION {
+        return Err(CommandError::parse(format!(
+            "Block pack version {} is newer than this app supports ({BLOCK_PACK_VERSION}).",
+            manifest.version
+        )));
+    }
+    Ok(manifest)
+}
+
+/// What extracting a pack did with each of its files.
+pub(crate) struct ExtractedBlockPack {
+    pub written_paths: Vec<String>,
+    pub skipped_paths: Vec<String>,
+    /// Where each manifest file now lives, relative to the root: the file
+    /// written, or the identical one already there. `None` for entries
+    /// with an unsafe path.
+    pub local_paths: Vec<Option<String>>,
+}
+
+/// Extracts pack files into `root`. Files already present with identical
+/// content are skipped; differing files are written alongside as
+/// "Name [imported].docx" so nothing local is overwritten.
+pub(crate) fn extract_block_pack(
+    pack_path: &Path,
+    root: &Path,
+    manifest: &BlockPackManifest,
+    operation: &mut OperationHandle,
+) -> CommandResult<ExtractedBlockPack> {
+    let file = File::open(pack_path).map_err(|error| {
+        CommandError::io(format!(
+            "Could not open '{}': {error}",
+            path_display(pack_path)
+        ))
+    })?;
+    let mut archive = ZipArchive::new(file).map_err(|error| {
+        CommandError::parse(format!(
+            "Could not read '{}': {error}",
+            path_display(pack_path)
+        ))
+    })?;
+
+    let mut written = Vec::new();
+    let mut skipped = Vec::new();
+    let mut local_paths = Vec::with_capacity(manifest.files.len());
+    let total = manifest.files.len();
+    for (index, pack_file) in manifest.files.iter().enumerate() {
+        operation.ensure_not_cancelled()?;
+        operation.update(
+            "extracting",
+            index,
+            Some(total),
+            Some(pack_file.relative_path.clone()),
+        );
+        let Some(safe_relative) = safe_relative_path(&pack_file.relative_path) else {
+            skipped.push(pack_file.relative_path.clone());
+            local_paths.push(None);
+            continue;
+        };
+        let entry_name = format!("{FILES_PREFIX}{}", pack_file.relative_path);
+        let mut bytes = Vec::new();
+        archive
+            .by_name(&entry_name)
+            .map_err(|error| {
+                CommandError::parse(format!("Block pack is missing '{entry_name}': {error}"))
+            })?
+            .read_to_end(&mut bytes)
+            .map_err(|error| {
+                CommandError::io(format!(
+                    "Could not read '{entry_name}' from block pack: {error}"
+                ))
+            })?;
+
+        let mut destination = root.join(&safe_relative);
+        if destination.is_file() {
+            if fast_file_hash(&destination)? == pack_file.file_hash {
+                skipped.push(pack_file.relative_path.clone());
+                local_paths.push(Some(relative_path(root, &destination)?));
+                continue;
+            }
+            destination = imported_copy_path(&destination);
+        }
+        if let Some(parent) = destination.parent() {
+            fs::create_dir_all(parent).map_err(|error| {
+                CommandError::io(format!(
+                    "Could not create import folder '{}': {error}",
+                    path_display(parent)
+                ))
+            })?;
+        }
+        fs::write(&destination, &bytes).map_err(|error| {
+            CommandError::io(format!(
+                "Could not write imported file '{}': {error}",
+                path_display(&destination)
+            ))
+        })?;
+        written.push(path_display(&destination));
+        local_paths.push(Some(relative_path(root, &destination)?));
+    }
+
+    Ok(ExtractedBlockPack {
+        written_paths: written,
+        skipped_paths: skipped,
+        local_paths,
+    })
+}
+
+/// Puts the pack's tags back on the files it was extracted to. Heading tags
+/// are keyed by normalized heading text, so they land on the heading again
+/// once the root is indexed. Returns how many tags were new.
+pub(crate) fn restore_pack_tags(
+    connection: &Connection,
+    root_id: i64,
+    manifest: &BlockPackManifest,
+    local_paths: &[Option<String>],
+) -> CommandResult<usize> {
+    let mut added = 0;
+    for (pack_file, local_path) in manifest.files.iter().zip(local_paths) {
+        let Some(local_path) = local_path else {
+            continue;
+        };
+        for tag in &pack_file.tags {
+            let heading = (!tag.heading_normalized.is_empty()).then(|| {
+                (
+                    tag.heading_text
+                        .as_deref()
+                        .unwrap_or(&tag.heading_normalized),
+                    tag.heading_normalized.as_str(),
+                )
+            });
+            if add_tag(connection, root_id, local_path, heading, &tag.tag)? {
+                added += 1;
+            }
+        }
+    }
+    Ok(added)
+}
@@ -0,0 +1,491 @@
+use std::collections::HashSet;
+use std::path::PathBuf;
+
+use rayon::prelude::*;
+use rusqlite::types::Value;
+use rusqlite::{params, params_from_iter, Connection, OptionalExtension};
+
+use crate::docx_parse::parse_docx_paragraphs;
+use crate::errors::CommandError;
+use crate::operations::OperationHandle;
+use crate::query_syntax::{parse_query, ParsedQuery, QueryField};
+use crate::search::SearchFilters;
+use crate::shards::{index_schemas, schema_for_file, schema_for_root};
+use crate::text_cache::load_file_paragraphs;
+use crate::types::{BodyParagraph, ParsedParagraph, SearchHit, SearchSettings};
+use crate::util::file_name_from_relative;
+use crate::CommandResult;
+
+/// Files handled per batch in the body phase, so cancellation is noticed
+/// between batches.
+const BODY_PHASE_BATCH_SIZE: usize = 64;
+
+/// Rows fetched per requested hit, leaving room for several matching
+/// paragraphs under one heading.
+const BODY_FETCH_MULTIPLIER: usize = 4;
+
+/// Tokens of context `snippet()` keeps around the match.
+pub(crate) const SNIPPET_TOKENS: i64 = 24;
+
+/// Markup around matched tokens in body snippets.
+pub(crate) const HIGHLIGHT_OPEN: &str = "<mark>";
+pub(crate) const HIGHLIGHT_CLOSE: &str = "</mark>";
+
+/// Characters of section text kept as a heading's preview.
+const HEADING_PREVIEW_CHARS: usize = 300;
+
+struct PendingBodyFile {
+    id: i64,
+    absolute_path: PathBuf,
+}
+
+/// The non-blank, non-heading paragraphs of `paragraphs`, each tagged with the
+/// heading above it. Items are `(order, text, is_heading)`.
+fn collect_body<'a>(
+    paragraphs: impl IntoIterator<Item = (i64, &'a str, bool)>,
+) -> Vec<BodyParagraph> {
+    let mut heading_order = None;
+    let mut body = Vec::new();
+    for (order, text, is_heading) in paragraphs {
+        if is_heading {
+            heading_order = Some(order);
+            continue;
+        }
+        let text = text.trim();
+        if text.is_empty() {
+            continue;
+        }
+        body.push(BodyParagraph {
+            paragraph_order: order,
+            heading_order,
+            text: text.to_string(),
+        });
+    }
+    body
+}
+
+pub(crate) fn body_paragraphs(paragraphs: &[ParsedParagraph]) -> Vec<BodyParagraph> {
+    collect_body(paragraphs.iter().map(|paragraph| {
+        (
+            paragraph.order,
+            paragraph.text.as_str(),
+            paragraph.heading_level.is_some(),
+        )
+    }))
+}
+
+/// Rewrites a file's body rows; the `body_fts` triggers keep the full-text
+/// index in step.
+pub(crate) fn replace_body_paragraphs(
+    connection: &Connection,
+    file_id: i64,
+    body: &[BodyParagraph],
+) -> CommandResult<()> {
+    let schema = schema_for_file(file_id);
+    connection
+        .execute(
+            &format!("DELETE FROM {schema}.body_paragraphs WHERE file_id = ?1"),
+            params![file_id],
+        )
+        .map_err(|error| {
+            CommandError::database(format!(
+                "Could not clear body text for file {file_id}: {error}"
+            ))
+        })?;
+
+    let mut insert_paragraph = connection
+        .prepare_cached(&format!(
+            "INSERT INTO {schema}.body_paragraphs(file_id, paragraph_order, heading_order, text)
+             VALUES(?1, ?2, ?3, ?4)"
+        ))
+        .map_err(|error| {
+            CommandError::database(format!("Could not prepare body text insert: {error}"))
+        })?;
+    for paragraph in body {
+        insert_paragraph
+            .execute(params![
+                file_id,
+                paragraph.paragraph_order,
+                paragraph.heading_order,
+                paragraph.text
+            ])
+            .map_err(|error| {
+                CommandError::database(format!(
+                    "Could not insert body text for file {file_id}: {error}"
+                ))
+            })?;
+    }
+    Ok(())
+}
+
+/// The opening text of the section under `heading_order`, cut to
+/// `HEADING_PREVIEW_CHARS`; empty when the heading has no body.
+fn heading_preview(body: &[BodyParagraph], heading_order: i64) -> String {
+    let section = body
+        .iter()
+        .filter(|paragraph| paragraph.heading_order == Some(heading_order))
+        .map(|paragraph| paragraph.text.as_str())
+        .collect::<Vec<&str>>()
+        .join(" ");
+    if section.chars().count() <= HEADING_PREVIEW_CHARS {
+        return section;
+    }
+    let mut preview = section
+        .chars()
+        .take(HEADING_PREVIEW_CHARS)
+        .collect::<String>();
+    preview.push('…');
+    preview
+}
+
+/// Stores the preview of each heading in `heading_orders`, read from the
+/// file's body paragraphs. Call after the heading rows are in place.
+pub(crate) fn store_heading_previews(
+    connection: &Connection,
+    file_id: i64,
+    heading_orders: impl IntoIterator<Item = i64>,
+    body: &[BodyParagraph],
+) -> CommandResult<()> {
+    let schema = schema_for_file(file_id);
+    let mut update_preview = connection
+        .prepare_cached(&format!(
+            "UPDATE {schema}.headings SET body_preview = ?1 WHERE file_id = ?2 AND heading_order = ?3"
+        ))
+        .map_err(|error| {
+            CommandError::database(format!("Could not prepare heading preview update: {error}"))
+        })?;
+    for heading_order in heading_orders {
+        update_preview
+            .execute(params![
+                heading_preview(body, heading_order),
+                file_id,
+                heading_order
+            ])
+            .map_err(|error| {
+                CommandError::database(format!(
+                    "Could not store heading preview for file {file_id}: {error}"
+                ))
+            })?;
+    }
+    Ok(())
+}
+
+fn heading_orders(connection: &Connection, file_id: i64) -> CommandResult<HashSet<i64>> {
+    let mut statement = connection
+        .prepare_cached("SELECT heading_order FROM headings WHERE file_id = ?1")
+        .map_err(|error| {
+            CommandError::database(format!("Could not prepare body heading query: {error}"))
+        })?;
+    let rows = statement
+        .query_map(params![file_id], |row| row.get::<_, i64>(0))
+        .map_err(|error| {
+            CommandError::database(format!("Could not load body headings: {error}"))
+        })?;
+    let mut orders = HashSet::new();
+    for row in rows {
+        orders.insert(row.map_err(|error| {
+            CommandError::database(format!("Could not parse body heading row: {error}"))
+        })?);
+    }
+    Ok(orders)
+}
+
+/// Fills in body rows for files indexed before body search existed, from the
+/// cached text where it is current. Files without any body paragraphs are
+/// revisited each run, which is cheap since they have nothing to insert.
+pub(crate) fn run_body_phase(
+    connection: &Connection,
+    root_id: i64,
+    operation: &OperationHandle,
+) -> CommandResult<usize> {
+    let mut statement = connection
+        .prepare(
+            "SELECT id, absolute_path FROM files f
+             WHERE root_id = ?1
+               AND NOT EXISTS(SELECT 1 FROM body_paragraphs b WHERE b.file_id = f.id)
+             ORDER BY relative_path",
+        )
+        .map_err(|error| {
+            CommandError::database(format!("Could not prepare pending body query: {error}"))
+        })?;
+    let rows = statement
+        .query_map(params![root_id], |row| {
+            Ok(PendingBodyFile {
+                id: row.get(0)?,
+                absolute_path: PathBuf::from(row.get::<_, String>(1)?),
+            })
+        })
+        .map_err(|error| {
+            CommandError::database(format!("Could not run pending body query: {error}"))
+        })?;
+    let mut pending = Vec::new();
+    for row in rows {
+        pending.push(row.map_err(|error| {
+            CommandError::database(format!("Could not parse pending body row: {error}"))
+        })?);
+    }
+
+    for batch in pending.chunks(BODY_PHASE_BATCH_SIZE) {
+        operation.ensure_not_cancelled()?;
+        let mut cached_texts = Vec::with_capacity(batch.len());
+        for file in batch {
+            let cached = match load_file_paragraphs(connection, file.id)? {
+                Some(texts) => Some((texts, heading_orders(connection, file.id)?)),
+                None => None,
+            };
+            cached_texts.push(cached);
+        }
+
+        let extracted = batch
+            .par_iter()
+            .zip(cached_texts.into_par_iter())
+            .map(|(file, cached)| match cached {
+                Some((texts, headings)) => {
+                    collect_body(texts.iter().enumerate().map(|(index, text)| {
+                        let order = i64::try_from(index).unwrap_or(0) + 1;
+                        (order, text.as_str(), headings.contains(&order))
+                    }))
+                }
+                None => parse_docx_paragraphs(&file.absolute_path)
+                    .map(|paragraphs| body_paragraphs(&paragraphs))
+                    .unwrap_or_default(),
+            })
+            .collect::<Vec<Vec<BodyParagraph>>>();
+
+        for (file, body) in batch.iter().zip(extracted) {
+            replace_body_paragraphs(connection, file.id, &body)?;
+            store_heading_previews(
+                connection,
+                file.id,
+                heading_orders(connection, file.id)?,
+                &body,
+            )?;
+        }
+    }
+
+    backfill_heading_previews(connection, root_id)?;
+    Ok(pending.len())
+}
+
+/// Fills in previews for headings stored before they existed, from body rows
+/// already in the index.
+fn backfill_heading_previews(connection: &Connection, root_id: i64) -> CommandResult<()> {
+    let schema = schema_for_root(connection, root_id)?;
+    connection
+        .execute(
+            &format!(
+                "
+                UPDATE {schema}.headings
+                SET body_preview = COALESCE((
+                  SELECT CASE
+                    WHEN length(section) > {HEADING_PREVIEW_CHARS}
+                      THEN substr(section, 1, {HEADING_PREVIEW_CHARS}) || '…'
+                    ELSE section
+                  END
+                  FROM (
+                    SELECT group_concat(text, ' ') AS section
+                    FROM (
+                      SELECT b.text
+                      FROM {schema}.body_paragraphs b
+                      WHERE b.file_id = headings.file_id
+                        AND b.heading_order = headings.heading_order
+                      ORDER BY b.paragraph_order
+                    )
+                  )
+                ), '')
+                WHERE body_preview IS NULL
+                  AND file_id IN (SELECT id FROM {schema}.files WHERE root_id = ?1)
+                "
+            ),
+            params![root_id],
+        )
+        .map_err(|error| {
+            CommandError::database(format!("Could not backfill heading previews: {error}"))
+        })?;
+    Ok(())
+}
+
+/// Sets the snippet of heading hits to the stored preview of their section.
+pub(crate) fn attach_heading_previews(
+    connection: &Connection,
+    hits: &mut [SearchHit],
+) -> CommandResult<()> {
+    let mut statement = connection
+        .prepare_cached(
+            "SELECT body_preview FROM headings WHERE file_id = ?1 AND heading_order = ?2",
+        )
+        .map_err(|error| {
+            CommandError::database(format!("Could not prepare heading preview query: {error}"))
+        })?;
+    for hit in hits
+        .iter_mut()
+        .filter(|hit| hit.kind == "heading" && hit.snippet.is_none())
+    {
+        let Some(heading_order) = hit.heading_order else {
+            continue;
+        };
+        let preview = statement
+            .query_row(params![hit.file_id, heading_order], |row| {
+                row.get::<_, Option<String>>(0)
+            })
+            .optional()
+            .map_err(|error| {
+                CommandError::database(format!("Could not load heading preview: {error}"))
+            })?
+            .flatten();
+        hit.snippet = preview.filter(|preview| !preview.is_empty());
+    }
+    Ok(())
+}
+
+/// SQL conditions on `f` for the query's `author:` and `file:` terms, with
+/// their LIKE patterns bound from `?{first_param}` on.
+pub(crate) fn scope_conditions(
+    parsed: &ParsedQuery,
+    schema: &str,
+    first_param: usize,
+) -> (String, Vec<Value>) {
+    let scoped = parsed.field_terms().map(|term| (term, false)).chain(
+        parsed
+            .excluded
+            .iter()
+            .filter(|term| term.field.is_some())
+            .map(|term| (term, true)),
+    );
+    let mut conditions = String::new();
+    let mut values = Vec::new();
+    for (term, excluded) in scoped {
+        let param = first_param + values.len();
+        let condition = match term.field {
+            Some(QueryField::Author) => format!(
+                "EXISTS(SELECT 1 FROM {schema}.authors a WHERE a.file_id = f.id AND a.normalized LIKE ?{param})"
+            ),
+            _ => format!("f.relative_path LIKE ?{param}"),
+        };
+        let negation = if excluded { "NOT " } else { "" };
+        conditions.push_str(&format!("\n                  AND {negation}{condition}"));
+        values.push(Value::Text(format!("%{}%", term.text.replace(' ', "%"))));
+    }
+    (conditions, values)
+}
+
+/// Paragraphs whose text matches `query`, best first, as `body` hits that
+/// point at the heading each paragraph sits under. Level bounds in `filters`
+/// apply to that heading; `author:` and `file:` terms narrow the files.
+/// Scores start at the body score base in `settings`.
+pub(crate) fn search(
+    connection: &Connection,
+    query: &str,
+    requested_root_id: Option<i64>,
+    limit: usize,
+    filters: &SearchFilters,
+    settings: &SearchSettings,
+) -> CommandResult<Vec<SearchHit>> {
+    if !filters.allows_kind("body") {
+        return Ok(Vec::new());
+    }
+    let parsed = parse_query(query);
+    let Some(match_query) = parsed.to_fts5() else {
+        return Ok(Vec::new());
+    };
+    let schemas = match requested_root_id {
+        Some(root_id) => vec![schema_for_root(connection, root_id)?],
+        None => index_schemas(connection)?,
+    };
+
+    let mut ranked = Vec::new();
+    for schema in schemas {
+        let (scope_sql, scope_values) = scope_conditions(&parsed, &schema, 6);
+        let mut statement = connection
+            .prepare_cached(&format!(
+                "
+                SELECT
+                  f.id,
+                  f.relative_path,
+                  f.absolute_path,
+                  h.level,
+                  h.text,
+                  b.heading_order,
+                  snippet(body_fts, 0, '{HIGHLIGHT_OPEN}', '{HIGHLIGHT_CLOSE}', '…', {SNIPPET_TOKENS}),
+                  bm25(body_fts)
+                FROM {schema}.body_fts
+                JOIN {schema}.body_paragraphs b ON b.id = body_fts.rowid
+                JOIN {schema}.files f ON f.id = b.file_id
+                LEFT JOIN {schema}.headings h
+                  ON h.file_id = b.file_id AND h.heading_order = b.heading_order
+                WHERE body_fts MATCH ?1
+                  AND (?2 IS NULL OR f.root_id = ?2)
+                  AND (?4 IS NULL OR h.level >= ?4)
+                  AND (?5 IS NULL OR h.level <= ?5){scope_sql}
+                ORDER BY bm25(body_fts)
+                LIMIT ?3
+                "
+            ))
+            .map_err(|error| {
+                CommandError::database(format!("Could not prepare body search: {error}"))
+            })?;
+        let rows = statement
+            .query_map(
+                params_from_iter(
+                    [
+                        Value::Text(match_query.clone()),
+                        Value::from(requested_root_id),
+                        Value::from(
+                            i64::try_from(limit.saturating_mul(BODY_FETCH_MULTIPLIER))
+                                .unwrap_or(i64::MAX),
+                        ),
+                        Value::from(filters.min_level),
+                        Value::from(filters.max_level),
+                    ]
+                    .into_iter()
+                    .chain(scope_values),
+                ),
+                |row| {
+                    let relative_path = row.get::<_, String>(1)?;
+                    Ok((
+                        row.get::<_, f64>(7)?,
+                        SearchHit {
+                            source: "lexical".to_string(),
+                            kind: "body".to_string(),
+                            file_id: row.get(0)?,
+                            file_name: file_name_from_relative(&relative_path),
+                            relative_path,
+                            absolute_path: row.get(2)?,
+                            heading_level: row.get(3)?,
+                            heading_text: row.get(4)?,
+                            heading_order: row.get(5)?,
+                            score: 0.0,
+                            snippet: row.get(6)?,
+                            word_count: None,
+                            underlined_word_count: None,
+                        },
+                    ))
+                },
+            )
+            .map_err(|error| CommandError::database(format!("Body search failed: {error}")))?;
+        for row in rows {
+            ranked.push(row.map_err(|error| {
+                CommandError::database(format!("Could not parse body search row: {error}"))
+            })?);
+        }
+    }
+
+    // bm25() is lower for better matches.
+    ranked.sort_by(|left, right| {
+        left.0
+            .partial_cmp(&right.0)
+            .unwrap_or(std::cmp::Ordering::Equal)
+    });
+    // One hit per card: the best paragraph under each heading.
+    let mut seen = HashSet::new();
+    ranked.retain(|(_, hit)| seen.insert((hit.file_id, hit.heading_order)));
+    ranked.truncate(limit);
+    Ok(ranked
+        .into_iter()
+        .enumerate()
+        .map(|(rank, (_, mut hit))| {
+            hit.score = settings.body_score_base + f64::from(rank as u32);
+            hit
+        })
+        .collect())
+}
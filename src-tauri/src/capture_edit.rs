@@ -0,0 +1,210 @@
+use std::collections::HashMap;
+use std::ops::Range;
+
+use roxmltree::{Document, Node};
+
+use crate::docx_capture::xml_escape_text;
+use crate::docx_parse::has_tag;
+use crate::errors::CommandError;
+use crate::xml_write::{apply_replacements, body_blocks_for_paragraphs};
+use crate::CommandResult;
+
+/// Length in bytes of the longest common prefix of `left` and `right`, cut
+/// at a character boundary.
+fn common_prefix_len(left: &str, right: &str) -> usize {
+    left.char_indices()
+        .zip(right.chars())
+        .find(|((_, left_char), right_char)| left_char != right_char)
+        .map_or_else(|| left.len().min(right.len()), |((index, _), _)| index)
+}
+
+/// Length in bytes of the longest common suffix, no longer than `max_len`.
+fn common_suffix_len(left: &str, right: &str, max_len: usize) -> usize {
+    let mut length = 0;
+    for (left_char, right_char) in left.chars().rev().zip(right.chars().rev()) {
+        if left_char != right_char || length + left_char.len_utf8() > max_len {
+            break;
+        }
+        length += left_char.len_utf8();
+    }
+    length
+}
+
+/// New contents for a paragraph's `w:t` elements that spell `new_text`.
+/// Only the stretch between the old and new text's common prefix and suffix
+/// changes, so fixing a typo leaves every other run, and its formatting, as
+/// it was. The replacement text goes into the run where the change starts.
+fn retext_segments(segments: &[String], new_text: &str) -> Vec<String> {
+    let old_text = segments.concat();
+    let prefix = common_prefix_len(&old_text, new_text);
+    let suffix = common_suffix_len(
+        &old_text,
+        new_text,
+        old_text.len().min(new_text.len()) - prefix,
+    );
+    let removed = prefix..old_text.len() - suffix;
+    let inserted = &new_text[prefix..new_text.len() - suffix];
+
+    let mut segment_start = 0;
+    let mut anchored = false;
+    segments
+        .iter()
+        .map(|segment| {
+            let segment_end = segment_start + segment.len();
+            let keep_before = removed.start.clamp(segment_start, segment_end) - segment_start;
+            let keep_after = removed.end.clamp(segment_start, segment_end) - segment_start;
+            let mut value = segment[..keep_before].to_string();
+            if !anchored && removed.start <= segment_end {
+                value.push_str(inserted);
+                anchored = true;
+            }
+            value.push_str(&segment[keep_after..]);
+            segment_start = segment_end;
+            value
+        })
+        .collect()
+}
+
+/// `document_xml` with the text of its `paragraph_index`th paragraph changed
+/// to `new_text`. Runs, their properties and the paragraph's style are kept;
+/// only the contents of its `w:t` elements are rewritten.
+pub(crate) fn rewrite_paragraph_text(
+    document_xml: &str,
+    paragraph_index: usize,
+    new_text: &str,
+) -> CommandResult<String> {
+    let document = Document::parse(document_xml).map_err(|error| {
+        CommandError::parse(format!("Could not parse capture document XML: {error}"))
+    })?;
+    let paragraph = document
+        .descendants()
+        .filter(|node| has_tag(*node, "p"))
+        .nth(paragraph_index)
+        .ok_or_else(|| {
+            CommandError::not_indexed("Heading is out of bounds in destination document.")
+        })?;
+    let text_nodes = paragraph
+        .descendants()
+        .filter(|node| has_tag(*node, "t"))
+        .collect::<Vec<Node<'_, '_>>>();
+    if text_nodes.is_empty() {
+        return Err(CommandError::validation(
+            "The heading has no text runs to edit.",
+        ));
+    }
+
+    let segments = text_nodes
+        .iter()
+        .map(|node| node.text().unwrap_or_default().to_string())
+        .collect::<Vec<String>>();
+    let replacements = text_nodes
+        .iter()
+        .zip(retext_segments(&segments, new_text))
+        .zip(&segments)
+        .filter(|((_, value), old_value)| value != *old_value)
+        .map(|((node, value), _)| {
+            (
+                node.range(),
+                format!(
+                    "<w:t xml:space=\"preserve\">{}</w:t>",
+                    xml_escape_text(&value)
+                ),
+            )
+        })
+        .collect::<Vec<(Range<usize>, String)>>();
+    Ok(apply_replacements(document_xml, replacements))
+}
+
+/// A paragraph's `w:pPr` pointed at the built-in `Heading{level}` style. Its
+/// other properties are kept; its own style and outline level are dropped so
+/// the heading style decides both. A paragraph without properties is not a
+/// heading, since its level comes from them, and is left alone.
+fn heading_properties_replacement(
+    document_xml: &str,
+    paragraph: Node<'_, '_>,
+    level: i64,
+) -> Option<(Range<usize>, String)> {
+    let existing = paragraph.children().find(|child| has_tag(*child, "pPr"))?;
+    let mut properties = format!("<w:pPr><w:pStyle w:val=\"Heading{level}\"/>");
+    for child in existing.children().filter(|child| {
+        child.is_element() && !has_tag(*child, "pStyle") && !has_tag(*child, "outlineLvl")
+    }) {
+        properties.push_str(&document_xml[child.range()]);
+    }
+    properties.push_str("</w:pPr>");
+    Some((existing.range(), properties))
+}
+
+/// `document_xml` with each paragraph in `levels`, keyed by its index among
+/// the document's paragraphs, restyled as a heading of the given level.
+pub(crate) fn rewrite_heading_levels(
+    document_xml: &str,
+    levels: &HashMap<usize, i64>,
+) -> CommandResult<String> {
+    let document = Document::parse(document_xml).map_err(|error| {
+        CommandError::parse(format!("Could not parse capture document XML: {error}"))
+    })?;
+    let replacements = document
+        .descendants()
+        .filter(|node| has_tag(*node, "p"))
+        .enumerate()
+        .filter_map(|(index, paragraph)| {
+            levels
+                .get(&index)
+                .and_then(|level| heading_properties_replacement(document_xml, paragraph, *level))
+        })
+        .collect::<Vec<(Range<usize>, String)>>();
+    Ok(apply_replacements(document_xml, replacements))
+}
+
+/// `document_xml` with the sections covering paragraphs `start..end` laid out
+/// in the order given. The sections trade places among the slots they hold
+/// now, so anything between them, such as a title line, stays where it is.
+/// A section holding part of another, or one of its own, is refused.
+pub(crate) fn reorder_sections(
+    document_xml: &str,
+    sections: &[(usize, usize)],
+) -> CommandResult<String> {
+    let document = Document::parse(document_xml).map_err(|error| {
+        CommandError::parse(format!("Could not parse capture document XML: {error}"))
+    })?;
+    let paragraph_nodes = document
+        .descendants()
+        .filter(|node| has_tag(*node, "p"))
+        .collect::<Vec<Node<'_, '_>>>();
+
+    let mut slots = Vec::with_capacity(sections.len());
+    let mut contents = Vec::with_capacity(sections.len());
+    for (start, end) in sections {
+        let blocks = body_blocks_for_paragraphs(&paragraph_nodes, *start, *end);
+        let (Some(first), Some(last)) = (blocks.first(), blocks.last()) else {
+            return Err(CommandError::not_indexed(
+                "Heading range is out of bounds in destination document.",
+            ));
+        };
+        slots.push(first.range().start..last.range().end);
+        contents.push(
+            blocks
+                .iter()
+                .map(|block| &document_xml[block.range()])
+                .collect::<String>(),
+        );
+    }
+
+    let mut ordered_slots = slots.clone();
+    ordered_slots.sort_by_key(|slot| slot.start);
+    if ordered_slots
+        .windows(2)
+        .any(|pair| pair[1].start < pair[0].end)
+    {
+        return Err(CommandError::validation(
+            "Cannot reorder a heading together with one nested under it.",
+        ));
+    }
+
+    let replacements = ordered_slots
+        .into_iter()
+        .zip(contents)
+        .collect::<Vec<(Range<usize>, String)>>();
+    Ok(apply_replacements(document_xml, replacements))
+}
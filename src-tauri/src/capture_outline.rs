@@ -0,0 +1,157 @@
+use std::path::Path;
+
+use roxmltree::Document;
+use rusqlite::{params, Connection};
+
+use crate::capture_writer::read_pending_parts;
+use crate::docx_cache::{file_stamp, FileStamp};
+use crate::docx_parse::{parse_document_paragraphs, read_style_map, DocxParts};
+use crate::errors::CommandError;
+use crate::preview::{extract_preview_content, preview_headings};
+use crate::types::FileHeading;
+use crate::util::path_display;
+use crate::CommandResult;
+
+/// Cached outline rows for one capture file, if every row was stored against
+/// the file's current stamp. A file with no headings has no rows and is
+/// simply parsed again, which is cheap for an empty document.
+fn load_cached_outline(
+    connection: &Connection,
+    capture_path: &str,
+    stamp: &FileStamp,
+) -> CommandResult<Option<Vec<FileHeading>>> {
+    let mut statement = connection
+        .prepare_cached(
+            "SELECT modified_ms, size_bytes, heading_order, level, heading_text, copy_text,
+               word_count, underlined_word_count
+             FROM capture_outline_cache
+             WHERE capture_path = ?1
+             ORDER BY heading_order",
+        )
+        .map_err(|error| {
+            CommandError::database(format!("Could not prepare capture outline query: {error}"))
+        })?;
+    let rows = statement
+        .query_map(params![capture_path], |row| {
+            let cached_stamp = FileStamp {
+                modified_ms: row.get(0)?,
+                size: u64::try_from(row.get::<_, i64>(1)?).unwrap_or_default(),
+            };
+            let order = row.get::<_, i64>(2)?;
+            Ok((
+                cached_stamp,
+                FileHeading {
+                    id: order,
+                    order,
+                    level: row.get(3)?,
+                    text: row.get(4)?,
+                    copy_text: row.get(5)?,
+                    word_count: usize::try_from(row.get::<_, i64>(6)?).unwrap_or_default(),
+                    underlined_word_count: usize::try_from(row.get::<_, i64>(7)?)
+                        .unwrap_or_default(),
+                },
+            ))
+        })
+        .map_err(|error| {
+            CommandError::database(format!("Could not load capture outline: {error}"))
+        })?;
+
+    let mut headings = Vec::new();
+    for row in rows {
+        let (cached_stamp, heading) = row.map_err(|error| {
+            CommandError::database(format!("Could not parse capture outline row: {error}"))
+        })?;
+        if &cached_stamp != stamp {
+            return Ok(None);
+        }
+        headings.push(heading);
+    }
+
+    Ok((!headings.is_empty()).then_some(headings))
+}
+
+fn store_outline(
+    connection: &Connection,
+    capture_path: &str,
+    stamp: &FileStamp,
+    headings: &[FileHeading],
+) -> CommandResult<()> {
+    let transaction = connection.unchecked_transaction().map_err(|error| {
+        CommandError::database(format!("Could not start capture outline update: {error}"))
+    })?;
+    transaction
+        .execute(
+            "DELETE FROM capture_outline_cache WHERE capture_path = ?1",
+            params![capture_path],
+        )
+        .map_err(|error| {
+            CommandError::database(format!("Could not clear capture outline: {error}"))
+        })?;
+    {
+        let mut insert = transaction
+            .prepare_cached(
+                "INSERT INTO capture_outline_cache(
+                   capture_path, modified_ms, size_bytes, heading_order, level, heading_text, copy_text,
+                   word_count, underlined_word_count
+                 ) VALUES(?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+            )
+            .map_err(|error| {
+                CommandError::database(format!("Could not prepare capture outline insert: {error}"))
+            })?;
+        let size_bytes = i64::try_from(stamp.size).unwrap_or(i64::MAX);
+        for heading in headings {
+            insert
+                .execute(params![
+                    capture_path,
+                    stamp.modified_ms,
+                    size_bytes,
+                    heading.order,
+                    heading.level,
+                    heading.text,
+                    heading.copy_text,
+                    i64::try_from(heading.word_count).unwrap_or(i64::MAX),
+                    i64::try_from(heading.underlined_word_count).unwrap_or(i64::MAX),
+                ])
+                .map_err(|error| {
+                    CommandError::database(format!("Could not store capture outline: {error}"))
+                })?;
+        }
+    }
+    transaction.commit().map_err(|error| {
+        CommandError::database(format!("Could not commit capture outline: {error}"))
+    })
+}
+
+/// The heading outline of a capture file, parsed only when the file changed
+/// since it was last read. Any write to the capture file, ours or Word's,
+/// moves its mtime or size and so invalidates the cached rows. Captures still
+/// queued for the file are read from the queue instead.
+pub(crate) fn capture_outline(
+    connection: &Connection,
+    capture_path: &Path,
+) -> CommandResult<Vec<FileHeading>> {
+    if let Some(headings) = read_pending_parts(capture_path, pending_outline) {
+        return headings;
+    }
+
+    let key = path_display(capture_path);
+    let stamp = file_stamp(capture_path)?;
+    if let Some(headings) = load_cached_outline(connection, &key, &stamp)? {
+        return Ok(headings);
+    }
+
+    let (mut headings, _) = extract_preview_content(capture_path)?;
+    headings.sort_by_key(|heading| heading.order);
+    store_outline(connection, &key, &stamp, &headings)?;
+    Ok(headings)
+}
+
+fn pending_outline(parts: &DocxParts) -> CommandResult<Vec<FileHeading>> {
+    let document = Document::parse(&parts.document_xml).map_err(|error| {
+        CommandError::parse(format!("Could not parse queued capture XML: {error}"))
+    })?;
+    let style_map = read_style_map(parts.styles_xml.as_deref());
+    Ok(preview_headings(&parse_document_paragraphs(
+        &document, &style_map,
+    )))
+}
@@ -0,0 +1,294 @@
+use std::fs;
+use std::path::Path;
+
+use rusqlite::{params, Connection, OptionalExtension};
+
+use crate::csv_export::parse_csv;
+use crate::errors::CommandError;
+use crate::preview::extract_preview_content;
+use crate::search::normalize_for_search;
+use crate::shared_roots::resolve_shared_path;
+use crate::text_cache::cached_section_text;
+use crate::types::CaptureRecord;
+use crate::util::{capture_marker, file_name_from_relative, path_display};
+use crate::CommandResult;
+
+/// Captures listed per page when the caller gives no limit.
+pub(crate) const DEFAULT_CAPTURE_LIST_LIMIT: usize = 100;
+pub(crate) const MAX_CAPTURE_LIST_LIMIT: usize = 1_000;
+
+/// One row of a capture log written by `export_captures_csv`.
+pub(crate) struct CaptureLogEntry {
+    pub source_path: String,
+    pub section_title: String,
+    pub heading_level: Option<i64>,
+    pub target: String,
+}
+
+pub(crate) fn read_capture_log(path: &Path) -> CommandResult<Vec<CaptureLogEntry>> {
+    let raw = fs::read_to_string(path).map_err(|error| {
+        CommandError::io(format!(
+            "Could not read capture log '{}': {error}",
+            path_display(path)
+        ))
+    })?;
+    let mut rows = parse_csv(&raw).into_iter();
+    let header = rows
+        .next()
+        .ok_or_else(|| CommandError::parse("The capture log is empty."))?;
+    let column = |name: &str| header.iter().position(|field| field.trim() == name);
+    let (Some(source_column), Some(title_column), Some(target_column)) = (
+        column("source_path"),
+        column("section_title"),
+        column("target"),
+    ) else {
+        return Err(
+            CommandError::parse(
+                "This CSV is not a capture log (expected source_path, section_title and target columns).",
+            )
+        );
+    };
+    let level_column = column("heading_level");
+
+    let field = |row: &[String], index: usize| {
+        row.get(index)
+            .map(|value| value.trim().to_string())
+            .unwrap_or_default()
+    };
+    Ok(rows
+        .map(|row| CaptureLogEntry {
+            source_path: field(&row, source_column),
+            section_title: field(&row, title_column),
+            heading_level: level_column.and_then(|index| field(&row, index).parse::<i64>().ok()),
+            target: field(&row, target_column),
+        })
+        .filter(|entry| !entry.source_path.is_empty() && !entry.section_title.is_empty())
+        .collect())
+}
+
+/// Finds the local copy of a source named by another machine's path: first
+/// through path mappings, then by the longest relative path in the root that
+/// the foreign path ends with, which covers roots nobody has mapped yet.
+pub(crate) fn locate_replay_source(
+    connection: &Connection,
+    root_id: i64,
+    foreign_path: &str,
+) -> CommandResult<Option<(i64, String)>> {
+    if let Some(resolved) = resolve_shared_path(connection, foreign_path)? {
+        if let Some(file_id) = resolved.file_id.filter(|_| resolved.exists) {
+            return Ok(Some((file_id, resolved.absolute_path)));
+        }
+    }
+
+    let foreign = foreign_path.trim().replace('\\', "/");
+    let mut statement = connection
+        .prepare("SELECT id, relative_path, absolute_path FROM files WHERE root_id = ?1")
+        .map_err(|error| {
+            CommandError::database(format!("Could not prepare replay source query: {error}"))
+        })?;
+    let rows = statement
+        .query_map(params![root_id], |row| {
+            Ok((
+                row.get::<_, i64>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, String>(2)?,
+            ))
+        })
+        .map_err(|error| {
+            CommandError::database(format!("Could not run replay source query: {error}"))
+        })?;
+    let mut best: Option<(usize, i64, String)> = None;
+    for row in rows {
+        let (file_id, relative_path, absolute_path) = row.map_err(|error| {
+            CommandError::database(format!("Could not parse replay source row: {error}"))
+        })?;
+        let matches = foreign == relative_path || foreign.ends_with(&format!("/{relative_path}"));
+        if matches
+            && best
+                .as_ref()
+                .is_none_or(|(length, _, _)| relative_path.len() > *length)
+        {
+            best = Some((relative_path.len(), file_id, absolute_path));
+        }
+    }
+    Ok(best.map(|(_, file_id, absolute_path)| (file_id, absolute_path)))
+}
+
+/// Looks up the heading a capture was taken from by its text, preferring one
+/// at the recorded level when the title repeats.
+pub(crate) fn locate_replay_heading(
+    connection: &Connection,
+    file_id: i64,
+    section_title: &str,
+    heading_level: Option<i64>,
+) -> CommandResult<Option<(i64, i64)>> {
+    connection
+        .query_row(
+            "
+            SELECT heading_order, level
+            FROM headings
+            WHERE file_id = ?1 AND normalized = ?2
+            ORDER BY CASE WHEN level = ?3 THEN 0 ELSE 1 END, heading_order
+            LIMIT 1
+            ",
+            params![
+                file_id,
+                normalize_for_search(section_title),
+                heading_level.unwrap_or(-1)
+            ],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .optional()
+        .map_err(|error| {
+            CommandError::database(format!("Could not look up '{section_title}': {error}"))
+        })
+}
+
+/// True when this root already holds the same capture, so replaying a log
+/// twice (or a log that includes our own cuts) does not duplicate sections.
+pub(crate) fn capture_already_recorded(
+    connection: &Connection,
+    root_id: i64,
+    source_path: &str,
+    section_title: &str,
+    target_relative_path: &str,
+) -> CommandResult<bool> {
+    connection
+        .query_row(
+            "
+            SELECT EXISTS(
+              SELECT 1 FROM captures
+              WHERE root_id = ?1
+                AND source_path = ?2
+                AND section_title = ?3
+                AND target_relative_path = ?4
+            )
+            ",
+            params![root_id, source_path, section_title, target_relative_path],
+            |row| row.get::<_, i64>(0),
+        )
+        .map(|exists| exists != 0)
+        .map_err(|error| {
+            CommandError::database(format!("Could not check existing captures: {error}"))
+        })
+}
+
+/// The section's plain text as the preview's copy action produces it, which is
+/// what a capture records alongside the styled paragraphs. The indexed text
+/// cache is tried first so an unchanged source is not unzipped again.
+pub(crate) fn replay_section_text(
+    connection: &Connection,
+    file_id: i64,
+    source_path: &Path,
+    heading_order: i64,
+) -> Option<String> {
+    if let Ok(Some(text)) = cached_section_text(connection, file_id, heading_order) {
+        if !text.trim().is_empty() {
+            return Some(text);
+        }
+    }
+    let (headings, _) = extract_preview_content(source_path).ok()?;
+    headings
+        .into_iter()
+        .find(|heading| heading.order == heading_order)
+        .map(|heading| heading.copy_text)
+        .filter(|text| !text.trim().is_empty())
+}
+
+/// A root's captures, newest first, optionally only those sent to one
+/// target. `limit` and `offset` page through the history.
+pub(crate) fn list_capture_records(
+    connection: &Connection,
+    root_id: i64,
+    target_relative_path: Option<&str>,
+    limit: usize,
+    offset: usize,
+) -> CommandResult<Vec<CaptureRecord>> {
+    let mut statement = connection
+        .prepare(
+            "
+            SELECT id, source_path, section_title, target_relative_path, heading_level, created_at_ms
+            FROM captures
+            WHERE root_id = ?1 AND (?2 IS NULL OR target_relative_path = ?2)
+            ORDER BY created_at_ms DESC, id DESC
+            LIMIT ?3 OFFSET ?4
+            ",
+        )
+        .map_err(|error| {
+            CommandError::database(format!("Could not prepare capture history query: {error}"))
+        })?;
+    let rows = statement
+        .query_map(
+            params![
+                root_id,
+                target_relative_path,
+                i64::try_from(limit).unwrap_or(i64::MAX),
+                i64::try_from(offset).unwrap_or(i64::MAX)
+            ],
+            |row| {
+                let id = row.get::<_, i64>(0)?;
+                let source_path = row.get::<_, String>(1)?;
+                Ok(CaptureRecord {
+                    id,
+                    source_file_name: file_name_from_relative(&source_path),
+                    source_path,
+                    section_title: row.get(2)?,
+                    target_relative_path: row.get(3)?,
+                    heading_level: row.get(4)?,
+                    created_at_ms: row.get(5)?,
+                    marker: capture_marker(id),
+                })
+            },
+        )
+        .map_err(|error| {
+            CommandError::database(format!("Could not run capture history query: {error}"))
+        })?;
+
+    let mut records = Vec::new();
+    for row in rows {
+        records.push(row.map_err(|error| {
+            CommandError::database(format!("Could not parse capture history row: {error}"))
+        })?);
+    }
+    Ok(records)
+}
+
+/// A stored capture: its root and what `reinsert_capture` replays.
+pub(crate) struct StoredCapture {
+    pub root_id: i64,
+    pub root_path: String,
+    pub source_path: String,
+    pub section_title: String,
+    pub heading_level: Option<i64>,
+    pub content: String,
+}
+
+pub(crate) fn load_stored_capture(
+    connection: &Connection,
+    capture_id: i64,
+) -> CommandResult<Option<StoredCapture>> {
+    connection
+        .query_row(
+            "
+            SELECT c.root_id, r.path, c.source_path, c.section_title, c.heading_level, c.content
+            FROM captures c
+            JOIN roots r ON r.id = c.root_id
+            WHERE c.id = ?1
+            ",
+            params![capture_id],
+            |row| {
+                Ok(StoredCapture {
+                    root_id: row.get(0)?,
+                    root_path: row.get(1)?,
+                    source_path: row.get(2)?,
+                    section_title: row.get(3)?,
+                    heading_level: row.get(4)?,
+                    content: row.get(5)?,
+                })
+            },
+        )
+        .optional()
+        .map_err(|error| {
+            CommandError::database(format!("Could not load capture {capture_id}: {error}"))
+        })
+}
@@ -0,0 +1,262 @@
+use std::collections::HashMap;
+use std::ops::Range;
+use std::path::Path;
+
+use roxmltree::{Document, Node};
+use rusqlite::{params, Connection, OptionalExtension};
+
+use crate::docx_capture::{rewrite_docx_with_parts, xml_escape_text};
+use crate::docx_parse::{
+    attribute_value, has_tag, parse_document_paragraphs, read_docx_parts, read_style_map, DocxParts,
+};
+use crate::errors::CommandError;
+use crate::types::CaptureTargetSettings;
+use crate::util::{now_ms, path_display};
+use crate::xml_write::apply_replacements;
+use crate::CommandResult;
+
+/// Marks the content control holding the table of contents, so it is found
+/// and replaced on the next refresh rather than stacked.
+const TOC_TAG: &str = "BlockFileTOC";
+/// Pockets and hats; blocks and tags would make the index as long as the file.
+const TOC_MAX_LEVEL: i64 = 2;
+const TOC_STYLES: [(&str, &str, &str); 2] = [
+    ("TOC1", "toc 1", "<w:spacing w:after=\"100\"/>"),
+    (
+        "TOC2",
+        "toc 2",
+        "<w:spacing w:after=\"100\"/><w:ind w:left=\"220\"/>",
+    ),
+];
+
+pub(crate) fn load_capture_target_settings(
+    connection: &Connection,
+    root_id: i64,
+    target_relative_path: &str,
+) -> CommandResult<CaptureTargetSettings> {
+    let table_of_contents = connection
+        .query_row(
+            "SELECT table_of_contents FROM capture_target_settings
+             WHERE root_id = ?1 AND target_relative_path = ?2",
+            params![root_id, target_relative_path],
+            |row| row.get::<_, i64>(0),
+        )
+        .optional()
+        .map_err(|error| {
+            CommandError::database(format!("Could not load capture target settings: {error}"))
+        })?
+        .map(|value| value != 0)
+        .unwrap_or(false);
+    Ok(CaptureTargetSettings { table_of_contents })
+}
+
+pub(crate) fn store_capture_target_settings(
+    connection: &Connection,
+    root_id: i64,
+    target_relative_path: &str,
+    settings: &CaptureTargetSettings,
+) -> CommandResult<()> {
+    connection
+        .execute(
+            "INSERT INTO capture_target_settings(
+               root_id, target_relative_path, table_of_contents, updated_at_ms
+             ) VALUES(?1, ?2, ?3, ?4)
+             ON CONFLICT(root_id, target_relative_path) DO UPDATE SET
+               table_of_contents = excluded.table_of_contents,
+               updated_at_ms = excluded.updated_at_ms",
+            params![
+                root_id,
+                target_relative_path,
+                i64::from(settings.table_of_contents),
+                now_ms(),
+            ],
+        )
+        .map_err(|error| {
+            CommandError::database(format!("Could not save capture target settings: {error}"))
+        })?;
+    Ok(())
+}
+
+fn is_toc_block(node: Node<'_, '_>) -> bool {
+    has_tag(node, "sdt")
+        && node
+            .children()
+            .find(|child| has_tag(*child, "sdtPr"))
+            .and_then(|properties| properties.children().find(|child| has_tag(*child, "tag")))
+            .and_then(|tag| attribute_value(tag, "val"))
+            == Some(TOC_TAG)
+}
+
+fn toc_entry_xml(level: i64, text: &str, field_start: bool, field_end: bool) -> String {
+    let mut paragraph = format!("<w:p><w:pPr><w:pStyle w:val=\"TOC{level}\"/></w:pPr>");
+    if field_start {
+        paragraph.push_str(
+            "<w:r><w:fldChar w:fldCharType=\"begin\" w:dirty=\"true\"/></w:r>\
+             <w:r><w:instrText xml:space=\"preserve\"> TOC \\o \"1-2\" \\h \\z \\u </w:instrText></w:r>\
+             <w:r><w:fldChar w:fldCharType=\"separate\"/></w:r>",
+        );
+    }
+    paragraph.push_str(&format!(
+        "<w:r><w:t xml:space=\"preserve\">{}</w:t></w:r>",
+        xml_escape_text(text)
+    ));
+    if field_end {
+        paragraph.push_str("<w:r><w:fldChar w:fldCharType=\"end\"/></w:r>");
+    }
+    paragraph.push_str("</w:p>");
+    paragraph
+}
+
+/// The table of contents for `entries`: a Word TOC field whose cached result
+/// lists the headings by name. The field is marked dirty, so Word offers to
+/// fill in page numbers and links when the file is opened.
+fn toc_block_xml(entries: &[(i64, String)]) -> String {
+    let last = entries.len().saturating_sub(1);
+    let paragraphs = entries
+        .iter()
+        .enumerate()
+        .map(|(index, (level, text))| toc_entry_xml(*level, text, index == 0, index == last))
+        .collect::<String>();
+    format!(
+        "<w:sdt><w:sdtPr><w:tag w:val=\"{TOC_TAG}\"/><w:docPartObj><w:docPartGallery w:val=\"Table of Contents\"/><w:docPartUnique/></w:docPartObj></w:sdtPr><w:sdtContent>{paragraphs}</w:sdtContent></w:sdt>"
+    )
+}
+
+/// Adds the TOC paragraph styles a capture file does not define yet.
+fn ensure_toc_styles(styles_xml: &str) -> String {
+    let Ok(document) = Document::parse(styles_xml) else {
+        return styles_xml.to_string();
+    };
+    let defined = document
+        .descendants()
+        .filter(|node| has_tag(*node, "style"))
+        .filter_map(|node| attribute_value(node, "styleId"))
+        .collect::<Vec<&str>>();
+    let missing = TOC_STYLES
+        .iter()
+        .filter(|(style_id, _, _)| !defined.contains(style_id))
+        .map(|(style_id, name, paragraph_properties)| {
+            format!("<w:style w:type=\"paragraph\" w:styleId=\"{style_id}\"><w:name w:val=\"{name}\"/><w:basedOn w:val=\"Normal\"/><w:next w:val=\"Normal\"/><w:uiPriority w:val=\"39\"/><w:unhideWhenUsed/><w:pPr>{paragraph_properties}</w:pPr></w:style>")
+        })
+        .collect::<String>();
+    match styles_xml.rfind("</w:styles>") {
+        Some(close) if !missing.is_empty() => {
+            apply_replacements(styles_xml, vec![(close..close, missing)])
+        }
+        _ => styles_xml.to_string(),
+    }
+}
+
+/// `document_xml` with its table of contents rebuilt from the headings, or
+/// removed when `enabled` is off or there is nothing to list, together with
+/// whether it still has one. `None` when nothing changes.
+fn rebuilt_document_xml(
+    document_xml: &str,
+    styles_xml: Option<&str>,
+    enabled: bool,
+) -> Option<(String, bool)> {
+    let document = Document::parse(document_xml).ok()?;
+    let body = document.descendants().find(|node| has_tag(*node, "body"))?;
+    let existing = body.children().find(|node| is_toc_block(*node));
+
+    let entries = if enabled {
+        parse_document_paragraphs(&document, &read_style_map(styles_xml))
+            .into_iter()
+            .filter_map(|paragraph| {
+                paragraph
+                    .heading_level
+                    .filter(|level| *level <= TOC_MAX_LEVEL)
+                    .map(|level| (level, paragraph.text.trim().to_string()))
+            })
+            .filter(|(_, text)| !text.is_empty())
+            .collect::<Vec<(i64, String)>>()
+    } else {
+        Vec::new()
+    };
+    let block = if entries.is_empty() {
+        String::new()
+    } else {
+        toc_block_xml(&entries)
+    };
+
+    let range: Range<usize> = match existing {
+        Some(node) => node.range(),
+        None if block.is_empty() => return None,
+        None => {
+            let start = body
+                .first_child()
+                .map_or(body.range().end, |child| child.range().start);
+            start..start
+        }
+    };
+    if document_xml[range.clone()] == block {
+        return None;
+    }
+    Some((
+        apply_replacements(document_xml, vec![(range, block)]),
+        !entries.is_empty(),
+    ))
+}
+
+/// Brings the table of contents in `parts` up to date with its Heading 1 and
+/// 2 paragraphs, or takes it out when `enabled` is off. An existing table
+/// keeps its place; a new one goes at the top of the body. Returns whether
+/// anything changed.
+pub(crate) fn refresh_table_of_contents(parts: &mut DocxParts, enabled: bool) -> bool {
+    let Some((document_xml, has_toc)) =
+        rebuilt_document_xml(&parts.document_xml, parts.styles_xml.as_deref(), enabled)
+    else {
+        return false;
+    };
+    parts.document_xml = document_xml;
+    if has_toc {
+        parts.styles_xml = parts.styles_xml.as_deref().map(ensure_toc_styles);
+    }
+    true
+}
+
+/// Refreshes the table of contents of a capture file on disk after an edit
+/// that rewrote it outside the capture writer. Targets without one are not
+/// read at all.
+pub(crate) fn sync_capture_toc(
+    connection: &Connection,
+    root_id: i64,
+    target_relative_path: &str,
+    capture_path: &Path,
+) -> CommandResult<()> {
+    let settings = load_capture_target_settings(connection, root_id, target_relative_path)?;
+    if !settings.table_of_contents {
+        return Ok(());
+    }
+    write_table_of_contents(capture_path, true)
+}
+
+/// Adds, refreshes or removes the table of contents in a capture file,
+/// rewriting the file only when it changes.
+pub(crate) fn write_table_of_contents(capture_path: &Path, enabled: bool) -> CommandResult<()> {
+    if !capture_path.is_file() {
+        return Ok(());
+    }
+    let mut parts = read_docx_parts(capture_path)?.ok_or_else(|| {
+        CommandError::invalid_docx(
+            capture_path,
+            format!(
+                "Missing word/document.xml in '{}'",
+                path_display(capture_path)
+            ),
+        )
+    })?;
+    if !refresh_table_of_contents(&mut parts, enabled) {
+        return Ok(());
+    }
+
+    let mut replacements = HashMap::new();
+    replacements.insert(
+        "word/document.xml".to_string(),
+        parts.document_xml.into_bytes(),
+    );
+    if let Some(styles_xml) = parts.styles_xml {
+        replacements.insert("word/styles.xml".to_string(), styles_xml.into_bytes());
+    }
+    rewrite_docx_with_parts(capture_path, &replacements)
+}
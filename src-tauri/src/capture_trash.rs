@@ -0,0 +1,132 @@
+use rusqlite::{params, Connection, OptionalExtension};
+
+use crate::errors::CommandError;
+use crate::types::{CaptureTrashEntry, CaptureTrashFragment};
+use crate::util::now_ms;
+use crate::CommandResult;
+
+pub(crate) fn insert_trash_entry(
+    connection: &Connection,
+    root_id: i64,
+    target_relative_path: &str,
+    heading_level: i64,
+    heading_text: &str,
+    fragment: &CaptureTrashFragment,
+) -> CommandResult<i64> {
+    connection
+        .execute(
+            "
+            INSERT INTO capture_trash(
+              root_id,
+              target_relative_path,
+              heading_level,
+              heading_text,
+              paragraph_offset,
+              fragment_xml,
+              styles_xml,
+              relationships_xml,
+              deleted_at_ms
+            )
+            VALUES(?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)
+            ",
+            params![
+                root_id,
+                target_relative_path,
+                heading_level,
+                heading_text,
+                i64::try_from(fragment.paragraph_offset).unwrap_or(0),
+                fragment.fragment_xml,
+                fragment.styles_xml,
+                fragment.relationships_xml,
+                now_ms()
+            ],
+        )
+        .map_err(|error| {
+            CommandError::database(format!("Could not move deleted heading to trash: {error}"))
+        })?;
+    Ok(connection.last_insert_rowid())
+}
+
+pub(crate) fn list_trash_entries(
+    connection: &Connection,
+    root_id: i64,
+    target_relative_path: Option<&str>,
+) -> CommandResult<Vec<CaptureTrashEntry>> {
+    let mut statement = connection
+        .prepare(
+            "
+            SELECT id, target_relative_path, heading_level, heading_text, deleted_at_ms
+            FROM capture_trash
+            WHERE root_id = ?1 AND (?2 IS NULL OR target_relative_path = ?2)
+            ORDER BY deleted_at_ms DESC, id DESC
+            ",
+        )
+        .map_err(|error| {
+            CommandError::database(format!("Could not prepare capture trash query: {error}"))
+        })?;
+    let rows = statement
+        .query_map(params![root_id, target_relative_path], |row| {
+            Ok(CaptureTrashEntry {
+                id: row.get(0)?,
+                target_relative_path: row.get(1)?,
+                heading_level: row.get(2)?,
+                heading_text: row.get(3)?,
+                deleted_at_ms: row.get(4)?,
+            })
+        })
+        .map_err(|error| {
+            CommandError::database(format!("Could not run capture trash query: {error}"))
+        })?;
+
+    let mut entries = Vec::new();
+    for row in rows {
+        entries.push(row.map_err(|error| {
+            CommandError::database(format!("Could not parse capture trash row: {error}"))
+        })?);
+    }
+    Ok(entries)
+}
+
+pub(crate) fn load_trash_fragment(
+    connection: &Connection,
+    root_id: i64,
+    trash_id: i64,
+) -> CommandResult<Option<(String, CaptureTrashFragment)>> {
+    connection
+        .query_row(
+            "
+            SELECT target_relative_path, paragraph_offset, fragment_xml, styles_xml, relationships_xml
+            FROM capture_trash
+            WHERE id = ?1 AND root_id = ?2
+            ",
+            params![trash_id, root_id],
+            |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    CaptureTrashFragment {
+                        paragraph_offset: usize::try_from(row.get::<_, i64>(1)?).unwrap_or(0),
+                        fragment_xml: row.get(2)?,
+                        styles_xml: row.get(3)?,
+                        relationships_xml: row.get(4)?,
+                    },
+                ))
+            },
+        )
+        .optional()
+        .map_err(|error| {
+            CommandError::database(format!(
+                "Could not load capture trash entry {trash_id}: {error}"
+            ))
+        })
+}
+
+pub(crate) fn delete_trash_entry(connection: &Connection, trash_id: i64) -> CommandResult<()> {
+    connection
+        .execute("DELETE FROM capture_trash WHERE id = ?1", params![trash_id])
+        .map_err(|error| {
+            CommandError::database(format!(
+                "Could not remove capture trash entry {trash_id}: {error}"
+            ))
+        })?;
+    Ok(())
+}
@@ -0,0 +1,234 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard, OnceLock};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::docx_capture::rewrite_docx_with_parts;
+use crate::docx_parse::DocxParts;
+use crate::notes::NoteKind;
+use crate::numbering::NUMBERING_PART_NAME;
+use crate::util::path_display;
+use crate::CommandResult;
+
+/// How long a target has to go without a new capture before its queued
+/// parts are written.
+const WRITE_BEHIND_WINDOW: Duration = Duration::from_millis(750);
+/// Longest a queued capture waits while captures keep arriving.
+const MAX_WRITE_DELAY: Duration = Duration::from_secs(5);
+
+struct PendingWrite {
+    parts: DocxParts,
+    first_queued: Instant,
+    last_queued: Instant,
+    failed: bool,
+}
+
+#[derive(Default)]
+struct TargetState {
+    pending: Option<PendingWrite>,
+    flush_scheduled: bool,
+}
+
+type TargetHandle = Arc<Mutex<TargetState>>;
+
+/// One entry per capture target that is in use or has parts queued.
+fn targets() -> MutexGuard<'static, HashMap<PathBuf, TargetHandle>> {
+    static TARGETS: OnceLock<Mutex<HashMap<PathBuf, TargetHandle>>> = OnceLock::new();
+    TARGETS
+        .get_or_init(|| Mutex::new(HashMap::new()))
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+fn lock_state(handle: &TargetHandle) -> MutexGuard<'_, TargetState> {
+    handle
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// Runs `f` with the target's state locked, so reads and writes of one file
+/// are serialized. The map entry is dropped again once no caller holds it
+/// and nothing is queued for it.
+fn with_target<T>(capture_path: &Path, f: impl FnOnce(&mut TargetState) -> T) -> T {
+    let handle = Arc::clone(targets().entry(capture_path.to_path_buf()).or_default());
+    let result = f(&mut lock_state(&handle));
+
+    let mut targets = targets();
+    drop(handle);
+    let unused = targets
+        .get(capture_path)
+        .is_some_and(|entry| Arc::strong_count(entry) == 1 && lock_state(entry).pending.is_none());
+    if unused {
+        targets.remove(capture_path);
+    }
+    result
+}
+
+/// Writes the queued parts, if any. A failed write keeps them queued and
+/// stops the timer; the next access to the target tries again and reports
+/// the error.
+fn write_pending(capture_path: &Path, state: &mut TargetState) -> CommandResult<()> {
+    if let Some(pending) = &mut state.pending {
+        if let Err(error) = write_parts(capture_path, &pending.parts) {
+            pending.failed = true;
+            return Err(error);
+        }
+        state.pending = None;
+    }
+    Ok(())
+}
+
+fn schedule_flush(capture_path: PathBuf) {
+    thread::spawn(move || loop {
+        thread::sleep(WRITE_BEHIND_WINDOW);
+        let finished = with_target(&capture_path, |state| {
+            if let Some(pending) = &state.pending {
+                if pending.last_queued.elapsed() < WRITE_BEHIND_WINDOW
+                    && pending.first_queued.elapsed() < MAX_WRITE_DELAY
+                {
+                    return false;
+                }
+            }
+            if let Err(error) = write_pending(&capture_path, state) {
+                eprintln!(
+                    "Could not write captures to '{}': {error}",
+                    path_display(&capture_path)
+                );
+            }
+            state.flush_scheduled = false;
+            true
+        });
+        if finished {
+            break;
+        }
+    });
+}
+
+pub(crate) fn write_parts(capture_path: &Path, parts: &DocxParts) -> CommandResult<()> {
+    let mut replacements = HashMap::new();
+    replacements.insert(
+        "word/document.xml".to_string(),
+        parts.document_xml.as_bytes().to_vec(),
+    );
+    if let Some(styles_xml) = &parts.styles_xml {
+        replacements.insert(
+            "word/styles.xml".to_string(),
+            styles_xml.as_bytes().to_vec(),
+        );
+    }
+    if let Some(relationships_xml) = &parts.relationships_xml {
+        replacements.insert(
+            "word/_rels/document.xml.rels".to_string(),
+            relationships_xml.as_bytes().to_vec(),
+        );
+    }
+    if let Some(numbering_xml) = &parts.numbering_xml {
+        replacements.insert(
+            NUMBERING_PART_NAME.to_string(),
+            numbering_xml.as_bytes().to_vec(),
+        );
+    }
+    if let Some(content_types_xml) = &parts.content_types_xml {
+        replacements.insert(
+            "[Content_Types].xml".to_string(),
+            content_types_xml.as_bytes().to_vec(),
+        );
+    }
+    for (kind, notes_xml) in [
+        (NoteKind::Footnote, &parts.footnotes_xml),
+        (NoteKind::Endnote, &parts.endnotes_xml),
+    ] {
+        if let Some(notes_xml) = notes_xml {
+            replacements.insert(kind.part_name().to_string(), notes_xml.as_bytes().to_vec());
+        }
+    }
+    for part in &parts.media_parts {
+        replacements.insert(part.part_name.clone(), part.bytes.clone());
+    }
+    rewrite_docx_with_parts(capture_path, &replacements)
+}
+
+/// Applies `update` to a capture target's parts and queues the result. The
+/// first update reads the file; later ones build on the queued parts, and
+/// the whole run is written with one rewrite once the target has been quiet
+/// for `WRITE_BEHIND_WINDOW`. If that write failed, the next update retries
+/// it first and returns its error, so a locked target reaches the caller.
+pub(crate) fn update_capture_target(
+    capture_path: &Path,
+    load: impl FnOnce(&Path) -> CommandResult<DocxParts>,
+    update: impl FnOnce(&DocxParts) -> CommandResult<DocxParts>,
+) -> CommandResult<()> {
+    with_target(capture_path, |state| {
+        if state.pending.as_ref().is_some_and(|pending| pending.failed) {
+            write_pending(capture_path, state)?;
+        }
+        let parts = match &state.pending {
+            Some(pending) => update(&pending.parts)?,
+            None => update(&load(capture_path)?)?,
+        };
+
+        let now = Instant::now();
+        match &mut state.pending {
+            Some(pending) => {
+                pending.parts = parts;
+                pending.last_queued = now;
+            }
+            None => {
+                state.pending = Some(PendingWrite {
+                    parts,
+                    first_queued: now,
+                    last_queued: now,
+                    failed: false,
+                });
+            }
+        }
+        if !state.flush_scheduled {
+            state.flush_scheduled = true;
+            schedule_flush(capture_path.to_path_buf());
+        }
+        Ok(())
+    })
+}
+
+/// Writes a target's queued captures now.
+pub(crate) fn flush_capture_target(capture_path: &Path) -> CommandResult<()> {
+    with_target(capture_path, |state| write_pending(capture_path, state))
+}
+
+/// Writes every target's queued captures, for shutdown.
+pub(crate) fn flush_all_capture_targets() {
+    let capture_paths = targets().keys().cloned().collect::<Vec<PathBuf>>();
+    for capture_path in capture_paths {
+        if let Err(error) = flush_capture_target(&capture_path) {
+            eprintln!(
+                "Could not write captures to '{}': {error}",
+                path_display(&capture_path)
+            );
+        }
+    }
+}
+
+/// Runs an edit that reads and rewrites the capture file itself, after
+/// writing anything queued for it and with captures to it held off until the
+/// edit is done.
+pub(crate) fn edit_capture_target<T>(
+    capture_path: &Path,
+    edit: impl FnOnce() -> CommandResult<T>,
+) -> CommandResult<T> {
+    with_target(capture_path, |state| {
+        write_pending(capture_path, state)?;
+        edit()
+    })
+}
+
+/// Calls `read` on a target's queued parts, if it has any, so previews show
+/// captures that are not on disk yet.
+pub(crate) fn read_pending_parts<T>(
+    capture_path: &Path,
+    read: impl FnOnce(&DocxParts) -> T,
+) -> Option<T> {
+    with_target(capture_path, |state| {
+        state.pending.as_ref().map(|pending| read(&pending.parts))
+    })
+}
@@ -0,0 +1,218 @@
+use std::collections::HashSet;
+use std::path::Path;
+
+use rayon::prelude::*;
+use rusqlite::{params, Connection};
+
+use crate::docx_parse::parse_docx_paragraphs;
+use crate::errors::CommandError;
+use crate::root_settings::load_root_settings;
+use crate::types::{CitationAuditItem, ParsedParagraph};
+use crate::util::{contains_year_token, file_name_from_relative};
+use crate::CommandResult;
+
+pub(crate) const CITE_MISSING_AUTHOR: &str = "author";
+pub(crate) const CITE_MISSING_YEAR: &str = "year";
+pub(crate) const CITE_MISSING_URL: &str = "url";
+
+// How far below a heading to look for an unstyled cite line.
+const CITE_SEARCH_WINDOW: usize = 3;
+const MAX_CITE_WORDS: usize = 90;
+const PLACEHOLDER_AUTHORS: &[&str] = &["no", "unknown", "anonymous", "author", "n/a", "na"];
+
+fn has_url(text: &str) -> bool {
+    let lowered = text.to_ascii_lowercase();
+    lowered.contains("http://")
+        || lowered.contains("https://")
+        || lowered.contains("www.")
+        || lowered.contains("doi.org")
+        || lowered.contains("doi:")
+}
+
+/// Looks for a capitalized name in the lead of the cite, i.e. the part before
+/// the first comma or digit, which is where "Smith 19" / "Smith, Prof. ..." sit.
+fn has_author(text: &str) -> bool {
+    let lead = text
+        .split(|character: char| character == ',' || character.is_ascii_digit())
+        .next()
+        .unwrap_or("");
+    lead.split_whitespace().any(|word| {
+        let trimmed = word.trim_matches(|character: char| !character.is_alphabetic());
+        let mut characters = trimmed.chars();
+        let starts_upper = characters
+            .next()
+            .map(|character| character.is_uppercase())
+            .unwrap_or(false);
+        starts_upper
+            && trimmed.chars().count() >= 2
+            && !PLACEHOLDER_AUTHORS.contains(&trimmed.to_lowercase().as_str())
+    })
+}
+
+fn missing_cite_parts(cite_text: Option<&str>) -> Vec<String> {
+    let Some(text) = cite_text else {
+        return vec![
+            CITE_MISSING_AUTHOR.to_string(),
+            CITE_MISSING_YEAR.to_string(),
+            CITE_MISSING_URL.to_string(),
+        ];
+    };
+
+    let mut missing = Vec::new();
+    if !has_author(text) {
+        missing.push(CITE_MISSING_AUTHOR.to_string());
+    }
+    if !contains_year_token(text) {
+        missing.push(CITE_MISSING_YEAR.to_string());
+    }
+    if !has_url(text) {
+        missing.push(CITE_MISSING_URL.to_string());
+    }
+    missing
+}
+
+/// Returns the cite line for the section that starts at `heading_index`, or
+/// `None` when the section has body text but nothing that reads as a cite.
+/// F8-styled paragraphs win; otherwise the first short paragraph just under
+/// the heading is taken as the cite.
+fn section_cite_line(paragraphs: &[ParsedParagraph], heading_index: usize) -> Option<&str> {
+    let body = paragraphs[heading_index + 1..]
+        .iter()
+        .take_while(|paragraph| paragraph.heading_level.is_none())
+        .filter(|paragraph| !paragraph.text.trim().is_empty())
+        .collect::<Vec<&ParsedParagraph>>();
+
+    if let Some(cite) = body.iter().find(|paragraph| paragraph.is_f8_cite) {
+        return Some(cite.text.trim());
+    }
+
+    body.iter()
+        .take(CITE_SEARCH_WINDOW)
+        .find(|paragraph| {
+            let word_count = paragraph.text.split_whitespace().count();
+            word_count <= MAX_CITE_WORDS
+                && (contains_year_token(&paragraph.text) || has_url(&paragraph.text))
+        })
+        .map(|paragraph| paragraph.text.trim())
+}
+
+/// Audits leaf sections (headings directly followed by body text), since
+/// structural headings such as pockets and hats never carry a cite.
+fn audit_paragraphs(
+    paragraphs: &[ParsedParagraph],
+    file_id: i64,
+    relative_path: &str,
+    is_capture_file: bool,
+) -> (usize, Vec<CitationAuditItem>) {
+    let mut sections_checked = 0_usize;
+    let mut findings = Vec::new();
+
+    for (index, paragraph) in paragraphs.iter().enumerate() {
+        let Some(level) = paragraph.heading_level else {
+            continue;
+        };
+        let has_body = paragraphs[index + 1..]
+            .iter()
+            .find(|candidate| !candidate.text.trim().is_empty())
+            .map(|candidate| candidate.heading_level.is_none())
+            .unwrap_or(false);
+        if !has_body {
+            continue;
+        }
+
+        sections_checked += 1;
+        let cite_text = section_cite_line(paragraphs, index);
+        let missing = missing_cite_parts(cite_text);
+        if !missing.is_empty() {
+            findings.push(CitationAuditItem {
+                file_id,
+                file_name: file_name_from_relative(relative_path),
+                relative_path: relative_path.to_string(),
+                heading_order: paragraph.order,
+                heading_level: level,
+                heading_text: paragraph.text.trim().to_string(),
+                cite_text: cite_text.map(str::to_string),
+                missing,
+                is_capture_file,
+            });
+        }
+    }
+
+    (sections_checked, findings)
+}
+
+fn capture_target_paths(connection: &Connection, root_id: i64) -> CommandResult<HashSet<String>> {
+    let mut statement = connection
+        .prepare("SELECT DISTINCT target_relative_path FROM captures WHERE root_id = ?1")
+        .map_err(|error| {
+            CommandError::database(format!("Could not prepare capture target query: {error}"))
+        })?;
+    let rows = statement
+        .query_map(params![root_id], |row| row.get::<_, String>(0))
+        .map_err(|error| {
+            CommandError::database(format!("Could not run capture target query: {error}"))
+        })?;
+
+    let mut targets =
+        HashSet::from([load_root_settings(connection, root_id)?.default_capture_target]);
+    for row in rows {
+        targets.insert(row.map_err(|error| {
+            CommandError::database(format!("Could not parse capture target: {error}"))
+        })?);
+    }
+    Ok(targets)
+}
+
+pub(crate) fn audit_root_citations(
+    connection: &Connection,
+    root_id: i64,
+) -> CommandResult<(usize, usize, Vec<CitationAuditItem>)> {
+    let capture_targets = capture_target_paths(connection, root_id)?;
+    let mut statement = connection
+        .prepare(
+            "SELECT id, relative_path, absolute_path FROM files WHERE root_id = ?1 ORDER BY relative_path ASC",
+        )
+        .map_err(|error| {
+            CommandError::database(format!("Could not prepare citation audit file query: {error}"))
+        })?;
+    let rows = statement
+        .query_map(params![root_id], |row| {
+            Ok((
+                row.get::<_, i64>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, String>(2)?,
+            ))
+        })
+        .map_err(|error| {
+            CommandError::database(format!("Could not run citation audit file query: {error}"))
+        })?;
+    let mut files = Vec::new();
+    for row in rows {
+        files.push(row.map_err(|error| {
+            CommandError::database(format!("Could not parse citation audit file: {error}"))
+        })?);
+    }
+
+    let audited = files
+        .par_iter()
+        .map(|(file_id, relative_path, absolute_path)| {
+            let paragraphs = parse_docx_paragraphs(Path::new(absolute_path)).unwrap_or_default();
+            audit_paragraphs(
+                &paragraphs,
+                *file_id,
+                relative_path,
+                capture_targets.contains(relative_path),
+            )
+        })
+        .collect::<Vec<(usize, Vec<CitationAuditItem>)>>();
+
+    let files_checked = files.len();
+    let mut sections_checked = 0_usize;
+    let mut items = Vec::new();
+    for (file_sections, file_items) in audited {
+        sections_checked += file_sections;
+        items.extend(file_items);
+    }
+
+    Ok((files_checked, sections_checked, items))
+}
@@ -0,0 +1,295 @@
+use std::collections::HashSet;
+use std::path::PathBuf;
+
+use rayon::prelude::*;
+use rusqlite::types::Value;
+use rusqlite::{params, params_from_iter, Connection};
+
+use crate::body_index::{scope_conditions, HIGHLIGHT_CLOSE, HIGHLIGHT_OPEN, SNIPPET_TOKENS};
+use crate::docx_parse::parse_docx_paragraphs;
+use crate::errors::CommandError;
+use crate::operations::OperationHandle;
+use crate::query_syntax::parse_query;
+use crate::search::SearchFilters;
+use crate::shards::{index_schemas, schema_for_file, schema_for_root};
+use crate::types::{CiteBlock, ParsedParagraph, SearchHit, SearchSettings};
+use crate::util::file_name_from_relative;
+use crate::CommandResult;
+
+/// Files parsed per batch in the cite phase, so cancellation is noticed
+/// between batches.
+const CITE_PHASE_BATCH_SIZE: usize = 64;
+
+/// Rows fetched per requested hit, leaving room for several cites under one
+/// heading.
+const CITE_FETCH_MULTIPLIER: usize = 4;
+
+struct PendingCiteFile {
+    id: i64,
+    absolute_path: PathBuf,
+    file_hash: String,
+}
+
+/// The F8 cites of `paragraphs`, one block per run of consecutive cite
+/// paragraphs (the way the preview groups them), each tagged with the heading
+/// above it.
+pub(crate) fn cite_blocks(paragraphs: &[ParsedParagraph]) -> Vec<CiteBlock> {
+    let mut heading_order = None;
+    let mut cites = Vec::<CiteBlock>::new();
+    let mut in_run = false;
+    for paragraph in paragraphs {
+        if paragraph.heading_level.is_some() {
+            heading_order = Some(paragraph.order);
+            in_run = false;
+            continue;
+        }
+        if !paragraph.is_f8_cite {
+            in_run = false;
+            continue;
+        }
+        let text = paragraph.text.trim();
+        if text.is_empty() {
+            continue;
+        }
+        match cites.last_mut().filter(|_| in_run) {
+            Some(cite) => {
+                cite.text.push(' ');
+                cite.text.push_str(text);
+            }
+            None => cites.push(CiteBlock {
+                paragraph_order: paragraph.order,
+                heading_order,
+                text: text.to_string(),
+            }),
+        }
+        in_run = true;
+    }
+    cites
+}
+
+/// Rewrites a file's cite rows and records the file hash they were read
+/// from; the `cites_fts` triggers keep the full-text index in step. An empty
+/// `cites_hash` leaves the file pending for the cite phase.
+pub(crate) fn replace_file_cites(
+    connection: &Connection,
+    file_id: i64,
+    cites: &[CiteBlock],
+    cites_hash: &str,
+) -> CommandResult<()> {
+    let schema = schema_for_file(file_id);
+    connection
+        .execute(
+            &format!("DELETE FROM {schema}.cites WHERE file_id = ?1"),
+            params![file_id],
+        )
+        .map_err(|error| {
+            CommandError::database(format!("Could not clear cites for file {file_id}: {error}"))
+        })?;
+
+    let mut insert_cite = connection
+        .prepare_cached(&format!(
+            "INSERT INTO {schema}.cites(file_id, paragraph_order, heading_order, text)
+             VALUES(?1, ?2, ?3, ?4)"
+        ))
+        .map_err(|error| {
+            CommandError::database(format!("Could not prepare cite insert: {error}"))
+        })?;
+    for cite in cites {
+        insert_cite
+            .execute(params![
+                file_id,
+                cite.paragraph_order,
+                cite.heading_order,
+                cite.text
+            ])
+            .map_err(|error| {
+                CommandError::database(format!("Could not insert cite for file {file_id}: {error}"))
+            })?;
+    }
+
+    connection
+        .execute(
+            &format!("UPDATE {schema}.files SET cites_hash = ?1 WHERE id = ?2"),
+            params![cites_hash, file_id],
+        )
+        .map_err(|error| {
+            CommandError::database(format!(
+                "Could not record cites for file {file_id}: {error}"
+            ))
+        })?;
+    Ok(())
+}
+
+/// Fills in cites for files whose rows predate their current hash, such as
+/// files indexed before cites were stored. The text cache does not keep
+/// paragraph styles, so these files are parsed again.
+pub(crate) fn run_cite_phase(
+    connection: &Connection,
+    root_id: i64,
+    operation: &OperationHandle,
+) -> CommandResult<usize> {
+    let mut statement = connection
+        .prepare(
+            "SELECT id, absolute_path, file_hash FROM files
+             WHERE root_id = ?1 AND cites_hash <> file_hash
+             ORDER BY relative_path",
+        )
+        .map_err(|error| {
+            CommandError::database(format!("Could not prepare pending cite query: {error}"))
+        })?;
+    let rows = statement
+        .query_map(params![root_id], |row| {
+            Ok(PendingCiteFile {
+                id: row.get(0)?,
+                absolute_path: PathBuf::from(row.get::<_, String>(1)?),
+                file_hash: row.get(2)?,
+            })
+        })
+        .map_err(|error| {
+            CommandError::database(format!("Could not run pending cite query: {error}"))
+        })?;
+    let mut pending = Vec::new();
+    for row in rows {
+        pending.push(row.map_err(|error| {
+            CommandError::database(format!("Could not parse pending cite row: {error}"))
+        })?);
+    }
+
+    for batch in pending.chunks(CITE_PHASE_BATCH_SIZE) {
+        operation.ensure_not_cancelled()?;
+        let extracted = batch
+            .par_iter()
+            .map(|file| {
+                parse_docx_paragraphs(&file.absolute_path)
+                    .map(|paragraphs| cite_blocks(&paragraphs))
+                    .unwrap_or_default()
+            })
+            .collect::<Vec<Vec<CiteBlock>>>();
+
+        for (file, cites) in batch.iter().zip(extracted) {
+            replace_file_cites(connection, file.id, &cites, &file.file_hash)?;
+        }
+    }
+
+    Ok(pending.len())
+}
+
+/// Cites whose text matches `query`, best first, as `cite` hits that point
+/// at the heading each cite sits under. Filters apply as for body hits, and
+/// scores start at the cite score base in `settings`.
+pub(crate) fn search(
+    connection: &Connection,
+    query: &str,
+    requested_root_id: Option<i64>,
+    limit: usize,
+    filters: &SearchFilters,
+    settings: &SearchSettings,
+) -> CommandResult<Vec<SearchHit>> {
+    if !filters.allows_kind("cite") {
+        return Ok(Vec::new());
+    }
+    let parsed = parse_query(query);
+    let Some(match_query) = parsed.to_fts5() else {
+        return Ok(Vec::new());
+    };
+    let schemas = match requested_root_id {
+        Some(root_id) => vec![schema_for_root(connection, root_id)?],
+        None => index_schemas(connection)?,
+    };
+
+    let mut ranked = Vec::new();
+    for schema in schemas {
+        let (scope_sql, scope_values) = scope_conditions(&parsed, &schema, 6);
+        let mut statement = connection
+            .prepare_cached(&format!(
+                "
+                SELECT
+                  f.id,
+                  f.relative_path,
+                  f.absolute_path,
+                  h.level,
+                  h.text,
+                  c.heading_order,
+                  snippet(cites_fts, 0, '{HIGHLIGHT_OPEN}', '{HIGHLIGHT_CLOSE}', '…', {SNIPPET_TOKENS}),
+                  bm25(cites_fts)
+                FROM {schema}.cites_fts
+                JOIN {schema}.cites c ON c.id = cites_fts.rowid
+                JOIN {schema}.files f ON f.id = c.file_id
+                LEFT JOIN {schema}.headings h
+                  ON h.file_id = c.file_id AND h.heading_order = c.heading_order
+                WHERE cites_fts MATCH ?1
+                  AND (?2 IS NULL OR f.root_id = ?2)
+                  AND (?4 IS NULL OR h.level >= ?4)
+                  AND (?5 IS NULL OR h.level <= ?5){scope_sql}
+                ORDER BY bm25(cites_fts)
+                LIMIT ?3
+                "
+            ))
+            .map_err(|error| {
+                CommandError::database(format!("Could not prepare cite search: {error}"))
+            })?;
+        let rows = statement
+            .query_map(
+                params_from_iter(
+                    [
+                        Value::Text(match_query.clone()),
+                        Value::from(requested_root_id),
+                        Value::from(
+                            i64::try_from(limit.saturating_mul(CITE_FETCH_MULTIPLIER))
+                                .unwrap_or(i64::MAX),
+                        ),
+                        Value::from(filters.min_level),
+                        Value::from(filters.max_level),
+                    ]
+                    .into_iter()
+                    .chain(scope_values),
+                ),
+                |row| {
+                    let relative_path = row.get::<_, String>(1)?;
+                    Ok((
+                        row.get::<_, f64>(7)?,
+                        SearchHit {
+                            source: "lexical".to_string(),
+                            kind: "cite".to_string(),
+                            file_id: row.get(0)?,
+                            file_name: file_name_from_relative(&relative_path),
+                            relative_path,
+                            absolute_path: row.get(2)?,
+                            heading_level: row.get(3)?,
+                            heading_text: row.get(4)?,
+                            heading_order: row.get(5)?,
+                            score: 0.0,
+                            snippet: row.get(6)?,
+                            word_count: None,
+                            underlined_word_count: None,
+                        },
+                    ))
+                },
+            )
+            .map_err(|error| CommandError::database(format!("Cite search failed: {error}")))?;
+        for row in rows {
+            ranked.push(row.map_err(|error| {
+                CommandError::database(format!("Could not parse cite search row: {error}"))
+            })?);
+        }
+    }
+
+    // bm25() is lower for better matches.
+    ranked.sort_by(|left, right| {
+        left.0
+            .partial_cmp(&right.0)
+            .unwrap_or(std::cmp::Ordering::Equal)
+    });
+    // One hit per card: the best cite under each heading.
+    let mut seen = HashSet::new();
+    ranked.retain(|(_, hit)| seen.insert((hit.file_id, hit.heading_order)));
+    ranked.truncate(limit);
+    Ok(ranked
+        .into_iter()
+        .enumerate()
+        .map(|(rank, (_, mut hit))| {
+            hit.score = settings.cite_score_base + f64::from(rank as u32);
+            hit
+        })
+        .collect())
+}
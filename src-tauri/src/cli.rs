@@ -0,0 +1,131 @@
+use serde::Serialize;
+use tauri::AppHandle;
+
+use crate::commands;
+use crate::errors::CommandError;
+use crate::query_engine;
+use crate::CommandResult;
+
+const CLI_USAGE: &str = "Usage:
+  blockfile index <folder>
+  blockfile search <query> [--root <folder>] [--limit <n>]
+  blockfile export opml <folder> <output.opml>
+  blockfile export markdown <file-id> <output.md>
+  blockfile export site <folder> <output-folder>
+  blockfile export headings-csv <folder> <output.csv>
+  blockfile export authors-csv <folder> <output.csv>
+  blockfile export captures-csv <folder> <output.csv>
+
+Results are printed as JSON on stdout; errors go to stderr with exit code 1.";
+
+const CLI_COMMANDS: [&str; 5] = ["index", "search", "export", "help", "--help"];
+
+/// True when the process was launched with a CLI subcommand rather than as
+/// the desktop app.
+pub(crate) fn is_cli_invocation(args: &[String]) -> bool {
+    args.get(1)
+        .map(|command| CLI_COMMANDS.contains(&command.as_str()))
+        .unwrap_or(false)
+}
+
+fn print_json<T: Serialize>(value: CommandResult<T>) -> CommandResult<()> {
+    let value = value?;
+    let raw = serde_json::to_string_pretty(&value)
+        .map_err(|error| CommandError::parse(format!("Could not encode output: {error}")))?;
+    println!("{raw}");
+    Ok(())
+}
+
+fn take_flag(args: &mut Vec<String>, flag: &str) -> CommandResult<Option<String>> {
+    let Some(position) = args.iter().position(|arg| arg == flag) else {
+        return Ok(None);
+    };
+    if position + 1 >= args.len() {
+        return Err(CommandError::validation(format!("{flag} needs a value.")));
+    }
+    let value = args.remove(position + 1);
+    args.remove(position);
+    Ok(Some(value))
+}
+
+fn positional<'a>(args: &'a [String], index: usize, name: &str) -> CommandResult<&'a str> {
+    args.get(index)
+        .map(String::as_str)
+        .ok_or_else(|| CommandError::validation(format!("Missing <{name}>.\n\n{CLI_USAGE}")))
+}
+
+fn run_export(app: &AppHandle, args: &[String]) -> CommandResult<()> {
+    let format = positional(args, 0, "format")?;
+    let source = positional(args, 1, "source")?.to_string();
+    let output = positional(args, 2, "output")?.to_string();
+    match format {
+        "opml" => print_json(commands::export_outline_opml(app.clone(), source, output)),
+        "markdown" | "md" => {
+            let file_id = source
+                .parse::<i64>()
+                .map_err(|_| CommandError::validation(format!("'{source}' is not a file id.")))?;
+            print_json(commands::export_file_markdown(app.clone(), file_id, output))
+        }
+        "site" | "html" => print_json(commands::export_static_site(app.clone(), source, output)),
+        "headings-csv" => print_json(commands::export_headings_csv(app.clone(), source, output)),
+        "authors-csv" => print_json(commands::export_authors_csv(app.clone(), source, output)),
+        "captures-csv" => print_json(commands::export_captures_csv(
+            app.clone(),
+            source,
+            output,
+            None,
+            None,
+        )),
+        other => Err(CommandError::validation(format!(
+            "Unknown export format '{other}'.\n\n{CLI_USAGE}"
+        ))),
+    }
+}
+
+fn run_command(app: &AppHandle, mut args: Vec<String>) -> CommandResult<()> {
+    let command = args.remove(0);
+    match command.as_str() {
+        "index" => {
+            let folder = positional(&args, 0, "folder")?.to_string();
+            print_json(commands::index_root_blocking(app.clone(), folder))
+        }
+        "search" => {
+            let root_path = take_flag(&mut args, "--root")?;
+            let limit = take_flag(&mut args, "--limit")?
+                .map(|value| {
+                    value.parse::<usize>().map_err(|_| {
+                        CommandError::validation(format!("'{value}' is not a valid limit."))
+                    })
+                })
+                .transpose()?;
+            if args.is_empty() {
+                return Err(CommandError::validation(format!(
+                    "Missing <query>.\n\n{CLI_USAGE}"
+                )));
+            }
+            let query = args.join(" ");
+            print_json(query_engine::search_lexical(
+                app, &query, root_path, limit, None,
+            ))
+        }
+        "export" => run_export(app, &args),
+        _ => {
+            println!("{CLI_USAGE}");
+            Ok(())
+        }
+    }
+}
+
+/// Runs a CLI invocation to completion and returns the process exit code.
+/// The backend resolves its data directory and emits progress through an
+/// `AppHandle`, so the caller still builds a Tauri app, just without windows;
+/// on Linux that needs a display (use `xvfb-run` on servers without one).
+pub(crate) fn run_cli(app: &AppHandle, args: Vec<String>) -> i32 {
+    match run_command(app, args.into_iter().skip(1).collect()) {
+        Ok(()) => 0,
+        Err(error) => {
+            eprintln!("{error}");
+            1
+        }
+    }
+}
@@ -1,33 +1,182 @@
 use std::collections::{HashMap, HashSet};
 use std::fs;
-use std::path::Path;
-use std::time::Instant;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
 
 use rayon::prelude::*;
-use rusqlite::{params, Connection};
+use rusqlite::{params, Connection, OptionalExtension};
 use tauri::AppHandle;
-use walkdir::WalkDir;
+use tauri_plugin_opener::OpenerExt;
 
+use crate::access::{
+    delete_allowed_location, guard_path, guarded_capture_path, load_allowed_locations,
+    store_allowed_location,
+};
+use crate::authors::{
+    clear_all_authors, list_root_authors, load_index_settings, replace_file_authors,
+    run_author_phase, store_index_settings,
+};
+use crate::block_pack::{
+    extract_block_pack, read_block_pack_manifest, restore_pack_tags, write_block_pack,
+    BLOCK_PACK_EXTENSION,
+};
+use crate::body_index::{
+    body_paragraphs, replace_body_paragraphs, run_body_phase, store_heading_previews,
+};
+use crate::capture_edit::{reorder_sections, rewrite_heading_levels, rewrite_paragraph_text};
+use crate::capture_outline::capture_outline;
+use crate::capture_replay::{
+    capture_already_recorded, list_capture_records, load_stored_capture, locate_replay_heading,
+    locate_replay_source, read_capture_log, replay_section_text, DEFAULT_CAPTURE_LIST_LIMIT,
+    MAX_CAPTURE_LIST_LIMIT,
+};
+use crate::capture_toc::{
+    load_capture_target_settings, store_capture_target_settings, sync_capture_toc,
+    write_table_of_contents,
+};
+use crate::capture_trash::{
+    delete_trash_entry, insert_trash_entry, list_trash_entries, load_trash_fragment,
+};
+use crate::capture_writer::{self, edit_capture_target};
 use crate::chunking::build_chunks;
+use crate::citations::audit_root_citations;
+use crate::cite_index::{cite_blocks, replace_file_cites, run_cite_phase};
+use crate::conflicts::{
+    load_sync_conflict_groups, record_sync_conflicts, resolve_group_prefer_newest,
+};
+use crate::csv_export::{
+    render_authors_csv, render_captures_csv, render_headings_csv, CSV_UTF8_BOM,
+};
+use crate::cut_queue::{
+    delete_cut_queue_row, insert_cut_queue_item, list_cut_queue_items, load_cut_queue_item,
+    normalize_cut_status, update_cut_queue_fields,
+};
 use crate::db::{add_or_get_root_id, load_existing_files, open_database, root_id};
+use crate::discovery::discover_docx_files;
+use crate::docx_build::{plain_text_paragraphs, write_converted_document};
 use crate::docx_capture::{
-    append_capture_to_docx, ensure_valid_capture_docx, extract_styled_section,
-    paragraph_xml_heading, rewrite_docx_with_parts,
+    append_capture_to_docx, append_captures_to_docx, collect_fragment_dependencies,
+    ensure_valid_capture_docx, extract_styled_section, fallback_styled_section,
+    paragraph_xml_heading, relationship_subset_xml, restore_fragment_into_docx,
+    rewrite_docx_with_parts, style_subset_xml,
+};
+use crate::docx_parse::{
+    build_heading_ranges, has_tag, parse_document_xml_paragraphs, parse_docx_paragraphs,
+    parse_insert_mode, read_docx_part, InsertMode,
+};
+use crate::errors::CommandError;
+use crate::event_feed::{publish_event, publish_search_event, FEED_EVENT_CAPTURE};
+use crate::file_activity::{
+    load_pinned_files, load_recent_files, record_file_open as store_file_open,
+    set_file_pinned as store_file_pinned, DEFAULT_RECENT_FILES_LIMIT,
+};
+use crate::file_stats::{
+    file_stats, heading_word_counts, load_heading_word_counts, load_root_stats, run_stats_phase,
+    run_word_count_phase, speech_length_estimate, store_file_stats, store_heading_word_counts,
+    DEFAULT_WORDS_PER_MINUTE,
+};
+use crate::google_drive::{
+    clear_drive_tokens, connect_drive, drive_status, store_drive_client, upload_docx_to_drive,
+};
+use crate::history::{load_file_history, record_heading_changes, sync_file_headings};
+use crate::html_import::convert_html_document;
+use crate::index_dump::{
+    load_index_dump, read_index_dump, render_index_dump_csv, render_index_dump_json,
+    restore_index_dump, INDEX_DUMP_FORMAT_CSV, INDEX_DUMP_FORMAT_JSON,
+};
+use crate::index_feed::{
+    load_index_feed, prune_index_changes, record_index_change, render_atom_feed, render_json_feed,
+    DEFAULT_FEED_RUNS, FEED_FORMAT_ATOM, FEED_FORMAT_JSON, INDEX_CHANGE_ADDED,
+    INDEX_CHANGE_CHANGED,
 };
-use crate::docx_parse::{build_heading_ranges, has_tag, parse_docx_paragraphs, read_docx_part};
+use crate::index_queue::{enqueue_roots, index_queue_busy, index_queue_status};
+use crate::index_rules::{load_root_exclusions, replace_root_exclusions, ExclusionRules};
 use crate::indexer::rebuild_lexical_index;
+use crate::intake::{delete_root_intake, list_root_intakes, upsert_root_intake};
 use crate::lexical;
+use crate::links::{
+    link_check_running, load_link_report, trigger_link_check, DEFAULT_LINK_HOST_INTERVAL_MS,
+    DEFAULT_LINK_RECHECK_AFTER_MS, LINK_STATUS_OK,
+};
+use crate::local_api::{
+    disable_local_api, enable_local_api, local_api_status, rotate_local_api_token,
+};
+use crate::markdown::{load_styled_paragraphs, render_docx_markdown, render_marked_text};
+use crate::merge_template::{generate_merged_document as write_merged_document, MergeSource};
+use crate::messages::{self, current_locale_settings, store_locale};
+use crate::near_duplicates::{find_near_duplicate_groups, DEFAULT_NEAR_DUPLICATE_THRESHOLD};
+use crate::operations::{active_operation_snapshots, request_cancel, OperationHandle};
+use crate::outline::{build_heading_tree, load_root_outline, render_outline_opml};
+use crate::pandoc::{
+    convert_docx_with_pandoc, pandoc_extension, pandoc_status, store_configured_path,
+};
+use crate::pdf_export::write_section_pdf;
 use crate::preview::{extract_heading_preview_html, extract_preview_content};
+use crate::profiles;
+use crate::profiling::{duration_ms, performance_report, record_index_run, set_profiling_enabled};
 use crate::query_engine;
-use crate::search::normalize_for_search;
+use crate::query_syntax::split_tag_filters;
+use crate::root_relocation::{
+    ensure_same_root_identity, relocate_root_rows, sample_relocated_files,
+};
+use crate::root_settings::{
+    capture_target_for_root, default_root_settings, load_root_settings, normalize_root_settings,
+    store_root_settings,
+};
+use crate::rtf_import::convert_rtf_document;
+use crate::search::{
+    ensure_current_search, next_search_generation, normalize_for_search, SearchChannel,
+    SearchFilters,
+};
+use crate::search_compile::{group_search_hits, write_search_compilation, SourceHeader};
+use crate::search_settings::{clamp_search_settings, load_search_settings, store_search_settings};
+use crate::session::{
+    load_last_capture_target, load_session_state, store_last_capture_target, store_last_location,
+};
+use crate::shards::{
+    remove_shard_files, root_is_sharded, schema_for_root, sharded_root_count, MAX_ROOT_SHARDS,
+};
+use crate::shared_roots::{
+    delete_path_mapping, ensure_root_identity, list_path_mappings as load_path_mappings,
+    portable_link, resolve_portable_link, resolve_shared_path, resolve_shared_root,
+    upsert_path_mapping,
+};
+use crate::similar_cards::{find_similar_cards, DEFAULT_SIMILAR_LIMIT, MAX_SIMILAR_LIMIT};
+use crate::site_export::write_static_site;
+use crate::snapshot_cache::{
+    cached_snapshot, clear_snapshots, invalidate_snapshot, store_snapshot,
+};
+use crate::tags::{
+    add_tag as store_tag, apply_tag_mapping, clean_tag, list_tag_summaries, load_file_tags,
+    read_tag_mapping, remove_tag as delete_tag,
+};
+use crate::text_cache::{compress_paragraph_text, store_file_text};
 use crate::types::*;
+use crate::usage::{
+    load_usage_metrics, purge_usage_counters, record_usage, store_usage_enabled, USAGE_CAPTURE,
+    USAGE_INDEX, USAGE_SEARCH_HYBRID, USAGE_SEARCH_LEXICAL, USAGE_SEARCH_SEMANTIC,
+};
 use crate::util::*;
+use crate::validation::{
+    self, input_path, normalize_capture_target_path, normalize_root_subfolder, optional_text,
+    readable_source_docx, required_body, required_text, CONTENT, HEADING_LEVEL, HEADING_TEXT,
+    MAX_ADDED_HEADING_LEVEL, MAX_CAPTURE_CONTENT_BYTES, MAX_CAPTURE_HEADING_LEVEL, MAX_TAG_CHARS,
+    MAX_TITLE_CHARS, ROOT_PATH, SECTION_TITLE, TAG, TITLE,
+};
+use crate::vault_export::write_obsidian_vault;
+use crate::verbatim_export::write_verbatim_outline;
+use crate::watch::{
+    delete_root_watch, load_watched_roots, restore_root_watches, start_watch, stop_all_watches,
+    stop_watch, store_root_watch,
+};
+use crate::xlsx_export::{snapshot_sheets, write_xlsx};
+use crate::xml_write::{body_blocks_for_paragraphs, write_document, write_fragment};
 use crate::CommandResult;
-use crate::DEFAULT_CAPTURE_TARGET;
 
 use crate::docx_capture::{fallback_body_insertion_index, insertion_index_after_paragraph_count};
 
-use roxmltree::{Document, Node};
+use roxmltree::{Document, Node, NodeId};
 
 #[tauri::command]
 pub(crate) fn add_root(app: AppHandle, path: String) -> CommandResult<AddRootResult> {
@@ -36,6 +185,7 @@ pub(crate) fn add_root(app: AppHandle, path: String) -> CommandResult<AddRootRes
 
     let connection = open_database(&app)?;
     let root_id = add_or_get_root_id(&connection, &canonical_string)?;
+    ensure_root_identity(&connection, root_id, &canonical)?;
 
     let marker_path = root_index_marker_path(&canonical);
     let marker_last_indexed_ms = fs::read_to_string(&marker_path)
@@ -51,7 +201,9 @@ pub(crate) fn add_root(app: AppHandle, path: String) -> CommandResult<AddRootRes
             |row| row.get::<_, i64>(0),
         )
         .map(|exists| exists != 0)
-        .map_err(|error| format!("Could not determine existing index rows: {error}"))?;
+        .map_err(|error| {
+            CommandError::database(format!("Could not determine existing index rows: {error}"))
+        })?;
 
     if !marker_path.is_file() {
         write_root_index_marker(&canonical, 0)?;
@@ -73,12 +225,25 @@ pub(crate) fn remove_root(app: AppHandle, path: String) -> CommandResult<()> {
         .map(|path| path_display(path))
         .unwrap_or(path);
     let connection = open_database(&app)?;
+    let removed_root_id = root_id(&connection, &canonical_string)?;
+    let sharded_root_id = match removed_root_id {
+        Some(id) if root_is_sharded(&connection, id)? => Some(id),
+        _ => None,
+    };
     connection
         .execute(
             "DELETE FROM roots WHERE path = ?1",
             params![canonical_string],
         )
-        .map_err(|error| format!("Could not remove root: {error}"))?;
+        .map_err(|error| CommandError::database(format!("Could not remove root: {error}")))?;
+    drop(connection);
+    stop_watch(&canonical_string);
+    if let Some(id) = removed_root_id {
+        invalidate_snapshot(id);
+    }
+    if let Some(id) = sharded_root_id {
+        remove_shard_files(&app, id)?;
+    }
 
     if let Some(root_path) = canonical_path {
         let marker_path = root_index_marker_path(&root_path);
@@ -88,8 +253,92 @@ pub(crate) fn remove_root(app: AppHandle, path: String) -> CommandResult<()> {
     Ok(())
 }
 
+/// Moves a root to the folder it now lives in, after a move or a drive letter
+/// change, keeping its index instead of rebuilding it. A random sample of the
+/// indexed files must be found under the new path, and the old path is kept
+/// as a mapping so links and captures that name it still resolve.
+#[tauri::command]
+pub(crate) fn relocate_root(
+    app: AppHandle,
+    old_path: String,
+    new_path: String,
+) -> CommandResult<RootRelocationResult> {
+    let mut connection = open_database(&app)?;
+    let old_path = old_path.trim().to_string();
+    let (root_id_value, old_root) = match root_id(&connection, &old_path)? {
+        Some(id) => (id, old_path),
+        None => {
+            let canonical = canonicalize_folder(&old_path)
+                .map(|path| path_display(&path))
+                .map_err(|_| CommandError::root_not_indexed(old_path.as_str()))?;
+            let id = root_id(&connection, &canonical)?
+                .ok_or_else(|| CommandError::root_not_indexed(old_path.as_str()))?;
+            (id, canonical)
+        }
+    };
+    let canonical_new = canonicalize_folder(&new_path)?;
+    let new_root = path_display(&canonical_new);
+    if new_root == old_root {
+        return Err(CommandError::validation_field(
+            "newPath",
+            "The root is already at this path.",
+        ));
+    }
+    if root_id(&connection, &new_root)?.is_some() {
+        return Err(CommandError::validation_field(
+            "newPath",
+            format!("'{new_root}' is already a root. Remove it before moving another root there."),
+        ));
+    }
+    ensure_same_root_identity(&connection, root_id_value, &canonical_new)?;
+    let (sampled_count, found_count) =
+        sample_relocated_files(&connection, root_id_value, &canonical_new)?;
+    if found_count * 2 < sampled_count {
+        return Err(CommandError::validation_field(
+            "newPath",
+            format!(
+                "Only {found_count} of {sampled_count} sampled files exist under '{new_root}'. Is this the right folder?"
+            ),
+        ));
+    }
+
+    let transaction = connection.transaction().map_err(|error| {
+        CommandError::database(format!(
+            "Could not start root relocation transaction: {error}"
+        ))
+    })?;
+    let file_count = relocate_root_rows(&transaction, root_id_value, &old_root, &new_root)?;
+    upsert_path_mapping(&transaction, &old_root, root_id_value)?;
+    transaction.commit().map_err(|error| {
+        CommandError::database(format!("Could not commit root relocation: {error}"))
+    })?;
+    ensure_root_identity(&connection, root_id_value, &canonical_new)?;
+
+    stop_watch(&old_root);
+    invalidate_snapshot(root_id_value);
+    if load_watched_roots(&connection)?
+        .iter()
+        .any(|watched| watched.root_path == new_root)
+    {
+        start_watch(&app, &new_root)?;
+    }
+    drop(connection);
+    rebuild_lexical_index(&app)?;
+    // Vector rows store absolute paths, so semantic hits would still point
+    // at the old folder until they are rebuilt.
+    crate::vector::trigger_rebuild(app.clone(), true);
+
+    Ok(RootRelocationResult {
+        root_path: new_root,
+        previous_path: old_root,
+        file_count,
+        sampled_count,
+        found_count,
+    })
+}
+
 #[tauri::command]
-pub(crate) fn insert_capture(
+pub(crate) async fn insert_capture(
     app: AppHandle,
     root_path: String,
     source_path: String,
@@ -100,19 +349,70 @@ pub(crate) fn insert_capture(
     heading_level: Option<i64>,
     heading_order: Option<i64>,
     selected_target_heading_order: Option<i64>,
+    insert_mode: Option<String>,
+) -> CommandResult<CaptureInsertResult> {
+    let request = CaptureRequest {
+        root_path,
+        source_path,
+        section_title,
+        content,
+        paragraph_xml,
+        target_path,
+        heading_level,
+        heading_order,
+        selected_target_heading_order,
+        insert_mode,
+    };
+    tauri::async_runtime::spawn_blocking(move || insert_capture_blocking(app, request))
+        .await
+        .map_err(|error| CommandError::internal(format!("Capture command failed: {error}")))?
+}
+
+/// Records a capture and appends it to its target on the calling thread.
+pub(crate) fn insert_capture_blocking(
+    app: AppHandle,
+    request: CaptureRequest,
 ) -> CommandResult<CaptureInsertResult> {
+    let CaptureRequest {
+        root_path,
+        source_path,
+        section_title,
+        content,
+        paragraph_xml,
+        target_path,
+        heading_level,
+        heading_order,
+        selected_target_heading_order,
+        insert_mode,
+    } = request;
     let content_value = content;
-    if content_value.trim().is_empty() {
-        return Err("Cannot insert empty content into capture file.".to_string());
-    }
+    input_path(ROOT_PATH, &root_path)?;
+    required_body(CONTENT, &content_value, MAX_CAPTURE_CONTENT_BYTES)?;
+    optional_text(SECTION_TITLE, &section_title, MAX_TITLE_CHARS)?;
+    let normalized_heading_level = heading_level
+        .map(|level| validation::heading_level(HEADING_LEVEL, level, MAX_CAPTURE_HEADING_LEVEL))
+        .transpose()?;
 
     let canonical_root = canonicalize_folder(&root_path)?;
-    let target_relative_path = normalize_capture_target_path(target_path.as_deref())?;
-    let normalized_heading_level = heading_level.filter(|level| (1..=9).contains(level));
     let normalized_target_heading_order = selected_target_heading_order.filter(|value| *value > 0);
+    let insert_mode = parse_insert_mode(insert_mode.as_deref())?;
     let root_path_string = path_display(&canonical_root);
     let connection = open_database(&app)?;
+    guard_path(&connection, &canonical_root)?;
     let root_id = add_or_get_root_id(&connection, &root_path_string)?;
+    let root_settings = load_root_settings(&connection, root_id)?;
+    let target_relative_path =
+        capture_target_for_root(&connection, root_id, target_path.as_deref())?;
+    // Captures queued on another machine name the source by its path there.
+    let source_path = if Path::new(&source_path).exists() {
+        source_path
+    } else {
+        resolve_shared_path(&connection, &source_path)?
+            .filter(|resolved| resolved.exists)
+            .map(|resolved| resolved.absolute_path)
+            .unwrap_or(source_path)
+    };
+    let readable_source = readable_source_docx(&connection, &source_path)?;
 
     let created_at_ms = now_ms();
     connection
@@ -139,11 +439,12 @@ pub(crate) fn insert_capture(
                 created_at_ms
             ],
         )
-        .map_err(|error| format!("Could not insert capture entry: {error}"))?;
+        .map_err(|error| {
+            CommandError::database(format!("Could not insert capture entry: {error}"))
+        })?;
 
     let capture_id = connection.last_insert_rowid();
-    let capture_path = capture_docx_path(&canonical_root, &target_relative_path);
-    let source_file_path = Path::new(&source_path);
+    let capture_path = guarded_capture_path(&connection, &canonical_root, &target_relative_path)?;
     let styled_section = paragraph_xml
         .and_then(|entries| {
             let cleaned = entries
@@ -159,22 +460,221 @@ pub(crate) fn insert_capture(
                     style_ids: HashSet::new(),
                     relationship_ids: HashSet::new(),
                     used_source_xml: false,
+                    source_styles_xml: None,
+                    source_relationships_xml: None,
+                    numbering_ids: HashSet::new(),
+                    source_numbering_xml: None,
+                    source_footnotes_xml: None,
+                    source_endnotes_xml: None,
+                    media_parts: Vec::new(),
                 })
             }
         })
-        .unwrap_or_else(|| extract_styled_section(source_file_path, heading_order, &content_value));
+        .unwrap_or_else(|| match &readable_source {
+            Some(source_file_path) => {
+                extract_styled_section(source_file_path, heading_order, &content_value)
+            }
+            None => fallback_styled_section(&content_value),
+        });
     append_capture_to_docx(
         &capture_path,
-        source_file_path,
         normalized_heading_level,
         normalized_target_heading_order,
+        insert_mode,
         &styled_section,
+        &root_settings,
+        load_capture_target_settings(&connection, root_id, &target_relative_path)?
+            .table_of_contents,
     )?;
+    store_last_capture_target(&connection, root_id, &target_relative_path)?;
 
-    Ok(CaptureInsertResult {
+    let result = CaptureInsertResult {
         capture_path: path_display(&capture_path),
         marker: capture_marker(capture_id),
         target_relative_path,
+    };
+    publish_event(
+        FEED_EVENT_CAPTURE,
+        &serde_json::json!({
+            "rootPath": root_path_string,
+            "sourcePath": source_path,
+            "sectionTitle": section_title,
+            "headingLevel": normalized_heading_level,
+            "capture": &result,
+        }),
+    );
+    record_usage(&app, USAGE_CAPTURE, None);
+    Ok(result)
+}
+
+#[tauri::command]
+pub(crate) async fn insert_captures_batch(
+    app: AppHandle,
+    root_path: String,
+    sections: Vec<CaptureBatchItem>,
+    target_path: Option<String>,
+    heading_level: Option<i64>,
+    selected_target_heading_order: Option<i64>,
+    insert_mode: Option<String>,
+) -> CommandResult<CaptureBatchResult> {
+    tauri::async_runtime::spawn_blocking(move || {
+        insert_captures_batch_blocking(
+            app,
+            root_path,
+            sections,
+            target_path,
+            heading_level,
+            selected_target_heading_order,
+            insert_mode,
+        )
+    })
+    .await
+    .map_err(|error| CommandError::internal(format!("Capture command failed: {error}")))?
+}
+
+/// Captures several indexed headings into one target: every section is
+/// extracted first, then all of them are spliced in at one place and the
+/// target is rewritten once.
+fn insert_captures_batch_blocking(
+    app: AppHandle,
+    root_path: String,
+    sections: Vec<CaptureBatchItem>,
+    target_path: Option<String>,
+    heading_level: Option<i64>,
+    selected_target_heading_order: Option<i64>,
+    insert_mode: Option<String>,
+) -> CommandResult<CaptureBatchResult> {
+    input_path(ROOT_PATH, &root_path)?;
+    if sections.is_empty() {
+        return Err(CommandError::validation(
+            "No sections were given to capture.",
+        ));
+    }
+    let normalized_heading_level = heading_level
+        .map(|level| validation::heading_level(HEADING_LEVEL, level, MAX_CAPTURE_HEADING_LEVEL))
+        .transpose()?;
+
+    let canonical_root = canonicalize_folder(&root_path)?;
+    let normalized_target_heading_order = selected_target_heading_order.filter(|value| *value > 0);
+    let insert_mode = parse_insert_mode(insert_mode.as_deref())?;
+    let root_path_string = path_display(&canonical_root);
+    let connection = open_database(&app)?;
+    guard_path(&connection, &canonical_root)?;
+    let root_id = add_or_get_root_id(&connection, &root_path_string)?;
+    let root_settings = load_root_settings(&connection, root_id)?;
+    let target_relative_path =
+        capture_target_for_root(&connection, root_id, target_path.as_deref())?;
+    let capture_path = guarded_capture_path(&connection, &canonical_root, &target_relative_path)?;
+
+    // Resolve every section before touching the target, so a missing heading
+    // fails the batch instead of leaving it half captured.
+    let mut captured = Vec::with_capacity(sections.len());
+    let mut styled_sections = Vec::with_capacity(sections.len());
+    for section in sections {
+        let Some(source_file_path) = readable_source_docx(&connection, &section.source_path)?
+        else {
+            return Err(CommandError::not_indexed(messages::message(
+                "error.headingGone",
+            )));
+        };
+        let (file_id, section_title) = connection
+            .query_row(
+                "
+                SELECT f.id, h.text
+                FROM files f
+                JOIN headings h ON h.file_id = f.id
+                WHERE f.absolute_path = ?1 AND h.heading_order = ?2
+                ",
+                params![section.source_path, section.heading_order],
+                |row| Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?)),
+            )
+            .optional()
+            .map_err(|error| {
+                CommandError::database(format!("Could not load capture heading: {error}"))
+            })?
+            .ok_or_else(|| CommandError::not_indexed(messages::message("error.headingGone")))?;
+        let content = replay_section_text(
+            &connection,
+            file_id,
+            &source_file_path,
+            section.heading_order,
+        )
+        .unwrap_or_else(|| section_title.clone());
+        styled_sections.push(extract_styled_section(
+            &source_file_path,
+            Some(section.heading_order),
+            &content,
+        ));
+        captured.push((section.source_path, section_title, content));
+    }
+
+    append_captures_to_docx(
+        &capture_path,
+        normalized_heading_level,
+        normalized_target_heading_order,
+        insert_mode,
+        &styled_sections,
+        &root_settings,
+        load_capture_target_settings(&connection, root_id, &target_relative_path)?
+            .table_of_contents,
+    )?;
+
+    let created_at_ms = now_ms();
+    let mut markers = Vec::with_capacity(captured.len());
+    for (source_path, section_title, content) in &captured {
+        connection
+            .execute(
+                "
+                INSERT INTO captures(
+                  root_id,
+                  source_path,
+                  section_title,
+                  target_relative_path,
+                  heading_level,
+                  content,
+                  created_at_ms
+                )
+                VALUES(?1, ?2, ?3, ?4, ?5, ?6, ?7)
+                ",
+                params![
+                    root_id,
+                    source_path,
+                    section_title,
+                    &target_relative_path,
+                    normalized_heading_level,
+                    content,
+                    created_at_ms
+                ],
+            )
+            .map_err(|error| {
+                CommandError::database(format!("Could not insert capture entry: {error}"))
+            })?;
+        markers.push(capture_marker(connection.last_insert_rowid()));
+    }
+    store_last_capture_target(&connection, root_id, &target_relative_path)?;
+
+    let capture_path = path_display(&capture_path);
+    for ((source_path, section_title, _), marker) in captured.iter().zip(&markers) {
+        publish_event(
+            FEED_EVENT_CAPTURE,
+            &serde_json::json!({
+                "rootPath": root_path_string,
+                "sourcePath": source_path,
+                "sectionTitle": section_title,
+                "headingLevel": normalized_heading_level,
+                "capture": CaptureInsertResult {
+                    capture_path: capture_path.clone(),
+                    marker: marker.clone(),
+                    target_relative_path: target_relative_path.clone(),
+                },
+            }),
+        );
+        record_usage(&app, USAGE_CAPTURE, None);
+    }
+    Ok(CaptureBatchResult {
+        capture_path,
+        markers,
+        target_relative_path,
     })
 }
 
@@ -187,9 +687,10 @@ pub(crate) fn list_capture_targets(
     let root_path_string = path_display(&canonical_root);
     let connection = open_database(&app)?;
     let root_id = add_or_get_root_id(&connection, &root_path_string)?;
+    let default_target = load_root_settings(&connection, root_id)?.default_capture_target;
 
     let mut by_target = HashMap::<String, i64>::new();
-    by_target.insert(DEFAULT_CAPTURE_TARGET.to_string(), 0);
+    by_target.insert(default_target.clone(), 0);
 
     let mut statement = connection
         .prepare(
@@ -201,17 +702,22 @@ pub(crate) fn list_capture_targets(
             ORDER BY target_relative_path ASC
             ",
         )
-        .map_err(|error| format!("Could not prepare capture targets query: {error}"))?;
+        .map_err(|error| {
+            CommandError::database(format!("Could not prepare capture targets query: {error}"))
+        })?;
 
     let rows = statement
         .query_map(params![root_id], |row| {
             Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)?))
         })
-        .map_err(|error| format!("Could not iterate capture targets query: {error}"))?;
+        .map_err(|error| {
+            CommandError::database(format!("Could not iterate capture targets query: {error}"))
+        })?;
 
     for row in rows {
-        let (target, count) =
-            row.map_err(|error| format!("Could not parse capture target row: {error}"))?;
+        let (target, count) = row.map_err(|error| {
+            CommandError::database(format!("Could not parse capture target row: {error}"))
+        })?;
         by_target.insert(target, count);
     }
 
@@ -229,293 +735,853 @@ pub(crate) fn list_capture_targets(
         .collect::<Vec<CaptureTarget>>();
 
     targets.sort_by(|left, right| {
-        (left.relative_path != DEFAULT_CAPTURE_TARGET)
-            .cmp(&(right.relative_path != DEFAULT_CAPTURE_TARGET))
+        (left.relative_path != default_target)
+            .cmp(&(right.relative_path != default_target))
             .then(left.relative_path.cmp(&right.relative_path))
     });
 
     Ok(targets)
 }
 
+/// `capture_target_for_root` for a root that may not have been added yet,
+/// which has no settings and so uses the shared default target.
+fn capture_target_for_path(
+    connection: &Connection,
+    canonical_root: &Path,
+    target_path: Option<&str>,
+) -> CommandResult<String> {
+    match root_id(connection, &path_display(canonical_root))? {
+        Some(id) => capture_target_for_root(connection, id, target_path),
+        None => normalize_capture_target_path(target_path),
+    }
+}
+
 fn capture_target_preview_for_path(
+    connection: &Connection,
     canonical_root: &Path,
     normalized_target: &str,
-) -> CaptureTargetPreview {
+) -> CommandResult<CaptureTargetPreview> {
     let absolute_path = capture_docx_path(canonical_root, normalized_target);
 
     if !absolute_path.is_file() {
-        return CaptureTargetPreview {
+        return Ok(CaptureTargetPreview {
             relative_path: normalized_target.to_string(),
             absolute_path: path_display(&absolute_path),
             exists: false,
             heading_count: 0,
             headings: Vec::new(),
-        };
+        });
     }
 
-    let (mut headings, _) = extract_preview_content(&absolute_path).unwrap_or_default();
-    headings.sort_by(|left, right| left.order.cmp(&right.order));
+    let headings = capture_outline(connection, &absolute_path).unwrap_or_default();
 
-    CaptureTargetPreview {
+    Ok(CaptureTargetPreview {
         relative_path: normalized_target.to_string(),
         absolute_path: path_display(&absolute_path),
         exists: true,
         heading_count: i64::try_from(headings.len()).unwrap_or(0),
         headings,
-    }
+    })
 }
 
 #[tauri::command]
 pub(crate) fn get_capture_target_preview(
-    _app: AppHandle,
+    app: AppHandle,
     root_path: String,
     target_path: String,
 ) -> CommandResult<CaptureTargetPreview> {
     let canonical_root = canonicalize_folder(&root_path)?;
     let normalized_target = normalize_capture_target_path(Some(&target_path))?;
-    Ok(capture_target_preview_for_path(
-        &canonical_root,
-        &normalized_target,
-    ))
+    let connection = open_database(&app)?;
+    guarded_capture_path(&connection, &canonical_root, &normalized_target)?;
+    capture_target_preview_for_path(&connection, &canonical_root, &normalized_target)
+}
+
+/// Writes captures still queued for a capture target to disk, for callers
+/// about to hand the file to another program.
+#[tauri::command]
+pub(crate) fn flush_capture_target(
+    app: AppHandle,
+    root_path: String,
+    target_path: String,
+) -> CommandResult<()> {
+    let canonical_root = canonicalize_folder(&root_path)?;
+    let normalized_target = normalize_capture_target_path(Some(&target_path))?;
+    let connection = open_database(&app)?;
+    let absolute_path = guarded_capture_path(&connection, &canonical_root, &normalized_target)?;
+    capture_writer::flush_capture_target(&absolute_path)
 }
 
 #[tauri::command]
 pub(crate) fn delete_capture_heading(
-    _app: AppHandle,
+    app: AppHandle,
     root_path: String,
     target_path: String,
     heading_order: i64,
 ) -> CommandResult<CaptureTargetPreview> {
     let canonical_root = canonicalize_folder(&root_path)?;
     let normalized_target = normalize_capture_target_path(Some(&target_path))?;
-    let absolute_path = capture_docx_path(&canonical_root, &normalized_target);
+    let connection = open_database(&app)?;
+    let absolute_path = guarded_capture_path(&connection, &canonical_root, &normalized_target)?;
 
     if !absolute_path.is_file() {
-        return Err(format!(
+        return Err(CommandError::not_found(format!(
             "Target capture file does not exist: {}",
             path_display(&absolute_path)
-        ));
+        )));
     }
 
-    ensure_valid_capture_docx(&absolute_path)?;
-    let paragraphs = parse_docx_paragraphs(&absolute_path)?;
-    let heading_ranges = build_heading_ranges(&paragraphs);
-    let target_range = heading_ranges
-        .iter()
-        .find(|range| range.order == heading_order)
-        .cloned()
-        .ok_or_else(|| format!("Heading order {heading_order} not found in target document."))?;
-
-    let document_xml = read_docx_part(&absolute_path, "word/document.xml")?.ok_or_else(|| {
-        format!(
-            "Missing word/document.xml in '{}'",
-            path_display(&absolute_path)
-        )
-    })?;
-    let document = Document::parse(&document_xml).map_err(|error| {
-        format!(
-            "Could not parse destination document XML '{}': {error}",
-            path_display(&absolute_path)
-        )
-    })?;
-    let paragraph_nodes = document
-        .descendants()
-        .filter(|node| has_tag(*node, "p"))
-        .collect::<Vec<Node<'_, '_>>>();
-
-    if target_range.start_index >= paragraph_nodes.len()
-        || target_range.end_index == 0
-        || target_range.end_index > paragraph_nodes.len()
-    {
-        return Err("Heading range is out of bounds in destination document.".to_string());
-    }
+    edit_capture_target(&absolute_path, || {
+        ensure_valid_capture_docx(&absolute_path)?;
+        let paragraphs = parse_document_xml_paragraphs(&absolute_path)?;
+        let heading_ranges = build_heading_ranges(&paragraphs);
+        let target_range = heading_ranges
+            .iter()
+            .find(|range| range.order == heading_order)
+            .cloned()
+            .ok_or_else(|| {
+                CommandError::not_indexed(format!(
+                    "Heading order {heading_order} not found in target document."
+                ))
+            })?;
 
-    let start = paragraph_nodes[target_range.start_index].range().start;
-    let end = paragraph_nodes[target_range.end_index - 1].range().end;
-    if start >= end || end > document_xml.len() {
-        return Err("Could not resolve heading XML range in destination document.".to_string());
-    }
+        let document_xml =
+            read_docx_part(&absolute_path, "word/document.xml")?.ok_or_else(|| {
+                CommandError::invalid_docx(
+                    &absolute_path,
+                    format!(
+                        "Missing word/document.xml in '{}'",
+                        path_display(&absolute_path)
+                    ),
+                )
+            })?;
+        let document = Document::parse(&document_xml).map_err(|error| {
+            CommandError::parse(format!(
+                "Could not parse destination document XML '{}': {error}",
+                path_display(&absolute_path)
+            ))
+        })?;
+        let paragraph_nodes = document
+            .descendants()
+            .filter(|node| has_tag(*node, "p"))
+            .collect::<Vec<Node<'_, '_>>>();
+
+        if target_range.start_index >= paragraph_nodes.len()
+            || target_range.end_index == 0
+            || target_range.end_index > paragraph_nodes.len()
+        {
+            return Err(CommandError::not_indexed(
+                "Heading range is out of bounds in destination document.",
+            ));
+        }
 
-    let mut updated_document_xml =
-        String::with_capacity(document_xml.len().saturating_sub(end.saturating_sub(start)));
-    updated_document_xml.push_str(&document_xml[..start]);
-    updated_document_xml.push_str(&document_xml[end..]);
+        let blocks = body_blocks_for_paragraphs(
+            &paragraph_nodes,
+            target_range.start_index,
+            target_range.end_index,
+        );
+        if blocks.is_empty() {
+            return Err(CommandError::parse(
+                "Could not resolve heading XML range in destination document.",
+            ));
+        }
 
-    let mut replacements = HashMap::new();
-    replacements.insert(
-        "word/document.xml".to_string(),
-        updated_document_xml.into_bytes(),
-    );
-    rewrite_docx_with_parts(&absolute_path, &replacements)?;
+        let fragment_xml = blocks
+            .iter()
+            .map(|node| write_fragment(*node))
+            .collect::<String>();
+        let (style_ids, relationship_ids) = collect_fragment_dependencies(&fragment_xml);
+        let styles_xml = read_docx_part(&absolute_path, "word/styles.xml")?.unwrap_or_default();
+        let relationships_xml =
+            read_docx_part(&absolute_path, "word/_rels/document.xml.rels")?.unwrap_or_default();
+        let trash_fragment = CaptureTrashFragment {
+            paragraph_offset: target_range.start_index,
+            fragment_xml,
+            styles_xml: style_subset_xml(&styles_xml, &style_ids),
+            relationships_xml: relationship_subset_xml(&relationships_xml, &relationship_ids),
+        };
+        let root_id = add_or_get_root_id(&connection, &path_display(&canonical_root))?;
+        // The trash row is committed only once the section is really gone, so a
+        // failed rewrite leaves nothing behind for restore to duplicate.
+        let transaction = connection.unchecked_transaction().map_err(|error| {
+            CommandError::database(format!("Could not start capture trash update: {error}"))
+        })?;
+        insert_trash_entry(
+            &transaction,
+            root_id,
+            &normalized_target,
+            target_range.level,
+            &paragraphs[target_range.start_index].text,
+            &trash_fragment,
+        )?;
+
+        let removed = blocks.iter().map(Node::id).collect::<HashSet<NodeId>>();
+        let updated_document_xml = write_document(&document, &removed);
+
+        let mut replacements = HashMap::new();
+        replacements.insert(
+            "word/document.xml".to_string(),
+            updated_document_xml.into_bytes(),
+        );
+        rewrite_docx_with_parts(&absolute_path, &replacements)?;
+        transaction.commit().map_err(|error| {
+            CommandError::database(format!("Could not commit capture trash entry: {error}"))
+        })?;
+        sync_capture_toc(&connection, root_id, &normalized_target, &absolute_path)
+    })?;
 
-    Ok(capture_target_preview_for_path(
-        &canonical_root,
-        &normalized_target,
-    ))
+    capture_target_preview_for_path(&connection, &canonical_root, &normalized_target)
 }
 
+/// Changes the text of one heading in a capture target, keeping its style and
+/// as much of its run formatting as the edit allows.
 #[tauri::command]
-pub(crate) fn move_capture_heading(
-    _app: AppHandle,
+pub(crate) fn edit_capture_heading(
+    app: AppHandle,
     root_path: String,
     target_path: String,
-    source_heading_order: i64,
-    target_heading_order: i64,
+    heading_order: i64,
+    new_text: String,
 ) -> CommandResult<CaptureTargetPreview> {
+    let trimmed_text =
+        required_text(HEADING_TEXT, &new_text, MAX_TITLE_CHARS)?.replace(['\r', '\n', '\t'], " ");
     let canonical_root = canonicalize_folder(&root_path)?;
     let normalized_target = normalize_capture_target_path(Some(&target_path))?;
-    let absolute_path = capture_docx_path(&canonical_root, &normalized_target);
-
-    if source_heading_order == target_heading_order {
-        return Ok(capture_target_preview_for_path(
-            &canonical_root,
-            &normalized_target,
-        ));
-    }
+    let connection = open_database(&app)?;
+    let absolute_path = guarded_capture_path(&connection, &canonical_root, &normalized_target)?;
 
     if !absolute_path.is_file() {
-        return Err(format!(
+        return Err(CommandError::not_found(format!(
             "Target capture file does not exist: {}",
             path_display(&absolute_path)
-        ));
+        )));
     }
 
-    ensure_valid_capture_docx(&absolute_path)?;
-    let paragraphs = parse_docx_paragraphs(&absolute_path)?;
-    let heading_ranges = build_heading_ranges(&paragraphs);
-
-    let source_range = heading_ranges
-        .iter()
-        .find(|range| range.order == source_heading_order)
-        .cloned()
-        .ok_or_else(|| {
-            format!("Source heading order {source_heading_order} not found in target document.")
-        })?;
-    let target_range = heading_ranges
-        .iter()
-        .find(|range| range.order == target_heading_order)
-        .cloned()
-        .ok_or_else(|| {
-            format!("Target heading order {target_heading_order} not found in target document.")
-        })?;
+    edit_capture_target(&absolute_path, || {
+        ensure_valid_capture_docx(&absolute_path)?;
+        let paragraphs = parse_document_xml_paragraphs(&absolute_path)?;
+        let target_range = build_heading_ranges(&paragraphs)
+            .into_iter()
+            .find(|range| range.order == heading_order)
+            .ok_or_else(|| {
+                CommandError::not_indexed(format!(
+                    "Heading order {heading_order} not found in target document."
+                ))
+            })?;
+        if paragraphs[target_range.start_index].text == trimmed_text {
+            return Ok(());
+        }
 
-    if target_range.start_index >= source_range.start_index
-        && target_range.start_index < source_range.end_index
-    {
-        return Err("Cannot move a heading into its own subtree.".to_string());
-    }
+        let document_xml =
+            read_docx_part(&absolute_path, "word/document.xml")?.ok_or_else(|| {
+                CommandError::invalid_docx(
+                    &absolute_path,
+                    format!(
+                        "Missing word/document.xml in '{}'",
+                        path_display(&absolute_path)
+                    ),
+                )
+            })?;
+        let updated_document_xml =
+            rewrite_paragraph_text(&document_xml, target_range.start_index, &trimmed_text)?;
 
-    let document_xml = read_docx_part(&absolute_path, "word/document.xml")?.ok_or_else(|| {
-        format!(
-            "Missing word/document.xml in '{}'",
-            path_display(&absolute_path)
-        )
-    })?;
-    let document = Document::parse(&document_xml).map_err(|error| {
-        format!(
-            "Could not parse destination document XML '{}': {error}",
-            path_display(&absolute_path)
-        )
+        let mut replacements = HashMap::new();
+        replacements.insert(
+            "word/document.xml".to_string(),
+            updated_document_xml.into_bytes(),
+        );
+        rewrite_docx_with_parts(&absolute_path, &replacements)?;
+        let root_id = add_or_get_root_id(&connection, &path_display(&canonical_root))?;
+        sync_capture_toc(&connection, root_id, &normalized_target, &absolute_path)
     })?;
-    let paragraph_nodes = document
-        .descendants()
-        .filter(|node| has_tag(*node, "p"))
-        .collect::<Vec<Node<'_, '_>>>();
-
-    if source_range.start_index >= paragraph_nodes.len()
-        || source_range.end_index == 0
-        || source_range.end_index > paragraph_nodes.len()
-        || target_range.start_index >= paragraph_nodes.len()
-        || target_range.end_index == 0
-        || target_range.end_index > paragraph_nodes.len()
-    {
-        return Err("Heading range is out of bounds in destination document.".to_string());
-    }
 
-    let source_start = paragraph_nodes[source_range.start_index].range().start;
-    let source_end = paragraph_nodes[source_range.end_index - 1].range().end;
-    if source_start >= source_end || source_end > document_xml.len() {
-        return Err("Could not resolve source heading XML range.".to_string());
-    }
+    capture_target_preview_for_path(&connection, &canonical_root, &normalized_target)
+}
 
-    let moved_fragment = document_xml[source_start..source_end].to_string();
-    let mut without_source =
-        String::with_capacity(document_xml.len() - (source_end - source_start));
-    without_source.push_str(&document_xml[..source_start]);
-    without_source.push_str(&document_xml[source_end..]);
+/// Re-files a heading in a capture target at `new_level`. With
+/// `include_descendants` the headings nested under it move by the same number
+/// of levels, so the block keeps its shape; levels stop at 1 and 9.
+#[tauri::command]
+pub(crate) fn set_capture_heading_level(
+    app: AppHandle,
+    root_path: String,
+    target_path: String,
+    heading_order: i64,
+    new_level: i64,
+    include_descendants: Option<bool>,
+) -> CommandResult<CaptureTargetPreview> {
+    let new_level = validation::heading_level(HEADING_LEVEL, new_level, MAX_CAPTURE_HEADING_LEVEL)?;
+    let canonical_root = canonicalize_folder(&root_path)?;
+    let normalized_target = normalize_capture_target_path(Some(&target_path))?;
+    let connection = open_database(&app)?;
+    let absolute_path = guarded_capture_path(&connection, &canonical_root, &normalized_target)?;
 
-    let source_len = source_range
-        .end_index
-        .saturating_sub(source_range.start_index);
-    let mut insertion_paragraph_count = target_range.end_index;
-    if source_range.start_index < target_range.end_index {
-        insertion_paragraph_count = insertion_paragraph_count.saturating_sub(source_len);
+    if !absolute_path.is_file() {
+        return Err(CommandError::not_found(format!(
+            "Target capture file does not exist: {}",
+            path_display(&absolute_path)
+        )));
     }
 
-    let insertion_index =
-        insertion_index_after_paragraph_count(&without_source, insertion_paragraph_count)
-            .unwrap_or(fallback_body_insertion_index(&without_source)?);
+    edit_capture_target(&absolute_path, || {
+        ensure_valid_capture_docx(&absolute_path)?;
+        let paragraphs = parse_document_xml_paragraphs(&absolute_path)?;
+        let target_range = build_heading_ranges(&paragraphs)
+            .into_iter()
+            .find(|range| range.order == heading_order)
+            .ok_or_else(|| {
+                CommandError::not_indexed(format!(
+                    "Heading order {heading_order} not found in target document."
+                ))
+            })?;
+        let shift = new_level - target_range.level;
+        if shift == 0 {
+            return Ok(());
+        }
+
+        let mut levels = HashMap::from([(target_range.start_index, new_level)]);
+        if include_descendants.unwrap_or(false) {
+            for (index, paragraph) in paragraphs
+                .iter()
+                .enumerate()
+                .take(target_range.end_index)
+                .skip(target_range.start_index + 1)
+            {
+                if let Some(level) = paragraph.heading_level {
+                    levels.insert(index, (level + shift).clamp(1, MAX_CAPTURE_HEADING_LEVEL));
+                }
+            }
+        }
 
-    let mut updated_document_xml =
-        String::with_capacity(without_source.len().saturating_add(moved_fragment.len()));
-    updated_document_xml.push_str(&without_source[..insertion_index]);
-    updated_document_xml.push_str(&moved_fragment);
-    updated_document_xml.push_str(&without_source[insertion_index..]);
+        let document_xml =
+            read_docx_part(&absolute_path, "word/document.xml")?.ok_or_else(|| {
+                CommandError::invalid_docx(
+                    &absolute_path,
+                    format!(
+                        "Missing word/document.xml in '{}'",
+                        path_display(&absolute_path)
+                    ),
+                )
+            })?;
+        let updated_document_xml = rewrite_heading_levels(&document_xml, &levels)?;
 
-    let mut replacements = HashMap::new();
-    replacements.insert(
-        "word/document.xml".to_string(),
-        updated_document_xml.into_bytes(),
-    );
-    rewrite_docx_with_parts(&absolute_path, &replacements)?;
+        let mut replacements = HashMap::new();
+        replacements.insert(
+            "word/document.xml".to_string(),
+            updated_document_xml.into_bytes(),
+        );
+        rewrite_docx_with_parts(&absolute_path, &replacements)?;
+        let root_id = add_or_get_root_id(&connection, &path_display(&canonical_root))?;
+        sync_capture_toc(&connection, root_id, &normalized_target, &absolute_path)
+    })?;
 
-    Ok(capture_target_preview_for_path(
-        &canonical_root,
-        &normalized_target,
-    ))
+    capture_target_preview_for_path(&connection, &canonical_root, &normalized_target)
 }
 
+/// Lays out several headings of a capture target, each with everything nested
+/// under it, in the order given, rewriting the document once. The sections
+/// swap among the places they hold now, so reordering siblings under one
+/// parent leaves the rest of the file untouched.
 #[tauri::command]
-pub(crate) fn add_capture_heading(
-    _app: AppHandle,
+pub(crate) fn reorder_capture_headings(
+    app: AppHandle,
     root_path: String,
     target_path: String,
-    heading_level: i64,
-    heading_text: String,
-    selected_target_heading_order: Option<i64>,
+    ordered_heading_orders: Vec<i64>,
+) -> CommandResult<CaptureTargetPreview> {
+    let canonical_root = canonicalize_folder(&root_path)?;
+    let normalized_target = normalize_capture_target_path(Some(&target_path))?;
+    let connection = open_database(&app)?;
+    let absolute_path = guarded_capture_path(&connection, &canonical_root, &normalized_target)?;
+
+    let mut seen = HashSet::new();
+    if let Some(duplicate) = ordered_heading_orders
+        .iter()
+        .find(|order| !seen.insert(**order))
+    {
+        return Err(CommandError::validation_field(
+            "orderedHeadingOrders",
+            format!("Heading order {duplicate} is listed more than once."),
+        ));
+    }
+
+    if !absolute_path.is_file() {
+        return Err(CommandError::not_found(format!(
+            "Target capture file does not exist: {}",
+            path_display(&absolute_path)
+        )));
+    }
+
+    edit_capture_target(&absolute_path, || {
+        ensure_valid_capture_docx(&absolute_path)?;
+        let paragraphs = parse_document_xml_paragraphs(&absolute_path)?;
+        let heading_ranges = build_heading_ranges(&paragraphs);
+        let sections = ordered_heading_orders
+            .iter()
+            .map(|order| {
+                heading_ranges
+                    .iter()
+                    .find(|range| range.order == *order)
+                    .map(|range| (range.start_index, range.end_index))
+                    .ok_or_else(|| {
+                        CommandError::not_indexed(format!(
+                            "Heading order {order} not found in target document."
+                        ))
+                    })
+            })
+            .collect::<CommandResult<Vec<(usize, usize)>>>()?;
+        if sections.windows(2).all(|pair| pair[0].0 < pair[1].0) {
+            return Ok(());
+        }
+
+        let document_xml =
+            read_docx_part(&absolute_path, "word/document.xml")?.ok_or_else(|| {
+                CommandError::invalid_docx(
+                    &absolute_path,
+                    format!(
+                        "Missing word/document.xml in '{}'",
+                        path_display(&absolute_path)
+                    ),
+                )
+            })?;
+        let updated_document_xml = reorder_sections(&document_xml, &sections)?;
+
+        let mut replacements = HashMap::new();
+        replacements.insert(
+            "word/document.xml".to_string(),
+            updated_document_xml.into_bytes(),
+        );
+        rewrite_docx_with_parts(&absolute_path, &replacements)?;
+        let root_id = add_or_get_root_id(&connection, &path_display(&canonical_root))?;
+        sync_capture_toc(&connection, root_id, &normalized_target, &absolute_path)
+    })?;
+
+    capture_target_preview_for_path(&connection, &canonical_root, &normalized_target)
+}
+
+#[tauri::command]
+pub(crate) fn get_capture_target_settings(
+    app: AppHandle,
+    root_path: String,
+    target_path: String,
+) -> CommandResult<CaptureTargetSettings> {
+    let canonical_root = canonicalize_folder(&root_path)?;
+    let normalized_target = normalize_capture_target_path(Some(&target_path))?;
+    let connection = open_database(&app)?;
+    let root_id = add_or_get_root_id(&connection, &path_display(&canonical_root))?;
+    load_capture_target_settings(&connection, root_id, &normalized_target)
+}
+
+/// Saves a capture file's options and applies them to the file straight
+/// away, adding or removing its table of contents.
+#[tauri::command]
+pub(crate) fn set_capture_target_settings(
+    app: AppHandle,
+    root_path: String,
+    target_path: String,
+    settings: CaptureTargetSettings,
+) -> CommandResult<CaptureTargetSettings> {
+    let canonical_root = canonicalize_folder(&root_path)?;
+    let normalized_target = normalize_capture_target_path(Some(&target_path))?;
+    let connection = open_database(&app)?;
+    let absolute_path = guarded_capture_path(&connection, &canonical_root, &normalized_target)?;
+    let root_id = add_or_get_root_id(&connection, &path_display(&canonical_root))?;
+    store_capture_target_settings(&connection, root_id, &normalized_target, &settings)?;
+    edit_capture_target(&absolute_path, || {
+        write_table_of_contents(&absolute_path, settings.table_of_contents)
+    })?;
+    Ok(settings)
+}
+
+/// A root's capture history, newest first, optionally for one target only.
+#[tauri::command]
+pub(crate) fn list_captures(
+    app: AppHandle,
+    root_path: String,
+    target_path: Option<String>,
+    limit: Option<usize>,
+    offset: Option<usize>,
+) -> CommandResult<Vec<CaptureRecord>> {
+    let canonical_root = canonicalize_folder(&root_path)?;
+    let normalized_target = target_path
+        .as_deref()
+        .map(|value| normalize_capture_target_path(Some(value)))
+        .transpose()?;
+    let connection = open_database(&app)?;
+    let root_id = add_or_get_root_id(&connection, &path_display(&canonical_root))?;
+    list_capture_records(
+        &connection,
+        root_id,
+        normalized_target.as_deref(),
+        limit
+            .unwrap_or(DEFAULT_CAPTURE_LIST_LIMIT)
+            .clamp(1, MAX_CAPTURE_LIST_LIMIT),
+        offset.unwrap_or(0),
+    )
+}
+
+/// Replays a stored capture into `new_target`, for a card captured to the
+/// wrong file. The stored content is written again, styled from the source
+/// heading while it can still be found; the original capture is left alone.
+#[tauri::command]
+pub(crate) async fn reinsert_capture(
+    app: AppHandle,
+    capture_id: i64,
+    new_target: String,
+) -> CommandResult<CaptureInsertResult> {
+    tauri::async_runtime::spawn_blocking(move || {
+        let connection = open_database(&app)?;
+        let capture = load_stored_capture(&connection, capture_id)?.ok_or_else(|| {
+            CommandError::not_found(format!("Capture {capture_id} was not found."))
+        })?;
+        let located =
+            match locate_replay_source(&connection, capture.root_id, &capture.source_path)? {
+                Some((file_id, source_path)) => locate_replay_heading(
+                    &connection,
+                    file_id,
+                    &capture.section_title,
+                    capture.heading_level,
+                )?
+                .map(|(heading_order, _)| (source_path, heading_order)),
+                None => None,
+            };
+        drop(connection);
+
+        let (source_path, heading_order) = match located {
+            Some((source_path, heading_order)) => (source_path, Some(heading_order)),
+            None => (capture.source_path, None),
+        };
+        insert_capture_blocking(
+            app,
+            CaptureRequest {
+                root_path: capture.root_path,
+                source_path,
+                section_title: capture.section_title,
+                content: capture.content,
+                paragraph_xml: None,
+                target_path: Some(new_target),
+                heading_level: capture.heading_level,
+                heading_order,
+                selected_target_heading_order: None,
+                insert_mode: None,
+            },
+        )
+    })
+    .await
+    .map_err(|error| CommandError::internal(format!("Reinsert capture command failed: {error}")))?
+}
+
+#[tauri::command]
+pub(crate) fn list_capture_trash(
+    app: AppHandle,
+    root_path: String,
+    target_path: Option<String>,
+) -> CommandResult<Vec<CaptureTrashEntry>> {
+    let canonical_root = canonicalize_folder(&root_path)?;
+    let normalized_target = target_path
+        .as_deref()
+        .map(|value| normalize_capture_target_path(Some(value)))
+        .transpose()?;
+    let connection = open_database(&app)?;
+    let root_id = add_or_get_root_id(&connection, &path_display(&canonical_root))?;
+    list_trash_entries(&connection, root_id, normalized_target.as_deref())
+}
+
+#[tauri::command]
+pub(crate) fn restore_capture_heading(
+    app: AppHandle,
+    root_path: String,
+    trash_id: i64,
+) -> CommandResult<CaptureTargetPreview> {
+    let canonical_root = canonicalize_folder(&root_path)?;
+    let connection = open_database(&app)?;
+    guard_path(&connection, &canonical_root)?;
+    let root_id = add_or_get_root_id(&connection, &path_display(&canonical_root))?;
+    let (target_relative_path, fragment) = load_trash_fragment(&connection, root_id, trash_id)?
+        .ok_or_else(|| CommandError::not_found(format!("Trash entry {trash_id} was not found.")))?;
+    let absolute_path = guarded_capture_path(&connection, &canonical_root, &target_relative_path)?;
+
+    edit_capture_target(&absolute_path, || {
+        let paragraph_count = if absolute_path.is_file() {
+            parse_document_xml_paragraphs(&absolute_path)
+                .map(|paragraphs| paragraphs.len())
+                .unwrap_or(0)
+        } else {
+            0
+        };
+        let after_paragraph_count =
+            (fragment.paragraph_offset <= paragraph_count).then_some(fragment.paragraph_offset);
+
+        restore_fragment_into_docx(
+            &absolute_path,
+            &fragment.fragment_xml,
+            &fragment.styles_xml,
+            &fragment.relationships_xml,
+            after_paragraph_count,
+        )?;
+        delete_trash_entry(&connection, trash_id)?;
+        sync_capture_toc(&connection, root_id, &target_relative_path, &absolute_path)
+    })?;
+
+    capture_target_preview_for_path(&connection, &canonical_root, &target_relative_path)
+}
+
+#[tauri::command]
+pub(crate) fn move_capture_heading(
+    app: AppHandle,
+    root_path: String,
+    target_path: String,
+    source_heading_order: i64,
+    target_heading_order: i64,
 ) -> CommandResult<CaptureTargetPreview> {
-    if !(1..=4).contains(&heading_level) {
-        return Err("Heading level must be H1, H2, H3, or H4.".to_string());
+    let canonical_root = canonicalize_folder(&root_path)?;
+    let normalized_target = normalize_capture_target_path(Some(&target_path))?;
+    let connection = open_database(&app)?;
+    let absolute_path = guarded_capture_path(&connection, &canonical_root, &normalized_target)?;
+
+    if source_heading_order == target_heading_order {
+        return capture_target_preview_for_path(&connection, &canonical_root, &normalized_target);
     }
 
-    let trimmed_text = heading_text.trim();
-    if trimmed_text.is_empty() {
-        return Err("Heading name cannot be empty.".to_string());
+    if !absolute_path.is_file() {
+        return Err(CommandError::not_found(format!(
+            "Target capture file does not exist: {}",
+            path_display(&absolute_path)
+        )));
     }
 
+    edit_capture_target(&absolute_path, || {
+        ensure_valid_capture_docx(&absolute_path)?;
+        let paragraphs = parse_document_xml_paragraphs(&absolute_path)?;
+        let heading_ranges = build_heading_ranges(&paragraphs);
+
+        let source_range = heading_ranges
+            .iter()
+            .find(|range| range.order == source_heading_order)
+            .cloned()
+            .ok_or_else(|| {
+                CommandError::not_indexed(format!(
+                    "Source heading order {source_heading_order} not found in target document."
+                ))
+            })?;
+        let target_range = heading_ranges
+            .iter()
+            .find(|range| range.order == target_heading_order)
+            .cloned()
+            .ok_or_else(|| {
+                CommandError::not_indexed(format!(
+                    "Target heading order {target_heading_order} not found in target document."
+                ))
+            })?;
+
+        if target_range.start_index >= source_range.start_index
+            && target_range.start_index < source_range.end_index
+        {
+            return Err(CommandError::validation(
+                "Cannot move a heading into its own subtree.",
+            ));
+        }
+
+        let document_xml =
+            read_docx_part(&absolute_path, "word/document.xml")?.ok_or_else(|| {
+                CommandError::invalid_docx(
+                    &absolute_path,
+                    format!(
+                        "Missing word/document.xml in '{}'",
+                        path_display(&absolute_path)
+                    ),
+                )
+            })?;
+        let document = Document::parse(&document_xml).map_err(|error| {
+            CommandError::parse(format!(
+                "Could not parse destination document XML '{}': {error}",
+                path_display(&absolute_path)
+            ))
+        })?;
+        let paragraph_nodes = document
+            .descendants()
+            .filter(|node| has_tag(*node, "p"))
+            .collect::<Vec<Node<'_, '_>>>();
+
+        if source_range.start_index >= paragraph_nodes.len()
+            || source_range.end_index == 0
+            || source_range.end_index > paragraph_nodes.len()
+            || target_range.start_index >= paragraph_nodes.len()
+            || target_range.end_index == 0
+            || target_range.end_index > paragraph_nodes.len()
+        {
+            return Err(CommandError::not_indexed(
+                "Heading range is out of bounds in destination document.",
+            ));
+        }
+
+        let source_blocks = body_blocks_for_paragraphs(
+            &paragraph_nodes,
+            source_range.start_index,
+            source_range.end_index,
+        );
+        if source_blocks.is_empty() {
+            return Err(CommandError::parse(
+                "Could not resolve source heading XML range.",
+            ));
+        }
+        let target_heading_node = paragraph_nodes[target_range.start_index];
+        if source_blocks.iter().any(|block| {
+            block
+                .descendants()
+                .any(|descendant| descendant == target_heading_node)
+        }) {
+            return Err(CommandError::validation(
+                "Cannot move a heading into its own subtree.",
+            ));
+        }
+
+        let moved_fragment = source_blocks
+            .iter()
+            .map(|node| write_fragment(*node))
+            .collect::<String>();
+        let moved_ids = source_blocks
+            .iter()
+            .map(Node::id)
+            .collect::<HashSet<NodeId>>();
+        let without_source = write_document(&document, &moved_ids);
+
+        // Whole blocks move, so count the paragraphs they really held.
+        let source_len = source_blocks
+            .iter()
+            .map(|node| {
+                node.descendants()
+                    .filter(|descendant| has_tag(*descendant, "p"))
+                    .count()
+            })
+            .sum::<usize>();
+        let source_first_index = paragraph_nodes
+            .iter()
+            .position(|node| {
+                source_blocks[0]
+                    .descendants()
+                    .any(|descendant| descendant == *node)
+            })
+            .unwrap_or(source_range.start_index);
+        let mut insertion_paragraph_count = target_range.end_index;
+        if source_first_index < target_range.end_index {
+            insertion_paragraph_count = insertion_paragraph_count.saturating_sub(source_len);
+        }
+
+        let insertion_index =
+            insertion_index_after_paragraph_count(&without_source, insertion_paragraph_count)
+                .unwrap_or(fallback_body_insertion_index(&without_source)?);
+
+        let mut updated_document_xml =
+            String::with_capacity(without_source.len().saturating_add(moved_fragment.len()));
+        updated_document_xml.push_str(&without_source[..insertion_index]);
+        updated_document_xml.push_str(&moved_fragment);
+        updated_document_xml.push_str(&without_source[insertion_index..]);
+
+        let mut replacements = HashMap::new();
+        replacements.insert(
+            "word/document.xml".to_string(),
+            updated_document_xml.into_bytes(),
+        );
+        rewrite_docx_with_parts(&absolute_path, &replacements)?;
+        let root_id = add_or_get_root_id(&connection, &path_display(&canonical_root))?;
+        sync_capture_toc(&connection, root_id, &normalized_target, &absolute_path)
+    })?;
+
+    capture_target_preview_for_path(&connection, &canonical_root, &normalized_target)
+}
+
+#[tauri::command]
+pub(crate) fn add_capture_heading(
+    app: AppHandle,
+    root_path: String,
+    target_path: String,
+    heading_level: i64,
+    heading_text: String,
+    selected_target_heading_order: Option<i64>,
+) -> CommandResult<CaptureTargetPreview> {
+    validation::heading_level(HEADING_LEVEL, heading_level, MAX_ADDED_HEADING_LEVEL)?;
+    let trimmed_text = required_text(HEADING_TEXT, &heading_text, MAX_TITLE_CHARS)?;
+
     let canonical_root = canonicalize_folder(&root_path)?;
     let normalized_target = normalize_capture_target_path(Some(&target_path))?;
-    let absolute_path = capture_docx_path(&canonical_root, &normalized_target);
+    let connection = open_database(&app)?;
+    let absolute_path = guarded_capture_path(&connection, &canonical_root, &normalized_target)?;
+    let root_id = add_or_get_root_id(&connection, &path_display(&canonical_root))?;
+    // An added heading keeps the level it was asked for.
+    let root_settings = RootSettings {
+        heading_level_shift: 0,
+        nest_child_captures: false,
+        ..load_root_settings(&connection, root_id)?
+    };
 
     let styled_section = StyledSection {
         paragraph_xml: vec![paragraph_xml_heading(heading_level, trimmed_text)],
         style_ids: HashSet::new(),
         relationship_ids: HashSet::new(),
         used_source_xml: false,
+        source_styles_xml: None,
+        source_relationships_xml: None,
+        numbering_ids: HashSet::new(),
+        source_numbering_xml: None,
+        source_footnotes_xml: None,
+        source_endnotes_xml: None,
+        media_parts: Vec::new(),
     };
 
     append_capture_to_docx(
-        &absolute_path,
         &absolute_path,
         Some(heading_level),
         selected_target_heading_order.filter(|value| *value > 0),
+        InsertMode::After,
         &styled_section,
+        &root_settings,
+        load_capture_target_settings(&connection, root_id, &normalized_target)?.table_of_contents,
     )?;
 
-    Ok(capture_target_preview_for_path(
-        &canonical_root,
-        &normalized_target,
-    ))
+    capture_target_preview_for_path(&connection, &canonical_root, &normalized_target)
+}
+
+#[tauri::command]
+pub(crate) fn get_session_state(app: AppHandle) -> CommandResult<SessionState> {
+    let connection = open_database(&app)?;
+    load_session_state(&connection)
+}
+
+#[tauri::command]
+pub(crate) fn set_session_location(
+    app: AppHandle,
+    root_path: Option<String>,
+    file_id: Option<i64>,
+    heading_order: Option<i64>,
+) -> CommandResult<SessionState> {
+    let root_path = root_path.map(|path| {
+        canonicalize_folder(&path)
+            .map(|canonical| path_display(&canonical))
+            .unwrap_or(path)
+    });
+    let connection = open_database(&app)?;
+    store_last_location(
+        &connection,
+        root_path.as_deref(),
+        file_id,
+        heading_order.filter(|order| *order > 0),
+    )?;
+    load_session_state(&connection)
+}
+
+#[tauri::command]
+pub(crate) fn set_session_capture_target(
+    app: AppHandle,
+    root_path: String,
+    target_path: String,
+) -> CommandResult<SessionState> {
+    let canonical_root = canonicalize_folder(&root_path)?;
+    let target_relative_path = normalize_capture_target_path(Some(&target_path))?;
+    let connection = open_database(&app)?;
+    let root_id = add_or_get_root_id(&connection, &path_display(&canonical_root))?;
+    store_last_capture_target(&connection, root_id, &target_relative_path)?;
+    load_session_state(&connection)
 }
 
 #[tauri::command]
@@ -534,12 +1600,16 @@ pub(crate) fn list_roots(app: AppHandle) -> CommandResult<Vec<RootSummary>> {
                 FROM headings h
                 JOIN files f ON f.id = h.file_id
                 WHERE f.root_id = r.id
-              ) AS heading_count
+              ) AS heading_count,
+              r.root_uid,
+              r.index_shard
             FROM roots r
             ORDER BY r.path
             ",
         )
-        .map_err(|error| format!("Could not prepare roots query: {error}"))?;
+        .map_err(|error| {
+            CommandError::database(format!("Could not prepare roots query: {error}"))
+        })?;
 
     let rows = statement
         .query_map([], |row| {
@@ -549,38 +1619,100 @@ pub(crate) fn list_roots(app: AppHandle) -> CommandResult<Vec<RootSummary>> {
                 last_indexed_ms: row.get(2)?,
                 file_count: row.get(3)?,
                 heading_count: row.get(4)?,
+                root_uid: row.get(5)?,
+                sharded: row.get::<_, i64>(6)? != 0,
             })
         })
-        .map_err(|error| format!("Could not iterate roots query: {error}"))?;
+        .map_err(|error| {
+            CommandError::database(format!("Could not iterate roots query: {error}"))
+        })?;
 
     let mut roots = Vec::new();
     for row in rows {
-        roots.push(row.map_err(|error| format!("Could not parse roots row: {error}"))?);
+        roots.push(row.map_err(|error| {
+            CommandError::database(format!("Could not parse roots row: {error}"))
+        })?);
     }
 
     Ok(roots)
 }
 
 #[tauri::command]
-pub(crate) fn index_root(app: AppHandle, path: String) -> CommandResult<IndexStats> {
-    let started_at = now_ms();
-    let canonical_root = canonicalize_folder(&path)?;
-    let root_path = path_display(&canonical_root);
+pub(crate) async fn index_root(app: AppHandle, path: String) -> CommandResult<IndexStats> {
+    tauri::async_runtime::spawn_blocking(move || index_root_blocking(app, path))
+        .await
+        .map_err(|error| CommandError::internal(format!("Index command failed: {error}")))?
+}
 
-    let mut connection = open_database(&app)?;
-    let root_id = add_or_get_root_id(&connection, &root_path)?;
-    let existing_files = load_existing_files(&connection, root_id)?;
+/// Word, card and cite totals for a root, summed from the counts each index
+/// run stores per file.
+#[tauri::command]
+pub(crate) fn get_root_stats(app: AppHandle, root_path: String) -> CommandResult<RootStats> {
+    let root_path = path_display(&canonicalize_folder(&root_path)?);
+    let connection = open_database(&app)?;
+    let id = root_id(&connection, &root_path)?
+        .ok_or_else(|| CommandError::root_not_indexed(root_path.as_str()))?;
+    load_root_stats(&connection, id, &root_path)
+}
 
-    let mut scanned = 0_usize;
-    let mut updated = 0_usize;
-    let mut skipped = 0_usize;
-    let mut removed = 0_usize;
-    let mut headings_extracted = 0_usize;
-    let mut seen_relative_paths = HashSet::new();
-    let mut indexing_candidates = Vec::new();
+/// Queues every registered root for a background index and returns at once.
+/// Progress arrives as `index-progress` per run, `index-queue-progress` per
+/// finished root and one `index-queue-finished` summary.
+#[tauri::command]
+pub(crate) fn index_all_roots(app: AppHandle) -> CommandResult<IndexQueueStatus> {
+    let connection = open_database(&app)?;
+    let mut statement = connection
+        .prepare("SELECT path FROM roots ORDER BY path")
+        .map_err(|error| {
+            CommandError::database(format!("Could not prepare roots query: {error}"))
+        })?;
+    let rows = statement
+        .query_map([], |row| row.get::<_, String>(0))
+        .map_err(|error| CommandError::database(format!("Could not query roots: {error}")))?;
+    let mut root_paths = Vec::new();
+    for row in rows {
+        root_paths.push(row.map_err(|error| {
+            CommandError::database(format!("Could not parse root row: {error}"))
+        })?);
+    }
+    enqueue_roots(&app, root_paths)
+}
 
+#[tauri::command]
+pub(crate) fn get_index_queue_status(app: AppHandle) -> CommandResult<IndexQueueStatus> {
+    index_queue_status(&app)
+}
+
+static ROOT_INDEX_LOCKS: OnceLock<Mutex<HashMap<String, Arc<Mutex<()>>>>> = OnceLock::new();
+
+/// The lock serializing index passes over one root, keyed by its canonical
+/// path so different spellings of a folder share it.
+fn root_index_lock(path: &str) -> Arc<Mutex<()>> {
+    let key = canonicalize_folder(path)
+        .map(|root| path_display(&root))
+        .unwrap_or_else(|_| path.to_string());
+    let mut locks = ROOT_INDEX_LOCKS
+        .get_or_init(|| Mutex::new(HashMap::new()))
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    Arc::clone(locks.entry(key).or_default())
+}
+
+/// Runs an index pass on the calling thread. The `index_root` command offloads
+/// this to a blocking worker; background callers use it directly.
+///
+/// Passes over the same root never overlap: the command, the index queue,
+/// the watcher and the intake poller can all ask for one, so a later caller
+/// waits for the running pass and then indexes whatever it left behind.
+pub(crate) fn index_root_blocking(app: AppHandle, path: String) -> CommandResult<IndexStats> {
+    let root_lock = root_index_lock(&path);
+    let _indexing = root_lock
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    let started_at = now_ms();
+    let mut operation = OperationHandle::start(&app, "index", Some(path.clone()), true);
     let mut progress = IndexProgress {
-        root_path: root_path.clone(),
+        root_path: path.clone(),
         phase: "discovering".to_string(),
         discovered: 0,
         changed: 0,
@@ -591,48 +1723,89 @@ pub(crate) fn index_root(app: AppHandle, path: String) -> CommandResult<IndexSta
         elapsed_ms: 0,
         current_file: None,
     };
+    match index_root_with_operation(&app, &path, started_at, &mut operation, &mut progress) {
+        Ok(stats) => {
+            operation.finish();
+            record_usage(&app, USAGE_INDEX, Some(stats.elapsed_ms));
+            Ok(stats)
+        }
+        Err(error) => {
+            // The run's transaction has already rolled back; tell listeners
+            // where it stopped.
+            if operation.is_cancelled() {
+                progress.phase = "cancelled".to_string();
+                progress.current_file = None;
+                let mut last_progress_emit_ms = 0_i64;
+                emit_index_progress(
+                    &app,
+                    &mut operation,
+                    started_at,
+                    &progress,
+                    &mut last_progress_emit_ms,
+                    true,
+                );
+            }
+            operation.fail(&error);
+            Err(error)
+        }
+    }
+}
+
+fn index_root_with_operation(
+    app: &AppHandle,
+    path: &str,
+    started_at: i64,
+    operation: &mut OperationHandle,
+    progress: &mut IndexProgress,
+) -> CommandResult<IndexStats> {
+    let canonical_root = canonicalize_folder(path)?;
+    let root_path = path_display(&canonical_root);
+
+    let mut connection = open_database(app)?;
+    let root_id = add_or_get_root_id(&connection, &root_path)?;
+    ensure_root_identity(&connection, root_id, &canonical_root)?;
+    let index_schema = schema_for_root(&connection, root_id)?;
+    invalidate_snapshot(root_id);
+    let existing_files = load_existing_files(&connection, root_id)?;
+    let extract_authors = load_index_settings(&connection)?.extract_authors;
+
+    let mut scanned = 0_usize;
+    let mut updated = 0_usize;
+    let mut skipped = 0_usize;
+    let mut removed = 0_usize;
+    let mut headings_extracted = 0_usize;
+    let mut seen_relative_paths = HashSet::new();
+    let mut indexing_candidates = Vec::new();
+
+    progress.root_path = root_path.clone();
     let mut last_progress_emit_ms = 0_i64;
     emit_index_progress(
-        &app,
+        app,
+        operation,
         started_at,
-        &progress,
+        progress,
         &mut last_progress_emit_ms,
         true,
     );
 
-    for entry in WalkDir::new(&canonical_root)
-        .follow_links(false)
-        .into_iter()
-        .filter_entry(is_visible_entry)
-    {
-        let Ok(entry) = entry else {
-            continue;
-        };
-
-        if !entry.file_type().is_file() {
-            continue;
-        }
-
-        let is_docx = entry
-            .path()
-            .extension()
-            .and_then(|extension| extension.to_str())
-            .map(|extension| extension.eq_ignore_ascii_case("docx"))
-            .unwrap_or(false);
-        if !is_docx {
-            continue;
-        }
-
+    let discovery_started = Instant::now();
+    let exclusions = ExclusionRules::new(&load_root_exclusions(&connection, root_id)?);
+    let (discovered_paths, excluded) =
+        discover_docx_files(&canonical_root, &exclusions, operation)?;
+    let walk_time = discovery_started.elapsed();
+    let mut metadata_time = Duration::ZERO;
+    for absolute_path in discovered_paths {
+        operation.ensure_not_cancelled()?;
         scanned += 1;
-        let absolute_path = entry.path().to_path_buf();
         let relative_path_value = relative_path(&canonical_root, &absolute_path)?;
         seen_relative_paths.insert(relative_path_value.clone());
 
+        let metadata_started = Instant::now();
         let metadata = fs::metadata(&absolute_path).map_err(|error| {
-            format!(
+            CommandError::io(format!(
                 "Could not read metadata for '{}': {error}",
                 path_display(&absolute_path)
-            )
+            ))
         })?;
         let modified_ms = metadata.modified().map(epoch_ms).unwrap_or(0);
         let size = i64::try_from(metadata.len()).unwrap_or(0);
@@ -667,15 +1840,17 @@ pub(crate) fn index_root(app: AppHandle, path: String) -> CommandResult<IndexSta
                 file_hash,
             });
         }
+        metadata_time += metadata_started.elapsed();
 
         progress.discovered = scanned;
         progress.changed = indexing_candidates.len();
         progress.skipped = skipped;
         progress.current_file = Some(relative_path_value);
         emit_index_progress(
-            &app,
+            app,
+            operation,
             started_at,
-            &progress,
+            progress,
             &mut last_progress_emit_ms,
             false,
         );
@@ -695,22 +1870,35 @@ pub(crate) fn index_root(app: AppHandle, path: String) -> CommandResult<IndexSta
     progress.changed = indexing_candidates.len();
     progress.skipped = skipped;
     emit_index_progress(
-        &app,
+        app,
+        operation,
         started_at,
-        &progress,
+        progress,
         &mut last_progress_emit_ms,
         true,
     );
 
     let parse_chunk_size = suggested_parse_chunk_size();
-    let transaction = connection
-        .transaction()
-        .map_err(|error| format!("Could not start index transaction: {error}"))?;
+    // Every write below goes through this transaction, so an early return,
+    // cancellation included, drops it and rolls the whole run back.
+    let transaction = connection.transaction().map_err(|error| {
+        CommandError::database(format!("Could not start index transaction: {error}"))
+    })?;
+    let cancel = operation.cancel_token();
 
+    let mut parse_time = Duration::ZERO;
+    let mut db_write_time = Duration::ZERO;
     for chunk in indexing_candidates.chunks(parse_chunk_size) {
+        operation.ensure_not_cancelled()?;
+        let parse_started = Instant::now();
         let parsed_chunk = chunk
             .par_iter()
             .map(|candidate| {
+                // Files not yet started are skipped once a cancel arrives,
+                // rather than finishing the whole chunk.
+                if cancel.is_cancelled() {
+                    return None;
+                }
                 let paragraphs =
                     parse_docx_paragraphs(&candidate.absolute_path).unwrap_or_default();
                 let headings = paragraphs
@@ -723,18 +1911,31 @@ pub(crate) fn index_root(app: AppHandle, path: String) -> CommandResult<IndexSta
                         })
                     })
                     .collect::<Vec<ParsedHeading>>();
-                let authors = extract_author_candidates(&paragraphs);
+                let authors = if extract_authors {
+                    extract_author_candidates(&paragraphs)
+                } else {
+                    Vec::new()
+                };
                 let chunks = build_chunks(&paragraphs);
-                ParsedIndexCandidate {
+                let text_zstd = compress_paragraph_text(&paragraphs).unwrap_or_default();
+                Some(ParsedIndexCandidate {
                     candidate: candidate.clone(),
                     headings,
                     authors,
                     chunks,
-                }
+                    paragraph_count: paragraphs.len(),
+                    text_zstd,
+                    body: body_paragraphs(&paragraphs),
+                    cites: cite_blocks(&paragraphs),
+                    heading_word_counts: heading_word_counts(&paragraphs),
+                })
             })
-            .collect::<Vec<ParsedIndexCandidate>>();
+            .collect::<Vec<Option<ParsedIndexCandidate>>>();
+        parse_time += parse_started.elapsed();
+        operation.ensure_not_cancelled()?;
 
-        for parsed in parsed_chunk {
+        let write_started = Instant::now();
+        for parsed in parsed_chunk.into_iter().flatten() {
             let relative_path_value = parsed.candidate.relative_path;
             let absolute_path_string = path_display(&parsed.candidate.absolute_path);
             let modified_ms = parsed.candidate.modified_ms;
@@ -747,9 +1948,11 @@ pub(crate) fn index_root(app: AppHandle, path: String) -> CommandResult<IndexSta
             let file_id = if let Some(existing) = existing_files.get(&relative_path_value) {
                 transaction
                     .execute(
-                        "UPDATE files
-                         SET absolute_path = ?1, modified_ms = ?2, size = ?3, file_hash = ?4, heading_count = ?5
-                         WHERE id = ?6",
+                        &format!(
+                            "UPDATE {index_schema}.files
+                             SET absolute_path = ?1, modified_ms = ?2, size = ?3, file_hash = ?4, heading_count = ?5
+                             WHERE id = ?6"
+                        ),
                         params![
                             absolute_path_string,
                             modified_ms,
@@ -759,18 +1962,18 @@ pub(crate) fn index_root(app: AppHandle, path: String) -> CommandResult<IndexSta
                             existing.id
                         ],
                     )
-                    .map_err(|error| {
-                        format!(
+                    .map_err(|error| CommandError::database(format!(
                             "Could not update indexed file '{}': {error}",
                             relative_path_value
-                        )
-                    })?;
+                        )))?;
                 existing.id
             } else {
                 transaction
                     .execute(
-                        "INSERT INTO files(root_id, relative_path, absolute_path, modified_ms, size, file_hash, heading_count)
-                         VALUES(?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                        &format!(
+                            "INSERT INTO {index_schema}.files(root_id, relative_path, absolute_path, modified_ms, size, file_hash, heading_count)
+                             VALUES(?1, ?2, ?3, ?4, ?5, ?6, ?7)"
+                        ),
                         params![
                             root_id,
                             relative_path_value.as_str(),
@@ -781,130 +1984,145 @@ pub(crate) fn index_root(app: AppHandle, path: String) -> CommandResult<IndexSta
                             heading_count
                         ],
                     )
-                    .map_err(|error| {
-                        format!(
+                    .map_err(|error| CommandError::database(format!(
                             "Could not insert indexed file '{}': {error}",
                             relative_path_value
-                        )
-                    })?;
+                        )))?;
                 transaction.last_insert_rowid()
             };
 
-            transaction
-                .execute("DELETE FROM headings WHERE file_id = ?1", params![file_id])
-                .map_err(|error| {
-                    format!(
-                        "Could not clear old headings for '{}': {error}",
-                        relative_path_value
-                    )
-                })?;
-
-            transaction
-                .execute("DELETE FROM authors WHERE file_id = ?1", params![file_id])
-                .map_err(|error| {
-                    format!(
-                        "Could not clear old author rows for '{}': {error}",
-                        relative_path_value
-                    )
-                })?;
+            // A root's first index is not news, so only later runs feed "what's new".
+            // Neither is the first read of a file restored from an index dump,
+            // which is stored without a hash.
+            let restored = existing_files
+                .get(&relative_path_value)
+                .is_some_and(|existing| existing.file_hash.is_empty());
+            if !existing_files.is_empty() && !restored {
+                let change_kind = if existing_files.contains_key(&relative_path_value) {
+                    INDEX_CHANGE_CHANGED
+                } else {
+                    INDEX_CHANGE_ADDED
+                };
+                record_index_change(
+                    &transaction,
+                    root_id,
+                    started_at,
+                    change_kind,
+                    &relative_path_value,
+                    heading_count,
+                )?;
+            }
 
             transaction
-                .execute("DELETE FROM chunks WHERE file_id = ?1", params![file_id])
+                .execute(
+                    &format!("DELETE FROM {index_schema}.chunks WHERE file_id = ?1"),
+                    params![file_id],
+                )
                 .map_err(|error| {
-                    format!(
+                    CommandError::database(format!(
                         "Could not clear old chunks for '{}': {error}",
                         relative_path_value
-                    )
+                    ))
                 })?;
 
-            for heading in parsed.headings {
-                let normalized = normalize_for_search(&heading.text);
-                transaction
-                    .execute(
-                        "INSERT INTO headings(file_id, heading_order, level, text, normalized, file_name, relative_path)
-                         VALUES(?1, ?2, ?3, ?4, ?5, ?6, ?7)",
-                        params![
-                            file_id,
-                            heading.order,
-                            heading.level,
-                            heading.text,
-                            normalized,
-                            file_name.as_str(),
-                            relative_path_value.as_str()
-                        ],
-                    )
-                    .map_err(|error| {
-                        format!(
-                            "Could not insert heading for '{}': {error}",
-                            relative_path_value
-                        )
-                    })?;
+            store_file_text(
+                &transaction,
+                file_id,
+                parsed.paragraph_count,
+                &parsed.text_zstd,
+            )?;
+            replace_body_paragraphs(&transaction, file_id, &parsed.body)?;
+            replace_file_cites(
+                &transaction,
+                file_id,
+                &parsed.cites,
+                parsed.candidate.file_hash.as_str(),
+            )?;
+            store_file_stats(
+                &transaction,
+                file_id,
+                &file_stats(&parsed.body, parsed.cites.len()),
+            )?;
+
+            let heading_changes = sync_file_headings(
+                &transaction,
+                file_id,
+                &file_name,
+                &relative_path_value,
+                &parsed.headings,
+            )?;
+            if existing_files.contains_key(&relative_path_value) {
+                record_heading_changes(&transaction, file_id, started_at, &heading_changes)?;
             }
-
-            for (author_order, author_text) in parsed.authors {
-                let normalized_author = normalize_for_search(&author_text);
-                transaction
-                    .execute(
-                        "INSERT INTO authors(file_id, author_order, text, normalized, file_name, relative_path)
-                         VALUES(?1, ?2, ?3, ?4, ?5, ?6)",
-                        params![
-                            file_id,
-                            author_order,
-                            author_text,
-                            normalized_author,
-                            file_name.as_str(),
-                            relative_path_value.as_str()
-                        ],
+            store_heading_previews(
+                &transaction,
+                file_id,
+                parsed.headings.iter().map(|heading| heading.order),
+                &parsed.body,
+            )?;
+            store_heading_word_counts(&transaction, file_id, &parsed.heading_word_counts)?;
+
+            // Author rows written here are current for this file's hash, so the
+            // author phase below skips it.
+            replace_file_authors(
+                &transaction,
+                file_id,
+                &relative_path_value,
+                &parsed.authors,
+                if extract_authors {
+                    parsed.candidate.file_hash.as_str()
+                } else {
+                    ""
+                },
+            )?;
+
+            // Chunk rows are rebuilt per file; their insert reuses one cached
+            // statement instead of re-preparing the SQL for every row.
+            let mut insert_chunk = transaction
+                .prepare_cached(&format!(
+                    "
+                    INSERT INTO {index_schema}.chunks(
+                      chunk_id,
+                      root_id,
+                      file_id,
+                      chunk_order,
+                      heading_order,
+                      heading_level,
+                      heading_text,
+                      author_text,
+                      chunk_text,
+                      file_name,
+                      relative_path,
+                      absolute_path
                     )
-                    .map_err(|error| {
-                        format!(
-                            "Could not insert author metadata for '{}': {error}",
-                            relative_path_value
-                        )
-                    })?;
-            }
-
+                    VALUES(?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)
+                    "
+                ))
+                .map_err(|error| {
+                    CommandError::database(format!("Could not prepare chunk insert: {error}"))
+                })?;
             for chunk in parsed.chunks {
                 let chunk_id = format!("{}:{}:{}", root_id, file_id, chunk.chunk_order);
-                transaction
-                    .execute(
-                        "
-                        INSERT INTO chunks(
-                          chunk_id,
-                          root_id,
-                          file_id,
-                          chunk_order,
-                          heading_order,
-                          heading_level,
-                          heading_text,
-                          author_text,
-                          chunk_text,
-                          file_name,
-                          relative_path,
-                          absolute_path
-                        )
-                        VALUES(?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)
-                        ",
-                        params![
-                            chunk_id,
-                            root_id,
-                            file_id,
-                            chunk.chunk_order,
-                            chunk.heading_order,
-                            chunk.heading_level,
-                            chunk.heading_text,
-                            chunk.author_text,
-                            chunk.chunk_text,
-                            file_name.as_str(),
-                            relative_path_value.as_str(),
-                            absolute_path_string.as_str()
-                        ],
-                    )
+                insert_chunk
+                    .execute(params![
+                        chunk_id,
+                        root_id,
+                        file_id,
+                        chunk.chunk_order,
+                        chunk.heading_order,
+                        chunk.heading_level,
+                        chunk.heading_text,
+                        chunk.author_text,
+                        chunk.chunk_text,
+                        file_name.as_str(),
+                        relative_path_value.as_str(),
+                        absolute_path_string.as_str()
+                    ])
                     .map_err(|error| {
-                        format!(
+                        CommandError::database(format!(
                             "Could not insert chunk row for '{}': {error}",
                             relative_path_value
-                        )
+                        ))
                     })?;
             }
 
@@ -913,47 +2131,122 @@ pub(crate) fn index_root(app: AppHandle, path: String) -> CommandResult<IndexSta
             progress.updated = updated;
             progress.current_file = Some(relative_path_value);
             emit_index_progress(
-                &app,
+                app,
+                operation,
                 started_at,
-                &progress,
+                progress,
                 &mut last_progress_emit_ms,
                 false,
             );
         }
+        db_write_time += write_started.elapsed();
+    }
+
+    let mut author_files_updated = 0_usize;
+    if extract_authors {
+        progress.phase = "authors".to_string();
+        progress.current_file = None;
+        emit_index_progress(
+            app,
+            operation,
+            started_at,
+            progress,
+            &mut last_progress_emit_ms,
+            true,
+        );
+        author_files_updated = run_author_phase(&transaction, root_id, operation)?;
     }
 
+    progress.phase = "body".to_string();
+    progress.current_file = None;
+    emit_index_progress(
+        app,
+        operation,
+        started_at,
+        progress,
+        &mut last_progress_emit_ms,
+        true,
+    );
+    run_body_phase(&transaction, root_id, operation)?;
+
+    progress.phase = "cites".to_string();
+    progress.current_file = None;
+    emit_index_progress(
+        app,
+        operation,
+        started_at,
+        progress,
+        &mut last_progress_emit_ms,
+        true,
+    );
+    run_cite_phase(&transaction, root_id, operation)?;
+
+    progress.phase = "stats".to_string();
+    progress.current_file = None;
+    emit_index_progress(
+        app,
+        operation,
+        started_at,
+        progress,
+        &mut last_progress_emit_ms,
+        true,
+    );
+    run_stats_phase(&transaction, root_id, operation)?;
+
+    progress.phase = "wordCounts".to_string();
+    progress.current_file = None;
+    emit_index_progress(
+        app,
+        operation,
+        started_at,
+        progress,
+        &mut last_progress_emit_ms,
+        true,
+    );
+    run_word_count_phase(&transaction, root_id, operation)?;
+
+    let cleanup_started = Instant::now();
     progress.phase = "cleaning".to_string();
     progress.current_file = None;
     emit_index_progress(
-        &app,
+        app,
+        operation,
         started_at,
-        &progress,
+        progress,
         &mut last_progress_emit_ms,
         true,
     );
 
     for (relative_path_value, file_id) in stale_entries {
         transaction
-            .execute("DELETE FROM files WHERE id = ?1", params![file_id])
+            .execute(
+                &format!("DELETE FROM {index_schema}.files WHERE id = ?1"),
+                params![file_id],
+            )
             .map_err(|error| {
-                format!(
+                CommandError::database(format!(
                     "Could not remove stale index row '{}': {error}",
                     relative_path_value
-                )
+                ))
             })?;
         removed += 1;
 
         progress.removed = removed;
         progress.current_file = Some(relative_path_value);
         emit_index_progress(
-            &app,
+            app,
+            operation,
             started_at,
-            &progress,
+            progress,
             &mut last_progress_emit_ms,
             false,
         );
     }
 
+    operation.ensure_not_cancelled()?;
+    let conflicts_detected = record_sync_conflicts(&transaction, root_id)?;
+    prune_index_changes(&transaction, root_id)?;
+
     let finished_at_ms = now_ms();
 
     transaction
@@ -961,15 +2254,31 @@ pub(crate) fn index_root(app: AppHandle, path: String) -> CommandResult<IndexSta
             "UPDATE roots SET last_indexed_ms = ?1 WHERE id = ?2",
             params![finished_at_ms, root_id],
         )
-        .map_err(|error| format!("Could not update root index timestamp: {error}"))?;
+        .map_err(|error| {
+            CommandError::database(format!("Could not update root index timestamp: {error}"))
+        })?;
 
-    transaction
-        .commit()
-        .map_err(|error| format!("Could not commit index transaction: {error}"))?;
+    transaction.commit().map_err(|error| {
+        CommandError::database(format!("Could not commit index transaction: {error}"))
+    })?;
+    db_write_time += cleanup_started.elapsed();
 
     write_root_index_marker(&canonical_root, finished_at_ms)?;
 
-    rebuild_lexical_index(&app)?;
+    let fts_started = Instant::now();
+    rebuild_lexical_index(app)?;
+    record_index_run(IndexPhaseTimings {
+        root_path: root_path.clone(),
+        started_at_ms: started_at,
+        files_scanned: scanned,
+        files_parsed: updated,
+        walk_ms: duration_ms(walk_time),
+        metadata_ms: duration_ms(metadata_time),
+        parse_ms: duration_ms(parse_time),
+        db_write_ms: duration_ms(db_write_time),
+        fts_ms: duration_ms(fts_started.elapsed()),
+        total_ms: duration_ms(discovery_started.elapsed()),
+    });
 
     progress.phase = "complete".to_string();
     progress.current_file = None;
@@ -980,9 +2289,10 @@ pub(crate) fn index_root(app: AppHandle, path: String) -> CommandResult<IndexSta
     progress.skipped = skipped;
     progress.removed = removed;
     emit_index_progress(
-        &app,
+        app,
+        operation,
         started_at,
-        &progress,
+        progress,
         &mut last_progress_emit_ms,
         true,
     );
@@ -996,6 +2306,9 @@ pub(crate) fn index_root(app: AppHandle, path: String) -> CommandResult<IndexSta
         skipped,
         removed,
         headings_extracted,
+        conflicts_detected,
+        author_files_updated,
+        excluded,
         elapsed_ms: finished_at_ms - started_at,
     })
 }
@@ -1045,6 +2358,177 @@ fn ensure_folder_with_ancestors(folders: &mut HashMap<String, FolderEntry>, fold
     }
 }
 
+/// Moves a root's index into its own database file, or back into the shared
+/// one. The root is reindexed from scratch in its new location, so its heading
+/// history starts over.
+#[tauri::command]
+pub(crate) async fn set_root_sharding(
+    app: AppHandle,
+    path: String,
+    enabled: bool,
+) -> CommandResult<IndexStats> {
+    tauri::async_runtime::spawn_blocking(move || set_root_sharding_blocking(app, path, enabled))
+        .await
+        .map_err(|error| CommandError::internal(format!("Shard command failed: {error}")))?
+}
+
+fn set_root_sharding_blocking(
+    app: AppHandle,
+    path: String,
+    enabled: bool,
+) -> CommandResult<IndexStats> {
+    let root_path = path_display(&canonicalize_folder(&path)?);
+    let connection = open_database(&app)?;
+    let id = root_id(&connection, &root_path)?
+        .ok_or_else(|| CommandError::root_not_indexed(root_path.as_str()))?;
+    let was_sharded = root_is_sharded(&connection, id)?;
+    if was_sharded == enabled {
+        drop(connection);
+        return index_root_blocking(app, path);
+    }
+    if enabled && sharded_root_count(&connection)? >= MAX_ROOT_SHARDS {
+        return Err(CommandError::validation(format!(
+            "At most {MAX_ROOT_SHARDS} roots can have their own index database"
+        )));
+    }
+
+    if !was_sharded {
+        connection
+            .execute("DELETE FROM main.files WHERE root_id = ?1", params![id])
+            .map_err(|error| {
+                CommandError::database(format!("Could not clear shared index rows: {error}"))
+            })?;
+    }
+    connection
+        .execute(
+            "UPDATE roots SET index_shard = ?1 WHERE id = ?2",
+            params![i64::from(enabled), id],
+        )
+        .map_err(|error| {
+            CommandError::database(format!("Could not save root shard setting: {error}"))
+        })?;
+    drop(connection);
+
+    // A fresh shard must not pick up rows from an earlier one.
+    remove_shard_files(&app, id)?;
+    index_root_blocking(app, path)
+}
+
+/// Throws away a root's index rows and indexes it again. For a sharded root the
+/// shard file is deleted outright, so a corrupted shard is recovered without
+/// touching other roots.
+#[tauri::command]
+pub(crate) async fn rebuild_root_index(app: AppHandle, path: String) -> CommandResult<IndexStats> {
+    tauri::async_runtime::spawn_blocking(move || rebuild_root_index_blocking(app, path))
+        .await
+        .map_err(|error| CommandError::internal(format!("Rebuild command failed: {error}")))?
+}
+
+fn rebuild_root_index_blocking(app: AppHandle, path: String) -> CommandResult<IndexStats> {
+    let root_path = path_display(&canonicalize_folder(&path)?);
+    let connection = open_database(&app)?;
+    let id = root_id(&connection, &root_path)?
+        .ok_or_else(|| CommandError::root_not_indexed(root_path.as_str()))?;
+    if root_is_sharded(&connection, id)? {
+        drop(connection);
+        remove_shard_files(&app, id)?;
+    } else {
+        connection
+            .execute("DELETE FROM main.files WHERE root_id = ?1", params![id])
+            .map_err(|error| {
+                CommandError::database(format!("Could not clear index rows: {error}"))
+            })?;
+    }
+    index_root_blocking(app, path)
+}
+
+#[tauri::command]
+pub(crate) fn get_index_settings(app: AppHandle) -> CommandResult<IndexSettings> {
+    let connection = open_database(&app)?;
+    load_index_settings(&connection)
+}
+
+/// Saves index settings. Turning author extraction off drops the stored author
+/// rows right away; turning it back on fills them in on the next index run.
+#[tauri::command]
+pub(crate) fn set_index_settings(
+    app: AppHandle,
+    extract_authors: bool,
+) -> CommandResult<IndexSettings> {
+    let connection = open_database(&app)?;
+    let previous = load_index_settings(&connection)?;
+    let settings = IndexSettings { extract_authors };
+    store_index_settings(&connection, &settings)?;
+    if previous.extract_authors && !extract_authors {
+        clear_all_authors(&connection)?;
+        drop(connection);
+        rebuild_lexical_index(&app)?;
+    }
+    Ok(settings)
+}
+
+#[tauri::command]
+pub(crate) fn get_search_settings(app: AppHandle) -> CommandResult<SearchSettings> {
+    let connection = open_database(&app)?;
+    load_search_settings(&connection)
+}
+
+/// Saves ranking settings, clamped into range, and drops cached results
+/// ranked under the old ones. Returns what was stored.
+#[tauri::command]
+pub(crate) fn set_search_settings(
+    app: AppHandle,
+    settings: SearchSettings,
+) -> CommandResult<SearchSettings> {
+    let connection = open_database(&app)?;
+    let settings = clamp_search_settings(settings);
+    store_search_settings(&connection, &settings)?;
+    query_engine::clear_query_cache();
+    Ok(settings)
+}
+
+#[tauri::command]
+pub(crate) fn get_root_settings(app: AppHandle, root_path: String) -> CommandResult<RootSettings> {
+    let canonical_root = canonicalize_folder(&root_path)?;
+    let connection = open_database(&app)?;
+    match root_id(&connection, &path_display(&canonical_root))? {
+        Some(id) => load_root_settings(&connection, id),
+        None => Ok(default_root_settings()),
+    }
+}
+
+/// Saves how captures into a root are laid out. The default target is
+/// normalized like any capture target; returns what was stored.
+#[tauri::command]
+pub(crate) fn set_root_settings(
+    app: AppHandle,
+    root_path: String,
+    settings: RootSettings,
+) -> CommandResult<RootSettings> {
+    let canonical_root = canonicalize_folder(&root_path)?;
+    let settings = normalize_root_settings(settings)?;
+    let connection = open_database(&app)?;
+    guard_path(&connection, &canonical_root)?;
+    let root_id = add_or_get_root_id(&connection, &path_display(&canonical_root))?;
+    store_root_settings(&connection, root_id, &settings)?;
+    Ok(settings)
+}
+
+/// Distinct authors in a root with how many cards cite each, best first.
+/// `prefix` narrows to surnames starting with it, for autocomplete.
+#[tauri::command]
+pub(crate) fn list_authors(
+    app: AppHandle,
+    root_path: String,
+    prefix: Option<String>,
+) -> CommandResult<Vec<AuthorEntry>> {
+    let root_path = path_display(&canonicalize_folder(&root_path)?);
+    let connection = open_database(&app)?;
+    let id = root_id(&connection, &root_path)?
+        .ok_or_else(|| CommandError::root_not_indexed(root_path.as_str()))?;
+    list_root_authors(&connection, id, prefix.as_deref().unwrap_or_default())
+}
+
 #[tauri::command]
 pub(crate) fn get_index_snapshot(app: AppHandle, path: String) -> CommandResult<IndexSnapshot> {
     let canonical_path = canonicalize_folder(&path)
@@ -1052,95 +2536,2121 @@ pub(crate) fn get_index_snapshot(app: AppHandle, path: String) -> CommandResult<
         .unwrap_or(path);
 
     let connection = open_database(&app)?;
-    let root_id = root_id(&connection, &canonical_path)?.ok_or_else(|| {
-        format!(
-            "No index found for '{}'. Add the folder first.",
-            canonical_path
-        )
-    })?;
-
-    let indexed_at_ms = connection
+    let root_id = root_id(&connection, &canonical_path)?
+        .ok_or_else(|| CommandError::root_not_indexed(canonical_path.as_str()))?;
+
+    let indexed_at_ms = connection
+        .query_row(
+            "SELECT last_indexed_ms FROM roots WHERE id = ?1",
+            params![root_id],
+            |row| row.get::<_, i64>(0),
+        )
+        .map_err(|error| {
+            CommandError::database(format!("Could not read root timestamp: {error}"))
+        })?;
+
+    // Folder aggregation over a large root is the slow part of opening the
+    // file browser; reuse it until the next index run.
+    if let Some(snapshot) = cached_snapshot(root_id, indexed_at_ms) {
+        return Ok(IndexSnapshot::clone(&snapshot));
+    }
+
+    let mut statement = connection
+        .prepare(
+            "
+            SELECT id, relative_path, modified_ms, heading_count
+            FROM files
+            WHERE root_id = ?1
+            ORDER BY relative_path
+            ",
+        )
+        .map_err(|error| {
+            CommandError::database(format!("Could not prepare file snapshot query: {error}"))
+        })?;
+
+    let rows = statement
+        .query_map(params![root_id], |row| {
+            Ok(FileRecord {
+                id: row.get(0)?,
+                relative_path: row.get(1)?,
+                modified_ms: row.get(2)?,
+                heading_count: row.get(3)?,
+            })
+        })
+        .map_err(|error| {
+            CommandError::database(format!("Could not iterate indexed files: {error}"))
+        })?;
+
+    let mut files = Vec::new();
+    let mut folders = HashMap::new();
+    ensure_folder_with_ancestors(&mut folders, "");
+
+    for row in rows {
+        let record = row.map_err(|error| {
+            CommandError::database(format!("Could not parse indexed file row: {error}"))
+        })?;
+        let folder_path = folder_from_relative(&record.relative_path);
+        ensure_folder_with_ancestors(&mut folders, &folder_path);
+
+        let mut current_folder = folder_path.clone();
+        loop {
+            if let Some(folder_entry) = folders.get_mut(&current_folder) {
+                folder_entry.file_count += 1;
+            }
+
+            if current_folder.is_empty() {
+                break;
+            }
+
+            current_folder = current_folder
+                .rsplit_once('/')
+                .map(|(parent, _)| parent.to_string())
+                .unwrap_or_default();
+        }
+
+        files.push(IndexedFile {
+            id: record.id,
+            file_name: file_name_from_relative(&record.relative_path),
+            relative_path: record.relative_path,
+            folder_path,
+            modified_ms: record.modified_ms,
+            heading_count: record.heading_count,
+        });
+    }
+
+    let mut folder_values = folders.into_values().collect::<Vec<FolderEntry>>();
+    folder_values.sort_by(|left, right| {
+        left.depth
+            .cmp(&right.depth)
+            .then(left.path.cmp(&right.path))
+    });
+
+    let snapshot = Arc::new(IndexSnapshot {
+        root_path: canonical_path,
+        indexed_at_ms,
+        folders: folder_values,
+        files,
+    });
+    store_snapshot(root_id, Arc::clone(&snapshot));
+    Ok(IndexSnapshot::clone(&snapshot))
+}
+
+#[tauri::command]
+pub(crate) fn get_sync_conflict_report(
+    app: AppHandle,
+    root_path: String,
+) -> CommandResult<Vec<SyncConflictGroup>> {
+    let canonical_root = canonicalize_folder(&root_path)?;
+    let root_path_string = path_display(&canonical_root);
+    let connection = open_database(&app)?;
+    let Some(root_id) = root_id(&connection, &root_path_string)? else {
+        return Ok(Vec::new());
+    };
+    load_sync_conflict_groups(&connection, root_id)
+}
+
+#[tauri::command]
+pub(crate) fn get_near_duplicate_headings(
+    app: AppHandle,
+    root_path: String,
+    threshold: Option<f64>,
+    limit: Option<usize>,
+) -> CommandResult<NearDuplicateReport> {
+    let canonical_root = canonicalize_folder(&root_path)?;
+    let root_path_string = path_display(&canonical_root);
+    let connection = open_database(&app)?;
+    let root_id = root_id(&connection, &root_path_string)?
+        .ok_or_else(|| CommandError::root_not_indexed(root_path_string.as_str()))?;
+
+    let threshold = threshold
+        .filter(|value| value.is_finite())
+        .map(|value| value.clamp(0.5, 0.99))
+        .unwrap_or(DEFAULT_NEAR_DUPLICATE_THRESHOLD);
+    let (headings_compared, mut groups) =
+        find_near_duplicate_groups(&connection, root_id, threshold)?;
+    if let Some(limit) = limit {
+        groups.truncate(limit);
+    }
+
+    Ok(NearDuplicateReport {
+        root_path: root_path_string,
+        threshold,
+        headings_compared,
+        groups,
+    })
+}
+
+/// How long the given headings of a file take to read at `words_per_minute`,
+/// in full and underlined words only, from the word counts kept at index
+/// time.
+#[tauri::command]
+pub(crate) fn estimate_speech_length(
+    app: AppHandle,
+    file_id: i64,
+    heading_orders: Vec<i64>,
+    words_per_minute: Option<f64>,
+) -> CommandResult<SpeechLengthEstimate> {
+    let words_per_minute = words_per_minute.unwrap_or(DEFAULT_WORDS_PER_MINUTE);
+    if !words_per_minute.is_finite() || words_per_minute <= 0.0 {
+        return Err(CommandError::validation_field(
+            "wordsPerMinute",
+            "Words per minute must be a positive number.",
+        ));
+    }
+    let connection = open_database(&app)?;
+    let (word_count, underlined_word_count) =
+        load_heading_word_counts(&connection, file_id, &heading_orders)?;
+    Ok(speech_length_estimate(
+        word_count,
+        underlined_word_count,
+        words_per_minute,
+    ))
+}
+
+/// Related cards from anywhere in the index for the card under
+/// `heading_order`, for finding extensions and answers to a block.
+#[tauri::command]
+pub(crate) fn find_similar(
+    app: AppHandle,
+    file_id: i64,
+    heading_order: i64,
+    limit: Option<usize>,
+) -> CommandResult<Vec<SimilarCard>> {
+    let connection = open_database(&app)?;
+    find_similar_cards(
+        &connection,
+        file_id,
+        heading_order,
+        limit
+            .unwrap_or(DEFAULT_SIMILAR_LIMIT)
+            .clamp(1, MAX_SIMILAR_LIMIT),
+    )
+}
+
+#[tauri::command]
+pub(crate) fn audit_citations(
+    app: AppHandle,
+    root_path: String,
+    captures_only: Option<bool>,
+) -> CommandResult<CitationAuditReport> {
+    let canonical_root = canonicalize_folder(&root_path)?;
+    let root_path_string = path_display(&canonical_root);
+    let connection = open_database(&app)?;
+    let root_id = root_id(&connection, &root_path_string)?
+        .ok_or_else(|| CommandError::root_not_indexed(root_path_string.as_str()))?;
+
+    let (files_checked, sections_checked, mut items) = audit_root_citations(&connection, root_id)?;
+    if captures_only.unwrap_or(false) {
+        items.retain(|item| item.is_capture_file);
+    }
+
+    Ok(CitationAuditReport {
+        root_path: root_path_string,
+        files_checked,
+        sections_checked,
+        items,
+    })
+}
+
+#[tauri::command]
+pub(crate) fn start_link_check(
+    app: AppHandle,
+    root_path: String,
+    host_interval_ms: Option<u64>,
+    recheck_after_ms: Option<i64>,
+) -> CommandResult<Option<String>> {
+    let canonical_root = canonicalize_folder(&root_path)?;
+    let root_path_string = path_display(&canonical_root);
+    let root_id = {
+        let connection = open_database(&app)?;
+        root_id(&connection, &root_path_string)?
+            .ok_or_else(|| CommandError::root_not_indexed(root_path_string.as_str()))?
+    };
+
+    Ok(trigger_link_check(
+        app,
+        root_id,
+        root_path_string,
+        host_interval_ms.unwrap_or(DEFAULT_LINK_HOST_INTERVAL_MS),
+        recheck_after_ms
+            .filter(|value| *value >= 0)
+            .unwrap_or(DEFAULT_LINK_RECHECK_AFTER_MS),
+    ))
+}
+
+#[tauri::command]
+pub(crate) fn get_link_report(
+    app: AppHandle,
+    root_path: String,
+    include_ok: Option<bool>,
+) -> CommandResult<LinkReport> {
+    let canonical_root = canonicalize_folder(&root_path)?;
+    let root_path_string = path_display(&canonical_root);
+    let connection = open_database(&app)?;
+    let root_id = root_id(&connection, &root_path_string)?
+        .ok_or_else(|| CommandError::root_not_indexed(root_path_string.as_str()))?;
+
+    let (total_links, checked_links, broken_links) = connection
+        .query_row(
+            "
+            SELECT COUNT(DISTINCT l.url),
+                   COUNT(DISTINCT s.url),
+                   COUNT(DISTINCT CASE WHEN s.status <> ?2 THEN s.url END)
+            FROM file_links l
+            LEFT JOIN link_status s ON s.url = l.url
+            WHERE l.root_id = ?1
+            ",
+            params![root_id, LINK_STATUS_OK],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+        )
+        .map_err(|error| {
+            CommandError::database(format!("Could not summarize link report: {error}"))
+        })?;
+    let files = load_link_report(&connection, root_id, include_ok.unwrap_or(false))?;
+
+    Ok(LinkReport {
+        root_path: root_path_string,
+        running: link_check_running(),
+        total_links,
+        checked_links,
+        broken_links,
+        files,
+    })
+}
+
+#[tauri::command]
+pub(crate) fn cancel_operation(operation_id: String) -> CommandResult<bool> {
+    Ok(request_cancel(&operation_id))
+}
+
+/// Cancels the running index pass for `root_path`, or every running pass
+/// when no root is given. Returns whether anything was cancelled.
+#[tauri::command]
+pub(crate) fn cancel_index(root_path: Option<String>) -> CommandResult<bool> {
+    let canonical = |path: &str| {
+        canonicalize_folder(path)
+            .map(|canonical| path_display(&canonical))
+            .unwrap_or_else(|_| path.to_string())
+    };
+    let target = root_path.as_deref().map(canonical);
+    let mut cancelled = false;
+    for operation in active_operation_snapshots() {
+        if operation.kind != "index" {
+            continue;
+        }
+        let matches = match (&target, operation.root_path.as_deref()) {
+            (None, _) => true,
+            (Some(target), Some(path)) => canonical(path) == *target,
+            (Some(_), None) => false,
+        };
+        if matches {
+            cancelled |= request_cancel(&operation.operation_id);
+        }
+    }
+    Ok(cancelled)
+}
+
+#[tauri::command]
+pub(crate) fn list_operations() -> CommandResult<Vec<OperationProgress>> {
+    Ok(active_operation_snapshots())
+}
+
+#[tauri::command]
+pub(crate) fn list_profiles(app: AppHandle) -> CommandResult<Vec<ProfileInfo>> {
+    profiles::list_profiles(&app)
+}
+
+#[tauri::command]
+pub(crate) fn create_profile(app: AppHandle, name: String) -> CommandResult<ProfileInfo> {
+    profiles::create_profile(&app, &name)
+}
+
+/// Points every command at another profile's index. Watchers and caches from
+/// the old profile are dropped so nothing keeps reading or writing its
+/// database.
+#[tauri::command]
+pub(crate) fn switch_profile(app: AppHandle, profile_id: String) -> CommandResult<ProfileInfo> {
+    if !active_operation_snapshots().is_empty() || index_queue_busy(&app) {
+        return Err(CommandError::validation(
+            "Wait for running operations to finish before switching profiles.",
+        ));
+    }
+    let profile = profiles::set_active_profile(&app, &profile_id)?;
+    stop_all_watches();
+    query_engine::clear_query_cache();
+    clear_snapshots();
+    lexical::reload_runtime(&app)?;
+    restore_root_watches(&app);
+    Ok(profile)
+}
+
+#[tauri::command]
+pub(crate) fn get_performance_report() -> CommandResult<PerformanceReport> {
+    Ok(performance_report())
+}
+
+#[tauri::command]
+pub(crate) fn set_performance_profiling(enabled: bool) -> CommandResult<PerformanceReport> {
+    set_profiling_enabled(enabled);
+    Ok(performance_report())
+}
+
+#[tauri::command]
+pub(crate) fn get_locale_settings() -> CommandResult<LocaleSettings> {
+    Ok(current_locale_settings())
+}
+
+/// Switches the language of backend error and progress messages. Takes effect
+/// for the next message; errors already shown stay as they were.
+#[tauri::command]
+pub(crate) fn set_locale(app: AppHandle, locale: String) -> CommandResult<LocaleSettings> {
+    let connection = open_database(&app)?;
+    store_locale(&connection, &locale)
+}
+
+#[tauri::command]
+pub(crate) fn get_usage_metrics(app: AppHandle) -> CommandResult<UsageMetrics> {
+    let connection = open_database(&app)?;
+    load_usage_metrics(&connection)
+}
+
+/// Opts in to or out of local usage counters. Opting out stops recording but
+/// keeps what was counted; `purge_usage_metrics` deletes it.
+#[tauri::command]
+pub(crate) fn set_usage_metrics_enabled(
+    app: AppHandle,
+    enabled: bool,
+) -> CommandResult<UsageMetrics> {
+    let connection = open_database(&app)?;
+    store_usage_enabled(&connection, enabled)?;
+    load_usage_metrics(&connection)
+}
+
+#[tauri::command]
+pub(crate) fn purge_usage_metrics(app: AppHandle) -> CommandResult<UsageMetrics> {
+    let connection = open_database(&app)?;
+    purge_usage_counters(&connection)?;
+    load_usage_metrics(&connection)
+}
+
+#[tauri::command]
+pub(crate) fn resolve_sync_conflicts(
+    app: AppHandle,
+    root_path: String,
+    original_relative_path: Option<String>,
+) -> CommandResult<Vec<SyncConflictResolution>> {
+    let canonical_root = canonicalize_folder(&root_path)?;
+    let root_path_string = path_display(&canonical_root);
+    let groups = {
+        let connection = open_database(&app)?;
+        let root_id = root_id(&connection, &root_path_string)?
+            .ok_or_else(|| CommandError::root_not_indexed(root_path_string.as_str()))?;
+        load_sync_conflict_groups(&connection, root_id)?
+    };
+
+    let mut resolutions = Vec::new();
+    // Resolving everything skips groups with no original to keep; asking for
+    // one of them by name reports why it can't be resolved.
+    for group in groups.iter().filter(|group| {
+        original_relative_path
+            .as_deref()
+            .map(|requested| requested == group.original_relative_path)
+            .unwrap_or(group.original.is_some())
+    }) {
+        resolutions.push(resolve_group_prefer_newest(&canonical_root, group)?);
+    }
+
+    if !resolutions.is_empty() {
+        index_root_blocking(app, root_path_string)?;
+    }
+
+    Ok(resolutions)
+}
+
+fn write_block_pack_for_selection(
+    app: &AppHandle,
+    mut selection: BlockPackSelection,
+    pack_path: &Path,
+    operation_kind: &str,
+    include_last_capture_target: bool,
+) -> CommandResult<BlockPackExportResult> {
+    let canonical_root = canonicalize_folder(&selection.root_path)?;
+    let root_path_string = path_display(&canonical_root);
+    let connection = open_database(app)?;
+    let root_id = root_id(&connection, &root_path_string)?
+        .ok_or_else(|| CommandError::root_not_indexed(root_path_string.as_str()))?;
+    if include_last_capture_target && selection.capture_target.is_none() {
+        selection.capture_target = load_last_capture_target(&connection, root_id)?;
+    }
+
+    let mut operation =
+        OperationHandle::start(app, operation_kind, Some(root_path_string.clone()), true);
+    let manifest = match write_block_pack(
+        &connection,
+        root_id,
+        &root_path_string,
+        &selection,
+        pack_path,
+        &mut operation,
+    ) {
+        Ok(manifest) => {
+            operation.finish();
+            manifest
+        }
+        Err(error) => {
+            operation.fail(&error);
+            return Err(error);
+        }
+    };
+
+    Ok(BlockPackExportResult {
+        pack_path: path_display(pack_path),
+        file_count: manifest.files.len(),
+        heading_count: manifest.files.iter().map(|file| file.headings.len()).sum(),
+        capture_target: manifest.capture_target,
+    })
+}
+
+#[tauri::command]
+pub(crate) fn export_block_pack(
+    app: AppHandle,
+    selection: BlockPackSelection,
+    path: String,
+) -> CommandResult<BlockPackExportResult> {
+    let mut pack_path = Path::new(path.trim()).to_path_buf();
+    if pack_path.extension().is_none() {
+        pack_path.set_extension(BLOCK_PACK_EXTENSION);
+    }
+    write_block_pack_for_selection(&app, selection, &pack_path, "block-pack-export", false)
+}
+
+/// Bundles the selected sources with a capture target into a plain `.zip`
+/// that can be sent as one file. Without an explicit target, the root's last
+/// used capture target goes in. The archive is a block pack, so it can also
+/// be imported as one.
+#[tauri::command]
+pub(crate) fn export_share_bundle(
+    app: AppHandle,
+    selection: BlockPackSelection,
+    path: String,
+) -> CommandResult<BlockPackExportResult> {
+    let mut bundle_path = Path::new(path.trim()).to_path_buf();
+    if bundle_path.extension().is_none() {
+        bundle_path.set_extension("zip");
+    }
+    write_block_pack_for_selection(&app, selection, &bundle_path, "share-bundle", true)
+}
+
+#[tauri::command]
+pub(crate) fn export_outline_opml(
+    app: AppHandle,
+    root_path: String,
+    path: String,
+) -> CommandResult<OutlineExportResult> {
+    let canonical_root = canonicalize_folder(&root_path)?;
+    let root_path_string = path_display(&canonical_root);
+    let connection = open_database(&app)?;
+    let root_id = root_id(&connection, &root_path_string)?
+        .ok_or_else(|| CommandError::root_not_indexed(root_path_string.as_str()))?;
+
+    let mut output_path = Path::new(path.trim()).to_path_buf();
+    if output_path.extension().is_none() {
+        output_path.set_extension("opml");
+    }
+    let files = load_root_outline(&connection, root_id)?;
+    let (contents, counts) = render_outline_opml(&root_path_string, &files);
+    write_export_file(&output_path, &contents)?;
+
+    Ok(OutlineExportResult {
+        output_path: path_display(&output_path),
+        folder_count: counts.folder_count,
+        file_count: counts.file_count,
+        heading_count: counts.heading_count,
+    })
+}
+
+#[tauri::command]
+pub(crate) fn export_file_markdown(
+    app: AppHandle,
+    file_id: i64,
+    path: String,
+) -> CommandResult<MarkdownExportResult> {
+    let connection = open_database(&app)?;
+    let absolute_path = connection
+        .query_row(
+            "SELECT absolute_path FROM files WHERE id = ?1",
+            params![file_id],
+            |row| row.get::<_, String>(0),
+        )
+        .map_err(|error| {
+            CommandError::database(format!(
+                "Could not load Markdown export source file: {error}"
+            ))
+        })?;
+    let absolute_path = guard_path(&connection, Path::new(&absolute_path))?;
+
+    let mut output_path = Path::new(path.trim()).to_path_buf();
+    if output_path.extension().is_none() {
+        output_path.set_extension("md");
+    }
+    let document = render_docx_markdown(&absolute_path)?;
+    write_export_file(&output_path, &document.markdown)?;
+
+    Ok(MarkdownExportResult {
+        output_path: path_display(&output_path),
+        heading_count: document.heading_count,
+        paragraph_count: document.paragraph_count,
+    })
+}
+
+/// Renders one heading's section to its own PDF, for sharing a single card.
+#[tauri::command]
+pub(crate) fn export_heading_pdf(
+    app: AppHandle,
+    file_id: i64,
+    heading_order: i64,
+    path: String,
+) -> CommandResult<PdfExportResult> {
+    let connection = open_database(&app)?;
+    let absolute_path = connection
+        .query_row(
+            "SELECT absolute_path FROM files WHERE id = ?1",
+            params![file_id],
+            |row| row.get::<_, String>(0),
+        )
+        .map_err(|error| {
+            CommandError::database(format!("Could not load PDF export source file: {error}"))
+        })?;
+    let absolute_path = guard_path(&connection, Path::new(&absolute_path))?;
+    let paragraphs = load_styled_paragraphs(&absolute_path, Some(heading_order))?;
+    if paragraphs.is_empty() {
+        return Err(CommandError::not_indexed(messages::message(
+            "error.headingGone",
+        )));
+    }
+    let heading_text = paragraphs[0]
+        .segments
+        .iter()
+        .map(|segment| segment.text.as_str())
+        .collect::<String>();
+    let mut output_path = Path::new(path.trim()).to_path_buf();
+    if output_path.extension().is_none() {
+        output_path.set_extension("pdf");
+    }
+    let counts = write_section_pdf(&output_path, &heading_text, &paragraphs)?;
+
+    Ok(PdfExportResult {
+        output_path: path_display(&output_path),
+        page_count: counts.page_count,
+        paragraph_count: counts.paragraph_count,
+    })
+}
+
+/// Builds a document from a docx template with `{{tag}}`, `{{cite}}` and
+/// `{{body}}` merge fields, repeating the template's field block once per
+/// selected heading in the order given.
+#[tauri::command]
+pub(crate) fn generate_merged_document(
+    app: AppHandle,
+    template_path: String,
+    headings: Vec<HeadingRef>,
+    path: String,
+) -> CommandResult<MergeDocumentResult> {
+    if headings.is_empty() {
+        return Err(CommandError::validation(
+            "Select at least one heading to merge.",
+        ));
+    }
+    let template_path = Path::new(template_path.trim()).to_path_buf();
+    let mut output_path = Path::new(path.trim()).to_path_buf();
+    if output_path.extension().is_none() {
+        output_path.set_extension("docx");
+    }
+    if fs::canonicalize(&template_path).ok() == fs::canonicalize(&output_path).ok()
+        && output_path.exists()
+    {
+        return Err(CommandError::validation(
+            "Choose an output file other than the template.",
+        ));
+    }
+
+    let connection = open_database(&app)?;
+    let mut sources = Vec::with_capacity(headings.len());
+    for heading in headings {
+        let absolute_path = connection
+            .query_row(
+                "SELECT absolute_path FROM files WHERE id = ?1",
+                params![heading.file_id],
+                |row| row.get::<_, String>(0),
+            )
+            .map_err(|error| {
+                CommandError::database(format!("Could not load merge source file: {error}"))
+            })?;
+        let absolute_path = guard_path(&connection, Path::new(&absolute_path))?;
+        sources.push(MergeSource {
+            file_id: heading.file_id,
+            absolute_path: path_display(&absolute_path),
+            heading_order: heading.heading_order,
+        });
+    }
+    let (section_count, skipped) = write_merged_document(&template_path, &sources, &output_path)?;
+
+    Ok(MergeDocumentResult {
+        output_path: path_display(&output_path),
+        section_count,
+        skipped,
+    })
+}
+
+const DEFAULT_UNDERLINE_MARKER: &str = "__";
+const DEFAULT_HIGHLIGHT_MARKER: &str = "**";
+
+/// Writes a file, or one heading's section, as plain text with underlined and
+/// highlighted text wrapped in markers. The text is also returned so the
+/// frontend can copy it straight to the clipboard.
+#[tauri::command]
+pub(crate) fn export_marked_text(
+    app: AppHandle,
+    file_id: i64,
+    heading_order: Option<i64>,
+    path: String,
+    markers: Option<TextMarkerOptions>,
+) -> CommandResult<MarkedTextExportResult> {
+    let connection = open_database(&app)?;
+    let absolute_path = connection
+        .query_row(
+            "SELECT absolute_path FROM files WHERE id = ?1",
+            params![file_id],
+            |row| row.get::<_, String>(0),
+        )
+        .map_err(|error| {
+            CommandError::database(format!("Could not load text export source file: {error}"))
+        })?;
+    let absolute_path = guard_path(&connection, Path::new(&absolute_path))?;
+
+    let paragraphs = load_styled_paragraphs(&absolute_path, heading_order)?;
+    if heading_order.is_some() && paragraphs.is_empty() {
+        return Err(CommandError::not_indexed(messages::message(
+            "error.headingGone",
+        )));
+    }
+    let markers = markers.unwrap_or_default();
+    let (text, paragraph_count) = render_marked_text(
+        &paragraphs,
+        markers
+            .underline
+            .as_deref()
+            .unwrap_or(DEFAULT_UNDERLINE_MARKER),
+        markers
+            .highlight
+            .as_deref()
+            .unwrap_or(DEFAULT_HIGHLIGHT_MARKER),
+    );
+
+    let mut output_path = Path::new(path.trim()).to_path_buf();
+    if output_path.extension().is_none() {
+        output_path.set_extension("txt");
+    }
+    write_export_file(&output_path, &text)?;
+
+    Ok(MarkedTextExportResult {
+        output_path: path_display(&output_path),
+        paragraph_count,
+        text,
+    })
+}
+
+#[tauri::command]
+pub(crate) fn export_static_site(
+    app: AppHandle,
+    root_path: String,
+    path: String,
+) -> CommandResult<SiteExportResult> {
+    let canonical_root = canonicalize_folder(&root_path)?;
+    let root_path_string = path_display(&canonical_root);
+    let connection = open_database(&app)?;
+    let root_id = root_id(&connection, &root_path_string)?
+        .ok_or_else(|| CommandError::root_not_indexed(root_path_string.as_str()))?;
+
+    let output_dir = Path::new(path.trim()).to_path_buf();
+    if output_dir.starts_with(&canonical_root) {
+        return Err(CommandError::validation(
+            "Choose an export folder outside the indexed root.",
+        ));
+    }
+    let mut operation =
+        OperationHandle::start(&app, "site-export", Some(root_path_string.clone()), true);
+    let counts = match write_static_site(
+        &connection,
+        root_id,
+        &root_path_string,
+        &output_dir,
+        &mut operation,
+    ) {
+        Ok(counts) => {
+            operation.finish();
+            counts
+        }
+        Err(error) => {
+            operation.fail(&error);
+            return Err(error);
+        }
+    };
+
+    Ok(SiteExportResult {
+        output_path: path_display(&output_dir),
+        page_count: counts.page_count,
+        folder_count: counts.folder_count,
+        heading_count: counts.heading_count,
+        skipped_files: counts.skipped_files,
+    })
+}
+
+/// Writes the root as an Obsidian vault: one note per file, linked to the
+/// files it was captured into or links to.
+#[tauri::command]
+pub(crate) fn export_obsidian_vault(
+    app: AppHandle,
+    root_path: String,
+    path: String,
+) -> CommandResult<VaultExportResult> {
+    let canonical_root = canonicalize_folder(&root_path)?;
+    let root_path_string = path_display(&canonical_root);
+    let connection = open_database(&app)?;
+    let root_id = root_id(&connection, &root_path_string)?
+        .ok_or_else(|| CommandError::root_not_indexed(root_path_string.as_str()))?;
+
+    let output_dir = Path::new(path.trim()).to_path_buf();
+    if output_dir.starts_with(&canonical_root) {
+        return Err(CommandError::validation(
+            "Choose a vault folder outside the indexed root.",
+        ));
+    }
+    let mut operation =
+        OperationHandle::start(&app, "vault-export", Some(root_path_string.clone()), true);
+    let counts = match write_obsidian_vault(&connection, root_id, &output_dir, &mut operation) {
+        Ok(counts) => {
+            operation.finish();
+            counts
+        }
+        Err(error) => {
+            operation.fail(&error);
+            return Err(error);
+        }
+    };
+
+    Ok(VaultExportResult {
+        output_path: path_display(&output_dir),
+        note_count: counts.note_count,
+        link_count: counts.link_count,
+        skipped_files: counts.skipped_files,
+    })
+}
+
+fn export_root_csv(
+    app: &AppHandle,
+    root_path: &str,
+    path: &str,
+    render: impl FnOnce(&Connection, i64) -> CommandResult<(String, usize)>,
+) -> CommandResult<CsvExportResult> {
+    let canonical_root = canonicalize_folder(root_path)?;
+    let root_path_string = path_display(&canonical_root);
+    let connection = open_database(app)?;
+    let root_id = root_id(&connection, &root_path_string)?
+        .ok_or_else(|| CommandError::root_not_indexed(root_path_string.as_str()))?;
+
+    let mut output_path = Path::new(path.trim()).to_path_buf();
+    if output_path.extension().is_none() {
+        output_path.set_extension("csv");
+    }
+    let (csv, row_count) = render(&connection, root_id)?;
+    write_export_file(&output_path, &format!("{CSV_UTF8_BOM}{csv}"))?;
+
+    Ok(CsvExportResult {
+        output_path: path_display(&output_path),
+        row_count,
+    })
+}
+
+#[tauri::command]
+pub(crate) fn export_headings_csv(
+    app: AppHandle,
+    root_path: String,
+    path: String,
+) -> CommandResult<CsvExportResult> {
+    export_root_csv(&app, &root_path, &path, render_headings_csv)
+}
+
+#[tauri::command]
+pub(crate) fn export_authors_csv(
+    app: AppHandle,
+    root_path: String,
+    path: String,
+) -> CommandResult<CsvExportResult> {
+    export_root_csv(&app, &root_path, &path, render_authors_csv)
+}
+
+#[tauri::command]
+pub(crate) fn export_captures_csv(
+    app: AppHandle,
+    root_path: String,
+    path: String,
+    since_ms: Option<i64>,
+    until_ms: Option<i64>,
+) -> CommandResult<CsvExportResult> {
+    export_root_csv(&app, &root_path, &path, |connection, root_id| {
+        render_captures_csv(connection, root_id, since_ms, until_ms)
+    })
+}
+
+/// Writes an Atom (default) or JSON feed of the files added or changed in the
+/// root's last `run_count` index runs, for subscribing to what's new in a
+/// shared folder. Entries link to portable `blockfile://` links.
+#[tauri::command]
+pub(crate) fn export_index_feed(
+    app: AppHandle,
+    root_path: String,
+    path: String,
+    format: Option<String>,
+    run_count: Option<usize>,
+) -> CommandResult<IndexFeedResult> {
+    let format = format
+        .map(|value| value.trim().to_lowercase())
+        .filter(|value| !value.is_empty())
+        .unwrap_or_else(|| FEED_FORMAT_ATOM.to_string());
+    let extension = match format.as_str() {
+        FEED_FORMAT_ATOM => "xml",
+        FEED_FORMAT_JSON => "json",
+        _ => {
+            return Err(CommandError::validation_field(
+                "format",
+                format!("Unknown feed format '{format}'. Use 'atom' or 'json'."),
+            ))
+        }
+    };
+
+    let canonical_root = canonicalize_folder(&root_path)?;
+    let root_path_string = path_display(&canonical_root);
+    let connection = open_database(&app)?;
+    let root_id = root_id(&connection, &root_path_string)?
+        .ok_or_else(|| CommandError::root_not_indexed(root_path_string.as_str()))?;
+    let root_uid = ensure_root_identity(&connection, root_id, &canonical_root)?;
+    let feed = load_index_feed(
+        &connection,
+        root_id,
+        &root_path_string,
+        &root_uid,
+        run_count.unwrap_or(DEFAULT_FEED_RUNS).max(1),
+    )?;
+
+    let mut output_path = Path::new(path.trim()).to_path_buf();
+    if output_path.extension().is_none() {
+        output_path.set_extension(extension);
+    }
+    let contents = if format == FEED_FORMAT_JSON {
+        render_json_feed(&feed)
+    } else {
+        render_atom_feed(&feed)
+    };
+    write_export_file(&output_path, &contents)?;
+
+    Ok(IndexFeedResult {
+        output_path: path_display(&output_path),
+        format,
+        run_count: feed.run_count,
+        entry_count: feed.entries.len(),
+    })
+}
+
+/// Writes the root's index, files with their headings, authors and cites, as
+/// a JSON (default) or CSV dump with paths relative to the root, for
+/// spreadsheets and other tools or for `import_index` on another machine.
+#[tauri::command]
+pub(crate) fn export_index(
+    app: AppHandle,
+    root_path: String,
+    path: String,
+    format: Option<String>,
+) -> CommandResult<IndexExportResult> {
+    let format = format
+        .map(|value| value.trim().to_lowercase())
+        .filter(|value| !value.is_empty())
+        .unwrap_or_else(|| INDEX_DUMP_FORMAT_JSON.to_string());
+    if format != INDEX_DUMP_FORMAT_JSON && format != INDEX_DUMP_FORMAT_CSV {
+        return Err(CommandError::validation_field(
+            "format",
+            format!("Unknown index format '{format}'. Use 'json' or 'csv'."),
+        ));
+    }
+
+    let canonical_root = canonicalize_folder(&root_path)?;
+    let root_path_string = path_display(&canonical_root);
+    let connection = open_database(&app)?;
+    let root_id = root_id(&connection, &root_path_string)?
+        .ok_or_else(|| CommandError::root_not_indexed(root_path_string.as_str()))?;
+    let dump = load_index_dump(&connection, root_id)?;
+
+    let mut output_path = Path::new(path.trim()).to_path_buf();
+    if output_path.extension().is_none() {
+        output_path.set_extension(&format);
+    }
+    let contents = if format == INDEX_DUMP_FORMAT_CSV {
+        format!("{CSV_UTF8_BOM}{}", render_index_dump_csv(&dump))
+    } else {
+        render_index_dump_json(&dump)?
+    };
+    write_export_file(&output_path, &contents)?;
+
+    Ok(IndexExportResult {
+        output_path: path_display(&output_path),
+        format,
+        file_count: dump.files.len(),
+        heading_count: dump.files.iter().map(|file| file.headings.len()).sum(),
+        author_count: dump.files.iter().map(|file| file.authors.len()).sum(),
+        cite_count: dump.files.iter().map(|file| file.cites.len()).sum(),
+    })
+}
+
+/// Restores a dump from `export_index` into a root that has not been scanned
+/// yet, so its headings, authors and cites are there before the first index
+/// run has read every file.
+#[tauri::command]
+pub(crate) fn import_index(
+    app: AppHandle,
+    root_path: String,
+    path: String,
+) -> CommandResult<IndexImportResult> {
+    let canonical_root = canonicalize_folder(&root_path)?;
+    let root_path_string = path_display(&canonical_root);
+    let dump = read_index_dump(Path::new(path.trim()))?;
+    let mut connection = open_database(&app)?;
+    let root_id = add_or_get_root_id(&connection, &root_path_string)?;
+    ensure_root_identity(&connection, root_id, &canonical_root)?;
+
+    let transaction = connection.transaction().map_err(|error| {
+        CommandError::database(format!("Could not start index import transaction: {error}"))
+    })?;
+    let result = restore_index_dump(&transaction, root_id, &canonical_root, &dump)?;
+    transaction.commit().map_err(|error| {
+        CommandError::database(format!("Could not commit index import: {error}"))
+    })?;
+    Ok(result)
+}
+
+/// Seeds tags from a spreadsheet: each CSV row maps a relative path (file,
+/// folder or `*` glob) and/or a heading text pattern to one or more tags.
+#[tauri::command]
+pub(crate) fn import_tags_csv(
+    app: AppHandle,
+    root_path: String,
+    path: String,
+) -> CommandResult<TagImportResult> {
+    let canonical_root = canonicalize_folder(&root_path)?;
+    let root_path_string = path_display(&canonical_root);
+    let rows = read_tag_mapping(Path::new(path.trim()))?;
+    let mut connection = open_database(&app)?;
+    let root_id = root_id(&connection, &root_path_string)?
+        .ok_or_else(|| CommandError::root_not_indexed(root_path_string.as_str()))?;
+
+    let transaction = connection.transaction().map_err(|error| {
+        CommandError::database(format!("Could not start tag import transaction: {error}"))
+    })?;
+    let result = apply_tag_mapping(&transaction, root_id, &rows)?;
+    transaction
+        .commit()
+        .map_err(|error| CommandError::database(format!("Could not commit tag import: {error}")))?;
+    Ok(result)
+}
+
+#[tauri::command]
+pub(crate) fn list_file_tags(app: AppHandle, file_id: i64) -> CommandResult<Vec<ItemTag>> {
+    let connection = open_database(&app)?;
+    load_file_tags(&connection, file_id)
+}
+
+/// What a tag is keyed by: the file's root and relative path, plus the
+/// heading's text and normalized text when a heading is tagged.
+struct TagTarget {
+    root_id: i64,
+    relative_path: String,
+    heading: Option<(String, String)>,
+}
+
+fn tag_target(
+    connection: &Connection,
+    file_id: i64,
+    heading_order: Option<i64>,
+) -> CommandResult<TagTarget> {
+    let (root_id, relative_path) = connection
+        .query_row(
+            "SELECT root_id, relative_path FROM files WHERE id = ?1",
+            params![file_id],
+            |row| Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?)),
+        )
+        .optional()
+        .map_err(|error| CommandError::database(format!("Could not load file {file_id}: {error}")))?
+        .ok_or_else(|| CommandError::not_indexed(format!("File {file_id} is not indexed")))?;
+    let heading = match heading_order {
+        Some(heading_order) => Some(
+            connection
+                .query_row(
+                    "SELECT text, normalized FROM headings WHERE file_id = ?1 AND heading_order = ?2",
+                    params![file_id, heading_order],
+                    |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)),
+                )
+                .optional()
+                .map_err(|error| {
+                    CommandError::database(format!("Could not load heading: {error}"))
+                })?
+                .ok_or_else(|| CommandError::not_indexed(messages::message("error.headingGone")))?,
+        ),
+        None => None,
+    };
+    Ok(TagTarget {
+        root_id,
+        relative_path,
+        heading,
+    })
+}
+
+/// Tags a file, or one of its headings, and returns the file's tags. Heading
+/// tags follow the heading's text, so they survive reindexing and edits
+/// elsewhere in the file.
+#[tauri::command]
+pub(crate) fn add_tag(
+    app: AppHandle,
+    file_id: i64,
+    heading_order: Option<i64>,
+    tag: String,
+) -> CommandResult<Vec<ItemTag>> {
+    let tag = clean_tag(required_text(TAG, &tag, MAX_TAG_CHARS)?);
+    let connection = open_database(&app)?;
+    let target = tag_target(&connection, file_id, heading_order)?;
+    store_tag(
+        &connection,
+        target.root_id,
+        &target.relative_path,
+        target
+            .heading
+            .as_ref()
+            .map(|(text, normalized)| (text.as_str(), normalized.as_str())),
+        &tag,
+    )?;
+    load_file_tags(&connection, file_id)
+}
+
+#[tauri::command]
+pub(crate) fn remove_tag(
+    app: AppHandle,
+    file_id: i64,
+    heading_order: Option<i64>,
+    tag: String,
+) -> CommandResult<Vec<ItemTag>> {
+    let connection = open_database(&app)?;
+    let target = tag_target(&connection, file_id, heading_order)?;
+    delete_tag(
+        &connection,
+        target.root_id,
+        &target.relative_path,
+        target
+            .heading
+            .as_ref()
+            .map(|(_, normalized)| normalized.as_str()),
+        &clean_tag(&tag),
+    )?;
+    load_file_tags(&connection, file_id)
+}
+
+/// Tags in use with their file and heading counts, in one root or all.
+#[tauri::command]
+pub(crate) fn list_tags(
+    app: AppHandle,
+    root_path: Option<String>,
+) -> CommandResult<Vec<TagSummary>> {
+    let connection = open_database(&app)?;
+    let root_id = match root_path {
+        Some(root_path) => {
+            let root_path = path_display(&canonicalize_folder(&root_path)?);
+            Some(
+                root_id(&connection, &root_path)?
+                    .ok_or_else(|| CommandError::root_not_indexed(root_path.as_str()))?,
+            )
+        }
+        None => None,
+    };
+    list_tag_summaries(&connection, root_id)
+}
+
+/// Writes a spreadsheet of the root's folders and files with heading counts
+/// and modification dates, for auditing coverage outside the app.
+#[tauri::command]
+pub(crate) fn export_snapshot_xlsx(
+    app: AppHandle,
+    root_path: String,
+    path: String,
+) -> CommandResult<SnapshotXlsxResult> {
+    let canonical_root = canonicalize_folder(&root_path)?;
+    let root_path_string = path_display(&canonical_root);
+    let connection = open_database(&app)?;
+    let root_id = root_id(&connection, &root_path_string)?
+        .ok_or_else(|| CommandError::root_not_indexed(root_path_string.as_str()))?;
+
+    let mut output_path = Path::new(path.trim()).to_path_buf();
+    if output_path.extension().is_none() {
+        output_path.set_extension("xlsx");
+    }
+    let (sheets, counts) = snapshot_sheets(&connection, root_id)?;
+    write_xlsx(&output_path, &sheets)?;
+
+    Ok(SnapshotXlsxResult {
+        output_path: path_display(&output_path),
+        folder_count: counts.folder_count,
+        file_count: counts.file_count,
+    })
+}
+
+/// Replays a capture log exported on another machine into this root, pulling
+/// each section fresh from the local copy of its source so styling survives.
+/// `target_path` sends every capture to one file instead of the logged targets.
+#[tauri::command]
+pub(crate) fn replay_capture_log(
+    app: AppHandle,
+    root_path: String,
+    path: String,
+    target_path: Option<String>,
+) -> CommandResult<CaptureReplayResult> {
+    let canonical_root = canonicalize_folder(&root_path)?;
+    let root_path_string = path_display(&canonical_root);
+    let entries = read_capture_log(Path::new(path.trim()))?;
+    let connection = open_database(&app)?;
+    let root_id = root_id(&connection, &root_path_string)?
+        .ok_or_else(|| CommandError::root_not_indexed(root_path_string.as_str()))?;
+    let target_override = target_path.filter(|value| !value.trim().is_empty());
+
+    let mut operation =
+        OperationHandle::start(&app, "capture-replay", Some(root_path_string.clone()), true);
+    let mut result = CaptureReplayResult {
+        replayed_count: 0,
+        duplicate_count: 0,
+        skipped: Vec::new(),
+        target_paths: Vec::new(),
+    };
+    let total = entries.len();
+    for (index, entry) in entries.into_iter().enumerate() {
+        if let Err(error) = operation.ensure_not_cancelled() {
+            operation.fail(&error);
+            return Err(error);
+        }
+        operation.update(
+            "replaying",
+            index,
+            Some(total),
+            Some(entry.section_title.clone()),
+        );
+
+        let mut skip = |reason: String| {
+            result.skipped.push(CaptureReplaySkip {
+                source_path: entry.source_path.clone(),
+                section_title: entry.section_title.clone(),
+                reason,
+            });
+        };
+        let target = target_override.as_deref().unwrap_or(&entry.target);
+        let target_relative_path = match normalize_capture_target_path(Some(target)) {
+            Ok(value) => value,
+            Err(error) => {
+                skip(error.to_string());
+                continue;
+            }
+        };
+        let Some((file_id, source_path)) =
+            locate_replay_source(&connection, root_id, &entry.source_path)?
+        else {
+            skip("Source file is not in this root's index.".to_string());
+            continue;
+        };
+        let Some((heading_order, heading_level)) = locate_replay_heading(
+            &connection,
+            file_id,
+            &entry.section_title,
+            entry.heading_level,
+        )?
+        else {
+            skip("Heading no longer exists in the local copy of the source.".to_string());
+            continue;
+        };
+        if capture_already_recorded(
+            &connection,
+            root_id,
+            &source_path,
+            &entry.section_title,
+            &target_relative_path,
+        )? {
+            result.duplicate_count += 1;
+            continue;
+        }
+        let content =
+            replay_section_text(&connection, file_id, Path::new(&source_path), heading_order)
+                .unwrap_or_else(|| entry.section_title.clone());
+
+        match insert_capture_blocking(
+            app.clone(),
+            CaptureRequest {
+                root_path: root_path_string.clone(),
+                source_path,
+                section_title: entry.section_title.clone(),
+                content,
+                paragraph_xml: None,
+                target_path: Some(target_relative_path),
+                heading_level: Some(heading_level),
+                heading_order: Some(heading_order),
+                selected_target_heading_order: None,
+                insert_mode: None,
+            },
+        ) {
+            Ok(inserted) => {
+                result.replayed_count += 1;
+                if !result.target_paths.contains(&inserted.capture_path) {
+                    result.target_paths.push(inserted.capture_path);
+                }
+            }
+            Err(error) => skip(error.to_string()),
+        }
+    }
+    operation.finish();
+    Ok(result)
+}
+
+/// Search's own cap; compilations want every match, not the first screen.
+const SEARCH_COMPILE_DEFAULT_LIMIT: usize = 400;
+
+/// Compiles every section matching `query` into one docx, grouped by source
+/// file with each file as a pocket.
+#[tauri::command]
+pub(crate) fn export_search_results_docx(
+    app: AppHandle,
+    query: String,
+    filters: Option<SearchExportFilters>,
+    path: String,
+) -> CommandResult<SearchCompileResult> {
+    compile_search_results(&app, &query, filters, &path, SourceHeader::Pocket)
+}
+
+/// Writes every section matching `query` to a new docx under a bold header
+/// per source file: a batch capture from search.
+#[tauri::command]
+pub(crate) fn export_search_results(
+    app: AppHandle,
+    query: String,
+    filters: Option<SearchExportFilters>,
+    path: String,
+) -> CommandResult<SearchCompileResult> {
+    compile_search_results(&app, &query, filters, &path, SourceHeader::Bold)
+}
+
+fn compile_search_results(
+    app: &AppHandle,
+    query: &str,
+    filters: Option<SearchExportFilters>,
+    path: &str,
+    source_header: SourceHeader,
+) -> CommandResult<SearchCompileResult> {
+    let filters = filters.unwrap_or_default();
+    let hits = query_engine::search_lexical(
+        app,
+        query,
+        filters.root_path.clone(),
+        Some(filters.limit.unwrap_or(SEARCH_COMPILE_DEFAULT_LIMIT)),
+        None,
+    )?;
+    let files = group_search_hits(hits, &filters);
+    if files.is_empty() {
+        return Err(CommandError::validation(format!(
+            "No headings match '{}'.",
+            query.trim()
+        )));
+    }
+
+    let mut output_path = Path::new(path.trim()).to_path_buf();
+    if output_path.extension().is_none() {
+        output_path.set_extension("docx");
+    }
+    let connection = open_database(app)?;
+    let mut operation = OperationHandle::start(app, "search-compile", filters.root_path, true);
+    let counts = match write_search_compilation(
+        &connection,
+        query,
+        &files,
+        source_header,
+        &output_path,
+        &mut operation,
+    ) {
+        Ok(counts) => {
+            operation.finish();
+            counts
+        }
+        Err(error) => {
+            operation.fail(&error);
+            let _ = fs::remove_file(&output_path);
+            return Err(error);
+        }
+    };
+
+    Ok(SearchCompileResult {
+        output_path: path_display(&output_path),
+        file_count: counts.file_count,
+        section_count: counts.section_count,
+        nested_count: counts.nested_count,
+    })
+}
+
+#[tauri::command]
+pub(crate) fn get_google_drive_status(app: AppHandle) -> CommandResult<GoogleDriveStatus> {
+    let connection = open_database(&app)?;
+    drive_status(&connection)
+}
+
+#[tauri::command]
+pub(crate) fn configure_google_drive(
+    app: AppHandle,
+    client_id: String,
+    client_secret: String,
+) -> CommandResult<GoogleDriveStatus> {
+    let client_id = client_id.trim();
+    let client_secret = client_secret.trim();
+    if client_id.is_empty() || client_secret.is_empty() {
+        return Err(CommandError::validation(
+            "Google OAuth client ID and secret are required.",
+        ));
+    }
+    let connection = open_database(&app)?;
+    store_drive_client(&connection, client_id, client_secret)?;
+    drive_status(&connection)
+}
+
+#[tauri::command]
+pub(crate) async fn connect_google_drive(app: AppHandle) -> CommandResult<GoogleDriveStatus> {
+    tauri::async_runtime::spawn_blocking(move || {
+        let mut operation = OperationHandle::start(&app, "drive-connect", None, true);
+        match connect_drive(&app, &mut operation) {
+            Ok(()) => operation.finish(),
+            Err(error) => {
+                operation.fail(&error);
+                return Err(error);
+            }
+        }
+        let connection = open_database(&app)?;
+        drive_status(&connection)
+    })
+    .await
+    .map_err(|error| {
+        CommandError::internal(format!("Google Drive connect command failed: {error}"))
+    })?
+}
+
+#[tauri::command]
+pub(crate) fn disconnect_google_drive(app: AppHandle) -> CommandResult<GoogleDriveStatus> {
+    let connection = open_database(&app)?;
+    clear_drive_tokens(&connection)?;
+    drive_status(&connection)
+}
+
+#[tauri::command]
+pub(crate) async fn upload_to_google_drive(
+    app: AppHandle,
+    path: String,
+    folder_id: Option<String>,
+    convert: Option<bool>,
+) -> CommandResult<DriveUploadResult> {
+    let file_path = Path::new(path.trim()).to_path_buf();
+    let is_docx = file_path
+        .extension()
+        .map(|extension| extension.eq_ignore_ascii_case("docx"))
+        .unwrap_or(false);
+    if !file_path.is_file() || !is_docx {
+        return Err(CommandError::validation(format!(
+            "'{}' is not a .docx file.",
+            path_display(&file_path)
+        )));
+    }
+
+    tauri::async_runtime::spawn_blocking(move || {
+        let mut operation = OperationHandle::start(&app, "drive-upload", None, true);
+        match upload_docx_to_drive(
+            &app,
+            &file_path,
+            folder_id.as_deref(),
+            convert.unwrap_or(true),
+            &mut operation,
+        ) {
+            Ok(result) => {
+                operation.finish();
+                Ok(result)
+            }
+            Err(error) => {
+                operation.fail(&error);
+                Err(error)
+            }
+        }
+    })
+    .await
+    .map_err(|error| {
+        CommandError::internal(format!("Google Drive upload command failed: {error}"))
+    })?
+}
+
+#[tauri::command]
+pub(crate) fn get_local_api_status(app: AppHandle) -> CommandResult<LocalApiStatus> {
+    let connection = open_database(&app)?;
+    local_api_status(&connection)
+}
+
+#[tauri::command]
+pub(crate) fn start_local_api(app: AppHandle, port: Option<u16>) -> CommandResult<LocalApiStatus> {
+    let connection = open_database(&app)?;
+    enable_local_api(&app, &connection, port)?;
+    local_api_status(&connection)
+}
+
+#[tauri::command]
+pub(crate) fn stop_local_api(app: AppHandle) -> CommandResult<LocalApiStatus> {
+    let connection = open_database(&app)?;
+    disable_local_api(&connection)?;
+    local_api_status(&connection)
+}
+
+#[tauri::command]
+pub(crate) fn regenerate_local_api_token(app: AppHandle) -> CommandResult<LocalApiStatus> {
+    let connection = open_database(&app)?;
+    rotate_local_api_token(&app, &connection)?;
+    local_api_status(&connection)
+}
+
+#[tauri::command]
+pub(crate) fn get_pandoc_status(app: AppHandle) -> CommandResult<PandocStatus> {
+    let connection = open_database(&app)?;
+    pandoc_status(&connection)
+}
+
+/// Points the bridge at a specific pandoc binary; `None` goes back to
+/// searching PATH and the usual install locations.
+#[tauri::command]
+pub(crate) fn set_pandoc_path(app: AppHandle, path: Option<String>) -> CommandResult<PandocStatus> {
+    let connection = open_database(&app)?;
+    store_configured_path(&connection, path.as_deref())?;
+    pandoc_status(&connection)
+}
+
+/// Converts a capture target to a format BlockFile does not write itself
+/// (see `PandocStatus.export_formats`) by handing it to pandoc.
+#[tauri::command]
+pub(crate) fn export_capture_target_with_pandoc(
+    app: AppHandle,
+    root_path: String,
+    target_path: Option<String>,
+    format: String,
+    path: String,
+) -> CommandResult<PandocExportResult> {
+    let format = format.trim().to_ascii_lowercase();
+    let extension = pandoc_extension(&format).ok_or_else(|| {
+        CommandError::validation_field(
+            "format",
+            format!("'{format}' is not a pandoc export format."),
+        )
+    })?;
+    let canonical_root = canonicalize_folder(&root_path)?;
+    let connection = open_database(&app)?;
+    let target_relative_path =
+        capture_target_for_path(&connection, &canonical_root, target_path.as_deref())?;
+    let capture_path = guarded_capture_path(&connection, &canonical_root, &target_relative_path)?;
+    capture_writer::flush_capture_target(&capture_path)?;
+    if !capture_path.is_file() {
+        return Err(CommandError::validation(format!(
+            "Capture target '{}' does not exist yet.",
+            target_relative_path
+        )));
+    }
+
+    let mut output_path = Path::new(path.trim()).to_path_buf();
+    if output_path.extension().is_none() {
+        output_path.set_extension(extension);
+    }
+    if let Some(parent) = output_path.parent() {
+        fs::create_dir_all(parent).map_err(|error| {
+            CommandError::io(format!(
+                "Could not create export folder '{}': {error}",
+                path_display(parent)
+            ))
+        })?;
+    }
+    let mut operation = OperationHandle::start(
+        &app,
+        "pandoc-export",
+        Some(path_display(&canonical_root)),
+        false,
+    );
+    operation.update("converting", 0, Some(1), Some(target_relative_path));
+    if let Err(error) = convert_docx_with_pandoc(&connection, &capture_path, &format, &output_path)
+    {
+        operation.fail(&error);
+        return Err(error);
+    }
+    operation.finish();
+
+    Ok(PandocExportResult {
+        output_path: path_display(&output_path),
+        format,
+    })
+}
+
+/// Writes a copy of a capture target with its Heading 1-4 paragraphs restyled
+/// as Verbatim pockets, hats, blocks and tags, ready to paste into a speech
+/// file.
+#[tauri::command]
+pub(crate) fn export_capture_outline(
+    app: AppHandle,
+    root_path: String,
+    target_path: Option<String>,
+    path: String,
+) -> CommandResult<VerbatimExportResult> {
+    let canonical_root = canonicalize_folder(&root_path)?;
+    let connection = open_database(&app)?;
+    let target_relative_path =
+        capture_target_for_path(&connection, &canonical_root, target_path.as_deref())?;
+    let capture_path = guarded_capture_path(&connection, &canonical_root, &target_relative_path)?;
+    capture_writer::flush_capture_target(&capture_path)?;
+    if !capture_path.is_file() {
+        return Err(CommandError::validation(format!(
+            "Capture target '{}' does not exist yet.",
+            target_relative_path
+        )));
+    }
+
+    let mut output_path = Path::new(path.trim()).to_path_buf();
+    if output_path.extension().is_none() {
+        output_path.set_extension("docx");
+    }
+    if fs::canonicalize(&capture_path).ok() == fs::canonicalize(&output_path).ok() {
+        return Err(CommandError::validation(
+            "Choose an output file other than the capture target.",
+        ));
+    }
+    let counts = write_verbatim_outline(&capture_path, &output_path)?;
+
+    Ok(VerbatimExportResult {
+        output_path: path_display(&output_path),
+        pocket_count: counts.pocket_count,
+        hat_count: counts.hat_count,
+        block_count: counts.block_count,
+        tag_count: counts.tag_count,
+    })
+}
+
+const DEFAULT_INTAKE_TARGET_FOLDER: &str = "Intake";
+
+#[tauri::command]
+pub(crate) fn list_intakes(app: AppHandle) -> CommandResult<Vec<RootIntake>> {
+    let connection = open_database(&app)?;
+    list_root_intakes(&connection)
+}
+
+/// Replaces the exclusion globs of a root, registering the root if needed,
+/// and returns them as stored.
+#[tauri::command]
+pub(crate) fn set_root_exclusions(
+    app: AppHandle,
+    root_path: String,
+    globs: Vec<String>,
+) -> CommandResult<Vec<String>> {
+    let canonical_root = canonicalize_folder(&root_path)?;
+    let mut connection = open_database(&app)?;
+    let root_id = add_or_get_root_id(&connection, &path_display(&canonical_root))?;
+    replace_root_exclusions(&mut connection, root_id, &globs)
+}
+
+#[tauri::command]
+pub(crate) fn get_root_exclusions(app: AppHandle, root_path: String) -> CommandResult<Vec<String>> {
+    let canonical_root = canonicalize_folder(&root_path)?;
+    let connection = open_database(&app)?;
+    let root_id = add_or_get_root_id(&connection, &path_display(&canonical_root))?;
+    load_root_exclusions(&connection, root_id)
+}
+
+#[tauri::command]
+pub(crate) fn set_root_intake(
+    app: AppHandle,
+    root_path: String,
+    intake_path: String,
+    target_folder: Option<String>,
+) -> CommandResult<Vec<RootIntake>> {
+    let canonical_root = canonicalize_folder(&root_path)?;
+    let canonical_intake = canonicalize_folder(&intake_path)?;
+    if canonical_intake.starts_with(&canonical_root)
+        || canonical_root.starts_with(&canonical_intake)
+    {
+        return Err(CommandError::validation(
+            "The intake folder must be outside the indexed root.",
+        ));
+    }
+
+    let target_folder =
+        normalize_root_subfolder(target_folder.as_deref(), DEFAULT_INTAKE_TARGET_FOLDER)?;
+    if target_folder.is_empty() {
+        return Err(CommandError::validation(
+            "Choose a folder inside the root for intake files.",
+        ));
+    }
+
+    let connection = open_database(&app)?;
+    let root_id = add_or_get_root_id(&connection, &path_display(&canonical_root))?;
+    upsert_root_intake(
+        &connection,
+        root_id,
+        &path_display(&canonical_intake),
+        &target_folder,
+    )?;
+    list_root_intakes(&connection)
+}
+
+#[tauri::command]
+pub(crate) fn remove_root_intake(
+    app: AppHandle,
+    root_path: String,
+) -> CommandResult<Vec<RootIntake>> {
+    let root_path_string = canonicalize_folder(&root_path)
+        .map(|canonical| path_display(&canonical))
+        .unwrap_or(root_path);
+    let connection = open_database(&app)?;
+    if let Some(root_id) = root_id(&connection, &root_path_string)? {
+        delete_root_intake(&connection, root_id)?;
+    }
+    list_root_intakes(&connection)
+}
+
+#[tauri::command]
+pub(crate) fn list_watched_roots(app: AppHandle) -> CommandResult<Vec<WatchedRoot>> {
+    let connection = open_database(&app)?;
+    load_watched_roots(&connection)
+}
+
+/// Watches an indexed root and re-indexes it whenever .docx files under it
+/// are created, saved, renamed or deleted. The watch is restored on launch.
+#[tauri::command]
+pub(crate) fn watch_root(app: AppHandle, root_path: String) -> CommandResult<Vec<WatchedRoot>> {
+    let root_path_string = path_display(&canonicalize_folder(&root_path)?);
+    let connection = open_database(&app)?;
+    let root_id = root_id(&connection, &root_path_string)?
+        .ok_or_else(|| CommandError::root_not_indexed(root_path_string.as_str()))?;
+    start_watch(&app, &root_path_string)?;
+    store_root_watch(&connection, root_id)?;
+    load_watched_roots(&connection)
+}
+
+#[tauri::command]
+pub(crate) fn unwatch_root(app: AppHandle, root_path: String) -> CommandResult<Vec<WatchedRoot>> {
+    let root_path_string = canonicalize_folder(&root_path)
+        .map(|canonical| path_display(&canonical))
+        .unwrap_or(root_path);
+    stop_watch(&root_path_string);
+    let connection = open_database(&app)?;
+    if let Some(root_id) = root_id(&connection, &root_path_string)? {
+        delete_root_watch(&connection, root_id)?;
+    }
+    load_watched_roots(&connection)
+}
+
+const DEFAULT_IMPORT_FOLDER: &str = "Imported";
+
+/// Writes a converted document into `folder` under the root and re-indexes
+/// the root so the new file is searchable straight away.
+fn write_document_into_root(
+    app: AppHandle,
+    root_path: &str,
+    folder: Option<&str>,
+    title: &str,
+    document: ConvertedDocument,
+    cite_stub: bool,
+) -> CommandResult<DocumentImportResult> {
+    let canonical_root = canonicalize_folder(root_path)?;
+    let root_path_string = path_display(&canonical_root);
+    let folder = normalize_root_subfolder(folder, DEFAULT_IMPORT_FOLDER)?;
+    let target_dir = canonical_root.join(&folder);
+    fs::create_dir_all(&target_dir).map_err(|error| {
+        CommandError::io(format!(
+            "Could not create folder '{}': {error}",
+            path_display(&target_dir)
+        ))
+    })?;
+    let docx_path =
+        unique_docx_path(target_dir.join(format!("{}.docx", file_stem_from_title(title))));
+
+    let counts = write_converted_document(&docx_path, title, document, cite_stub)?;
+    let relative_path = docx_path
+        .strip_prefix(&canonical_root)
+        .map(|relative| path_display(relative).replace('\\', "/"))
+        .unwrap_or_else(|_| path_display(&docx_path));
+    let index = index_root_blocking(app, root_path_string)?;
+
+    Ok(DocumentImportResult {
+        docx_path: path_display(&docx_path),
+        relative_path,
+        title: title.to_string(),
+        heading_count: counts.heading_count,
+        paragraph_count: counts.paragraph_count,
+        link_count: counts.link_count,
+        index,
+    })
+}
+
+#[tauri::command]
+pub(crate) fn import_html_document(
+    app: AppHandle,
+    root_path: String,
+    html_path: String,
+    folder: Option<String>,
+) -> CommandResult<DocumentImportResult> {
+    let html_path = Path::new(html_path.trim());
+    let bytes = fs::read(html_path).map_err(|error| {
+        CommandError::io(format!(
+            "Could not read HTML file '{}': {error}",
+            path_display(html_path)
+        ))
+    })?;
+    let document = convert_html_document(&String::from_utf8_lossy(&bytes));
+    if document.paragraphs.is_empty() {
+        return Err(CommandError::parse(format!(
+            "'{}' has no readable text to import.",
+            path_display(html_path)
+        )));
+    }
+
+    let title = document
+        .title
+        .clone()
+        .or_else(|| {
+            document
+                .paragraphs
+                .iter()
+                .find(|paragraph| paragraph.heading_level.is_some())
+                .map(|paragraph| {
+                    paragraph
+                        .runs
+                        .iter()
+                        .map(|run| run.text.as_str())
+                        .collect::<String>()
+                })
+        })
+        .or_else(|| {
+            html_path
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().into_owned())
+        })
+        .unwrap_or_else(|| "Imported article".to_string());
+    write_document_into_root(app, &root_path, folder.as_deref(), &title, document, false)
+}
+
+/// Creates a source document from pasted rich text. The webview reads the
+/// clipboard and passes whichever flavours it has; HTML is preferred over
+/// RTF, and plain text is the last resort.
+#[tauri::command]
+pub(crate) fn create_document_from_clipboard(
+    app: AppHandle,
+    root_path: String,
+    folder: Option<String>,
+    title: String,
+    html: Option<String>,
+    rtf: Option<String>,
+    text: Option<String>,
+) -> CommandResult<DocumentImportResult> {
+    let title = title.split_whitespace().collect::<Vec<&str>>().join(" ");
+    if title.is_empty() {
+        return Err(CommandError::validation_field(
+            "title",
+            "Enter a title for the new document.",
+        ));
+    }
+    optional_text(TITLE, &title, MAX_TITLE_CHARS)?;
+
+    let non_empty = |value: Option<String>| value.filter(|value| !value.trim().is_empty());
+    let mut document = if let Some(html) = non_empty(html) {
+        convert_html_document(&html)
+    } else if let Some(rtf) = non_empty(rtf) {
+        convert_rtf_document(&rtf)
+    } else {
+        ConvertedDocument::default()
+    };
+    if document.paragraphs.is_empty() {
+        document.paragraphs = non_empty(text)
+            .map(|text| plain_text_paragraphs(&text))
+            .unwrap_or_default();
+    }
+    if document.paragraphs.is_empty() {
+        return Err(CommandError::validation(
+            "The clipboard has no text to turn into a document.",
+        ));
+    }
+
+    write_document_into_root(app, &root_path, folder.as_deref(), &title, document, true)
+}
+
+#[tauri::command]
+pub(crate) fn list_path_mappings(app: AppHandle) -> CommandResult<Vec<PathMapping>> {
+    let connection = open_database(&app)?;
+    load_path_mappings(&connection)
+}
+
+/// Maps the path a shared root has on another machine (e.g. a Windows
+/// Dropbox folder) onto the local root.
+#[tauri::command]
+pub(crate) fn add_path_mapping(
+    app: AppHandle,
+    foreign_root_path: String,
+    root_path: String,
+) -> CommandResult<Vec<PathMapping>> {
+    let canonical_root = canonicalize_folder(&root_path)?;
+    let root_path_string = path_display(&canonical_root);
+    let connection = open_database(&app)?;
+    let root_id = root_id(&connection, &root_path_string)?
+        .ok_or_else(|| CommandError::root_not_indexed(root_path_string.as_str()))?;
+    upsert_path_mapping(&connection, &foreign_root_path, root_id)?;
+    load_path_mappings(&connection)
+}
+
+#[tauri::command]
+pub(crate) fn remove_path_mapping(
+    app: AppHandle,
+    foreign_root_path: String,
+) -> CommandResult<Vec<PathMapping>> {
+    let connection = open_database(&app)?;
+    delete_path_mapping(&connection, &foreign_root_path)?;
+    load_path_mappings(&connection)
+}
+
+#[tauri::command]
+pub(crate) fn list_allowed_locations(app: AppHandle) -> CommandResult<Vec<AllowedLocation>> {
+    let connection = open_database(&app)?;
+    load_allowed_locations(&connection)
+}
+
+/// Lets commands read and write inside `path` even though it is not a root,
+/// e.g. a shared folder of source files that should not be indexed.
+#[tauri::command]
+pub(crate) fn add_allowed_location(
+    app: AppHandle,
+    path: String,
+) -> CommandResult<Vec<AllowedLocation>> {
+    let canonical = canonicalize_folder(&path)?;
+    let connection = open_database(&app)?;
+    store_allowed_location(&connection, &canonical)?;
+    load_allowed_locations(&connection)
+}
+
+#[tauri::command]
+pub(crate) fn remove_allowed_location(
+    app: AppHandle,
+    path: String,
+) -> CommandResult<Vec<AllowedLocation>> {
+    let connection = open_database(&app)?;
+    delete_allowed_location(&connection, &path)?;
+    load_allowed_locations(&connection)
+}
+
+#[tauri::command]
+pub(crate) fn resolve_shared_file_path(
+    app: AppHandle,
+    path: String,
+) -> CommandResult<Option<ResolvedSharedPath>> {
+    let connection = open_database(&app)?;
+    resolve_shared_path(&connection, &path)
+}
+
+#[tauri::command]
+pub(crate) fn get_portable_link(
+    app: AppHandle,
+    file_id: i64,
+    heading_order: Option<i64>,
+) -> CommandResult<String> {
+    let connection = open_database(&app)?;
+    let (root_id, root_path, relative_path) = connection
         .query_row(
-            "SELECT last_indexed_ms FROM roots WHERE id = ?1",
-            params![root_id],
-            |row| row.get::<_, i64>(0),
-        )
-        .map_err(|error| format!("Could not read root timestamp: {error}"))?;
-
-    let mut statement = connection
-        .prepare(
             "
-            SELECT id, relative_path, modified_ms, heading_count
-            FROM files
-            WHERE root_id = ?1
-            ORDER BY relative_path
+            SELECT r.id, r.path, f.relative_path
+            FROM files f
+            JOIN roots r ON r.id = f.root_id
+            WHERE f.id = ?1
             ",
+            params![file_id],
+            |row| {
+                Ok((
+                    row.get::<_, i64>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, String>(2)?,
+                ))
+            },
         )
-        .map_err(|error| format!("Could not prepare file snapshot query: {error}"))?;
-
-    let rows = statement
-        .query_map(params![root_id], |row| {
-            Ok(FileRecord {
-                id: row.get(0)?,
-                relative_path: row.get(1)?,
-                modified_ms: row.get(2)?,
-                heading_count: row.get(3)?,
-            })
-        })
-        .map_err(|error| format!("Could not iterate indexed files: {error}"))?;
+        .map_err(|error| {
+            CommandError::database(format!("Could not load file {file_id}: {error}"))
+        })?;
+    let root_uid = ensure_root_identity(&connection, root_id, Path::new(&root_path))?;
+    Ok(portable_link(&root_uid, &relative_path, heading_order))
+}
 
-    let mut files = Vec::new();
-    let mut folders = HashMap::new();
-    ensure_folder_with_ancestors(&mut folders, "");
+#[tauri::command]
+pub(crate) fn open_portable_link(
+    app: AppHandle,
+    link: String,
+) -> CommandResult<ResolvedSharedPath> {
+    let connection = open_database(&app)?;
+    resolve_portable_link(&connection, &link)
+}
 
-    for row in rows {
-        let record = row.map_err(|error| format!("Could not parse indexed file row: {error}"))?;
-        let folder_path = folder_from_relative(&record.relative_path);
-        ensure_folder_with_ancestors(&mut folders, &folder_path);
+/// The checked absolute path of an indexed file, for handing to the OS.
+fn external_file_path(connection: &Connection, file_id: i64) -> CommandResult<PathBuf> {
+    let absolute_path = connection
+        .query_row(
+            "SELECT absolute_path FROM files WHERE id = ?1",
+            params![file_id],
+            |row| row.get::<_, String>(0),
+        )
+        .optional()
+        .map_err(|error| CommandError::database(format!("Could not load file {file_id}: {error}")))?
+        .ok_or_else(|| CommandError::not_indexed(format!("File {file_id} is not indexed")))?;
+    let absolute_path = guard_path(connection, Path::new(&absolute_path))?;
+    if !absolute_path.is_file() {
+        return Err(CommandError::io(format!(
+            "'{}' no longer exists; reindex its root",
+            path_display(&absolute_path)
+        )));
+    }
+    Ok(absolute_path)
+}
 
-        let mut current_folder = folder_path.clone();
-        loop {
-            if let Some(folder_entry) = folders.get_mut(&current_folder) {
-                folder_entry.file_count += 1;
-            }
+/// Shows the file selected in Explorer or Finder.
+#[tauri::command]
+pub(crate) fn reveal_in_file_manager(app: AppHandle, file_id: i64) -> CommandResult<()> {
+    let connection = open_database(&app)?;
+    let absolute_path = external_file_path(&connection, file_id)?;
+    app.opener()
+        .reveal_item_in_dir(&absolute_path)
+        .map_err(|error| {
+            CommandError::io(format!(
+                "Could not show '{}' in the file manager: {error}",
+                path_display(&absolute_path)
+            ))
+        })
+}
 
-            if current_folder.is_empty() {
-                break;
-            }
+/// Opens the file in its default app, normally Word. A `heading_order` is
+/// checked against the index so a stale hit fails here rather than in Word.
+#[tauri::command]
+pub(crate) fn open_externally(
+    app: AppHandle,
+    file_id: i64,
+    heading_order: Option<i64>,
+) -> CommandResult<ExternalOpenResult> {
+    let connection = open_database(&app)?;
+    let absolute_path = external_file_path(&connection, file_id)?;
+    let heading_text = match heading_order {
+        Some(heading_order) => Some(
+            connection
+                .query_row(
+                    "SELECT text FROM headings WHERE file_id = ?1 AND heading_order = ?2",
+                    params![file_id, heading_order],
+                    |row| row.get::<_, String>(0),
+                )
+                .optional()
+                .map_err(|error| {
+                    CommandError::database(format!("Could not load heading: {error}"))
+                })?
+                .ok_or_else(|| CommandError::not_indexed(messages::message("error.headingGone")))?,
+        ),
+        None => None,
+    };
+    let path_string = path_display(&absolute_path);
+    app.opener()
+        .open_path(path_string.as_str(), None::<&str>)
+        .map_err(|error| CommandError::io(format!("Could not open '{path_string}': {error}")))?;
+    Ok(ExternalOpenResult {
+        absolute_path: path_string,
+        heading_text,
+    })
+}
 
-            current_folder = current_folder
-                .rsplit_once('/')
-                .map(|(parent, _)| parent.to_string())
-                .unwrap_or_default();
+#[tauri::command]
+pub(crate) fn import_block_pack(
+    app: AppHandle,
+    path: String,
+    root: Option<String>,
+) -> CommandResult<BlockPackImportResult> {
+    let pack_path = Path::new(path.trim());
+    let manifest = read_block_pack_manifest(pack_path)?;
+    let root = match root.filter(|root| !root.trim().is_empty()) {
+        Some(root) => root,
+        None => {
+            let connection = open_database(&app)?;
+            resolve_shared_root(
+                &connection,
+                &manifest.source_root_path,
+                manifest.source_root_uid.as_deref(),
+            )?
+            .map(|(_, root_path)| root_path)
+            .ok_or_else(|| {
+                CommandError::not_indexed(format!(
+                    "No local root matches '{}'. Choose a folder or map the path first.",
+                    manifest.source_root_path
+                ))
+            })?
         }
+    };
+    let canonical_root = canonicalize_folder(&root)?;
+    let root_path_string = path_display(&canonical_root);
+    let mut operation = OperationHandle::start(
+        &app,
+        "block-pack-import",
+        Some(root_path_string.clone()),
+        true,
+    );
+    let extracted = match extract_block_pack(pack_path, &canonical_root, &manifest, &mut operation)
+    {
+        Ok(extracted) => {
+            operation.finish();
+            extracted
+        }
+        Err(error) => {
+            operation.fail(&error);
+            return Err(error);
+        }
+    };
+    let index = index_root_blocking(app.clone(), root_path_string.clone())?;
+    let connection = open_database(&app)?;
+    let root_id_value = add_or_get_root_id(&connection, &root_path_string)?;
+    let tag_count = restore_pack_tags(
+        &connection,
+        root_id_value,
+        &manifest,
+        &extracted.local_paths,
+    )?;
 
-        files.push(IndexedFile {
-            id: record.id,
-            file_name: file_name_from_relative(&record.relative_path),
-            relative_path: record.relative_path,
-            folder_path,
-            modified_ms: record.modified_ms,
-            heading_count: record.heading_count,
-        });
-    }
-
-    let mut folder_values = folders.into_values().collect::<Vec<FolderEntry>>();
-    folder_values.sort_by(|left, right| {
-        left.depth
-            .cmp(&right.depth)
-            .then(left.path.cmp(&right.path))
-    });
-
-    Ok(IndexSnapshot {
-        root_path: canonical_path,
-        indexed_at_ms,
-        folders: folder_values,
-        files,
+    Ok(BlockPackImportResult {
+        root_path: root_path_string,
+        written_paths: extracted.written_paths,
+        skipped_paths: extracted.skipped_paths,
+        index,
+        tag_count,
     })
 }
 
 #[tauri::command]
-pub(crate) fn get_file_preview(app: AppHandle, file_id: i64) -> CommandResult<FilePreview> {
+pub(crate) async fn get_file_preview(app: AppHandle, file_id: i64) -> CommandResult<FilePreview> {
+    tauri::async_runtime::spawn_blocking(move || get_file_preview_blocking(app, file_id))
+        .await
+        .map_err(|error| CommandError::internal(format!("File preview command failed: {error}")))?
+}
+
+pub(crate) fn get_file_preview_blocking(
+    app: AppHandle,
+    file_id: i64,
+) -> CommandResult<FilePreview> {
     let connection = open_database(&app)?;
 
     let (relative_path, absolute_path, heading_count) = connection
@@ -1155,9 +4665,11 @@ pub(crate) fn get_file_preview(app: AppHandle, file_id: i64) -> CommandResult<Fi
                 ))
             },
         )
-        .map_err(|error| format!("Could not load file preview metadata: {error}"))?;
-    let (mut headings, mut f8_cites) =
-        extract_preview_content(Path::new(&absolute_path)).unwrap_or_default();
+        .map_err(|error| {
+            CommandError::database(format!("Could not load file preview metadata: {error}"))
+        })?;
+    let source_path = guard_path(&connection, Path::new(&absolute_path))?;
+    let (mut headings, mut f8_cites) = extract_preview_content(&source_path).unwrap_or_default();
 
     headings.sort_by(|left, right| left.order.cmp(&right.order));
     f8_cites.sort_by(|left, right| left.order.cmp(&right.order));
@@ -1173,8 +4685,174 @@ pub(crate) fn get_file_preview(app: AppHandle, file_id: i64) -> CommandResult<Fi
     })
 }
 
+/// The file's headings as a tree, read from the document so it matches what
+/// the preview shows.
+#[tauri::command]
+pub(crate) async fn get_file_outline(app: AppHandle, file_id: i64) -> CommandResult<FileOutline> {
+    tauri::async_runtime::spawn_blocking(move || {
+        let connection = open_database(&app)?;
+        let (relative_path, absolute_path) = connection
+            .query_row(
+                "SELECT relative_path, absolute_path FROM files WHERE id = ?1",
+                params![file_id],
+                |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)),
+            )
+            .map_err(|error| {
+                CommandError::database(format!("Could not load file outline metadata: {error}"))
+            })?;
+        let source_path = guard_path(&connection, Path::new(&absolute_path))?;
+        let paragraphs = parse_docx_paragraphs(&source_path)?;
+        Ok(FileOutline {
+            file_id,
+            file_name: file_name_from_relative(&relative_path),
+            relative_path,
+            headings: build_heading_tree(&paragraphs),
+        })
+    })
+    .await
+    .map_err(|error| CommandError::internal(format!("File outline command failed: {error}")))?
+}
+
+#[tauri::command]
+pub(crate) fn get_file_history(app: AppHandle, file_id: i64) -> CommandResult<FileHistory> {
+    let connection = open_database(&app)?;
+    let relative_path = connection
+        .query_row(
+            "SELECT relative_path FROM files WHERE id = ?1",
+            params![file_id],
+            |row| row.get::<_, String>(0),
+        )
+        .map_err(|error| {
+            CommandError::database(format!("Could not load file history metadata: {error}"))
+        })?;
+    let runs = load_file_history(&connection, file_id)?;
+
+    Ok(FileHistory {
+        file_id,
+        file_name: file_name_from_relative(&relative_path),
+        relative_path,
+        runs,
+    })
+}
+
+#[tauri::command]
+pub(crate) fn add_cut_queue_item(
+    app: AppHandle,
+    root_path: String,
+    file_id: i64,
+    heading_order: Option<i64>,
+    assignee: Option<String>,
+    note: Option<String>,
+) -> CommandResult<CutQueueItem> {
+    let canonical_root = canonicalize_folder(&root_path)?;
+    let connection = open_database(&app)?;
+    let root_id = add_or_get_root_id(&connection, &path_display(&canonical_root))?;
+    let relative_path = connection
+        .query_row(
+            "SELECT relative_path FROM files WHERE id = ?1 AND root_id = ?2",
+            params![file_id, root_id],
+            |row| row.get::<_, String>(0),
+        )
+        .map_err(|error| {
+            CommandError::database(format!(
+                "Could not find file {file_id} for cut queue: {error}"
+            ))
+        })?;
+
+    let heading_order = heading_order.filter(|order| *order > 0);
+    let heading_text = match heading_order {
+        Some(order) => Some(
+            connection
+                .query_row(
+                    "SELECT text FROM headings WHERE file_id = ?1 AND heading_order = ?2",
+                    params![file_id, order],
+                    |row| row.get::<_, String>(0),
+                )
+                .map_err(|error| {
+                    CommandError::database(format!(
+                        "Could not find heading {order} for cut queue: {error}"
+                    ))
+                })?,
+        ),
+        None => None,
+    };
+
+    let item_id = insert_cut_queue_item(
+        &connection,
+        root_id,
+        &relative_path,
+        heading_order,
+        heading_text.as_deref(),
+        assignee.as_deref().map(str::trim).unwrap_or(""),
+        note.as_deref().map(str::trim).unwrap_or(""),
+    )?;
+    load_cut_queue_item(&connection, item_id)?
+        .ok_or_else(|| CommandError::not_found(format!("Cut queue item {item_id} was not found.")))
+}
+
+#[tauri::command]
+pub(crate) fn list_cut_queue(
+    app: AppHandle,
+    root_path: String,
+    status: Option<String>,
+) -> CommandResult<Vec<CutQueueItem>> {
+    let canonical_root = canonicalize_folder(&root_path)?;
+    let connection = open_database(&app)?;
+    let root_id = add_or_get_root_id(&connection, &path_display(&canonical_root))?;
+    let status = status.as_deref().map(normalize_cut_status).transpose()?;
+    list_cut_queue_items(&connection, root_id, status)
+}
+
+#[tauri::command]
+pub(crate) fn update_cut_queue_item(
+    app: AppHandle,
+    item_id: i64,
+    status: Option<String>,
+    assignee: Option<String>,
+    note: Option<String>,
+) -> CommandResult<CutQueueItem> {
+    let connection = open_database(&app)?;
+    let status = status.as_deref().map(normalize_cut_status).transpose()?;
+    if !update_cut_queue_fields(
+        &connection,
+        item_id,
+        status,
+        assignee.as_deref().map(str::trim),
+        note.as_deref().map(str::trim),
+    )? {
+        return Err(CommandError::not_found(format!(
+            "Cut queue item {item_id} was not found."
+        )));
+    }
+    load_cut_queue_item(&connection, item_id)?
+        .ok_or_else(|| CommandError::not_found(format!("Cut queue item {item_id} was not found.")))
+}
+
+#[tauri::command]
+pub(crate) fn delete_cut_queue_item(app: AppHandle, item_id: i64) -> CommandResult<()> {
+    let connection = open_database(&app)?;
+    if !delete_cut_queue_row(&connection, item_id)? {
+        return Err(CommandError::not_found(format!(
+            "Cut queue item {item_id} was not found."
+        )));
+    }
+    Ok(())
+}
+
 #[tauri::command]
-pub(crate) fn get_heading_preview_html(
+pub(crate) async fn get_heading_preview_html(
+    app: AppHandle,
+    file_id: i64,
+    heading_order: i64,
+) -> CommandResult<String> {
+    tauri::async_runtime::spawn_blocking(move || {
+        get_heading_preview_html_blocking(app, file_id, heading_order)
+    })
+    .await
+    .map_err(|error| CommandError::internal(format!("Heading preview command failed: {error}")))?
+}
+
+pub(crate) fn get_heading_preview_html_blocking(
     app: AppHandle,
     file_id: i64,
     heading_order: i64,
@@ -1190,9 +4868,14 @@ pub(crate) fn get_heading_preview_html(
             params![file_id],
             |row| row.get::<_, String>(0),
         )
-        .map_err(|error| format!("Could not load heading preview source file: {error}"))?;
+        .map_err(|error| {
+            CommandError::database(format!(
+                "Could not load heading preview source file: {error}"
+            ))
+        })?;
+    let absolute_path = guard_path(&connection, Path::new(&absolute_path))?;
 
-    extract_heading_preview_html(Path::new(&absolute_path), heading_order)
+    extract_heading_preview_html(&absolute_path, heading_order)
 }
 
 #[tauri::command]
@@ -1201,12 +4884,51 @@ pub(crate) async fn search_index(
     query: String,
     root_path: Option<String>,
     limit: Option<usize>,
-) -> CommandResult<Vec<SearchHit>> {
+    options: Option<SearchPageOptions>,
+) -> CommandResult<SearchPage> {
+    let generation = next_search_generation(SearchChannel::Lexical);
+    let started = Instant::now();
+    let options = options.unwrap_or_default();
+    // A cursor from the previous page takes precedence over a raw offset.
+    let offset = match options
+        .cursor
+        .as_deref()
+        .map(str::trim)
+        .filter(|value| !value.is_empty())
+    {
+        Some(cursor) => cursor.parse::<usize>().map_err(|_| {
+            CommandError::validation_field(
+                "cursor",
+                "This search cursor is not valid. Run the search again from the first page.",
+            )
+        })?,
+        None => options.offset.unwrap_or(0),
+    };
+    let (text_query, tags) = split_tag_filters(&query);
+    let filters = SearchFilters {
+        min_level: options.min_level,
+        max_level: options.max_level,
+        // An empty selection means no kind filter.
+        kinds: options.kind.filter(|kinds| !kinds.is_empty()),
+        tags,
+    };
     tauri::async_runtime::spawn_blocking(move || {
-        query_engine::search_lexical(&app, &query, root_path, limit)
+        let page = query_engine::search_lexical_page(
+            &app,
+            &text_query,
+            root_path.clone(),
+            limit,
+            offset,
+            &filters,
+            Some(generation),
+        )?;
+        ensure_current_search(Some(generation))?;
+        publish_search_event("lexical", &query, root_path.as_deref(), page.hits.len());
+        record_usage(&app, USAGE_SEARCH_LEXICAL, Some(elapsed_ms(started) as i64));
+        Ok(page)
     })
     .await
-    .map_err(|error| format!("Lexical search command failed: {error}"))?
+    .map_err(|error| CommandError::internal(format!("Lexical search command failed: {error}")))?
 }
 
 #[tauri::command]
@@ -1216,7 +4938,15 @@ pub(crate) async fn search_index_semantic(
     root_path: Option<String>,
     limit: Option<usize>,
 ) -> CommandResult<Vec<SearchHit>> {
-    query_engine::search_semantic(&app, &query, root_path, limit).await
+    let started = Instant::now();
+    let hits = query_engine::search_semantic(&app, &query, root_path.clone(), limit).await?;
+    publish_search_event("semantic", &query, root_path.as_deref(), hits.len());
+    record_usage(
+        &app,
+        USAGE_SEARCH_SEMANTIC,
+        Some(elapsed_ms(started) as i64),
+    );
+    Ok(hits)
 }
 
 #[tauri::command]
@@ -1228,15 +4958,22 @@ pub(crate) async fn search_index_hybrid(
     file_name_only: Option<bool>,
     semantic_enabled: Option<bool>,
 ) -> CommandResult<Vec<SearchHit>> {
-    query_engine::search_hybrid(
+    let generation = next_search_generation(SearchChannel::Hybrid);
+    let started = Instant::now();
+    let hits = query_engine::search_hybrid(
         &app,
         &query,
-        root_path,
+        root_path.clone(),
         limit,
         file_name_only.unwrap_or(false),
         semantic_enabled.unwrap_or(true),
+        Some(generation),
     )
-    .await
+    .await?;
+    ensure_current_search(Some(generation))?;
+    publish_search_event("hybrid", &query, root_path.as_deref(), hits.len());
+    record_usage(&app, USAGE_SEARCH_HYBRID, Some(elapsed_ms(started) as i64));
+    Ok(hits)
 }
 
 fn elapsed_ms(started: Instant) -> f64 {
@@ -1375,16 +5112,25 @@ fn collect_benchmark_queries(
             LIMIT 240
             ",
         )
-        .map_err(|error| format!("Could not prepare benchmark heading query source: {error}"))?;
+        .map_err(|error| {
+            CommandError::database(format!(
+                "Could not prepare benchmark heading query source: {error}"
+            ))
+        })?;
     let heading_rows = heading_statement
         .query_map(params![root_id_value], |row| row.get::<_, String>(0))
-        .map_err(|error| format!("Could not load benchmark heading query source: {error}"))?;
+        .map_err(|error| {
+            CommandError::database(format!(
+                "Could not load benchmark heading query source: {error}"
+            ))
+        })?;
     for row in heading_rows {
         if queries.len() >= max_queries {
             break;
         }
-        let text =
-            row.map_err(|error| format!("Could not parse benchmark heading text: {error}"))?;
+        let text = row.map_err(|error| {
+            CommandError::database(format!("Could not parse benchmark heading text: {error}"))
+        })?;
         for candidate in query_candidates_from_text(&text) {
             if queries.len() >= max_queries {
                 break;
@@ -1404,16 +5150,25 @@ fn collect_benchmark_queries(
             LIMIT 120
             ",
         )
-        .map_err(|error| format!("Could not prepare benchmark author query source: {error}"))?;
+        .map_err(|error| {
+            CommandError::database(format!(
+                "Could not prepare benchmark author query source: {error}"
+            ))
+        })?;
     let author_rows = author_statement
         .query_map(params![root_id_value], |row| row.get::<_, String>(0))
-        .map_err(|error| format!("Could not load benchmark author query source: {error}"))?;
+        .map_err(|error| {
+            CommandError::database(format!(
+                "Could not load benchmark author query source: {error}"
+            ))
+        })?;
     for row in author_rows {
         if queries.len() >= max_queries {
             break;
         }
-        let text =
-            row.map_err(|error| format!("Could not parse benchmark author text: {error}"))?;
+        let text = row.map_err(|error| {
+            CommandError::database(format!("Could not parse benchmark author text: {error}"))
+        })?;
         for candidate in query_candidates_from_text(&text) {
             if queries.len() >= max_queries {
                 break;
@@ -1432,16 +5187,27 @@ fn collect_benchmark_queries(
             LIMIT 180
             ",
         )
-        .map_err(|error| format!("Could not prepare benchmark file query source: {error}"))?;
+        .map_err(|error| {
+            CommandError::database(format!(
+                "Could not prepare benchmark file query source: {error}"
+            ))
+        })?;
     let file_rows = file_statement
         .query_map(params![root_id_value], |row| row.get::<_, String>(0))
-        .map_err(|error| format!("Could not load benchmark file query source: {error}"))?;
+        .map_err(|error| {
+            CommandError::database(format!(
+                "Could not load benchmark file query source: {error}"
+            ))
+        })?;
     for row in file_rows {
         if queries.len() >= max_queries {
             break;
         }
-        let relative_path_value =
-            row.map_err(|error| format!("Could not parse benchmark file relative path: {error}"))?;
+        let relative_path_value = row.map_err(|error| {
+            CommandError::database(format!(
+                "Could not parse benchmark file relative path: {error}"
+            ))
+        })?;
         let file_name = file_name_from_relative(&relative_path_value);
         for candidate in query_candidates_from_text(&file_name) {
             if queries.len() >= max_queries {
@@ -1488,17 +5254,25 @@ fn sample_file_ids(
             ",
         )
         .map_err(|error| {
-            format!("Could not prepare benchmark file preview sample query: {error}")
+            CommandError::database(format!(
+                "Could not prepare benchmark file preview sample query: {error}"
+            ))
         })?;
     let rows = statement
         .query_map(params![root_id_value, limit_i64], |row| {
             row.get::<_, i64>(0)
         })
-        .map_err(|error| format!("Could not run benchmark file preview sample query: {error}"))?;
+        .map_err(|error| {
+            CommandError::database(format!(
+                "Could not run benchmark file preview sample query: {error}"
+            ))
+        })?;
 
     let mut output = Vec::new();
     for row in rows {
-        output.push(row.map_err(|error| format!("Could not parse sampled file id: {error}"))?);
+        output.push(row.map_err(|error| {
+            CommandError::database(format!("Could not parse sampled file id: {error}"))
+        })?);
     }
     Ok(output)
 }
@@ -1525,21 +5299,27 @@ fn sample_heading_refs(
             ",
         )
         .map_err(|error| {
-            format!("Could not prepare benchmark heading preview sample query: {error}")
+            CommandError::database(format!(
+                "Could not prepare benchmark heading preview sample query: {error}"
+            ))
         })?;
     let rows = statement
         .query_map(params![root_id_value, limit_i64], |row| {
             Ok((row.get::<_, i64>(0)?, row.get::<_, i64>(1)?))
         })
         .map_err(|error| {
-            format!("Could not run benchmark heading preview sample query: {error}")
+            CommandError::database(format!(
+                "Could not run benchmark heading preview sample query: {error}"
+            ))
         })?;
 
     let mut output = Vec::new();
     for row in rows {
-        output.push(
-            row.map_err(|error| format!("Could not parse sampled heading reference: {error}"))?,
-        );
+        output.push(row.map_err(|error| {
+            CommandError::database(format!(
+                "Could not parse sampled heading reference: {error}"
+            ))
+        })?);
     }
     Ok(output)
 }
@@ -1559,15 +5339,15 @@ pub(crate) async fn benchmark_root_performance(
     let root_path = path_display(&canonical_root);
 
     let _ = add_root(app.clone(), root_path.clone())?;
-    let index_full = index_root(app.clone(), root_path.clone())?;
-    let index_incremental = index_root(app.clone(), root_path.clone())?;
+    let index_full = index_root_blocking(app.clone(), root_path.clone())?;
+    let index_incremental = index_root_blocking(app.clone(), root_path.clone())?;
 
     let connection = open_database(&app)?;
     let root_id_value = root_id(&connection, &root_path)?.ok_or_else(|| {
-        format!(
+        CommandError::not_indexed(format!(
             "Benchmark root id missing for '{}'. Try indexing again.",
             root_path
-        )
+        ))
     })?;
 
     let benchmark_iterations = iterations.unwrap_or(3).clamp(1, 12);
@@ -1577,6 +5357,7 @@ pub(crate) async fn benchmark_root_performance(
     let provided_queries = queries.unwrap_or_default();
     let benchmark_queries =
         collect_benchmark_queries(&connection, root_id_value, &provided_queries, 32)?;
+    let search_settings = load_search_settings(&connection)?;
 
     let mut search = BenchmarkSearchSummary {
         query_count: benchmark_queries.len(),
@@ -1591,13 +5372,24 @@ pub(crate) async fn benchmark_root_performance(
     'lexical_raw: for _ in 0..benchmark_iterations {
         for query in &benchmark_queries {
             let started = Instant::now();
-            match lexical::search(&app, query, Some(root_id_value), benchmark_limit, false) {
+            match lexical::search(
+                &app,
+                query,
+                Some(root_id_value),
+                benchmark_limit,
+                false,
+                &lexical::LexicalSearchOptions {
+                    filters: &SearchFilters::default(),
+                    settings: &search_settings,
+                    generation: None,
+                },
+            ) {
                 Ok(hits) => {
                     lexical_raw_samples.push(elapsed_ms(started));
                     lexical_raw_hits = lexical_raw_hits.saturating_add(hits.len());
                 }
                 Err(error) => {
-                    lexical_raw_error = Some(error);
+                    lexical_raw_error = Some(error.to_string());
                     break 'lexical_raw;
                 }
             }
@@ -1617,6 +5409,7 @@ pub(crate) async fn benchmark_root_performance(
             query,
             Some(root_path.clone()),
             Some(benchmark_limit),
+            None,
         );
     }
     let mut lexical_cached_samples = Vec::new();
@@ -1630,13 +5423,14 @@ pub(crate) async fn benchmark_root_performance(
                 query,
                 Some(root_path.clone()),
                 Some(benchmark_limit),
+                None,
             ) {
                 Ok(hits) => {
                     lexical_cached_samples.push(elapsed_ms(started));
                     lexical_cached_hits = lexical_cached_hits.saturating_add(hits.len());
                 }
                 Err(error) => {
-                    lexical_cached_error = Some(error);
+                    lexical_cached_error = Some(error.to_string());
                     break 'lexical_cached;
                 }
             }
@@ -1659,6 +5453,7 @@ pub(crate) async fn benchmark_root_performance(
                 Some(benchmark_limit),
                 false,
                 true,
+                None,
             )
             .await;
         }
@@ -1676,6 +5471,7 @@ pub(crate) async fn benchmark_root_performance(
                     Some(benchmark_limit),
                     false,
                     true,
+                    None,
                 )
                 .await
                 {
@@ -1684,7 +5480,7 @@ pub(crate) async fn benchmark_root_performance(
                         hybrid_hits = hybrid_hits.saturating_add(hits.len());
                     }
                     Err(error) => {
-                        hybrid_error = Some(error);
+                        hybrid_error = Some(error.to_string());
                         break 'hybrid;
                     }
                 }
@@ -1720,7 +5516,7 @@ pub(crate) async fn benchmark_root_performance(
                         semantic_hits = semantic_hits.saturating_add(hits.len());
                     }
                     Err(error) => {
-                        semantic_error = Some(error);
+                        semantic_error = Some(error.to_string());
                         break 'semantic;
                     }
                 }
@@ -1745,14 +5541,14 @@ pub(crate) async fn benchmark_root_performance(
     let mut file_preview_error: Option<String> = None;
     for file_id in sampled_file_ids {
         let started = Instant::now();
-        match get_file_preview(app.clone(), file_id) {
+        match get_file_preview_blocking(app.clone(), file_id) {
             Ok(file_preview) => {
                 file_preview_samples.push(elapsed_ms(started));
                 file_preview_hits = file_preview_hits
                     .saturating_add(usize::try_from(file_preview.heading_count).unwrap_or(0));
             }
             Err(error) => {
-                file_preview_error = Some(error);
+                file_preview_error = Some(error.to_string());
                 break;
             }
         }
@@ -1771,7 +5567,7 @@ pub(crate) async fn benchmark_root_performance(
     let mut heading_preview_error: Option<String> = None;
     for (file_id, heading_order) in sampled_heading_refs {
         let started = Instant::now();
-        match get_heading_preview_html(app.clone(), file_id, heading_order) {
+        match get_heading_preview_html_blocking(app.clone(), file_id, heading_order) {
             Ok(html) => {
                 heading_preview_samples.push(elapsed_ms(started));
                 if !html.trim().is_empty() {
@@ -1779,7 +5575,7 @@ pub(crate) async fn benchmark_root_performance(
                 }
             }
             Err(error) => {
-                heading_preview_error = Some(error);
+                heading_preview_error = Some(error.to_string());
                 break;
             }
         }
@@ -1802,3 +5598,31 @@ pub(crate) async fn benchmark_root_performance(
         elapsed_ms: elapsed_ms(benchmark_started).round() as i64,
     })
 }
+
+/// Notes that a file was opened, so it shows up in `list_recent_files`.
+#[tauri::command]
+pub(crate) fn record_file_open(app: AppHandle, file_id: i64) -> CommandResult<()> {
+    let connection = open_database(&app)?;
+    store_file_open(&connection, file_id)
+}
+
+#[tauri::command]
+pub(crate) fn set_file_pinned(app: AppHandle, file_id: i64, pinned: bool) -> CommandResult<()> {
+    let connection = open_database(&app)?;
+    store_file_pinned(&connection, file_id, pinned)
+}
+
+#[tauri::command]
+pub(crate) fn list_recent_files(
+    app: AppHandle,
+    limit: Option<usize>,
+) -> CommandResult<Vec<ActivityFile>> {
+    let connection = open_database(&app)?;
+    load_recent_files(&connection, limit.unwrap_or(DEFAULT_RECENT_FILES_LIMIT))
+}
+
+#[tauri::command]
+pub(crate) fn list_pinned_files(app: AppHandle) -> CommandResult<Vec<ActivityFile>> {
+    let connection = open_database(&app)?;
+    load_pinned_files(&connection)
+}
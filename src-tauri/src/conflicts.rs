@@ -0,0 +1,307 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs;
+use std::path::Path;
+
+use rusqlite::{params, Connection};
+
+use crate::errors::CommandError;
+use crate::shards::schema_for_root;
+use crate::types::{SyncConflictCopy, SyncConflictGroup, SyncConflictResolution};
+use crate::util::{file_name_from_relative, folder_from_relative, now_ms, path_display};
+use crate::CommandResult;
+
+pub(crate) const CONFLICT_ARCHIVE_DIR_NAME: &str = ".blockfile-conflicts";
+const MAX_DUPLICATE_SUFFIX: u32 = 99;
+
+/// How a copy's name marks it as a conflict copy.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum ConflictMarker {
+    /// "(conflicted copy ...)" and the like, which only sync clients write.
+    Explicit,
+    /// A "(n)" suffix, which people also use for their own numbered files.
+    Numbered,
+}
+
+fn strip_conflict_parenthetical(stem: &str) -> Option<(&str, ConflictMarker)> {
+    let trimmed = stem.trim_end();
+    let inner_start = trimmed.strip_suffix(')')?.rfind('(')?;
+    let inner = &trimmed[inner_start + 1..trimmed.len() - 1];
+    let base = trimmed[..inner_start].trim_end();
+    if base.is_empty() {
+        return None;
+    }
+
+    let lowered = inner.to_ascii_lowercase();
+    if lowered.contains("conflicted copy") || lowered.starts_with("conflict") {
+        return Some((base, ConflictMarker::Explicit));
+    }
+
+    // Drive and OneDrive name duplicate uploads "Name (1)", "Name (2)", ...
+    if let Ok(copy_number) = inner.trim().parse::<u32>() {
+        if (1..=MAX_DUPLICATE_SUFFIX).contains(&copy_number) {
+            return Some((base, ConflictMarker::Numbered));
+        }
+    }
+
+    None
+}
+
+/// Returns the relative path of the file a sync-conflict copy was forked from,
+/// or `None` when the path does not look like a conflict copy.
+fn conflict_original_relative_path(relative_path: &str) -> Option<(String, ConflictMarker)> {
+    let file_name = file_name_from_relative(relative_path);
+    let path = Path::new(&file_name);
+    let stem = path.file_stem()?.to_str()?;
+    let extension = path.extension().and_then(|value| value.to_str())?;
+    let (base, marker) = strip_conflict_parenthetical(stem)?;
+
+    let original_name = format!("{base}.{extension}");
+    let folder = folder_from_relative(relative_path);
+    let original_relative_path = if folder.is_empty() {
+        original_name
+    } else {
+        format!("{folder}/{original_name}")
+    };
+    Some((original_relative_path, marker))
+}
+
+pub(crate) fn record_sync_conflicts(connection: &Connection, root_id: i64) -> CommandResult<usize> {
+    let schema = schema_for_root(connection, root_id)?;
+    connection
+        .execute(
+            &format!("DELETE FROM {schema}.sync_conflicts WHERE root_id = ?1"),
+            params![root_id],
+        )
+        .map_err(|error| {
+            CommandError::database(format!("Could not clear previous sync conflicts: {error}"))
+        })?;
+
+    let mut statement = connection
+        .prepare("SELECT id, relative_path FROM files WHERE root_id = ?1")
+        .map_err(|error| {
+            CommandError::database(format!("Could not prepare sync conflict scan: {error}"))
+        })?;
+    let rows = statement
+        .query_map(params![root_id], |row| {
+            Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?))
+        })
+        .map_err(|error| {
+            CommandError::database(format!("Could not run sync conflict scan: {error}"))
+        })?;
+
+    let files = rows
+        .collect::<Result<Vec<(i64, String)>, _>>()
+        .map_err(|error| {
+            CommandError::database(format!("Could not parse sync conflict scan row: {error}"))
+        })?;
+    let relative_paths = files
+        .iter()
+        .map(|(_, relative_path)| relative_path.as_str())
+        .collect::<HashSet<&str>>();
+
+    // "Name (2).docx" only counts as a copy when "Name.docx" is there too.
+    let mut conflicts = Vec::new();
+    for (file_id, relative_path) in &files {
+        if let Some((original_relative_path, marker)) =
+            conflict_original_relative_path(relative_path)
+        {
+            if marker == ConflictMarker::Explicit
+                || relative_paths.contains(original_relative_path.as_str())
+            {
+                conflicts.push((*file_id, original_relative_path));
+            }
+        }
+    }
+
+    let detected_at_ms = now_ms();
+    for (file_id, original_relative_path) in &conflicts {
+        connection
+            .execute(
+                &format!(
+                    "INSERT INTO {schema}.sync_conflicts(root_id, file_id, original_relative_path, detected_at_ms)
+                     VALUES(?1, ?2, ?3, ?4)"
+                ),
+                params![root_id, file_id, original_relative_path, detected_at_ms],
+            )
+            .map_err(|error| {
+                CommandError::database(format!("Could not record sync conflict: {error}"))
+            })?;
+    }
+
+    Ok(conflicts.len())
+}
+
+pub(crate) fn load_sync_conflict_groups(
+    connection: &Connection,
+    root_id: i64,
+) -> CommandResult<Vec<SyncConflictGroup>> {
+    let mut statement = connection
+        .prepare(
+            "
+            SELECT c.original_relative_path, f.id, f.relative_path, f.modified_ms, f.size
+            FROM sync_conflicts c
+            JOIN files f ON f.id = c.file_id
+            WHERE c.root_id = ?1
+            ORDER BY c.original_relative_path ASC, f.relative_path ASC
+            ",
+        )
+        .map_err(|error| {
+            CommandError::database(format!(
+                "Could not prepare sync conflict report query: {error}"
+            ))
+        })?;
+    let rows = statement
+        .query_map(params![root_id], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                SyncConflictCopy {
+                    file_id: row.get(1)?,
+                    relative_path: row.get(2)?,
+                    modified_ms: row.get(3)?,
+                    size: row.get(4)?,
+                },
+            ))
+        })
+        .map_err(|error| {
+            CommandError::database(format!("Could not run sync conflict report query: {error}"))
+        })?;
+
+    let mut copies_by_original = BTreeMap::<String, Vec<SyncConflictCopy>>::new();
+    for row in rows {
+        let (original_relative_path, copy) = row.map_err(|error| {
+            CommandError::database(format!("Could not parse sync conflict row: {error}"))
+        })?;
+        copies_by_original
+            .entry(original_relative_path)
+            .or_default()
+            .push(copy);
+    }
+
+    let mut originals = HashMap::<String, SyncConflictCopy>::new();
+    {
+        let mut original_statement = connection
+            .prepare(
+                "SELECT id, relative_path, modified_ms, size FROM files WHERE root_id = ?1 AND relative_path = ?2",
+            )
+            .map_err(|error| {
+                CommandError::database(format!(
+                    "Could not prepare sync conflict original query: {error}"
+                ))
+            })?;
+        for original_relative_path in copies_by_original.keys() {
+            let mut original_rows = original_statement
+                .query_map(params![root_id, original_relative_path], |row| {
+                    Ok(SyncConflictCopy {
+                        file_id: row.get(0)?,
+                        relative_path: row.get(1)?,
+                        modified_ms: row.get(2)?,
+                        size: row.get(3)?,
+                    })
+                })
+                .map_err(|error| {
+                    CommandError::database(format!(
+                        "Could not run sync conflict original query: {error}"
+                    ))
+                })?;
+            if let Some(original) = original_rows.next() {
+                let original = original.map_err(|error| {
+                    CommandError::parse(format!("Could not parse sync conflict original: {error}"))
+                })?;
+                originals.insert(original_relative_path.clone(), original);
+            }
+        }
+    }
+
+    let mut groups = Vec::with_capacity(copies_by_original.len());
+    for (original_relative_path, copies) in copies_by_original {
+        let original = originals.remove(&original_relative_path);
+        let newest_relative_path = original
+            .iter()
+            .chain(copies.iter())
+            .max_by(|left, right| {
+                left.modified_ms
+                    .cmp(&right.modified_ms)
+                    .then(right.relative_path.cmp(&left.relative_path))
+            })
+            .map(|entry| entry.relative_path.clone())
+            .unwrap_or_else(|| original_relative_path.clone());
+
+        groups.push(SyncConflictGroup {
+            original_relative_path,
+            original,
+            copies,
+            newest_relative_path,
+        });
+    }
+
+    Ok(groups)
+}
+
+fn archive_conflict_file(root: &Path, relative_path: &str, stamp_ms: i64) -> CommandResult<String> {
+    let source = root.join(relative_path);
+    let archive_relative = format!("{CONFLICT_ARCHIVE_DIR_NAME}/{stamp_ms}/{relative_path}");
+    let destination = root.join(&archive_relative);
+    if let Some(parent) = destination.parent() {
+        fs::create_dir_all(parent).map_err(|error| {
+            CommandError::io(format!(
+                "Could not create conflict archive folder '{}': {error}",
+                path_display(parent)
+            ))
+        })?;
+    }
+    fs::rename(&source, &destination).map_err(|error| {
+        CommandError::io(format!(
+            "Could not archive conflict copy '{}': {error}",
+            path_display(&source)
+        ))
+    })?;
+    Ok(archive_relative)
+}
+
+/// Keeps the newest member of a conflict group at the original path and moves
+/// every other member into the hidden conflict archive folder, so nothing is
+/// deleted and the walker no longer sees duplicates. A group whose original
+/// is gone is refused: there is no file to keep the newest copy in place of.
+pub(crate) fn resolve_group_prefer_newest(
+    root: &Path,
+    group: &SyncConflictGroup,
+) -> CommandResult<SyncConflictResolution> {
+    let original_path = root.join(&group.original_relative_path);
+    if group.original.is_none() || !original_path.is_file() {
+        return Err(CommandError::validation(format!(
+            "'{}' has no original file, so its copies were left in place.",
+            group.original_relative_path
+        )));
+    }
+
+    let stamp_ms = now_ms();
+    let mut archived_paths = Vec::new();
+
+    if group.newest_relative_path != group.original_relative_path {
+        archived_paths.push(archive_conflict_file(
+            root,
+            &group.original_relative_path,
+            stamp_ms,
+        )?);
+        let newest_path = root.join(&group.newest_relative_path);
+        fs::copy(&newest_path, &original_path).map_err(|error| {
+            CommandError::io(format!(
+                "Could not promote newest conflict copy '{}': {error}",
+                path_display(&newest_path)
+            ))
+        })?;
+    }
+
+    for copy in &group.copies {
+        if !root.join(&copy.relative_path).is_file() {
+            continue;
+        }
+        archived_paths.push(archive_conflict_file(root, &copy.relative_path, stamp_ms)?);
+    }
+
+    Ok(SyncConflictResolution {
+        original_relative_path: group.original_relative_path.clone(),
+        kept_from_relative_path: group.newest_relative_path.clone(),
+        archived_paths,
+    })
+}
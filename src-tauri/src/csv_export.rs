@@ -0,0 +1,258 @@
+use rusqlite::{params, Connection};
+
+use crate::errors::CommandError;
+use crate::util::format_utc_timestamp;
+use crate::CommandResult;
+
+/// Prepended so spreadsheet apps detect UTF-8 instead of a legacy code page.
+pub(crate) const CSV_UTF8_BOM: &str = "\u{feff}";
+
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// Parses RFC 4180 CSV (quoted fields, doubled quotes, CRLF or LF rows),
+/// ignoring a leading byte-order mark and blank lines.
+pub(crate) fn parse_csv(text: &str) -> Vec<Vec<String>> {
+    let mut rows = Vec::new();
+    let mut row = Vec::new();
+    let mut field = String::new();
+    let mut in_quotes = false;
+    let mut characters = text.trim_start_matches(CSV_UTF8_BOM).chars().peekable();
+    while let Some(character) = characters.next() {
+        match (in_quotes, character) {
+            (true, '"') if characters.peek() == Some(&'"') => {
+                field.push('"');
+                characters.next();
+            }
+            (true, '"') => in_quotes = false,
+            (true, _) => field.push(character),
+            (false, '"') => in_quotes = true,
+            (false, ',') => row.push(std::mem::take(&mut field)),
+            (false, '\r') => {}
+            (false, '\n') => {
+                row.push(std::mem::take(&mut field));
+                if row.iter().any(|value| !value.is_empty()) {
+                    rows.push(std::mem::take(&mut row));
+                }
+                row.clear();
+            }
+            (false, _) => field.push(character),
+        }
+    }
+    row.push(field);
+    if row.iter().any(|value| !value.is_empty()) {
+        rows.push(row);
+    }
+    rows
+}
+
+pub(crate) fn push_csv_row(output: &mut String, fields: &[String]) {
+    let row = fields
+        .iter()
+        .map(|field| csv_field(field))
+        .collect::<Vec<String>>()
+        .join(",");
+    output.push_str(&row);
+    output.push_str("\r\n");
+}
+
+/// Renders every heading in a root as CSV rows of
+/// `relative_path,file_name,level,order,text`, in file and document order.
+pub(crate) fn render_headings_csv(
+    connection: &Connection,
+    root_id: i64,
+) -> CommandResult<(String, usize)> {
+    let mut statement = connection
+        .prepare(
+            "
+            SELECT h.relative_path, h.file_name, h.level, h.heading_order, h.text
+            FROM headings h
+            JOIN files f ON f.id = h.file_id
+            WHERE f.root_id = ?1
+            ORDER BY h.relative_path, h.heading_order
+            ",
+        )
+        .map_err(|error| {
+            CommandError::database(format!("Could not prepare heading CSV query: {error}"))
+        })?;
+    let rows = statement
+        .query_map(params![root_id], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, i64>(2)?,
+                row.get::<_, i64>(3)?,
+                row.get::<_, String>(4)?,
+            ))
+        })
+        .map_err(|error| {
+            CommandError::database(format!("Could not run heading CSV query: {error}"))
+        })?;
+
+    let mut output = String::new();
+    push_csv_row(
+        &mut output,
+        &[
+            "relative_path".to_string(),
+            "file_name".to_string(),
+            "level".to_string(),
+            "order".to_string(),
+            "text".to_string(),
+        ],
+    );
+    let mut count = 0;
+    for row in rows {
+        let (relative_path, file_name, level, order, text) = row.map_err(|error| {
+            CommandError::database(format!("Could not parse heading CSV row: {error}"))
+        })?;
+        push_csv_row(
+            &mut output,
+            &[
+                relative_path,
+                file_name,
+                level.to_string(),
+                order.to_string(),
+                text,
+            ],
+        );
+        count += 1;
+    }
+    Ok((output, count))
+}
+
+/// Renders every author line in a root as CSV rows of
+/// `relative_path,file_name,order,text`.
+pub(crate) fn render_authors_csv(
+    connection: &Connection,
+    root_id: i64,
+) -> CommandResult<(String, usize)> {
+    let mut statement = connection
+        .prepare(
+            "
+            SELECT a.relative_path, a.file_name, a.author_order, a.text
+            FROM authors a
+            JOIN files f ON f.id = a.file_id
+            WHERE f.root_id = ?1
+            ORDER BY a.relative_path, a.author_order
+            ",
+        )
+        .map_err(|error| {
+            CommandError::database(format!("Could not prepare author CSV query: {error}"))
+        })?;
+    let rows = statement
+        .query_map(params![root_id], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, i64>(2)?,
+                row.get::<_, String>(3)?,
+            ))
+        })
+        .map_err(|error| {
+            CommandError::database(format!("Could not run author CSV query: {error}"))
+        })?;
+
+    let mut output = String::new();
+    push_csv_row(
+        &mut output,
+        &[
+            "relative_path".to_string(),
+            "file_name".to_string(),
+            "order".to_string(),
+            "text".to_string(),
+        ],
+    );
+    let mut count = 0;
+    for row in rows {
+        let (relative_path, file_name, order, text) = row.map_err(|error| {
+            CommandError::database(format!("Could not parse author CSV row: {error}"))
+        })?;
+        push_csv_row(
+            &mut output,
+            &[relative_path, file_name, order.to_string(), text],
+        );
+        count += 1;
+    }
+    Ok((output, count))
+}
+
+/// Renders the capture log for a root as CSV rows of
+/// `captured_at,captured_at_ms,source_path,section_title,heading_level,target`,
+/// oldest first. `since_ms`/`until_ms` bound the export to a prep period.
+pub(crate) fn render_captures_csv(
+    connection: &Connection,
+    root_id: i64,
+    since_ms: Option<i64>,
+    until_ms: Option<i64>,
+) -> CommandResult<(String, usize)> {
+    let mut statement = connection
+        .prepare(
+            "
+            SELECT created_at_ms, source_path, section_title, heading_level, target_relative_path
+            FROM captures
+            WHERE root_id = ?1
+              AND created_at_ms >= ?2
+              AND created_at_ms <= ?3
+            ORDER BY created_at_ms, id
+            ",
+        )
+        .map_err(|error| {
+            CommandError::database(format!("Could not prepare capture CSV query: {error}"))
+        })?;
+    let rows = statement
+        .query_map(
+            params![root_id, since_ms.unwrap_or(0), until_ms.unwrap_or(i64::MAX)],
+            |row| {
+                Ok((
+                    row.get::<_, i64>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, String>(2)?,
+                    row.get::<_, Option<i64>>(3)?,
+                    row.get::<_, String>(4)?,
+                ))
+            },
+        )
+        .map_err(|error| {
+            CommandError::database(format!("Could not run capture CSV query: {error}"))
+        })?;
+
+    let mut output = String::new();
+    push_csv_row(
+        &mut output,
+        &[
+            "captured_at".to_string(),
+            "captured_at_ms".to_string(),
+            "source_path".to_string(),
+            "section_title".to_string(),
+            "heading_level".to_string(),
+            "target".to_string(),
+        ],
+    );
+    let mut count = 0;
+    for row in rows {
+        let (created_at_ms, source_path, section_title, heading_level, target) =
+            row.map_err(|error| {
+                CommandError::database(format!("Could not parse capture CSV row: {error}"))
+            })?;
+        push_csv_row(
+            &mut output,
+            &[
+                format_utc_timestamp(created_at_ms),
+                created_at_ms.to_string(),
+                source_path,
+                section_title,
+                heading_level
+                    .map(|level| level.to_string())
+                    .unwrap_or_default(),
+                target,
+            ],
+        );
+        count += 1;
+    }
+    Ok((output, count))
+}
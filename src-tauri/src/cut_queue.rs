@@ -0,0 +1,174 @@
+use rusqlite::{params, Connection, OptionalExtension};
+
+use crate::errors::CommandError;
+use crate::types::CutQueueItem;
+use crate::util::{file_name_from_relative, now_ms};
+use crate::CommandResult;
+
+pub(crate) const CUT_STATUS_TODO: &str = "todo";
+pub(crate) const CUT_STATUS_IN_PROGRESS: &str = "in-progress";
+pub(crate) const CUT_STATUS_DONE: &str = "done";
+
+pub(crate) fn normalize_cut_status(status: &str) -> CommandResult<&'static str> {
+    match status.trim().to_ascii_lowercase().as_str() {
+        "todo" => Ok(CUT_STATUS_TODO),
+        "in-progress" | "in_progress" | "inprogress" => Ok(CUT_STATUS_IN_PROGRESS),
+        "done" => Ok(CUT_STATUS_DONE),
+        other => Err(CommandError::validation_field(
+            "status",
+            format!("Unknown cut queue status '{other}'. Use todo, in-progress, or done."),
+        )),
+    }
+}
+
+const CUT_QUEUE_SELECT: &str = "
+    SELECT q.id, q.relative_path, q.heading_order, q.heading_text, q.status, q.assignee, q.note,
+           q.created_at_ms, q.updated_at_ms, f.id
+    FROM cut_queue q
+    LEFT JOIN files f ON f.root_id = q.root_id AND f.relative_path = q.relative_path
+";
+
+fn cut_queue_item_from_row(row: &rusqlite::Row<'_>) -> rusqlite::Result<CutQueueItem> {
+    let relative_path = row.get::<_, String>(1)?;
+    Ok(CutQueueItem {
+        id: row.get(0)?,
+        file_name: file_name_from_relative(&relative_path),
+        relative_path,
+        heading_order: row.get(2)?,
+        heading_text: row.get(3)?,
+        status: row.get(4)?,
+        assignee: row.get(5)?,
+        note: row.get(6)?,
+        created_at_ms: row.get(7)?,
+        updated_at_ms: row.get(8)?,
+        file_id: row.get(9)?,
+    })
+}
+
+pub(crate) fn load_cut_queue_item(
+    connection: &Connection,
+    item_id: i64,
+) -> CommandResult<Option<CutQueueItem>> {
+    connection
+        .query_row(
+            &format!("{CUT_QUEUE_SELECT} WHERE q.id = ?1"),
+            params![item_id],
+            cut_queue_item_from_row,
+        )
+        .optional()
+        .map_err(|error| {
+            CommandError::database(format!("Could not load cut queue item {item_id}: {error}"))
+        })
+}
+
+/// Queue rows reference files by relative path rather than file id so they
+/// survive a file being removed and re-added between index runs.
+pub(crate) fn insert_cut_queue_item(
+    connection: &Connection,
+    root_id: i64,
+    relative_path: &str,
+    heading_order: Option<i64>,
+    heading_text: Option<&str>,
+    assignee: &str,
+    note: &str,
+) -> CommandResult<i64> {
+    let created_at_ms = now_ms();
+    connection
+        .execute(
+            "
+            INSERT INTO cut_queue(
+              root_id,
+              relative_path,
+              heading_order,
+              heading_text,
+              status,
+              assignee,
+              note,
+              created_at_ms,
+              updated_at_ms
+            )
+            VALUES(?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?8)
+            ",
+            params![
+                root_id,
+                relative_path,
+                heading_order,
+                heading_text,
+                CUT_STATUS_TODO,
+                assignee,
+                note,
+                created_at_ms
+            ],
+        )
+        .map_err(|error| {
+            CommandError::database(format!("Could not add cut queue item: {error}"))
+        })?;
+    Ok(connection.last_insert_rowid())
+}
+
+pub(crate) fn list_cut_queue_items(
+    connection: &Connection,
+    root_id: i64,
+    status: Option<&str>,
+) -> CommandResult<Vec<CutQueueItem>> {
+    let mut statement = connection
+        .prepare(&format!(
+            "{CUT_QUEUE_SELECT}
+             WHERE q.root_id = ?1 AND (?2 IS NULL OR q.status = ?2)
+             ORDER BY q.relative_path ASC, COALESCE(q.heading_order, 0) ASC, q.id ASC"
+        ))
+        .map_err(|error| {
+            CommandError::database(format!("Could not prepare cut queue query: {error}"))
+        })?;
+    let rows = statement
+        .query_map(params![root_id, status], cut_queue_item_from_row)
+        .map_err(|error| {
+            CommandError::database(format!("Could not run cut queue query: {error}"))
+        })?;
+
+    let mut items = Vec::new();
+    for row in rows {
+        items.push(row.map_err(|error| {
+            CommandError::database(format!("Could not parse cut queue row: {error}"))
+        })?);
+    }
+    Ok(items)
+}
+
+pub(crate) fn update_cut_queue_fields(
+    connection: &Connection,
+    item_id: i64,
+    status: Option<&str>,
+    assignee: Option<&str>,
+    note: Option<&str>,
+) -> CommandResult<bool> {
+    let changed = connection
+        .execute(
+            "
+            UPDATE cut_queue
+            SET status = COALESCE(?2, status),
+                assignee = COALESCE(?3, assignee),
+                note = COALESCE(?4, note),
+                updated_at_ms = ?5
+            WHERE id = ?1
+            ",
+            params![item_id, status, assignee, note, now_ms()],
+        )
+        .map_err(|error| {
+            CommandError::database(format!(
+                "Could not update cut queue item {item_id}: {error}"
+            ))
+        })?;
+    Ok(changed > 0)
+}
+
+pub(crate) fn delete_cut_queue_row(connection: &Connection, item_id: i64) -> CommandResult<bool> {
+    let changed = connection
+        .execute("DELETE FROM cut_queue WHERE id = ?1", params![item_id])
+        .map_err(|error| {
+            CommandError::database(format!(
+                "Could not remove cut queue item {item_id}: {error}"
+            ))
+        })?;
+    Ok(changed > 0)
+}
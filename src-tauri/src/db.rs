@@ -5,6 +5,8 @@ use std::path::PathBuf;
 use rusqlite::{params, Connection, OptionalExtension};
 use tauri::{AppHandle, Manager};
 
+use crate::errors::CommandError;
+use crate::shards::attach_root_shards;
 use crate::types::ExistingFileMeta;
 use crate::util::{now_ms, path_display};
 use crate::CommandResult;
@@ -24,18 +26,18 @@ pub(crate) fn app_data_dir(app: &AppHandle) -> CommandResult<PathBuf> {
     let app_data = app
         .path()
         .app_data_dir()
-        .map_err(|error| format!("Could not resolve app data dir: {error}"))?;
+        .map_err(|error| CommandError::io(format!("Could not resolve app data dir: {error}")))?;
     fs::create_dir_all(&app_data).map_err(|error| {
-        format!(
+        CommandError::io(format!(
             "Could not create app data dir '{}': {error}",
             path_display(&app_data)
-        )
+        ))
     })?;
     Ok(app_data)
 }
 
 pub(crate) fn index_layout_dir(app: &AppHandle) -> CommandResult<PathBuf> {
-    Ok(app_data_dir(app)?.join(INDEX_LAYOUT_DIR_NAME))
+    Ok(crate::profiles::profile_data_dir(app)?.join(INDEX_LAYOUT_DIR_NAME))
 }
 
 pub(crate) fn index_meta_dir(app: &AppHandle) -> CommandResult<PathBuf> {
@@ -56,20 +58,24 @@ fn remove_path_if_exists(path: &PathBuf) -> CommandResult<()> {
     }
     if path.is_dir() {
         fs::remove_dir_all(path).map_err(|error| {
-            format!(
+            CommandError::io(format!(
                 "Could not remove directory '{}': {error}",
                 path_display(path)
-            )
+            ))
         })?;
         return Ok(());
     }
-    fs::remove_file(path)
-        .map_err(|error| format!("Could not remove file '{}': {error}", path_display(path)))
+    fs::remove_file(path).map_err(|error| {
+        CommandError::io(format!(
+            "Could not remove file '{}': {error}",
+            path_display(path)
+        ))
+    })
 }
 
 fn ensure_index_layout(app: &AppHandle) -> CommandResult<()> {
     let app_data = app_data_dir(app)?;
-    let layout_dir = app_data.join(INDEX_LAYOUT_DIR_NAME);
+    let layout_dir = index_layout_dir(app)?;
     let layout_file = layout_dir.join(INDEX_LAYOUT_FILE_NAME);
     let current_version = fs::read_to_string(&layout_file).ok().and_then(|raw| {
         serde_json::from_str::<serde_json::Value>(&raw)
@@ -79,22 +85,22 @@ fn ensure_index_layout(app: &AppHandle) -> CommandResult<()> {
 
     if current_version == Some(INDEX_LAYOUT_VERSION) {
         fs::create_dir_all(layout_dir.join(INDEX_META_DIR_NAME)).map_err(|error| {
-            format!(
+            CommandError::io(format!(
                 "Could not create index meta dir '{}': {error}",
                 path_display(&layout_dir.join(INDEX_META_DIR_NAME))
-            )
+            ))
         })?;
         fs::create_dir_all(layout_dir.join(INDEX_LEXICAL_DIR_NAME)).map_err(|error| {
-            format!(
+            CommandError::io(format!(
                 "Could not create lexical index dir '{}': {error}",
                 path_display(&layout_dir.join(INDEX_LEXICAL_DIR_NAME))
-            )
+            ))
         })?;
         fs::create_dir_all(layout_dir.join(INDEX_VECTOR_DIR_NAME)).map_err(|error| {
-            format!(
+            CommandError::io(format!(
                 "Could not create vector index dir '{}': {error}",
                 path_display(&layout_dir.join(INDEX_VECTOR_DIR_NAME))
-            )
+            ))
         })?;
         return Ok(());
     }
@@ -106,35 +112,38 @@ fn ensure_index_layout(app: &AppHandle) -> CommandResult<()> {
     remove_path_if_exists(&layout_dir)?;
 
     fs::create_dir_all(layout_dir.join(INDEX_META_DIR_NAME)).map_err(|error| {
-        format!(
+        CommandError::io(format!(
             "Could not create index meta dir '{}': {error}",
             path_display(&layout_dir.join(INDEX_META_DIR_NAME))
-        )
+        ))
     })?;
     fs::create_dir_all(layout_dir.join(INDEX_LEXICAL_DIR_NAME)).map_err(|error| {
-        format!(
+        CommandError::io(format!(
             "Could not create lexical index dir '{}': {error}",
             path_display(&layout_dir.join(INDEX_LEXICAL_DIR_NAME))
-        )
+        ))
     })?;
     fs::create_dir_all(layout_dir.join(INDEX_VECTOR_DIR_NAME)).map_err(|error| {
-        format!(
+        CommandError::io(format!(
             "Could not create vector index dir '{}': {error}",
             path_display(&layout_dir.join(INDEX_VECTOR_DIR_NAME))
-        )
+        ))
     })?;
 
     let manifest = serde_json::json!({
         "version": INDEX_LAYOUT_VERSION,
         "updatedAtMs": now_ms(),
     });
-    let manifest_raw = serde_json::to_string_pretty(&manifest)
-        .map_err(|error| format!("Could not serialize index layout manifest: {error}"))?;
+    let manifest_raw = serde_json::to_string_pretty(&manifest).map_err(|error| {
+        CommandError::parse(format!(
+            "Could not serialize index layout manifest: {error}"
+        ))
+    })?;
     fs::write(&layout_file, manifest_raw).map_err(|error| {
-        format!(
+        CommandError::io(format!(
             "Could not write index layout manifest '{}': {error}",
             path_display(&layout_file)
-        )
+        ))
     })?;
 
     Ok(())
@@ -151,15 +160,22 @@ pub(crate) fn table_has_column(
 ) -> CommandResult<bool> {
     let mut statement = connection
         .prepare(&format!("PRAGMA table_info({table})"))
-        .map_err(|error| format!("Could not inspect table schema for '{table}': {error}"))?;
+        .map_err(|error| {
+            CommandError::database(format!(
+                "Could not inspect table schema for '{table}': {error}"
+            ))
+        })?;
 
     let rows = statement
         .query_map([], |row| row.get::<_, String>(1))
-        .map_err(|error| format!("Could not iterate schema for '{table}': {error}"))?;
+        .map_err(|error| {
+            CommandError::database(format!("Could not iterate schema for '{table}': {error}"))
+        })?;
 
     for row in rows {
-        if row.map_err(|error| format!("Could not parse schema row for '{table}': {error}"))?
-            == column
+        if row.map_err(|error| {
+            CommandError::database(format!("Could not parse schema row for '{table}': {error}"))
+        })? == column
         {
             return Ok(true);
         }
@@ -175,13 +191,19 @@ pub(crate) fn ensure_capture_schema(connection: &Connection) -> CommandResult<()
                 "ALTER TABLE captures ADD COLUMN target_relative_path TEXT NOT NULL DEFAULT 'BlockFile-Captures.docx'",
                 [],
             )
-            .map_err(|error| format!("Could not add captures.target_relative_path: {error}"))?;
+            .map_err(|error| {
+                CommandError::database(format!(
+                    "Could not add captures.target_relative_path: {error}"
+                ))
+            })?;
     }
 
     if !table_has_column(connection, "captures", "heading_level")? {
         connection
             .execute("ALTER TABLE captures ADD COLUMN heading_level INTEGER", [])
-            .map_err(|error| format!("Could not add captures.heading_level: {error}"))?;
+            .map_err(|error| {
+                CommandError::database(format!("Could not add captures.heading_level: {error}"))
+            })?;
     }
 
     connection
@@ -189,13 +211,170 @@ pub(crate) fn ensure_capture_schema(connection: &Connection) -> CommandResult<()
             "UPDATE captures SET target_relative_path = 'BlockFile-Captures.docx' WHERE target_relative_path IS NULL OR target_relative_path = ''",
             [],
         )
-        .map_err(|error| format!("Could not backfill capture target paths: {error}"))?;
+        .map_err(|error| {
+            CommandError::database(format!("Could not backfill capture target paths: {error}"))
+        })?;
 
     connection
         .execute_batch(
             "CREATE INDEX IF NOT EXISTS idx_captures_root_target ON captures(root_id, target_relative_path, id);",
         )
-        .map_err(|error| format!("Could not create captures target index: {error}"))?;
+        .map_err(|error| {
+            CommandError::database(format!("Could not create captures target index: {error}"))
+        })?;
+
+    Ok(())
+}
+
+pub(crate) fn ensure_session_schema(connection: &Connection) -> CommandResult<()> {
+    if !table_has_column(connection, "roots", "last_capture_target")? {
+        connection
+            .execute("ALTER TABLE roots ADD COLUMN last_capture_target TEXT", [])
+            .map_err(|error| {
+                CommandError::database(format!("Could not add roots.last_capture_target: {error}"))
+            })?;
+    }
+
+    Ok(())
+}
+
+pub(crate) fn ensure_root_identity_schema(connection: &Connection) -> CommandResult<()> {
+    if !table_has_column(connection, "roots", "root_uid")? {
+        connection
+            .execute("ALTER TABLE roots ADD COLUMN root_uid TEXT", [])
+            .map_err(|error| {
+                CommandError::database(format!("Could not add roots.root_uid: {error}"))
+            })?;
+    }
+
+    Ok(())
+}
+
+pub(crate) fn ensure_author_schema(connection: &Connection) -> CommandResult<()> {
+    if !table_has_column(connection, "files", "authors_hash")? {
+        connection
+            .execute(
+                "ALTER TABLE files ADD COLUMN authors_hash TEXT NOT NULL DEFAULT ''",
+                [],
+            )
+            .map_err(|error| {
+                CommandError::database(format!("Could not add files.authors_hash: {error}"))
+            })?;
+        // Files indexed before the author phase existed already have authors.
+        connection
+            .execute("UPDATE files SET authors_hash = file_hash", [])
+            .map_err(|error| {
+                CommandError::database(format!("Could not backfill files.authors_hash: {error}"))
+            })?;
+    }
+
+    Ok(())
+}
+
+/// Author rows from before names were parsed keep `surnames` NULL until the
+/// author phase fills them in.
+pub(crate) fn ensure_author_name_schema(connection: &Connection) -> CommandResult<()> {
+    for (column, definition) in [("surnames", "TEXT"), ("year", "INTEGER")] {
+        if !table_has_column(connection, "authors", column)? {
+            connection
+                .execute(
+                    &format!("ALTER TABLE authors ADD COLUMN {column} {definition}"),
+                    [],
+                )
+                .map_err(|error| {
+                    CommandError::database(format!("Could not add authors.{column}: {error}"))
+                })?;
+        }
+    }
+
+    Ok(())
+}
+
+pub(crate) fn ensure_cite_schema(connection: &Connection) -> CommandResult<()> {
+    // Left empty so the cite phase reparses files indexed before cites were.
+    if !table_has_column(connection, "files", "cites_hash")? {
+        connection
+            .execute(
+                "ALTER TABLE files ADD COLUMN cites_hash TEXT NOT NULL DEFAULT ''",
+                [],
+            )
+            .map_err(|error| {
+                CommandError::database(format!("Could not add files.cites_hash: {error}"))
+            })?;
+    }
+
+    Ok(())
+}
+
+/// Rows from before Drive secrets moved to the keychain have no
+/// `secret_account`; they are migrated the next time Drive settings load.
+pub(crate) fn ensure_drive_secret_schema(connection: &Connection) -> CommandResult<()> {
+    if !table_has_column(connection, "drive_auth", "secret_account")? {
+        connection
+            .execute("ALTER TABLE drive_auth ADD COLUMN secret_account TEXT", [])
+            .map_err(|error| {
+                CommandError::database(format!("Could not add drive_auth.secret_account: {error}"))
+            })?;
+    }
+
+    Ok(())
+}
+
+pub(crate) fn ensure_heading_preview_schema(connection: &Connection) -> CommandResult<()> {
+    if !table_has_column(connection, "headings", "body_preview")? {
+        connection
+            .execute("ALTER TABLE headings ADD COLUMN body_preview TEXT", [])
+            .map_err(|error| {
+                CommandError::database(format!("Could not add headings.body_preview: {error}"))
+            })?;
+    }
+
+    Ok(())
+}
+
+/// Heading rows from before word counts were kept leave them NULL until the
+/// file is next indexed. Cached capture outlines are dropped instead, since
+/// they are reparsed on the next read anyway.
+pub(crate) fn ensure_word_count_schema(connection: &Connection) -> CommandResult<()> {
+    for column in ["word_count", "underlined_word_count"] {
+        if !table_has_column(connection, "headings", column)? {
+            connection
+                .execute(
+                    &format!("ALTER TABLE headings ADD COLUMN {column} INTEGER"),
+                    [],
+                )
+                .map_err(|error| {
+                    CommandError::database(format!("Could not add headings.{column}: {error}"))
+                })?;
+        }
+        if !table_has_column(connection, "capture_outline_cache", column)? {
+            connection
+                .execute_batch(&format!(
+                    "DELETE FROM capture_outline_cache;
+                     ALTER TABLE capture_outline_cache ADD COLUMN {column} INTEGER NOT NULL DEFAULT 0;"
+                ))
+                .map_err(|error| {
+                    CommandError::database(format!(
+                        "Could not add capture_outline_cache.{column}: {error}"
+                    ))
+                })?;
+        }
+    }
+
+    Ok(())
+}
+
+pub(crate) fn ensure_shard_catalog_schema(connection: &Connection) -> CommandResult<()> {
+    if !table_has_column(connection, "roots", "index_shard")? {
+        connection
+            .execute(
+                "ALTER TABLE roots ADD COLUMN index_shard INTEGER NOT NULL DEFAULT 0",
+                [],
+            )
+            .map_err(|error| {
+                CommandError::database(format!("Could not add roots.index_shard: {error}"))
+            })?;
+    }
 
     Ok(())
 }
@@ -204,17 +383,17 @@ pub(crate) fn open_database(app: &AppHandle) -> CommandResult<Connection> {
     ensure_index_layout(app)?;
     let db_path = database_path(app)?;
     let connection = Connection::open(&db_path).map_err(|error| {
-        format!(
+        CommandError::database(format!(
             "Could not open database '{}': {error}",
             path_display(&db_path)
-        )
+        ))
     })?;
 
     connection
         .query_row("PRAGMA journal_mode = WAL", [], |row| {
             row.get::<_, String>(0)
         })
-        .map_err(|error| format!("Could not set journal mode: {error}"))?;
+        .map_err(|error| CommandError::database(format!("Could not set journal mode: {error}")))?;
 
     connection
         .execute_batch(
@@ -252,6 +431,9 @@ pub(crate) fn open_database(app: &AppHandle) -> CommandResult<Connection> {
               normalized TEXT NOT NULL,
               file_name TEXT NOT NULL,
               relative_path TEXT NOT NULL,
+              body_preview TEXT,
+              word_count INTEGER,
+              underlined_word_count INTEGER,
               FOREIGN KEY(file_id) REFERENCES files(id) ON DELETE CASCADE
             );
 
@@ -263,6 +445,8 @@ pub(crate) fn open_database(app: &AppHandle) -> CommandResult<Connection> {
               normalized TEXT NOT NULL,
               file_name TEXT NOT NULL,
               relative_path TEXT NOT NULL,
+              surnames TEXT,
+              year INTEGER,
               FOREIGN KEY(file_id) REFERENCES files(id) ON DELETE CASCADE
             );
 
@@ -284,6 +468,70 @@ pub(crate) fn open_database(app: &AppHandle) -> CommandResult<Connection> {
               FOREIGN KEY(file_id) REFERENCES files(id) ON DELETE CASCADE
             );
 
+            CREATE TABLE IF NOT EXISTS file_text (
+              file_id INTEGER PRIMARY KEY,
+              paragraph_count INTEGER NOT NULL,
+              text_zstd BLOB NOT NULL,
+              FOREIGN KEY(file_id) REFERENCES files(id) ON DELETE CASCADE
+            );
+
+            CREATE TABLE IF NOT EXISTS file_stats (
+              file_id INTEGER PRIMARY KEY,
+              word_count INTEGER NOT NULL,
+              card_count INTEGER NOT NULL,
+              card_word_count INTEGER NOT NULL,
+              cite_count INTEGER NOT NULL,
+              FOREIGN KEY(file_id) REFERENCES files(id) ON DELETE CASCADE
+            );
+
+            CREATE TABLE IF NOT EXISTS body_paragraphs (
+              id INTEGER PRIMARY KEY,
+              file_id INTEGER NOT NULL,
+              paragraph_order INTEGER NOT NULL,
+              heading_order INTEGER,
+              text TEXT NOT NULL,
+              FOREIGN KEY(file_id) REFERENCES files(id) ON DELETE CASCADE
+            );
+
+            CREATE VIRTUAL TABLE IF NOT EXISTS body_fts USING fts5(
+              text,
+              content = 'body_paragraphs',
+              content_rowid = 'id',
+              tokenize = 'unicode61 remove_diacritics 2'
+            );
+
+            CREATE TRIGGER IF NOT EXISTS body_paragraphs_insert AFTER INSERT ON body_paragraphs BEGIN
+              INSERT INTO body_fts(rowid, text) VALUES(new.id, new.text);
+            END;
+
+            CREATE TRIGGER IF NOT EXISTS body_paragraphs_delete AFTER DELETE ON body_paragraphs BEGIN
+              INSERT INTO body_fts(body_fts, rowid, text) VALUES('delete', old.id, old.text);
+            END;
+
+            CREATE TABLE IF NOT EXISTS cites (
+              id INTEGER PRIMARY KEY,
+              file_id INTEGER NOT NULL,
+              paragraph_order INTEGER NOT NULL,
+              heading_order INTEGER,
+              text TEXT NOT NULL,
+              FOREIGN KEY(file_id) REFERENCES files(id) ON DELETE CASCADE
+            );
+
+            CREATE VIRTUAL TABLE IF NOT EXISTS cites_fts USING fts5(
+              text,
+              content = 'cites',
+              content_rowid = 'id',
+              tokenize = 'unicode61 remove_diacritics 2'
+            );
+
+            CREATE TRIGGER IF NOT EXISTS cites_insert AFTER INSERT ON cites BEGIN
+              INSERT INTO cites_fts(rowid, text) VALUES(new.id, new.text);
+            END;
+
+            CREATE TRIGGER IF NOT EXISTS cites_delete AFTER DELETE ON cites BEGIN
+              INSERT INTO cites_fts(cites_fts, rowid, text) VALUES('delete', old.id, old.text);
+            END;
+
             CREATE TABLE IF NOT EXISTS captures (
               id INTEGER PRIMARY KEY,
               root_id INTEGER NOT NULL,
@@ -296,6 +544,251 @@ pub(crate) fn open_database(app: &AppHandle) -> CommandResult<Connection> {
               FOREIGN KEY(root_id) REFERENCES roots(id) ON DELETE CASCADE
             );
 
+            CREATE TABLE IF NOT EXISTS capture_trash (
+              id INTEGER PRIMARY KEY,
+              root_id INTEGER NOT NULL,
+              target_relative_path TEXT NOT NULL,
+              heading_level INTEGER NOT NULL,
+              heading_text TEXT NOT NULL,
+              paragraph_offset INTEGER NOT NULL,
+              fragment_xml TEXT NOT NULL,
+              styles_xml TEXT NOT NULL,
+              relationships_xml TEXT NOT NULL,
+              deleted_at_ms INTEGER NOT NULL,
+              FOREIGN KEY(root_id) REFERENCES roots(id) ON DELETE CASCADE
+            );
+
+            CREATE TABLE IF NOT EXISTS sync_conflicts (
+              id INTEGER PRIMARY KEY,
+              root_id INTEGER NOT NULL,
+              file_id INTEGER NOT NULL UNIQUE,
+              original_relative_path TEXT NOT NULL,
+              detected_at_ms INTEGER NOT NULL,
+              FOREIGN KEY(root_id) REFERENCES roots(id) ON DELETE CASCADE,
+              FOREIGN KEY(file_id) REFERENCES files(id) ON DELETE CASCADE
+            );
+
+            CREATE TABLE IF NOT EXISTS heading_history (
+              id INTEGER PRIMARY KEY,
+              file_id INTEGER NOT NULL,
+              indexed_at_ms INTEGER NOT NULL,
+              change_kind TEXT NOT NULL,
+              level INTEGER NOT NULL,
+              heading_order INTEGER NOT NULL,
+              previous_text TEXT,
+              text TEXT,
+              FOREIGN KEY(file_id) REFERENCES files(id) ON DELETE CASCADE
+            );
+
+            CREATE TABLE IF NOT EXISTS cut_queue (
+              id INTEGER PRIMARY KEY,
+              root_id INTEGER NOT NULL,
+              relative_path TEXT NOT NULL,
+              heading_order INTEGER,
+              heading_text TEXT,
+              status TEXT NOT NULL DEFAULT 'todo',
+              assignee TEXT NOT NULL DEFAULT '',
+              note TEXT NOT NULL DEFAULT '',
+              created_at_ms INTEGER NOT NULL,
+              updated_at_ms INTEGER NOT NULL,
+              FOREIGN KEY(root_id) REFERENCES roots(id) ON DELETE CASCADE
+            );
+
+            CREATE TABLE IF NOT EXISTS file_links (
+              id INTEGER PRIMARY KEY,
+              root_id INTEGER NOT NULL,
+              file_id INTEGER NOT NULL,
+              paragraph_order INTEGER NOT NULL,
+              url TEXT NOT NULL,
+              link_text TEXT NOT NULL DEFAULT '',
+              FOREIGN KEY(root_id) REFERENCES roots(id) ON DELETE CASCADE,
+              FOREIGN KEY(file_id) REFERENCES files(id) ON DELETE CASCADE
+            );
+
+            CREATE TABLE IF NOT EXISTS link_status (
+              url TEXT PRIMARY KEY,
+              status TEXT NOT NULL,
+              status_code INTEGER,
+              error TEXT,
+              checked_at_ms INTEGER NOT NULL
+            );
+
+            CREATE TABLE IF NOT EXISTS drive_auth (
+              id INTEGER PRIMARY KEY CHECK(id = 1),
+              client_id TEXT NOT NULL,
+              client_secret TEXT NOT NULL,
+              secret_account TEXT,
+              refresh_token TEXT,
+              access_token TEXT,
+              access_expires_at_ms INTEGER,
+              updated_at_ms INTEGER NOT NULL
+            );
+
+            CREATE TABLE IF NOT EXISTS local_api_settings (
+              id INTEGER PRIMARY KEY CHECK(id = 1),
+              enabled INTEGER NOT NULL DEFAULT 0,
+              port INTEGER NOT NULL,
+              token TEXT NOT NULL,
+              updated_at_ms INTEGER NOT NULL
+            );
+
+            CREATE TABLE IF NOT EXISTS capture_outline_cache (
+              capture_path TEXT NOT NULL,
+              modified_ms INTEGER NOT NULL,
+              size_bytes INTEGER NOT NULL,
+              heading_order INTEGER NOT NULL,
+              level INTEGER NOT NULL,
+              heading_text TEXT NOT NULL,
+              copy_text TEXT NOT NULL,
+              word_count INTEGER NOT NULL DEFAULT 0,
+              underlined_word_count INTEGER NOT NULL DEFAULT 0,
+              PRIMARY KEY(capture_path, heading_order)
+            );
+
+            CREATE TABLE IF NOT EXISTS file_activity (
+              file_id INTEGER PRIMARY KEY,
+              open_count INTEGER NOT NULL DEFAULT 0,
+              last_opened_ms INTEGER,
+              pinned_at_ms INTEGER
+            );
+
+            CREATE TABLE IF NOT EXISTS index_rules (
+              id INTEGER PRIMARY KEY,
+              root_id INTEGER NOT NULL,
+              kind TEXT NOT NULL,
+              pattern TEXT NOT NULL,
+              created_at_ms INTEGER NOT NULL,
+              UNIQUE(root_id, kind, pattern),
+              FOREIGN KEY(root_id) REFERENCES roots(id) ON DELETE CASCADE
+            );
+
+            CREATE TABLE IF NOT EXISTS root_intake (
+              root_id INTEGER PRIMARY KEY,
+              intake_path TEXT NOT NULL,
+              target_folder TEXT NOT NULL,
+              last_moved_at_ms INTEGER,
+              updated_at_ms INTEGER NOT NULL,
+              FOREIGN KEY(root_id) REFERENCES roots(id) ON DELETE CASCADE
+            );
+
+            CREATE TABLE IF NOT EXISTS path_mappings (
+              foreign_root_path TEXT PRIMARY KEY,
+              root_id INTEGER NOT NULL,
+              created_at_ms INTEGER NOT NULL,
+              FOREIGN KEY(root_id) REFERENCES roots(id) ON DELETE CASCADE
+            );
+
+            CREATE TABLE IF NOT EXISTS pandoc_settings (
+              id INTEGER PRIMARY KEY CHECK(id = 1),
+              binary_path TEXT,
+              updated_at_ms INTEGER NOT NULL
+            );
+
+            CREATE TABLE IF NOT EXISTS index_changes (
+              id INTEGER PRIMARY KEY,
+              root_id INTEGER NOT NULL,
+              indexed_at_ms INTEGER NOT NULL,
+              change_kind TEXT NOT NULL,
+              relative_path TEXT NOT NULL,
+              heading_count INTEGER NOT NULL DEFAULT 0,
+              FOREIGN KEY(root_id) REFERENCES roots(id) ON DELETE CASCADE
+            );
+
+            CREATE TABLE IF NOT EXISTS tags (
+              id INTEGER PRIMARY KEY,
+              root_id INTEGER NOT NULL,
+              relative_path TEXT NOT NULL,
+              heading_normalized TEXT NOT NULL DEFAULT '',
+              heading_text TEXT,
+              tag TEXT NOT NULL COLLATE NOCASE,
+              created_at_ms INTEGER NOT NULL,
+              UNIQUE(root_id, relative_path, heading_normalized, tag),
+              FOREIGN KEY(root_id) REFERENCES roots(id) ON DELETE CASCADE
+            );
+
+            CREATE TABLE IF NOT EXISTS index_settings (
+              id INTEGER PRIMARY KEY CHECK(id = 1),
+              extract_authors INTEGER NOT NULL DEFAULT 1,
+              updated_at_ms INTEGER NOT NULL
+            );
+
+            CREATE TABLE IF NOT EXISTS search_settings (
+              id INTEGER PRIMARY KEY CHECK(id = 1),
+              heading_weight REAL NOT NULL,
+              author_weight REAL NOT NULL,
+              file_weight REAL NOT NULL,
+              prefix_min_chars INTEGER NOT NULL,
+              fuzzy_min_chars INTEGER NOT NULL,
+              recall_score_base REAL NOT NULL,
+              cite_score_base REAL NOT NULL,
+              body_score_base REAL NOT NULL,
+              prefix_score_base REAL NOT NULL,
+              fuzzy_score_base REAL NOT NULL,
+              updated_at_ms INTEGER NOT NULL
+            );
+
+            CREATE TABLE IF NOT EXISTS root_settings (
+              root_id INTEGER PRIMARY KEY,
+              default_capture_target TEXT NOT NULL,
+              insert_title INTEGER NOT NULL DEFAULT 1,
+              insert_separator INTEGER NOT NULL DEFAULT 1,
+              separator_style TEXT,
+              heading_level_shift INTEGER NOT NULL DEFAULT 0,
+              nest_child_captures INTEGER NOT NULL DEFAULT 0,
+              updated_at_ms INTEGER NOT NULL,
+              FOREIGN KEY(root_id) REFERENCES roots(id) ON DELETE CASCADE
+            );
+
+            CREATE TABLE IF NOT EXISTS capture_target_settings (
+              root_id INTEGER NOT NULL,
+              target_relative_path TEXT NOT NULL,
+              table_of_contents INTEGER NOT NULL DEFAULT 0,
+              updated_at_ms INTEGER NOT NULL,
+              PRIMARY KEY(root_id, target_relative_path),
+              FOREIGN KEY(root_id) REFERENCES roots(id) ON DELETE CASCADE
+            );
+
+            CREATE TABLE IF NOT EXISTS root_watches (
+              root_id INTEGER PRIMARY KEY,
+              enabled_at_ms INTEGER NOT NULL,
+              FOREIGN KEY(root_id) REFERENCES roots(id) ON DELETE CASCADE
+            );
+
+            CREATE TABLE IF NOT EXISTS allowed_locations (
+              id INTEGER PRIMARY KEY,
+              path TEXT NOT NULL UNIQUE,
+              added_at_ms INTEGER NOT NULL
+            );
+
+            CREATE TABLE IF NOT EXISTS locale_settings (
+              id INTEGER PRIMARY KEY CHECK(id = 1),
+              locale TEXT NOT NULL,
+              updated_at_ms INTEGER NOT NULL
+            );
+
+            CREATE TABLE IF NOT EXISTS usage_settings (
+              id INTEGER PRIMARY KEY CHECK(id = 1),
+              enabled INTEGER NOT NULL DEFAULT 0,
+              updated_at_ms INTEGER NOT NULL
+            );
+
+            CREATE TABLE IF NOT EXISTS usage_counters (
+              metric TEXT PRIMARY KEY,
+              count INTEGER NOT NULL DEFAULT 0,
+              total_ms INTEGER NOT NULL DEFAULT 0,
+              max_ms INTEGER NOT NULL DEFAULT 0,
+              first_at_ms INTEGER NOT NULL,
+              last_at_ms INTEGER NOT NULL
+            );
+
+            CREATE TABLE IF NOT EXISTS session_state (
+              id INTEGER PRIMARY KEY CHECK(id = 1),
+              last_root_path TEXT,
+              last_file_id INTEGER,
+              last_heading_order INTEGER,
+              updated_at_ms INTEGER NOT NULL
+            );
+
             CREATE INDEX IF NOT EXISTS idx_files_root_relative ON files(root_id, relative_path);
             CREATE INDEX IF NOT EXISTS idx_files_root_modified ON files(root_id, modified_ms DESC, id DESC);
             CREATE INDEX IF NOT EXISTS idx_headings_file ON headings(file_id);
@@ -307,11 +800,23 @@ pub(crate) fn open_database(app: &AppHandle) -> CommandResult<Connection> {
             CREATE INDEX IF NOT EXISTS idx_chunks_file_order ON chunks(file_id, chunk_order);
             CREATE INDEX IF NOT EXISTS idx_chunks_root_file ON chunks(root_id, file_id);
             CREATE INDEX IF NOT EXISTS idx_chunks_root_file_order ON chunks(root_id, file_id, chunk_order);
+            CREATE INDEX IF NOT EXISTS idx_body_paragraphs_file ON body_paragraphs(file_id, paragraph_order);
+            CREATE INDEX IF NOT EXISTS idx_cites_file ON cites(file_id, paragraph_order);
             CREATE INDEX IF NOT EXISTS idx_files_relative_length ON files(length(relative_path));
             CREATE INDEX IF NOT EXISTS idx_captures_root ON captures(root_id, id);
+            CREATE INDEX IF NOT EXISTS idx_capture_trash_root ON capture_trash(root_id, target_relative_path, deleted_at_ms DESC);
+            CREATE INDEX IF NOT EXISTS idx_heading_history_file ON heading_history(file_id, indexed_at_ms DESC);
+            CREATE INDEX IF NOT EXISTS idx_sync_conflicts_root ON sync_conflicts(root_id, original_relative_path);
+            CREATE INDEX IF NOT EXISTS idx_file_links_root_url ON file_links(root_id, url);
+            CREATE INDEX IF NOT EXISTS idx_file_links_file ON file_links(file_id, paragraph_order);
+            CREATE INDEX IF NOT EXISTS idx_index_changes_root ON index_changes(root_id, indexed_at_ms DESC);
+            CREATE INDEX IF NOT EXISTS idx_tags_root_tag ON tags(root_id, tag);
+            CREATE INDEX IF NOT EXISTS idx_cut_queue_root_status ON cut_queue(root_id, status, updated_at_ms DESC);
             ",
         )
-        .map_err(|error| format!("Could not initialize index database: {error}"))?;
+        .map_err(|error| {
+            CommandError::database(format!("Could not initialize index database: {error}"))
+        })?;
 
     let _ = connection.query_row("PRAGMA cache_size = -65536", [], |row| row.get::<_, i64>(0));
     let _ = connection.query_row("PRAGMA mmap_size = 268435456", [], |row| {
@@ -322,6 +827,16 @@ pub(crate) fn open_database(app: &AppHandle) -> CommandResult<Connection> {
     });
 
     ensure_capture_schema(&connection)?;
+    ensure_session_schema(&connection)?;
+    ensure_root_identity_schema(&connection)?;
+    ensure_author_schema(&connection)?;
+    ensure_author_name_schema(&connection)?;
+    ensure_shard_catalog_schema(&connection)?;
+    ensure_heading_preview_schema(&connection)?;
+    ensure_word_count_schema(&connection)?;
+    ensure_cite_schema(&connection)?;
+    ensure_drive_secret_schema(&connection)?;
+    attach_root_shards(app, &connection)?;
 
     Ok(connection)
 }
@@ -334,7 +849,9 @@ pub(crate) fn root_id(connection: &Connection, root_path: &str) -> CommandResult
             |row| row.get(0),
         )
         .optional()
-        .map_err(|error| format!("Could not query root path '{root_path}': {error}"))
+        .map_err(|error| {
+            CommandError::database(format!("Could not query root path '{root_path}': {error}"))
+        })
 }
 
 pub(crate) fn add_or_get_root_id(connection: &Connection, root_path: &str) -> CommandResult<i64> {
@@ -344,10 +861,12 @@ pub(crate) fn add_or_get_root_id(connection: &Connection, root_path: &str) -> Co
              ON CONFLICT(path) DO NOTHING",
             params![root_path, now_ms()],
         )
-        .map_err(|error| format!("Could not store root path '{root_path}': {error}"))?;
+        .map_err(|error| {
+            CommandError::database(format!("Could not store root path '{root_path}': {error}"))
+        })?;
 
     root_id(connection, root_path)?
-        .ok_or_else(|| format!("Could not find root row for '{root_path}'"))
+        .ok_or_else(|| CommandError::database(format!("Could not find root row for '{root_path}'")))
 }
 
 pub(crate) fn load_existing_files(
@@ -358,7 +877,9 @@ pub(crate) fn load_existing_files(
         .prepare(
             "SELECT id, relative_path, modified_ms, size, file_hash FROM files WHERE root_id = ?1",
         )
-        .map_err(|error| format!("Could not prepare file metadata query: {error}"))?;
+        .map_err(|error| {
+            CommandError::database(format!("Could not prepare file metadata query: {error}"))
+        })?;
 
     let rows = statement
         .query_map(params![root_id], |row| {
@@ -370,12 +891,17 @@ pub(crate) fn load_existing_files(
                 row.get::<_, String>(4)?,
             ))
         })
-        .map_err(|error| format!("Could not iterate existing files: {error}"))?;
+        .map_err(|error| {
+            CommandError::database(format!("Could not iterate existing files: {error}"))
+        })?;
 
     let mut metadata = HashMap::new();
     for row in rows {
-        let (id, relative_path, modified_ms, size, file_hash) =
-            row.map_err(|error| format!("Could not parse existing file metadata row: {error}"))?;
+        let (id, relative_path, modified_ms, size, file_hash) = row.map_err(|error| {
+            CommandError::database(format!(
+                "Could not parse existing file metadata row: {error}"
+            ))
+        })?;
         metadata.insert(
             relative_path,
             ExistingFileMeta {
@@ -0,0 +1,89 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use rayon::prelude::*;
+
+use crate::document_parser::is_indexable_path;
+use crate::index_rules::ExclusionRules;
+use crate::operations::OperationHandle;
+use crate::CommandResult;
+
+fn is_hidden_name(name: &std::ffi::OsStr) -> bool {
+    name.to_string_lossy().starts_with('.')
+}
+
+/// One directory's visible indexable files (.docx, .doc, .odt, .rtf and
+/// .pdf) and subdirectories, less those `exclusions` match, plus how many
+/// were excluded. Unreadable
+/// directories and entries are skipped, as the sequential walk did.
+fn read_directory(
+    root: &Path,
+    directory: &Path,
+    exclusions: &ExclusionRules,
+) -> (Vec<PathBuf>, Vec<PathBuf>, usize) {
+    let mut files = Vec::new();
+    let mut subdirectories = Vec::new();
+    let mut excluded = 0;
+    let Ok(entries) = fs::read_dir(directory) else {
+        return (files, subdirectories, excluded);
+    };
+    for entry in entries.flatten() {
+        if is_hidden_name(&entry.file_name()) {
+            continue;
+        }
+        // `DirEntry::file_type` does not follow symlinks, so linked folders
+        // are not descended into.
+        let Ok(file_type) = entry.file_type() else {
+            continue;
+        };
+        let path = entry.path();
+        let is_dir = file_type.is_dir();
+        let wanted = is_dir || (file_type.is_file() && is_indexable_path(&path));
+        if !wanted {
+            continue;
+        }
+        let relative_path = path
+            .strip_prefix(root)
+            .map(|relative| relative.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        if exclusions.excludes(&relative_path) {
+            excluded += 1;
+        } else if is_dir {
+            subdirectories.push(path);
+        } else {
+            files.push(path);
+        }
+    }
+    (files, subdirectories, excluded)
+}
+
+/// Finds every visible indexable file under `root` that `exclusions`
+/// leave in, reading each level of the tree in parallel. Directory listings
+/// on network shares are latency-bound, so many in flight at once is what
+/// makes discovery fast. The result is sorted so runs are deterministic
+/// regardless of completion order. Also returns how many folders and files
+/// were excluded; an excluded folder counts once, since it is never walked.
+pub(crate) fn discover_docx_files(
+    root: &Path,
+    exclusions: &ExclusionRules,
+    operation: &OperationHandle,
+) -> CommandResult<(Vec<PathBuf>, usize)> {
+    let mut files = Vec::new();
+    let mut excluded = 0;
+    let mut frontier = vec![root.to_path_buf()];
+    while !frontier.is_empty() {
+        operation.ensure_not_cancelled()?;
+        let listings = frontier
+            .par_iter()
+            .map(|directory| read_directory(root, directory, exclusions))
+            .collect::<Vec<(Vec<PathBuf>, Vec<PathBuf>, usize)>>();
+        frontier = Vec::new();
+        for (directory_files, subdirectories, directory_excluded) in listings {
+            files.extend(directory_files);
+            frontier.extend(subdirectories);
+            excluded += directory_excluded;
+        }
+    }
+    files.sort();
+    Ok((files, excluded))
+}
@@ -0,0 +1,439 @@
+use std::io::Read;
+use std::path::Path;
+
+use crate::docx_parse::{is_f8_cite_style, parse_trailing_level};
+use crate::errors::CommandError;
+use crate::types::ParsedParagraph;
+use crate::util::{is_probable_author_line, path_display};
+use crate::CommandResult;
+
+/// `wIdent` of a Word binary document's FIB.
+const WORD_IDENT: u16 = 0xA5EC;
+/// Oldest `nFib` with the Word 97 layout read here; Word 6/95 files differ.
+const MIN_WORD97_NFIB: u16 = 0x00C1;
+const FIB_FLAGS_OFFSET: usize = 0x0A;
+const FIB_ENCRYPTED: u16 = 0x0100;
+const FIB_WHICH_TABLE_STREAM: u16 = 0x0200;
+const FIB_CCP_TEXT_OFFSET: usize = 0x4C;
+const FIB_STSHF_OFFSET: usize = 0xA2;
+const FIB_PLCF_BTE_PAPX_OFFSET: usize = 0x102;
+const FIB_CLX_OFFSET: usize = 0x1A2;
+
+const FKP_PAGE_BYTES: usize = 512;
+const PIECE_DESCRIPTOR_BYTES: usize = 8;
+const PIECE_COMPRESSED: u32 = 0x4000_0000;
+const BX_PAP_BYTES: usize = 13;
+const SPRM_P_OUT_LVL: u16 = 0x2640;
+
+const FIELD_BEGIN: u16 = 0x13;
+const FIELD_SEPARATOR: u16 = 0x14;
+const FIELD_END: u16 = 0x15;
+
+/// Windows-1252 characters for bytes 0x80..=0x9F; compressed pieces store
+/// text in that code page.
+const CP1252_HIGH: [char; 32] = [
+    '€', '\u{81}', '‚', 'ƒ', '„', '…', '†', '‡', 'ˆ', '‰', 'Š', '‹', 'Œ', '\u{8d}', 'Ž', '\u{8f}',
+    '\u{90}', '‘', '’', '“', '”', '•', '–', '—', '˜', '™', 'š', '›', 'œ', '\u{9d}', 'ž', 'Ÿ',
+];
+
+struct Piece {
+    cp_start: u32,
+    cp_end: u32,
+    fc: u32,
+    compressed: bool,
+}
+
+/// Paragraph properties for paragraph marks in `fc_start..fc_end`.
+struct PapxRun {
+    fc_start: u32,
+    fc_end: u32,
+    istd: u16,
+    outline_level: Option<u8>,
+}
+
+struct DocStyle {
+    sti: u16,
+    name: String,
+}
+
+fn u16_at(bytes: &[u8], offset: usize) -> Option<u16> {
+    let slice = bytes.get(offset..offset.checked_add(2)?)?;
+    Some(u16::from_le_bytes([slice[0], slice[1]]))
+}
+
+fn u32_at(bytes: &[u8], offset: usize) -> Option<u32> {
+    let slice = bytes.get(offset..offset.checked_add(4)?)?;
+    Some(u32::from_le_bytes([slice[0], slice[1], slice[2], slice[3]]))
+}
+
+/// The `fc`/`lcb` pair at `offset` in the FIB, as a byte range of the table
+/// stream.
+fn fib_range(word_document: &[u8], offset: usize) -> Option<(usize, usize)> {
+    let fc = usize::try_from(u32_at(word_document, offset)?).ok()?;
+    let lcb = usize::try_from(u32_at(word_document, offset + 4)?).ok()?;
+    Some((fc, lcb))
+}
+
+fn read_stream(compound: &mut cfb::CompoundFile<std::fs::File>, name: &str) -> Option<Vec<u8>> {
+    let mut stream = compound.open_stream(name).ok()?;
+    let mut bytes = Vec::new();
+    stream.read_to_end(&mut bytes).ok()?;
+    Some(bytes)
+}
+
+/// The piece table from the CLX: which stretches of the WordDocument stream
+/// hold each run of character positions.
+fn read_pieces(table: &[u8], clx_start: usize, clx_len: usize) -> Option<Vec<Piece>> {
+    let clx = table.get(clx_start..clx_start.checked_add(clx_len)?)?;
+    let mut offset = 0;
+    // Skip the Prc entries (property modifiers) that precede the Pcdt.
+    while *clx.get(offset)? == 0x01 {
+        let size = usize::from(u16_at(clx, offset + 1)?);
+        offset += 3 + size;
+    }
+    if *clx.get(offset)? != 0x02 {
+        return None;
+    }
+    let plc_len = usize::try_from(u32_at(clx, offset + 1)?).ok()?;
+    let plc = clx.get(offset + 5..offset + 5 + plc_len)?;
+    let count = plc.len().checked_sub(4)? / (4 + PIECE_DESCRIPTOR_BYTES);
+
+    let mut pieces = Vec::with_capacity(count);
+    for index in 0..count {
+        let cp_start = u32_at(plc, index * 4)?;
+        let cp_end = u32_at(plc, (index + 1) * 4)?;
+        let descriptor = (count + 1) * 4 + index * PIECE_DESCRIPTOR_BYTES;
+        let raw_fc = u32_at(plc, descriptor + 2)?;
+        let compressed = raw_fc & PIECE_COMPRESSED != 0;
+        let fc = if compressed {
+            (raw_fc & !PIECE_COMPRESSED) / 2
+        } else {
+            raw_fc
+        };
+        pieces.push(Piece {
+            cp_start,
+            cp_end,
+            fc,
+            compressed,
+        });
+    }
+    Some(pieces)
+}
+
+/// The outline level a grpprl sets directly, if any.
+fn outline_level_from_grpprl(grpprl: &[u8]) -> Option<u8> {
+    let mut offset = 0;
+    while offset + 2 <= grpprl.len() {
+        let sprm = u16_at(grpprl, offset)?;
+        offset += 2;
+        let operand_len = match sprm >> 13 {
+            0 | 1 => 1,
+            2 | 4 | 5 => 2,
+            3 => 4,
+            7 => 3,
+            _ => {
+                let size = usize::from(*grpprl.get(offset)?);
+                // sprmPChgTabs can flag an extended size the scan cannot follow.
+                if size == 255 {
+                    return None;
+                }
+                size + 1
+            }
+        };
+        if sprm == SPRM_P_OUT_LVL {
+            return grpprl.get(offset).copied();
+        }
+        offset += operand_len;
+    }
+    None
+}
+
+/// Paragraph property runs from every PAPX FKP page, sorted by position.
+fn read_papx_runs(word_document: &[u8], table: &[u8], start: usize, len: usize) -> Vec<PapxRun> {
+    let Some(plc) = table.get(start..start.saturating_add(len)) else {
+        return Vec::new();
+    };
+    let count = plc.len().saturating_sub(4) / 8;
+    let mut runs = Vec::new();
+    for index in 0..count {
+        let Some(page_number) = u32_at(plc, (count + 1) * 4 + index * 4) else {
+            continue;
+        };
+        let page_start = usize::try_from(page_number & 0x003F_FFFF).unwrap_or(0) * FKP_PAGE_BYTES;
+        let Some(page) = word_document.get(page_start..page_start + FKP_PAGE_BYTES) else {
+            continue;
+        };
+        let run_count = usize::from(page[FKP_PAGE_BYTES - 1]);
+        for run in 0..run_count {
+            let (Some(fc_start), Some(fc_end)) = (u32_at(page, run * 4), u32_at(page, run * 4 + 4))
+            else {
+                continue;
+            };
+            let bx_offset = (run_count + 1) * 4 + run * BX_PAP_BYTES;
+            let papx_offset = usize::from(*page.get(bx_offset).unwrap_or(&0)) * 2;
+            let (istd, outline_level) = if papx_offset == 0 {
+                (0, None)
+            } else {
+                let cb = usize::from(*page.get(papx_offset).unwrap_or(&0));
+                let (grpprl_start, grpprl_len) = if cb == 0 {
+                    (
+                        papx_offset + 2,
+                        usize::from(*page.get(papx_offset + 1).unwrap_or(&0)) * 2,
+                    )
+                } else {
+                    (papx_offset + 1, cb * 2 - 1)
+                };
+                let grpprl = page
+                    .get(grpprl_start..(grpprl_start + grpprl_len).min(FKP_PAGE_BYTES))
+                    .unwrap_or(&[]);
+                (
+                    u16_at(grpprl, 0).unwrap_or(0),
+                    grpprl.get(2..).and_then(outline_level_from_grpprl),
+                )
+            };
+            runs.push(PapxRun {
+                fc_start,
+                fc_end,
+                istd,
+                outline_level,
+            });
+        }
+    }
+    runs.sort_by_key(|run| run.fc_start);
+    runs
+}
+
+/// Style names and built-in identifiers from the STSH, indexed by istd.
+fn read_styles(table: &[u8], start: usize, len: usize) -> Vec<Option<DocStyle>> {
+    let Some(stsh) = table.get(start..start.saturating_add(len)) else {
+        return Vec::new();
+    };
+    let Some(stshi_len) = u16_at(stsh, 0).map(usize::from) else {
+        return Vec::new();
+    };
+    let (Some(style_count), Some(base_len)) = (u16_at(stsh, 2), u16_at(stsh, 4)) else {
+        return Vec::new();
+    };
+    let base_len = usize::from(base_len);
+
+    let mut styles = Vec::with_capacity(usize::from(style_count));
+    let mut offset = 2 + stshi_len;
+    for _ in 0..style_count {
+        let Some(std_len) = u16_at(stsh, offset).map(usize::from) else {
+            break;
+        };
+        let std_start = offset + 2;
+        offset = std_start + std_len;
+        if std_len == 0 {
+            styles.push(None);
+            continue;
+        }
+        let Some(std) = stsh.get(std_start..offset) else {
+            break;
+        };
+        let sti = u16_at(std, 0).unwrap_or(0) & 0x0FFF;
+        let name_len = usize::from(u16_at(std, base_len).unwrap_or(0));
+        let name_units = (0..name_len)
+            .filter_map(|unit| u16_at(std, base_len + 2 + unit * 2))
+            .collect::<Vec<u16>>();
+        styles.push(Some(DocStyle {
+            sti,
+            name: String::from_utf16_lossy(&name_units),
+        }));
+    }
+    styles
+}
+
+fn decode_compressed(byte: u8) -> char {
+    match byte {
+        0x80..=0x9F => CP1252_HIGH[usize::from(byte - 0x80)],
+        _ => char::from(byte),
+    }
+}
+
+/// Appends one UTF-16 code unit, holding a high surrogate until its pair
+/// arrives.
+fn push_utf16(text: &mut String, pending_high: &mut Option<u16>, unit: u16) {
+    if (0xD800..0xDC00).contains(&unit) {
+        *pending_high = Some(unit);
+        return;
+    }
+    let units = match pending_high.take() {
+        Some(high) => vec![high, unit],
+        None => vec![unit],
+    };
+    text.extend(
+        char::decode_utf16(units).map(|decoded| decoded.unwrap_or(char::REPLACEMENT_CHARACTER)),
+    );
+}
+
+/// Main-document text as paragraphs, each with the file position of its
+/// paragraph mark. Field codes are dropped and field results kept.
+fn read_paragraph_text(
+    word_document: &[u8],
+    pieces: &[Piece],
+    ccp_text: u32,
+) -> Vec<(String, u32)> {
+    let mut paragraphs = Vec::new();
+    let mut current = String::new();
+    let mut pending_high = None;
+    // One entry per open field: true once its separator is past.
+    let mut fields = Vec::<bool>::new();
+
+    for piece in pieces {
+        if piece.cp_start >= ccp_text {
+            break;
+        }
+        let cp_end = piece.cp_end.min(ccp_text);
+        for index in 0..cp_end.saturating_sub(piece.cp_start) {
+            let (fc, unit) = if piece.compressed {
+                let fc = piece.fc.saturating_add(index);
+                let Some(byte) = word_document.get(fc as usize) else {
+                    break;
+                };
+                (fc, u16::from(*byte))
+            } else {
+                let fc = piece.fc.saturating_add(index.saturating_mul(2));
+                let Some(unit) = u16_at(word_document, fc as usize) else {
+                    break;
+                };
+                (fc, unit)
+            };
+
+            match unit {
+                FIELD_BEGIN => {
+                    fields.push(false);
+                    continue;
+                }
+                FIELD_SEPARATOR => {
+                    if let Some(in_result) = fields.last_mut() {
+                        *in_result = true;
+                    }
+                    continue;
+                }
+                FIELD_END => {
+                    fields.pop();
+                    continue;
+                }
+                _ => {}
+            }
+            if fields.iter().any(|in_result| !in_result) {
+                continue;
+            }
+
+            match unit {
+                // Paragraph mark, table cell or row end, page or section break.
+                0x0D | 0x07 | 0x0C => {
+                    paragraphs.push((std::mem::take(&mut current), fc));
+                    pending_high = None;
+                }
+                0x0B => current.push('\n'),
+                0x09 => current.push('\t'),
+                0x1E => current.push('-'),
+                unit if unit < 0x20 => {}
+                unit if piece.compressed => current.push(decode_compressed(unit as u8)),
+                unit => push_utf16(&mut current, &mut pending_high, unit),
+            }
+        }
+    }
+    paragraphs
+}
+
+fn not_a_word97_document(file_path: &Path) -> CommandError {
+    CommandError::parse(format!(
+        "'{}' is not a Word 97-2003 document this app can read.",
+        path_display(file_path)
+    ))
+}
+
+/// Paragraphs of a legacy Word (.doc) file, numbered from 1 like
+/// `parse_docx_paragraphs`. Only text, paragraph styles and direct outline
+/// levels are read; headings come out the same way they do for .docx.
+pub(crate) fn parse_doc_paragraphs(file_path: &Path) -> CommandResult<Vec<ParsedParagraph>> {
+    let mut compound = cfb::open(file_path).map_err(|error| {
+        CommandError::io(format!(
+            "Could not open '{}': {error}",
+            path_display(file_path)
+        ))
+    })?;
+    let word_document = read_stream(&mut compound, "WordDocument")
+        .ok_or_else(|| not_a_word97_document(file_path))?;
+    if u16_at(&word_document, 0) != Some(WORD_IDENT)
+        || u16_at(&word_document, 2).unwrap_or(0) < MIN_WORD97_NFIB
+    {
+        return Err(not_a_word97_document(file_path));
+    }
+    let flags = u16_at(&word_document, FIB_FLAGS_OFFSET).unwrap_or(0);
+    if flags & FIB_ENCRYPTED != 0 {
+        return Err(CommandError::parse(format!(
+            "'{}' is password protected.",
+            path_display(file_path)
+        )));
+    }
+    let table_name = if flags & FIB_WHICH_TABLE_STREAM != 0 {
+        "1Table"
+    } else {
+        "0Table"
+    };
+    let table =
+        read_stream(&mut compound, table_name).ok_or_else(|| not_a_word97_document(file_path))?;
+
+    let ccp_text = u32_at(&word_document, FIB_CCP_TEXT_OFFSET).unwrap_or(0);
+    let pieces = fib_range(&word_document, FIB_CLX_OFFSET)
+        .and_then(|(start, len)| read_pieces(&table, start, len))
+        .ok_or_else(|| not_a_word97_document(file_path))?;
+    let runs = fib_range(&word_document, FIB_PLCF_BTE_PAPX_OFFSET)
+        .map(|(start, len)| read_papx_runs(&word_document, &table, start, len))
+        .unwrap_or_default();
+    let styles = fib_range(&word_document, FIB_STSHF_OFFSET)
+        .map(|(start, len)| read_styles(&table, start, len))
+        .unwrap_or_default();
+
+    let mut paragraphs = Vec::new();
+    for (index, (text, mark_fc)) in read_paragraph_text(&word_document, &pieces, ccp_text)
+        .into_iter()
+        .enumerate()
+    {
+        let run_index = runs.partition_point(|run| run.fc_start <= mark_fc);
+        let run = run_index
+            .checked_sub(1)
+            .map(|index| &runs[index])
+            .filter(|run| mark_fc < run.fc_end);
+        let style = run
+            .and_then(|run| styles.get(usize::from(run.istd)))
+            .and_then(Option::as_ref);
+
+        let style_label = style.map(|style| style.name.clone());
+        let is_f8_cite = style_label
+            .as_ref()
+            .map(|label| is_f8_cite_style(label))
+            .unwrap_or(false);
+        // Outline level 9 is body text; sti 1-9 are the built-in headings.
+        let mut heading_level = run
+            .and_then(|run| run.outline_level)
+            .filter(|level| *level < 9)
+            .map(|level| i64::from(level) + 1)
+            .or_else(|| {
+                style.and_then(|style| {
+                    (1..=9)
+                        .contains(&style.sti)
+                        .then_some(i64::from(style.sti))
+                        .or_else(|| parse_trailing_level(&style.name))
+                })
+            });
+        if heading_level.is_some() && (is_probable_author_line(&text) || is_f8_cite) {
+            heading_level = None;
+        }
+
+        paragraphs.push(ParsedParagraph {
+            order: i64::try_from(index).unwrap_or(0) + 1,
+            text,
+            heading_level,
+            style_label,
+            is_f8_cite,
+            // Character formatting is not read from .doc files.
+            underlined_word_count: 0,
+        });
+    }
+    Ok(paragraphs)
+}
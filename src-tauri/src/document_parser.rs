@@ -0,0 +1,170 @@
+use std::fs;
+use std::path::Path;
+
+use roxmltree::Document;
+
+use crate::doc_parse::parse_doc_paragraphs;
+use crate::docx_build::generated_docx_parts;
+use crate::docx_parse::{
+    open_docx_archive, parse_document_paragraphs, read_docx_file, read_docx_file_paragraphs,
+    read_zip_file, ParsedDocx,
+};
+use crate::errors::CommandError;
+use crate::odt_import::convert_odt_document;
+use crate::pdf_parse::parse_pdf_paragraphs;
+use crate::rtf_import::convert_rtf_document;
+use crate::types::{GeneratedParagraph, GeneratedRun, ParsedParagraph};
+use crate::util::path_display;
+use crate::CommandResult;
+
+/// Reads one file format. Formats other than docx come back as docx parts
+/// built from their paragraphs.
+pub(crate) trait DocumentParser: Sync {
+    /// Paragraphs numbered from 1, altChunk content only with `include_alt_chunks`.
+    fn paragraphs(
+        &self,
+        file_path: &Path,
+        include_alt_chunks: bool,
+    ) -> CommandResult<Vec<ParsedParagraph>>;
+
+    fn parse(&self, file_path: &Path) -> CommandResult<ParsedDocx>;
+}
+
+struct DocxParser;
+struct LegacyDocParser;
+struct OdtParser;
+struct PdfParser;
+struct RtfParser;
+
+impl DocumentParser for DocxParser {
+    fn paragraphs(
+        &self,
+        file_path: &Path,
+        include_alt_chunks: bool,
+    ) -> CommandResult<Vec<ParsedParagraph>> {
+        read_docx_file_paragraphs(file_path, include_alt_chunks)
+    }
+
+    fn parse(&self, file_path: &Path) -> CommandResult<ParsedDocx> {
+        read_docx_file(file_path)
+    }
+}
+
+impl DocumentParser for LegacyDocParser {
+    fn paragraphs(&self, file_path: &Path, _: bool) -> CommandResult<Vec<ParsedParagraph>> {
+        parse_doc_paragraphs(file_path)
+    }
+
+    /// Empty paragraphs are kept so numbering matches `paragraphs`.
+    fn parse(&self, file_path: &Path) -> CommandResult<ParsedDocx> {
+        let paragraphs = parse_doc_paragraphs(file_path)?
+            .into_iter()
+            .map(|paragraph| GeneratedParagraph {
+                heading_level: paragraph.heading_level,
+                runs: vec![GeneratedRun {
+                    text: paragraph.text,
+                    ..GeneratedRun::default()
+                }],
+            })
+            .collect::<Vec<GeneratedParagraph>>();
+        converted_docx(&paragraphs)
+    }
+}
+
+impl DocumentParser for OdtParser {
+    fn paragraphs(&self, file_path: &Path, _: bool) -> CommandResult<Vec<ParsedParagraph>> {
+        Ok(self.parse(file_path)?.paragraphs)
+    }
+
+    fn parse(&self, file_path: &Path) -> CommandResult<ParsedDocx> {
+        let mut archive = open_docx_archive(file_path)?;
+        let content_xml = read_zip_file(&mut archive, "content.xml").ok_or_else(|| {
+            CommandError::invalid_docx(
+                file_path,
+                format!(
+                    "Missing content.xml in '{}'. Is this a valid OpenDocument file?",
+                    path_display(file_path)
+                ),
+            )
+        })?;
+        let styles_xml = read_zip_file(&mut archive, "styles.xml");
+        let converted =
+            convert_odt_document(&content_xml, styles_xml.as_deref()).ok_or_else(|| {
+                CommandError::parse(format!(
+                    "Could not parse content.xml in '{}'",
+                    path_display(file_path)
+                ))
+            })?;
+        converted_docx(&converted.paragraphs)
+    }
+}
+
+impl DocumentParser for PdfParser {
+    fn paragraphs(&self, file_path: &Path, _: bool) -> CommandResult<Vec<ParsedParagraph>> {
+        Ok(self.parse(file_path)?.paragraphs)
+    }
+
+    fn parse(&self, file_path: &Path) -> CommandResult<ParsedDocx> {
+        converted_docx(&parse_pdf_paragraphs(file_path)?)
+    }
+}
+
+impl DocumentParser for RtfParser {
+    fn paragraphs(&self, file_path: &Path, _: bool) -> CommandResult<Vec<ParsedParagraph>> {
+        Ok(self.parse(file_path)?.paragraphs)
+    }
+
+    /// RTF is 7-bit, so a lossy read only mangles files that are not RTF.
+    fn parse(&self, file_path: &Path) -> CommandResult<ParsedDocx> {
+        let bytes = fs::read(file_path).map_err(|error| {
+            CommandError::io(format!(
+                "Could not read '{}': {error}",
+                path_display(file_path)
+            ))
+        })?;
+        if !bytes.starts_with(b"{\\rtf") {
+            return Err(CommandError::parse(format!(
+                "'{}' is not an RTF document.",
+                path_display(file_path)
+            )));
+        }
+        let converted = convert_rtf_document(&String::from_utf8_lossy(&bytes));
+        converted_docx(&converted.paragraphs)
+    }
+}
+
+fn converted_docx(paragraphs: &[GeneratedParagraph]) -> CommandResult<ParsedDocx> {
+    let parts = generated_docx_parts(paragraphs)?;
+    let document = Document::parse(&parts.document_xml).map_err(|error| {
+        CommandError::internal(format!("Could not parse generated document XML: {error}"))
+    })?;
+    let paragraphs = parse_document_paragraphs(&document, &Default::default());
+    drop(document);
+    Ok(ParsedDocx {
+        parts,
+        paragraphs,
+        alt_chunks: Vec::new(),
+    })
+}
+
+fn extension_parser(path: &Path) -> Option<&'static dyn DocumentParser> {
+    let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+    match extension.as_str() {
+        "docx" => Some(&DocxParser),
+        "doc" => Some(&LegacyDocParser),
+        "odt" => Some(&OdtParser),
+        "pdf" => Some(&PdfParser),
+        "rtf" => Some(&RtfParser),
+        _ => None,
+    }
+}
+
+/// Whether files like `path` are indexed: .docx, .doc, .odt, .rtf and .pdf.
+pub(crate) fn is_indexable_path(path: &Path) -> bool {
+    extension_parser(path).is_some()
+}
+
+/// The parser for `path` by its extension; anything unrecognised is a docx.
+pub(crate) fn parser_for_path(path: &Path) -> &'static dyn DocumentParser {
+    extension_parser(path).unwrap_or(&DocxParser)
+}
@@ -0,0 +1,90 @@
+use std::collections::{HashMap, VecDeque};
+use std::fs;
+use std::path::Path;
+use std::sync::{Arc, Mutex, OnceLock};
+
+use crate::docx_parse::{read_parsed_docx, ParsedDocx};
+use crate::errors::CommandError;
+use crate::util::{epoch_ms, path_display};
+use crate::CommandResult;
+
+/// Enough for the handful of files someone clicks between while cutting;
+/// whole-root passes (indexing, exports) read files directly instead.
+const PARSED_DOCX_CACHE_CAPACITY: usize = 12;
+
+/// A file's identity for caching: any save changes its mtime or size.
+#[derive(Clone, PartialEq, Eq)]
+pub(crate) struct FileStamp {
+    pub modified_ms: i64,
+    pub size: u64,
+}
+
+#[derive(Default)]
+struct ParsedDocxCache {
+    order: VecDeque<String>,
+    entries: HashMap<String, (FileStamp, Arc<ParsedDocx>)>,
+}
+
+impl ParsedDocxCache {
+    fn get(&mut self, path: &str, stamp: &FileStamp) -> Option<Arc<ParsedDocx>> {
+        let (cached_stamp, parsed) = self.entries.get(path)?;
+        if cached_stamp != stamp {
+            return None;
+        }
+        let parsed = Arc::clone(parsed);
+        self.order.retain(|item| item != path);
+        self.order.push_back(path.to_string());
+        Some(parsed)
+    }
+
+    fn put(&mut self, path: String, stamp: FileStamp, parsed: Arc<ParsedDocx>) {
+        if self.entries.contains_key(&path) {
+            self.order.retain(|item| item != &path);
+        }
+        self.order.push_back(path.clone());
+        self.entries.insert(path, (stamp, parsed));
+        while self.order.len() > PARSED_DOCX_CACHE_CAPACITY {
+            if let Some(oldest) = self.order.pop_front() {
+                self.entries.remove(&oldest);
+            }
+        }
+    }
+}
+
+static PARSED_DOCX_CACHE: OnceLock<Mutex<ParsedDocxCache>> = OnceLock::new();
+
+fn parsed_docx_cache() -> &'static Mutex<ParsedDocxCache> {
+    PARSED_DOCX_CACHE.get_or_init(|| Mutex::new(ParsedDocxCache::default()))
+}
+
+pub(crate) fn file_stamp(file_path: &Path) -> CommandResult<FileStamp> {
+    let metadata = fs::metadata(file_path).map_err(|error| {
+        CommandError::io(format!(
+            "Could not open '{}': {error}",
+            path_display(file_path)
+        ))
+    })?;
+    Ok(FileStamp {
+        modified_ms: metadata.modified().map(epoch_ms).unwrap_or_default(),
+        size: metadata.len(),
+    })
+}
+
+/// Reads and parses a docx, reusing the previous result while the file is
+/// unchanged on disk. The lock is not held while parsing, so two callers may
+/// occasionally parse the same file; the later result wins.
+pub(crate) fn cached_parsed_docx(file_path: &Path) -> CommandResult<Arc<ParsedDocx>> {
+    let key = path_display(file_path);
+    let stamp = file_stamp(file_path)?;
+    if let Ok(mut cache) = parsed_docx_cache().lock() {
+        if let Some(parsed) = cache.get(&key, &stamp) {
+            return Ok(parsed);
+        }
+    }
+
+    let parsed = Arc::new(read_parsed_docx(file_path)?);
+    if let Ok(mut cache) = parsed_docx_cache().lock() {
+        cache.put(key, stamp, Arc::clone(&parsed));
+    }
+    Ok(parsed)
+}
@@ -7,15 +7,29 @@ use docx_rs::Docx;
 use roxmltree::{Document, Node};
 use zip::ZipArchive;
 
+use crate::capture_toc::refresh_table_of_contents;
+use crate::capture_writer::update_capture_target;
+use crate::docx_cache::cached_parsed_docx;
 use crate::docx_parse::{
-    attribute_value, has_tag, parse_docx_paragraphs, read_docx_part, read_zip_file,
-    resolve_insert_after_order,
+    attribute_value, detect_heading_level, has_tag, parse_document_paragraphs, read_docx_part,
+    read_docx_parts, read_style_map, resolve_insert_after_order, DocxParts, InsertMode,
 };
-use crate::types::{RelationshipDef, SourceStyleDefinition, StyledSection};
+use crate::errors::CommandError;
+use crate::media_parts::{collect_section_media, register_media_content_types};
+use crate::notes::{
+    collect_note_ids, collect_note_style_ids, merge_notes, remap_note_references, NoteKind,
+};
+use crate::numbering::{
+    collect_numbering_ids, ensure_numbering_part_registered, merge_numbering, remap_numbering_ids,
+};
+use crate::types::{RelationshipDef, RootSettings, SourceStyleDefinition, StyledSection};
 use crate::util::{is_probable_author_line, path_display};
+use crate::xml_write::{paragraph_fragments, rewrite_attribute_values};
 use crate::CommandResult;
 
 const CITATION_STYLE_PLACEHOLDER: &str = "__BF_CITATION_STYLE__";
+pub(crate) const EMPTY_STYLES_XML: &str = "<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?><w:styles xmlns:w=\"http://schemas.openxmlformats.org/wordprocessingml/2006/main\"></w:styles>";
+pub(crate) const EMPTY_RELATIONSHIPS_XML: &str = "<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?><Relationships xmlns=\"http://schemas.openxmlformats.org/package/2006/relationships\"></Relationships>";
 
 pub(crate) fn xml_escape_text(value: &str) -> String {
     value
@@ -72,9 +86,70 @@ pub(crate) fn fallback_styled_section(content: &str) -> StyledSection {
         style_ids: HashSet::new(),
         relationship_ids: HashSet::new(),
         used_source_xml: false,
+        source_styles_xml: None,
+        source_relationships_xml: None,
+        numbering_ids: HashSet::new(),
+        source_numbering_xml: None,
+        source_footnotes_xml: None,
+        source_endnotes_xml: None,
+        media_parts: Vec::new(),
     }
 }
 
+/// Collects the style ids and relationship ids referenced by a run of
+/// paragraph XML so they can be carried along with the fragment.
+pub(crate) fn collect_fragment_dependencies(
+    fragment_xml: &str,
+) -> (HashSet<String>, HashSet<String>) {
+    let wrapped = format!(
+        "<w:root xmlns:w=\"http://schemas.openxmlformats.org/wordprocessingml/2006/main\" xmlns:r=\"http://schemas.openxmlformats.org/officeDocument/2006/relationships\">{}</w:root>",
+        fragment_xml
+    );
+
+    let mut style_ids = HashSet::new();
+    let mut relationship_ids = HashSet::new();
+    if let Ok(wrapper_document) = Document::parse(&wrapped) {
+        for node in wrapper_document
+            .descendants()
+            .filter(|node| node.is_element())
+        {
+            if has_tag(node, "pStyle") || has_tag(node, "rStyle") {
+                if let Some(style_id) = attribute_value(node, "val") {
+                    if !style_id.is_empty() {
+                        style_ids.insert(style_id.to_string());
+                    }
+                }
+            }
+
+            if has_tag(node, "hyperlink") {
+                if let Some(rel_id) = attribute_value(node, "id") {
+                    if !rel_id.is_empty() {
+                        relationship_ids.insert(rel_id.to_string());
+                    }
+                }
+            }
+
+            if has_tag(node, "blip") {
+                if let Some(rel_id) = attribute_value(node, "embed") {
+                    if !rel_id.is_empty() {
+                        relationship_ids.insert(rel_id.to_string());
+                    }
+                }
+                if let Some(rel_id) = attribute_value(node, "link") {
+                    if !rel_id.is_empty() {
+                        relationship_ids.insert(rel_id.to_string());
+                    }
+                }
+            }
+        }
+    }
+
+    (style_ids, relationship_ids)
+}
+
+/// Copies a heading's section out of its source as raw paragraph XML. The
+/// source comes from the parsed-docx cache; its styles and relationships
+/// travel with the section for `merge_section_into_parts`.
 pub(crate) fn extract_styled_section(
     source_file_path: &Path,
     heading_order: Option<i64>,
@@ -84,9 +159,11 @@ pub(crate) fn extract_styled_section(
         return fallback_styled_section(fallback_content);
     };
 
-    let Ok(paragraphs) = parse_docx_paragraphs(source_file_path) else {
+    let Ok(parsed) = cached_parsed_docx(source_file_path) else {
         return fallback_styled_section(fallback_content);
     };
+    let parts = &parsed.parts;
+    let paragraphs = &parsed.paragraphs;
 
     let Some((start_index, start_paragraph)) = paragraphs
         .iter()
@@ -121,110 +198,75 @@ pub(crate) fn extract_styled_section(
         return fallback_styled_section(fallback_content);
     }
 
-    let file = match File::open(source_file_path) {
-        Ok(file) => file,
-        Err(_) => return fallback_styled_section(fallback_content),
-    };
-    let mut archive = match ZipArchive::new(file) {
-        Ok(archive) => archive,
-        Err(_) => return fallback_styled_section(fallback_content),
-    };
-
-    let Some(document_xml) = read_zip_file(&mut archive, "word/document.xml") else {
-        return fallback_styled_section(fallback_content);
-    };
-    let Ok(document) = Document::parse(&document_xml) else {
+    // Paragraphs are re-serialized from the parsed tree rather than sliced
+    // out of the source bytes, so each one is well-formed on its own.
+    let Ok(document) = Document::parse(&parts.document_xml) else {
         return fallback_styled_section(fallback_content);
     };
-
     let paragraph_nodes = document
         .descendants()
         .filter(|node| has_tag(*node, "p"))
         .collect::<Vec<Node<'_, '_>>>();
-
-    let mut paragraph_xml = Vec::new();
-    for node in paragraph_nodes
-        .iter()
-        .skip(start_index)
-        .take(end_index - start_index)
-    {
-        let range = node.range();
-        if range.end > document_xml.len() || range.start >= range.end {
-            continue;
-        }
-        let snippet = document_xml[range].to_string();
-        if !snippet.trim().is_empty() {
-            paragraph_xml.push(snippet);
-        }
-    }
+    let paragraph_xml = paragraph_fragments(&paragraph_nodes, start_index, end_index);
 
     if paragraph_xml.is_empty() {
         return fallback_styled_section(fallback_content);
     }
 
-    let wrapped = format!(
-        "<w:root xmlns:w=\"http://schemas.openxmlformats.org/wordprocessingml/2006/main\" xmlns:r=\"http://schemas.openxmlformats.org/officeDocument/2006/relationships\">{}</w:root>",
-        paragraph_xml.join("")
-    );
-
-    let mut style_ids = HashSet::new();
-    let mut relationship_ids = HashSet::new();
-    if let Ok(wrapper_document) = Document::parse(&wrapped) {
-        for node in wrapper_document
-            .descendants()
-            .filter(|node| node.is_element())
-        {
-            if has_tag(node, "pStyle") || has_tag(node, "rStyle") {
-                if let Some(style_id) = attribute_value(node, "val") {
-                    if !style_id.is_empty() {
-                        style_ids.insert(style_id.to_string());
-                    }
-                }
-            }
-
-            if has_tag(node, "hyperlink") {
-                if let Some(rel_id) = attribute_value(node, "id") {
-                    if !rel_id.is_empty() {
-                        relationship_ids.insert(rel_id.to_string());
-                    }
-                }
-            }
-
-            if has_tag(node, "blip") {
-                if let Some(rel_id) = attribute_value(node, "embed") {
-                    if !rel_id.is_empty() {
-                        relationship_ids.insert(rel_id.to_string());
-                    }
-                }
-                if let Some(rel_id) = attribute_value(node, "link") {
-                    if !rel_id.is_empty() {
-                        relationship_ids.insert(rel_id.to_string());
-                    }
-                }
-            }
+    let (mut style_ids, relationship_ids) = collect_fragment_dependencies(&paragraph_xml.join(""));
+    let numbering_ids = collect_numbering_ids(&paragraph_xml);
+    for (kind, notes_xml) in [
+        (NoteKind::Footnote, &parts.footnotes_xml),
+        (NoteKind::Endnote, &parts.endnotes_xml),
+    ] {
+        let Some(notes_xml) = notes_xml else {
+            continue;
+        };
+        let note_ids = collect_note_ids(&paragraph_xml, kind);
+        if !note_ids.is_empty() {
+            style_ids.extend(collect_note_style_ids(notes_xml, &note_ids, kind));
         }
     }
+    let (source_relationships_xml, media_parts) = match parts.relationships_xml.as_deref() {
+        Some(relationships_xml) => {
+            let (relationships_xml, media_parts) = collect_section_media(
+                source_file_path,
+                relationships_xml,
+                parts.content_types_xml.as_deref(),
+                &relationship_ids,
+            );
+            (Some(relationships_xml), media_parts)
+        }
+        None => (None, Vec::new()),
+    };
 
     StyledSection {
         paragraph_xml,
         style_ids,
         relationship_ids,
         used_source_xml: true,
+        source_styles_xml: parts.styles_xml.clone(),
+        source_relationships_xml,
+        numbering_ids,
+        source_numbering_xml: parts.numbering_xml.clone(),
+        source_footnotes_xml: parts.footnotes_xml.clone(),
+        source_endnotes_xml: parts.endnotes_xml.clone(),
+        media_parts,
     }
 }
 
 pub(crate) fn create_blank_docx(capture_path: &Path) -> CommandResult<()> {
     let mut output = File::create(capture_path).map_err(|error| {
-        format!(
+        CommandError::io(format!(
             "Could not create capture docx '{}': {error}",
             path_display(capture_path)
-        )
+        ))
     })?;
     Docx::new().build().pack(&mut output).map_err(|error| {
-        format!(
+        CommandError::io(format!(
             "Could not initialize capture docx '{}': {error}",
             path_display(capture_path)
-        )
+        ))
     })
 }
 
@@ -234,20 +276,21 @@ pub(crate) fn ensure_valid_capture_docx(capture_path: &Path) -> CommandResult<()
     }
 
     let file = File::open(capture_path).map_err(|error| {
-        format!(
+        CommandError::io(format!(
             "Could not open capture docx '{}': {error}",
             path_display(capture_path)
-        )
+        ))
     })?;
 
-    let mut archive = ZipArchive::new(file).map_err(|error| {
-        format!(
+    let archive = ZipArchive::new(file).map_err(|error| {
+        CommandError::parse(format!(
             "Could not read capture docx '{}': {error}",
             path_display(capture_path)
-        )
+        ))
     })?;
 
-    if read_zip_file(&mut archive, "word/document.xml").is_some() {
+    // Only the entry's presence matters here, so skip decompressing it.
+    if archive.index_for_name("word/document.xml").is_some() {
         return Ok(());
     }
 
@@ -256,11 +299,7 @@ pub(crate) fn ensure_valid_capture_docx(capture_path: &Path) -> CommandResult<()
     create_blank_docx(capture_path)
 }
 
-pub(crate) fn document_has_body_content(document_xml: &str) -> bool {
-    let Ok(document) = Document::parse(document_xml) else {
-        return document_xml.contains("<w:p") || document_xml.contains("<w:tbl");
-    };
-
+fn body_has_content(document: &Document<'_>) -> bool {
     let Some(body) = document.descendants().find(|node| has_tag(*node, "body")) else {
         return false;
     };
@@ -269,17 +308,24 @@ pub(crate) fn document_has_body_content(document_xml: &str) -> bool {
         .any(|node| node.is_element() && !has_tag(node, "sectPr"))
 }
 
+pub(crate) fn document_has_body_content(document_xml: &str) -> bool {
+    let Ok(document) = Document::parse(document_xml) else {
+        return document_xml.contains("<w:p") || document_xml.contains("<w:tbl");
+    };
+    body_has_content(&document)
+}
+
 pub(crate) fn body_bounds(document_xml: &str) -> CommandResult<(usize, usize)> {
-    let body_open = document_xml
-        .find("<w:body")
-        .ok_or_else(|| "Could not find <w:body> in destination document.xml".to_string())?;
+    let body_open = document_xml.find("<w:body").ok_or_else(|| {
+        CommandError::parse("Could not find <w:body> in destination document.xml")
+    })?;
     let body_open_end = document_xml[body_open..]
         .find('>')
         .map(|offset| body_open + offset + 1)
-        .ok_or_else(|| "Could not parse <w:body> opening tag".to_string())?;
-    let body_close = document_xml
-        .rfind("</w:body>")
-        .ok_or_else(|| "Could not find </w:body> in destination document.xml".to_string())?;
+        .ok_or_else(|| CommandError::parse("Could not parse <w:body> opening tag"))?;
+    let body_close = document_xml.rfind("</w:body>").ok_or_else(|| {
+        CommandError::parse("Could not find </w:body> in destination document.xml")
+    })?;
 
     Ok((body_open_end, body_close))
 }
@@ -297,7 +343,8 @@ pub(crate) fn fallback_body_insertion_index(document_xml: &str) -> CommandResult
     )
 }
 
-pub(crate) fn insertion_index_after_paragraph_count(
+fn insertion_index_in_document(
+    document: &Document<'_>,
     document_xml: &str,
     paragraph_count: usize,
 ) -> Option<usize> {
@@ -305,18 +352,34 @@ pub(crate) fn insertion_index_after_paragraph_count(
         return body_bounds(document_xml).ok().map(|(open, _)| open);
     }
 
-    let document = Document::parse(document_xml).ok()?;
-    let paragraphs = document
+    let paragraph = document
         .descendants()
         .filter(|node| has_tag(*node, "p"))
-        .collect::<Vec<Node<'_, '_>>>();
-
-    let paragraph_index = paragraph_count.saturating_sub(1);
-    let paragraph = paragraphs.get(paragraph_index)?;
+        .nth(paragraph_count - 1)?;
     let range = paragraph.range();
     (range.end <= document_xml.len()).then_some(range.end)
 }
 
+pub(crate) fn insertion_index_after_paragraph_count(
+    document_xml: &str,
+    paragraph_count: usize,
+) -> Option<usize> {
+    if paragraph_count == 0 {
+        return body_bounds(document_xml).ok().map(|(open, _)| open);
+    }
+
+    let document = Document::parse(document_xml).ok()?;
+    insertion_index_in_document(&document, document_xml, paragraph_count)
+}
+
+fn splice_fragment(document_xml: &str, fragment: &str, insertion_index: usize) -> String {
+    let mut updated = String::with_capacity(document_xml.len() + fragment.len() + 32);
+    updated.push_str(&document_xml[..insertion_index]);
+    updated.push_str(fragment);
+    updated.push_str(&document_xml[insertion_index..]);
+    updated
+}
+
 pub(crate) fn insert_fragment_into_document_xml(
     document_xml: &str,
     fragment: &str,
@@ -327,11 +390,7 @@ pub(crate) fn insert_fragment_into_document_xml(
         .and_then(|count| insertion_index_after_paragraph_count(document_xml, count))
         .unwrap_or(fallback_index);
 
-    let mut updated = String::with_capacity(document_xml.len() + fragment.len() + 32);
-    updated.push_str(&document_xml[..insertion_index]);
-    updated.push_str(fragment);
-    updated.push_str(&document_xml[insertion_index..]);
-    Ok(updated)
+    Ok(splice_fragment(document_xml, fragment, insertion_index))
 }
 
 fn parse_source_style_definitions(styles_xml: &str) -> HashMap<String, SourceStyleDefinition> {
@@ -520,7 +579,7 @@ pub(crate) fn parse_relationships(relationships_xml: &str) -> HashMap<String, Re
     relationships
 }
 
-fn next_relationship_id(existing_ids: &HashSet<String>) -> String {
+pub(crate) fn next_relationship_id(existing_ids: &HashSet<String>) -> String {
     let mut max_numeric = 0_i64;
     for id in existing_ids {
         if let Some(raw) = id.strip_prefix("rId") {
@@ -540,7 +599,7 @@ fn next_relationship_id(existing_ids: &HashSet<String>) -> String {
     }
 }
 
-fn relationship_xml(id: &str, definition: &RelationshipDef) -> String {
+pub(crate) fn relationship_xml(id: &str, definition: &RelationshipDef) -> String {
     let mut xml = format!(
         "<Relationship Id=\"{}\" Type=\"{}\" Target=\"{}\"",
         xml_escape_attr(id),
@@ -633,6 +692,61 @@ pub(crate) fn merge_relationships(
     (fallback, id_remap)
 }
 
+/// Makes sure the document links to `part_name` (a `word/` part) and the
+/// package declares its content type. Targets created by Word or docx-rs
+/// without footnotes or lists lack both.
+pub(crate) fn ensure_part_registered(
+    relationships_xml: &mut String,
+    content_types_xml: &mut Option<String>,
+    part_name: &str,
+    relationship_type: &str,
+    content_type: &str,
+) {
+    let relationships = parse_relationships(relationships_xml);
+    if !relationships
+        .values()
+        .any(|definition| definition.rel_type == relationship_type)
+    {
+        if let Some(close_index) = relationships_xml.rfind("</Relationships>") {
+            let id = next_relationship_id(&relationships.keys().cloned().collect());
+            let definition = RelationshipDef {
+                rel_type: relationship_type.to_string(),
+                target: part_name
+                    .strip_prefix("word/")
+                    .unwrap_or(part_name)
+                    .to_string(),
+                target_mode: None,
+            };
+            relationships_xml.insert_str(close_index, &relationship_xml(&id, &definition));
+        }
+    }
+
+    let Some(content_types) = content_types_xml.as_mut() else {
+        return;
+    };
+    let absolute_name = format!("/{part_name}");
+    let declared = Document::parse(content_types)
+        .map(|document| {
+            document.descendants().any(|node| {
+                has_tag(node, "Override")
+                    && attribute_value(node, "PartName") == Some(absolute_name.as_str())
+            })
+        })
+        .unwrap_or(true);
+    if declared {
+        return;
+    }
+    if let Some(close_index) = content_types.rfind("</Types>") {
+        content_types.insert_str(
+            close_index,
+            &format!(
+                "<Override PartName=\"{absolute_name}\" ContentType=\"{}\"/>",
+                xml_escape_attr(content_type)
+            ),
+        );
+    }
+}
+
 pub(crate) fn remap_relationship_ids(
     paragraph_xml: &mut [String],
     id_remap: &HashMap<String, String>,
@@ -659,6 +773,104 @@ pub(crate) fn remap_relationship_ids(
     }
 }
 
+pub(crate) fn style_subset_xml(styles_xml: &str, style_ids: &HashSet<String>) -> String {
+    let definitions = parse_source_style_definitions(styles_xml);
+    let mut subset = String::from(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?><w:styles xmlns:w=\"http://schemas.openxmlformats.org/wordprocessingml/2006/main\">",
+    );
+    for style_id in collect_required_style_ids(style_ids, &definitions) {
+        if let Some(definition) = definitions.get(&style_id) {
+            subset.push_str(&definition.xml);
+        }
+    }
+    subset.push_str("</w:styles>");
+    subset
+}
+
+pub(crate) fn relationship_subset_xml(
+    relationships_xml: &str,
+    relationship_ids: &HashSet<String>,
+) -> String {
+    let relationships = parse_relationships(relationships_xml);
+    let mut ids = relationship_ids
+        .iter()
+        .filter(|id| relationships.contains_key(*id))
+        .collect::<Vec<&String>>();
+    ids.sort();
+
+    let mut subset = String::from(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?><Relationships xmlns=\"http://schemas.openxmlformats.org/package/2006/relationships\">",
+    );
+    for id in ids {
+        if let Some(definition) = relationships.get(id) {
+            subset.push_str(&relationship_xml(id, definition));
+        }
+    }
+    subset.push_str("</Relationships>");
+    subset
+}
+
+/// Reinserts a previously removed paragraph fragment, merging back any style
+/// definitions and relationships it depended on that the target has since lost.
+pub(crate) fn restore_fragment_into_docx(
+    capture_path: &Path,
+    fragment_xml: &str,
+    styles_subset_xml: &str,
+    relationships_subset_xml: &str,
+    after_paragraph_count: Option<usize>,
+) -> CommandResult<()> {
+    ensure_valid_capture_docx(capture_path)?;
+
+    let target_document_xml =
+        read_docx_part(capture_path, "word/document.xml")?.ok_or_else(|| {
+            CommandError::invalid_docx(
+                capture_path,
+                format!(
+                    "Missing word/document.xml in '{}'",
+                    path_display(capture_path)
+                ),
+            )
+        })?;
+    let target_styles_xml = read_docx_part(capture_path, "word/styles.xml")?.unwrap_or_else(|| {
+        "<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?><w:styles xmlns:w=\"http://schemas.openxmlformats.org/wordprocessingml/2006/main\"></w:styles>".to_string()
+    });
+    let target_relationships_xml = read_docx_part(capture_path, "word/_rels/document.xml.rels")?
+        .unwrap_or_else(|| {
+            "<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?><Relationships xmlns=\"http://schemas.openxmlformats.org/package/2006/relationships\"></Relationships>".to_string()
+        });
+
+    let (style_ids, relationship_ids) = collect_fragment_dependencies(fragment_xml);
+    let merged_styles_xml = merge_missing_styles(&target_styles_xml, styles_subset_xml, &style_ids);
+    let (merged_relationships_xml, id_remap) = merge_relationships(
+        &target_relationships_xml,
+        relationships_subset_xml,
+        &relationship_ids,
+    );
+    let mut fragment = vec![fragment_xml.to_string()];
+    remap_relationship_ids(&mut fragment, &id_remap);
+
+    let updated_document_xml = insert_fragment_into_document_xml(
+        &target_document_xml,
+        &fragment.join(""),
+        after_paragraph_count,
+    )?;
+
+    let mut replacements = HashMap::new();
+    replacements.insert(
+        "word/document.xml".to_string(),
+        updated_document_xml.into_bytes(),
+    );
+    replacements.insert(
+        "word/styles.xml".to_string(),
+        merged_styles_xml.into_bytes(),
+    );
+    replacements.insert(
+        "word/_rels/document.xml.rels".to_string(),
+        merged_relationships_xml.into_bytes(),
+    );
+    rewrite_docx_with_parts(capture_path, &replacements)
+}
+
 fn citation_style_score(style_id: &str, style_name: &str) -> i32 {
     let combined = format!("{} {}", style_id, style_name).to_lowercase();
     let has_f8 = combined.contains("f8");
@@ -762,32 +974,36 @@ pub(crate) fn rewrite_docx_with_parts(
     replacements: &HashMap<String, Vec<u8>>,
 ) -> CommandResult<()> {
     let source_file = File::open(capture_path).map_err(|error| {
-        format!(
-            "Could not open capture docx '{}' for update: {error}",
-            path_display(capture_path)
+        CommandError::file_access(
+            capture_path,
+            &error,
+            format!(
+                "Could not open capture docx '{}' for update: {error}",
+                path_display(capture_path)
+            ),
         )
     })?;
     let mut archive = ZipArchive::new(source_file).map_err(|error| {
-        format!(
+        CommandError::parse(format!(
             "Could not read capture docx '{}' for update: {error}",
             path_display(capture_path)
-        )
+        ))
     })?;
 
     let temp_path = capture_path.with_extension("docx.tmp");
     let temp_file = File::create(&temp_path).map_err(|error| {
-        format!(
+        CommandError::io(format!(
             "Could not create temporary capture file '{}': {error}",
             path_display(&temp_path)
-        )
+        ))
     })?;
     let mut writer = zip::ZipWriter::new(temp_file);
     let mut copied_names = HashSet::new();
 
     for index in 0..archive.len() {
-        let mut entry = archive
-            .by_index(index)
-            .map_err(|error| format!("Could not read capture docx entry: {error}"))?;
+        let mut entry = archive.by_index(index).map_err(|error| {
+            CommandError::parse(format!("Could not read capture docx entry: {error}"))
+        })?;
         let name = entry.name().to_string();
         if entry.is_dir() {
             continue;
@@ -795,22 +1011,30 @@ pub(crate) fn rewrite_docx_with_parts(
 
         let options =
             zip::write::SimpleFileOptions::default().compression_method(entry.compression());
-        writer
-            .start_file(name.clone(), options)
-            .map_err(|error| format!("Could not write capture zip entry '{name}': {error}"))?;
+        writer.start_file(name.clone(), options).map_err(|error| {
+            CommandError::io(format!(
+                "Could not write capture zip entry '{name}': {error}"
+            ))
+        })?;
 
         if let Some(updated_bytes) = replacements.get(&name) {
-            writer
-                .write_all(updated_bytes)
-                .map_err(|error| format!("Could not write capture zip entry '{name}': {error}"))?;
+            writer.write_all(updated_bytes).map_err(|error| {
+                CommandError::io(format!(
+                    "Could not write capture zip entry '{name}': {error}"
+                ))
+            })?;
         } else {
             let mut original = Vec::new();
-            entry
-                .read_to_end(&mut original)
-                .map_err(|error| format!("Could not read capture zip entry '{name}': {error}"))?;
-            writer
-                .write_all(&original)
-                .map_err(|error| format!("Could not write capture zip entry '{name}': {error}"))?;
+            entry.read_to_end(&mut original).map_err(|error| {
+                CommandError::io(format!(
+                    "Could not read capture zip entry '{name}': {error}"
+                ))
+            })?;
+            writer.write_all(&original).map_err(|error| {
+                CommandError::io(format!(
+                    "Could not write capture zip entry '{name}': {error}"
+                ))
+            })?;
         }
 
         copied_names.insert(name);
@@ -823,141 +1047,412 @@ pub(crate) fn rewrite_docx_with_parts(
 
         writer
             .start_file(name, zip::write::SimpleFileOptions::default())
-            .map_err(|error| format!("Could not add capture zip entry '{name}': {error}"))?;
-        writer
-            .write_all(updated_bytes)
-            .map_err(|error| format!("Could not add capture zip entry '{name}': {error}"))?;
+            .map_err(|error| {
+                CommandError::io(format!("Could not add capture zip entry '{name}': {error}"))
+            })?;
+        writer.write_all(updated_bytes).map_err(|error| {
+            CommandError::io(format!("Could not add capture zip entry '{name}': {error}"))
+        })?;
     }
 
-    writer
-        .finish()
-        .map_err(|error| format!("Could not finish capture zip rewrite: {error}"))?;
+    writer.finish().map_err(|error| {
+        CommandError::io(format!("Could not finish capture zip rewrite: {error}"))
+    })?;
 
     match fs::rename(&temp_path, capture_path) {
         Ok(()) => Ok(()),
         Err(_) => {
             fs::remove_file(capture_path).map_err(|error| {
-                format!(
-                    "Could not replace capture docx '{}': {error}",
-                    path_display(capture_path)
+                CommandError::file_access(
+                    capture_path,
+                    &error,
+                    format!(
+                        "Could not replace capture docx '{}': {error}",
+                        path_display(capture_path)
+                    ),
                 )
             })?;
             fs::rename(&temp_path, capture_path).map_err(|error| {
-                format!(
-                    "Could not move updated capture docx into place '{}': {error}",
-                    path_display(capture_path)
+                CommandError::file_access(
+                    capture_path,
+                    &error,
+                    format!(
+                        "Could not move updated capture docx into place '{}': {error}",
+                        path_display(capture_path)
+                    ),
                 )
             })
         }
     }
 }
 
-pub(crate) fn append_capture_to_docx(
-    capture_path: &Path,
-    source_file_path: &Path,
-    heading_level: Option<i64>,
-    selected_target_heading_order: Option<i64>,
+/// Copies the styles and relationships a section depends on from its source
+/// into the target parts, returning the section's paragraphs with
+/// relationship ids remapped to the merged ones.
+pub(crate) fn merge_section_into_parts(
+    target_styles_xml: &mut String,
+    target_relationships_xml: &mut String,
     styled_section: &StyledSection,
-) -> CommandResult<()> {
-    if let Some(parent) = capture_path.parent() {
-        fs::create_dir_all(parent).map_err(|error| {
-            format!(
-                "Could not create capture target folder '{}': {error}",
-                path_display(parent)
-            )
-        })?;
-    }
-
-    ensure_valid_capture_docx(capture_path)?;
-
-    let target_document_xml =
-        read_docx_part(capture_path, "word/document.xml")?.ok_or_else(|| {
-            format!(
-                "Missing word/document.xml in '{}' after initialization",
-                path_display(capture_path)
-            )
-        })?;
-    let mut target_styles_xml = read_docx_part(capture_path, "word/styles.xml")?.unwrap_or_else(|| {
-        "<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?><w:styles xmlns:w=\"http://schemas.openxmlformats.org/wordprocessingml/2006/main\"></w:styles>".to_string()
-    });
-    let mut target_relationships_xml = read_docx_part(capture_path, "word/_rels/document.xml.rels")?
-        .unwrap_or_else(|| {
-            "<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?><Relationships xmlns=\"http://schemas.openxmlformats.org/package/2006/relationships\"></Relationships>".to_string()
-        });
-
+) -> Vec<String> {
     let mut section_paragraph_xml = styled_section.paragraph_xml.clone();
-    let destination_paragraphs = parse_docx_paragraphs(capture_path).unwrap_or_default();
-
     if styled_section.used_source_xml {
         if !styled_section.style_ids.is_empty() {
-            if let Ok(Some(source_styles_xml)) = read_docx_part(source_file_path, "word/styles.xml")
-            {
-                target_styles_xml = merge_missing_styles(
-                    &target_styles_xml,
-                    &source_styles_xml,
+            if let Some(source_styles_xml) = &styled_section.source_styles_xml {
+                *target_styles_xml = merge_missing_styles(
+                    target_styles_xml,
+                    source_styles_xml,
                     &styled_section.style_ids,
                 );
             }
         }
 
         if !styled_section.relationship_ids.is_empty() {
-            if let Ok(Some(source_relationships_xml)) =
-                read_docx_part(source_file_path, "word/_rels/document.xml.rels")
-            {
+            if let Some(source_relationships_xml) = &styled_section.source_relationships_xml {
                 let (merged_relationships, id_remap) = merge_relationships(
-                    &target_relationships_xml,
-                    &source_relationships_xml,
+                    target_relationships_xml,
+                    source_relationships_xml,
                     &styled_section.relationship_ids,
                 );
-                target_relationships_xml = merged_relationships;
+                *target_relationships_xml = merged_relationships;
                 remap_relationship_ids(&mut section_paragraph_xml, &id_remap);
             }
         }
     }
 
-    let citation_paragraph_style_id = resolve_citation_paragraph_style_id(&target_styles_xml);
+    let citation_paragraph_style_id = resolve_citation_paragraph_style_id(target_styles_xml);
     apply_citation_style_placeholders(
         &mut section_paragraph_xml,
         citation_paragraph_style_id.as_deref(),
     );
+    section_paragraph_xml
+}
 
+/// Merges a section's styles, relationships, list numbering, notes and media
+/// into `parts` and returns its paragraphs with ids rewritten to match. The
+/// document part itself is left for the caller to splice into.
+pub(crate) fn merge_section_into_docx_parts(
+    parts: &mut DocxParts,
+    styled_section: &StyledSection,
+) -> Vec<String> {
+    let mut styles_xml = parts
+        .styles_xml
+        .take()
+        .unwrap_or_else(|| EMPTY_STYLES_XML.to_string());
+    let mut relationships_xml = parts
+        .relationships_xml
+        .take()
+        .unwrap_or_else(|| EMPTY_RELATIONSHIPS_XML.to_string());
+
+    let mut section_paragraph_xml =
+        merge_section_into_parts(&mut styles_xml, &mut relationships_xml, styled_section);
+    if let Some(source_numbering_xml) = styled_section
+        .source_numbering_xml
+        .as_deref()
+        .filter(|_| !styled_section.numbering_ids.is_empty())
+    {
+        if let Some(merged) = merge_numbering(
+            parts.numbering_xml.as_deref(),
+            source_numbering_xml,
+            &styled_section.numbering_ids,
+        ) {
+            remap_numbering_ids(&mut section_paragraph_xml, &merged.id_remap);
+            ensure_numbering_part_registered(&mut relationships_xml, &mut parts.content_types_xml);
+            parts.numbering_xml = Some(merged.numbering_xml);
+        }
+    }
+    for kind in NoteKind::ALL {
+        let (source_notes_xml, target_notes_xml) = match kind {
+            NoteKind::Footnote => (
+                styled_section.source_footnotes_xml.as_deref(),
+                &mut parts.footnotes_xml,
+            ),
+            NoteKind::Endnote => (
+                styled_section.source_endnotes_xml.as_deref(),
+                &mut parts.endnotes_xml,
+            ),
+        };
+        let Some(source_notes_xml) = source_notes_xml else {
+            continue;
+        };
+        let note_ids = collect_note_ids(&section_paragraph_xml, kind);
+        if note_ids.is_empty() {
+            continue;
+        }
+        if let Some(merged) = merge_notes(
+            target_notes_xml.as_deref(),
+            source_notes_xml,
+            &note_ids,
+            kind,
+        ) {
+            remap_note_references(&mut section_paragraph_xml, &merged.id_remap, kind);
+            kind.ensure_registered(&mut relationships_xml, &mut parts.content_types_xml);
+            *target_notes_xml = Some(merged.notes_xml);
+        }
+    }
+    for part in &styled_section.media_parts {
+        if !parts
+            .media_parts
+            .iter()
+            .any(|existing| existing.part_name == part.part_name)
+        {
+            parts.media_parts.push(part.clone());
+        }
+    }
+    if let Some(content_types_xml) = parts.content_types_xml.as_mut() {
+        register_media_content_types(content_types_xml, &styled_section.media_parts);
+    }
+
+    parts.styles_xml = Some(styles_xml);
+    parts.relationships_xml = Some(relationships_xml);
+    section_paragraph_xml
+}
+
+/// Reads a capture target's parts, creating the file when it is missing and
+/// replacing it (after a `.bak` copy) when it has no document part.
+fn read_capture_parts(capture_path: &Path) -> CommandResult<DocxParts> {
+    if !capture_path.is_file() {
+        create_blank_docx(capture_path)?;
+    } else if let Some(parts) = read_docx_parts(capture_path)? {
+        return Ok(parts);
+    } else {
+        let backup_path = capture_path.with_extension("docx.bak");
+        let _ = fs::copy(capture_path, &backup_path);
+        create_blank_docx(capture_path)?;
+    }
+
+    read_docx_parts(capture_path)?.ok_or_else(|| {
+        CommandError::invalid_docx(
+            capture_path,
+            format!(
+                "Missing word/document.xml in '{}' after initialization",
+                path_display(capture_path)
+            ),
+        )
+    })
+}
+
+/// The empty paragraph that follows each capture, if the root wants one.
+fn separator_paragraph_xml(settings: &RootSettings) -> Option<String> {
+    if !settings.insert_separator {
+        return None;
+    }
+    Some(match settings.separator_style.as_deref() {
+        Some(style_id) => format!(
+            "<w:p><w:pPr><w:pStyle w:val=\"{}\"/></w:pPr></w:p>",
+            xml_escape_attr(style_id)
+        ),
+        None => "<w:p/>".to_string(),
+    })
+}
+
+/// `HeadingN` for N in 1-9, Word's built-in heading style ids.
+fn builtin_heading_level(style_id: &str) -> Option<i64> {
+    style_id
+        .strip_prefix("Heading")
+        .and_then(|level| level.parse::<i64>().ok())
+        .filter(|level| (1..=9).contains(level))
+}
+
+/// The highest heading level among a section's paragraphs, judged by their
+/// outline levels and built-in heading styles.
+fn section_top_heading_level(styled_section: &StyledSection) -> Option<i64> {
+    let style_map = HashMap::new();
+    styled_section
+        .paragraph_xml
+        .iter()
+        .filter_map(|paragraph| {
+            let document = Document::parse(paragraph).ok()?;
+            detect_heading_level(document.root_element(), &style_map)
+        })
+        .min()
+}
+
+/// A copy of a section with its headings moved `shift` levels down, stopping
+/// at Heading 9. Only built-in heading styles and direct outline levels are
+/// rewritten; a custom style that carries its own outline level is left as
+/// it is.
+fn demote_section_headings(styled_section: &StyledSection, shift: i64) -> StyledSection {
+    let mut demoted = styled_section.clone();
+    for paragraph in demoted.paragraph_xml.iter_mut() {
+        *paragraph = rewrite_attribute_values(paragraph, |node, name, value| {
+            if name != "val" {
+                return None;
+            }
+            if has_tag(node, "pStyle") {
+                let level = builtin_heading_level(value)?;
+                Some(format!("Heading{}", (level + shift).min(9)))
+            } else if has_tag(node, "outlineLvl") {
+                let level = value
+                    .parse::<i64>()
+                    .ok()
+                    .filter(|level| (0..9).contains(level))?;
+                Some((level + shift).min(8).to_string())
+            } else {
+                None
+            }
+        });
+    }
+    let (style_ids, _) = collect_fragment_dependencies(&demoted.paragraph_xml.concat());
+    demoted.style_ids.extend(style_ids);
+    demoted
+}
+
+/// Inserts a section into a capture target. The target is read once and its
+/// document parsed once; that parse places the fragment and decides whether
+/// the file still needs its title line. With `table_of_contents` the file's
+/// index is rebuilt in the same write.
+pub(crate) fn append_capture_to_docx(
+    capture_path: &Path,
+    heading_level: Option<i64>,
+    selected_target_heading_order: Option<i64>,
+    insert_mode: InsertMode,
+    styled_section: &StyledSection,
+    settings: &RootSettings,
+    table_of_contents: bool,
+) -> CommandResult<()> {
+    if let Some(parent) = capture_path.parent() {
+        fs::create_dir_all(parent).map_err(|error| {
+            CommandError::io(format!(
+                "Could not create capture target folder '{}': {error}",
+                path_display(parent)
+            ))
+        })?;
+    }
+
+    update_capture_target(capture_path, read_capture_parts, |target| {
+        let mut parts = splice_capture_into_parts(
+            target,
+            heading_level,
+            selected_target_heading_order,
+            insert_mode,
+            std::slice::from_ref(styled_section),
+            settings,
+        )?;
+        if table_of_contents {
+            refresh_table_of_contents(&mut parts, true);
+        }
+        Ok(parts)
+    })
+}
+
+/// Inserts several sections into a capture target at one place, in order.
+/// Their styles and relationships are merged into one copy of the target's
+/// parts and queued as one update.
+pub(crate) fn append_captures_to_docx(
+    capture_path: &Path,
+    heading_level: Option<i64>,
+    selected_target_heading_order: Option<i64>,
+    insert_mode: InsertMode,
+    styled_sections: &[StyledSection],
+    settings: &RootSettings,
+    table_of_contents: bool,
+) -> CommandResult<()> {
+    if let Some(parent) = capture_path.parent() {
+        fs::create_dir_all(parent).map_err(|error| {
+            CommandError::io(format!(
+                "Could not create capture target folder '{}': {error}",
+                path_display(parent)
+            ))
+        })?;
+    }
+
+    update_capture_target(capture_path, read_capture_parts, |target| {
+        let mut parts = splice_capture_into_parts(
+            target,
+            heading_level,
+            selected_target_heading_order,
+            insert_mode,
+            styled_sections,
+            settings,
+        )?;
+        if table_of_contents {
+            refresh_table_of_contents(&mut parts, true);
+        }
+        Ok(parts)
+    })
+}
+
+fn splice_capture_into_parts(
+    target: &DocxParts,
+    heading_level: Option<i64>,
+    selected_target_heading_order: Option<i64>,
+    insert_mode: InsertMode,
+    styled_sections: &[StyledSection],
+    settings: &RootSettings,
+) -> CommandResult<DocxParts> {
+    let target_document_xml = target.document_xml.as_str();
+    let parsed_target = Document::parse(target_document_xml).ok();
+    let destination_paragraphs = parsed_target
+        .as_ref()
+        .map(|document| {
+            parse_document_paragraphs(document, &read_style_map(target.styles_xml.as_deref()))
+        })
+        .unwrap_or_default();
+    let has_body_content = match &parsed_target {
+        Some(document) => body_has_content(document),
+        None => document_has_body_content(target_document_xml),
+    };
+
+    let mut parts = target.clone();
     let mut fragment = String::new();
-    if !document_has_body_content(&target_document_xml) {
+    if !has_body_content && settings.insert_title {
         fragment.push_str(&paragraph_xml_bold("Block File Captures"));
     }
 
-    for paragraph in &section_paragraph_xml {
-        fragment.push_str(paragraph);
+    // Inside a heading, a capture that should nest is pushed at least one
+    // level below it.
+    let parent_level = selected_target_heading_order
+        .filter(|_| {
+            settings.nest_child_captures
+                && matches!(
+                    insert_mode,
+                    InsertMode::AsChildEnd | InsertMode::AsChildStart
+                )
+        })
+        .and_then(|order| {
+            destination_paragraphs
+                .iter()
+                .find(|paragraph| paragraph.order == order)
+        })
+        .and_then(|paragraph| paragraph.heading_level);
+    let separator = separator_paragraph_xml(settings);
+    for styled_section in styled_sections {
+        let nesting_shift = parent_level
+            .zip(section_top_heading_level(styled_section))
+            .map_or(0, |(parent, top)| parent + 1 - top);
+        let shift = settings.heading_level_shift.max(nesting_shift);
+        let demoted;
+        let styled_section = if shift > 0 {
+            demoted = demote_section_headings(styled_section, shift);
+            &demoted
+        } else {
+            styled_section
+        };
+        for paragraph in merge_section_into_docx_parts(&mut parts, styled_section) {
+            fragment.push_str(&paragraph);
+        }
+        if let Some(separator) = &separator {
+            fragment.push_str(separator);
+        }
     }
-    fragment.push_str("<w:p/>");
 
     let insert_after_order = resolve_insert_after_order(
         &destination_paragraphs,
         selected_target_heading_order,
-        heading_level,
-    );
-    let insert_after_paragraph_count =
-        insert_after_order.and_then(|value| usize::try_from(value).ok());
-
-    let updated_document_xml = insert_fragment_into_document_xml(
-        &target_document_xml,
-        &fragment,
-        insert_after_paragraph_count,
-    )?;
-
-    let mut replacements = HashMap::new();
-    replacements.insert(
-        "word/document.xml".to_string(),
-        updated_document_xml.into_bytes(),
-    );
-    replacements.insert(
-        "word/styles.xml".to_string(),
-        target_styles_xml.into_bytes(),
-    );
-    replacements.insert(
-        "word/_rels/document.xml.rels".to_string(),
-        target_relationships_xml.into_bytes(),
+        heading_level.map(|level| (level + settings.heading_level_shift).min(9)),
+        insert_mode,
     );
+    let insertion_index = match insert_after_order
+        .and_then(|value| usize::try_from(value).ok())
+        .zip(parsed_target.as_ref())
+        .and_then(|(count, document)| {
+            insertion_index_in_document(document, target_document_xml, count)
+        }) {
+        Some(index) => index,
+        None => fallback_body_insertion_index(target_document_xml)?,
+    };
+    let updated_document_xml = splice_fragment(target_document_xml, &fragment, insertion_index);
 
-    rewrite_docx_with_parts(capture_path, &replacements)
+    parts.document_xml = updated_document_xml;
+    Ok(parts)
 }
@@ -1,16 +1,38 @@
+use std::cell::RefCell;
 use std::collections::HashMap;
 use std::fs::File;
-use std::io::Read;
+use std::io::{BufReader, Cursor, Read, Seek};
+use std::ops::Range;
 use std::path::Path;
 
 use roxmltree::{Document, Node};
 use zip::ZipArchive;
 
+use crate::document_parser::parser_for_path;
+use crate::docx_capture::parse_relationships;
+use crate::errors::CommandError;
+use crate::media_parts::package_part_name;
+use crate::notes::NoteKind;
+use crate::numbering::NUMBERING_PART_NAME;
 use crate::search::normalize_for_search;
-use crate::types::{HeadingRange, ParsedHeading, ParsedParagraph};
+use crate::types::{HeadingRange, MediaPart, ParsedHeading, ParsedParagraph};
 use crate::util::{is_probable_author_line, path_display};
 use crate::CommandResult;
 
+/// Upper bound on how much a zip entry's declared size may preallocate.
+const MAX_PREALLOCATED_PART_BYTES: usize = 64 << 20;
+/// XML rarely deflates better than this, so a declared size beyond this
+/// multiple of the compressed size is not trusted for preallocation.
+const MAX_PREALLOCATED_COMPRESSION_RATIO: usize = 20;
+/// Per-thread document buffers larger than this are released after use.
+const MAX_RETAINED_BUFFER_BYTES: usize = 64 << 20;
+/// Relationship type suffix of a `w:altChunk` target.
+const ALT_CHUNK_RELATIONSHIP_SUFFIX: &str = "/aFChunk";
+
+thread_local! {
+    static DOCUMENT_XML_BUFFER: RefCell<String> = const { RefCell::new(String::new()) };
+}
+
 pub(crate) fn has_tag(node: Node<'_, '_>, expected: &str) -> bool {
     node.is_element() && node.tag_name().name() == expected
 }
@@ -50,28 +72,82 @@ pub(crate) fn parse_trailing_level(value: &str) -> Option<i64> {
     None
 }
 
-pub(crate) fn read_zip_file(archive: &mut ZipArchive<File>, entry_name: &str) -> Option<String> {
-    let mut entry = archive.by_name(entry_name).ok()?;
+/// Reads a zip entry into `value`, reserving the entry's uncompressed size up
+/// front so a large part is allocated once instead of doubling its way up.
+fn read_zip_file_into<R: Read + Seek>(
+    archive: &mut ZipArchive<R>,
+    entry_name: &str,
+    value: &mut String,
+) -> bool {
+    let Ok(mut entry) = archive.by_name(entry_name) else {
+        return false;
+    };
+    // The declared size comes from the file, so it only sizes the buffer up
+    // to a sane bound; a lying header just means the buffer grows as usual.
+    let declared = usize::try_from(entry.size()).unwrap_or(usize::MAX);
+    let plausible = usize::try_from(entry.compressed_size())
+        .unwrap_or(usize::MAX)
+        .saturating_mul(MAX_PREALLOCATED_COMPRESSION_RATIO);
+    value.reserve(declared.min(plausible).min(MAX_PREALLOCATED_PART_BYTES));
+    entry.read_to_string(value).is_ok()
+}
+
+pub(crate) fn read_zip_file<R: Read + Seek>(
+    archive: &mut ZipArchive<R>,
+    entry_name: &str,
+) -> Option<String> {
     let mut value = String::new();
-    entry.read_to_string(&mut value).ok()?;
-    Some(value)
+    read_zip_file_into(archive, entry_name, &mut value).then_some(value)
+}
+
+pub(crate) fn open_docx_archive(path: &Path) -> CommandResult<ZipArchive<BufReader<File>>> {
+    let file = File::open(path).map_err(|error| {
+        CommandError::io(format!("Could not open '{}': {error}", path_display(path)))
+    })?;
+    ZipArchive::new(BufReader::new(file)).map_err(|error| {
+        CommandError::invalid_docx(
+            path,
+            format!("Could not read '{}': {error}", path_display(path)),
+        )
+    })
 }
 
 pub(crate) fn read_docx_part(path: &Path, part_name: &str) -> CommandResult<Option<String>> {
-    let file = File::open(path)
-        .map_err(|error| format!("Could not open '{}': {error}", path_display(path)))?;
-    let mut archive = ZipArchive::new(file)
-        .map_err(|error| format!("Could not read '{}': {error}", path_display(path)))?;
+    let mut archive = open_docx_archive(path)?;
     Ok(read_zip_file(&mut archive, part_name))
 }
 
-pub(crate) fn read_style_map(styles_xml: Option<String>) -> HashMap<String, String> {
+/// The raw bytes of each named part that exists, for binary parts such as
+/// images.
+pub(crate) fn read_docx_binary_parts(
+    path: &Path,
+    part_names: &[String],
+) -> CommandResult<HashMap<String, Vec<u8>>> {
+    let mut archive = open_docx_archive(path)?;
+    let mut parts = HashMap::new();
+    for part_name in part_names {
+        let Ok(mut entry) = archive.by_name(part_name) else {
+            continue;
+        };
+        let mut bytes = Vec::new();
+        entry.read_to_end(&mut bytes).map_err(|error| {
+            CommandError::io(format!(
+                "Could not read '{part_name}' from '{}': {error}",
+                path_display(path)
+            ))
+        })?;
+        parts.insert(part_name.clone(), bytes);
+    }
+    Ok(parts)
+}
+
+pub(crate) fn read_style_map(styles_xml: Option<&str>) -> HashMap<String, String> {
     let mut map = HashMap::new();
     let Some(styles_xml) = styles_xml else {
         return map;
     };
 
-    let Ok(document) = Document::parse(&styles_xml) else {
+    let Ok(document) = Document::parse(styles_xml) else {
         return map;
     };
 
@@ -114,6 +190,43 @@ pub(crate) fn extract_paragraph_text(paragraph: Node<'_, '_>) -> String {
     value
 }
 
+/// Words in the paragraph with at least one underlined character, read with
+/// the same walk as `extract_paragraph_text` so a word split across runs
+/// counts once.
+pub(crate) fn paragraph_underlined_word_count(paragraph: Node<'_, '_>) -> usize {
+    let mut count = 0;
+    let mut in_word = false;
+    let mut word_underlined = false;
+    let mut end_word = |in_word: &mut bool, word_underlined: &mut bool| {
+        if *in_word && *word_underlined {
+            count += 1;
+        }
+        *in_word = false;
+        *word_underlined = false;
+    };
+
+    for node in paragraph.descendants().filter(|node| node.is_element()) {
+        if has_tag(node, "t") {
+            let underlined = node
+                .ancestors()
+                .find(|ancestor| has_tag(*ancestor, "r"))
+                .is_some_and(run_has_active_underline);
+            for character in node.text().unwrap_or_default().chars() {
+                if character.is_whitespace() {
+                    end_word(&mut in_word, &mut word_underlined);
+                } else {
+                    in_word = true;
+                    word_underlined |= underlined;
+                }
+            }
+        } else if has_tag(node, "tab") || has_tag(node, "br") || has_tag(node, "cr") {
+            end_word(&mut in_word, &mut word_underlined);
+        }
+    }
+    end_word(&mut in_word, &mut word_underlined);
+    count
+}
+
 pub(crate) fn html_escape(value: &str) -> String {
     value
         .replace('&', "&amp;")
@@ -169,6 +282,37 @@ pub(crate) fn run_highlight_class(run: Node<'_, '_>) -> Option<&'static str> {
     }
 }
 
+/// A `w:fill` or `w:val` colour as CSS `#rrggbb`; `None` for `auto` and
+/// anything that is not six hex digits.
+fn hex_color(value: &str) -> Option<String> {
+    let value = value.trim();
+    (value.len() == 6 && value.chars().all(|character| character.is_ascii_hexdigit()))
+        .then(|| format!("#{}", value.to_ascii_lowercase()))
+}
+
+fn shading_fill(properties: Node<'_, '_>) -> Option<String> {
+    let shading = properties.children().find(|node| has_tag(*node, "shd"))?;
+    hex_color(attribute_value(shading, "fill")?)
+}
+
+/// The run's `w:shd` fill, which custom evidence schemes use in place of the
+/// fixed highlight palette.
+pub(crate) fn run_shading_fill(run: Node<'_, '_>) -> Option<String> {
+    shading_fill(run_properties_node(run)?)
+}
+
+/// The run's explicit `w:color`. Theme colours without a literal value are
+/// left to the preview's default text colour.
+pub(crate) fn run_text_color(run: Node<'_, '_>) -> Option<String> {
+    let props = run_properties_node(run)?;
+    let color = props.children().find(|node| has_tag(*node, "color"))?;
+    hex_color(attribute_value(color, "val")?)
+}
+
+pub(crate) fn paragraph_shading_fill(paragraph: Node<'_, '_>) -> Option<String> {
+    shading_fill(paragraph.children().find(|node| has_tag(*node, "pPr"))?)
+}
+
 pub(crate) fn run_underline_class(run: Node<'_, '_>) -> Option<&'static str> {
     let props = run_properties_node(run)?;
     let highlight = props.children().find(|node| has_tag(*node, "underline"))?;
@@ -239,28 +383,44 @@ pub(crate) fn is_f8_cite_style(style_label: &str) -> bool {
     normalized.contains("f8 cite") || normalized.contains("f8cite")
 }
 
-pub(crate) fn parse_docx_paragraphs(file_path: &Path) -> CommandResult<Vec<ParsedParagraph>> {
-    let file = File::open(file_path)
-        .map_err(|error| format!("Could not open '{}': {error}", path_display(file_path)))?;
-    let mut archive = ZipArchive::new(file)
-        .map_err(|error| format!("Could not read '{}': {error}", path_display(file_path)))?;
-
-    let document_xml = read_zip_file(&mut archive, "word/document.xml").ok_or_else(|| {
-        format!(
-            "Missing word/document.xml in '{}'. Is this a valid docx file?",
-            path_display(file_path)
-        )
-    })?;
-
-    let style_map = read_style_map(read_zip_file(&mut archive, "word/styles.xml"));
+/// The parts of a docx that reading and capturing need, taken from the zip in
+/// one pass. `None` means the zip has no `word/document.xml`.
+#[derive(Clone)]
+pub(crate) struct DocxParts {
+    pub document_xml: String,
+    pub styles_xml: Option<String>,
+    pub relationships_xml: Option<String>,
+    pub numbering_xml: Option<String>,
+    pub content_types_xml: Option<String>,
+    pub footnotes_xml: Option<String>,
+    pub endnotes_xml: Option<String>,
+    /// Media added since the parts were read, still to be written.
+    pub media_parts: Vec<MediaPart>,
+}
 
-    let document = Document::parse(&document_xml).map_err(|error| {
-        format!(
-            "Could not parse XML in '{}': {error}",
-            path_display(file_path)
-        )
-    })?;
+pub(crate) fn read_docx_parts(file_path: &Path) -> CommandResult<Option<DocxParts>> {
+    let mut archive = open_docx_archive(file_path)?;
+    let Some(document_xml) = read_zip_file(&mut archive, "word/document.xml") else {
+        return Ok(None);
+    };
+    Ok(Some(DocxParts {
+        document_xml,
+        styles_xml: read_zip_file(&mut archive, "word/styles.xml"),
+        relationships_xml: read_zip_file(&mut archive, "word/_rels/document.xml.rels"),
+        numbering_xml: read_zip_file(&mut archive, NUMBERING_PART_NAME),
+        content_types_xml: read_zip_file(&mut archive, "[Content_Types].xml"),
+        footnotes_xml: read_zip_file(&mut archive, NoteKind::Footnote.part_name()),
+        endnotes_xml: read_zip_file(&mut archive, NoteKind::Endnote.part_name()),
+        media_parts: Vec::new(),
+    }))
+}
 
+/// Paragraphs of an already parsed document.xml, numbered from 1 in document
+/// order (the same order as its `w:p` descendants).
+pub(crate) fn parse_document_paragraphs(
+    document: &Document<'_>,
+    style_map: &HashMap<String, String>,
+) -> Vec<ParsedParagraph> {
     let mut order = 0_i64;
     let mut paragraphs = Vec::new();
 
@@ -268,12 +428,12 @@ pub(crate) fn parse_docx_paragraphs(file_path: &Path) -> CommandResult<Vec<Parse
         let text = extract_paragraph_text(paragraph);
 
         order += 1;
-        let style_label = paragraph_style_label(paragraph, &style_map);
+        let style_label = paragraph_style_label(paragraph, style_map);
         let is_f8_cite = style_label
             .as_ref()
             .map(|label| is_f8_cite_style(label))
             .unwrap_or(false);
-        let mut heading_level = detect_heading_level(paragraph, &style_map);
+        let mut heading_level = detect_heading_level(paragraph, style_map);
         if heading_level.is_some() && (is_probable_author_line(&text) || is_f8_cite) {
             heading_level = None;
         }
@@ -284,10 +444,410 @@ pub(crate) fn parse_docx_paragraphs(file_path: &Path) -> CommandResult<Vec<Parse
             heading_level,
             style_label,
             is_f8_cite,
+            underlined_word_count: paragraph_underlined_word_count(paragraph),
+        });
+    }
+
+    paragraphs
+}
+
+/// A start, end or empty-element tag found by `scan_element_tags`, with its
+/// local name and byte range.
+struct ScannedTag<'a> {
+    name: &'a str,
+    closing: bool,
+    self_closing: bool,
+    range: Range<usize>,
+}
+
+/// The element tags of `document_xml` in order, found by scanning for `<`
+/// rather than building a DOM. Comments, CDATA and declarations are skipped.
+fn scan_element_tags(document_xml: &str) -> impl Iterator<Item = ScannedTag<'_>> {
+    let mut cursor = 0_usize;
+    std::iter::from_fn(move || loop {
+        let tag_start = cursor + document_xml[cursor..].find('<')?;
+        let rest = &document_xml[tag_start..];
+        let tag_length = if rest.starts_with("<!--") {
+            rest.find("-->").map(|end| end + 3)
+        } else if rest.starts_with("<![CDATA[") {
+            rest.find("]]>").map(|end| end + 3)
+        } else {
+            rest.find('>').map(|end| end + 1)
+        }?;
+        let tag_end = tag_start + tag_length;
+        cursor = tag_end;
+
+        let tag = &rest[..tag_length];
+        if tag.starts_with("<!") || tag.starts_with("<?") {
+            continue;
+        }
+        let closing = tag.starts_with("</");
+        let name = tag[if closing { 2 } else { 1 }..]
+            .split(|character: char| {
+                character.is_whitespace() || character == '/' || character == '>'
+            })
+            .next()
+            .unwrap_or("");
+        return Some(ScannedTag {
+            name: name.rsplit(':').next().unwrap_or(name),
+            closing,
+            self_closing: tag.ends_with("/>"),
+            range: tag_start..tag_end,
         });
+    })
+}
+
+/// Byte ranges of the paragraphs at `indices` (0-based, in the same order as
+/// the `w:p` descendants, nested ones included), found by scanning tags rather
+/// than building a DOM. Scanning stops once the last wanted paragraph closes.
+pub(crate) fn paragraph_byte_ranges(
+    document_xml: &str,
+    indices: Range<usize>,
+) -> Vec<(usize, Range<usize>)> {
+    let mut ranges = Vec::new();
+    let mut open = Vec::<(usize, usize)>::new();
+    let mut next_index = 0_usize;
+
+    for tag in scan_element_tags(document_xml) {
+        if tag.name != "p" {
+            continue;
+        }
+
+        if tag.closing {
+            if let Some((index, start)) = open.pop() {
+                if indices.contains(&index) {
+                    ranges.push((index, start..tag.range.end));
+                }
+            }
+        } else {
+            let index = next_index;
+            next_index += 1;
+            if tag.self_closing {
+                if indices.contains(&index) {
+                    ranges.push((index, tag.range));
+                }
+            } else {
+                open.push((index, tag.range.start));
+            }
+        }
+
+        if next_index >= indices.end && open.is_empty() {
+            break;
+        }
+    }
+
+    ranges.sort_by_key(|(index, _)| *index);
+    ranges
+}
+
+/// Outermost `w:tbl` elements holding any of the paragraphs at `indices`:
+/// the indices of every paragraph inside each table (nested tables included)
+/// and the table's byte range. Paragraphs are counted as in
+/// `paragraph_byte_ranges`.
+pub(crate) fn table_byte_ranges(
+    document_xml: &str,
+    indices: Range<usize>,
+) -> Vec<(Range<usize>, Range<usize>)> {
+    let mut tables = Vec::new();
+    let mut depth = 0_usize;
+    let mut table_start = (0_usize, 0_usize);
+    let mut next_index = 0_usize;
+
+    for tag in scan_element_tags(document_xml) {
+        match tag.name {
+            "p" if !tag.closing => next_index += 1,
+            "tbl" if !tag.self_closing => {
+                if tag.closing {
+                    depth = depth.saturating_sub(1);
+                    if depth == 0 {
+                        let paragraphs = table_start.0..next_index;
+                        if paragraphs.start < indices.end && indices.start < paragraphs.end {
+                            tables.push((paragraphs, table_start.1..tag.range.end));
+                        }
+                    }
+                } else {
+                    if depth == 0 {
+                        table_start = (next_index, tag.range.start);
+                    }
+                    depth += 1;
+                }
+            }
+            _ => {}
+        }
+
+        if next_index >= indices.end && depth == 0 {
+            break;
+        }
+    }
+
+    tables
+}
+
+/// The `xmlns` declarations on document.xml's root element, so a paragraph
+/// sliced out of it can be parsed on its own.
+pub(crate) fn root_namespace_declarations(document_xml: &str) -> String {
+    let mut search_from = 0_usize;
+    let root_tag = loop {
+        let Some(offset) = document_xml[search_from..].find('<') else {
+            return String::new();
+        };
+        let start = search_from + offset;
+        let Some(length) = document_xml[start..].find('>') else {
+            return String::new();
+        };
+        let tag = &document_xml[start..start + length];
+        search_from = start + length + 1;
+        if !tag.starts_with("<?") && !tag.starts_with("<!") {
+            break tag;
+        }
+    };
+
+    let mut declarations = Vec::new();
+    let mut rest = root_tag;
+    while let Some(offset) = rest.find("xmlns") {
+        let declaration = &rest[offset..];
+        let Some(equals) = declaration.find('=') else {
+            break;
+        };
+        let Some(quote) = declaration[equals + 1..].chars().next() else {
+            break;
+        };
+        let value_start = equals + 2;
+        let Some(value_length) = declaration[value_start..].find(quote) else {
+            break;
+        };
+        let end = value_start + value_length + 1;
+        declarations.push(&declaration[..end]);
+        rest = &declaration[end..];
     }
+    declarations.join(" ")
+}
+
+/// A WordprocessingML document embedded through `w:altChunk`, and where it
+/// sits among the host's paragraphs.
+pub(crate) struct AltChunk {
+    /// Host `w:p` elements before the chunk in document order.
+    pub after_paragraphs: usize,
+    pub document_xml: String,
+    pub styles_xml: Option<String>,
+}
+
+/// `(after_paragraphs, relationship id)` of each `w:altChunk` in `document`.
+fn alt_chunk_references(document: &Document<'_>) -> Vec<(usize, String)> {
+    let mut paragraphs_seen = 0_usize;
+    let mut references = Vec::new();
+    for node in document.descendants() {
+        if has_tag(node, "p") {
+            paragraphs_seen += 1;
+        } else if has_tag(node, "altChunk") {
+            if let Some(id) = attribute_value(node, "id") {
+                references.push((paragraphs_seen, id.to_string()));
+            }
+        }
+    }
+    references
+}
+
+/// The document and styles of an altChunk part: either an embedded docx
+/// package or a bare `w:document` part. HTML, MHT and RTF chunks are left
+/// for Word to convert and skipped here.
+fn read_alt_chunk_content(bytes: Vec<u8>) -> Option<(String, Option<String>)> {
+    if bytes.starts_with(b"PK") {
+        let mut archive = ZipArchive::new(Cursor::new(bytes)).ok()?;
+        let document_xml = read_zip_file(&mut archive, "word/document.xml")?;
+        let styles_xml = read_zip_file(&mut archive, "word/styles.xml");
+        return Some((document_xml, styles_xml));
+    }
+    let document_xml = String::from_utf8(bytes).ok()?;
+    let is_wordprocessing_ml = Document::parse(&document_xml)
+        .is_ok_and(|document| has_tag(document.root_element(), "document"));
+    is_wordprocessing_ml.then_some((document_xml, None))
+}
+
+/// The WordprocessingML altChunks `document` references, read from
+/// `archive`. Chunks nested inside a chunk are not followed.
+pub(crate) fn read_alt_chunks<R: Read + Seek>(
+    archive: &mut ZipArchive<R>,
+    document: &Document<'_>,
+) -> Vec<AltChunk> {
+    let references = alt_chunk_references(document);
+    if references.is_empty() {
+        return Vec::new();
+    }
+    let relationships = read_zip_file(archive, "word/_rels/document.xml.rels")
+        .map(|xml| parse_relationships(&xml))
+        .unwrap_or_default();
+
+    references
+        .into_iter()
+        .filter_map(|(after_paragraphs, id)| {
+            let relationship = relationships.get(&id)?;
+            if !relationship
+                .rel_type
+                .ends_with(ALT_CHUNK_RELATIONSHIP_SUFFIX)
+                || relationship.target_mode.as_deref() == Some("External")
+            {
+                return None;
+            }
+            let mut entry = archive
+                .by_name(&package_part_name(&relationship.target))
+                .ok()?;
+            let mut bytes = Vec::new();
+            entry.read_to_end(&mut bytes).ok()?;
+            let (document_xml, styles_xml) = read_alt_chunk_content(bytes)?;
+            Some(AltChunk {
+                after_paragraphs,
+                document_xml,
+                styles_xml,
+            })
+        })
+        .collect()
+}
 
-    Ok(paragraphs)
+/// Inserts each chunk's paragraphs after the host paragraph it follows.
+/// They are numbered on from the host's last order, so host orders still
+/// match `w:p` positions in document.xml, and their headings are kept as
+/// body text: a heading with no place in document.xml could not be
+/// previewed or copied.
+fn splice_alt_chunk_paragraphs(
+    host: Vec<ParsedParagraph>,
+    chunks: &[AltChunk],
+    host_style_map: &HashMap<String, String>,
+) -> Vec<ParsedParagraph> {
+    let mut next_order = i64::try_from(host.len()).unwrap_or(i64::MAX);
+    let mut chunks = chunks.iter().peekable();
+    let mut paragraphs = Vec::with_capacity(host.len());
+    let mut push_chunks_after = |paragraphs: &mut Vec<ParsedParagraph>, seen: usize| {
+        while let Some(chunk) = chunks.next_if(|chunk| chunk.after_paragraphs <= seen) {
+            let Ok(document) = Document::parse(&chunk.document_xml) else {
+                continue;
+            };
+            let chunk_style_map = chunk
+                .styles_xml
+                .as_deref()
+                .map(|styles_xml| read_style_map(Some(styles_xml)));
+            let style_map = chunk_style_map.as_ref().unwrap_or(host_style_map);
+            for mut paragraph in parse_document_paragraphs(&document, style_map) {
+                next_order += 1;
+                paragraph.order = next_order;
+                paragraph.heading_level = None;
+                paragraphs.push(paragraph);
+            }
+        }
+    };
+
+    push_chunks_after(&mut paragraphs, 0);
+    for (index, paragraph) in host.into_iter().enumerate() {
+        paragraphs.push(paragraph);
+        push_chunks_after(&mut paragraphs, index + 1);
+    }
+    paragraphs
+}
+
+/// A docx's parts together with its parsed paragraphs (document.xml's own,
+/// one per `w:p`) and any altChunks it embeds.
+pub(crate) struct ParsedDocx {
+    pub parts: DocxParts,
+    pub paragraphs: Vec<ParsedParagraph>,
+    pub alt_chunks: Vec<AltChunk>,
+}
+
+pub(crate) fn read_parsed_docx(file_path: &Path) -> CommandResult<ParsedDocx> {
+    parser_for_path(file_path).parse(file_path)
+}
+
+pub(crate) fn read_docx_file(file_path: &Path) -> CommandResult<ParsedDocx> {
+    let parts = read_docx_parts(file_path)?.ok_or_else(|| {
+        CommandError::invalid_docx(
+            file_path,
+            format!(
+                "Missing word/document.xml in '{}'. Is this a valid docx file?",
+                path_display(file_path)
+            ),
+        )
+    })?;
+    let style_map = read_style_map(parts.styles_xml.as_deref());
+
+    let document = Document::parse(&parts.document_xml).map_err(|error| {
+        CommandError::parse(format!(
+            "Could not parse XML in '{}': {error}",
+            path_display(file_path)
+        ))
+    })?;
+    let paragraphs = parse_document_paragraphs(&document, &style_map);
+    let alt_chunks = if parts.document_xml.contains("altChunk") {
+        read_alt_chunks(&mut open_docx_archive(file_path)?, &document)
+    } else {
+        Vec::new()
+    };
+    drop(document);
+
+    Ok(ParsedDocx {
+        parts,
+        paragraphs,
+        alt_chunks,
+    })
+}
+
+/// Paragraphs only, for bulk passes like indexing: document.xml is read into
+/// a per-thread buffer that is reused from file to file rather than
+/// allocated afresh, and never outlives the parse. Paragraphs of embedded
+/// altChunk documents are included (see `splice_alt_chunk_paragraphs`).
+/// Other formats are handed to their `DocumentParser`.
+pub(crate) fn parse_docx_paragraphs(file_path: &Path) -> CommandResult<Vec<ParsedParagraph>> {
+    parser_for_path(file_path).paragraphs(file_path, true)
+}
+
+/// Like `parse_docx_paragraphs` without altChunk content: exactly one entry
+/// per `w:p` of document.xml, for callers that pair paragraphs with
+/// document.xml nodes or edit the file by paragraph position.
+pub(crate) fn parse_document_xml_paragraphs(
+    file_path: &Path,
+) -> CommandResult<Vec<ParsedParagraph>> {
+    parser_for_path(file_path).paragraphs(file_path, false)
+}
+
+pub(crate) fn read_docx_file_paragraphs(
+    file_path: &Path,
+    include_alt_chunks: bool,
+) -> CommandResult<Vec<ParsedParagraph>> {
+    let mut archive = open_docx_archive(file_path)?;
+    let style_map = read_style_map(read_zip_file(&mut archive, "word/styles.xml").as_deref());
+
+    DOCUMENT_XML_BUFFER.with(|buffer| {
+        let mut document_xml = buffer.borrow_mut();
+        document_xml.clear();
+        if !read_zip_file_into(&mut archive, "word/document.xml", &mut document_xml) {
+            return Err(CommandError::invalid_docx(
+                file_path,
+                format!(
+                    "Missing word/document.xml in '{}'. Is this a valid docx file?",
+                    path_display(file_path)
+                ),
+            ));
+        }
+
+        let paragraphs = Document::parse(&document_xml)
+            .map(|document| {
+                let paragraphs = parse_document_paragraphs(&document, &style_map);
+                if !include_alt_chunks || !document_xml.contains("altChunk") {
+                    return paragraphs;
+                }
+                let chunks = read_alt_chunks(&mut archive, &document);
+                splice_alt_chunk_paragraphs(paragraphs, &chunks, &style_map)
+            })
+            .map_err(|error| {
+                CommandError::parse(format!(
+                    "Could not parse XML in '{}': {error}",
+                    path_display(file_path)
+                ))
+            });
+        // One outsized file should not pin its buffer for the thread's life.
+        if document_xml.capacity() > MAX_RETAINED_BUFFER_BYTES {
+            *document_xml = String::new();
+        }
+        paragraphs
+    })
 }
 
 pub(crate) fn build_heading_ranges(paragraphs: &[ParsedParagraph]) -> Vec<HeadingRange> {
@@ -329,10 +889,47 @@ pub(crate) fn build_heading_ranges(paragraphs: &[ParsedParagraph]) -> Vec<Headin
     ranges
 }
 
+/// Where a capture lands relative to the heading selected in its target.
+#[derive(Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) enum InsertMode {
+    /// After the selected heading's subtree, or after an ancestor's when the
+    /// capture is the higher-level heading.
+    #[default]
+    After,
+    /// Inside the selected heading, after its last child.
+    AsChildEnd,
+    /// Inside the selected heading, before its first child heading.
+    AsChildStart,
+    /// Directly before the selected heading.
+    Before,
+}
+
+pub(crate) fn parse_insert_mode(value: Option<&str>) -> CommandResult<InsertMode> {
+    let Some(value) = value.map(str::trim).filter(|value| !value.is_empty()) else {
+        return Ok(InsertMode::default());
+    };
+    match value.to_ascii_lowercase().as_str() {
+        "after" => Ok(InsertMode::After),
+        "as-child-end" => Ok(InsertMode::AsChildEnd),
+        "as-child-start" => Ok(InsertMode::AsChildStart),
+        "before" => Ok(InsertMode::Before),
+        other => Err(CommandError::validation_field(
+            "insertMode",
+            format!(
+                "Unknown insert mode '{other}'. Use after, as-child-end, as-child-start, or before."
+            ),
+        )),
+    }
+}
+
+/// The paragraph order a capture goes after, where 0 means the start of the
+/// body. Without a selected heading `insert_mode` is ignored and the capture
+/// follows the last heading at or above its level.
 pub(crate) fn resolve_insert_after_order(
     paragraphs: &[ParsedParagraph],
     selected_target_heading_order: Option<i64>,
     incoming_heading_level: Option<i64>,
+    insert_mode: InsertMode,
 ) -> Option<i64> {
     let heading_ranges = build_heading_ranges(paragraphs);
     if heading_ranges.is_empty() {
@@ -344,12 +941,34 @@ pub(crate) fn resolve_insert_after_order(
             .get(range.end_index.saturating_sub(1))
             .map(|paragraph| paragraph.order)
     };
+    let order_before = |index: usize| match index.checked_sub(1) {
+        Some(previous) => paragraphs.get(previous).map(|paragraph| paragraph.order),
+        None => Some(0),
+    };
 
     if let Some(selected_order) = selected_target_heading_order {
         if let Some(selected_range) = heading_ranges
             .iter()
             .find(|range| range.order == selected_order)
         {
+            match insert_mode {
+                InsertMode::After => {}
+                InsertMode::AsChildEnd => return end_order(selected_range),
+                InsertMode::AsChildStart => {
+                    // The heading's own text stays with it; the capture goes
+                    // in front of the first nested heading.
+                    let first_child_start = heading_ranges
+                        .iter()
+                        .map(|range| range.start_index)
+                        .find(|start| {
+                            *start > selected_range.start_index && *start < selected_range.end_index
+                        })
+                        .unwrap_or(selected_range.end_index);
+                    return order_before(first_child_start);
+                }
+                InsertMode::Before => return order_before(selected_range.start_index),
+            }
+
             if let Some(incoming_level) = incoming_heading_level {
                 if incoming_level < selected_range.level {
                     let mut ancestor_match: Option<&HeadingRange> = None;
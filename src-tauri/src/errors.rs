@@ -0,0 +1,236 @@
+use std::fmt;
+use std::io;
+use std::path::Path;
+
+use serde::ser::{Serialize, SerializeStruct, Serializer};
+
+use crate::messages::{format_message, message};
+use crate::util::path_display;
+
+/// Windows reports a file another program holds open as a sharing or lock
+/// violation rather than a permission error.
+const ERROR_SHARING_VIOLATION: i32 = 32;
+const ERROR_LOCK_VIOLATION: i32 = 33;
+
+/// The error every command returns. It reaches the frontend as
+/// `{ code, title, message, retryable }` plus the variant's context fields, so the UI
+/// can pick a recovery action from `code` instead of matching on `message`.
+#[derive(Debug, Clone)]
+pub(crate) enum CommandError {
+    /// Reading or writing a file, folder, socket or child process failed.
+    Io { message: String },
+    /// A docx, CSV, JSON or other input could not be understood.
+    Parse { message: String },
+    /// The file is not a Word document: not a zip, or a zip without
+    /// `word/document.xml`.
+    InvalidDocx { message: String, path: String },
+    /// A capture, trash entry, queue item or other record named by id no
+    /// longer exists.
+    NotFound { message: String },
+    /// The root or file is not in the index, or the index no longer matches
+    /// the file on disk.
+    NotIndexed {
+        message: String,
+        path: Option<String>,
+    },
+    /// Another program (usually Word) holds the capture target open.
+    TargetLocked { message: String, path: String },
+    /// The path is outside every registered root and allow-listed location.
+    AccessDenied { message: String, path: String },
+    /// The request itself is invalid and retrying it unchanged will not help.
+    Validation {
+        message: String,
+        field: Option<String>,
+    },
+    /// The index database, lexical index or vector store failed.
+    Database { message: String },
+    /// The user cancelled a long-running operation before it finished.
+    Cancelled { message: String, operation: String },
+    /// A background task, lock or runtime failed.
+    Internal { message: String },
+}
+
+impl CommandError {
+    pub(crate) fn io(message: impl Into<String>) -> Self {
+        Self::Io {
+            message: message.into(),
+        }
+    }
+
+    pub(crate) fn parse(message: impl Into<String>) -> Self {
+        Self::Parse {
+            message: message.into(),
+        }
+    }
+
+    pub(crate) fn invalid_docx(path: &Path, message: impl Into<String>) -> Self {
+        Self::InvalidDocx {
+            message: message.into(),
+            path: path_display(path),
+        }
+    }
+
+    pub(crate) fn not_found(message: impl Into<String>) -> Self {
+        Self::NotFound {
+            message: message.into(),
+        }
+    }
+
+    pub(crate) fn not_indexed(message: impl Into<String>) -> Self {
+        Self::NotIndexed {
+            message: message.into(),
+            path: None,
+        }
+    }
+
+    /// A root that was never added, or was removed, on this machine.
+    pub(crate) fn root_not_indexed(path: impl Into<String>) -> Self {
+        let path = path.into();
+        Self::NotIndexed {
+            message: format_message("error.rootNotIndexed", &[("path", &path)]),
+            path: Some(path),
+        }
+    }
+
+    pub(crate) fn target_locked(path: &Path, message: impl Into<String>) -> Self {
+        Self::TargetLocked {
+            message: message.into(),
+            path: path_display(path),
+        }
+    }
+
+    pub(crate) fn access_denied(path: &Path) -> Self {
+        let path = path_display(path);
+        Self::AccessDenied {
+            message: format_message("error.outsideRoots", &[("path", &path)]),
+            path,
+        }
+    }
+
+    pub(crate) fn validation(message: impl Into<String>) -> Self {
+        Self::Validation {
+            message: message.into(),
+            field: None,
+        }
+    }
+
+    pub(crate) fn validation_field(field: &str, message: impl Into<String>) -> Self {
+        Self::Validation {
+            message: message.into(),
+            field: Some(field.to_string()),
+        }
+    }
+
+    pub(crate) fn database(message: impl Into<String>) -> Self {
+        Self::Database {
+            message: message.into(),
+        }
+    }
+
+    /// `operation` is the operation kind, as reported in progress events.
+    pub(crate) fn cancelled(operation: &str) -> Self {
+        Self::Cancelled {
+            message: format_message("error.cancelled", &[("kind", operation)]),
+            operation: operation.to_string(),
+        }
+    }
+
+    pub(crate) fn internal(message: impl Into<String>) -> Self {
+        Self::Internal {
+            message: message.into(),
+        }
+    }
+
+    /// An I/O failure on `path`, reported as `TargetLocked` with a localized
+    /// message when another program has the file open.
+    pub(crate) fn file_access(path: &Path, error: &io::Error, message: impl Into<String>) -> Self {
+        let locked = error.kind() == io::ErrorKind::PermissionDenied
+            || matches!(
+                error.raw_os_error(),
+                Some(ERROR_SHARING_VIOLATION | ERROR_LOCK_VIOLATION)
+            );
+        if locked {
+            Self::target_locked(
+                path,
+                format_message("error.targetLocked", &[("path", &path_display(path))]),
+            )
+        } else {
+            Self::io(message)
+        }
+    }
+
+    pub(crate) fn code(&self) -> &'static str {
+        match self {
+            Self::Io { .. } => "io",
+            Self::Parse { .. } => "parse",
+            Self::InvalidDocx { .. } => "invalid_docx",
+            Self::NotFound { .. } => "not_found",
+            Self::NotIndexed { .. } => "not_indexed",
+            Self::TargetLocked { .. } => "target_locked",
+            Self::AccessDenied { .. } => "access_denied",
+            Self::Validation { .. } => "validation",
+            Self::Database { .. } => "database",
+            Self::Cancelled { .. } => "cancelled",
+            Self::Internal { .. } => "internal",
+        }
+    }
+
+    pub(crate) fn message(&self) -> &str {
+        match self {
+            Self::Io { message }
+            | Self::Parse { message }
+            | Self::InvalidDocx { message, .. }
+            | Self::NotFound { message }
+            | Self::NotIndexed { message, .. }
+            | Self::TargetLocked { message, .. }
+            | Self::AccessDenied { message, .. }
+            | Self::Validation { message, .. }
+            | Self::Database { message }
+            | Self::Cancelled { message, .. }
+            | Self::Internal { message } => message,
+        }
+    }
+
+    /// A short localized heading for the error's code.
+    pub(crate) fn title(&self) -> String {
+        message(&format!("error.title.{}", self.code()))
+    }
+
+    /// Whether the same request can succeed later without the user changing
+    /// it, e.g. once Word closes the capture target or a cancelled run is
+    /// started again.
+    pub(crate) fn retryable(&self) -> bool {
+        matches!(
+            self,
+            Self::TargetLocked { .. } | Self::Database { .. } | Self::Cancelled { .. }
+        )
+    }
+}
+
+impl fmt::Display for CommandError {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        formatter.write_str(self.message())
+    }
+}
+
+impl std::error::Error for CommandError {}
+
+impl Serialize for CommandError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("CommandError", 6)?;
+        state.serialize_field("code", self.code())?;
+        state.serialize_field("title", &self.title())?;
+        state.serialize_field("message", self.message())?;
+        state.serialize_field("retryable", &self.retryable())?;
+        match self {
+            Self::InvalidDocx { path, .. } => state.serialize_field("path", path)?,
+            Self::NotIndexed { path, .. } => state.serialize_field("path", path)?,
+            Self::TargetLocked { path, .. } => state.serialize_field("path", path)?,
+            Self::AccessDenied { path, .. } => state.serialize_field("path", path)?,
+            Self::Validation { field, .. } => state.serialize_field("field", field)?,
+            Self::Cancelled { operation, .. } => state.serialize_field("operation", operation)?,
+            _ => {}
+        }
+        state.end()
+    }
+}
@@ -0,0 +1,250 @@
+use std::io::{Read, Write};
+use std::net::{Shutdown, TcpStream};
+use std::sync::atomic::{AtomicU64, Ordering as AtomicOrdering};
+use std::sync::mpsc::{sync_channel, SyncSender};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::Duration;
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use serde::Serialize;
+use serde_json::json;
+use sha1::{Digest, Sha1};
+
+use crate::util::now_ms;
+
+pub(crate) const FEED_EVENT_OPERATION: &str = "operation";
+pub(crate) const FEED_EVENT_CAPTURE: &str = "capture";
+pub(crate) const FEED_EVENT_SEARCH: &str = "search";
+
+const WEBSOCKET_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+/// Subscribers only send control frames, so anything larger is not a client
+/// we understand.
+const MAX_CLIENT_FRAME_BYTES: u64 = 64 * 1024;
+/// A subscriber whose writer cannot hand a frame to the socket this quickly
+/// is disconnected.
+const SUBSCRIBER_WRITE_TIMEOUT_MS: u64 = 2_000;
+/// Frames waiting for a subscriber's writer thread. A subscriber that falls
+/// this far behind is dropped instead of making publishers wait.
+const SUBSCRIBER_QUEUE_FRAMES: usize = 64;
+
+const OPCODE_TEXT: u8 = 0x1;
+const OPCODE_CLOSE: u8 = 0x8;
+const OPCODE_PING: u8 = 0x9;
+const OPCODE_PONG: u8 = 0xA;
+
+type Frame = Arc<[u8]>;
+
+/// Publishers only ever queue frames; the subscriber's own writer thread does
+/// the socket writes, so no I/O happens under the subscriber list lock.
+struct Subscriber {
+    id: u64,
+    frames: SyncSender<Frame>,
+    stream: TcpStream,
+}
+
+impl Subscriber {
+    /// Queues a frame without blocking. False when the subscriber is gone or
+    /// too far behind, in which case the caller drops it.
+    fn queue(&self, frame: &Frame) -> bool {
+        let queued = self.frames.try_send(Arc::clone(frame)).is_ok();
+        if !queued {
+            // Also ends the subscriber's read loop and writer thread.
+            let _ = self.stream.shutdown(Shutdown::Both);
+        }
+        queued
+    }
+}
+
+static SUBSCRIBERS: OnceLock<Mutex<Vec<Subscriber>>> = OnceLock::new();
+static NEXT_SUBSCRIBER_ID: AtomicU64 = AtomicU64::new(1);
+
+fn subscribers() -> &'static Mutex<Vec<Subscriber>> {
+    SUBSCRIBERS.get_or_init(|| Mutex::new(Vec::new()))
+}
+
+/// `Sec-WebSocket-Accept` for a client's `Sec-WebSocket-Key` (RFC 6455 §4.2.2).
+fn websocket_accept_key(client_key: &str) -> String {
+    STANDARD.encode(Sha1::digest(
+        format!("{}{WEBSOCKET_GUID}", client_key.trim()).as_bytes(),
+    ))
+}
+
+/// Builds an unmasked server frame; servers never mask.
+fn websocket_frame(opcode: u8, payload: &[u8]) -> Frame {
+    let mut frame = vec![0x80 | opcode];
+    match payload.len() {
+        length @ 0..=125 => frame.push(length as u8),
+        length @ 126..=0xFFFF => {
+            frame.push(126);
+            frame.extend_from_slice(&(length as u16).to_be_bytes());
+        }
+        length => {
+            frame.push(127);
+            frame.extend_from_slice(&(length as u64).to_be_bytes());
+        }
+    }
+    frame.extend_from_slice(payload);
+    frame.into()
+}
+
+/// Reads one client frame, returning its opcode and unmasked payload.
+fn read_client_frame(mut stream: &TcpStream) -> Option<(u8, Vec<u8>)> {
+    let mut header = [0_u8; 2];
+    stream.read_exact(&mut header).ok()?;
+    let opcode = header[0] & 0x0F;
+    let masked = header[1] & 0x80 != 0;
+    let length = match header[1] & 0x7F {
+        126 => {
+            let mut extended = [0_u8; 2];
+            stream.read_exact(&mut extended).ok()?;
+            u64::from(u16::from_be_bytes(extended))
+        }
+        127 => {
+            let mut extended = [0_u8; 8];
+            stream.read_exact(&mut extended).ok()?;
+            u64::from_be_bytes(extended)
+        }
+        length => u64::from(length),
+    };
+    if length > MAX_CLIENT_FRAME_BYTES {
+        return None;
+    }
+    let mut mask = [0_u8; 4];
+    if masked {
+        stream.read_exact(&mut mask).ok()?;
+    }
+    let mut payload = vec![0_u8; length as usize];
+    stream.read_exact(&mut payload).ok()?;
+    if masked {
+        for (index, byte) in payload.iter_mut().enumerate() {
+            *byte ^= mask[index % 4];
+        }
+    }
+    Some((opcode, payload))
+}
+
+/// Queues a frame for one subscriber behind anything already broadcast to it.
+fn send_to_subscriber(id: u64, frame: &Frame) {
+    if let Ok(mut subscribers) = subscribers().lock() {
+        if let Some(position) = subscribers
+            .iter()
+            .position(|subscriber| subscriber.id == id)
+        {
+            if !subscribers[position].queue(frame) {
+                subscribers.remove(position);
+            }
+        }
+    }
+}
+
+fn remove_subscriber(id: u64) {
+    if let Ok(mut subscribers) = subscribers().lock() {
+        subscribers.retain(|subscriber| subscriber.id != id);
+    }
+}
+
+/// Sends an event to every connected feed client as a JSON text frame of the
+/// form `{"event", "atMs", "data"}`. Cheap when nobody is listening.
+pub(crate) fn publish_event<T: Serialize>(event: &str, data: &T) {
+    let Ok(mut subscribers) = subscribers().lock() else {
+        return;
+    };
+    if subscribers.is_empty() {
+        return;
+    }
+    let Ok(data) = serde_json::to_value(data) else {
+        return;
+    };
+    let message = json!({ "event": event, "atMs": now_ms(), "data": data }).to_string();
+    let frame = websocket_frame(OPCODE_TEXT, message.as_bytes());
+    subscribers.retain(|subscriber| subscriber.queue(&frame));
+}
+
+/// Completes the WebSocket handshake on an authorized local API connection
+/// and keeps it subscribed until the client goes away. Runs on the
+/// connection's own thread.
+pub(crate) fn serve_event_subscriber(mut stream: TcpStream, client_key: &str) {
+    let handshake = format!(
+        "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Accept: {}\r\n\r\n",
+        websocket_accept_key(client_key)
+    );
+    if stream.write_all(handshake.as_bytes()).is_err() {
+        return;
+    }
+    let _ = stream.set_read_timeout(None);
+    let _ = stream.set_write_timeout(Some(Duration::from_millis(SUBSCRIBER_WRITE_TIMEOUT_MS)));
+    let (Ok(mut writer), Ok(handle)) = (stream.try_clone(), stream.try_clone()) else {
+        return;
+    };
+
+    let (frames, queued) = sync_channel::<Frame>(SUBSCRIBER_QUEUE_FRAMES);
+    let writer_thread = std::thread::spawn(move || {
+        for frame in queued {
+            if writer.write_all(&frame).is_err() {
+                let _ = writer.shutdown(Shutdown::Both);
+                break;
+            }
+        }
+    });
+    let id = NEXT_SUBSCRIBER_ID.fetch_add(1, AtomicOrdering::SeqCst);
+    if let Ok(mut subscribers) = subscribers().lock() {
+        subscribers.push(Subscriber {
+            id,
+            frames,
+            stream: handle,
+        });
+    }
+    let hello = json!({
+        "event": "hello",
+        "atMs": now_ms(),
+        "data": { "version": env!("CARGO_PKG_VERSION") },
+    })
+    .to_string();
+    send_to_subscriber(id, &websocket_frame(OPCODE_TEXT, hello.as_bytes()));
+
+    while let Some((opcode, payload)) = read_client_frame(&stream) {
+        match opcode {
+            OPCODE_CLOSE => {
+                send_to_subscriber(id, &websocket_frame(OPCODE_CLOSE, &payload));
+                break;
+            }
+            OPCODE_PING => send_to_subscriber(id, &websocket_frame(OPCODE_PONG, &payload)),
+            _ => {}
+        }
+    }
+    // Dropping the sender lets the writer flush what is queued (such as the
+    // close reply) and exit before the socket is closed.
+    remove_subscriber(id);
+    let _ = writer_thread.join();
+    let _ = stream.shutdown(Shutdown::Both);
+}
+
+/// Drops every feed client, e.g. when the local API stops or its token
+/// changes, so stale subscriptions do not outlive their authorization.
+pub(crate) fn disconnect_event_subscribers() {
+    if let Ok(mut subscribers) = subscribers().lock() {
+        for subscriber in subscribers.drain(..) {
+            let _ = subscriber.stream.shutdown(Shutdown::Both);
+        }
+    }
+}
+
+/// Announces a search from the app. Only the query and hit count are sent;
+/// the hits themselves stay in the app.
+pub(crate) fn publish_search_event(
+    mode: &str,
+    query: &str,
+    root_path: Option<&str>,
+    result_count: usize,
+) {
+    publish_event(
+        FEED_EVENT_SEARCH,
+        &json!({
+            "mode": mode,
+            "query": query,
+            "rootPath": root_path,
+            "resultCount": result_count,
+        }),
+    );
+}
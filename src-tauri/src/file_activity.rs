@@ -0,0 +1,135 @@
+use rusqlite::{params, Connection, OptionalExtension};
+
+use crate::errors::CommandError;
+use crate::types::ActivityFile;
+use crate::util::{file_name_from_relative, now_ms};
+use crate::CommandResult;
+
+/// Files listed under "recent" when the caller gives no limit.
+pub(crate) const DEFAULT_RECENT_FILES_LIMIT: usize = 20;
+
+/// Activity rows are keyed by file id and live in the main database, so they
+/// follow a file across shards; rows for files that have since left the
+/// index are skipped by the joins below rather than cleaned up.
+const ACTIVITY_SELECT: &str = "
+    SELECT
+      f.id,
+      f.relative_path,
+      f.absolute_path,
+      r.path,
+      a.open_count,
+      a.last_opened_ms,
+      a.pinned_at_ms
+    FROM file_activity a
+    JOIN files f ON f.id = a.file_id
+    JOIN roots r ON r.id = f.root_id
+";
+
+fn ensure_file_indexed(connection: &Connection, file_id: i64) -> CommandResult<()> {
+    connection
+        .query_row(
+            "SELECT 1 FROM files WHERE id = ?1",
+            params![file_id],
+            |_| Ok(()),
+        )
+        .optional()
+        .map_err(|error| CommandError::database(format!("Could not load file {file_id}: {error}")))?
+        .ok_or_else(|| CommandError::not_indexed(format!("File {file_id} is not indexed")))
+}
+
+pub(crate) fn record_file_open(connection: &Connection, file_id: i64) -> CommandResult<()> {
+    ensure_file_indexed(connection, file_id)?;
+    connection
+        .execute(
+            "INSERT INTO file_activity(file_id, open_count, last_opened_ms) VALUES(?1, 1, ?2)
+             ON CONFLICT(file_id) DO UPDATE SET
+               open_count = open_count + 1,
+               last_opened_ms = excluded.last_opened_ms",
+            params![file_id, now_ms()],
+        )
+        .map_err(|error| {
+            CommandError::database(format!("Could not record opening file {file_id}: {error}"))
+        })?;
+    Ok(())
+}
+
+/// Pins or unpins a file. Pinning an already pinned file keeps its place.
+pub(crate) fn set_file_pinned(
+    connection: &Connection,
+    file_id: i64,
+    pinned: bool,
+) -> CommandResult<()> {
+    ensure_file_indexed(connection, file_id)?;
+    let pinned_at_ms = pinned.then(now_ms);
+    connection
+        .execute(
+            "INSERT INTO file_activity(file_id, pinned_at_ms) VALUES(?1, ?2)
+             ON CONFLICT(file_id) DO UPDATE SET
+               pinned_at_ms = CASE
+                 WHEN excluded.pinned_at_ms IS NULL THEN NULL
+                 ELSE COALESCE(pinned_at_ms, excluded.pinned_at_ms)
+               END",
+            params![file_id, pinned_at_ms],
+        )
+        .map_err(|error| {
+            CommandError::database(format!("Could not update pin for file {file_id}: {error}"))
+        })?;
+    Ok(())
+}
+
+fn load_activity_files(
+    connection: &Connection,
+    conditions: &str,
+    limit: usize,
+) -> CommandResult<Vec<ActivityFile>> {
+    let mut statement = connection
+        .prepare_cached(&format!("{ACTIVITY_SELECT} {conditions} LIMIT ?1"))
+        .map_err(|error| {
+            CommandError::database(format!("Could not prepare file activity query: {error}"))
+        })?;
+    let rows = statement
+        .query_map(params![i64::try_from(limit).unwrap_or(i64::MAX)], |row| {
+            let relative_path = row.get::<_, String>(1)?;
+            Ok(ActivityFile {
+                file_id: row.get(0)?,
+                file_name: file_name_from_relative(&relative_path),
+                relative_path,
+                absolute_path: row.get(2)?,
+                root_path: row.get(3)?,
+                open_count: row.get(4)?,
+                last_opened_ms: row.get(5)?,
+                pinned: row.get::<_, Option<i64>>(6)?.is_some(),
+            })
+        })
+        .map_err(|error| {
+            CommandError::database(format!("Could not load file activity: {error}"))
+        })?;
+    let mut files = Vec::new();
+    for row in rows {
+        files.push(row.map_err(|error| {
+            CommandError::database(format!("Could not parse file activity row: {error}"))
+        })?);
+    }
+    Ok(files)
+}
+
+/// Most recently opened files first.
+pub(crate) fn load_recent_files(
+    connection: &Connection,
+    limit: usize,
+) -> CommandResult<Vec<ActivityFile>> {
+    load_activity_files(
+        connection,
+        "WHERE a.last_opened_ms IS NOT NULL ORDER BY a.last_opened_ms DESC",
+        limit,
+    )
+}
+
+/// Pinned files in the order they were pinned.
+pub(crate) fn load_pinned_files(connection: &Connection) -> CommandResult<Vec<ActivityFile>> {
+    load_activity_files(
+        connection,
+        "WHERE a.pinned_at_ms IS NOT NULL ORDER BY a.pinned_at_ms, f.relative_path",
+        usize::MAX,
+    )
+}
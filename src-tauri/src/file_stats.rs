@@ -0,0 +1,478 @@
+use std::collections::HashSet;
+use std::path::PathBuf;
+
+use rayon::prelude::*;
+use rusqlite::{params, Connection, OptionalExtension};
+
+use crate::docx_parse::parse_docx_paragraphs;
+use crate::errors::CommandError;
+use crate::messages;
+use crate::operations::OperationHandle;
+use crate::shards::schema_for_file;
+use crate::types::{
+    BodyParagraph, FileStatsEntry, HeadingWordCount, ParsedParagraph, RootStats, SearchHit,
+    SpeechLengthEstimate,
+};
+use crate::util::file_name_from_relative;
+use crate::CommandResult;
+
+/// Files handled per batch in the stats phase, so cancellation is noticed
+/// between batches.
+const STATS_PHASE_BATCH_SIZE: usize = 256;
+
+/// Files reparsed per batch in the word count phase.
+const WORD_COUNT_PHASE_BATCH_SIZE: usize = 64;
+
+/// Files listed in a root's "largest files".
+const LARGEST_FILES_LIMIT: i64 = 10;
+
+/// Reading pace used when none is given.
+pub(crate) const DEFAULT_WORDS_PER_MINUTE: f64 = 250.0;
+
+/// Counts kept per file so root totals are a sum rather than a reparse. A
+/// card is a heading with body text directly under it.
+#[derive(Default)]
+pub(crate) struct FileStats {
+    pub word_count: usize,
+    pub card_count: usize,
+    pub card_word_count: usize,
+    pub cite_count: usize,
+}
+
+pub(crate) fn file_stats(body: &[BodyParagraph], cite_count: usize) -> FileStats {
+    let mut stats = FileStats {
+        cite_count,
+        ..FileStats::default()
+    };
+    let mut cards = HashSet::new();
+    for paragraph in body {
+        let words = paragraph.text.split_whitespace().count();
+        stats.word_count += words;
+        if let Some(heading_order) = paragraph.heading_order {
+            cards.insert(heading_order);
+            stats.card_word_count += words;
+        }
+    }
+    stats.card_count = cards.len();
+    stats
+}
+
+fn count_value(count: usize) -> i64 {
+    i64::try_from(count).unwrap_or(i64::MAX)
+}
+
+pub(crate) fn store_file_stats(
+    connection: &Connection,
+    file_id: i64,
+    stats: &FileStats,
+) -> CommandResult<()> {
+    let schema = schema_for_file(file_id);
+    connection
+        .prepare_cached(&format!(
+            "INSERT INTO {schema}.file_stats(file_id, word_count, card_count, card_word_count, cite_count)
+             VALUES(?1, ?2, ?3, ?4, ?5)
+             ON CONFLICT(file_id) DO UPDATE SET
+               word_count = excluded.word_count,
+               card_count = excluded.card_count,
+               card_word_count = excluded.card_word_count,
+               cite_count = excluded.cite_count"
+        ))
+        .and_then(|mut statement| {
+            statement.execute(params![
+                file_id,
+                count_value(stats.word_count),
+                count_value(stats.card_count),
+                count_value(stats.card_word_count),
+                count_value(stats.cite_count)
+            ])
+        })
+        .map_err(|error| {
+            CommandError::database(format!(
+                "Could not store stats for file {file_id}: {error}"
+            ))
+        })?;
+    Ok(())
+}
+
+/// Word and underlined-word counts for each heading in `paragraphs`, counted
+/// over the body paragraphs up to the next heading of any level, the same
+/// span `file_stats` treats as a card.
+pub(crate) fn heading_word_counts(paragraphs: &[ParsedParagraph]) -> Vec<HeadingWordCount> {
+    let mut counts = Vec::<HeadingWordCount>::new();
+    for paragraph in paragraphs {
+        if paragraph.heading_level.is_some() {
+            counts.push(HeadingWordCount {
+                heading_order: paragraph.order,
+                word_count: 0,
+                underlined_word_count: 0,
+            });
+        } else if let Some(current) = counts.last_mut() {
+            current.word_count += paragraph.text.split_whitespace().count();
+            current.underlined_word_count += paragraph.underlined_word_count;
+        }
+    }
+    counts
+}
+
+/// Stores each heading's word counts. Call after the heading rows are in
+/// place.
+pub(crate) fn store_heading_word_counts(
+    connection: &Connection,
+    file_id: i64,
+    counts: &[HeadingWordCount],
+) -> CommandResult<()> {
+    let schema = schema_for_file(file_id);
+    let mut update_counts = connection
+        .prepare_cached(&format!(
+            "UPDATE {schema}.headings SET word_count = ?1, underlined_word_count = ?2
+             WHERE file_id = ?3 AND heading_order = ?4"
+        ))
+        .map_err(|error| {
+            CommandError::database(format!(
+                "Could not prepare heading word count update: {error}"
+            ))
+        })?;
+    for count in counts {
+        update_counts
+            .execute(params![
+                count_value(count.word_count),
+                count_value(count.underlined_word_count),
+                file_id,
+                count.heading_order
+            ])
+            .map_err(|error| {
+                CommandError::database(format!(
+                    "Could not store heading word counts for file {file_id}: {error}"
+                ))
+            })?;
+    }
+    Ok(())
+}
+
+/// Sets the word counts of hits that point at a heading.
+pub(crate) fn attach_heading_word_counts(
+    connection: &Connection,
+    hits: &mut [SearchHit],
+) -> CommandResult<()> {
+    let mut statement = connection
+        .prepare_cached(
+            "SELECT word_count, underlined_word_count FROM headings
+             WHERE file_id = ?1 AND heading_order = ?2",
+        )
+        .map_err(|error| {
+            CommandError::database(format!(
+                "Could not prepare heading word count query: {error}"
+            ))
+        })?;
+    for hit in hits.iter_mut() {
+        let Some(heading_order) = hit.heading_order else {
+            continue;
+        };
+        let counts = statement
+            .query_row(params![hit.file_id, heading_order], |row| {
+                Ok((row.get::<_, Option<i64>>(0)?, row.get::<_, Option<i64>>(1)?))
+            })
+            .optional()
+            .map_err(|error| {
+                CommandError::database(format!("Could not load heading word counts: {error}"))
+            })?;
+        if let Some((word_count, underlined_word_count)) = counts {
+            hit.word_count = word_count;
+            hit.underlined_word_count = underlined_word_count;
+        }
+    }
+    Ok(())
+}
+
+/// How long `word_count` words take to read at `words_per_minute`, in full
+/// and counting only the underlined words, which is what gets read aloud
+/// from a highlighted card.
+pub(crate) fn speech_length_estimate(
+    word_count: usize,
+    underlined_word_count: usize,
+    words_per_minute: f64,
+) -> SpeechLengthEstimate {
+    let seconds = |words: usize| words as f64 * 60.0 / words_per_minute;
+    SpeechLengthEstimate {
+        words_per_minute,
+        word_count,
+        underlined_word_count,
+        seconds: seconds(word_count),
+        underlined_seconds: seconds(underlined_word_count),
+    }
+}
+
+/// The stored word counts of the given headings in a file, summed. Headings
+/// without counts, from before they were kept, are reported as not indexed.
+pub(crate) fn load_heading_word_counts(
+    connection: &Connection,
+    file_id: i64,
+    heading_orders: &[i64],
+) -> CommandResult<(usize, usize)> {
+    let mut statement = connection
+        .prepare_cached(
+            "SELECT word_count, underlined_word_count FROM headings
+             WHERE file_id = ?1 AND heading_order = ?2",
+        )
+        .map_err(|error| {
+            CommandError::database(format!(
+                "Could not prepare heading word count query: {error}"
+            ))
+        })?;
+    let mut totals = (0, 0);
+    for heading_order in heading_orders {
+        let counts = statement
+            .query_row(params![file_id, heading_order], |row| {
+                Ok((row.get::<_, Option<i64>>(0)?, row.get::<_, Option<i64>>(1)?))
+            })
+            .optional()
+            .map_err(|error| {
+                CommandError::database(format!("Could not load heading word counts: {error}"))
+            })?;
+        let (word_count, underlined_word_count) = match counts {
+            Some((Some(word_count), Some(underlined_word_count))) => {
+                (word_count, underlined_word_count)
+            }
+            Some(_) => {
+                return Err(CommandError::not_indexed(format!(
+                    "Word counts for heading {heading_order} have not been counted yet. Index the folder again to count them."
+                )))
+            }
+            None => {
+                return Err(CommandError::not_indexed(messages::message(
+                    "error.headingGone",
+                )))
+            }
+        };
+        totals.0 += usize::try_from(word_count).unwrap_or(0);
+        totals.1 += usize::try_from(underlined_word_count).unwrap_or(0);
+    }
+    Ok(totals)
+}
+
+fn load_body_rows(connection: &Connection, file_id: i64) -> CommandResult<Vec<BodyParagraph>> {
+    let mut statement = connection
+        .prepare_cached(
+            "SELECT paragraph_order, heading_order, text FROM body_paragraphs
+             WHERE file_id = ?1 ORDER BY paragraph_order",
+        )
+        .map_err(|error| {
+            CommandError::database(format!("Could not prepare stats body query: {error}"))
+        })?;
+    let rows = statement
+        .query_map(params![file_id], |row| {
+            Ok(BodyParagraph {
+                paragraph_order: row.get(0)?,
+                heading_order: row.get(1)?,
+                text: row.get(2)?,
+            })
+        })
+        .map_err(|error| CommandError::database(format!("Could not load stats body: {error}")))?;
+    let mut body = Vec::new();
+    for row in rows {
+        body.push(row.map_err(|error| {
+            CommandError::database(format!("Could not parse stats body row: {error}"))
+        })?);
+    }
+    Ok(body)
+}
+
+/// Fills in stats for files indexed before they were kept, from the body and
+/// cite rows already in the index. Run after the body and cite phases.
+pub(crate) fn run_stats_phase(
+    connection: &Connection,
+    root_id: i64,
+    operation: &OperationHandle,
+) -> CommandResult<usize> {
+    let mut statement = connection
+        .prepare(
+            "SELECT id FROM files f
+             WHERE root_id = ?1
+               AND NOT EXISTS(SELECT 1 FROM file_stats s WHERE s.file_id = f.id)",
+        )
+        .map_err(|error| {
+            CommandError::database(format!("Could not prepare pending stats query: {error}"))
+        })?;
+    let rows = statement
+        .query_map(params![root_id], |row| row.get::<_, i64>(0))
+        .map_err(|error| {
+            CommandError::database(format!("Could not run pending stats query: {error}"))
+        })?;
+    let mut pending = Vec::new();
+    for row in rows {
+        pending.push(row.map_err(|error| {
+            CommandError::database(format!("Could not parse pending stats row: {error}"))
+        })?);
+    }
+
+    let mut count_cites = connection
+        .prepare_cached("SELECT COUNT(*) FROM cites WHERE file_id = ?1")
+        .map_err(|error| {
+            CommandError::database(format!("Could not prepare stats cite query: {error}"))
+        })?;
+    for batch in pending.chunks(STATS_PHASE_BATCH_SIZE) {
+        operation.ensure_not_cancelled()?;
+        for file_id in batch {
+            let body = load_body_rows(connection, *file_id)?;
+            let cite_count = count_cites
+                .query_row(params![file_id], |row| row.get::<_, i64>(0))
+                .map_err(|error| {
+                    CommandError::database(format!("Could not count cites: {error}"))
+                })?;
+            let stats = file_stats(&body, usize::try_from(cite_count).unwrap_or(0));
+            store_file_stats(connection, *file_id, &stats)?;
+        }
+    }
+    Ok(pending.len())
+}
+
+/// Counts words under headings indexed before word counts were kept. Their
+/// files are unchanged, so indexing skips them; this reparses just those files.
+pub(crate) fn run_word_count_phase(
+    connection: &Connection,
+    root_id: i64,
+    operation: &OperationHandle,
+) -> CommandResult<usize> {
+    let mut statement = connection
+        .prepare(
+            "SELECT id, absolute_path FROM files f
+             WHERE root_id = ?1
+               AND EXISTS(SELECT 1 FROM headings h WHERE h.file_id = f.id AND h.word_count IS NULL)",
+        )
+        .map_err(|error| {
+            CommandError::database(format!("Could not prepare pending word count query: {error}"))
+        })?;
+    let rows = statement
+        .query_map(params![root_id], |row| {
+            Ok((
+                row.get::<_, i64>(0)?,
+                PathBuf::from(row.get::<_, String>(1)?),
+            ))
+        })
+        .map_err(|error| {
+            CommandError::database(format!("Could not run pending word count query: {error}"))
+        })?;
+    let mut pending = Vec::new();
+    for row in rows {
+        pending.push(row.map_err(|error| {
+            CommandError::database(format!("Could not parse pending word count row: {error}"))
+        })?);
+    }
+
+    for batch in pending.chunks(WORD_COUNT_PHASE_BATCH_SIZE) {
+        operation.ensure_not_cancelled()?;
+        let counted = batch
+            .par_iter()
+            .map(|(_, absolute_path)| {
+                parse_docx_paragraphs(absolute_path)
+                    .ok()
+                    .map(|paragraphs| heading_word_counts(&paragraphs))
+            })
+            .collect::<Vec<Option<Vec<HeadingWordCount>>>>();
+        for ((file_id, _), counts) in batch.iter().zip(counted) {
+            // An unreadable file keeps its gaps and is retried next run.
+            let Some(counts) = counts else {
+                continue;
+            };
+            store_heading_word_counts(connection, *file_id, &counts)?;
+            // A heading the parse did not place has nothing under it.
+            connection
+                .execute(
+                    &format!(
+                        "UPDATE {}.headings SET word_count = 0, underlined_word_count = 0
+                         WHERE file_id = ?1 AND word_count IS NULL",
+                        schema_for_file(*file_id)
+                    ),
+                    params![file_id],
+                )
+                .map_err(|error| {
+                    CommandError::database(format!(
+                        "Could not store heading word counts for file {file_id}: {error}"
+                    ))
+                })?;
+        }
+    }
+    Ok(pending.len())
+}
+
+pub(crate) fn load_root_stats(
+    connection: &Connection,
+    root_id: i64,
+    root_path: &str,
+) -> CommandResult<RootStats> {
+    let (file_count, word_count, card_count, card_word_count, cite_count) = connection
+        .query_row(
+            "
+            SELECT
+              COUNT(*),
+              COALESCE(SUM(s.word_count), 0),
+              COALESCE(SUM(s.card_count), 0),
+              COALESCE(SUM(s.card_word_count), 0),
+              COALESCE(SUM(s.cite_count), 0)
+            FROM files f
+            LEFT JOIN file_stats s ON s.file_id = f.id
+            WHERE f.root_id = ?1
+            ",
+            params![root_id],
+            |row| {
+                Ok((
+                    row.get::<_, i64>(0)?,
+                    row.get::<_, i64>(1)?,
+                    row.get::<_, i64>(2)?,
+                    row.get::<_, i64>(3)?,
+                    row.get::<_, i64>(4)?,
+                ))
+            },
+        )
+        .map_err(|error| CommandError::database(format!("Could not load root stats: {error}")))?;
+
+    let mut statement = connection
+        .prepare_cached(
+            "
+            SELECT f.id, f.relative_path, s.word_count, s.card_count, s.cite_count
+            FROM file_stats s
+            JOIN files f ON f.id = s.file_id
+            WHERE f.root_id = ?1
+            ORDER BY s.word_count DESC, f.relative_path
+            LIMIT ?2
+            ",
+        )
+        .map_err(|error| {
+            CommandError::database(format!("Could not prepare largest files query: {error}"))
+        })?;
+    let rows = statement
+        .query_map(params![root_id, LARGEST_FILES_LIMIT], |row| {
+            let relative_path = row.get::<_, String>(1)?;
+            Ok(FileStatsEntry {
+                file_id: row.get(0)?,
+                file_name: file_name_from_relative(&relative_path),
+                relative_path,
+                word_count: row.get(2)?,
+                card_count: row.get(3)?,
+                cite_count: row.get(4)?,
+            })
+        })
+        .map_err(|error| {
+            CommandError::database(format!("Could not load largest files: {error}"))
+        })?;
+    let mut largest_files = Vec::new();
+    for row in rows {
+        largest_files.push(row.map_err(|error| {
+            CommandError::database(format!("Could not parse largest file row: {error}"))
+        })?);
+    }
+
+    Ok(RootStats {
+        root_path: root_path.to_string(),
+        file_count,
+        word_count,
+        card_count,
+        cite_count,
+        average_card_words: if card_count > 0 {
+            card_word_count as f64 / card_count as f64
+        } else {
+            0.0
+        },
+        largest_files,
+    })
+}
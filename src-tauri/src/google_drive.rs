@@ -0,0 +1,571 @@
+use std::fs;
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::Path;
+use std::time::Duration;
+
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use tauri::AppHandle;
+use tauri_plugin_opener::OpenerExt;
+
+use crate::db::open_database;
+use crate::errors::CommandError;
+use crate::operations::OperationHandle;
+use crate::types::{DriveUploadResult, GoogleDriveStatus};
+use crate::util::{form_encode, now_ms, path_display, query_param, random_token};
+use crate::CommandResult;
+
+const GOOGLE_AUTH_URL: &str = "https://accounts.google.com/o/oauth2/v2/auth";
+const GOOGLE_TOKEN_URL: &str = "https://oauth2.googleapis.com/token";
+const DRIVE_UPLOAD_URL: &str =
+    "https://www.googleapis.com/upload/drive/v3/files?uploadType=multipart&fields=id,name,webViewLink";
+/// Only files created by the app are visible to it, which keeps the consent
+/// screen narrow.
+const DRIVE_SCOPE: &str = "https://www.googleapis.com/auth/drive.file";
+const GOOGLE_DOC_MIME: &str = "application/vnd.google-apps.document";
+const DOCX_MIME: &str = "application/vnd.openxmlformats-officedocument.wordprocessingml.document";
+const DRIVE_REQUEST_TIMEOUT_SECS: u64 = 120;
+const OAUTH_CALLBACK_TIMEOUT_MS: i64 = 5 * 60 * 1_000;
+const ACCESS_TOKEN_REFRESH_MARGIN_MS: i64 = 60_000;
+const KEYCHAIN_SERVICE: &str = "BlockFile Google Drive";
+
+/// Secrets for the Drive connection. They live in the OS keychain under the
+/// row's `secret_account`, never in the index database.
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct DriveSecrets {
+    client_secret: String,
+    refresh_token: Option<String>,
+    access_token: Option<String>,
+    access_expires_at_ms: i64,
+}
+
+struct DriveAuth {
+    client_id: String,
+    secret_account: String,
+    secrets: DriveSecrets,
+}
+
+fn keychain_entry(secret_account: &str) -> CommandResult<keyring::Entry> {
+    keyring::Entry::new(KEYCHAIN_SERVICE, secret_account).map_err(|error| {
+        CommandError::internal(format!("Could not open the system keychain: {error}"))
+    })
+}
+
+fn load_drive_secrets(secret_account: &str) -> CommandResult<DriveSecrets> {
+    match keychain_entry(secret_account)?.get_password() {
+        Ok(raw) => serde_json::from_str::<DriveSecrets>(&raw).map_err(|error| {
+            CommandError::parse(format!(
+                "Could not read Google Drive secrets from the keychain: {error}"
+            ))
+        }),
+        Err(keyring::Error::NoEntry) => Ok(DriveSecrets::default()),
+        Err(error) => Err(CommandError::internal(format!(
+            "Could not read Google Drive secrets from the keychain: {error}"
+        ))),
+    }
+}
+
+fn store_drive_secrets(secret_account: &str, secrets: &DriveSecrets) -> CommandResult<()> {
+    let raw = serde_json::to_string(secrets).map_err(|error| {
+        CommandError::parse(format!("Could not serialize Google Drive secrets: {error}"))
+    })?;
+    keychain_entry(secret_account)?
+        .set_password(&raw)
+        .map_err(|error| {
+            CommandError::internal(format!(
+                "Could not save Google Drive secrets to the keychain: {error}"
+            ))
+        })
+}
+
+/// Moves secrets stored in the database by earlier versions into the
+/// keychain and blanks the columns that held them.
+fn migrate_plaintext_secrets(
+    connection: &Connection,
+    secrets: DriveSecrets,
+) -> CommandResult<String> {
+    let secret_account = random_token()?;
+    store_drive_secrets(&secret_account, &secrets)?;
+    connection
+        .execute(
+            "
+            UPDATE drive_auth
+            SET secret_account = ?1, client_secret = '', refresh_token = NULL,
+                access_token = NULL, access_expires_at_ms = NULL
+            WHERE id = 1
+            ",
+            params![secret_account],
+        )
+        .map_err(|error| {
+            CommandError::database(format!(
+                "Could not clear stored Google Drive secrets: {error}"
+            ))
+        })?;
+    Ok(secret_account)
+}
+
+fn load_drive_auth(connection: &Connection) -> CommandResult<Option<DriveAuth>> {
+    let row = connection
+        .query_row(
+            "
+            SELECT client_id, secret_account, client_secret, refresh_token, access_token,
+                   access_expires_at_ms
+            FROM drive_auth
+            WHERE id = 1
+            ",
+            [],
+            |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, Option<String>>(1)?,
+                    DriveSecrets {
+                        client_secret: row.get(2)?,
+                        refresh_token: row.get(3)?,
+                        access_token: row.get(4)?,
+                        access_expires_at_ms: row.get::<_, Option<i64>>(5)?.unwrap_or(0),
+                    },
+                ))
+            },
+        )
+        .optional()
+        .map_err(|error| {
+            CommandError::database(format!("Could not load Google Drive settings: {error}"))
+        })?;
+    let Some((client_id, secret_account, legacy_secrets)) = row else {
+        return Ok(None);
+    };
+    let (secret_account, secrets) = match secret_account {
+        Some(secret_account) => {
+            let secrets = load_drive_secrets(&secret_account)?;
+            (secret_account, secrets)
+        }
+        None => (
+            migrate_plaintext_secrets(connection, legacy_secrets.clone())?,
+            legacy_secrets,
+        ),
+    };
+    Ok(Some(DriveAuth {
+        client_id,
+        secret_account,
+        secrets,
+    }))
+}
+
+pub(crate) fn drive_status(connection: &Connection) -> CommandResult<GoogleDriveStatus> {
+    let auth = load_drive_auth(connection)?;
+    Ok(GoogleDriveStatus {
+        configured: auth.is_some(),
+        connected: auth
+            .as_ref()
+            .map(|auth| auth.secrets.refresh_token.is_some())
+            .unwrap_or(false),
+        client_id: auth.map(|auth| auth.client_id),
+    })
+}
+
+/// Stores the OAuth desktop client. Changing the client drops any tokens
+/// issued to the previous one.
+pub(crate) fn store_drive_client(
+    connection: &Connection,
+    client_id: &str,
+    client_secret: &str,
+) -> CommandResult<()> {
+    let existing = load_drive_auth(connection)?;
+    let (secret_account, mut secrets) = match existing {
+        Some(auth) if auth.client_id == client_id => (auth.secret_account, auth.secrets),
+        Some(auth) => (auth.secret_account, DriveSecrets::default()),
+        None => (random_token()?, DriveSecrets::default()),
+    };
+    secrets.client_secret = client_secret.to_string();
+    store_drive_secrets(&secret_account, &secrets)?;
+    connection
+        .execute(
+            "
+            INSERT INTO drive_auth(id, client_id, client_secret, secret_account, updated_at_ms)
+            VALUES(1, ?1, '', ?2, ?3)
+            ON CONFLICT(id) DO UPDATE SET
+              client_id = excluded.client_id,
+              secret_account = excluded.secret_account,
+              updated_at_ms = excluded.updated_at_ms
+            ",
+            params![client_id, secret_account, now_ms()],
+        )
+        .map_err(|error| {
+            CommandError::database(format!("Could not store Google Drive client: {error}"))
+        })?;
+    Ok(())
+}
+
+pub(crate) fn clear_drive_tokens(connection: &Connection) -> CommandResult<()> {
+    let Some(auth) = load_drive_auth(connection)? else {
+        return Ok(());
+    };
+    store_drive_secrets(
+        &auth.secret_account,
+        &DriveSecrets {
+            refresh_token: None,
+            access_token: None,
+            access_expires_at_ms: 0,
+            ..auth.secrets
+        },
+    )?;
+    touch_drive_auth(connection)
+}
+
+fn store_drive_tokens(
+    connection: &Connection,
+    auth: &DriveAuth,
+    refresh_token: Option<&str>,
+    access_token: &str,
+    expires_in_secs: i64,
+) -> CommandResult<()> {
+    store_drive_secrets(
+        &auth.secret_account,
+        &DriveSecrets {
+            client_secret: auth.secrets.client_secret.clone(),
+            refresh_token: refresh_token
+                .map(str::to_string)
+                .or_else(|| auth.secrets.refresh_token.clone()),
+            access_token: Some(access_token.to_string()),
+            access_expires_at_ms: now_ms() + expires_in_secs * 1_000,
+        },
+    )?;
+    touch_drive_auth(connection)
+}
+
+fn touch_drive_auth(connection: &Connection) -> CommandResult<()> {
+    connection
+        .execute(
+            "UPDATE drive_auth SET updated_at_ms = ?1 WHERE id = 1",
+            params![now_ms()],
+        )
+        .map_err(|error| {
+            CommandError::database(format!("Could not update Google Drive settings: {error}"))
+        })?;
+    Ok(())
+}
+
+/// The S256 PKCE challenge for `verifier`: its SHA-256, base64url without
+/// padding.
+fn pkce_challenge(verifier: &str) -> String {
+    URL_SAFE_NO_PAD.encode(Sha256::digest(verifier.as_bytes()))
+}
+
+fn drive_agent() -> ureq::Agent {
+    ureq::Agent::config_builder()
+        .timeout_global(Some(Duration::from_secs(DRIVE_REQUEST_TIMEOUT_SECS)))
+        .http_status_as_error(false)
+        .build()
+        .into()
+}
+
+/// Sends a request and parses the JSON reply, turning Google's
+/// `{"error": ...}` bodies into readable messages.
+fn read_json_response(
+    response: Result<ureq::http::Response<ureq::Body>, ureq::Error>,
+    context: &str,
+) -> CommandResult<Value> {
+    let mut response =
+        response.map_err(|error| CommandError::io(format!("Could not {context}: {error}")))?;
+    let status = response.status().as_u16();
+    let raw = response.body_mut().read_to_string().map_err(|error| {
+        CommandError::io(format!("Could not read response to {context}: {error}"))
+    })?;
+    let body = serde_json::from_str::<Value>(&raw).unwrap_or(Value::Null);
+    if status >= 400 {
+        let detail = body
+            .get("error_description")
+            .or_else(|| body.pointer("/error/message"))
+            .or_else(|| body.get("error"))
+            .and_then(Value::as_str)
+            .unwrap_or(raw.trim());
+        return Err(CommandError::io(format!(
+            "Could not {context} (HTTP {status}): {detail}"
+        )));
+    }
+    Ok(body)
+}
+
+fn post_token_form(
+    agent: &ureq::Agent,
+    fields: &[(&str, &str)],
+    context: &str,
+) -> CommandResult<Value> {
+    let body = fields
+        .iter()
+        .map(|(key, value)| format!("{key}={}", form_encode(value)))
+        .collect::<Vec<String>>()
+        .join("&");
+    read_json_response(
+        agent
+            .post(GOOGLE_TOKEN_URL)
+            .header("Content-Type", "application/x-www-form-urlencoded")
+            .send(body.as_bytes()),
+        context,
+    )
+}
+
+fn respond_to_browser(stream: &mut TcpStream, message: &str) {
+    let body = format!(
+        "<!DOCTYPE html><html><body style=\"font-family:sans-serif;padding:40px\"><p>{message}</p></body></html>"
+    );
+    let _ = stream.write_all(
+        format!(
+            "HTTP/1.1 200 OK\r\nContent-Type: text/html; charset=utf-8\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+            body.len()
+        )
+        .as_bytes(),
+    );
+}
+
+/// Waits on the loopback listener for Google's redirect and returns the
+/// authorization code. Requests without the expected `state` are ignored.
+fn await_authorization_code(
+    listener: &TcpListener,
+    expected_state: &str,
+    operation: &OperationHandle,
+) -> CommandResult<String> {
+    listener.set_nonblocking(true).map_err(|error| {
+        CommandError::io(format!(
+            "Could not configure OAuth callback listener: {error}"
+        ))
+    })?;
+    let deadline = now_ms() + OAUTH_CALLBACK_TIMEOUT_MS;
+    loop {
+        operation.ensure_not_cancelled()?;
+        if now_ms() > deadline {
+            return Err(CommandError::io("Timed out waiting for Google sign-in."));
+        }
+
+        let mut stream = match listener.accept() {
+            Ok((stream, _)) => stream,
+            Err(error) if error.kind() == std::io::ErrorKind::WouldBlock => {
+                std::thread::sleep(Duration::from_millis(150));
+                continue;
+            }
+            Err(error) => {
+                return Err(CommandError::io(format!(
+                    "OAuth callback listener failed: {error}"
+                )))
+            }
+        };
+        let _ = stream.set_nonblocking(false);
+        let _ = stream.set_read_timeout(Some(Duration::from_secs(5)));
+        let mut buffer = [0_u8; 8192];
+        let read = stream.read(&mut buffer).unwrap_or(0);
+        let request = String::from_utf8_lossy(&buffer[..read]);
+        let target = request
+            .lines()
+            .next()
+            .and_then(|line| line.split_whitespace().nth(1))
+            .unwrap_or("");
+        let query = target.split_once('?').map(|(_, query)| query).unwrap_or("");
+
+        if query_param(query, "state").as_deref() != Some(expected_state) {
+            respond_to_browser(&mut stream, "This request did not come from BlockFile.");
+            continue;
+        }
+        if let Some(error) = query_param(query, "error") {
+            respond_to_browser(
+                &mut stream,
+                "Google sign-in was not completed. You can close this tab.",
+            );
+            return Err(CommandError::io(format!("Google sign-in failed: {error}")));
+        }
+        if let Some(code) = query_param(query, "code") {
+            respond_to_browser(
+                &mut stream,
+                "BlockFile is connected to Google Drive. You can close this tab.",
+            );
+            return Ok(code);
+        }
+        respond_to_browser(&mut stream, "Missing authorization code.");
+    }
+}
+
+/// Runs the installed-app OAuth flow: opens the consent page in the browser,
+/// receives the redirect on a loopback port, and stores the refresh token.
+pub(crate) fn connect_drive(app: &AppHandle, operation: &mut OperationHandle) -> CommandResult<()> {
+    let connection = open_database(app)?;
+    let auth = load_drive_auth(&connection)?.ok_or_else(|| {
+        CommandError::validation("Add a Google OAuth client ID before connecting Drive.")
+    })?;
+
+    let listener = TcpListener::bind("127.0.0.1:0").map_err(|error| {
+        CommandError::io(format!("Could not open OAuth callback listener: {error}"))
+    })?;
+    let port = listener
+        .local_addr()
+        .map_err(|error| CommandError::io(format!("Could not read OAuth callback port: {error}")))?
+        .port();
+    let redirect_uri = format!("http://127.0.0.1:{port}");
+    let state = random_token()?;
+    // The verifier is 64 hex characters, within PKCE's unreserved 43-128.
+    let code_verifier = random_token()?;
+    let auth_url = format!(
+        "{GOOGLE_AUTH_URL}?client_id={}&redirect_uri={}&response_type=code&scope={}&access_type=offline&prompt=consent&state={state}&code_challenge={}&code_challenge_method=S256",
+        form_encode(&auth.client_id),
+        form_encode(&redirect_uri),
+        form_encode(DRIVE_SCOPE),
+        pkce_challenge(&code_verifier),
+    );
+
+    operation.update("awaiting-consent", 0, None, None);
+    app.opener()
+        .open_url(auth_url, None::<&str>)
+        .map_err(|error| {
+            CommandError::io(format!("Could not open the Google sign-in page: {error}"))
+        })?;
+    let code = await_authorization_code(&listener, &state, operation)?;
+
+    operation.update("exchanging", 0, None, None);
+    let tokens = post_token_form(
+        &drive_agent(),
+        &[
+            ("code", code.as_str()),
+            ("code_verifier", code_verifier.as_str()),
+            ("client_id", auth.client_id.as_str()),
+            ("client_secret", auth.secrets.client_secret.as_str()),
+            ("redirect_uri", redirect_uri.as_str()),
+            ("grant_type", "authorization_code"),
+        ],
+        "exchange the Google authorization code",
+    )?;
+    let access_token = tokens
+        .get("access_token")
+        .and_then(Value::as_str)
+        .ok_or_else(|| CommandError::io("Google did not return an access token."))?;
+    let refresh_token = tokens.get("refresh_token").and_then(Value::as_str);
+    if refresh_token.is_none() && auth.secrets.refresh_token.is_none() {
+        return Err(
+            CommandError::validation(
+                "Google did not return a refresh token. Remove BlockFile's access in your Google account and connect again.",
+            )
+        );
+    }
+    let expires_in = tokens
+        .get("expires_in")
+        .and_then(Value::as_i64)
+        .unwrap_or(3_600);
+    store_drive_tokens(&connection, &auth, refresh_token, access_token, expires_in)
+}
+
+fn valid_access_token(connection: &Connection, agent: &ureq::Agent) -> CommandResult<String> {
+    let auth = load_drive_auth(connection)?
+        .ok_or_else(|| CommandError::validation("Google Drive is not configured."))?;
+    let refresh_token = auth.secrets.refresh_token.clone().ok_or_else(|| {
+        CommandError::validation("Google Drive is not connected. Connect it first.")
+    })?;
+    if let Some(access_token) = &auth.secrets.access_token {
+        if auth.secrets.access_expires_at_ms - ACCESS_TOKEN_REFRESH_MARGIN_MS > now_ms() {
+            return Ok(access_token.clone());
+        }
+    }
+
+    let tokens = post_token_form(
+        agent,
+        &[
+            ("refresh_token", refresh_token.as_str()),
+            ("client_id", auth.client_id.as_str()),
+            ("client_secret", auth.secrets.client_secret.as_str()),
+            ("grant_type", "refresh_token"),
+        ],
+        "refresh the Google Drive access token",
+    )?;
+    let access_token = tokens
+        .get("access_token")
+        .and_then(Value::as_str)
+        .ok_or_else(|| CommandError::io("Google did not return an access token."))?
+        .to_string();
+    let expires_in = tokens
+        .get("expires_in")
+        .and_then(Value::as_i64)
+        .unwrap_or(3_600);
+    store_drive_tokens(connection, &auth, None, &access_token, expires_in)?;
+    Ok(access_token)
+}
+
+/// Uploads a docx to Drive with a multipart request. With `convert`, Drive
+/// stores it as a Google Doc instead of an uploaded Word file.
+pub(crate) fn upload_docx_to_drive(
+    app: &AppHandle,
+    file_path: &Path,
+    folder_id: Option<&str>,
+    convert: bool,
+    operation: &mut OperationHandle,
+) -> CommandResult<DriveUploadResult> {
+    let connection = open_database(app)?;
+    let agent = drive_agent();
+    operation.update("authorizing", 0, None, None);
+    let access_token = valid_access_token(&connection, &agent)?;
+
+    let bytes = fs::read(file_path).map_err(|error| {
+        CommandError::io(format!(
+            "Could not read '{}' for upload: {error}",
+            path_display(file_path)
+        ))
+    })?;
+    let name = file_path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "BlockFile export".to_string());
+    let mut metadata = json!({ "name": name });
+    if convert {
+        metadata["mimeType"] = json!(GOOGLE_DOC_MIME);
+    } else {
+        metadata["name"] = json!(format!("{name}.docx"));
+    }
+    if let Some(folder_id) = folder_id.map(str::trim).filter(|value| !value.is_empty()) {
+        metadata["parents"] = json!([folder_id]);
+    }
+
+    let boundary = format!("blockfile-{}", now_ms());
+    let mut body = Vec::with_capacity(bytes.len() + 512);
+    body.extend_from_slice(
+        format!(
+            "--{boundary}\r\nContent-Type: application/json; charset=UTF-8\r\n\r\n{metadata}\r\n--{boundary}\r\nContent-Type: {DOCX_MIME}\r\n\r\n"
+        )
+        .as_bytes(),
+    );
+    body.extend_from_slice(&bytes);
+    body.extend_from_slice(format!("\r\n--{boundary}--\r\n").as_bytes());
+
+    operation.ensure_not_cancelled()?;
+    operation.update("uploading", 0, Some(bytes.len()), Some(name.clone()));
+    let uploaded = read_json_response(
+        agent
+            .post(DRIVE_UPLOAD_URL)
+            .header("Authorization", &format!("Bearer {access_token}"))
+            .header(
+                "Content-Type",
+                &format!("multipart/related; boundary={boundary}"),
+            )
+            .send(&body[..]),
+        "upload to Google Drive",
+    )?;
+    operation.update("uploading", bytes.len(), Some(bytes.len()), Some(name));
+
+    Ok(DriveUploadResult {
+        drive_file_id: uploaded
+            .get("id")
+            .and_then(Value::as_str)
+            .unwrap_or_default()
+            .to_string(),
+        name: uploaded
+            .get("name")
+            .and_then(Value::as_str)
+            .unwrap_or_default()
+            .to_string(),
+        web_view_link: uploaded
+            .get("webViewLink")
+            .and_then(Value::as_str)
+            .map(str::to_string),
+        converted: convert,
+    })
+}
@@ -0,0 +1,368 @@
+use std::collections::HashMap;
+
+use rusqlite::{params, Connection};
+
+use crate::errors::CommandError;
+use crate::search::normalize_for_search;
+use crate::shards::schema_for_file;
+use crate::similar_cards::{jaccard, trigrams};
+use crate::types::{HeadingChange, HeadingHistoryRun, ParsedHeading};
+use crate::CommandResult;
+
+pub(crate) const CHANGE_ADDED: &str = "added";
+pub(crate) const CHANGE_REMOVED: &str = "removed";
+pub(crate) const CHANGE_RENAMED: &str = "renamed";
+
+struct StoredHeading {
+    id: i64,
+    order: i64,
+    level: i64,
+    text: String,
+}
+
+fn load_stored_headings(
+    connection: &Connection,
+    file_id: i64,
+) -> CommandResult<Vec<StoredHeading>> {
+    let mut statement = connection
+        .prepare_cached(
+            "SELECT id, heading_order, level, text FROM headings WHERE file_id = ?1 ORDER BY heading_order",
+        )
+        .map_err(|error| {
+            CommandError::database(format!("Could not prepare stored headings query: {error}"))
+        })?;
+    let rows = statement
+        .query_map(params![file_id], |row| {
+            Ok(StoredHeading {
+                id: row.get(0)?,
+                order: row.get(1)?,
+                level: row.get(2)?,
+                text: row.get(3)?,
+            })
+        })
+        .map_err(|error| {
+            CommandError::database(format!("Could not load stored headings: {error}"))
+        })?;
+    let mut stored = Vec::new();
+    for row in rows {
+        stored.push(row.map_err(|error| {
+            CommandError::database(format!("Could not parse stored heading: {error}"))
+        })?);
+    }
+    Ok(stored)
+}
+
+/// Below this character trigram overlap two headings are different cards,
+/// not one card reworded.
+const MIN_RENAME_SIMILARITY: f64 = 0.3;
+
+/// Where a freshly parsed heading came from among the stored ones.
+#[derive(Clone, Copy)]
+enum HeadingMatch {
+    /// Same normalized text; the heading may have moved or changed level.
+    Kept(usize),
+    /// Reworded from the stored heading at this index.
+    Renamed(usize),
+    Added,
+}
+
+/// Pairs each current heading with at most one stored heading. Equal
+/// normalized text pairs first, preferring a stored heading at the same
+/// order. Headings left on both sides are paired as renames when they share a
+/// level and their text still overlaps, preferring the closest text and then
+/// the nearest relative position among the unmatched headings. Stored
+/// headings that stay unpaired were removed.
+fn pair_headings(stored: &[StoredHeading], current: &[ParsedHeading]) -> Vec<HeadingMatch> {
+    let mut stored_by_text = HashMap::<String, Vec<usize>>::new();
+    for (index, heading) in stored.iter().enumerate() {
+        stored_by_text
+            .entry(normalize_for_search(&heading.text))
+            .or_default()
+            .push(index);
+    }
+
+    let mut pairing = vec![HeadingMatch::Added; current.len()];
+    let mut stored_matched = vec![false; stored.len()];
+    let mut unmatched_current = Vec::new();
+    for (index, heading) in current.iter().enumerate() {
+        let candidates = stored_by_text
+            .get_mut(&normalize_for_search(&heading.text))
+            .filter(|candidates| !candidates.is_empty());
+        match candidates {
+            Some(candidates) => {
+                let position = candidates
+                    .iter()
+                    .position(|stored_index| stored[*stored_index].order == heading.order)
+                    .unwrap_or(0);
+                let stored_index = candidates.remove(position);
+                stored_matched[stored_index] = true;
+                pairing[index] = HeadingMatch::Kept(stored_index);
+            }
+            None => unmatched_current.push(index),
+        }
+    }
+    let unmatched_stored = (0..stored.len())
+        .filter(|index| !stored_matched[*index])
+        .collect::<Vec<usize>>();
+    let stored_trigrams = unmatched_stored
+        .iter()
+        .map(|index| trigrams(&normalize_for_search(&stored[*index].text)))
+        .collect::<Vec<_>>();
+
+    let mut paired_stored = vec![false; unmatched_stored.len()];
+    for (position, current_index) in unmatched_current.iter().enumerate() {
+        let heading = &current[*current_index];
+        let heading_trigrams = trigrams(&normalize_for_search(&heading.text));
+        let rename_source = unmatched_stored
+            .iter()
+            .enumerate()
+            .filter(|(slot, stored_index)| {
+                !paired_stored[*slot] && stored[**stored_index].level == heading.level
+            })
+            .map(|(slot, stored_index)| {
+                let similarity = jaccard(&heading_trigrams, &stored_trigrams[slot]);
+                (slot, *stored_index, similarity)
+            })
+            .filter(|(_, _, similarity)| *similarity >= MIN_RENAME_SIMILARITY)
+            .max_by(|left, right| {
+                left.2
+                    .partial_cmp(&right.2)
+                    .unwrap_or(std::cmp::Ordering::Equal)
+                    .then_with(|| right.0.abs_diff(position).cmp(&left.0.abs_diff(position)))
+            });
+        if let Some((slot, stored_index, _)) = rename_source {
+            paired_stored[slot] = true;
+            pairing[*current_index] = HeadingMatch::Renamed(stored_index);
+        }
+    }
+    pairing
+}
+
+/// The history rows for one pairing: renames, adds, and unpaired stored
+/// headings as removes, in heading order.
+fn heading_changes(
+    stored: &[StoredHeading],
+    current: &[ParsedHeading],
+    pairing: &[HeadingMatch],
+) -> Vec<HeadingChange> {
+    let mut stored_paired = vec![false; stored.len()];
+    let mut changes = Vec::new();
+    for (heading, matched) in current.iter().zip(pairing) {
+        match *matched {
+            HeadingMatch::Kept(stored_index) => stored_paired[stored_index] = true,
+            HeadingMatch::Renamed(stored_index) => {
+                stored_paired[stored_index] = true;
+                changes.push(HeadingChange {
+                    kind: CHANGE_RENAMED.to_string(),
+                    level: heading.level,
+                    heading_order: heading.order,
+                    previous_text: Some(stored[stored_index].text.clone()),
+                    text: Some(heading.text.clone()),
+                });
+            }
+            HeadingMatch::Added => changes.push(HeadingChange {
+                kind: CHANGE_ADDED.to_string(),
+                level: heading.level,
+                heading_order: heading.order,
+                previous_text: None,
+                text: Some(heading.text.clone()),
+            }),
+        }
+    }
+    for (heading, paired) in stored.iter().zip(stored_paired) {
+        if paired {
+            continue;
+        }
+        changes.push(HeadingChange {
+            kind: CHANGE_REMOVED.to_string(),
+            level: heading.level,
+            heading_order: heading.order,
+            previous_text: Some(heading.text.clone()),
+            text: None,
+        });
+    }
+
+    changes.sort_by_key(|change| change.heading_order);
+    changes
+}
+
+pub(crate) fn record_heading_changes(
+    connection: &Connection,
+    file_id: i64,
+    indexed_at_ms: i64,
+    changes: &[HeadingChange],
+) -> CommandResult<()> {
+    let schema = schema_for_file(file_id);
+    for change in changes {
+        connection
+            .execute(
+                &format!(
+                    "INSERT INTO {schema}.heading_history(file_id, indexed_at_ms, change_kind, level, heading_order, previous_text, text)
+                     VALUES(?1, ?2, ?3, ?4, ?5, ?6, ?7)"
+                ),
+                params![
+                    file_id,
+                    indexed_at_ms,
+                    change.kind,
+                    change.level,
+                    change.heading_order,
+                    change.previous_text,
+                    change.text
+                ],
+            )
+            .map_err(|error| {
+                CommandError::database(format!("Could not record heading history: {error}"))
+            })?;
+    }
+    Ok(())
+}
+
+/// Brings a file's `headings` rows in line with a fresh parse without wiping
+/// them, and returns what changed. Rows are paired by `pair_headings`, so
+/// kept, moved, and renamed headings keep their ids and are edited in place;
+/// only added and removed headings are inserted or deleted. The returned
+/// changes come from the same pairing, so history always describes the rows
+/// that were actually written.
+pub(crate) fn sync_file_headings(
+    connection: &Connection,
+    file_id: i64,
+    file_name: &str,
+    relative_path: &str,
+    headings: &[ParsedHeading],
+) -> CommandResult<Vec<HeadingChange>> {
+    let schema = schema_for_file(file_id);
+    let stored = load_stored_headings(connection, file_id)?;
+    let pairing = pair_headings(&stored, headings);
+    let changes = heading_changes(&stored, headings, &pairing);
+
+    let mut stored_matched = vec![false; stored.len()];
+    for matched in &pairing {
+        if let HeadingMatch::Kept(stored_index) | HeadingMatch::Renamed(stored_index) = *matched {
+            stored_matched[stored_index] = true;
+        }
+    }
+    for (heading, matched) in stored.iter().zip(&stored_matched) {
+        if *matched {
+            continue;
+        }
+        connection
+            .execute(
+                &format!("DELETE FROM {schema}.headings WHERE id = ?1"),
+                params![heading.id],
+            )
+            .map_err(|error| {
+                CommandError::database(format!(
+                    "Could not remove old heading for '{relative_path}': {error}"
+                ))
+            })?;
+    }
+
+    let mut update_heading = connection
+        .prepare_cached(&format!(
+            "UPDATE {schema}.headings SET heading_order = ?1, level = ?2, text = ?3, normalized = ?4 WHERE id = ?5"
+        ))
+        .map_err(|error| {
+            CommandError::database(format!("Could not prepare heading update: {error}"))
+        })?;
+    let mut insert_heading = connection
+        .prepare_cached(&format!(
+            "INSERT INTO {schema}.headings(file_id, heading_order, level, text, normalized, file_name, relative_path)
+             VALUES(?1, ?2, ?3, ?4, ?5, ?6, ?7)"
+        ))
+        .map_err(|error| {
+            CommandError::database(format!("Could not prepare heading insert: {error}"))
+        })?;
+    for (heading, matched) in headings.iter().zip(pairing) {
+        match matched {
+            HeadingMatch::Kept(stored_index) | HeadingMatch::Renamed(stored_index) => {
+                let previous = &stored[stored_index];
+                if previous.order == heading.order
+                    && previous.level == heading.level
+                    && previous.text == heading.text
+                {
+                    continue;
+                }
+                update_heading
+                    .execute(params![
+                        heading.order,
+                        heading.level,
+                        heading.text,
+                        normalize_for_search(&heading.text),
+                        previous.id
+                    ])
+                    .map_err(|error| {
+                        CommandError::database(format!(
+                            "Could not update heading for '{relative_path}': {error}"
+                        ))
+                    })?;
+            }
+            HeadingMatch::Added => {
+                insert_heading
+                    .execute(params![
+                        file_id,
+                        heading.order,
+                        heading.level,
+                        heading.text,
+                        normalize_for_search(&heading.text),
+                        file_name,
+                        relative_path
+                    ])
+                    .map_err(|error| {
+                        CommandError::database(format!(
+                            "Could not insert heading for '{relative_path}': {error}"
+                        ))
+                    })?;
+            }
+        }
+    }
+    Ok(changes)
+}
+
+pub(crate) fn load_file_history(
+    connection: &Connection,
+    file_id: i64,
+) -> CommandResult<Vec<HeadingHistoryRun>> {
+    let mut statement = connection
+        .prepare(
+            "
+            SELECT indexed_at_ms, change_kind, level, heading_order, previous_text, text
+            FROM heading_history
+            WHERE file_id = ?1
+            ORDER BY indexed_at_ms DESC, heading_order ASC, id ASC
+            ",
+        )
+        .map_err(|error| {
+            CommandError::database(format!("Could not prepare file history query: {error}"))
+        })?;
+    let rows = statement
+        .query_map(params![file_id], |row| {
+            Ok((
+                row.get::<_, i64>(0)?,
+                HeadingChange {
+                    kind: row.get(1)?,
+                    level: row.get(2)?,
+                    heading_order: row.get(3)?,
+                    previous_text: row.get(4)?,
+                    text: row.get(5)?,
+                },
+            ))
+        })
+        .map_err(|error| {
+            CommandError::database(format!("Could not run file history query: {error}"))
+        })?;
+
+    let mut runs: Vec<HeadingHistoryRun> = Vec::new();
+    for row in rows {
+        let (indexed_at_ms, change) = row.map_err(|error| {
+            CommandError::database(format!("Could not parse file history row: {error}"))
+        })?;
+        match runs.last_mut() {
+            Some(run) if run.indexed_at_ms == indexed_at_ms => run.changes.push(change),
+            _ => runs.push(HeadingHistoryRun {
+                indexed_at_ms,
+                changes: vec![change],
+            }),
+        }
+    }
+    Ok(runs)
+}
@@ -0,0 +1,402 @@
+use crate::docx_build::ParagraphBuilder;
+use crate::types::{ConvertedDocument, GeneratedRun};
+
+/// Elements whose content is never readable article text.
+const SKIPPED_ELEMENTS: [&str; 9] = [
+    "script", "style", "noscript", "template", "svg", "iframe", "object", "select", "button",
+];
+const BLOCK_ELEMENTS: [&str; 24] = [
+    "p",
+    "div",
+    "section",
+    "article",
+    "header",
+    "footer",
+    "main",
+    "aside",
+    "nav",
+    "blockquote",
+    "li",
+    "ul",
+    "ol",
+    "dl",
+    "dt",
+    "dd",
+    "table",
+    "tr",
+    "pre",
+    "figure",
+    "figcaption",
+    "hr",
+    "address",
+    "form",
+];
+
+#[derive(Default)]
+struct HtmlConverter {
+    document: ConvertedDocument,
+    builder: ParagraphBuilder,
+    bold_depth: usize,
+    italic_depth: usize,
+    underline_depth: usize,
+    links: Vec<Option<String>>,
+    pre_depth: usize,
+}
+
+impl HtmlConverter {
+    fn active_link(&self) -> Option<String> {
+        self.links.iter().rev().find_map(Clone::clone)
+    }
+
+    fn push_text(&mut self, raw: &str) {
+        let text = if self.pre_depth > 0 {
+            raw.replace("\r\n", "\n")
+        } else {
+            collapse_whitespace(raw)
+        };
+        let text = if self.pre_depth == 0 && self.builder.at_word_boundary() {
+            text.trim_start_matches(' ').to_string()
+        } else {
+            text
+        };
+        self.builder.push_run(GeneratedRun {
+            text,
+            bold: self.bold_depth > 0,
+            italic: self.italic_depth > 0,
+            underline: self.underline_depth > 0,
+            link: self.active_link(),
+        });
+    }
+
+    fn open_tag(&mut self, name: &str, attributes: &[(String, String)]) {
+        match name {
+            "b" | "strong" => self.bold_depth += 1,
+            "i" | "em" | "cite" => self.italic_depth += 1,
+            "u" | "ins" => self.underline_depth += 1,
+            "a" => self
+                .links
+                .push(attribute(attributes, "href").and_then(|href| absolute_link(&href))),
+            "br" => self.builder.push_line_break(),
+            "img" => {
+                if let Some(alt) = attribute(attributes, "alt").filter(|alt| !alt.trim().is_empty())
+                {
+                    self.push_text(&format!(" {} ", alt.trim()));
+                }
+            }
+            "link" | "meta" => self.read_source_url(name, attributes),
+            _ => {
+                if let Some(level) = heading_level(name) {
+                    self.builder.flush();
+                    self.builder.set_heading_level(Some(level));
+                } else if BLOCK_ELEMENTS.contains(&name) {
+                    self.builder.flush();
+                    if name == "pre" {
+                        self.pre_depth += 1;
+                    }
+                    if name == "li" {
+                        self.push_text("• ");
+                    }
+                } else if name == "td" || name == "th" {
+                    self.push_text(" ");
+                }
+            }
+        }
+    }
+
+    fn close_tag(&mut self, name: &str) {
+        match name {
+            "b" | "strong" => self.bold_depth = self.bold_depth.saturating_sub(1),
+            "i" | "em" | "cite" => self.italic_depth = self.italic_depth.saturating_sub(1),
+            "u" | "ins" => self.underline_depth = self.underline_depth.saturating_sub(1),
+            "a" => {
+                self.links.pop();
+            }
+            _ => {
+                if heading_level(name).is_some() {
+                    self.builder.flush();
+                    self.builder.set_heading_level(None);
+                } else if BLOCK_ELEMENTS.contains(&name) {
+                    self.builder.flush();
+                    if name == "pre" {
+                        self.pre_depth = self.pre_depth.saturating_sub(1);
+                    }
+                }
+            }
+        }
+    }
+
+    fn read_source_url(&mut self, name: &str, attributes: &[(String, String)]) {
+        if self.document.source_url.is_some() {
+            return;
+        }
+        let candidate = if name == "link" {
+            attribute(attributes, "rel")
+                .filter(|rel| rel.eq_ignore_ascii_case("canonical"))
+                .and_then(|_| attribute(attributes, "href"))
+        } else {
+            attribute(attributes, "property")
+                .or_else(|| attribute(attributes, "name"))
+                .filter(|property| property.eq_ignore_ascii_case("og:url"))
+                .and_then(|_| attribute(attributes, "content"))
+        };
+        self.document.source_url = candidate.and_then(|url| absolute_link(&url));
+    }
+
+    fn read_comment(&mut self, comment: &str) {
+        // Browsers stamp "Save Page As" output with `saved from url=(0042)https://...`.
+        if self.document.source_url.is_some() {
+            return;
+        }
+        let Some(start) = comment.find("saved from url=") else {
+            return;
+        };
+        let rest = &comment[start + "saved from url=".len()..];
+        let rest = rest
+            .strip_prefix('(')
+            .and_then(|value| value.split_once(')'))
+            .map(|(_, url)| url)
+            .unwrap_or(rest);
+        let url = rest.split_whitespace().next().unwrap_or_default();
+        self.document.source_url = absolute_link(url);
+    }
+}
+
+fn heading_level(name: &str) -> Option<i64> {
+    match name {
+        "h1" => Some(1),
+        "h2" => Some(2),
+        "h3" => Some(3),
+        "h4" | "h5" | "h6" => Some(4),
+        _ => None,
+    }
+}
+
+fn attribute(attributes: &[(String, String)], name: &str) -> Option<String> {
+    attributes
+        .iter()
+        .find(|(key, _)| key == name)
+        .map(|(_, value)| value.clone())
+}
+
+/// Keeps only links that still resolve once the page is out of its site.
+fn absolute_link(href: &str) -> Option<String> {
+    let href = href.trim();
+    let lower = href.to_ascii_lowercase();
+    if lower.starts_with("http://") || lower.starts_with("https://") || lower.starts_with("mailto:")
+    {
+        return Some(href.to_string());
+    }
+    href.strip_prefix("//")
+        .map(|rest| format!("https://{rest}"))
+}
+
+fn collapse_whitespace(value: &str) -> String {
+    let mut collapsed = String::with_capacity(value.len());
+    let mut in_space = false;
+    for character in value.chars() {
+        if character.is_whitespace() {
+            if !in_space {
+                collapsed.push(' ');
+            }
+            in_space = true;
+        } else {
+            collapsed.push(character);
+            in_space = false;
+        }
+    }
+    collapsed
+}
+
+pub(crate) fn decode_html_entities(value: &str) -> String {
+    if !value.contains('&') {
+        return value.to_string();
+    }
+    let mut decoded = String::with_capacity(value.len());
+    let mut rest = value;
+    while let Some(start) = rest.find('&') {
+        decoded.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let entity_end = rest
+            .char_indices()
+            .take(12)
+            .find(|(_, character)| *character == ';')
+            .map(|(index, _)| index);
+        let replacement = entity_end.and_then(|end| decode_entity(&rest[1..end]));
+        match (entity_end, replacement) {
+            (Some(end), Some(character)) => {
+                decoded.push(character);
+                rest = &rest[end + 1..];
+            }
+            _ => {
+                decoded.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    decoded.push_str(rest);
+    decoded
+}
+
+fn decode_entity(entity: &str) -> Option<char> {
+    if let Some(number) = entity.strip_prefix('#') {
+        let code = match number.strip_prefix(['x', 'X']) {
+            Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+            None => number.parse::<u32>().ok()?,
+        };
+        return char::from_u32(code);
+    }
+    Some(match entity {
+        "amp" => '&',
+        "lt" => '<',
+        "gt" => '>',
+        "quot" => '"',
+        "apos" => '\'',
+        "nbsp" => ' ',
+        "ndash" => '–',
+        "mdash" => '—',
+        "hellip" => '…',
+        "lsquo" => '‘',
+        "rsquo" => '’',
+        "ldquo" => '“',
+        "rdquo" => '”',
+        "laquo" => '«',
+        "raquo" => '»',
+        "middot" => '·',
+        "bull" => '•',
+        "copy" => '©',
+        "reg" => '®',
+        "trade" => '™',
+        "sect" => '§',
+        "para" => '¶',
+        "deg" => '°',
+        _ => return None,
+    })
+}
+
+fn find_ascii_case_insensitive(haystack: &str, needle: &str) -> Option<usize> {
+    let needle = needle.as_bytes();
+    haystack
+        .as_bytes()
+        .windows(needle.len())
+        .position(|window| window.eq_ignore_ascii_case(needle))
+}
+
+/// Splits the inside of a start tag (`a href="x" class=y`) into its name and
+/// lowercased attribute keys with decoded values.
+fn parse_tag(inner: &str) -> (String, Vec<(String, String)>) {
+    let inner = inner.trim_end_matches('/');
+    let name_end = inner
+        .find(|character: char| character.is_whitespace())
+        .unwrap_or(inner.len());
+    let name = inner[..name_end].to_ascii_lowercase();
+
+    let mut attributes = Vec::new();
+    let mut rest = inner[name_end..].trim_start();
+    while !rest.is_empty() {
+        let key_end = rest
+            .find(|character: char| character == '=' || character.is_whitespace())
+            .unwrap_or(rest.len());
+        let key = rest[..key_end].to_ascii_lowercase();
+        rest = rest[key_end..].trim_start();
+        let mut value = String::new();
+        if let Some(after_equals) = rest.strip_prefix('=') {
+            let after_equals = after_equals.trim_start();
+            let (raw, remaining) = match after_equals.chars().next() {
+                Some(quote @ ('"' | '\'')) => {
+                    let body = &after_equals[1..];
+                    let end = body.find(quote).unwrap_or(body.len());
+                    (&body[..end], body.get(end + 1..).unwrap_or_default())
+                }
+                _ => {
+                    let end = after_equals
+                        .find(|character: char| character.is_whitespace())
+                        .unwrap_or(after_equals.len());
+                    (&after_equals[..end], &after_equals[end..])
+                }
+            };
+            value = decode_html_entities(raw);
+            rest = remaining.trim_start();
+        }
+        if !key.is_empty() {
+            attributes.push((key, value));
+        }
+    }
+    (name, attributes)
+}
+
+/// Finds the `>` that closes a tag, ignoring any inside quoted attributes.
+fn tag_end(html: &str) -> Option<usize> {
+    let mut quote = None;
+    for (index, character) in html.char_indices() {
+        match (quote, character) {
+            (Some(open), _) if character == open => quote = None,
+            (Some(_), _) => {}
+            (None, '"' | '\'') => quote = Some(character),
+            (None, '>') => return Some(index),
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Converts saved web pages into paragraphs for a generated docx. The parser
+/// is deliberately forgiving: it never fails, drops anything that is not
+/// readable text, and keeps headings, emphasis and absolute links.
+pub(crate) fn convert_html_document(html: &str) -> ConvertedDocument {
+    let mut converter = HtmlConverter::default();
+    let mut rest = html;
+    while !rest.is_empty() {
+        let Some(open) = rest.find('<') else {
+            converter.push_text(&decode_html_entities(rest));
+            break;
+        };
+        if open > 0 {
+            converter.push_text(&decode_html_entities(&rest[..open]));
+        }
+        rest = &rest[open..];
+
+        if let Some(comment) = rest.strip_prefix("<!--") {
+            let end = comment.find("-->").unwrap_or(comment.len());
+            converter.read_comment(&comment[..end]);
+            rest = comment.get(end + 3..).unwrap_or_default();
+            continue;
+        }
+        let Some(end) = tag_end(rest) else {
+            converter.push_text(&decode_html_entities(rest));
+            break;
+        };
+        let inner = &rest[1..end];
+        rest = &rest[end + 1..];
+        if inner.starts_with('!') || inner.starts_with('?') {
+            continue;
+        }
+
+        if let Some(closing) = inner.strip_prefix('/') {
+            converter.close_tag(&closing.trim().to_ascii_lowercase());
+            continue;
+        }
+        let (name, attributes) = parse_tag(inner);
+        if name == "title" || SKIPPED_ELEMENTS.contains(&name.as_str()) {
+            let closing = format!("</{name}");
+            let body_end = find_ascii_case_insensitive(rest, &closing).unwrap_or(rest.len());
+            if name == "title" && converter.document.title.is_none() {
+                let title = collapse_whitespace(&decode_html_entities(&rest[..body_end]));
+                let title = title.trim();
+                if !title.is_empty() {
+                    converter.document.title = Some(title.to_string());
+                }
+            }
+            rest = &rest[body_end..];
+            rest = tag_end(rest)
+                .and_then(|close| rest.get(close + 1..))
+                .unwrap_or_default();
+            continue;
+        }
+        converter.open_tag(&name, &attributes);
+        if inner.ends_with('/') && name != "br" {
+            converter.close_tag(&name);
+        }
+    }
+    converter.document.paragraphs = converter.builder.finish();
+    converter.document
+}
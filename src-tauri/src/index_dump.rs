@@ -0,0 +1,429 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use rusqlite::{params, Connection, Row};
+
+use crate::authors::replace_file_authors;
+use crate::block_pack::safe_relative_path;
+use crate::cite_index::replace_file_cites;
+use crate::csv_export::{parse_csv, push_csv_row, CSV_UTF8_BOM};
+use crate::errors::CommandError;
+use crate::history::sync_file_headings;
+use crate::shards::schema_for_root;
+use crate::types::{
+    CiteBlock, IndexDump, IndexDumpAuthor, IndexDumpCite, IndexDumpFile, IndexDumpHeading,
+    IndexImportResult, ParsedHeading,
+};
+use crate::util::{file_name_from_relative, now_ms, path_display};
+use crate::CommandResult;
+
+pub(crate) const INDEX_DUMP_VERSION: i64 = 1;
+
+pub(crate) const INDEX_DUMP_FORMAT_JSON: &str = "json";
+pub(crate) const INDEX_DUMP_FORMAT_CSV: &str = "csv";
+
+const CSV_HEADER: [&str; 9] = [
+    "kind",
+    "relative_path",
+    "order",
+    "level",
+    "heading_order",
+    "text",
+    "size",
+    "modified_ms",
+    "file_hash",
+];
+const KIND_FILE: &str = "file";
+const KIND_HEADING: &str = "heading";
+const KIND_AUTHOR: &str = "author";
+const KIND_CITE: &str = "cite";
+
+/// Runs `sql` with the root id and groups what `read_row` makes of each row
+/// by the file id in the first column.
+fn rows_by_file<T>(
+    connection: &Connection,
+    root_id: i64,
+    label: &str,
+    sql: &str,
+    read_row: impl Fn(&Row<'_>) -> rusqlite::Result<T>,
+) -> CommandResult<HashMap<i64, Vec<T>>> {
+    let mut statement = connection.prepare(sql).map_err(|error| {
+        CommandError::database(format!("Could not prepare index {label} query: {error}"))
+    })?;
+    let rows = statement
+        .query_map(params![root_id], |row| {
+            Ok((row.get::<_, i64>(0)?, read_row(row)?))
+        })
+        .map_err(|error| {
+            CommandError::database(format!("Could not run index {label} query: {error}"))
+        })?;
+    let mut grouped = HashMap::<i64, Vec<T>>::new();
+    for row in rows {
+        let (file_id, value) = row.map_err(|error| {
+            CommandError::database(format!("Could not parse index {label} row: {error}"))
+        })?;
+        grouped.entry(file_id).or_default().push(value);
+    }
+    Ok(grouped)
+}
+
+/// Every indexed file in a root with its headings, authors and cites, in
+/// path and document order.
+pub(crate) fn load_index_dump(connection: &Connection, root_id: i64) -> CommandResult<IndexDump> {
+    let mut headings = rows_by_file(
+        connection,
+        root_id,
+        "heading",
+        "SELECT h.file_id, h.heading_order, h.level, h.text
+         FROM headings h JOIN files f ON f.id = h.file_id
+         WHERE f.root_id = ?1 ORDER BY h.file_id, h.heading_order",
+        |row| {
+            Ok(IndexDumpHeading {
+                order: row.get(1)?,
+                level: row.get(2)?,
+                text: row.get(3)?,
+            })
+        },
+    )?;
+    let mut authors = rows_by_file(
+        connection,
+        root_id,
+        "author",
+        "SELECT a.file_id, a.author_order, a.text
+         FROM authors a JOIN files f ON f.id = a.file_id
+         WHERE f.root_id = ?1 ORDER BY a.file_id, a.author_order",
+        |row| {
+            Ok(IndexDumpAuthor {
+                order: row.get(1)?,
+                text: row.get(2)?,
+            })
+        },
+    )?;
+    let mut cites = rows_by_file(
+        connection,
+        root_id,
+        "cite",
+        "SELECT c.file_id, c.paragraph_order, c.heading_order, c.text
+         FROM cites c JOIN files f ON f.id = c.file_id
+         WHERE f.root_id = ?1 ORDER BY c.file_id, c.paragraph_order",
+        |row| {
+            Ok(IndexDumpCite {
+                paragraph_order: row.get(1)?,
+                heading_order: row.get(2)?,
+                text: row.get(3)?,
+            })
+        },
+    )?;
+
+    let mut statement = connection
+        .prepare(
+            "SELECT id, relative_path, modified_ms, size, file_hash
+             FROM files WHERE root_id = ?1 ORDER BY relative_path",
+        )
+        .map_err(|error| {
+            CommandError::database(format!("Could not prepare index file query: {error}"))
+        })?;
+    let rows = statement
+        .query_map(params![root_id], |row| {
+            Ok((
+                row.get::<_, i64>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, i64>(2)?,
+                row.get::<_, i64>(3)?,
+                row.get::<_, String>(4)?,
+            ))
+        })
+        .map_err(|error| {
+            CommandError::database(format!("Could not run index file query: {error}"))
+        })?;
+    let mut files = Vec::new();
+    for row in rows {
+        let (file_id, relative_path, modified_ms, size, file_hash) = row.map_err(|error| {
+            CommandError::database(format!("Could not parse index file row: {error}"))
+        })?;
+        files.push(IndexDumpFile {
+            relative_path,
+            modified_ms,
+            size,
+            file_hash,
+            headings: headings.remove(&file_id).unwrap_or_default(),
+            authors: authors.remove(&file_id).unwrap_or_default(),
+            cites: cites.remove(&file_id).unwrap_or_default(),
+        });
+    }
+
+    Ok(IndexDump {
+        version: INDEX_DUMP_VERSION,
+        exported_at_ms: now_ms(),
+        files,
+    })
+}
+
+pub(crate) fn render_index_dump_json(dump: &IndexDump) -> CommandResult<String> {
+    serde_json::to_string_pretty(dump)
+        .map_err(|error| CommandError::parse(format!("Could not serialize index dump: {error}")))
+}
+
+/// One table for spreadsheets: a `file` row per file followed by its
+/// `heading`, `author` and `cite` rows. Columns a kind has no use for are
+/// left empty; `order` is the paragraph order for cites.
+pub(crate) fn render_index_dump_csv(dump: &IndexDump) -> String {
+    let mut output = String::new();
+    push_csv_row(&mut output, &CSV_HEADER.map(str::to_string));
+    let row = |kind: &str, file: &IndexDumpFile, order: Option<i64>, level: Option<i64>| {
+        let number = |value: Option<i64>| value.map(|value| value.to_string()).unwrap_or_default();
+        vec![
+            kind.to_string(),
+            file.relative_path.clone(),
+            number(order),
+            number(level),
+        ]
+    };
+    for file in &dump.files {
+        let mut fields = row(KIND_FILE, file, None, None);
+        fields.extend([
+            String::new(),
+            String::new(),
+            file.size.to_string(),
+            file.modified_ms.to_string(),
+            file.file_hash.clone(),
+        ]);
+        push_csv_row(&mut output, &fields);
+        for heading in &file.headings {
+            let mut fields = row(KIND_HEADING, file, Some(heading.order), Some(heading.level));
+            fields.extend([String::new(), heading.text.clone()]);
+            push_csv_row(&mut output, &fields);
+        }
+        for author in &file.authors {
+            let mut fields = row(KIND_AUTHOR, file, Some(author.order), None);
+            fields.extend([String::new(), author.text.clone()]);
+            push_csv_row(&mut output, &fields);
+        }
+        for cite in &file.cites {
+            let mut fields = row(KIND_CITE, file, Some(cite.paragraph_order), None);
+            fields.extend([
+                cite.heading_order
+                    .map(|order| order.to_string())
+                    .unwrap_or_default(),
+                cite.text.clone(),
+            ]);
+            push_csv_row(&mut output, &fields);
+        }
+    }
+    output
+}
+
+fn csv_number(row_number: usize, column: &str, value: &str) -> CommandResult<i64> {
+    value.trim().parse::<i64>().map_err(|_| {
+        CommandError::parse(format!(
+            "Row {row_number}: '{value}' is not a valid {column}."
+        ))
+    })
+}
+
+fn csv_optional_number(row_number: usize, column: &str, value: &str) -> CommandResult<Option<i64>> {
+    if value.trim().is_empty() {
+        Ok(None)
+    } else {
+        csv_number(row_number, column, value).map(Some)
+    }
+}
+
+fn parse_index_dump_csv(text: &str) -> CommandResult<IndexDump> {
+    let rows = parse_csv(text);
+    let Some((header, rows)) = rows.split_first() else {
+        return Err(CommandError::parse("The index dump is empty."));
+    };
+    if header.iter().map(|value| value.trim()).ne(CSV_HEADER) {
+        return Err(CommandError::parse(format!(
+            "Index CSV must start with the header row '{}'.",
+            CSV_HEADER.join(",")
+        )));
+    }
+
+    let mut files = Vec::<IndexDumpFile>::new();
+    let mut file_indexes = HashMap::<String, usize>::new();
+    for (index, row) in rows.iter().enumerate() {
+        let row_number = index + 2;
+        let field = |column: usize| row.get(column).map(String::as_str).unwrap_or_default();
+        let relative_path = field(1).to_string();
+        let kind = field(0).trim();
+        if kind == KIND_FILE {
+            file_indexes.insert(relative_path.clone(), files.len());
+            files.push(IndexDumpFile {
+                relative_path,
+                modified_ms: csv_number(row_number, "modified_ms", field(7))?,
+                size: csv_number(row_number, "size", field(6))?,
+                file_hash: field(8).to_string(),
+                headings: Vec::new(),
+                authors: Vec::new(),
+                cites: Vec::new(),
+            });
+            continue;
+        }
+
+        let file = file_indexes
+            .get(&relative_path)
+            .map(|file_index| &mut files[*file_index])
+            .ok_or_else(|| {
+                CommandError::parse(format!(
+                    "Row {row_number}: '{relative_path}' has no file row before it."
+                ))
+            })?;
+        let order = csv_number(row_number, "order", field(2))?;
+        let text = field(5).to_string();
+        match kind {
+            KIND_HEADING => file.headings.push(IndexDumpHeading {
+                order,
+                level: csv_number(row_number, "level", field(3))?,
+                text,
+            }),
+            KIND_AUTHOR => file.authors.push(IndexDumpAuthor { order, text }),
+            KIND_CITE => file.cites.push(IndexDumpCite {
+                paragraph_order: order,
+                heading_order: csv_optional_number(row_number, "heading_order", field(4))?,
+                text,
+            }),
+            _ => {
+                return Err(CommandError::parse(format!(
+                    "Row {row_number}: unknown row kind '{kind}'."
+                )))
+            }
+        }
+    }
+
+    Ok(IndexDump {
+        version: INDEX_DUMP_VERSION,
+        exported_at_ms: 0,
+        files,
+    })
+}
+
+/// Reads a dump written by `export_index`, telling JSON from CSV by its
+/// first character.
+pub(crate) fn read_index_dump(path: &Path) -> CommandResult<IndexDump> {
+    let raw = fs::read_to_string(path).map_err(|error| {
+        CommandError::io(format!("Could not read '{}': {error}", path_display(path)))
+    })?;
+    let text = raw.trim_start_matches(CSV_UTF8_BOM);
+    if !text.trim_start().starts_with('{') {
+        return parse_index_dump_csv(text);
+    }
+    let dump = serde_json::from_str::<IndexDump>(text)
+        .map_err(|error| CommandError::parse(format!("Could not parse index dump: {error}")))?;
+    if dump.version > INDEX_DUMP_VERSION {
+        return Err(CommandError::parse(format!(
+            "Index dump version {} is newer than this app supports ({INDEX_DUMP_VERSION}).",
+            dump.version
+        )));
+    }
+    Ok(dump)
+}
+
+/// Fills an empty root's index from a dump. Files are stored without a hash,
+/// so the first scan still reads each one for its text and chunks; until then
+/// headings, authors and cites are browsable and searchable as exported.
+pub(crate) fn restore_index_dump(
+    connection: &Connection,
+    root_id: i64,
+    root: &Path,
+    dump: &IndexDump,
+) -> CommandResult<IndexImportResult> {
+    let indexed_files = connection
+        .query_row(
+            "SELECT COUNT(*) FROM files WHERE root_id = ?1",
+            params![root_id],
+            |row| row.get::<_, i64>(0),
+        )
+        .map_err(|error| {
+            CommandError::database(format!("Could not count indexed files: {error}"))
+        })?;
+    if indexed_files > 0 {
+        return Err(CommandError::validation(
+            "This folder is already indexed. Import an index only before its first scan.",
+        ));
+    }
+
+    let schema = schema_for_root(connection, root_id)?;
+    let mut result = IndexImportResult {
+        root_path: path_display(root),
+        file_count: 0,
+        heading_count: 0,
+        author_count: 0,
+        cite_count: 0,
+        skipped_paths: Vec::new(),
+    };
+    for file in &dump.files {
+        let Some(safe_relative) = safe_relative_path(&file.relative_path) else {
+            result.skipped_paths.push(file.relative_path.clone());
+            continue;
+        };
+        let relative_path = safe_relative.to_string_lossy().replace('\\', "/");
+        connection
+            .execute(
+                &format!(
+                    "INSERT INTO {schema}.files(root_id, relative_path, absolute_path, modified_ms, size, file_hash, heading_count)
+                     VALUES(?1, ?2, ?3, ?4, ?5, '', ?6)
+                     ON CONFLICT(root_id, relative_path) DO NOTHING"
+                ),
+                params![
+                    root_id,
+                    relative_path.as_str(),
+                    path_display(&root.join(&safe_relative)),
+                    file.modified_ms,
+                    file.size,
+                    i64::try_from(file.headings.len()).unwrap_or(0)
+                ],
+            )
+            .map_err(|error| {
+                CommandError::database(format!(
+                    "Could not insert imported file '{relative_path}': {error}"
+                ))
+            })?;
+        if connection.changes() == 0 {
+            result.skipped_paths.push(file.relative_path.clone());
+            continue;
+        }
+        let file_id = connection.last_insert_rowid();
+
+        let headings = file
+            .headings
+            .iter()
+            .map(|heading| ParsedHeading {
+                order: heading.order,
+                level: heading.level,
+                text: heading.text.clone(),
+            })
+            .collect::<Vec<ParsedHeading>>();
+        sync_file_headings(
+            connection,
+            file_id,
+            &file_name_from_relative(&relative_path),
+            &relative_path,
+            &headings,
+        )?;
+        let authors = file
+            .authors
+            .iter()
+            .map(|author| (author.order, author.text.clone()))
+            .collect::<Vec<(i64, String)>>();
+        replace_file_authors(connection, file_id, &relative_path, &authors, "")?;
+        let cites = file
+            .cites
+            .iter()
+            .map(|cite| CiteBlock {
+                paragraph_order: cite.paragraph_order,
+                heading_order: cite.heading_order,
+                text: cite.text.clone(),
+            })
+            .collect::<Vec<CiteBlock>>();
+        replace_file_cites(connection, file_id, &cites, "")?;
+
+        result.file_count += 1;
+        result.heading_count += headings.len();
+        result.author_count += authors.len();
+        result.cite_count += cites.len();
+    }
+    Ok(result)
+}
@@ -0,0 +1,249 @@
+use std::path::Path;
+
+use rusqlite::{params, Connection};
+use serde_json::json;
+
+use crate::docx_parse::html_escape;
+use crate::errors::CommandError;
+use crate::shared_roots::portable_link;
+use crate::util::{file_name_from_relative, folder_from_relative, format_utc_timestamp};
+use crate::CommandResult;
+
+pub(crate) const INDEX_CHANGE_ADDED: &str = "added";
+pub(crate) const INDEX_CHANGE_CHANGED: &str = "changed";
+
+pub(crate) const FEED_FORMAT_ATOM: &str = "atom";
+pub(crate) const FEED_FORMAT_JSON: &str = "json";
+
+/// Index runs kept per root; older runs are pruned after each index.
+const RECORDED_INDEX_RUNS: i64 = 50;
+pub(crate) const DEFAULT_FEED_RUNS: usize = 5;
+
+pub(crate) struct IndexChangeEntry {
+    pub indexed_at_ms: i64,
+    pub change_kind: String,
+    pub relative_path: String,
+    pub heading_count: i64,
+}
+
+pub(crate) struct IndexFeed {
+    pub title: String,
+    pub root_uid: String,
+    pub run_count: usize,
+    pub entries: Vec<IndexChangeEntry>,
+}
+
+pub(crate) fn record_index_change(
+    connection: &Connection,
+    root_id: i64,
+    indexed_at_ms: i64,
+    change_kind: &str,
+    relative_path: &str,
+    heading_count: i64,
+) -> CommandResult<()> {
+    connection
+        .execute(
+            "INSERT INTO index_changes(root_id, indexed_at_ms, change_kind, relative_path, heading_count)
+             VALUES(?1, ?2, ?3, ?4, ?5)",
+            params![root_id, indexed_at_ms, change_kind, relative_path, heading_count],
+        )
+        .map_err(|error| {
+            CommandError::database(format!("Could not record index change: {error}"))
+        })?;
+    Ok(())
+}
+
+pub(crate) fn prune_index_changes(connection: &Connection, root_id: i64) -> CommandResult<()> {
+    connection
+        .execute(
+            "
+            DELETE FROM index_changes
+            WHERE root_id = ?1
+              AND indexed_at_ms NOT IN (
+                SELECT DISTINCT indexed_at_ms FROM index_changes
+                WHERE root_id = ?1
+                ORDER BY indexed_at_ms DESC
+                LIMIT ?2
+              )
+            ",
+            params![root_id, RECORDED_INDEX_RUNS],
+        )
+        .map_err(|error| {
+            CommandError::database(format!("Could not prune index changes: {error}"))
+        })?;
+    Ok(())
+}
+
+/// Loads the files added or changed in the root's last `run_count` index runs
+/// that changed anything, newest first.
+pub(crate) fn load_index_feed(
+    connection: &Connection,
+    root_id: i64,
+    root_path: &str,
+    root_uid: &str,
+    run_count: usize,
+) -> CommandResult<IndexFeed> {
+    let mut statement = connection
+        .prepare(
+            "
+            SELECT indexed_at_ms, change_kind, relative_path, heading_count
+            FROM index_changes
+            WHERE root_id = ?1
+              AND indexed_at_ms IN (
+                SELECT DISTINCT indexed_at_ms FROM index_changes
+                WHERE root_id = ?1
+                ORDER BY indexed_at_ms DESC
+                LIMIT ?2
+              )
+            ORDER BY indexed_at_ms DESC, relative_path ASC
+            ",
+        )
+        .map_err(|error| {
+            CommandError::database(format!("Could not prepare index feed query: {error}"))
+        })?;
+    let rows = statement
+        .query_map(
+            params![root_id, i64::try_from(run_count).unwrap_or(i64::MAX)],
+            |row| {
+                Ok(IndexChangeEntry {
+                    indexed_at_ms: row.get(0)?,
+                    change_kind: row.get(1)?,
+                    relative_path: row.get(2)?,
+                    heading_count: row.get(3)?,
+                })
+            },
+        )
+        .map_err(|error| {
+            CommandError::database(format!("Could not run index feed query: {error}"))
+        })?;
+
+    let mut entries = Vec::new();
+    for row in rows {
+        entries.push(row.map_err(|error| {
+            CommandError::database(format!("Could not parse index feed row: {error}"))
+        })?);
+    }
+    let mut runs = entries
+        .iter()
+        .map(|entry| entry.indexed_at_ms)
+        .collect::<Vec<i64>>();
+    runs.dedup();
+
+    let root_name = Path::new(root_path)
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_else(|| root_path.to_string());
+    Ok(IndexFeed {
+        title: format!("What's new in {root_name}"),
+        root_uid: root_uid.to_string(),
+        run_count: runs.len(),
+        entries,
+    })
+}
+
+fn entry_title(entry: &IndexChangeEntry) -> String {
+    let verb = if entry.change_kind == INDEX_CHANGE_ADDED {
+        "Added"
+    } else {
+        "Updated"
+    };
+    format!("{verb}: {}", file_name_from_relative(&entry.relative_path))
+}
+
+fn entry_summary(entry: &IndexChangeEntry) -> String {
+    let folder = folder_from_relative(&entry.relative_path);
+    let headings = match entry.heading_count {
+        1 => "1 heading".to_string(),
+        count => format!("{count} headings"),
+    };
+    if folder.is_empty() {
+        headings
+    } else {
+        format!("{folder} · {headings}")
+    }
+}
+
+/// Entry ids are stable per file and run, so a file changed in two runs shows
+/// up twice in a reader rather than silently replacing its older entry.
+fn entry_id(feed: &IndexFeed, entry: &IndexChangeEntry) -> String {
+    format!(
+        "{}&indexed={}",
+        portable_link(&feed.root_uid, &entry.relative_path, None),
+        entry.indexed_at_ms
+    )
+}
+
+pub(crate) fn render_atom_feed(feed: &IndexFeed) -> String {
+    let updated = feed
+        .entries
+        .first()
+        .map(|entry| entry.indexed_at_ms)
+        .unwrap_or_default();
+    let mut xml = format!(
+        "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n<feed xmlns=\"http://www.w3.org/2005/Atom\">\n  <title>{}</title>\n  <id>{}</id>\n  <updated>{}</updated>\n  <generator>BlockFile</generator>\n",
+        html_escape(&feed.title),
+        html_escape(&portable_link(&feed.root_uid, "", None)),
+        format_utc_timestamp(updated)
+    );
+    for entry in &feed.entries {
+        let link = portable_link(&feed.root_uid, &entry.relative_path, None);
+        let folder = folder_from_relative(&entry.relative_path);
+        xml.push_str("  <entry>\n");
+        xml.push_str(&format!(
+            "    <title>{}</title>\n",
+            html_escape(&entry_title(entry))
+        ));
+        xml.push_str(&format!(
+            "    <id>{}</id>\n",
+            html_escape(&entry_id(feed, entry))
+        ));
+        xml.push_str(&format!("    <link href=\"{}\"/>\n", html_escape(&link)));
+        xml.push_str(&format!(
+            "    <updated>{}</updated>\n",
+            format_utc_timestamp(entry.indexed_at_ms)
+        ));
+        if !folder.is_empty() {
+            xml.push_str(&format!(
+                "    <category term=\"{}\"/>\n",
+                html_escape(&folder)
+            ));
+        }
+        xml.push_str(&format!(
+            "    <summary>{}</summary>\n",
+            html_escape(&entry_summary(entry))
+        ));
+        xml.push_str("  </entry>\n");
+    }
+    xml.push_str("</feed>\n");
+    xml
+}
+
+/// JSON Feed 1.1 (https://jsonfeed.org/version/1.1).
+pub(crate) fn render_json_feed(feed: &IndexFeed) -> String {
+    let items = feed
+        .entries
+        .iter()
+        .map(|entry| {
+            let folder = folder_from_relative(&entry.relative_path);
+            json!({
+                "id": entry_id(feed, entry),
+                "url": portable_link(&feed.root_uid, &entry.relative_path, None),
+                "title": entry_title(entry),
+                "content_text": entry_summary(entry),
+                "date_modified": format_utc_timestamp(entry.indexed_at_ms),
+                "tags": if folder.is_empty() { Vec::new() } else { vec![folder] },
+                "_blockfile": {
+                    "change": entry.change_kind,
+                    "relativePath": entry.relative_path,
+                    "headingCount": entry.heading_count,
+                },
+            })
+        })
+        .collect::<Vec<serde_json::Value>>();
+    let document = json!({
+        "version": "https://jsonfeed.org/version/1.1",
+        "title": feed.title,
+        "items": items,
+    });
+    serde_json::to_string_pretty(&document).unwrap_or_default()
+}
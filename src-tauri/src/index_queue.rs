@@ -0,0 +1,145 @@
+use std::collections::VecDeque;
+use std::sync::{Mutex, MutexGuard};
+
+use tauri::{AppHandle, Emitter, Manager};
+
+use crate::commands::index_root_blocking;
+use crate::errors::CommandError;
+use crate::types::{IndexQueueProgress, IndexQueueRootResult, IndexQueueStatus, IndexQueueSummary};
+use crate::util::now_ms;
+use crate::CommandResult;
+
+pub(crate) const INDEX_QUEUE_PROGRESS_EVENT: &str = "index-queue-progress";
+pub(crate) const INDEX_QUEUE_FINISHED_EVENT: &str = "index-queue-finished";
+
+/// Roots waiting to be indexed and the batch the worker is draining. Each
+/// index run holds one write transaction on the index database, so roots are
+/// indexed one after another rather than in parallel.
+#[derive(Default)]
+struct IndexQueue {
+    pending: VecDeque<String>,
+    running: Option<String>,
+    started_at_ms: i64,
+    results: Vec<IndexQueueRootResult>,
+}
+
+/// The index queue, kept in Tauri's managed state.
+#[derive(Default)]
+pub(crate) struct IndexQueueState(Mutex<IndexQueue>);
+
+fn lock_queue(app: &AppHandle) -> CommandResult<MutexGuard<'_, IndexQueue>> {
+    app.state::<IndexQueueState>()
+        .inner()
+        .0
+        .lock()
+        .map_err(|_| CommandError::internal("Could not lock index queue"))
+}
+
+fn queue_status(queue: &IndexQueue) -> IndexQueueStatus {
+    IndexQueueStatus {
+        running: queue.running.clone(),
+        pending: queue.pending.iter().cloned().collect(),
+        completed: queue.results.len(),
+    }
+}
+
+pub(crate) fn index_queue_status(app: &AppHandle) -> CommandResult<IndexQueueStatus> {
+    let queue = lock_queue(app)?;
+    Ok(queue_status(&queue))
+}
+
+/// Whether the worker is indexing or has roots left to index.
+pub(crate) fn index_queue_busy(app: &AppHandle) -> bool {
+    lock_queue(app)
+        .map(|queue| queue.running.is_some() || !queue.pending.is_empty())
+        .unwrap_or(false)
+}
+
+/// Adds `root_paths` to the queue, skipping roots already waiting or being
+/// indexed, and starts the worker when it is idle. Roots queued while a batch
+/// runs join that batch and its summary.
+pub(crate) fn enqueue_roots(
+    app: &AppHandle,
+    root_paths: Vec<String>,
+) -> CommandResult<IndexQueueStatus> {
+    let mut queue = lock_queue(app)?;
+    let idle = queue.running.is_none() && queue.pending.is_empty();
+    for root_path in root_paths {
+        if queue.running.as_ref() == Some(&root_path) || queue.pending.contains(&root_path) {
+            continue;
+        }
+        queue.pending.push_back(root_path);
+    }
+    if idle && !queue.pending.is_empty() {
+        queue.started_at_ms = now_ms();
+        queue.results.clear();
+        queue.running = queue.pending.pop_front();
+        let app = app.clone();
+        tauri::async_runtime::spawn_blocking(move || run_index_queue(app));
+    }
+    Ok(queue_status(&queue))
+}
+
+/// Indexes queued roots until none are left. Each run reports its own
+/// `index-progress` events; the queue adds one event per finished root and a
+/// summary once the batch is done.
+fn run_index_queue(app: AppHandle) {
+    loop {
+        let root_path = match lock_queue(&app) {
+            Ok(queue) => queue.running.clone(),
+            Err(_) => None,
+        };
+        let Some(root_path) = root_path else {
+            return;
+        };
+
+        let result = match index_root_blocking(app.clone(), root_path.clone()) {
+            Ok(stats) => IndexQueueRootResult {
+                root_path,
+                stats: Some(stats),
+                error: None,
+            },
+            Err(error) => {
+                eprintln!("Queued index of '{root_path}' failed: {error}");
+                IndexQueueRootResult {
+                    root_path,
+                    stats: None,
+                    error: Some(error),
+                }
+            }
+        };
+
+        let Ok(mut queue) = lock_queue(&app) else {
+            return;
+        };
+        let _ = app.emit(
+            INDEX_QUEUE_PROGRESS_EVENT,
+            IndexQueueProgress {
+                root_path: result.root_path.clone(),
+                error: result.error.clone(),
+                completed: queue.results.len() + 1,
+                total: queue.results.len() + 1 + queue.pending.len(),
+            },
+        );
+        queue.results.push(result);
+        queue.running = queue.pending.pop_front();
+        if queue.running.is_none() {
+            let results = std::mem::take(&mut queue.results);
+            let summary = IndexQueueSummary {
+                indexed: results
+                    .iter()
+                    .filter(|result| result.error.is_none())
+                    .count(),
+                failed: results
+                    .iter()
+                    .filter(|result| result.error.is_some())
+                    .count(),
+                elapsed_ms: now_ms() - queue.started_at_ms,
+                results,
+            };
+            drop(queue);
+            let _ = app.emit(INDEX_QUEUE_FINISHED_EVENT, summary);
+            return;
+        }
+    }
+}
@@ -0,0 +1,123 @@
+use rusqlite::{params, Connection};
+
+use crate::errors::CommandError;
+use crate::util::{now_ms, wildcard_match};
+use crate::CommandResult;
+
+const RULE_KIND_EXCLUDE: &str = "exclude";
+
+/// A root's exclusion globs, matched against paths relative to the root.
+/// A pattern without `/` matches a folder or file name at any depth
+/// (`_archive`, `old tubs`, `*backup*`); one with `/` matches from the root:
+/// the path itself, everything under it, or by glob when it contains `*`.
+/// Case and slash direction are ignored.
+#[derive(Default)]
+pub(crate) struct ExclusionRules {
+    name_patterns: Vec<String>,
+    path_patterns: Vec<String>,
+}
+
+impl ExclusionRules {
+    pub(crate) fn new(patterns: &[String]) -> Self {
+        let mut rules = ExclusionRules::default();
+        for pattern in patterns {
+            let pattern = pattern.to_lowercase();
+            if pattern.contains('/') {
+                rules.path_patterns.push(pattern);
+            } else {
+                rules.name_patterns.push(pattern);
+            }
+        }
+        rules
+    }
+
+    pub(crate) fn excludes(&self, relative_path: &str) -> bool {
+        if self.name_patterns.is_empty() && self.path_patterns.is_empty() {
+            return false;
+        }
+        let relative_path = relative_path.replace('\\', "/").to_lowercase();
+        let name_excluded = relative_path.split('/').any(|name| {
+            self.name_patterns
+                .iter()
+                .any(|pattern| wildcard_match(pattern, name))
+        });
+        name_excluded
+            || self.path_patterns.iter().any(|pattern| {
+                if pattern.contains('*') {
+                    wildcard_match(pattern, &relative_path)
+                } else {
+                    relative_path == *pattern || relative_path.starts_with(&format!("{pattern}/"))
+                }
+            })
+    }
+}
+
+/// Trims a pattern and its slashes and unifies slash direction; `None` when
+/// nothing is left.
+fn normalize_exclusion(pattern: &str) -> Option<String> {
+    let pattern = pattern.trim().replace('\\', "/");
+    let pattern = pattern.trim_matches('/');
+    (!pattern.is_empty()).then(|| pattern.to_string())
+}
+
+pub(crate) fn load_root_exclusions(
+    connection: &Connection,
+    root_id: i64,
+) -> CommandResult<Vec<String>> {
+    let mut statement = connection
+        .prepare_cached(
+            "SELECT pattern FROM index_rules WHERE root_id = ?1 AND kind = ?2 ORDER BY pattern",
+        )
+        .map_err(|error| {
+            CommandError::database(format!("Could not prepare exclusion query: {error}"))
+        })?;
+    let rows = statement
+        .query_map(params![root_id, RULE_KIND_EXCLUDE], |row| {
+            row.get::<_, String>(0)
+        })
+        .map_err(|error| CommandError::database(format!("Could not load exclusions: {error}")))?;
+    let mut patterns = Vec::new();
+    for row in rows {
+        patterns.push(row.map_err(|error| {
+            CommandError::database(format!("Could not parse exclusion row: {error}"))
+        })?);
+    }
+    Ok(patterns)
+}
+
+/// Replaces a root's exclusion globs and returns them as stored. They apply
+/// from the next index run, which drops files they now exclude.
+pub(crate) fn replace_root_exclusions(
+    connection: &mut Connection,
+    root_id: i64,
+    patterns: &[String],
+) -> CommandResult<Vec<String>> {
+    let transaction = connection.transaction().map_err(|error| {
+        CommandError::database(format!("Could not start exclusion update: {error}"))
+    })?;
+    transaction
+        .execute(
+            "DELETE FROM index_rules WHERE root_id = ?1 AND kind = ?2",
+            params![root_id, RULE_KIND_EXCLUDE],
+        )
+        .map_err(|error| CommandError::database(format!("Could not clear exclusions: {error}")))?;
+    let created_at_ms = now_ms();
+    for pattern in patterns
+        .iter()
+        .filter_map(|pattern| normalize_exclusion(pattern))
+    {
+        transaction
+            .execute(
+                "INSERT OR IGNORE INTO index_rules(root_id, kind, pattern, created_at_ms)
+                 VALUES(?1, ?2, ?3, ?4)",
+                params![root_id, RULE_KIND_EXCLUDE, pattern, created_at_ms],
+            )
+            .map_err(|error| {
+                CommandError::database(format!("Could not store exclusion '{pattern}': {error}"))
+            })?;
+    }
+    transaction
+        .commit()
+        .map_err(|error| CommandError::database(format!("Could not commit exclusions: {error}")))?;
+    load_root_exclusions(connection, root_id)
+}
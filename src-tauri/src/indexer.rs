@@ -2,10 +2,17 @@ use tauri::AppHandle;
 
 use crate::db::open_database;
 use crate::lexical;
+use crate::operations::OperationHandle;
 use crate::CommandResult;
 
 pub(crate) fn rebuild_lexical_index(app: &AppHandle) -> CommandResult<()> {
-    let connection = open_database(app)?;
-    lexical::replace_all_documents_from_connection(app, &connection)?;
-    Ok(())
+    let mut operation = OperationHandle::start(app, "lexical-rebuild", None, false);
+    let result = open_database(app).and_then(|connection| {
+        lexical::replace_all_documents_from_connection(app, &connection, &mut operation)
+    });
+    match &result {
+        Ok(()) => operation.finish(),
+        Err(error) => operation.fail(error),
+    }
+    result
 }
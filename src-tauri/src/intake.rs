@@ -0,0 +1,237 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering as AtomicOrdering};
+use std::time::Duration;
+
+use rusqlite::{params, Connection};
+use tauri::{AppHandle, Emitter};
+
+use crate::commands;
+use crate::db::open_database;
+use crate::errors::CommandError;
+use crate::types::{IntakeMovedEvent, RootIntake};
+use crate::util::{epoch_ms, now_ms, path_display, unique_docx_path};
+use crate::CommandResult;
+
+pub(crate) const INTAKE_MOVED_EVENT: &str = "intake-files-moved";
+const INTAKE_POLL_INTERVAL_SECS: u64 = 3;
+
+static INTAKE_WATCHER_STARTED: AtomicBool = AtomicBool::new(false);
+
+pub(crate) fn list_root_intakes(connection: &Connection) -> CommandResult<Vec<RootIntake>> {
+    let mut statement = connection
+        .prepare(
+            "
+            SELECT r.path, i.intake_path, i.target_folder, i.last_moved_at_ms
+            FROM root_intake i
+            JOIN roots r ON r.id = i.root_id
+            ORDER BY r.path
+            ",
+        )
+        .map_err(|error| {
+            CommandError::database(format!("Could not prepare intake query: {error}"))
+        })?;
+    let rows = statement
+        .query_map([], |row| {
+            Ok(RootIntake {
+                root_path: row.get(0)?,
+                intake_path: row.get(1)?,
+                target_folder: row.get(2)?,
+                last_moved_at_ms: row.get(3)?,
+            })
+        })
+        .map_err(|error| CommandError::database(format!("Could not run intake query: {error}")))?;
+    let mut intakes = Vec::new();
+    for row in rows {
+        intakes.push(row.map_err(|error| {
+            CommandError::database(format!("Could not parse intake row: {error}"))
+        })?);
+    }
+    Ok(intakes)
+}
+
+pub(crate) fn upsert_root_intake(
+    connection: &Connection,
+    root_id: i64,
+    intake_path: &str,
+    target_folder: &str,
+) -> CommandResult<()> {
+    connection
+        .execute(
+            "
+            INSERT INTO root_intake(root_id, intake_path, target_folder, updated_at_ms)
+            VALUES(?1, ?2, ?3, ?4)
+            ON CONFLICT(root_id) DO UPDATE SET
+              intake_path = excluded.intake_path,
+              target_folder = excluded.target_folder,
+              updated_at_ms = excluded.updated_at_ms
+            ",
+            params![root_id, intake_path, target_folder, now_ms()],
+        )
+        .map_err(|error| {
+            CommandError::database(format!("Could not store intake folder: {error}"))
+        })?;
+    Ok(())
+}
+
+pub(crate) fn delete_root_intake(connection: &Connection, root_id: i64) -> CommandResult<bool> {
+    let removed = connection
+        .execute(
+            "DELETE FROM root_intake WHERE root_id = ?1",
+            params![root_id],
+        )
+        .map_err(|error| {
+            CommandError::database(format!("Could not remove intake folder: {error}"))
+        })?;
+    Ok(removed > 0)
+}
+
+fn is_intake_candidate(path: &Path) -> bool {
+    let Some(name) = path.file_name().map(|name| name.to_string_lossy()) else {
+        return false;
+    };
+    path.is_file()
+        && !name.starts_with('.')
+        && !name.starts_with("~$")
+        && path
+            .extension()
+            .map(|extension| extension.eq_ignore_ascii_case("docx"))
+            .unwrap_or(false)
+}
+
+/// Renames into the root, falling back to copy + delete when the intake sits
+/// on another volume.
+fn move_into_root(source: &Path, destination: &Path) -> CommandResult<()> {
+    if fs::rename(source, destination).is_ok() {
+        return Ok(());
+    }
+    fs::copy(source, destination).map_err(|error| {
+        CommandError::io(format!(
+            "Could not copy '{}' into the root: {error}",
+            path_display(source)
+        ))
+    })?;
+    fs::remove_file(source).map_err(|error| {
+        CommandError::io(format!(
+            "Could not remove '{}' from the intake folder: {error}",
+            path_display(source)
+        ))
+    })
+}
+
+/// Moves every settled docx out of one intake folder. A file is settled once
+/// its size and modified time match the previous poll, so half-finished
+/// downloads are left alone.
+fn drain_intake(
+    intake: &RootIntake,
+    previous: &mut HashMap<PathBuf, (u64, i64)>,
+) -> CommandResult<Vec<String>> {
+    let intake_dir = Path::new(&intake.intake_path);
+    let Ok(entries) = fs::read_dir(intake_dir) else {
+        return Ok(Vec::new());
+    };
+    let target_dir = Path::new(&intake.root_path).join(&intake.target_folder);
+
+    let mut moved = Vec::new();
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if !is_intake_candidate(&path) {
+            continue;
+        }
+        let Ok(metadata) = entry.metadata() else {
+            continue;
+        };
+        let signature = (
+            metadata.len(),
+            metadata.modified().map(epoch_ms).unwrap_or_default(),
+        );
+        if previous.insert(path.clone(), signature) != Some(signature) {
+            continue;
+        }
+
+        fs::create_dir_all(&target_dir).map_err(|error| {
+            CommandError::io(format!(
+                "Could not create intake target '{}': {error}",
+                path_display(&target_dir)
+            ))
+        })?;
+        let Some(file_name) = path.file_name() else {
+            continue;
+        };
+        let destination = unique_docx_path(target_dir.join(file_name));
+        previous.remove(&path);
+        if let Err(error) = move_into_root(&path, &destination) {
+            eprintln!("{error}");
+            continue;
+        }
+        moved.push(path_display(&destination));
+    }
+    Ok(moved)
+}
+
+fn mark_intake_moved(connection: &Connection, root_path: &str) -> CommandResult<()> {
+    connection
+        .execute(
+            "
+            UPDATE root_intake
+            SET last_moved_at_ms = ?1
+            WHERE root_id = (SELECT id FROM roots WHERE path = ?2)
+            ",
+            params![now_ms(), root_path],
+        )
+        .map_err(|error| {
+            CommandError::database(format!("Could not update intake timestamp: {error}"))
+        })?;
+    Ok(())
+}
+
+fn poll_intakes(app: &AppHandle, previous: &mut HashMap<PathBuf, (u64, i64)>) -> CommandResult<()> {
+    let connection = open_database(app)?;
+    for intake in list_root_intakes(&connection)? {
+        let moved = match drain_intake(&intake, previous) {
+            Ok(moved) => moved,
+            Err(error) => {
+                eprintln!("Intake for '{}' failed: {error}", intake.root_path);
+                continue;
+            }
+        };
+        if moved.is_empty() {
+            continue;
+        }
+
+        mark_intake_moved(&connection, &intake.root_path)?;
+        let _ = app.emit(
+            INTAKE_MOVED_EVENT,
+            IntakeMovedEvent {
+                root_path: intake.root_path.clone(),
+                moved_paths: moved,
+            },
+        );
+        if let Err(error) = commands::index_root_blocking(app.clone(), intake.root_path.clone()) {
+            eprintln!(
+                "Indexing after intake for '{}' failed: {error}",
+                intake.root_path
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Starts the background intake poller once per process. The intake list is
+/// re-read every poll, so configuration changes apply without a restart.
+pub(crate) fn start_intake_watcher(app: &AppHandle) {
+    if INTAKE_WATCHER_STARTED.swap(true, AtomicOrdering::SeqCst) {
+        return;
+    }
+    let app = app.clone();
+    std::thread::spawn(move || {
+        let mut previous = HashMap::new();
+        loop {
+            if let Err(error) = poll_intakes(&app, &mut previous) {
+                eprintln!("Intake poll failed: {error}");
+            }
+            std::thread::sleep(Duration::from_secs(INTAKE_POLL_INTERVAL_SECS));
+        }
+    });
+}
@@ -1,11 +1,12 @@
 use std::collections::HashSet;
 use std::fs;
+use std::ops::Bound;
 use std::sync::{Mutex, OnceLock};
 use std::time::Instant;
 
 use rusqlite::Connection;
 use tantivy::collector::TopDocs;
-use tantivy::query::{BooleanQuery, Occur, Query, QueryParser, TermQuery};
+use tantivy::query::{BooleanQuery, EmptyQuery, Occur, Query, QueryParser, RangeQuery, TermQuery};
 use tantivy::schema::{
     Field, IndexRecordOption, NumericOptions, Schema, TextFieldIndexing, TextOptions, Value,
     STORED, STRING, TEXT,
@@ -15,12 +16,20 @@ use tantivy::{doc, Index, IndexReader, ReloadPolicy, TantivyDocument, Term};
 use tauri::AppHandle;
 
 use crate::db::index_lexical_dir;
-use crate::search::normalize_for_search;
-use crate::types::SearchHit;
+use crate::errors::CommandError;
+use crate::operations::OperationHandle;
+use crate::query_syntax::parse_query;
+use crate::search::{ensure_current_search, normalize_for_search, SearchFilters, SearchGeneration};
+use crate::types::{SearchHit, SearchSettings};
 use crate::CommandResult;
 
 const PREFIX_TOKENIZER: &str = "bf_prefix";
 const NGRAM_TOKENIZER: &str = "bf_ngram";
+/// Score base of the strict tier, which every other result type's base in
+/// `SearchSettings` is placed around.
+const STRICT_SCORE_BASE: f64 = 1_000.0;
+/// Most hits one search collects; only paged searches go past a screenful.
+pub(crate) const MAX_LEXICAL_HITS: usize = 2_000;
 const MIN_FETCH_MULTIPLIER: usize = 5;
 const MIN_FETCH_FLOOR: usize = 80;
 const MAX_FETCH_LIMIT: usize = 1_800;
@@ -111,10 +120,12 @@ fn has_required_fields(schema: &Schema) -> bool {
 }
 
 fn register_tokenizers(index: &Index) -> CommandResult<()> {
-    let prefix_tokenizer = NgramTokenizer::new(2, 18, true)
-        .map_err(|error| format!("Could not build lexical prefix tokenizer: {error}"))?;
-    let ngram_tokenizer = NgramTokenizer::new(3, 4, false)
-        .map_err(|error| format!("Could not build lexical ngram tokenizer: {error}"))?;
+    let prefix_tokenizer = NgramTokenizer::new(2, 18, true).map_err(|error| {
+        CommandError::internal(format!("Could not build lexical prefix tokenizer: {error}"))
+    })?;
+    let ngram_tokenizer = NgramTokenizer::new(3, 4, false).map_err(|error| {
+        CommandError::internal(format!("Could not build lexical ngram tokenizer: {error}"))
+    })?;
 
     index.tokenizers().register(
         PREFIX_TOKENIZER,
@@ -132,9 +143,9 @@ fn register_tokenizers(index: &Index) -> CommandResult<()> {
 }
 
 fn field(schema: &Schema, name: &str) -> CommandResult<Field> {
-    schema
-        .get_field(name)
-        .map_err(|error| format!("Missing lexical schema field '{name}': {error}"))
+    schema.get_field(name).map_err(|error| {
+        CommandError::internal(format!("Missing lexical schema field '{name}': {error}"))
+    })
 }
 
 fn lexical_fields(schema: &Schema) -> CommandResult<LexicalFields> {
@@ -161,10 +172,10 @@ fn init_runtime(app: &AppHandle) -> CommandResult<LexicalRuntime> {
     let schema = build_schema();
     let path = index_lexical_dir(app)?;
     fs::create_dir_all(&path).map_err(|error| {
-        format!(
+        CommandError::io(format!(
             "Could not create lexical index directory '{}': {error}",
             path.display()
-        )
+        ))
     })?;
 
     let recreate = match Index::open_in_dir(&path) {
@@ -175,16 +186,18 @@ fn init_runtime(app: &AppHandle) -> CommandResult<LexicalRuntime> {
     let index = if recreate {
         let _ = fs::remove_dir_all(&path);
         fs::create_dir_all(&path).map_err(|error| {
-            format!(
+            CommandError::io(format!(
                 "Could not reset lexical index directory '{}': {error}",
                 path.display()
-            )
+            ))
         })?;
-        Index::create_in_dir(&path, schema.clone())
-            .map_err(|error| format!("Could not recreate lexical index: {error}"))?
+        Index::create_in_dir(&path, schema.clone()).map_err(|error| {
+            CommandError::database(format!("Could not recreate lexical index: {error}"))
+        })?
     } else {
-        Index::open_in_dir(&path)
-            .map_err(|error| format!("Could not open lexical index: {error}"))?
+        Index::open_in_dir(&path).map_err(|error| {
+            CommandError::database(format!("Could not open lexical index: {error}"))
+        })?
     };
 
     register_tokenizers(&index)?;
@@ -193,7 +206,9 @@ fn init_runtime(app: &AppHandle) -> CommandResult<LexicalRuntime> {
         .reader_builder()
         .reload_policy(ReloadPolicy::Manual)
         .try_into()
-        .map_err(|error| format!("Could not build lexical index reader: {error}"))?;
+        .map_err(|error| {
+            CommandError::database(format!("Could not build lexical index reader: {error}"))
+        })?;
 
     Ok(LexicalRuntime {
         index,
@@ -210,7 +225,21 @@ fn lexical_runtime(app: &AppHandle) -> CommandResult<&'static Mutex<LexicalRunti
     let _ = LEXICAL_RUNTIME.set(Mutex::new(runtime));
     LEXICAL_RUNTIME
         .get()
-        .ok_or_else(|| "Could not initialize lexical runtime".to_string())
+        .ok_or_else(|| CommandError::internal("Could not initialize lexical runtime"))
+}
+
+/// Reopens the lexical index from the active profile's folder. Nothing to do
+/// when no search has opened it yet.
+pub(crate) fn reload_runtime(app: &AppHandle) -> CommandResult<()> {
+    let Some(runtime) = LEXICAL_RUNTIME.get() else {
+        return Ok(());
+    };
+    let reloaded = init_runtime(app)?;
+    let mut runtime = runtime
+        .lock()
+        .map_err(|_| CommandError::internal("Could not lock lexical runtime"))?;
+    *runtime = reloaded;
+    Ok(())
 }
 
 fn field_text(document: &TantivyDocument, field: Field) -> Option<String> {
@@ -298,6 +327,9 @@ fn build_hit(
         heading_text,
         heading_order,
         score,
+        snippet: None,
+        word_count: None,
+        underlined_word_count: None,
     })
 }
 
@@ -367,32 +399,38 @@ fn add_document_to_writer(
     }
 
     writer.add_document(document).map_err(|error| {
-        format!(
+        CommandError::database(format!(
             "Could not add lexical document for '{}': {error}",
             entry.relative_path
-        )
+        ))
     })?;
     Ok(())
 }
 
+const LEXICAL_REBUILD_STAGES: usize = 5;
+
 pub(crate) fn replace_all_documents_from_connection(
     app: &AppHandle,
     connection: &Connection,
+    operation: &mut OperationHandle,
 ) -> CommandResult<()> {
     let runtime = lexical_runtime(app)?;
     let runtime = runtime
         .lock()
-        .map_err(|_| "Could not lock lexical runtime".to_string())?;
+        .map_err(|_| CommandError::internal("Could not lock lexical runtime"))?;
 
     let mut writer = runtime
         .index
         .writer(LEXICAL_WRITER_HEAP_BYTES)
-        .map_err(|error| format!("Could not create lexical index writer: {error}"))?;
+        .map_err(|error| {
+            CommandError::database(format!("Could not create lexical index writer: {error}"))
+        })?;
 
-    writer
-        .delete_all_documents()
-        .map_err(|error| format!("Could not clear lexical index: {error}"))?;
+    writer.delete_all_documents().map_err(|error| {
+        CommandError::database(format!("Could not clear lexical index: {error}"))
+    })?;
 
+    operation.update("files", 0, Some(LEXICAL_REBUILD_STAGES), None);
     {
         let mut statement = connection
             .prepare(
@@ -402,7 +440,11 @@ pub(crate) fn replace_all_documents_from_connection(
                 ORDER BY root_id ASC, relative_path ASC
                 ",
             )
-            .map_err(|error| format!("Could not prepare lexical file rows query: {error}"))?;
+            .map_err(|error| {
+                CommandError::database(format!(
+                    "Could not prepare lexical file rows query: {error}"
+                ))
+            })?;
 
         let rows = statement
             .query_map([], |row| {
@@ -413,11 +455,14 @@ pub(crate) fn replace_all_documents_from_connection(
                     row.get::<_, String>(3)?,
                 ))
             })
-            .map_err(|error| format!("Could not read lexical file rows: {error}"))?;
+            .map_err(|error| {
+                CommandError::database(format!("Could not read lexical file rows: {error}"))
+            })?;
 
         for row in rows {
-            let (root_id, file_id, relative_path, absolute_path) =
-                row.map_err(|error| format!("Could not parse lexical file row: {error}"))?;
+            let (root_id, file_id, relative_path, absolute_path) = row.map_err(|error| {
+                CommandError::database(format!("Could not parse lexical file row: {error}"))
+            })?;
             let file_name = crate::util::file_name_from_relative(&relative_path);
             let entry = LexicalDocument {
                 root_id,
@@ -436,6 +481,7 @@ pub(crate) fn replace_all_documents_from_connection(
         }
     }
 
+    operation.update("headings", 1, Some(LEXICAL_REBUILD_STAGES), None);
     {
         let mut statement = connection
             .prepare(
@@ -453,7 +499,11 @@ pub(crate) fn replace_all_documents_from_connection(
                 ORDER BY f.root_id ASC, f.id ASC, h.heading_order ASC
                 ",
             )
-            .map_err(|error| format!("Could not prepare lexical heading rows query: {error}"))?;
+            .map_err(|error| {
+                CommandError::database(format!(
+                    "Could not prepare lexical heading rows query: {error}"
+                ))
+            })?;
 
         let rows = statement
             .query_map([], |row| {
@@ -467,7 +517,9 @@ pub(crate) fn replace_all_documents_from_connection(
                     row.get::<_, i64>(6)?,
                 ))
             })
-            .map_err(|error| format!("Could not read lexical heading rows: {error}"))?;
+            .map_err(|error| {
+                CommandError::database(format!("Could not read lexical heading rows: {error}"))
+            })?;
 
         for row in rows {
             let (
@@ -478,7 +530,9 @@ pub(crate) fn replace_all_documents_from_connection(
                 level,
                 heading_text,
                 heading_order,
-            ) = row.map_err(|error| format!("Could not parse lexical heading row: {error}"))?;
+            ) = row.map_err(|error| {
+                CommandError::database(format!("Could not parse lexical heading row: {error}"))
+            })?;
             let file_name = crate::util::file_name_from_relative(&relative_path);
             let entry = LexicalDocument {
                 root_id,
@@ -497,6 +551,7 @@ pub(crate) fn replace_all_documents_from_connection(
         }
     }
 
+    operation.update("authors", 2, Some(LEXICAL_REBUILD_STAGES), None);
     {
         let mut statement = connection
             .prepare(
@@ -513,7 +568,11 @@ pub(crate) fn replace_all_documents_from_connection(
                 ORDER BY f.root_id ASC, f.id ASC, a.author_order ASC
                 ",
             )
-            .map_err(|error| format!("Could not prepare lexical author rows query: {error}"))?;
+            .map_err(|error| {
+                CommandError::database(format!(
+                    "Could not prepare lexical author rows query: {error}"
+                ))
+            })?;
 
         let rows = statement
             .query_map([], |row| {
@@ -526,11 +585,15 @@ pub(crate) fn replace_all_documents_from_connection(
                     row.get::<_, i64>(5)?,
                 ))
             })
-            .map_err(|error| format!("Could not read lexical author rows: {error}"))?;
+            .map_err(|error| {
+                CommandError::database(format!("Could not read lexical author rows: {error}"))
+            })?;
 
         for row in rows {
             let (root_id, file_id, relative_path, absolute_path, author_text, author_order) =
-                row.map_err(|error| format!("Could not parse lexical author row: {error}"))?;
+                row.map_err(|error| {
+                    CommandError::database(format!("Could not parse lexical author row: {error}"))
+                })?;
             let file_name = crate::util::file_name_from_relative(&relative_path);
             let entry = LexicalDocument {
                 root_id,
@@ -549,6 +612,7 @@ pub(crate) fn replace_all_documents_from_connection(
         }
     }
 
+    operation.update("chunks", 3, Some(LEXICAL_REBUILD_STAGES), None);
     {
         let mut statement = connection
             .prepare(
@@ -567,7 +631,11 @@ pub(crate) fn replace_all_documents_from_connection(
                 ORDER BY root_id ASC, file_id ASC, chunk_order ASC
                 ",
             )
-            .map_err(|error| format!("Could not prepare lexical chunk rows query: {error}"))?;
+            .map_err(|error| {
+                CommandError::database(format!(
+                    "Could not prepare lexical chunk rows query: {error}"
+                ))
+            })?;
 
         let rows = statement
             .query_map([], |row| {
@@ -583,7 +651,9 @@ pub(crate) fn replace_all_documents_from_connection(
                     row.get::<_, String>(8)?,
                 ))
             })
-            .map_err(|error| format!("Could not read lexical chunk rows: {error}"))?;
+            .map_err(|error| {
+                CommandError::database(format!("Could not read lexical chunk rows: {error}"))
+            })?;
 
         for row in rows {
             let (
@@ -596,7 +666,9 @@ pub(crate) fn replace_all_documents_from_connection(
                 heading_order,
                 author_text,
                 chunk_text,
-            ) = row.map_err(|error| format!("Could not parse lexical chunk row: {error}"))?;
+            ) = row.map_err(|error| {
+                CommandError::database(format!("Could not parse lexical chunk row: {error}"))
+            })?;
 
             if chunk_text.trim().is_empty() {
                 continue;
@@ -620,39 +692,97 @@ pub(crate) fn replace_all_documents_from_connection(
         }
     }
 
-    writer
-        .commit()
-        .map_err(|error| format!("Could not commit lexical index: {error}"))?;
+    operation.update("committing", 4, Some(LEXICAL_REBUILD_STAGES), None);
+    writer.commit().map_err(|error| {
+        CommandError::database(format!("Could not commit lexical index: {error}"))
+    })?;
 
-    let segment_ids = runtime
-        .index
-        .searchable_segment_ids()
-        .map_err(|error| format!("Could not read lexical segment IDs: {error}"))?;
+    let segment_ids = runtime.index.searchable_segment_ids().map_err(|error| {
+        CommandError::database(format!("Could not read lexical segment IDs: {error}"))
+    })?;
     if segment_ids.len() > 1 {
-        writer
-            .merge(&segment_ids)
-            .wait()
-            .map_err(|error| format!("Could not compact lexical segments: {error}"))?;
+        writer.merge(&segment_ids).wait().map_err(|error| {
+            CommandError::database(format!("Could not compact lexical segments: {error}"))
+        })?;
     }
-    writer
-        .wait_merging_threads()
-        .map_err(|error| format!("Could not finalize lexical merge threads: {error}"))?;
+    writer.wait_merging_threads().map_err(|error| {
+        CommandError::database(format!("Could not finalize lexical merge threads: {error}"))
+    })?;
 
-    runtime
-        .reader
-        .reload()
-        .map_err(|error| format!("Could not reload lexical reader: {error}"))?;
+    runtime.reader.reload().map_err(|error| {
+        CommandError::database(format!("Could not reload lexical reader: {error}"))
+    })?;
 
     Ok(())
 }
 
+/// Must clauses narrowing every tier to `filters`. Stored kinds differ from
+/// hit kinds: chunk documents surface as `heading` hits.
+fn filter_clauses(fields: &LexicalFields, filters: &SearchFilters) -> Vec<(Occur, Box<dyn Query>)> {
+    let mut clauses = Vec::new();
+    if filters.has_level_bounds() {
+        let lower = filters
+            .min_level
+            .map(Bound::Included)
+            .unwrap_or(Bound::Unbounded);
+        let upper = filters
+            .max_level
+            .map(Bound::Included)
+            .unwrap_or(Bound::Unbounded);
+        let level_query: Box<dyn Query> = Box::new(RangeQuery::new_i64_bounds(
+            "heading_level".to_string(),
+            lower,
+            upper,
+        ));
+        clauses.push((Occur::Must, level_query));
+    }
+    if filters.kinds.is_some() {
+        let kind_clauses = ["file", "heading", "author"]
+            .into_iter()
+            .filter(|kind| filters.allows_kind(kind))
+            .flat_map(|kind| match kind {
+                "heading" => vec!["heading", "chunk"],
+                _ => vec![kind],
+            })
+            .map(|stored_kind| {
+                let term = Term::from_field_text(fields.kind, stored_kind);
+                let term_query: Box<dyn Query> =
+                    Box::new(TermQuery::new(term, IndexRecordOption::Basic));
+                (Occur::Should, term_query)
+            })
+            .collect::<Vec<_>>();
+        // With no indexed kind allowed (say, body hits only) nothing matches.
+        let kind_query: Box<dyn Query> = if kind_clauses.is_empty() {
+            Box::new(EmptyQuery)
+        } else {
+            Box::new(BooleanQuery::new(kind_clauses))
+        };
+        clauses.push((Occur::Must, kind_query));
+    }
+    clauses
+}
+
+/// How `search` narrows and ranks hits, and the search generation it runs for
+/// so a superseded search stops between tiers.
+pub(crate) struct LexicalSearchOptions<'a> {
+    pub filters: &'a SearchFilters,
+    pub settings: &'a SearchSettings,
+    pub generation: Option<SearchGeneration>,
+}
+
 pub(crate) fn search(
     app: &AppHandle,
     query: &str,
     requested_root_id: Option<i64>,
     limit: usize,
     file_name_only: bool,
+    options: &LexicalSearchOptions,
 ) -> CommandResult<Vec<SearchHit>> {
+    let LexicalSearchOptions {
+        filters,
+        settings,
+        generation,
+    } = *options;
     let started = Instant::now();
     let normalized = normalize_for_search(query);
     if normalized.is_empty() {
@@ -663,7 +793,7 @@ pub(crate) fn search(
     let (index, searcher, runtime_fields) = {
         let runtime = runtime
             .lock()
-            .map_err(|_| "Could not lock lexical runtime".to_string())?;
+            .map_err(|_| CommandError::internal("Could not lock lexical runtime"))?;
         (
             runtime.index.clone(),
             runtime.reader.searcher(),
@@ -671,7 +801,7 @@ pub(crate) fn search(
         )
     };
 
-    let target_limit = limit.clamp(10, 400);
+    let target_limit = limit.clamp(10, MAX_LEXICAL_HITS);
     let mut results = Vec::with_capacity(target_limit);
     let mut seen = HashSet::with_capacity(target_limit.saturating_mul(2));
 
@@ -720,6 +850,10 @@ pub(crate) fn search(
                     conjunction: bool|
      -> CommandResult<Vec<TantivyDocument>> {
         let mut parser = QueryParser::for_index(&index, query_fields);
+        parser.set_field_boost(runtime_fields.heading_text, settings.heading_weight as f32);
+        parser.set_field_boost(runtime_fields.author_text, settings.author_weight as f32);
+        parser.set_field_boost(runtime_fields.file_name, settings.file_weight as f32);
+        parser.set_field_boost(runtime_fields.relative_path, settings.file_weight as f32);
         if conjunction {
             parser.set_conjunction_by_default();
         }
@@ -727,58 +861,97 @@ pub(crate) fn search(
             Ok(parsed) => parsed,
             Err(_) => return Ok(Vec::new()),
         };
-        let query: Box<dyn Query> = if let Some(root_id) = requested_root_id {
+        let mut clauses = filter_clauses(&runtime_fields, filters);
+        if let Some(root_id) = requested_root_id {
             let Ok(root_id_u64) = u64::try_from(root_id) else {
                 return Ok(Vec::new());
             };
             let root_term = Term::from_field_u64(runtime_fields.root_id, root_id_u64);
             let root_query: Box<dyn Query> =
                 Box::new(TermQuery::new(root_term, IndexRecordOption::Basic));
-            Box::new(BooleanQuery::new(vec![
-                (Occur::Must, parsed),
-                (Occur::Must, root_query),
-            ]))
-        } else {
+            clauses.push((Occur::Must, root_query));
+        }
+        let query: Box<dyn Query> = if clauses.is_empty() {
             parsed
+        } else {
+            clauses.push((Occur::Must, parsed));
+            Box::new(BooleanQuery::new(clauses))
         };
 
         let docs = searcher
             .search(&query, &TopDocs::with_limit(fetch_limit))
-            .map_err(|error| format!("Lexical search execution failed: {error}"))?;
+            .map_err(|error| {
+                CommandError::database(format!("Lexical search execution failed: {error}"))
+            })?;
         let mut output = Vec::with_capacity(docs.len());
         for (_score, address) in docs {
-            let doc = searcher
-                .doc::<TantivyDocument>(address)
-                .map_err(|error| format!("Could not read lexical result document: {error}"))?;
+            let doc = searcher.doc::<TantivyDocument>(address).map_err(|error| {
+                CommandError::database(format!("Could not read lexical result document: {error}"))
+            })?;
             output.push(doc);
         }
         Ok(output)
     };
 
-    let mut tiers = vec![(normalized.clone(), strict_fields, true, 1_000.0_f64)];
-    if !file_name_only {
-        tiers.push((normalized.clone(), recall_fields, false, 1_450.0_f64));
-    }
-    tiers.push((
-        normalized
-            .split_whitespace()
-            .map(|token| format!("{token}*"))
-            .collect::<Vec<String>>()
-            .join(" "),
-        prefix_fields,
-        true,
-        2_000.0_f64,
-    ));
-    if !ngram_fields.is_empty() {
+    let syntax = parse_query(query);
+    let mut tiers = Vec::new();
+    if syntax.has_syntax {
+        // Phrases, OR, exclusions and field prefixes run as written; the
+        // prefix and fuzzy tiers would drop them.
+        let file_fields = if file_name_only {
+            vec!["file_name"]
+        } else {
+            vec!["file_name", "relative_path"]
+        };
+        let Some(query_text) = syntax.to_tantivy(&["author_text"], &file_fields) else {
+            return Ok(Vec::new());
+        };
+        tiers.push((query_text.clone(), strict_fields, false, STRICT_SCORE_BASE));
+        if !file_name_only {
+            tiers.push((query_text, recall_fields, false, settings.recall_score_base));
+        }
+    } else {
+        tiers.push((normalized.clone(), strict_fields, true, STRICT_SCORE_BASE));
+        if !file_name_only {
+            tiers.push((
+                normalized.clone(),
+                recall_fields,
+                false,
+                settings.recall_score_base,
+            ));
+        }
+        let prefix_min_chars = usize::try_from(settings.prefix_min_chars).unwrap_or(0);
         tiers.push((
-            ngrams_for_query(&normalized),
-            ngram_fields,
-            false,
-            3_000.0_f64,
+            normalized
+                .split_whitespace()
+                .map(|token| {
+                    if token.chars().count() < prefix_min_chars {
+                        token.to_string()
+                    } else {
+                        format!("{token}*")
+                    }
+                })
+                .collect::<Vec<String>>()
+                .join(" "),
+            prefix_fields,
+            true,
+            settings.prefix_score_base,
         ));
+        let fuzzy_min_chars = usize::try_from(settings.fuzzy_min_chars).unwrap_or(0);
+        let query_chars = normalized.chars().filter(|ch| *ch != ' ').count();
+        if !ngram_fields.is_empty() && query_chars >= fuzzy_min_chars {
+            tiers.push((
+                ngrams_for_query(&normalized),
+                ngram_fields,
+                false,
+                settings.fuzzy_score_base,
+            ));
+        }
     }
 
     for (query_text, tier_fields, conjunction, score_base) in tiers {
+        // A newer search has started; skip the remaining (and slowest) tiers.
+        ensure_current_search(generation)?;
         if query_text.trim().is_empty() {
             continue;
         }
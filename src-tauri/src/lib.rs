@@ -1,46 +1,278 @@
+mod access;
+mod authors;
+mod block_pack;
+mod body_index;
+mod capture_edit;
+mod capture_outline;
+mod capture_replay;
+mod capture_toc;
+mod capture_trash;
+mod capture_writer;
 mod chunking;
+mod citations;
+mod cite_index;
+mod cli;
 mod commands;
+mod conflicts;
+mod csv_export;
+mod cut_queue;
 mod db;
+mod discovery;
+mod doc_parse;
+mod document_parser;
+mod docx_build;
+mod docx_cache;
 mod docx_capture;
 mod docx_parse;
+mod errors;
+mod event_feed;
+mod file_activity;
+mod file_stats;
+mod google_drive;
+mod history;
+mod html_import;
+mod index_dump;
+mod index_feed;
+mod index_queue;
+mod index_rules;
 mod indexer;
+mod intake;
 mod lexical;
+mod links;
+mod local_api;
+mod markdown;
+mod media_parts;
+mod merge_template;
+mod messages;
+mod near_duplicates;
+mod notes;
+mod numbering;
+mod odt_import;
+mod operations;
+mod outline;
+mod pandoc;
+mod pdf_export;
+mod pdf_parse;
 mod preview;
+mod profiles;
+mod profiling;
 mod query_engine;
+mod query_syntax;
+mod root_relocation;
+mod root_settings;
+mod rtf_import;
 mod search;
+mod search_compile;
+mod search_settings;
 mod semantic;
+mod session;
+mod shards;
+mod shared_roots;
+mod similar_cards;
+mod site_export;
+mod snapshot_cache;
+mod tags;
+mod text_cache;
 mod types;
+mod usage;
 mod util;
+mod validation;
+mod vault_export;
 mod vector;
+mod verbatim_export;
+mod watch;
+mod xlsx_export;
+mod xml_write;
 
-pub(crate) type CommandResult<T> = Result<T, String>;
+use errors::CommandError;
+
+pub(crate) type CommandResult<T> = Result<T, CommandError>;
 
 pub(crate) const DEFAULT_CAPTURE_TARGET: &str = "BlockFile-Captures.docx";
 
+fn app_context() -> tauri::Context {
+    tauri::generate_context!()
+}
+
+/// Handles `blockfile index|search|export ...` without opening a window.
+/// Returns `None` when the arguments are not a CLI invocation, in which case
+/// the caller should start the desktop app with `run`.
+pub fn run_cli() -> Option<i32> {
+    let args = std::env::args().collect::<Vec<String>>();
+    if !cli::is_cli_invocation(&args) {
+        return None;
+    }
+
+    let mut context = app_context();
+    context.config_mut().app.windows.clear();
+    let app = match tauri::Builder::default()
+        .manage(index_queue::IndexQueueState::default())
+        .build(context)
+    {
+        Ok(app) => app,
+        Err(error) => {
+            eprintln!("Could not start BlockFile: {error}");
+            return Some(1);
+        }
+    };
+    messages::restore_locale(app.handle());
+    Some(cli::run_cli(app.handle(), args))
+}
+
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
     tauri::Builder::default()
         .plugin(tauri_plugin_opener::init())
         .plugin(tauri_plugin_dialog::init())
+        .manage(index_queue::IndexQueueState::default())
+        .setup(|app| {
+            messages::restore_locale(app.handle());
+            local_api::restore_local_api(app.handle());
+            intake::start_intake_watcher(app.handle());
+            watch::restore_root_watches(app.handle());
+            Ok(())
+        })
         .invoke_handler(tauri::generate_handler![
             commands::add_root,
             commands::remove_root,
+            commands::relocate_root,
             commands::insert_capture,
+            commands::insert_captures_batch,
             commands::list_capture_targets,
             commands::get_capture_target_preview,
             commands::add_capture_heading,
             commands::delete_capture_heading,
+            commands::flush_capture_target,
+            commands::edit_capture_heading,
+            commands::get_capture_target_settings,
+            commands::set_capture_target_settings,
+            commands::set_capture_heading_level,
+            commands::list_captures,
+            commands::reinsert_capture,
+            commands::list_capture_trash,
+            commands::restore_capture_heading,
+            commands::export_block_pack,
+            commands::export_share_bundle,
+            commands::import_html_document,
+            commands::create_document_from_clipboard,
+            commands::list_path_mappings,
+            commands::add_path_mapping,
+            commands::remove_path_mapping,
+            commands::list_allowed_locations,
+            commands::add_allowed_location,
+            commands::remove_allowed_location,
+            commands::resolve_shared_file_path,
+            commands::get_portable_link,
+            commands::open_portable_link,
+            commands::reveal_in_file_manager,
+            commands::open_externally,
+            commands::record_file_open,
+            commands::set_file_pinned,
+            commands::list_recent_files,
+            commands::list_pinned_files,
+            commands::import_block_pack,
+            commands::export_outline_opml,
+            commands::export_file_markdown,
+            commands::export_heading_pdf,
+            commands::export_marked_text,
+            commands::export_static_site,
+            commands::export_obsidian_vault,
+            commands::export_headings_csv,
+            commands::export_authors_csv,
+            commands::export_captures_csv,
+            commands::export_snapshot_xlsx,
+            commands::export_index_feed,
+            commands::export_index,
+            commands::import_index,
+            commands::import_tags_csv,
+            commands::list_file_tags,
+            commands::add_tag,
+            commands::remove_tag,
+            commands::list_tags,
+            commands::generate_merged_document,
+            commands::replay_capture_log,
+            commands::export_search_results_docx,
+            commands::export_search_results,
+            commands::get_google_drive_status,
+            commands::configure_google_drive,
+            commands::connect_google_drive,
+            commands::disconnect_google_drive,
+            commands::upload_to_google_drive,
+            commands::get_local_api_status,
+            commands::start_local_api,
+            commands::stop_local_api,
+            commands::regenerate_local_api_token,
+            commands::get_pandoc_status,
+            commands::set_pandoc_path,
+            commands::export_capture_target_with_pandoc,
+            commands::export_capture_outline,
+            commands::list_intakes,
+            commands::set_root_intake,
+            commands::set_root_exclusions,
+            commands::get_root_exclusions,
+            commands::remove_root_intake,
+            commands::list_watched_roots,
+            commands::watch_root,
+            commands::unwatch_root,
+            commands::add_cut_queue_item,
+            commands::list_cut_queue,
+            commands::update_cut_queue_item,
+            commands::delete_cut_queue_item,
+            commands::get_near_duplicate_headings,
+            commands::find_similar,
+            commands::estimate_speech_length,
+            commands::audit_citations,
+            commands::start_link_check,
+            commands::get_link_report,
+            commands::cancel_operation,
+            commands::cancel_index,
+            commands::list_operations,
+            commands::list_profiles,
+            commands::create_profile,
+            commands::switch_profile,
+            commands::get_performance_report,
+            commands::set_performance_profiling,
+            commands::get_locale_settings,
+            commands::set_locale,
+            commands::get_usage_metrics,
+            commands::set_usage_metrics_enabled,
+            commands::purge_usage_metrics,
+            commands::get_session_state,
+            commands::set_session_location,
+            commands::set_session_capture_target,
             commands::move_capture_heading,
+            commands::reorder_capture_headings,
             commands::list_roots,
             commands::index_root,
+            commands::index_all_roots,
+            commands::get_root_stats,
+            commands::get_index_queue_status,
+            commands::get_index_settings,
+            commands::set_index_settings,
+            commands::get_search_settings,
+            commands::set_search_settings,
+            commands::get_root_settings,
+            commands::set_root_settings,
+            commands::list_authors,
+            commands::set_root_sharding,
+            commands::rebuild_root_index,
             commands::get_index_snapshot,
+            commands::get_sync_conflict_report,
+            commands::resolve_sync_conflicts,
             commands::get_file_preview,
+            commands::get_file_outline,
+            commands::get_file_history,
             commands::get_heading_preview_html,
             commands::search_index,
             commands::search_index_semantic,
             commands::search_index_hybrid,
             commands::benchmark_root_performance
         ])
-        .run(tauri::generate_context!())
-        .expect("error while running tauri application");
+        .build(app_context())
+        .expect("error while building tauri application")
+        .run(|_, event| {
+            if let tauri::RunEvent::Exit = event {
+                capture_writer::flush_all_capture_targets();
+            }
+        });
 }
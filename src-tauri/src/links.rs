@@ -0,0 +1,452 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs::File;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering as AtomicOrdering};
+use std::time::{Duration, Instant};
+
+use rayon::prelude::*;
+use roxmltree::{Document, Node};
+use rusqlite::{params, Connection};
+use tauri::AppHandle;
+use zip::ZipArchive;
+
+use crate::db::open_database;
+use crate::docx_capture::parse_relationships;
+use crate::docx_parse::{attribute_value, has_tag, read_zip_file};
+use crate::errors::CommandError;
+use crate::operations::OperationHandle;
+use crate::shards::schema_for_root;
+use crate::types::{ExtractedLink, FileLinkReport, LinkStatusEntry};
+use crate::util::{file_name_from_relative, now_ms, path_display};
+use crate::CommandResult;
+
+pub(crate) const LINK_STATUS_OK: &str = "ok";
+pub(crate) const LINK_STATUS_BROKEN: &str = "broken";
+pub(crate) const LINK_STATUS_UNREACHABLE: &str = "unreachable";
+pub(crate) const DEFAULT_LINK_HOST_INTERVAL_MS: u64 = 1_000;
+pub(crate) const DEFAULT_LINK_RECHECK_AFTER_MS: i64 = 7 * 24 * 60 * 60 * 1_000;
+const LINK_REQUEST_TIMEOUT_SECS: u64 = 15;
+const LINK_USER_AGENT: &str = "BlockFile link checker";
+
+static LINK_CHECK_IN_FLIGHT: AtomicBool = AtomicBool::new(false);
+
+fn trim_url_token(token: &str) -> &str {
+    token
+        .trim_start_matches(['(', '[', '<', '"', '\''])
+        .trim_end_matches(['.', ',', ';', ':', ')', ']', '>', '"', '\''])
+}
+
+fn hyperlink_text(node: Node<'_, '_>) -> String {
+    node.descendants()
+        .filter(|child| has_tag(*child, "t"))
+        .filter_map(|child| child.text())
+        .collect::<String>()
+        .trim()
+        .to_string()
+}
+
+/// Collects external hyperlinks (from `w:hyperlink` relationships) and bare
+/// http(s) URLs typed into paragraph text. Paragraph orders match
+/// `parse_docx_paragraphs`, so links can be tied back to headings.
+pub(crate) fn extract_docx_links(file_path: &Path) -> CommandResult<Vec<ExtractedLink>> {
+    let file = File::open(file_path).map_err(|error| {
+        CommandError::io(format!(
+            "Could not open '{}': {error}",
+            path_display(file_path)
+        ))
+    })?;
+    let mut archive = ZipArchive::new(file).map_err(|error| {
+        CommandError::parse(format!(
+            "Could not read '{}': {error}",
+            path_display(file_path)
+        ))
+    })?;
+    let document_xml = read_zip_file(&mut archive, "word/document.xml").ok_or_else(|| {
+        CommandError::invalid_docx(
+            file_path,
+            format!(
+                "Missing word/document.xml in '{}'. Is this a valid docx file?",
+                path_display(file_path)
+            ),
+        )
+    })?;
+    let relationships = read_zip_file(&mut archive, "word/_rels/document.xml.rels")
+        .map(|xml| parse_relationships(&xml))
+        .unwrap_or_default();
+    let document = Document::parse(&document_xml).map_err(|error| {
+        CommandError::parse(format!(
+            "Could not parse XML in '{}': {error}",
+            path_display(file_path)
+        ))
+    })?;
+
+    let mut links = Vec::new();
+    for (index, paragraph) in document
+        .descendants()
+        .filter(|node| has_tag(*node, "p"))
+        .enumerate()
+    {
+        let order = index as i64 + 1;
+        let mut seen = HashSet::new();
+
+        for hyperlink in paragraph
+            .descendants()
+            .filter(|node| has_tag(*node, "hyperlink"))
+        {
+            let Some(relationship) =
+                attribute_value(hyperlink, "id").and_then(|id| relationships.get(id))
+            else {
+                continue;
+            };
+            let is_external = relationship
+                .target_mode
+                .as_deref()
+                .map(|mode| mode.eq_ignore_ascii_case("External"))
+                .unwrap_or(false);
+            let target = relationship.target.trim();
+            if !is_external || !(target.starts_with("http://") || target.starts_with("https://")) {
+                continue;
+            }
+            if seen.insert(target.to_string()) {
+                links.push(ExtractedLink {
+                    paragraph_order: order,
+                    url: target.to_string(),
+                    link_text: hyperlink_text(hyperlink),
+                });
+            }
+        }
+
+        let text = paragraph
+            .descendants()
+            .filter(|node| has_tag(*node, "t"))
+            .filter_map(|node| node.text())
+            .collect::<String>();
+        for token in text.split_whitespace() {
+            let candidate = trim_url_token(token);
+            if !(candidate.starts_with("http://") || candidate.starts_with("https://")) {
+                continue;
+            }
+            if seen.insert(candidate.to_string()) {
+                links.push(ExtractedLink {
+                    paragraph_order: order,
+                    url: candidate.to_string(),
+                    link_text: String::new(),
+                });
+            }
+        }
+    }
+
+    Ok(links)
+}
+
+fn refresh_root_links(connection: &mut Connection, root_id: i64) -> CommandResult<usize> {
+    let files = {
+        let mut statement = connection
+            .prepare("SELECT id, absolute_path FROM files WHERE root_id = ?1")
+            .map_err(|error| {
+                CommandError::database(format!("Could not prepare link extraction query: {error}"))
+            })?;
+        let rows = statement
+            .query_map(params![root_id], |row| {
+                Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?))
+            })
+            .map_err(|error| {
+                CommandError::database(format!("Could not run link extraction query: {error}"))
+            })?;
+        let mut files = Vec::new();
+        for row in rows {
+            files.push(row.map_err(|error| {
+                CommandError::database(format!("Could not parse link file row: {error}"))
+            })?);
+        }
+        files
+    };
+
+    let extracted = files
+        .par_iter()
+        .map(|(file_id, absolute_path)| {
+            (
+                *file_id,
+                extract_docx_links(Path::new(absolute_path)).unwrap_or_default(),
+            )
+        })
+        .collect::<Vec<(i64, Vec<ExtractedLink>)>>();
+
+    let transaction = connection.transaction().map_err(|error| {
+        CommandError::database(format!("Could not start link transaction: {error}"))
+    })?;
+    let schema = schema_for_root(&transaction, root_id)?;
+    transaction
+        .execute(
+            &format!("DELETE FROM {schema}.file_links WHERE root_id = ?1"),
+            params![root_id],
+        )
+        .map_err(|error| {
+            CommandError::database(format!("Could not clear previous links: {error}"))
+        })?;
+    let mut link_count = 0_usize;
+    {
+        let mut insert = transaction
+            .prepare(&format!(
+                "INSERT INTO {schema}.file_links(root_id, file_id, paragraph_order, url, link_text)
+                 VALUES(?1, ?2, ?3, ?4, ?5)"
+            ))
+            .map_err(|error| {
+                CommandError::database(format!("Could not prepare link insert: {error}"))
+            })?;
+        for (file_id, links) in &extracted {
+            for link in links {
+                insert
+                    .execute(params![
+                        root_id,
+                        file_id,
+                        link.paragraph_order,
+                        link.url,
+                        link.link_text
+                    ])
+                    .map_err(|error| {
+                        CommandError::database(format!("Could not store link: {error}"))
+                    })?;
+                link_count += 1;
+            }
+        }
+    }
+    transaction
+        .commit()
+        .map_err(|error| CommandError::database(format!("Could not commit links: {error}")))?;
+
+    Ok(link_count)
+}
+
+fn urls_due_for_check(
+    connection: &Connection,
+    root_id: i64,
+    checked_before_ms: i64,
+) -> CommandResult<Vec<String>> {
+    let mut statement = connection
+        .prepare(
+            "
+            SELECT DISTINCT l.url
+            FROM file_links l
+            LEFT JOIN link_status s ON s.url = l.url
+            WHERE l.root_id = ?1 AND (s.url IS NULL OR s.checked_at_ms < ?2)
+            ORDER BY l.url ASC
+            ",
+        )
+        .map_err(|error| {
+            CommandError::database(format!("Could not prepare pending link query: {error}"))
+        })?;
+    let rows = statement
+        .query_map(params![root_id, checked_before_ms], |row| {
+            row.get::<_, String>(0)
+        })
+        .map_err(|error| {
+            CommandError::database(format!("Could not run pending link query: {error}"))
+        })?;
+    let mut urls = Vec::new();
+    for row in rows {
+        urls.push(row.map_err(|error| {
+            CommandError::database(format!("Could not parse pending link: {error}"))
+        })?);
+    }
+    Ok(urls)
+}
+
+fn url_host(url: &str) -> String {
+    url.split("://")
+        .nth(1)
+        .and_then(|rest| rest.split(['/', '?', '#']).next())
+        .unwrap_or("")
+        .to_ascii_lowercase()
+}
+
+/// HEAD first; servers that reject HEAD (405/501) get a GET whose body is
+/// never read.
+fn check_url(agent: &ureq::Agent, url: &str) -> (String, Option<i64>, Option<String>) {
+    let response = match agent.head(url).call() {
+        Ok(response) if matches!(response.status().as_u16(), 405 | 501) => agent.get(url).call(),
+        other => other,
+    };
+    match response {
+        Ok(response) => {
+            let code = response.status().as_u16();
+            let status = if code < 400 {
+                LINK_STATUS_OK
+            } else {
+                LINK_STATUS_BROKEN
+            };
+            (status.to_string(), Some(i64::from(code)), None)
+        }
+        Err(error) => (
+            LINK_STATUS_UNREACHABLE.to_string(),
+            None,
+            Some(error.to_string()),
+        ),
+    }
+}
+
+fn store_link_status(
+    connection: &Connection,
+    url: &str,
+    status: &str,
+    status_code: Option<i64>,
+    error: Option<&str>,
+) -> CommandResult<()> {
+    connection
+        .execute(
+            "
+            INSERT INTO link_status(url, status, status_code, error, checked_at_ms)
+            VALUES(?1, ?2, ?3, ?4, ?5)
+            ON CONFLICT(url) DO UPDATE SET
+              status = excluded.status,
+              status_code = excluded.status_code,
+              error = excluded.error,
+              checked_at_ms = excluded.checked_at_ms
+            ",
+            params![url, status, status_code, error, now_ms()],
+        )
+        .map_err(|error| CommandError::database(format!("Could not store link status: {error}")))?;
+    Ok(())
+}
+
+fn run_link_check(
+    app: &AppHandle,
+    root_id: i64,
+    operation: &mut OperationHandle,
+    host_interval: Duration,
+    recheck_after_ms: i64,
+) -> CommandResult<()> {
+    let mut connection = open_database(app)?;
+    operation.update("extracting", 0, None, None);
+    refresh_root_links(&mut connection, root_id)?;
+
+    let urls = urls_due_for_check(&connection, root_id, now_ms() - recheck_after_ms)?;
+    operation.update("checking", 0, Some(urls.len()), None);
+
+    let agent: ureq::Agent = ureq::Agent::config_builder()
+        .timeout_global(Some(Duration::from_secs(LINK_REQUEST_TIMEOUT_SECS)))
+        .http_status_as_error(false)
+        .user_agent(LINK_USER_AGENT)
+        .build()
+        .into();
+    let mut last_request_by_host = HashMap::<String, Instant>::new();
+
+    let total = urls.len();
+    for (index, url) in urls.into_iter().enumerate() {
+        operation.ensure_not_cancelled()?;
+        let host = url_host(&url);
+        if let Some(last_request) = last_request_by_host.get(&host) {
+            let elapsed = last_request.elapsed();
+            if elapsed < host_interval {
+                std::thread::sleep(host_interval - elapsed);
+            }
+        }
+        last_request_by_host.insert(host, Instant::now());
+
+        let (status, status_code, error) = check_url(&agent, &url);
+        store_link_status(&connection, &url, &status, status_code, error.as_deref())?;
+        operation.update("checking", index + 1, Some(total), Some(url));
+    }
+
+    Ok(())
+}
+
+/// Starts a background link check for a root and returns its operation id,
+/// or `None` without doing anything when a check is already running.
+pub(crate) fn trigger_link_check(
+    app: AppHandle,
+    root_id: i64,
+    root_path: String,
+    host_interval_ms: u64,
+    recheck_after_ms: i64,
+) -> Option<String> {
+    if LINK_CHECK_IN_FLIGHT
+        .compare_exchange(false, true, AtomicOrdering::SeqCst, AtomicOrdering::SeqCst)
+        .is_err()
+    {
+        return None;
+    }
+
+    let mut operation = OperationHandle::start(&app, "link-check", Some(root_path), true);
+    let operation_id = operation.id().to_string();
+    tauri::async_runtime::spawn_blocking(move || {
+        match run_link_check(
+            &app,
+            root_id,
+            &mut operation,
+            Duration::from_millis(host_interval_ms),
+            recheck_after_ms,
+        ) {
+            Ok(()) => operation.finish(),
+            Err(error) => {
+                eprintln!("Link check failed: {error}");
+                operation.fail(&error);
+            }
+        }
+        LINK_CHECK_IN_FLIGHT.store(false, AtomicOrdering::SeqCst);
+    });
+    Some(operation_id)
+}
+
+pub(crate) fn link_check_running() -> bool {
+    LINK_CHECK_IN_FLIGHT.load(AtomicOrdering::SeqCst)
+}
+
+pub(crate) fn load_link_report(
+    connection: &Connection,
+    root_id: i64,
+    include_ok: bool,
+) -> CommandResult<Vec<FileLinkReport>> {
+    let mut statement = connection
+        .prepare(
+            "
+            SELECT f.id, f.relative_path, l.url, l.link_text, l.paragraph_order,
+                   s.status, s.status_code, s.error, s.checked_at_ms
+            FROM file_links l
+            JOIN files f ON f.id = l.file_id
+            LEFT JOIN link_status s ON s.url = l.url
+            WHERE l.root_id = ?1 AND (?2 = 1 OR s.status IS NOT NULL AND s.status <> ?3)
+            ORDER BY f.relative_path ASC, l.paragraph_order ASC, l.id ASC
+            ",
+        )
+        .map_err(|error| {
+            CommandError::database(format!("Could not prepare link report query: {error}"))
+        })?;
+    let rows = statement
+        .query_map(params![root_id, include_ok, LINK_STATUS_OK], |row| {
+            Ok((
+                row.get::<_, i64>(0)?,
+                row.get::<_, String>(1)?,
+                LinkStatusEntry {
+                    url: row.get(2)?,
+                    link_text: row.get(3)?,
+                    paragraph_order: row.get(4)?,
+                    status: row.get(5)?,
+                    status_code: row.get(6)?,
+                    error: row.get(7)?,
+                    checked_at_ms: row.get(8)?,
+                },
+            ))
+        })
+        .map_err(|error| {
+            CommandError::database(format!("Could not run link report query: {error}"))
+        })?;
+
+    let mut by_file = BTreeMap::<String, FileLinkReport>::new();
+    for row in rows {
+        let (file_id, relative_path, entry) = row.map_err(|error| {
+            CommandError::database(format!("Could not parse link report row: {error}"))
+        })?;
+        by_file
+            .entry(relative_path.clone())
+            .or_insert_with(|| FileLinkReport {
+                file_id,
+                file_name: file_name_from_relative(&relative_path),
+                relative_path,
+                links: Vec::new(),
+            })
+            .links
+            .push(entry);
+    }
+    Ok(by_file.into_values().collect())
+}
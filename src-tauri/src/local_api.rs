@@ -0,0 +1,585 @@
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering as AtomicOrdering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::Serialize;
+use serde_json::{json, Value};
+use tauri::AppHandle;
+
+use crate::commands;
+use crate::db::open_database;
+use crate::errors::CommandError;
+use crate::event_feed::{disconnect_event_subscribers, serve_event_subscriber};
+use crate::types::{CaptureRequest, LocalApiPreviewRequest, LocalApiSearchRequest, LocalApiStatus};
+use crate::util::{now_ms, query_param, random_token};
+use crate::CommandResult;
+
+pub(crate) const DEFAULT_LOCAL_API_PORT: u16 = 47_321;
+const LOCAL_API_MAX_BODY_BYTES: usize = 8 * 1024 * 1024;
+/// Request line and headers together.
+const LOCAL_API_MAX_HEADER_BYTES: usize = 16 * 1024;
+const LOCAL_API_READ_TIMEOUT_SECS: u64 = 10;
+const EVENTS_PATH: &str = "/v1/events";
+/// Connections served at once, event feed subscribers included. Further
+/// connections are answered with 503 instead of getting a thread each.
+const LOCAL_API_MAX_CONNECTIONS: usize = 16;
+/// Browser origins allowed to call the API. Any other page is refused, so a
+/// website cannot drive the API even if it learns the token.
+const ALLOWED_APP_ORIGINS: [&str; 3] = [
+    "tauri://localhost",
+    "http://tauri.localhost",
+    "https://tauri.localhost",
+];
+
+struct RunningApi {
+    port: u16,
+    stop: Arc<AtomicBool>,
+    listener_thread: JoinHandle<()>,
+}
+
+impl RunningApi {
+    /// Signals the accept loop and waits so the port is free to rebind.
+    fn shutdown(self) {
+        self.stop.store(true, AtomicOrdering::SeqCst);
+        let _ = self.listener_thread.join();
+    }
+}
+
+static RUNNING_API: OnceLock<Mutex<Option<RunningApi>>> = OnceLock::new();
+
+fn running_api() -> &'static Mutex<Option<RunningApi>> {
+    RUNNING_API.get_or_init(|| Mutex::new(None))
+}
+
+struct LocalApiSettings {
+    enabled: bool,
+    port: u16,
+    token: String,
+}
+
+fn load_local_api_settings(connection: &Connection) -> CommandResult<Option<LocalApiSettings>> {
+    connection
+        .query_row(
+            "SELECT enabled, port, token FROM local_api_settings WHERE id = 1",
+            [],
+            |row| {
+                Ok(LocalApiSettings {
+                    enabled: row.get::<_, i64>(0)? != 0,
+                    port: u16::try_from(row.get::<_, i64>(1)?).unwrap_or(DEFAULT_LOCAL_API_PORT),
+                    token: row.get(2)?,
+                })
+            },
+        )
+        .optional()
+        .map_err(|error| {
+            CommandError::database(format!("Could not load local API settings: {error}"))
+        })
+}
+
+fn store_local_api_settings(
+    connection: &Connection,
+    settings: &LocalApiSettings,
+) -> CommandResult<()> {
+    connection
+        .execute(
+            "
+            INSERT INTO local_api_settings(id, enabled, port, token, updated_at_ms)
+            VALUES(1, ?1, ?2, ?3, ?4)
+            ON CONFLICT(id) DO UPDATE SET
+              enabled = excluded.enabled,
+              port = excluded.port,
+              token = excluded.token,
+              updated_at_ms = excluded.updated_at_ms
+            ",
+            params![
+                i64::from(settings.enabled),
+                i64::from(settings.port),
+                settings.token,
+                now_ms()
+            ],
+        )
+        .map_err(|error| {
+            CommandError::database(format!("Could not store local API settings: {error}"))
+        })?;
+    Ok(())
+}
+
+fn settings_or_default(connection: &Connection) -> CommandResult<LocalApiSettings> {
+    match load_local_api_settings(connection)? {
+        Some(settings) => Ok(settings),
+        None => Ok(LocalApiSettings {
+            enabled: false,
+            port: DEFAULT_LOCAL_API_PORT,
+            token: random_token()?,
+        }),
+    }
+}
+
+pub(crate) fn local_api_status(connection: &Connection) -> CommandResult<LocalApiStatus> {
+    let settings = load_local_api_settings(connection)?;
+    let running_port = running_api()
+        .lock()
+        .ok()
+        .and_then(|running| running.as_ref().map(|api| api.port));
+    let port = running_port
+        .or(settings.as_ref().map(|settings| settings.port))
+        .unwrap_or(DEFAULT_LOCAL_API_PORT);
+    Ok(LocalApiStatus {
+        running: running_port.is_some(),
+        enabled: settings
+            .as_ref()
+            .map(|settings| settings.enabled)
+            .unwrap_or(false),
+        port,
+        base_url: format!("http://127.0.0.1:{port}/v1"),
+        events_url: format!("ws://127.0.0.1:{port}{EVENTS_PATH}"),
+        token: settings.map(|settings| settings.token),
+    })
+}
+
+fn tokens_match(expected: &str, provided: &str) -> bool {
+    expected.len() == provided.len()
+        && expected
+            .bytes()
+            .zip(provided.bytes())
+            .fold(0_u8, |diff, (left, right)| diff | (left ^ right))
+            == 0
+}
+
+/// True for the app's own webview and for pages served from this machine's
+/// loopback interface.
+fn is_allowed_origin(origin: &str) -> bool {
+    if ALLOWED_APP_ORIGINS.contains(&origin) {
+        return true;
+    }
+    let Some(host) = origin
+        .strip_prefix("http://")
+        .or_else(|| origin.strip_prefix("https://"))
+    else {
+        return false;
+    };
+    let host = match host.rsplit_once(':') {
+        Some((host, port)) if port.parse::<u16>().is_ok() => host,
+        _ => host,
+    };
+    matches!(host, "127.0.0.1" | "localhost" | "[::1]")
+}
+
+/// Holds one of the server's connection slots until dropped.
+struct ConnectionSlot(Arc<AtomicUsize>);
+
+impl ConnectionSlot {
+    fn acquire(active: &Arc<AtomicUsize>) -> Option<Self> {
+        active
+            .fetch_update(AtomicOrdering::SeqCst, AtomicOrdering::SeqCst, |count| {
+                (count < LOCAL_API_MAX_CONNECTIONS).then_some(count + 1)
+            })
+            .ok()
+            .map(|_| Self(Arc::clone(active)))
+    }
+}
+
+impl Drop for ConnectionSlot {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, AtomicOrdering::SeqCst);
+    }
+}
+
+struct ApiRequest {
+    method: String,
+    path: String,
+    query: String,
+    origin: Option<String>,
+    authorization: Option<String>,
+    websocket_key: Option<String>,
+    body: Vec<u8>,
+}
+
+/// One line of the request head, counted against `LOCAL_API_MAX_HEADER_BYTES`
+/// so a client cannot stream headers without end.
+fn read_head_line(
+    reader: &mut BufReader<&TcpStream>,
+    head_bytes: &mut usize,
+) -> Result<String, (u16, String)> {
+    let remaining = LOCAL_API_MAX_HEADER_BYTES.saturating_sub(*head_bytes);
+    let mut line = String::new();
+    *head_bytes += reader
+        .by_ref()
+        .take(remaining as u64)
+        .read_line(&mut line)
+        .map_err(|error| (400, format!("Could not read request: {error}")))?;
+    if *head_bytes >= LOCAL_API_MAX_HEADER_BYTES && !line.ends_with('\n') {
+        return Err((431, "Request headers are too large.".to_string()));
+    }
+    Ok(line)
+}
+
+fn read_api_request(stream: &TcpStream) -> Result<ApiRequest, (u16, String)> {
+    let mut reader = BufReader::new(stream);
+    let mut head_bytes = 0_usize;
+    let request_line = read_head_line(&mut reader, &mut head_bytes)?;
+    let mut parts = request_line.split_whitespace();
+    let method = parts.next().unwrap_or("").to_ascii_uppercase();
+    let target = parts.next().unwrap_or("/");
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    let (path, query) = (path.to_string(), query.to_string());
+
+    let mut content_length = 0_usize;
+    let mut origin = None;
+    let mut authorization = None;
+    let mut websocket_key = None;
+    loop {
+        let line = read_head_line(&mut reader, &mut head_bytes)?;
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        let Some((name, value)) = line.split_once(':') else {
+            continue;
+        };
+        let value = value.trim();
+        if name.eq_ignore_ascii_case("content-length") {
+            content_length = value
+                .parse()
+                .map_err(|_| (400, "Invalid Content-Length.".to_string()))?;
+        } else if name.eq_ignore_ascii_case("origin") {
+            origin = Some(value.to_string());
+        } else if name.eq_ignore_ascii_case("authorization") {
+            authorization = value
+                .strip_prefix("Bearer ")
+                .or_else(|| value.strip_prefix("bearer "))
+                .map(|token| token.trim().to_string());
+        } else if name.eq_ignore_ascii_case("sec-websocket-key") {
+            websocket_key = Some(value.to_string());
+        }
+    }
+    if content_length > LOCAL_API_MAX_BODY_BYTES {
+        return Err((413, "Request body is too large.".to_string()));
+    }
+
+    let mut body = vec![0_u8; content_length];
+    reader
+        .read_exact(&mut body)
+        .map_err(|error| (400, format!("Could not read request body: {error}")))?;
+    Ok(ApiRequest {
+        method,
+        path,
+        query,
+        origin,
+        authorization,
+        websocket_key,
+        body,
+    })
+}
+
+/// `cors_origin` must already have passed `is_allowed_origin`; it is echoed
+/// back so that origin alone may read the response.
+fn write_api_response(
+    mut stream: &TcpStream,
+    cors_origin: Option<&str>,
+    status: u16,
+    body: &Value,
+) {
+    let reason = match status {
+        200 => "OK",
+        204 => "No Content",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        403 => "Forbidden",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        409 => "Conflict",
+        413 => "Payload Too Large",
+        431 => "Request Header Fields Too Large",
+        503 => "Service Unavailable",
+        _ => "Internal Server Error",
+    };
+    let raw = if status == 204 {
+        String::new()
+    } else {
+        body.to_string()
+    };
+    let cors = cors_origin
+        .map(|origin| {
+            format!(
+                "Access-Control-Allow-Origin: {origin}\r\nVary: Origin\r\nAccess-Control-Allow-Headers: Authorization, Content-Type\r\nAccess-Control-Allow-Methods: GET, POST, OPTIONS\r\n"
+            )
+        })
+        .unwrap_or_default();
+    let _ = stream.write_all(
+        format!(
+            "HTTP/1.1 {status} {reason}\r\nContent-Type: application/json\r\nContent-Length: {}\r\n{cors}Connection: close\r\n\r\n{raw}",
+            raw.len()
+        )
+        .as_bytes(),
+    );
+}
+
+fn parse_body<T: serde::de::DeserializeOwned>(body: &[u8]) -> Result<T, (u16, String)> {
+    serde_json::from_slice(body).map_err(|error| (400, format!("Invalid JSON body: {error}")))
+}
+
+fn error_status(error: &CommandError) -> u16 {
+    match error {
+        CommandError::Validation { .. }
+        | CommandError::Parse { .. }
+        | CommandError::InvalidDocx { .. } => 400,
+        CommandError::AccessDenied { .. } => 403,
+        CommandError::NotIndexed { .. } | CommandError::NotFound { .. } => 404,
+        CommandError::TargetLocked { .. } => 409,
+        _ => 500,
+    }
+}
+
+fn to_json<T: Serialize>(result: CommandResult<T>) -> Result<Value, (u16, String)> {
+    let value = result.map_err(|error| (error_status(&error), error.to_string()))?;
+    serde_json::to_value(value)
+        .map_err(|error| (500, format!("Could not encode response: {error}")))
+}
+
+fn route_api_request(app: &AppHandle, request: &ApiRequest) -> Result<Value, (u16, String)> {
+    match (request.method.as_str(), request.path.as_str()) {
+        ("POST", "/v1/search") => {
+            let body = parse_body::<LocalApiSearchRequest>(&request.body)?;
+            to_json(crate::query_engine::search_lexical(
+                app,
+                &body.query,
+                body.root_path,
+                body.limit,
+                None,
+            ))
+        }
+        ("POST", "/v1/preview") => {
+            let body = parse_body::<LocalApiPreviewRequest>(&request.body)?;
+            match body.heading_order {
+                Some(heading_order) => to_json(
+                    commands::get_heading_preview_html_blocking(
+                        app.clone(),
+                        body.file_id,
+                        heading_order,
+                    )
+                    .map(|html| json!({ "html": html })),
+                ),
+                None => to_json(commands::get_file_preview_blocking(
+                    app.clone(),
+                    body.file_id,
+                )),
+            }
+        }
+        ("POST", "/v1/capture") => {
+            let body = parse_body::<CaptureRequest>(&request.body)?;
+            to_json(commands::insert_capture_blocking(app.clone(), body))
+        }
+        (_, "/v1/search" | "/v1/preview" | "/v1/capture") => {
+            Err((405, "Use POST for this endpoint.".to_string()))
+        }
+        _ => Err((404, format!("Unknown endpoint '{}'.", request.path))),
+    }
+}
+
+fn handle_api_connection(app: &AppHandle, stream: TcpStream, token: &str) {
+    let _ = stream.set_nonblocking(false);
+    let _ = stream.set_read_timeout(Some(Duration::from_secs(LOCAL_API_READ_TIMEOUT_SECS)));
+    let request = match read_api_request(&stream) {
+        Ok(request) => request,
+        Err((status, error)) => {
+            write_api_response(&stream, None, status, &json!({ "error": error }));
+            return;
+        }
+    };
+
+    // Non-browser clients send no Origin; browsers always do, including on
+    // WebSocket upgrades, which CORS does not cover.
+    let cors_origin = request.origin.as_deref();
+    if cors_origin.is_some_and(|origin| !is_allowed_origin(origin)) {
+        write_api_response(
+            &stream,
+            None,
+            403,
+            &json!({ "error": "This origin may not use the local API." }),
+        );
+        return;
+    }
+    if request.method == "OPTIONS" {
+        write_api_response(&stream, cors_origin, 204, &Value::Null);
+        return;
+    }
+    if request.method == "GET" && request.path == "/v1/health" {
+        write_api_response(
+            &stream,
+            cors_origin,
+            200,
+            &json!({ "ok": true, "version": env!("CARGO_PKG_VERSION") }),
+        );
+        return;
+    }
+    // Browsers cannot set headers on a WebSocket, so the feed also accepts
+    // the token as a query parameter.
+    let query_token = (request.path == EVENTS_PATH)
+        .then(|| query_param(&request.query, "token"))
+        .flatten();
+    let authorized = request
+        .authorization
+        .as_deref()
+        .or(query_token.as_deref())
+        .map(|provided| tokens_match(token, provided))
+        .unwrap_or(false);
+    if !authorized {
+        write_api_response(
+            &stream,
+            cors_origin,
+            401,
+            &json!({ "error": "Missing or invalid bearer token." }),
+        );
+        return;
+    }
+
+    if request.path == EVENTS_PATH {
+        match (request.method.as_str(), request.websocket_key.as_deref()) {
+            ("GET", Some(key)) => serve_event_subscriber(stream, key),
+            _ => write_api_response(
+                &stream,
+                cors_origin,
+                400,
+                &json!({ "error": "Connect to the event feed with a WebSocket." }),
+            ),
+        }
+        return;
+    }
+
+    match route_api_request(app, &request) {
+        Ok(value) => write_api_response(&stream, cors_origin, 200, &value),
+        Err((status, error)) => {
+            write_api_response(&stream, cors_origin, status, &json!({ "error": error }))
+        }
+    }
+}
+
+fn spawn_api_server(app: AppHandle, port: u16, token: String) -> CommandResult<()> {
+    let mut running = running_api()
+        .lock()
+        .map_err(|_| CommandError::internal("Local API state is unavailable."))?;
+    if let Some(existing) = running.take() {
+        existing.shutdown();
+    }
+    disconnect_event_subscribers();
+
+    let listener = TcpListener::bind(("127.0.0.1", port)).map_err(|error| {
+        CommandError::io(format!("Could not listen on 127.0.0.1:{port}: {error}"))
+    })?;
+    listener.set_nonblocking(true).map_err(|error| {
+        CommandError::io(format!("Could not configure local API listener: {error}"))
+    })?;
+    let stop = Arc::new(AtomicBool::new(false));
+    let thread_stop = Arc::clone(&stop);
+    let token = Arc::new(token);
+    let active_connections = Arc::new(AtomicUsize::new(0));
+    let listener_thread = std::thread::spawn(move || {
+        while !thread_stop.load(AtomicOrdering::SeqCst) {
+            match listener.accept() {
+                Ok((stream, _)) => {
+                    let Some(slot) = ConnectionSlot::acquire(&active_connections) else {
+                        let _ = stream.set_nonblocking(false);
+                        write_api_response(
+                            &stream,
+                            None,
+                            503,
+                            &json!({ "error": "The local API is busy; try again shortly." }),
+                        );
+                        continue;
+                    };
+                    let app = app.clone();
+                    let token = Arc::clone(&token);
+                    std::thread::spawn(move || {
+                        let _slot = slot;
+                        handle_api_connection(&app, stream, &token);
+                    });
+                }
+                Err(error) if error.kind() == std::io::ErrorKind::WouldBlock => {
+                    std::thread::sleep(Duration::from_millis(100));
+                }
+                Err(error) => {
+                    eprintln!("Local API listener failed: {error}");
+                    break;
+                }
+            }
+        }
+    });
+
+    *running = Some(RunningApi {
+        port,
+        stop,
+        listener_thread,
+    });
+    Ok(())
+}
+
+pub(crate) fn stop_api_server() {
+    if let Ok(mut running) = running_api().lock() {
+        if let Some(existing) = running.take() {
+            existing.shutdown();
+        }
+    }
+    disconnect_event_subscribers();
+}
+
+/// Enables the API, persists the choice, and (re)starts the listener.
+pub(crate) fn enable_local_api(
+    app: &AppHandle,
+    connection: &Connection,
+    port: Option<u16>,
+) -> CommandResult<()> {
+    let mut settings = settings_or_default(connection)?;
+    settings.enabled = true;
+    if let Some(port) = port.filter(|port| *port != 0) {
+        settings.port = port;
+    }
+    spawn_api_server(app.clone(), settings.port, settings.token.clone())?;
+    store_local_api_settings(connection, &settings)
+}
+
+pub(crate) fn disable_local_api(connection: &Connection) -> CommandResult<()> {
+    stop_api_server();
+    let mut settings = settings_or_default(connection)?;
+    settings.enabled = false;
+    store_local_api_settings(connection, &settings)
+}
+
+/// Issues a new token; a running server is restarted so the old token stops
+/// working immediately.
+pub(crate) fn rotate_local_api_token(
+    app: &AppHandle,
+    connection: &Connection,
+) -> CommandResult<()> {
+    let mut settings = settings_or_default(connection)?;
+    settings.token = random_token()?;
+    store_local_api_settings(connection, &settings)?;
+    let running = running_api()
+        .lock()
+        .map(|running| running.is_some())
+        .unwrap_or(false);
+    if running {
+        spawn_api_server(app.clone(), settings.port, settings.token)?;
+    }
+    Ok(())
+}
+
+/// Starts the API at launch when it was left enabled.
+pub(crate) fn restore_local_api(app: &AppHandle) {
+    let settings =
+        match open_database(app).and_then(|connection| load_local_api_settings(&connection)) {
+            Ok(Some(settings)) if settings.enabled => settings,
+            Ok(_) => return,
+            Err(error) => {
+                eprintln!("Could not restore local API: {error}");
+                return;
+            }
+        };
+    if let Err(error) = spawn_api_server(app.clone(), settings.port, settings.token) {
+        eprintln!("Could not restore local API: {error}");
+    }
+}
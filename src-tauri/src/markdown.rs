@@ -0,0 +1,413 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::path::Path;
+
+use roxmltree::{Document, Node};
+use zip::ZipArchive;
+
+use crate::docx_capture::parse_relationships;
+use crate::docx_parse::{
+    attribute_value, build_heading_ranges, has_tag, parse_document_xml_paragraphs, read_zip_file,
+    run_has_active_underline, run_has_property, run_highlight_class,
+};
+use crate::errors::CommandError;
+use crate::types::RelationshipDef;
+use crate::util::path_display;
+use crate::CommandResult;
+
+#[derive(Clone, Copy, PartialEq, Eq, Default)]
+pub(crate) struct InlineStyle {
+    pub bold: bool,
+    pub italic: bool,
+    pub underline: bool,
+    pub highlight: bool,
+}
+
+/// A stretch of paragraph text with one formatting and link.
+pub(crate) struct InlineSegment {
+    pub text: String,
+    pub style: InlineStyle,
+    pub link: Option<String>,
+}
+
+/// A paragraph of a heading section with its formatting kept, for exports that
+/// lay text out themselves rather than copying docx XML.
+pub(crate) struct StyledParagraph {
+    pub heading_level: Option<i64>,
+    pub segments: Vec<InlineSegment>,
+}
+
+pub(crate) struct MarkdownDocument {
+    pub markdown: String,
+    pub heading_count: usize,
+    pub paragraph_count: usize,
+}
+
+fn escape_markdown(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for character in text.chars() {
+        if matches!(
+            character,
+            '\\' | '`' | '*' | '_' | '[' | ']' | '<' | '>' | '#'
+        ) {
+            escaped.push('\\');
+        }
+        escaped.push(character);
+    }
+    escaped
+}
+
+fn run_style(run: Node<'_, '_>) -> InlineStyle {
+    InlineStyle {
+        bold: run_has_property(run, "b"),
+        italic: run_has_property(run, "i"),
+        underline: run_has_active_underline(run),
+        highlight: run_highlight_class(run).is_some(),
+    }
+}
+
+fn push_segment(
+    segments: &mut Vec<InlineSegment>,
+    text: &str,
+    style: InlineStyle,
+    link: &Option<String>,
+) {
+    if text.is_empty() {
+        return;
+    }
+    if let Some(last) = segments.last_mut() {
+        if last.style == style && last.link == *link {
+            last.text.push_str(text);
+            return;
+        }
+    }
+    segments.push(InlineSegment {
+        text: text.to_string(),
+        style,
+        link: link.clone(),
+    });
+}
+
+fn collect_inline_segments(
+    node: Node<'_, '_>,
+    relationships: &HashMap<String, RelationshipDef>,
+    link: &Option<String>,
+    segments: &mut Vec<InlineSegment>,
+) {
+    if !node.is_element() {
+        return;
+    }
+
+    if has_tag(node, "hyperlink") {
+        let target = attribute_value(node, "id")
+            .and_then(|id| relationships.get(id))
+            .map(|relationship| relationship.target.trim().to_string())
+            .filter(|target| !target.is_empty())
+            .or_else(|| attribute_value(node, "anchor").map(|anchor| format!("#{anchor}")));
+        for child in node.children() {
+            collect_inline_segments(child, relationships, &target, segments);
+        }
+        return;
+    }
+
+    if has_tag(node, "r") {
+        let style = run_style(node);
+        for child in node.descendants().filter(|child| child.is_element()) {
+            if has_tag(child, "t") {
+                if let Some(text) = child.text() {
+                    push_segment(segments, text, style, link);
+                }
+            } else if has_tag(child, "tab") {
+                push_segment(segments, " ", style, link);
+            } else if has_tag(child, "br") || has_tag(child, "cr") {
+                push_segment(segments, "\n", InlineStyle::default(), link);
+            }
+        }
+        return;
+    }
+
+    for child in node.children() {
+        collect_inline_segments(child, relationships, link, segments);
+    }
+}
+
+/// Wraps text in Markdown markers while keeping surrounding whitespace outside
+/// them, since `** bold**` is not parsed as emphasis.
+fn wrap_styled(text: &str, style: InlineStyle) -> String {
+    let trimmed = text.trim();
+    if trimmed.is_empty() {
+        return text.to_string();
+    }
+    let leading = &text[..text.len() - text.trim_start().len()];
+    let trailing = &text[text.trim_end().len()..];
+
+    let mut body = escape_markdown(trimmed);
+    if style.underline {
+        body = format!("<u>{body}</u>");
+    }
+    if style.italic {
+        body = format!("*{body}*");
+    }
+    if style.bold {
+        body = format!("**{body}**");
+    }
+    if style.highlight {
+        body = format!("=={body}==");
+    }
+    format!("{leading}{body}{trailing}")
+}
+
+fn render_inline_markdown(segments: &[InlineSegment]) -> String {
+    let mut output = String::new();
+    let mut index = 0;
+    while index < segments.len() {
+        let link = &segments[index].link;
+        let mut body = String::new();
+        while index < segments.len() && segments[index].link == *link {
+            body.push_str(&wrap_styled(&segments[index].text, segments[index].style));
+            index += 1;
+        }
+        match link {
+            Some(target) if !body.trim().is_empty() => {
+                output.push_str(&format!(
+                    "[{}]({})",
+                    body.trim(),
+                    target.replace(' ', "%20")
+                ));
+            }
+            _ => output.push_str(&body),
+        }
+    }
+    output.trim().replace('\n', "  \n")
+}
+
+fn read_document_with_relationships(
+    file_path: &Path,
+) -> CommandResult<(String, HashMap<String, RelationshipDef>)> {
+    let file = File::open(file_path).map_err(|error| {
+        CommandError::io(format!(
+            "Could not open '{}': {error}",
+            path_display(file_path)
+        ))
+    })?;
+    let mut archive = ZipArchive::new(file).map_err(|error| {
+        CommandError::parse(format!(
+            "Could not read '{}': {error}",
+            path_display(file_path)
+        ))
+    })?;
+    let document_xml = read_zip_file(&mut archive, "word/document.xml").ok_or_else(|| {
+        CommandError::invalid_docx(
+            file_path,
+            format!(
+                "Missing word/document.xml in '{}'. Is this a valid docx file?",
+                path_display(file_path)
+            ),
+        )
+    })?;
+    let relationships = read_zip_file(&mut archive, "word/_rels/document.xml.rels")
+        .map(|xml| parse_relationships(&xml))
+        .unwrap_or_default();
+    Ok((document_xml, relationships))
+}
+
+/// Collects the paragraphs of the section under `heading_order`, heading
+/// included, or of the whole file when no heading is given. Returns an empty
+/// list when the heading no longer exists.
+pub(crate) fn load_styled_paragraphs(
+    file_path: &Path,
+    heading_order: Option<i64>,
+) -> CommandResult<Vec<StyledParagraph>> {
+    let paragraphs = parse_document_xml_paragraphs(file_path)?;
+    let (start, end) = match heading_order {
+        Some(order) => {
+            let Some(range) = build_heading_ranges(&paragraphs)
+                .into_iter()
+                .find(|range| range.order == order)
+            else {
+                return Ok(Vec::new());
+            };
+            (range.start_index, range.end_index)
+        }
+        None => (0, paragraphs.len()),
+    };
+    let (document_xml, relationships) = read_document_with_relationships(file_path)?;
+    let document = Document::parse(&document_xml).map_err(|error| {
+        CommandError::parse(format!(
+            "Could not parse XML in '{}': {error}",
+            path_display(file_path)
+        ))
+    })?;
+
+    let mut section = Vec::new();
+    for (paragraph_node, paragraph_meta) in document
+        .descendants()
+        .filter(|node| has_tag(*node, "p"))
+        .zip(paragraphs.iter())
+        .skip(start)
+        .take(end.saturating_sub(start))
+    {
+        let mut segments = Vec::new();
+        for child in paragraph_node.children() {
+            collect_inline_segments(child, &relationships, &None, &mut segments);
+        }
+        section.push(StyledParagraph {
+            heading_level: paragraph_meta.heading_level,
+            segments,
+        });
+    }
+    Ok(section)
+}
+
+/// Converts a docx into Markdown: heading paragraphs become `#` headings,
+/// everything else becomes a paragraph with bold, italic, underline,
+/// highlight (`==mark==`) and hyperlinks preserved.
+pub(crate) fn render_docx_markdown(file_path: &Path) -> CommandResult<MarkdownDocument> {
+    let paragraphs = parse_document_xml_paragraphs(file_path)?;
+    let (document_xml, relationships) = read_document_with_relationships(file_path)?;
+    let document = Document::parse(&document_xml).map_err(|error| {
+        CommandError::parse(format!(
+            "Could not parse XML in '{}': {error}",
+            path_display(file_path)
+        ))
+    })?;
+
+    let mut blocks = Vec::new();
+    let mut heading_count = 0;
+    let mut paragraph_count = 0;
+    for (paragraph_node, paragraph_meta) in document
+        .descendants()
+        .filter(|node| has_tag(*node, "p"))
+        .zip(paragraphs.iter())
+    {
+        if paragraph_meta.text.trim().is_empty() {
+            continue;
+        }
+
+        if let Some(level) = paragraph_meta.heading_level {
+            heading_count += 1;
+            blocks.push(format!(
+                "{} {}",
+                "#".repeat(level.clamp(1, 6) as usize),
+                escape_markdown(paragraph_meta.text.trim())
+            ));
+            continue;
+        }
+
+        let mut segments = Vec::new();
+        for child in paragraph_node.children() {
+            collect_inline_segments(child, &relationships, &None, &mut segments);
+        }
+        let rendered = render_inline_markdown(&segments);
+        if rendered.is_empty() {
+            continue;
+        }
+        paragraph_count += 1;
+        blocks.push(rendered);
+    }
+
+    let mut markdown = blocks.join("\n\n");
+    markdown.push('\n');
+    Ok(MarkdownDocument {
+        markdown,
+        heading_count,
+        paragraph_count,
+    })
+}
+
+/// Moves trailing whitespace out of the text so a closing marker sits right
+/// after the last word, returning the whitespace to re-append afterwards.
+fn split_trailing_whitespace(output: &mut String) -> String {
+    let trimmed = output.trim_end().len();
+    output.split_off(trimmed)
+}
+
+/// Renders a paragraph as plain text with underlined and highlighted stretches
+/// wrapped in the given markers (an empty marker leaves that formatting out).
+/// Highlight wraps underline, and adjacent runs share one pair of markers.
+fn render_marked_paragraph(
+    segments: &[InlineSegment],
+    underline_marker: &str,
+    highlight_marker: &str,
+) -> String {
+    let mut output = String::new();
+    let (mut underline_open, mut highlight_open) = (false, false);
+    for segment in segments {
+        if segment.text.trim().is_empty() {
+            output.push_str(&segment.text);
+            continue;
+        }
+        let highlight = segment.style.highlight && !highlight_marker.is_empty();
+        let underline = segment.style.underline && !underline_marker.is_empty();
+        let close_underline = underline_open && (!underline || highlight != highlight_open);
+        let close_highlight = highlight_open && !highlight;
+        if close_underline || close_highlight {
+            let whitespace = split_trailing_whitespace(&mut output);
+            if close_underline {
+                output.push_str(underline_marker);
+                underline_open = false;
+            }
+            if close_highlight {
+                output.push_str(highlight_marker);
+                highlight_open = false;
+            }
+            output.push_str(&whitespace);
+        }
+
+        let text = segment.text.as_str();
+        let body = text.trim_start();
+        output.push_str(&text[..text.len() - body.len()]);
+        if highlight && !highlight_open {
+            output.push_str(highlight_marker);
+            highlight_open = true;
+        }
+        if underline && !underline_open {
+            output.push_str(underline_marker);
+            underline_open = true;
+        }
+        output.push_str(body);
+    }
+    let whitespace = split_trailing_whitespace(&mut output);
+    if underline_open {
+        output.push_str(underline_marker);
+    }
+    if highlight_open {
+        output.push_str(highlight_marker);
+    }
+    output.push_str(&whitespace);
+    output.trim().to_string()
+}
+
+/// Joins styled paragraphs into plain text for apps that cannot show rich
+/// formatting. Headings stay unmarked and get a blank line before them.
+pub(crate) fn render_marked_text(
+    paragraphs: &[StyledParagraph],
+    underline_marker: &str,
+    highlight_marker: &str,
+) -> (String, usize) {
+    let mut output = String::new();
+    let mut count = 0;
+    for paragraph in paragraphs {
+        let line = if paragraph.heading_level.is_some() {
+            paragraph
+                .segments
+                .iter()
+                .map(|segment| segment.text.as_str())
+                .collect::<String>()
+                .trim()
+                .to_string()
+        } else {
+            render_marked_paragraph(&paragraph.segments, underline_marker, highlight_marker)
+        };
+        if line.is_empty() {
+            continue;
+        }
+        if paragraph.heading_level.is_some() && !output.is_empty() {
+            output.push('\n');
+        }
+        output.push_str(&line);
+        output.push('\n');
+        count += 1;
+    }
+    (output, count)
+}
@@ -0,0 +1,146 @@
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+
+use roxmltree::Document;
+
+use crate::docx_capture::{parse_relationships, xml_escape_attr};
+use crate::docx_parse::{attribute_value, has_tag, read_docx_binary_parts};
+use crate::types::MediaPart;
+use crate::xml_write::rewrite_attribute_values;
+
+const IMAGE_RELATIONSHIP_SUFFIX: &str = "/image";
+const FALLBACK_MEDIA_CONTENT_TYPE: &str = "application/octet-stream";
+
+/// The package part a `word/document.xml` relationship target names.
+pub(crate) fn package_part_name(target: &str) -> String {
+    match target.strip_prefix('/') {
+        Some(absolute) => absolute.to_string(),
+        None => format!("word/{target}"),
+    }
+}
+
+fn part_extension(part_name: &str) -> String {
+    Path::new(part_name)
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .map(str::to_ascii_lowercase)
+        .unwrap_or_else(|| "bin".to_string())
+}
+
+/// The content type `content_types_xml` declares for `part_name`, by
+/// override first and extension second.
+fn declared_content_type(content_types_xml: Option<&str>, part_name: &str) -> Option<String> {
+    let document = Document::parse(content_types_xml?).ok()?;
+    let absolute_name = format!("/{part_name}");
+    let extension = part_extension(part_name);
+    let content_type_of = |tag: &str, key: &str, matches: &dyn Fn(&str) -> bool| {
+        document
+            .descendants()
+            .filter(|node| has_tag(*node, tag))
+            .find(|node| attribute_value(*node, key).is_some_and(matches))
+            .and_then(|node| attribute_value(node, "ContentType"))
+            .map(str::to_string)
+    };
+    content_type_of("Override", "PartName", &|name| {
+        name.eq_ignore_ascii_case(&absolute_name)
+    })
+    .or_else(|| {
+        content_type_of("Default", "Extension", &|value| {
+            value.eq_ignore_ascii_case(&extension)
+        })
+    })
+}
+
+/// Reads the images among a section's relationships out of the source and
+/// renames each after a hash of its bytes. Returns the source relationships
+/// rewritten to the new names, so captures from different sources never
+/// collide on `media/image1.png` and a picture captured twice keeps one part.
+/// Linked (external) images have no part to copy and are left alone.
+pub(crate) fn collect_section_media(
+    source_path: &Path,
+    relationships_xml: &str,
+    content_types_xml: Option<&str>,
+    relationship_ids: &HashSet<String>,
+) -> (String, Vec<MediaPart>) {
+    let source_parts = parse_relationships(relationships_xml)
+        .into_iter()
+        .filter(|(id, definition)| {
+            relationship_ids.contains(id)
+                && definition.rel_type.ends_with(IMAGE_RELATIONSHIP_SUFFIX)
+                && definition.target_mode.is_none()
+        })
+        .map(|(id, definition)| (id, package_part_name(&definition.target)))
+        .collect::<Vec<(String, String)>>();
+    if source_parts.is_empty() {
+        return (relationships_xml.to_string(), Vec::new());
+    }
+
+    let part_names = source_parts
+        .iter()
+        .map(|(_, part_name)| part_name.clone())
+        .collect::<Vec<String>>();
+    let Ok(mut bytes_by_part) = read_docx_binary_parts(source_path, &part_names) else {
+        return (relationships_xml.to_string(), Vec::new());
+    };
+
+    let mut renamed_targets = HashMap::new();
+    let mut media_parts = Vec::<MediaPart>::new();
+    for (id, source_part_name) in source_parts {
+        let Some(bytes) = bytes_by_part.remove(&source_part_name) else {
+            continue;
+        };
+        let hash = blake3::hash(&bytes).to_hex();
+        let file_name = format!(
+            "capture-{}.{}",
+            &hash.as_str()[..16],
+            part_extension(&source_part_name)
+        );
+        renamed_targets.insert(id, format!("media/{file_name}"));
+
+        let part_name = format!("word/media/{file_name}");
+        if media_parts.iter().any(|part| part.part_name == part_name) {
+            continue;
+        }
+        media_parts.push(MediaPart {
+            content_type: declared_content_type(content_types_xml, &source_part_name)
+                .unwrap_or_else(|| FALLBACK_MEDIA_CONTENT_TYPE.to_string()),
+            part_name,
+            bytes,
+        });
+    }
+
+    let relationships_xml = rewrite_attribute_values(relationships_xml, |node, name, _| {
+        if has_tag(node, "Relationship") && name == "Target" {
+            attribute_value(node, "Id").and_then(|id| renamed_targets.get(id).cloned())
+        } else {
+            None
+        }
+    });
+    (relationships_xml, media_parts)
+}
+
+/// Declares a content type for each media part whose extension the package
+/// does not know yet.
+pub(crate) fn register_media_content_types(content_types_xml: &mut String, media: &[MediaPart]) {
+    let mut additions = String::new();
+    let mut added = HashSet::new();
+    for part in media {
+        if declared_content_type(Some(content_types_xml), &part.part_name).is_some() {
+            continue;
+        }
+        let extension = part_extension(&part.part_name);
+        if added.insert(extension.clone()) {
+            additions.push_str(&format!(
+                "<Default Extension=\"{}\" ContentType=\"{}\"/>",
+                xml_escape_attr(&extension),
+                xml_escape_attr(&part.content_type)
+            ));
+        }
+    }
+    if additions.is_empty() {
+        return;
+    }
+    if let Some(close_index) = content_types_xml.rfind("</Types>") {
+        content_types_xml.insert_str(close_index, &additions);
+    }
+}
@@ -0,0 +1,371 @@
+use std::collections::HashMap;
+use std::fs;
+use std::ops::Range;
+use std::path::Path;
+
+use roxmltree::{Document, Node};
+
+use crate::docx_cache::cached_parsed_docx;
+use crate::docx_capture::{
+    extract_styled_section, merge_section_into_parts, rewrite_docx_with_parts, xml_escape_text,
+    EMPTY_RELATIONSHIPS_XML, EMPTY_STYLES_XML,
+};
+use crate::docx_parse::{extract_paragraph_text, has_tag, read_docx_part};
+use crate::errors::CommandError;
+use crate::types::MergeDocumentSkip;
+use crate::util::{is_probable_author_line, path_display};
+use crate::CommandResult;
+
+const FIELD_TAG: &str = "tag";
+const FIELD_CITE: &str = "cite";
+const FIELD_BODY: &str = "body";
+const MERGE_FIELDS: [&str; 3] = [FIELD_TAG, FIELD_CITE, FIELD_BODY];
+
+/// How a template paragraph holding merge fields is filled in. A paragraph
+/// that is nothing but `{{cite}}` or `{{body}}` is replaced by the source
+/// paragraphs so card formatting survives; any other field paragraph keeps
+/// the template's own formatting with the fields swapped for plain text.
+enum FieldParagraph {
+    Source(&'static str),
+    Inline {
+        paragraph_properties: String,
+        run_properties: String,
+        text: String,
+    },
+}
+
+/// A template's body split around the block that repeats once per section.
+struct MergeTemplate {
+    document_xml: String,
+    block: Range<usize>,
+    fields: Vec<(Range<usize>, FieldParagraph)>,
+}
+
+/// A selected heading, split into the parts the merge fields refer to.
+struct MergeSection {
+    tag: String,
+    cite_text: String,
+    cite_xml: Vec<String>,
+    body_text: String,
+    body_xml: Vec<String>,
+}
+
+/// Lowercased names of the `{{field}}`s in a piece of text, in order.
+fn field_names(text: &str) -> Vec<String> {
+    let mut names = Vec::new();
+    let mut rest = text;
+    while let Some(open) = rest.find("{{") {
+        let after_open = &rest[open + 2..];
+        let Some(close) = after_open.find("}}") else {
+            break;
+        };
+        names.push(after_open[..close].trim().to_lowercase());
+        rest = &after_open[close + 2..];
+    }
+    names
+}
+
+fn is_merge_field(name: &str) -> bool {
+    MERGE_FIELDS.contains(&name)
+}
+
+/// The field a paragraph consists of entirely, if any.
+fn sole_field(text: &str) -> Option<String> {
+    let trimmed = text.trim();
+    let inner = trimmed.strip_prefix("{{")?.strip_suffix("}}")?;
+    (!inner.contains("{{") && !inner.contains("}}")).then(|| inner.trim().to_lowercase())
+}
+
+fn substitute_fields(text: &str, section: &MergeSection) -> String {
+    let mut output = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(open) = rest.find("{{") {
+        output.push_str(&rest[..open]);
+        let after_open = &rest[open + 2..];
+        let Some(close) = after_open.find("}}") else {
+            output.push_str(&rest[open..]);
+            return output;
+        };
+        match after_open[..close].trim().to_lowercase().as_str() {
+            FIELD_TAG => output.push_str(&section.tag),
+            FIELD_CITE => output.push_str(&section.cite_text),
+            FIELD_BODY => output.push_str(&section.body_text),
+            _ => output.push_str(&rest[open..open + 2 + close + 2]),
+        }
+        rest = &after_open[close + 2..];
+    }
+    output.push_str(rest);
+    output
+}
+
+fn child_xml(document_xml: &str, node: Node<'_, '_>, tag: &str) -> String {
+    node.children()
+        .find(|child| has_tag(*child, tag))
+        .map(|child| document_xml[child.range()].to_string())
+        .unwrap_or_default()
+}
+
+fn parse_template(template_path: &Path) -> CommandResult<MergeTemplate> {
+    let document_xml = read_docx_part(template_path, "word/document.xml")?.ok_or_else(|| {
+        CommandError::invalid_docx(
+            template_path,
+            format!(
+                "Missing word/document.xml in '{}'. Is this a valid docx file?",
+                path_display(template_path)
+            ),
+        )
+    })?;
+    let document = Document::parse(&document_xml).map_err(|error| {
+        CommandError::parse(format!(
+            "Could not parse template '{}': {error}",
+            path_display(template_path)
+        ))
+    })?;
+    let body = document
+        .descendants()
+        .find(|node| has_tag(*node, "body"))
+        .ok_or_else(|| CommandError::parse("The template has no document body."))?;
+
+    let blocks = body
+        .children()
+        .filter(|node| node.is_element() && !has_tag(*node, "sectPr"))
+        .filter(|node| {
+            node.descendants()
+                .filter(|descendant| has_tag(*descendant, "p"))
+                .any(|paragraph| {
+                    field_names(&extract_paragraph_text(paragraph))
+                        .iter()
+                        .any(|name| is_merge_field(name))
+                })
+        })
+        .collect::<Vec<Node<'_, '_>>>();
+    let (Some(first), Some(last)) = (blocks.first(), blocks.last()) else {
+        return Err(
+            CommandError::validation(
+                "The template has no merge fields. Add {{tag}}, {{cite}} or {{body}} where each section should go.",
+            )
+        );
+    };
+    let block = first.range().start..last.range().end;
+
+    let mut fields = Vec::new();
+    for paragraph in document.descendants().filter(|node| {
+        has_tag(*node, "p") && node.range().start >= block.start && node.range().end <= block.end
+    }) {
+        let text = extract_paragraph_text(paragraph);
+        if !field_names(&text).iter().any(|name| is_merge_field(name)) {
+            continue;
+        }
+        let field = match sole_field(&text).as_deref() {
+            Some(FIELD_CITE) => FieldParagraph::Source(FIELD_CITE),
+            Some(FIELD_BODY) => FieldParagraph::Source(FIELD_BODY),
+            _ => FieldParagraph::Inline {
+                paragraph_properties: child_xml(&document_xml, paragraph, "pPr"),
+                run_properties: paragraph
+                    .children()
+                    .find(|child| has_tag(*child, "r"))
+                    .map(|run| child_xml(&document_xml, run, "rPr"))
+                    .unwrap_or_default(),
+                text,
+            },
+        };
+        let range = paragraph.range();
+        // Paragraphs nested in a text box inside a field paragraph are
+        // replaced along with it.
+        if fields
+            .last()
+            .is_some_and(|(previous, _): &(Range<usize>, FieldParagraph)| {
+                range.start < previous.end + block.start
+            })
+        {
+            continue;
+        }
+        fields.push((range.start - block.start..range.end - block.start, field));
+    }
+
+    Ok(MergeTemplate {
+        document_xml,
+        block,
+        fields,
+    })
+}
+
+fn fill_block(template: &MergeTemplate, section: &MergeSection) -> String {
+    let block_xml = &template.document_xml[template.block.clone()];
+    let mut filled = String::with_capacity(block_xml.len());
+    let mut cursor = 0;
+    for (range, field) in &template.fields {
+        filled.push_str(&block_xml[cursor..range.start]);
+        match field {
+            FieldParagraph::Source(name) => {
+                let paragraphs = if *name == FIELD_CITE {
+                    &section.cite_xml
+                } else {
+                    &section.body_xml
+                };
+                // A cell or body must keep at least one paragraph.
+                if paragraphs.is_empty() {
+                    filled.push_str("<w:p/>");
+                }
+                for paragraph in paragraphs {
+                    filled.push_str(paragraph);
+                }
+            }
+            FieldParagraph::Inline {
+                paragraph_properties,
+                run_properties,
+                text,
+            } => filled.push_str(&format!(
+                "<w:p>{paragraph_properties}<w:r>{run_properties}<w:t xml:space=\"preserve\">{}</w:t></w:r></w:p>",
+                xml_escape_text(&substitute_fields(text, section))
+            )),
+        }
+        cursor = range.end;
+    }
+    filled.push_str(&block_xml[cursor..]);
+    filled
+}
+
+/// Pulls one heading's section out of its source with styles and
+/// relationships merged into the output parts, then splits it into tag,
+/// cite (the first non-empty paragraph after the heading when it looks like
+/// one) and body.
+fn load_merge_section(
+    source_path: &Path,
+    heading_order: i64,
+    styles_xml: &mut String,
+    relationships_xml: &mut String,
+) -> CommandResult<MergeSection> {
+    let parsed = cached_parsed_docx(source_path)?;
+    let paragraphs = &parsed.paragraphs;
+    let start = paragraphs
+        .iter()
+        .position(|paragraph| paragraph.order == heading_order)
+        .filter(|index| paragraphs[*index].heading_level.is_some())
+        .ok_or_else(|| CommandError::not_indexed("That heading no longer exists in the file."))?;
+    let styled = extract_styled_section(source_path, Some(heading_order), "");
+    if !styled.used_source_xml {
+        return Err(CommandError::parse(
+            "Could not read the section's formatting.",
+        ));
+    }
+    let section_xml = merge_section_into_parts(styles_xml, relationships_xml, &styled);
+    let section_paragraphs = &paragraphs[start..(start + section_xml.len()).min(paragraphs.len())];
+
+    let mut remaining = (1..section_xml.len())
+        .skip_while(|index| {
+            section_paragraphs
+                .get(*index)
+                .is_some_and(|paragraph| paragraph.text.trim().is_empty())
+        })
+        .peekable();
+    let cite_index = remaining.peek().copied().filter(|index| {
+        section_paragraphs.get(*index).is_some_and(|paragraph| {
+            paragraph.is_f8_cite || is_probable_author_line(&paragraph.text)
+        })
+    });
+    if cite_index.is_some() {
+        remaining.next();
+    }
+    let body_indices = remaining.collect::<Vec<usize>>();
+
+    let text_of = |indices: &[usize]| {
+        indices
+            .iter()
+            .filter_map(|index| section_paragraphs.get(*index))
+            .map(|paragraph| paragraph.text.trim())
+            .filter(|text| !text.is_empty())
+            .collect::<Vec<&str>>()
+            .join(" ")
+    };
+    let cite_indices = cite_index.into_iter().collect::<Vec<usize>>();
+    Ok(MergeSection {
+        tag: section_paragraphs[0].text.trim().to_string(),
+        cite_text: text_of(&cite_indices),
+        cite_xml: cite_indices
+            .iter()
+            .map(|index| section_xml[*index].clone())
+            .collect(),
+        body_text: text_of(&body_indices),
+        body_xml: body_indices
+            .iter()
+            .map(|index| section_xml[*index].clone())
+            .collect(),
+    })
+}
+
+/// A selected heading to merge, as stored in the index.
+pub(crate) struct MergeSource {
+    pub file_id: i64,
+    pub absolute_path: String,
+    pub heading_order: i64,
+}
+
+/// Fills a copy of the template with one repetition of its merge block per
+/// source section, in the given order. Content before and after the block
+/// appears once, so a template can carry a title page or closing notes
+/// around the cards. Sections that cannot be read are skipped and reported.
+pub(crate) fn generate_merged_document(
+    template_path: &Path,
+    sources: &[MergeSource],
+    output_path: &Path,
+) -> CommandResult<(usize, Vec<MergeDocumentSkip>)> {
+    let template = parse_template(template_path)?;
+    let mut styles_xml = read_docx_part(template_path, "word/styles.xml")?
+        .unwrap_or_else(|| EMPTY_STYLES_XML.to_string());
+    let mut relationships_xml = read_docx_part(template_path, "word/_rels/document.xml.rels")?
+        .unwrap_or_else(|| EMPTY_RELATIONSHIPS_XML.to_string());
+
+    let mut document_xml = String::with_capacity(template.document_xml.len() * 2);
+    document_xml.push_str(&template.document_xml[..template.block.start]);
+    let mut section_count = 0;
+    let mut skipped = Vec::new();
+    for source in sources {
+        match load_merge_section(
+            Path::new(&source.absolute_path),
+            source.heading_order,
+            &mut styles_xml,
+            &mut relationships_xml,
+        ) {
+            Ok(section) => {
+                document_xml.push_str(&fill_block(&template, &section));
+                section_count += 1;
+            }
+            Err(reason) => skipped.push(MergeDocumentSkip {
+                file_id: source.file_id,
+                heading_order: source.heading_order,
+                reason: reason.to_string(),
+            }),
+        }
+    }
+    if section_count == 0 {
+        return Err(CommandError::validation(
+            "None of the selected headings could be merged.",
+        ));
+    }
+    document_xml.push_str(&template.document_xml[template.block.end..]);
+
+    if let Some(parent) = output_path.parent() {
+        fs::create_dir_all(parent).map_err(|error| {
+            CommandError::io(format!(
+                "Could not create output folder '{}': {error}",
+                path_display(parent)
+            ))
+        })?;
+    }
+    fs::copy(template_path, output_path).map_err(|error| {
+        CommandError::io(format!(
+            "Could not copy template to '{}': {error}",
+            path_display(output_path)
+        ))
+    })?;
+    let mut replacements = HashMap::new();
+    replacements.insert("word/document.xml".to_string(), document_xml.into_bytes());
+    replacements.insert("word/styles.xml".to_string(), styles_xml.into_bytes());
+    replacements.insert(
+        "word/_rels/document.xml.rels".to_string(),
+        relationships_xml.into_bytes(),
+    );
+    rewrite_docx_with_parts(output_path, &replacements)?;
+    Ok((section_count, skipped))
+}
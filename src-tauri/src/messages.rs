@@ -0,0 +1,418 @@
+use std::sync::{Mutex, OnceLock};
+
+use rusqlite::{params, Connection, OptionalExtension};
+use tauri::AppHandle;
+
+use crate::db::open_database;
+use crate::errors::CommandError;
+use crate::types::{LocaleOption, LocaleSettings};
+use crate::util::now_ms;
+use crate::CommandResult;
+
+/// Languages the backend can word its user-facing errors and progress in.
+/// Messages that wrap an OS or library error stay in English.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Locale {
+    English,
+    Spanish,
+    French,
+}
+
+impl Locale {
+    const ALL: [Locale; 3] = [Locale::English, Locale::Spanish, Locale::French];
+
+    pub(crate) fn code(self) -> &'static str {
+        match self {
+            Locale::English => "en",
+            Locale::Spanish => "es",
+            Locale::French => "fr",
+        }
+    }
+
+    fn native_name(self) -> &'static str {
+        match self {
+            Locale::English => "English",
+            Locale::Spanish => "Español",
+            Locale::French => "Français",
+        }
+    }
+
+    /// Accepts `es`, `es-MX`, `es_MX` and the like.
+    pub(crate) fn from_code(code: &str) -> Option<Locale> {
+        let language = code.trim().split(['-', '_']).next().unwrap_or_default();
+        Locale::ALL
+            .into_iter()
+            .find(|locale| locale.code().eq_ignore_ascii_case(language))
+    }
+
+    fn catalog(self) -> &'static [(&'static str, &'static str)] {
+        match self {
+            Locale::English => ENGLISH,
+            Locale::Spanish => SPANISH,
+            Locale::French => FRENCH,
+        }
+    }
+}
+
+/// The English catalog is the reference: every key used in the backend must
+/// be here. Other catalogs may lag behind and fall back to English.
+const ENGLISH: &[(&str, &str)] = &[
+    ("error.title.io", "File access failed"),
+    ("error.title.parse", "Could not read the document"),
+    ("error.title.invalid_docx", "Not a Word document"),
+    ("error.title.not_found", "Not found"),
+    ("error.title.not_indexed", "Not indexed"),
+    ("error.title.target_locked", "File in use"),
+    ("error.title.access_denied", "Access denied"),
+    ("error.title.validation", "Invalid input"),
+    ("error.title.database", "Index database error"),
+    ("error.title.cancelled", "Cancelled"),
+    ("error.title.internal", "Unexpected error"),
+    (
+        "error.rootNotIndexed",
+        "No index found for '{path}'. Add the folder first.",
+    ),
+    (
+        "error.headingGone",
+        "That heading no longer exists in the file. Re-index and try again.",
+    ),
+    (
+        "error.targetLocked",
+        "'{path}' is open in another program or not writable. Close it and try again.",
+    ),
+    ("error.cancelled", "Operation '{kind}' was cancelled."),
+    (
+        "error.outsideRoots",
+        "'{path}' is outside every added root and allowed location.",
+    ),
+    ("field.rootPath", "Root folder"),
+    ("field.sourcePath", "Source file"),
+    ("field.targetPath", "Capture target"),
+    ("field.sectionTitle", "Section title"),
+    ("field.content", "Capture content"),
+    ("field.headingLevel", "Heading level"),
+    ("field.headingText", "Heading name"),
+    ("field.title", "Title"),
+    ("field.folder", "Folder"),
+    ("field.tag", "Tag"),
+    ("validation.empty", "{label} cannot be empty."),
+    (
+        "validation.tooLong",
+        "{label} is too long (at most {max} characters).",
+    ),
+    (
+        "validation.tooLarge",
+        "{label} is too large (at most {max} MB).",
+    ),
+    (
+        "validation.levelRange",
+        "{label} must be between 1 and {max}.",
+    ),
+    ("validation.nul", "{label} contains a NUL character."),
+    (
+        "validation.fileNameTooLong",
+        "File name '{name}' is too long (at most {max} bytes).",
+    ),
+    (
+        "validation.fileNameCharacter",
+        "File name '{name}' cannot contain {character}.",
+    ),
+    (
+        "validation.fileNameEnding",
+        "File name '{name}' cannot end with a dot or a space.",
+    ),
+    (
+        "validation.fileNameReserved",
+        "'{name}' is a reserved file name on Windows.",
+    ),
+    (
+        "validation.targetOutsideRoot",
+        "Capture target path cannot use '..' or root-prefix components when relative.",
+    ),
+    (
+        "validation.targetEmpty",
+        "Capture target path cannot be empty.",
+    ),
+    (
+        "validation.folderOutsideRoot",
+        "Folder '{folder}' must be inside the root.",
+    ),
+    (
+        "validation.sourceNotDocx",
+        "'{path}' is not a .docx, .doc, .odt, .rtf or .pdf file.",
+    ),
+    ("phase.starting", "Starting"),
+    ("phase.complete", "Complete"),
+    ("phase.cancelled", "Cancelled"),
+    ("phase.discovering", "Finding files"),
+    ("phase.indexing", "Indexing files"),
+    ("phase.cleaning", "Removing deleted files"),
+    ("phase.files", "Rebuilding file index"),
+    ("phase.headings", "Rebuilding heading index"),
+    ("phase.authors", "Rebuilding author index"),
+    ("phase.body", "Indexing paragraph text"),
+    ("phase.chunks", "Rebuilding text index"),
+    ("phase.committing", "Saving"),
+    ("phase.extracting", "Extracting"),
+    ("phase.checking", "Checking links"),
+    ("phase.awaiting-consent", "Waiting for sign-in"),
+    ("phase.exchanging", "Completing sign-in"),
+    ("phase.authorizing", "Authorizing"),
+    ("phase.uploading", "Uploading"),
+    ("phase.packing", "Packing files"),
+    ("phase.rendering", "Rendering pages"),
+    ("phase.indexes", "Writing folder indexes"),
+    ("phase.replaying", "Replaying captures"),
+    ("phase.compiling", "Compiling results"),
+    ("phase.writing", "Writing files"),
+    ("phase.converting", "Converting"),
+];
+
+const SPANISH: &[(&str, &str)] = &[
+    ("error.title.io", "No se pudo acceder al archivo"),
+    ("error.title.parse", "No se pudo leer el documento"),
+    ("error.title.invalid_docx", "No es un documento de Word"),
+    ("error.title.not_found", "No encontrado"),
+    ("error.title.not_indexed", "Sin indexar"),
+    ("error.title.target_locked", "Archivo en uso"),
+    ("error.title.access_denied", "Acceso denegado"),
+    ("error.title.validation", "Entrada no válida"),
+    ("error.title.database", "Error de la base de datos del índice"),
+    ("error.title.cancelled", "Cancelado"),
+    ("error.title.internal", "Error inesperado"),
+    ("error.rootNotIndexed", "No se encontró un índice para '{path}'. Añade primero la carpeta."),
+    ("error.headingGone", "Ese encabezado ya no existe en el archivo. Vuelve a indexar e inténtalo de nuevo."),
+    ("error.targetLocked", "'{path}' está abierto en otro programa o no se puede escribir. Ciérralo e inténtalo de nuevo."),
+    ("error.cancelled", "Se canceló la operación '{kind}'."),
+    ("error.outsideRoots", "'{path}' está fuera de todas las raíces añadidas y ubicaciones permitidas."),
+    ("field.rootPath", "Carpeta raíz"),
+    ("field.sourcePath", "Archivo de origen"),
+    ("field.targetPath", "Destino de captura"),
+    ("field.sectionTitle", "Título de la sección"),
+    ("field.content", "Contenido de la captura"),
+    ("field.headingLevel", "Nivel de encabezado"),
+    ("field.headingText", "Nombre del encabezado"),
+    ("field.title", "Título"),
+    ("field.folder", "Carpeta"),
+    ("field.tag", "Etiqueta"),
+    ("validation.empty", "{label}: este campo no puede estar vacío."),
+    ("validation.tooLong", "{label}: demasiado largo (máximo {max} caracteres)."),
+    ("validation.tooLarge", "{label}: demasiado grande (máximo {max} MB)."),
+    ("validation.levelRange", "{label}: debe estar entre 1 y {max}."),
+    ("validation.nul", "{label}: contiene un carácter NUL."),
+    ("validation.fileNameTooLong", "El nombre de archivo '{name}' es demasiado largo (máximo {max} bytes)."),
+    ("validation.fileNameCharacter", "El nombre de archivo '{name}' no puede contener {character}."),
+    ("validation.fileNameEnding", "El nombre de archivo '{name}' no puede terminar en punto ni en espacio."),
+    ("validation.fileNameReserved", "'{name}' es un nombre de archivo reservado en Windows."),
+    ("validation.targetOutsideRoot", "La ruta relativa del destino de captura no puede usar '..' ni componentes de raíz."),
+    ("validation.targetEmpty", "La ruta del destino de captura no puede estar vacía."),
+    ("validation.folderOutsideRoot", "La carpeta '{folder}' debe estar dentro de la raíz."),
+    ("validation.sourceNotDocx", "'{path}' no es un archivo .docx, .doc, .odt, .rtf o .pdf."),
+    ("phase.starting", "Iniciando"),
+    ("phase.complete", "Completado"),
+    ("phase.cancelled", "Cancelado"),
+    ("phase.discovering", "Buscando archivos"),
+    ("phase.indexing", "Indexando archivos"),
+    ("phase.cleaning", "Quitando archivos eliminados"),
+    ("phase.files", "Reconstruyendo el índice de archivos"),
+    ("phase.headings", "Reconstruyendo el índice de encabezados"),
+    ("phase.authors", "Reconstruyendo el índice de autores"),
+    ("phase.body", "Indexando el texto de los párrafos"),
+    ("phase.chunks", "Reconstruyendo el índice de texto"),
+    ("phase.committing", "Guardando"),
+    ("phase.extracting", "Extrayendo"),
+    ("phase.checking", "Comprobando enlaces"),
+    ("phase.awaiting-consent", "Esperando el inicio de sesión"),
+    ("phase.exchanging", "Completando el inicio de sesión"),
+    ("phase.authorizing", "Autorizando"),
+    ("phase.uploading", "Subiendo"),
+    ("phase.packing", "Empaquetando archivos"),
+    ("phase.rendering", "Generando páginas"),
+    ("phase.indexes", "Escribiendo índices de carpetas"),
+    ("phase.replaying", "Reproduciendo capturas"),
+    ("phase.compiling", "Compilando resultados"),
+    ("phase.writing", "Escribiendo archivos"),
+    ("phase.converting", "Convirtiendo"),
+];
+
+const FRENCH: &[(&str, &str)] = &[
+    ("error.title.io", "Échec de l'accès au fichier"),
+    ("error.title.parse", "Impossible de lire le document"),
+    ("error.title.invalid_docx", "Pas un document Word"),
+    ("error.title.not_found", "Introuvable"),
+    ("error.title.not_indexed", "Non indexé"),
+    ("error.title.target_locked", "Fichier en cours d'utilisation"),
+    ("error.title.access_denied", "Accès refusé"),
+    ("error.title.validation", "Saisie non valide"),
+    ("error.title.database", "Erreur de la base de données d'index"),
+    ("error.title.cancelled", "Annulé"),
+    ("error.title.internal", "Erreur inattendue"),
+    ("error.rootNotIndexed", "Aucun index trouvé pour '{path}'. Ajoutez d'abord le dossier."),
+    ("error.headingGone", "Ce titre n'existe plus dans le fichier. Réindexez puis réessayez."),
+    ("error.targetLocked", "'{path}' est ouvert dans un autre programme ou protégé en écriture. Fermez-le puis réessayez."),
+    ("error.cancelled", "L'opération '{kind}' a été annulée."),
+    ("error.outsideRoots", "'{path}' se trouve hors de toutes les racines ajoutées et de tous les emplacements autorisés."),
+    ("field.rootPath", "Dossier racine"),
+    ("field.sourcePath", "Fichier source"),
+    ("field.targetPath", "Cible de capture"),
+    ("field.sectionTitle", "Titre de la section"),
+    ("field.content", "Contenu de la capture"),
+    ("field.headingLevel", "Niveau de titre"),
+    ("field.headingText", "Nom du titre"),
+    ("field.title", "Titre"),
+    ("field.folder", "Dossier"),
+    ("field.tag", "Étiquette"),
+    ("validation.empty", "{label} : ce champ ne peut pas être vide."),
+    ("validation.tooLong", "{label} : trop long ({max} caractères au maximum)."),
+    ("validation.tooLarge", "{label} : trop volumineux ({max} Mo au maximum)."),
+    ("validation.levelRange", "{label} : doit être compris entre 1 et {max}."),
+    ("validation.nul", "{label} : contient un caractère NUL."),
+    ("validation.fileNameTooLong", "Le nom de fichier '{name}' est trop long ({max} octets au maximum)."),
+    ("validation.fileNameCharacter", "Le nom de fichier '{name}' ne peut pas contenir {character}."),
+    ("validation.fileNameEnding", "Le nom de fichier '{name}' ne peut pas se terminer par un point ou une espace."),
+    ("validation.fileNameReserved", "'{name}' est un nom de fichier réservé sous Windows."),
+    ("validation.targetOutsideRoot", "Un chemin relatif de cible de capture ne peut pas contenir '..' ni de préfixe racine."),
+    ("validation.targetEmpty", "Le chemin de la cible de capture ne peut pas être vide."),
+    ("validation.folderOutsideRoot", "Le dossier '{folder}' doit se trouver dans la racine."),
+    ("validation.sourceNotDocx", "'{path}' n'est pas un fichier .docx, .doc, .odt, .rtf ou .pdf."),
+    ("phase.starting", "Démarrage"),
+    ("phase.complete", "Terminé"),
+    ("phase.cancelled", "Annulé"),
+    ("phase.discovering", "Recherche des fichiers"),
+    ("phase.indexing", "Indexation des fichiers"),
+    ("phase.cleaning", "Retrait des fichiers supprimés"),
+    ("phase.files", "Reconstruction de l'index des fichiers"),
+    ("phase.headings", "Reconstruction de l'index des titres"),
+    ("phase.authors", "Reconstruction de l'index des auteurs"),
+    ("phase.body", "Indexation du texte des paragraphes"),
+    ("phase.chunks", "Reconstruction de l'index du texte"),
+    ("phase.committing", "Enregistrement"),
+    ("phase.extracting", "Extraction"),
+    ("phase.checking", "Vérification des liens"),
+    ("phase.awaiting-consent", "En attente de connexion"),
+    ("phase.exchanging", "Finalisation de la connexion"),
+    ("phase.authorizing", "Autorisation"),
+    ("phase.uploading", "Envoi"),
+    ("phase.packing", "Empaquetage des fichiers"),
+    ("phase.rendering", "Génération des pages"),
+    ("phase.indexes", "Écriture des index de dossiers"),
+    ("phase.replaying", "Relecture des captures"),
+    ("phase.compiling", "Compilation des résultats"),
+    ("phase.writing", "Écriture des fichiers"),
+    ("phase.converting", "Conversion"),
+];
+
+static ACTIVE_LOCALE: OnceLock<Mutex<Locale>> = OnceLock::new();
+
+fn active_locale_cell() -> &'static Mutex<Locale> {
+    ACTIVE_LOCALE.get_or_init(|| Mutex::new(Locale::English))
+}
+
+pub(crate) fn active_locale() -> Locale {
+    active_locale_cell()
+        .lock()
+        .map(|locale| *locale)
+        .unwrap_or(Locale::English)
+}
+
+fn set_active_locale(locale: Locale) {
+    if let Ok(mut active) = active_locale_cell().lock() {
+        *active = locale;
+    }
+}
+
+fn lookup(key: &str) -> Option<&'static str> {
+    let find = |catalog: &'static [(&'static str, &'static str)]| {
+        catalog
+            .iter()
+            .find(|(entry_key, _)| *entry_key == key)
+            .map(|(_, text)| *text)
+    };
+    find(active_locale().catalog()).or_else(|| find(ENGLISH))
+}
+
+/// The catalog text for `key` in the active locale. An unknown key comes back
+/// as itself so a missing entry shows up in the UI instead of an empty string.
+pub(crate) fn message(key: &str) -> String {
+    lookup(key).unwrap_or(key).to_string()
+}
+
+/// Like `message`, replacing each `{name}` placeholder with its argument.
+pub(crate) fn format_message(key: &str, args: &[(&str, &str)]) -> String {
+    args.iter().fold(message(key), |text, (name, value)| {
+        text.replace(&format!("{{{name}}}"), value)
+    })
+}
+
+/// A readable label for an operation phase; phases without a catalog entry
+/// fall back to their id.
+pub(crate) fn phase_label(phase: &str) -> String {
+    lookup(&format!("phase.{phase}"))
+        .map(str::to_string)
+        .unwrap_or_else(|| phase.to_string())
+}
+
+fn load_locale(connection: &Connection) -> CommandResult<Locale> {
+    let stored = connection
+        .query_row(
+            "SELECT locale FROM locale_settings WHERE id = 1",
+            [],
+            |row| row.get::<_, String>(0),
+        )
+        .optional()
+        .map_err(|error| {
+            CommandError::database(format!("Could not load locale settings: {error}"))
+        })?;
+    Ok(stored
+        .as_deref()
+        .and_then(Locale::from_code)
+        .unwrap_or(Locale::English))
+}
+
+fn locale_settings(locale: Locale) -> LocaleSettings {
+    LocaleSettings {
+        locale: locale.code().to_string(),
+        available: Locale::ALL
+            .into_iter()
+            .map(|option| LocaleOption {
+                code: option.code().to_string(),
+                name: option.native_name().to_string(),
+            })
+            .collect(),
+    }
+}
+
+pub(crate) fn current_locale_settings() -> LocaleSettings {
+    locale_settings(active_locale())
+}
+
+pub(crate) fn store_locale(connection: &Connection, code: &str) -> CommandResult<LocaleSettings> {
+    let locale = Locale::from_code(code).ok_or_else(|| {
+        CommandError::validation_field("locale", format!("Unsupported locale '{code}'."))
+    })?;
+    connection
+        .execute(
+            "
+            INSERT INTO locale_settings(id, locale, updated_at_ms)
+            VALUES(1, ?1, ?2)
+            ON CONFLICT(id) DO UPDATE SET
+              locale = excluded.locale,
+              updated_at_ms = excluded.updated_at_ms
+            ",
+            params![locale.code(), now_ms()],
+        )
+        .map_err(|error| {
+            CommandError::database(format!("Could not store locale settings: {error}"))
+        })?;
+    set_active_locale(locale);
+    Ok(locale_settings(locale))
+}
+
+/// Applies the stored locale at startup. Failures keep English rather than
+/// blocking the app from opening.
+pub(crate) fn restore_locale(app: &AppHandle) {
+    let locale = open_database(app)
+        .and_then(|connection| load_locale(&connection))
+        .unwrap_or(Locale::English);
+    set_active_locale(locale);
+}
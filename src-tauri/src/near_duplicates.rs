@@ -0,0 +1,288 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use rayon::prelude::*;
+use rusqlite::{params, Connection};
+
+use crate::errors::CommandError;
+use crate::types::{NearDuplicateGroup, NearDuplicateHeading};
+use crate::CommandResult;
+
+pub(crate) const DEFAULT_NEAR_DUPLICATE_THRESHOLD: f64 = 0.88;
+const MIN_NORMALIZED_LENGTH: usize = 12;
+const MIN_TOKEN_LENGTH: usize = 3;
+// Tokens shared by more distinct headings than this ("the", "impact", ...)
+// do not narrow the candidate set and are skipped when blocking.
+const MAX_TOKEN_POSTINGS: usize = 400;
+
+struct HeadingVariant {
+    normalized: String,
+    chars: Vec<char>,
+    members: Vec<NearDuplicateHeading>,
+}
+
+fn load_root_headings(
+    connection: &Connection,
+    root_id: i64,
+) -> CommandResult<BTreeMap<String, Vec<NearDuplicateHeading>>> {
+    let mut statement = connection
+        .prepare(
+            "
+            SELECT h.file_id, h.file_name, h.relative_path, h.heading_order, h.level, h.text, h.normalized
+            FROM headings h
+            JOIN files f ON f.id = h.file_id
+            WHERE f.root_id = ?1
+            ORDER BY h.relative_path ASC, h.heading_order ASC
+            ",
+        )
+        .map_err(|error| {
+            CommandError::database(format!(
+                "Could not prepare near-duplicate heading query: {error}"
+            ))
+        })?;
+    let rows = statement
+        .query_map(params![root_id], |row| {
+            Ok((
+                NearDuplicateHeading {
+                    file_id: row.get(0)?,
+                    file_name: row.get(1)?,
+                    relative_path: row.get(2)?,
+                    heading_order: row.get(3)?,
+                    level: row.get(4)?,
+                    text: row.get(5)?,
+                },
+                row.get::<_, String>(6)?,
+            ))
+        })
+        .map_err(|error| {
+            CommandError::database(format!(
+                "Could not run near-duplicate heading query: {error}"
+            ))
+        })?;
+
+    let mut by_normalized = BTreeMap::<String, Vec<NearDuplicateHeading>>::new();
+    for row in rows {
+        let (heading, normalized) = row.map_err(|error| {
+            CommandError::database(format!("Could not parse near-duplicate heading: {error}"))
+        })?;
+        if normalized.chars().count() < MIN_NORMALIZED_LENGTH {
+            continue;
+        }
+        by_normalized.entry(normalized).or_default().push(heading);
+    }
+    Ok(by_normalized)
+}
+
+/// Edit distance between `left` and `right` if it is at most `max_distance`.
+/// Only the diagonal band that can stay within the bound is filled, and the
+/// pass stops as soon as a whole row of the band exceeds it.
+fn bounded_levenshtein(left: &[char], right: &[char], max_distance: usize) -> Option<usize> {
+    let (shorter, longer) = if left.len() <= right.len() {
+        (left, right)
+    } else {
+        (right, left)
+    };
+    if longer.len() - shorter.len() > max_distance {
+        return None;
+    }
+
+    // Anything past the bound is stored as `beyond` so sums cannot overflow.
+    let beyond = max_distance + 1;
+    let mut previous = (0..=longer.len())
+        .map(|index| index.min(beyond))
+        .collect::<Vec<usize>>();
+    let mut current = vec![beyond; longer.len() + 1];
+    for (shorter_index, shorter_char) in shorter.iter().enumerate() {
+        let row = shorter_index + 1;
+        let low = row.saturating_sub(max_distance).max(1);
+        let high = (row + max_distance).min(longer.len());
+        current[low - 1] = if low == 1 { row.min(beyond) } else { beyond };
+        let mut row_min = current[low - 1];
+        for column in low..=high {
+            let substitution =
+                previous[column - 1] + usize::from(*shorter_char != longer[column - 1]);
+            let value = substitution
+                .min(previous[column] + 1)
+                .min(current[column - 1] + 1)
+                .min(beyond);
+            current[column] = value;
+            row_min = row_min.min(value);
+        }
+        if high < longer.len() {
+            current[high + 1] = beyond;
+        }
+        if row_min > max_distance {
+            return None;
+        }
+        std::mem::swap(&mut previous, &mut current);
+    }
+
+    let distance = previous[longer.len()];
+    (distance <= max_distance).then_some(distance)
+}
+
+/// Normalized edit similarity in `0.0..=1.0`, or `None` when it is below
+/// `threshold`.
+fn similarity_at_least(left: &[char], right: &[char], threshold: f64) -> Option<f64> {
+    let longest = left.len().max(right.len());
+    if longest == 0 {
+        return Some(1.0);
+    }
+    // The small epsilon keeps a distance that lands exactly on the threshold
+    // from being rounded away.
+    let max_distance = ((1.0 - threshold).max(0.0) * longest as f64 + 1e-9).floor() as usize;
+    let distance = bounded_levenshtein(left, right, max_distance)?;
+    let similarity = 1.0 - distance as f64 / longest as f64;
+    (similarity >= threshold).then_some(similarity)
+}
+
+fn find_root(parents: &mut [usize], index: usize) -> usize {
+    let mut root = index;
+    while parents[root] != root {
+        root = parents[root];
+    }
+    let mut node = index;
+    while parents[node] != root {
+        let next = parents[node];
+        parents[node] = root;
+        node = next;
+    }
+    root
+}
+
+/// Groups headings whose normalized text is close but not identical.
+/// Identical wording is exact duplication and is deliberately not reported
+/// here; only groups spanning at least two files are returned.
+pub(crate) fn find_near_duplicate_groups(
+    connection: &Connection,
+    root_id: i64,
+    threshold: f64,
+) -> CommandResult<(usize, Vec<NearDuplicateGroup>)> {
+    let by_normalized = load_root_headings(connection, root_id)?;
+    let headings_compared = by_normalized.values().map(Vec::len).sum::<usize>();
+    let variants = by_normalized
+        .into_iter()
+        .map(|(normalized, members)| HeadingVariant {
+            chars: normalized.chars().collect(),
+            normalized,
+            members,
+        })
+        .collect::<Vec<HeadingVariant>>();
+
+    let mut postings = HashMap::<&str, Vec<usize>>::new();
+    for (index, variant) in variants.iter().enumerate() {
+        let tokens = variant
+            .normalized
+            .split_whitespace()
+            .filter(|token| token.len() >= MIN_TOKEN_LENGTH)
+            .collect::<HashSet<&str>>();
+        for token in tokens {
+            postings.entry(token).or_default().push(index);
+        }
+    }
+
+    let mut candidate_pairs = HashSet::<(usize, usize)>::new();
+    for indices in postings.values() {
+        if indices.len() < 2 || indices.len() > MAX_TOKEN_POSTINGS {
+            continue;
+        }
+        for (position, left) in indices.iter().enumerate() {
+            for right in &indices[position + 1..] {
+                let shorter = variants[*left]
+                    .chars
+                    .len()
+                    .min(variants[*right].chars.len());
+                let longer = variants[*left]
+                    .chars
+                    .len()
+                    .max(variants[*right].chars.len());
+                if (shorter as f64) / (longer as f64) >= threshold {
+                    candidate_pairs.insert((*left, *right));
+                }
+            }
+        }
+    }
+
+    // Only pairs at or above the threshold are kept; everything downstream
+    // ignores the rest.
+    let pair_similarity = candidate_pairs
+        .into_par_iter()
+        .filter_map(|(left, right)| {
+            similarity_at_least(&variants[left].chars, &variants[right].chars, threshold)
+                .map(|similarity| ((left, right), similarity))
+        })
+        .collect::<HashMap<(usize, usize), f64>>();
+
+    let mut parents = (0..variants.len()).collect::<Vec<usize>>();
+    let mut similar_pairs = pair_similarity.keys().copied().collect::<Vec<_>>();
+    similar_pairs.sort_unstable();
+    for (left, right) in similar_pairs {
+        let left_root = find_root(&mut parents, left);
+        let right_root = find_root(&mut parents, right);
+        if left_root != right_root {
+            parents[right_root] = left_root;
+        }
+    }
+
+    let mut clusters = BTreeMap::<usize, Vec<usize>>::new();
+    for index in 0..variants.len() {
+        let root = find_root(&mut parents, index);
+        clusters.entry(root).or_default().push(index);
+    }
+
+    let mut groups = Vec::new();
+    for members in clusters.into_values() {
+        if members.len() < 2 {
+            continue;
+        }
+        let file_count = members
+            .iter()
+            .flat_map(|index| {
+                variants[*index]
+                    .members
+                    .iter()
+                    .map(|heading| heading.file_id)
+            })
+            .collect::<HashSet<i64>>()
+            .len();
+        if file_count < 2 {
+            continue;
+        }
+
+        let mut min_similarity = 1.0_f64;
+        for (position, left) in members.iter().enumerate() {
+            for right in &members[position + 1..] {
+                if let Some(similarity) = pair_similarity.get(&(*left, *right)) {
+                    min_similarity = min_similarity.min(*similarity);
+                }
+            }
+        }
+
+        let variant_texts = members
+            .iter()
+            .filter_map(|index| variants[*index].members.first())
+            .map(|heading| heading.text.clone())
+            .collect::<Vec<String>>();
+        let headings = members
+            .iter()
+            .flat_map(|index| variants[*index].members.iter().cloned())
+            .collect::<Vec<NearDuplicateHeading>>();
+
+        groups.push(NearDuplicateGroup {
+            variant_count: variant_texts.len(),
+            file_count,
+            min_similarity,
+            variants: variant_texts,
+            headings,
+        });
+    }
+
+    groups.sort_by(|left, right| {
+        right
+            .file_count
+            .cmp(&left.file_count)
+            .then(right.headings.len().cmp(&left.headings.len()))
+            .then(left.variants.cmp(&right.variants))
+    });
+
+    Ok((headings_compared, groups))
+}
@@ -0,0 +1,221 @@
+use std::collections::{HashMap, HashSet};
+
+use roxmltree::Document;
+
+use crate::docx_capture::ensure_part_registered;
+use crate::docx_parse::{attribute_value, has_tag};
+use crate::xml_write::{rewrite_attribute_values, write_fragment};
+
+const WORDPROCESSING_NAMESPACE: &str =
+    "http://schemas.openxmlformats.org/wordprocessingml/2006/main";
+
+/// Footnotes and endnotes share one layout: a part of numbered notes that
+/// paragraphs point into with reference runs.
+#[derive(Clone, Copy)]
+pub(crate) enum NoteKind {
+    Footnote,
+    Endnote,
+}
+
+impl NoteKind {
+    pub(crate) const ALL: [NoteKind; 2] = [NoteKind::Footnote, NoteKind::Endnote];
+
+    pub(crate) fn part_name(self) -> &'static str {
+        match self {
+            NoteKind::Footnote => "word/footnotes.xml",
+            NoteKind::Endnote => "word/endnotes.xml",
+        }
+    }
+
+    fn note_tag(self) -> &'static str {
+        match self {
+            NoteKind::Footnote => "footnote",
+            NoteKind::Endnote => "endnote",
+        }
+    }
+
+    fn reference_tag(self) -> &'static str {
+        match self {
+            NoteKind::Footnote => "footnoteReference",
+            NoteKind::Endnote => "endnoteReference",
+        }
+    }
+
+    fn relationship_type(self) -> &'static str {
+        match self {
+            NoteKind::Footnote => {
+                "http://schemas.openxmlformats.org/officeDocument/2006/relationships/footnotes"
+            }
+            NoteKind::Endnote => {
+                "http://schemas.openxmlformats.org/officeDocument/2006/relationships/endnotes"
+            }
+        }
+    }
+
+    fn content_type(self) -> &'static str {
+        match self {
+            NoteKind::Footnote => {
+                "application/vnd.openxmlformats-officedocument.wordprocessingml.footnotes+xml"
+            }
+            NoteKind::Endnote => {
+                "application/vnd.openxmlformats-officedocument.wordprocessingml.endnotes+xml"
+            }
+        }
+    }
+
+    /// A notes part holding only the separator notes Word writes into every
+    /// one, for targets that have never had a note of this kind.
+    fn empty_part_xml(self) -> String {
+        let tag = self.note_tag();
+        format!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?><w:{tag}s xmlns:w=\"{WORDPROCESSING_NAMESPACE}\"><w:{tag} w:type=\"separator\" w:id=\"-1\"><w:p><w:r><w:separator/></w:r></w:p></w:{tag}><w:{tag} w:type=\"continuationSeparator\" w:id=\"0\"><w:p><w:r><w:continuationSeparator/></w:r></w:p></w:{tag}></w:{tag}s>"
+        )
+    }
+
+    pub(crate) fn ensure_registered(
+        self,
+        relationships_xml: &mut String,
+        content_types_xml: &mut Option<String>,
+    ) {
+        ensure_part_registered(
+            relationships_xml,
+            content_types_xml,
+            self.part_name(),
+            self.relationship_type(),
+            self.content_type(),
+        );
+    }
+}
+
+/// Notes copied into a target's notes part, with the new id of each source
+/// note.
+pub(crate) struct NoteMerge {
+    pub notes_xml: String,
+    pub id_remap: HashMap<String, String>,
+}
+
+/// Ids of the notes of `kind` the paragraphs reference.
+pub(crate) fn collect_note_ids(paragraph_xml: &[String], kind: NoteKind) -> HashSet<String> {
+    let mut ids = HashSet::new();
+    for paragraph in paragraph_xml {
+        let Ok(document) = Document::parse(paragraph) else {
+            continue;
+        };
+        for node in document
+            .descendants()
+            .filter(|node| has_tag(*node, kind.reference_tag()))
+        {
+            if let Some(id) = attribute_value(node, "id") {
+                ids.insert(id.to_string());
+            }
+        }
+    }
+    ids
+}
+
+/// Style ids the referenced notes use, so they can travel with the section.
+pub(crate) fn collect_note_style_ids(
+    notes_xml: &str,
+    note_ids: &HashSet<String>,
+    kind: NoteKind,
+) -> HashSet<String> {
+    let mut style_ids = HashSet::new();
+    let Ok(document) = Document::parse(notes_xml) else {
+        return style_ids;
+    };
+    for note in document.descendants().filter(|node| {
+        has_tag(*node, kind.note_tag())
+            && attribute_value(*node, "id").is_some_and(|id| note_ids.contains(id))
+    }) {
+        for node in note
+            .descendants()
+            .filter(|node| has_tag(*node, "pStyle") || has_tag(*node, "rStyle"))
+        {
+            if let Some(style_id) = attribute_value(node, "val").filter(|id| !id.is_empty()) {
+                style_ids.insert(style_id.to_string());
+            }
+        }
+    }
+    style_ids
+}
+
+/// Points the paragraphs' note references at the merged ids.
+pub(crate) fn remap_note_references(
+    paragraph_xml: &mut [String],
+    id_remap: &HashMap<String, String>,
+    kind: NoteKind,
+) {
+    if id_remap.is_empty() {
+        return;
+    }
+    for paragraph in paragraph_xml.iter_mut() {
+        *paragraph = rewrite_attribute_values(paragraph, |node, name, value| {
+            if has_tag(node, kind.reference_tag()) && name == "id" {
+                id_remap.get(value).cloned()
+            } else {
+                None
+            }
+        });
+    }
+}
+
+/// Appends the source notes named in `note_ids` to the target's notes part,
+/// numbered after the target's highest id. The part is created, separators
+/// included, when the target has none. `None` when the source defines none
+/// of the notes.
+pub(crate) fn merge_notes(
+    target_notes_xml: Option<&str>,
+    source_notes_xml: &str,
+    note_ids: &HashSet<String>,
+    kind: NoteKind,
+) -> Option<NoteMerge> {
+    let source = Document::parse(source_notes_xml).ok()?;
+    let empty_part_xml = kind.empty_part_xml();
+    let target_notes_xml = target_notes_xml.unwrap_or(&empty_part_xml);
+    let target = Document::parse(target_notes_xml).ok()?;
+
+    let mut next_id = target
+        .descendants()
+        .filter(|node| has_tag(*node, kind.note_tag()))
+        .filter_map(|node| attribute_value(node, "id")?.parse::<i64>().ok())
+        .max()
+        .unwrap_or(0)
+        .max(0)
+        + 1;
+
+    let mut id_remap = HashMap::new();
+    let mut appended = String::new();
+    // Source order keeps the notes numbered the way they read.
+    for note in source.descendants().filter(|node| {
+        has_tag(*node, kind.note_tag())
+            && attribute_value(*node, "id").is_some_and(|id| note_ids.contains(id))
+    }) {
+        let Some(source_id) = attribute_value(note, "id") else {
+            continue;
+        };
+        let new_id = next_id.to_string();
+        next_id += 1;
+        appended.push_str(&rewrite_attribute_values(
+            &write_fragment(note),
+            |node, name, _| {
+                (has_tag(node, kind.note_tag()) && name == "id").then(|| new_id.clone())
+            },
+        ));
+        id_remap.insert(source_id.to_string(), new_id);
+    }
+
+    if appended.is_empty() {
+        return None;
+    }
+
+    // The root's closing tag is the last one in the part.
+    let close_index = target_notes_xml.rfind("</")?;
+    let mut notes_xml = String::with_capacity(target_notes_xml.len() + appended.len());
+    notes_xml.push_str(&target_notes_xml[..close_index]);
+    notes_xml.push_str(&appended);
+    notes_xml.push_str(&target_notes_xml[close_index..]);
+    Some(NoteMerge {
+        notes_xml,
+        id_remap,
+    })
+}
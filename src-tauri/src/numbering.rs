@@ -0,0 +1,212 @@
+use std::collections::{BTreeSet, HashMap, HashSet};
+
+use roxmltree::{Document, Node};
+
+use crate::docx_capture::ensure_part_registered;
+use crate::docx_parse::{attribute_value, has_tag};
+use crate::xml_write::{rewrite_attribute_values, write_fragment};
+
+pub(crate) const NUMBERING_PART_NAME: &str = "word/numbering.xml";
+const NUMBERING_RELATIONSHIP_TYPE: &str =
+    "http://schemas.openxmlformats.org/officeDocument/2006/relationships/numbering";
+const NUMBERING_CONTENT_TYPE: &str =
+    "application/vnd.openxmlformats-officedocument.wordprocessingml.numbering+xml";
+const EMPTY_NUMBERING_XML: &str = "<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?><w:numbering xmlns:w=\"http://schemas.openxmlformats.org/wordprocessingml/2006/main\"></w:numbering>";
+
+/// List definitions copied into a target's numbering part, with the source
+/// `numId`s that had to change to avoid ids the target already used.
+pub(crate) struct NumberingMerge {
+    pub numbering_xml: String,
+    pub id_remap: HashMap<String, String>,
+}
+
+/// The list instances (`w:num` ids) a section's paragraphs are numbered
+/// with. Id 0 switches numbering off and needs no definition.
+pub(crate) fn collect_numbering_ids(paragraph_xml: &[String]) -> HashSet<String> {
+    let mut ids = HashSet::new();
+    for paragraph in paragraph_xml {
+        let Ok(document) = Document::parse(paragraph) else {
+            continue;
+        };
+        for node in document
+            .descendants()
+            .filter(|node| has_tag(*node, "numId"))
+        {
+            if let Some(id) = attribute_value(node, "val").filter(|id| *id != "0") {
+                ids.insert(id.to_string());
+            }
+        }
+    }
+    ids
+}
+
+/// Points the paragraphs' `w:numId` references at their merged ids.
+pub(crate) fn remap_numbering_ids(
+    paragraph_xml: &mut [String],
+    id_remap: &HashMap<String, String>,
+) {
+    if id_remap.is_empty() {
+        return;
+    }
+    for paragraph in paragraph_xml.iter_mut() {
+        *paragraph = rewrite_attribute_values(paragraph, |node, name, value| {
+            if has_tag(node, "numId") && name == "val" {
+                id_remap.get(value).cloned()
+            } else {
+                None
+            }
+        });
+    }
+}
+
+/// `preferred` when it is free, otherwise one past the largest id in use.
+fn claim_id(used: &mut BTreeSet<i64>, preferred: i64) -> i64 {
+    let id = if used.contains(&preferred) {
+        used.last().map_or(preferred, |largest| largest + 1)
+    } else {
+        preferred
+    };
+    used.insert(id);
+    id
+}
+
+fn numeric_ids(document: &Document<'_>, tag: &str, key: &str) -> BTreeSet<i64> {
+    document
+        .descendants()
+        .filter(|node| has_tag(*node, tag))
+        .filter_map(|node| attribute_value(node, key)?.parse::<i64>().ok())
+        .collect()
+}
+
+/// Copies the `w:num` instances named in `num_ids`, and the abstract list
+/// definitions behind them, from the source numbering part into the target's.
+/// Ids that collide with the target's get fresh ones, so a captured list
+/// never picks up one of the target's list formats. `None` when the source
+/// defines none of the ids.
+pub(crate) fn merge_numbering(
+    target_numbering_xml: Option<&str>,
+    source_numbering_xml: &str,
+    num_ids: &HashSet<String>,
+) -> Option<NumberingMerge> {
+    let source = Document::parse(source_numbering_xml).ok()?;
+    let target_numbering_xml = target_numbering_xml.unwrap_or(EMPTY_NUMBERING_XML);
+    let target = Document::parse(target_numbering_xml).ok()?;
+
+    let source_nums = source
+        .descendants()
+        .filter(|node| has_tag(*node, "num"))
+        .filter_map(|node| Some((attribute_value(node, "numId")?, node)))
+        .collect::<HashMap<&str, Node<'_, '_>>>();
+    let source_abstracts = source
+        .descendants()
+        .filter(|node| has_tag(*node, "abstractNum"))
+        .filter_map(|node| Some((attribute_value(node, "abstractNumId")?, node)))
+        .collect::<HashMap<&str, Node<'_, '_>>>();
+
+    let mut used_num_ids = numeric_ids(&target, "num", "numId");
+    let mut used_abstract_ids = numeric_ids(&target, "abstractNum", "abstractNumId");
+    let mut abstract_remap = HashMap::<String, String>::new();
+    let mut id_remap = HashMap::new();
+    let mut abstract_xml = String::new();
+    let mut num_xml = String::new();
+
+    let mut requested = num_ids
+        .iter()
+        .filter_map(|id| Some((id.parse::<i64>().ok()?, id.as_str())))
+        .collect::<Vec<_>>();
+    requested.sort();
+    for (numeric_id, num_id) in requested {
+        let Some(num) = source_nums.get(num_id) else {
+            continue;
+        };
+        let Some(abstract_id) = num
+            .children()
+            .find(|child| has_tag(*child, "abstractNumId"))
+            .and_then(|child| attribute_value(child, "val"))
+        else {
+            continue;
+        };
+        let Some(abstract_num) = source_abstracts.get(abstract_id) else {
+            continue;
+        };
+
+        // Instances sharing an abstract definition keep sharing one copy.
+        let new_abstract_id = match abstract_remap.get(abstract_id) {
+            Some(id) => id.clone(),
+            None => {
+                let preferred = abstract_id.parse::<i64>().unwrap_or(0);
+                let id = claim_id(&mut used_abstract_ids, preferred).to_string();
+                abstract_xml.push_str(&rewrite_attribute_values(
+                    &write_fragment(*abstract_num),
+                    |node, name, _| {
+                        (has_tag(node, "abstractNum") && name == "abstractNumId")
+                            .then(|| id.clone())
+                    },
+                ));
+                abstract_remap.insert(abstract_id.to_string(), id.clone());
+                id
+            }
+        };
+
+        let new_num_id = claim_id(&mut used_num_ids, numeric_id).to_string();
+        num_xml.push_str(&rewrite_attribute_values(
+            &write_fragment(*num),
+            |node, name, _| {
+                if has_tag(node, "num") && name == "numId" {
+                    Some(new_num_id.clone())
+                } else if has_tag(node, "abstractNumId") && name == "val" {
+                    Some(new_abstract_id.clone())
+                } else {
+                    None
+                }
+            },
+        ));
+        if new_num_id != num_id {
+            id_remap.insert(num_id.to_string(), new_num_id);
+        }
+    }
+
+    if num_xml.is_empty() {
+        return None;
+    }
+
+    // The schema wants every abstract definition before the first instance,
+    // and instances before the trailing cleanup marker.
+    let numbering_close = target_numbering_xml.rfind("</w:numbering>")?;
+    let first_element_start = |tag: &str| {
+        target
+            .root_element()
+            .children()
+            .find(|child| has_tag(*child, tag))
+            .map(|child| child.range().start)
+    };
+    let nums_start = first_element_start("numIdMacAtCleanup").unwrap_or(numbering_close);
+    let abstracts_start = first_element_start("num").unwrap_or(nums_start);
+
+    let mut numbering_xml =
+        String::with_capacity(target_numbering_xml.len() + abstract_xml.len() + num_xml.len());
+    numbering_xml.push_str(&target_numbering_xml[..abstracts_start]);
+    numbering_xml.push_str(&abstract_xml);
+    numbering_xml.push_str(&target_numbering_xml[abstracts_start..nums_start]);
+    numbering_xml.push_str(&num_xml);
+    numbering_xml.push_str(&target_numbering_xml[nums_start..]);
+    Some(NumberingMerge {
+        numbering_xml,
+        id_remap,
+    })
+}
+
+/// Makes sure the document links to a numbering part and the package
+/// declares its content type, which targets created without lists lack.
+pub(crate) fn ensure_numbering_part_registered(
+    relationships_xml: &mut String,
+    content_types_xml: &mut Option<String>,
+) {
+    ensure_part_registered(
+        relationships_xml,
+        content_types_xml,
+        NUMBERING_PART_NAME,
+        NUMBERING_RELATIONSHIP_TYPE,
+        NUMBERING_CONTENT_TYPE,
+    );
+}
@@ -0,0 +1,217 @@
+use std::collections::HashMap;
+
+use roxmltree::{Document, Node};
+
+use crate::docx_build::ParagraphBuilder;
+use crate::docx_parse::has_tag;
+use crate::types::{ConvertedDocument, GeneratedRun};
+
+const TEXT_NS: &str = "urn:oasis:names:tc:opendocument:xmlns:text:1.0";
+const STYLE_NS: &str = "urn:oasis:names:tc:opendocument:xmlns:style:1.0";
+const FO_NS: &str = "urn:oasis:names:tc:opendocument:xmlns:xsl-fo-compatible:1.0";
+const XLINK_NS: &str = "http://www.w3.org/1999/xlink";
+/// Parent chains longer than this are treated as cycles.
+const MAX_STYLE_DEPTH: usize = 16;
+/// Inline elements whose content is not part of the paragraph's text.
+const SKIPPED_INLINE: [&str; 4] = ["note", "annotation", "frame", "tracked-changes"];
+
+#[derive(Clone, Copy, Default)]
+struct TextFormat {
+    bold: bool,
+    italic: bool,
+    underline: bool,
+}
+
+/// `None` leaves a property to the parent style.
+#[derive(Default)]
+struct StyleEntry {
+    parent: Option<String>,
+    bold: Option<bool>,
+    italic: Option<bool>,
+    underline: Option<bool>,
+}
+
+fn read_styles(xml: &str, styles: &mut HashMap<String, StyleEntry>) {
+    let Ok(document) = Document::parse(xml) else {
+        return;
+    };
+    for node in document
+        .descendants()
+        .filter(|node| has_tag(*node, "style"))
+    {
+        let Some(name) = node.attribute((STYLE_NS, "name")) else {
+            continue;
+        };
+        let properties = node
+            .children()
+            .find(|child| has_tag(*child, "text-properties"));
+        let property = |namespace: &str, key: &str| {
+            properties.and_then(|properties| properties.attribute((namespace, key)))
+        };
+        styles.insert(
+            name.to_string(),
+            StyleEntry {
+                parent: node
+                    .attribute((STYLE_NS, "parent-style-name"))
+                    .map(str::to_string),
+                bold: property(FO_NS, "font-weight").map(|weight| {
+                    weight == "bold" || weight.parse::<u32>().is_ok_and(|weight| weight >= 600)
+                }),
+                italic: property(FO_NS, "font-style")
+                    .map(|style| style == "italic" || style == "oblique"),
+                underline: property(STYLE_NS, "text-underline-style").map(|style| style != "none"),
+            },
+        );
+    }
+}
+
+/// The nearest style in the parent chain wins.
+fn apply_style(
+    format: TextFormat,
+    style_name: Option<&str>,
+    styles: &HashMap<String, StyleEntry>,
+) -> TextFormat {
+    let mut chain = Vec::new();
+    let mut current = style_name;
+    while let Some(name) = current {
+        let Some(entry) = styles.get(name).filter(|_| chain.len() < MAX_STYLE_DEPTH) else {
+            break;
+        };
+        chain.push(entry);
+        current = entry.parent.as_deref();
+    }
+    chain.iter().rev().fold(format, |format, entry| TextFormat {
+        bold: entry.bold.unwrap_or(format.bold),
+        italic: entry.italic.unwrap_or(format.italic),
+        underline: entry.underline.unwrap_or(format.underline),
+    })
+}
+
+struct OdtConverter<'a> {
+    styles: &'a HashMap<String, StyleEntry>,
+    builder: ParagraphBuilder,
+}
+
+impl OdtConverter<'_> {
+    fn blocks(&mut self, node: Node<'_, '_>) {
+        for child in node.children().filter(Node::is_element) {
+            if has_tag(child, "h") {
+                let level = child
+                    .attribute((TEXT_NS, "outline-level"))
+                    .and_then(|value| value.parse::<i64>().ok())
+                    .unwrap_or(1)
+                    .clamp(1, 9);
+                self.paragraph(child, Some(level));
+            } else if has_tag(child, "p") {
+                self.paragraph(child, None);
+            } else if !SKIPPED_INLINE.iter().any(|name| has_tag(child, name)) {
+                self.blocks(child);
+            }
+        }
+    }
+
+    fn paragraph(&mut self, node: Node<'_, '_>, heading_level: Option<i64>) {
+        self.builder.set_heading_level(heading_level);
+        let format = apply_style(
+            TextFormat::default(),
+            node.attribute((TEXT_NS, "style-name")),
+            self.styles,
+        );
+        self.inline(node, format, None);
+        self.builder.flush();
+    }
+
+    fn inline(&mut self, node: Node<'_, '_>, format: TextFormat, link: Option<&str>) {
+        for child in node.children() {
+            if child.is_text() {
+                self.push_collapsed(child.text().unwrap_or_default(), format, link);
+            } else if !child.is_element() || SKIPPED_INLINE.iter().any(|name| has_tag(child, name))
+            {
+                continue;
+            } else if has_tag(child, "span") {
+                let format = apply_style(
+                    format,
+                    child.attribute((TEXT_NS, "style-name")),
+                    self.styles,
+                );
+                self.inline(child, format, link);
+            } else if has_tag(child, "a") {
+                self.inline(child, format, child.attribute((XLINK_NS, "href")).or(link));
+            } else if has_tag(child, "s") {
+                let count = child
+                    .attribute((TEXT_NS, "c"))
+                    .and_then(|value| value.parse::<usize>().ok())
+                    .unwrap_or(1);
+                self.push(" ".repeat(count), format, link);
+            } else if has_tag(child, "tab") {
+                self.push("\t".to_string(), format, link);
+            } else if has_tag(child, "line-break") {
+                self.builder.push_line_break();
+            } else {
+                self.inline(child, format, link);
+            }
+        }
+    }
+
+    /// ODF collapses whitespace like HTML; spaces that matter are `text:s`.
+    fn push_collapsed(&mut self, text: &str, format: TextFormat, link: Option<&str>) {
+        let mut collapsed = String::with_capacity(text.len());
+        let mut after_space = self.builder.at_word_boundary();
+        for character in text.chars() {
+            if character.is_whitespace() {
+                if !after_space {
+                    collapsed.push(' ');
+                }
+                after_space = true;
+            } else {
+                collapsed.push(character);
+                after_space = false;
+            }
+        }
+        self.push(collapsed, format, link);
+    }
+
+    fn push(&mut self, text: String, format: TextFormat, link: Option<&str>) {
+        if text.is_empty() {
+            return;
+        }
+        self.builder.push_run(GeneratedRun {
+            text,
+            bold: format.bold,
+            italic: format.italic,
+            underline: format.underline,
+            link: link.map(str::to_string),
+        });
+    }
+}
+
+/// Headings and paragraphs of an OpenDocument `content.xml`, with the named
+/// styles from `styles_xml`. `None` when `content_xml` is not XML.
+pub(crate) fn convert_odt_document(
+    content_xml: &str,
+    styles_xml: Option<&str>,
+) -> Option<ConvertedDocument> {
+    let mut styles = HashMap::new();
+    if let Some(styles_xml) = styles_xml {
+        read_styles(styles_xml, &mut styles);
+    }
+    read_styles(content_xml, &mut styles);
+
+    let document = Document::parse(content_xml).ok()?;
+    let mut converter = OdtConverter {
+        styles: &styles,
+        builder: ParagraphBuilder::default(),
+    };
+    if let Some(body) = document.descendants().find(|node| {
+        has_tag(*node, "text")
+            && node
+                .parent_element()
+                .is_some_and(|parent| has_tag(parent, "body"))
+    }) {
+        converter.blocks(body);
+    }
+    Some(ConvertedDocument {
+        paragraphs: converter.builder.finish(),
+        ..ConvertedDocument::default()
+    })
+}
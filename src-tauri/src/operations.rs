@@ -0,0 +1,232 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering as AtomicOrdering};
+use std::sync::{Arc, Mutex, OnceLock};
+
+use tauri::{AppHandle, Emitter};
+
+use crate::errors::CommandError;
+use crate::event_feed::{publish_event, FEED_EVENT_OPERATION};
+use crate::messages::phase_label;
+use crate::types::OperationProgress;
+use crate::util::{now_ms, INDEX_PROGRESS_EMIT_INTERVAL_MS};
+use crate::CommandResult;
+
+pub(crate) const OPERATION_PROGRESS_EVENT: &str = "operation-progress";
+pub(crate) const OPERATION_STATUS_RUNNING: &str = "running";
+pub(crate) const OPERATION_STATUS_COMPLETE: &str = "complete";
+pub(crate) const OPERATION_STATUS_FAILED: &str = "failed";
+pub(crate) const OPERATION_STATUS_CANCELLED: &str = "cancelled";
+
+struct ActiveOperation {
+    cancel: Arc<AtomicBool>,
+    snapshot: OperationProgress,
+}
+
+static ACTIVE_OPERATIONS: OnceLock<Mutex<HashMap<String, ActiveOperation>>> = OnceLock::new();
+static NEXT_OPERATION_ID: AtomicU64 = AtomicU64::new(1);
+
+fn active_operations() -> &'static Mutex<HashMap<String, ActiveOperation>> {
+    ACTIVE_OPERATIONS.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+/// A shareable view of an operation's cancel flag, for worker threads (such
+/// as rayon closures) that cannot borrow the handle itself.
+#[derive(Clone)]
+pub(crate) struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    pub(crate) fn is_cancelled(&self) -> bool {
+        self.0.load(AtomicOrdering::SeqCst)
+    }
+}
+
+/// Progress reporter shared by long-running backend work. Every update is
+/// emitted as `operation-progress` with a stable operation id so the frontend
+/// can render one progress UI and cancel by id. Dropping a handle that was
+/// never finished (an early `?` return) still emits a terminal event.
+pub(crate) struct OperationHandle {
+    app: AppHandle,
+    progress: OperationProgress,
+    started_at_ms: i64,
+    last_emitted_ms: i64,
+    cancel: Arc<AtomicBool>,
+    finished: bool,
+}
+
+impl OperationHandle {
+    pub(crate) fn start(
+        app: &AppHandle,
+        kind: &str,
+        root_path: Option<String>,
+        cancellable: bool,
+    ) -> Self {
+        let sequence = NEXT_OPERATION_ID.fetch_add(1, AtomicOrdering::SeqCst);
+        let started_at_ms = now_ms();
+        let progress = OperationProgress {
+            operation_id: format!("{kind}-{started_at_ms}-{sequence}"),
+            kind: kind.to_string(),
+            root_path,
+            phase: "starting".to_string(),
+            phase_label: phase_label("starting"),
+            status: OPERATION_STATUS_RUNNING.to_string(),
+            percent: None,
+            processed: 0,
+            total: None,
+            message: None,
+            cancellable,
+            elapsed_ms: 0,
+            error: None,
+        };
+        let cancel = Arc::new(AtomicBool::new(false));
+        if let Ok(mut operations) = active_operations().lock() {
+            operations.insert(
+                progress.operation_id.clone(),
+                ActiveOperation {
+                    cancel: Arc::clone(&cancel),
+                    snapshot: progress.clone(),
+                },
+            );
+        }
+
+        let mut handle = Self {
+            app: app.clone(),
+            progress,
+            started_at_ms,
+            last_emitted_ms: 0,
+            cancel,
+            finished: false,
+        };
+        handle.emit();
+        handle
+    }
+
+    pub(crate) fn id(&self) -> &str {
+        &self.progress.operation_id
+    }
+
+    fn emit(&mut self) {
+        let now = now_ms();
+        self.progress.elapsed_ms = now - self.started_at_ms;
+        self.progress.phase_label = phase_label(&self.progress.phase);
+        self.last_emitted_ms = now;
+        if let Ok(mut operations) = active_operations().lock() {
+            if let Some(active) = operations.get_mut(&self.progress.operation_id) {
+                active.snapshot = self.progress.clone();
+            }
+        }
+        let _ = self
+            .app
+            .emit(OPERATION_PROGRESS_EVENT, self.progress.clone());
+        publish_event(FEED_EVENT_OPERATION, &self.progress);
+    }
+
+    /// Records progress. Emission is throttled, except when the phase changes.
+    pub(crate) fn update(
+        &mut self,
+        phase: &str,
+        processed: usize,
+        total: Option<usize>,
+        message: Option<String>,
+    ) {
+        let phase_changed = self.progress.phase != phase;
+        self.progress.phase = phase.to_string();
+        self.progress.processed = processed;
+        self.progress.total = total;
+        self.progress.percent = total.map(|total| {
+            if total == 0 {
+                100.0
+            } else {
+                (processed.min(total) as f64 / total as f64) * 100.0
+            }
+        });
+        self.progress.message = message;
+
+        if phase_changed || now_ms() - self.last_emitted_ms >= INDEX_PROGRESS_EMIT_INTERVAL_MS {
+            self.emit();
+        }
+    }
+
+    pub(crate) fn is_cancelled(&self) -> bool {
+        self.cancel.load(AtomicOrdering::SeqCst)
+    }
+
+    pub(crate) fn cancel_token(&self) -> CancelToken {
+        CancelToken(Arc::clone(&self.cancel))
+    }
+
+    pub(crate) fn ensure_not_cancelled(&self) -> CommandResult<()> {
+        if self.is_cancelled() {
+            return Err(CommandError::cancelled(&self.progress.kind));
+        }
+        Ok(())
+    }
+
+    pub(crate) fn finish(mut self) {
+        self.finished = true;
+        self.progress.status = OPERATION_STATUS_COMPLETE.to_string();
+        self.progress.phase = "complete".to_string();
+        if self.progress.total.is_some() {
+            self.progress.percent = Some(100.0);
+        }
+        self.progress.message = None;
+        self.emit();
+    }
+
+    pub(crate) fn fail(mut self, error: &CommandError) {
+        self.finished = true;
+        self.progress.status = if self.is_cancelled() {
+            self.progress.phase = OPERATION_STATUS_CANCELLED.to_string();
+            OPERATION_STATUS_CANCELLED
+        } else {
+            OPERATION_STATUS_FAILED
+        }
+        .to_string();
+        self.progress.error = Some(error.clone());
+        self.emit();
+    }
+}
+
+impl Drop for OperationHandle {
+    fn drop(&mut self) {
+        if !self.finished {
+            self.progress.status = if self.is_cancelled() {
+                self.progress.phase = OPERATION_STATUS_CANCELLED.to_string();
+                OPERATION_STATUS_CANCELLED
+            } else {
+                OPERATION_STATUS_FAILED
+            }
+            .to_string();
+            self.emit();
+        }
+        if let Ok(mut operations) = active_operations().lock() {
+            operations.remove(&self.progress.operation_id);
+        }
+    }
+}
+
+/// Flags a running operation for cancellation. Returns `false` when the id is
+/// unknown or the operation does not support cancelling.
+pub(crate) fn request_cancel(operation_id: &str) -> bool {
+    let Ok(operations) = active_operations().lock() else {
+        return false;
+    };
+    match operations.get(operation_id) {
+        Some(active) if active.snapshot.cancellable => {
+            active.cancel.store(true, AtomicOrdering::SeqCst);
+            true
+        }
+        _ => false,
+    }
+}
+
+pub(crate) fn active_operation_snapshots() -> Vec<OperationProgress> {
+    let Ok(operations) = active_operations().lock() else {
+        return Vec::new();
+    };
+    let mut snapshots = operations
+        .values()
+        .map(|active| active.snapshot.clone())
+        .collect::<Vec<OperationProgress>>();
+    snapshots.sort_by(|left, right| left.operation_id.cmp(&right.operation_id));
+    snapshots
+}
@@ -0,0 +1,243 @@
+use std::path::Path;
+
+use rusqlite::{params, Connection};
+
+use crate::docx_capture::{xml_escape_attr, xml_escape_text};
+use crate::docx_parse::build_heading_ranges;
+use crate::errors::CommandError;
+use crate::types::{OutlineNode, ParsedParagraph};
+use crate::util::file_name_from_relative;
+use crate::CommandResult;
+
+pub(crate) struct OutlineHeading {
+    pub level: i64,
+    pub text: String,
+}
+
+pub(crate) struct OutlineFile {
+    pub relative_path: String,
+    pub file_name: String,
+    pub headings: Vec<OutlineHeading>,
+}
+
+#[derive(Default)]
+pub(crate) struct OutlineCounts {
+    pub folder_count: usize,
+    pub file_count: usize,
+    pub heading_count: usize,
+}
+
+/// Loads every indexed file of a root with its headings, ordered by relative
+/// path so files sharing a folder prefix are contiguous.
+pub(crate) fn load_root_outline(
+    connection: &Connection,
+    root_id: i64,
+) -> CommandResult<Vec<OutlineFile>> {
+    let mut statement = connection
+        .prepare(
+            "
+            SELECT f.relative_path, h.level, h.text
+            FROM files f
+            LEFT JOIN headings h ON h.file_id = f.id
+            WHERE f.root_id = ?1
+            ORDER BY f.relative_path, h.heading_order
+            ",
+        )
+        .map_err(|error| {
+            CommandError::database(format!("Could not prepare outline query: {error}"))
+        })?;
+    let rows = statement
+        .query_map(params![root_id], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, Option<i64>>(1)?,
+                row.get::<_, Option<String>>(2)?,
+            ))
+        })
+        .map_err(|error| CommandError::database(format!("Could not run outline query: {error}")))?;
+
+    let mut files: Vec<OutlineFile> = Vec::new();
+    for row in rows {
+        let (relative_path, level, text) = row.map_err(|error| {
+            CommandError::database(format!("Could not parse outline row: {error}"))
+        })?;
+        if files
+            .last()
+            .map(|file| file.relative_path != relative_path)
+            .unwrap_or(true)
+        {
+            files.push(OutlineFile {
+                file_name: file_name_from_relative(&relative_path),
+                relative_path,
+                headings: Vec::new(),
+            });
+        }
+        if let (Some(level), Some(text), Some(file)) = (level, text, files.last_mut()) {
+            file.headings.push(OutlineHeading { level, text });
+        }
+    }
+    Ok(files)
+}
+
+fn folder_segments(relative_path: &str) -> Vec<&str> {
+    let mut segments = relative_path.split('/').collect::<Vec<&str>>();
+    segments.pop();
+    segments
+}
+
+fn outline_open(output: &mut String, depth: usize, text: &str) {
+    output.push_str(&"  ".repeat(depth));
+    output.push_str(&format!("<outline text=\"{}\">\n", xml_escape_attr(text)));
+}
+
+fn outline_close(output: &mut String, depth: usize) {
+    output.push_str(&"  ".repeat(depth));
+    output.push_str("</outline>\n");
+}
+
+fn outline_leaf(output: &mut String, depth: usize, text: &str) {
+    output.push_str(&"  ".repeat(depth));
+    output.push_str(&format!("<outline text=\"{}\"/>\n", xml_escape_attr(text)));
+}
+
+/// Renders folders → files → headings as nested OPML outlines. Headings nest
+/// by level, so an H4 under an H3 becomes its child.
+pub(crate) fn render_outline_opml(
+    root_path: &str,
+    files: &[OutlineFile],
+) -> (String, OutlineCounts) {
+    const BODY_DEPTH: usize = 2;
+    let title = Path::new(root_path)
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_else(|| root_path.to_string());
+
+    let mut counts = OutlineCounts::default();
+    let mut output = String::new();
+    output.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    output.push_str("<opml version=\"2.0\">\n");
+    output.push_str("  <head>\n");
+    output.push_str(&format!("    <title>{}</title>\n", xml_escape_text(&title)));
+    output.push_str(&format!(
+        "    <ownerName>{}</ownerName>\n",
+        xml_escape_text(root_path)
+    ));
+    output.push_str("  </head>\n");
+    output.push_str("  <body>\n");
+
+    let mut open_folders: Vec<&str> = Vec::new();
+    for file in files {
+        let segments = folder_segments(&file.relative_path);
+        let shared = open_folders
+            .iter()
+            .zip(segments.iter())
+            .take_while(|(open, wanted)| open == wanted)
+            .count();
+        while open_folders.len() > shared {
+            open_folders.pop();
+            outline_close(&mut output, BODY_DEPTH + open_folders.len());
+        }
+        for segment in &segments[shared..] {
+            outline_open(&mut output, BODY_DEPTH + open_folders.len(), segment);
+            open_folders.push(segment);
+            counts.folder_count += 1;
+        }
+
+        let file_depth = BODY_DEPTH + open_folders.len();
+        counts.file_count += 1;
+        if file.headings.is_empty() {
+            outline_leaf(&mut output, file_depth, &file.file_name);
+            continue;
+        }
+        outline_open(&mut output, file_depth, &file.file_name);
+        let mut open_levels: Vec<i64> = Vec::new();
+        for (index, heading) in file.headings.iter().enumerate() {
+            while open_levels
+                .last()
+                .map(|level| *level >= heading.level)
+                .unwrap_or(false)
+            {
+                open_levels.pop();
+                outline_close(&mut output, file_depth + 1 + open_levels.len());
+            }
+            counts.heading_count += 1;
+            let has_children = file
+                .headings
+                .get(index + 1)
+                .map(|next| next.level > heading.level)
+                .unwrap_or(false);
+            let depth = file_depth + 1 + open_levels.len();
+            if has_children {
+                outline_open(&mut output, depth, &heading.text);
+                open_levels.push(heading.level);
+            } else {
+                outline_leaf(&mut output, depth, &heading.text);
+            }
+        }
+        while !open_levels.is_empty() {
+            open_levels.pop();
+            outline_close(&mut output, file_depth + 1 + open_levels.len());
+        }
+        outline_close(&mut output, file_depth);
+    }
+    while !open_folders.is_empty() {
+        open_folders.pop();
+        outline_close(&mut output, BODY_DEPTH + open_folders.len());
+    }
+
+    output.push_str("  </body>\n");
+    output.push_str("</opml>\n");
+    (output, counts)
+}
+
+/// Nests a file's headings by section: a heading is the child of the nearest
+/// earlier heading whose range from `build_heading_ranges` contains it.
+pub(crate) fn build_heading_tree(paragraphs: &[ParsedParagraph]) -> Vec<OutlineNode> {
+    let ranges = build_heading_ranges(paragraphs);
+    let mut nodes = Vec::with_capacity(ranges.len());
+    let mut parents = Vec::with_capacity(ranges.len());
+    let mut open = Vec::<usize>::new();
+    for (index, range) in ranges.iter().enumerate() {
+        while open
+            .last()
+            .is_some_and(|open_index| ranges[*open_index].end_index <= range.start_index)
+        {
+            open.pop();
+        }
+        parents.push(open.last().copied());
+        open.push(index);
+
+        let body = paragraphs[range.start_index + 1..range.end_index]
+            .iter()
+            .filter(|paragraph| {
+                paragraph.heading_level.is_none() && !paragraph.text.trim().is_empty()
+            });
+        let (word_count, paragraph_count) = body.fold((0, 0), |(words, count), paragraph| {
+            (words + paragraph.text.split_whitespace().count(), count + 1)
+        });
+        nodes.push(Some(OutlineNode {
+            order: range.order,
+            level: range.level,
+            text: paragraphs[range.start_index].text.clone(),
+            word_count,
+            paragraph_count,
+            children: Vec::new(),
+        }));
+    }
+
+    // Children come after their parents, so filling parents back to front
+    // moves every subtree before its parent is moved.
+    let mut roots = Vec::new();
+    for index in (0..nodes.len()).rev() {
+        let Some(mut node) = nodes[index].take() else {
+            continue;
+        };
+        node.children.reverse();
+        match parents[index].and_then(|parent| nodes[parent].as_mut()) {
+            Some(parent) => parent.children.push(node),
+            None => roots.push(node),
+        }
+    }
+    roots.reverse();
+    roots
+}
@@ -0,0 +1,212 @@
+use std::env;
+use std::ffi::OsStr;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+use rusqlite::{params, Connection, OptionalExtension};
+
+use crate::errors::CommandError;
+use crate::types::PandocStatus;
+use crate::util::{now_ms, path_display};
+use crate::CommandResult;
+
+/// Output formats offered through pandoc, as `(pandoc writer, file extension)`.
+/// Only the ones the detected pandoc actually lists are reported as usable.
+const PANDOC_EXPORT_FORMATS: [(&str, &str); 3] = [("epub", "epub"), ("rtf", "rtf"), ("odt", "odt")];
+
+#[cfg(windows)]
+const PANDOC_BINARY: &str = "pandoc.exe";
+#[cfg(not(windows))]
+const PANDOC_BINARY: &str = "pandoc";
+
+/// Install locations that are often missing from a GUI app's PATH, notably
+/// Homebrew's on macOS and the per-user installer's on Windows.
+fn well_known_locations() -> Vec<PathBuf> {
+    let mut locations = vec![
+        PathBuf::from("/opt/homebrew/bin/pandoc"),
+        PathBuf::from("/usr/local/bin/pandoc"),
+        PathBuf::from("/usr/bin/pandoc"),
+    ];
+    if let Some(local) = env::var_os("LOCALAPPDATA") {
+        locations.push(Path::new(&local).join("Pandoc").join("pandoc.exe"));
+    }
+    if let Some(program_files) = env::var_os("ProgramFiles") {
+        locations.push(Path::new(&program_files).join("Pandoc").join("pandoc.exe"));
+    }
+    locations
+}
+
+fn load_configured_path(connection: &Connection) -> CommandResult<Option<String>> {
+    connection
+        .query_row(
+            "SELECT binary_path FROM pandoc_settings WHERE id = 1",
+            [],
+            |row| row.get::<_, Option<String>>(0),
+        )
+        .optional()
+        .map(Option::flatten)
+        .map_err(|error| CommandError::database(format!("Could not load pandoc settings: {error}")))
+}
+
+pub(crate) fn store_configured_path(
+    connection: &Connection,
+    binary_path: Option<&str>,
+) -> CommandResult<()> {
+    let binary_path = binary_path.map(str::trim).filter(|path| !path.is_empty());
+    if let Some(path) = binary_path {
+        if !Path::new(path).is_file() {
+            return Err(CommandError::validation(format!("'{path}' is not a file.")));
+        }
+    }
+    connection
+        .execute(
+            "
+            INSERT INTO pandoc_settings(id, binary_path, updated_at_ms)
+            VALUES(1, ?1, ?2)
+            ON CONFLICT(id) DO UPDATE SET
+              binary_path = excluded.binary_path,
+              updated_at_ms = excluded.updated_at_ms
+            ",
+            params![binary_path, now_ms()],
+        )
+        .map_err(|error| {
+            CommandError::database(format!("Could not store pandoc settings: {error}"))
+        })?;
+    Ok(())
+}
+
+fn run_pandoc(binary: &Path, arguments: &[&OsStr]) -> CommandResult<Output> {
+    let mut command = Command::new(binary);
+    command.args(arguments);
+    #[cfg(windows)]
+    {
+        use std::os::windows::process::CommandExt;
+        // CREATE_NO_WINDOW, so each call doesn't flash a console.
+        command.creation_flags(0x0800_0000);
+    }
+    command.output().map_err(|error| {
+        CommandError::io(format!(
+            "Could not run pandoc at '{}': {error}",
+            path_display(binary)
+        ))
+    })
+}
+
+fn pandoc_version(binary: &Path) -> Option<String> {
+    let output = run_pandoc(binary, &["--version".as_ref()]).ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let first_line = String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .next()
+        .unwrap_or_default()
+        .to_string();
+    // Windows builds print `pandoc.exe 3.1.2`.
+    let version = first_line.trim().strip_prefix("pandoc")?;
+    Some(version.trim_start_matches(".exe").trim().to_string())
+}
+
+/// Finds a working pandoc: the configured binary if set, otherwise the first
+/// one on PATH or in a well-known install location that answers `--version`.
+fn detect_pandoc(configured: Option<&str>) -> Option<(PathBuf, String)> {
+    if let Some(path) = configured {
+        let binary = PathBuf::from(path);
+        return pandoc_version(&binary).map(|version| (binary, version));
+    }
+    let on_path = env::var_os("PATH")
+        .map(|paths| {
+            env::split_paths(&paths)
+                .map(|directory| directory.join(PANDOC_BINARY))
+                .collect::<Vec<PathBuf>>()
+        })
+        .unwrap_or_default();
+    on_path
+        .into_iter()
+        .chain(well_known_locations())
+        .filter(|candidate| candidate.is_file())
+        .find_map(|candidate| pandoc_version(&candidate).map(|version| (candidate, version)))
+}
+
+fn supported_export_formats(binary: &Path) -> Vec<String> {
+    let Ok(output) = run_pandoc(binary, &["--list-output-formats".as_ref()]) else {
+        return Vec::new();
+    };
+    let listed = String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .map(|line| line.trim().to_string())
+        .collect::<Vec<String>>();
+    PANDOC_EXPORT_FORMATS
+        .iter()
+        .filter(|(writer, _)| listed.iter().any(|line| line == writer))
+        .map(|(writer, _)| writer.to_string())
+        .collect()
+}
+
+pub(crate) fn pandoc_status(connection: &Connection) -> CommandResult<PandocStatus> {
+    let configured_path = load_configured_path(connection)?;
+    let detected = detect_pandoc(configured_path.as_deref());
+    let export_formats = detected
+        .as_ref()
+        .map(|(binary, _)| supported_export_formats(binary))
+        .unwrap_or_default();
+    Ok(PandocStatus {
+        available: detected.is_some(),
+        binary_path: detected.as_ref().map(|(binary, _)| path_display(binary)),
+        version: detected.map(|(_, version)| version),
+        configured_path,
+        export_formats,
+    })
+}
+
+/// File extension pandoc output should get for a supported writer.
+pub(crate) fn pandoc_extension(format: &str) -> Option<&'static str> {
+    PANDOC_EXPORT_FORMATS
+        .iter()
+        .find(|(writer, _)| *writer == format)
+        .map(|(_, extension)| *extension)
+}
+
+/// Converts a docx with pandoc. Fails with pandoc's own message when the
+/// conversion does, since that is the only useful diagnostic.
+pub(crate) fn convert_docx_with_pandoc(
+    connection: &Connection,
+    input: &Path,
+    format: &str,
+    output: &Path,
+) -> CommandResult<()> {
+    let status = pandoc_status(connection)?;
+    let binary = status.binary_path.ok_or_else(|| {
+        CommandError::io("Pandoc was not found. Install it or set its location in settings.")
+    })?;
+    if !status.export_formats.iter().any(|known| known == format) {
+        return Err(CommandError::validation(format!(
+            "This pandoc ({}) cannot write '{format}'.",
+            status.version.unwrap_or_default()
+        )));
+    }
+
+    let result = run_pandoc(
+        Path::new(&binary),
+        &[
+            "--from=docx".as_ref(),
+            format!("--to={format}").as_ref(),
+            "--output".as_ref(),
+            output.as_os_str(),
+            input.as_os_str(),
+        ],
+    )?;
+    if !result.status.success() {
+        let message = String::from_utf8_lossy(&result.stderr).trim().to_string();
+        return Err(CommandError::io(format!(
+            "Pandoc could not convert '{}': {}",
+            path_display(input),
+            if message.is_empty() {
+                format!("exited with {}", result.status)
+            } else {
+                message
+            }
+        )));
+    }
+    Ok(())
+}
@@ -0,0 +1,404 @@
+use std::fs;
+use std::path::Path;
+
+use crate::errors::CommandError;
+use crate::markdown::{InlineStyle, StyledParagraph};
+use crate::util::path_display;
+use crate::CommandResult;
+
+// US Letter with 0.75in margins, in points.
+const PAGE_WIDTH: f64 = 612.0;
+const PAGE_HEIGHT: f64 = 792.0;
+const MARGIN: f64 = 54.0;
+const BODY_FONT_SIZE: f64 = 11.0;
+const LINE_SPACING: f64 = 1.25;
+const PARAGRAPH_GAP: f64 = 6.0;
+
+/// Helvetica advance widths for ASCII 32..=126, in 1/1000 em. The base-14
+/// fonts need no embedding, so these are all the metrics line breaking needs.
+const HELVETICA_WIDTHS: [u16; 95] = [
+    278, 278, 355, 556, 556, 889, 667, 191, 333, 333, 389, 584, 278, 333, 278, 278, 556, 556, 556,
+    556, 556, 556, 556, 556, 556, 556, 278, 278, 584, 584, 584, 556, 1015, 667, 667, 722, 722, 667,
+    611, 778, 722, 278, 500, 667, 556, 833, 722, 778, 667, 778, 722, 667, 611, 722, 667, 944, 667,
+    667, 611, 278, 278, 278, 469, 556, 333, 556, 556, 500, 556, 556, 278, 556, 556, 222, 222, 500,
+    222, 833, 556, 556, 556, 556, 333, 500, 278, 556, 500, 722, 500, 500, 500, 334, 260, 334, 584,
+];
+const HELVETICA_BOLD_WIDTHS: [u16; 95] = [
+    278, 333, 474, 556, 556, 889, 722, 238, 333, 333, 389, 584, 278, 333, 278, 278, 556, 556, 556,
+    556, 556, 556, 556, 556, 556, 556, 333, 333, 584, 584, 584, 611, 975, 722, 722, 722, 722, 667,
+    611, 778, 722, 278, 556, 722, 611, 833, 722, 778, 667, 778, 722, 667, 611, 722, 667, 944, 667,
+    667, 611, 333, 278, 333, 584, 556, 333, 556, 611, 556, 611, 556, 333, 611, 611, 278, 278, 556,
+    278, 889, 611, 611, 611, 611, 389, 556, 333, 611, 556, 778, 556, 556, 500, 389, 280, 389, 584,
+];
+const FALLBACK_WIDTH: u16 = 556;
+
+const FONT_NAMES: [&str; 4] = [
+    "Helvetica",
+    "Helvetica-Bold",
+    "Helvetica-Oblique",
+    "Helvetica-BoldOblique",
+];
+
+pub(crate) struct PdfExportCounts {
+    pub page_count: usize,
+    pub paragraph_count: usize,
+}
+
+fn heading_font_size(level: i64) -> f64 {
+    match level {
+        1 => 18.0,
+        2 => 16.0,
+        3 => 14.0,
+        _ => 12.0,
+    }
+}
+
+/// Index into `FONT_NAMES`, i.e. the `/F{n}` resource used for the style.
+fn font_index(style: InlineStyle) -> usize {
+    match (style.bold, style.italic) {
+        (false, false) => 0,
+        (true, false) => 1,
+        (false, true) => 2,
+        (true, true) => 3,
+    }
+}
+
+/// Maps a character to its WinAnsiEncoding byte, which is what the standard
+/// fonts use. Characters outside it print as `?`.
+fn win_ansi_byte(character: char) -> u8 {
+    match character {
+        ' '..='~' => character as u8,
+        '\u{a0}'..='\u{ff}' => character as u32 as u8,
+        '€' => 0x80,
+        '…' => 0x85,
+        '‘' => 0x91,
+        '’' => 0x92,
+        '“' => 0x93,
+        '”' => 0x94,
+        '•' => 0x95,
+        '–' => 0x96,
+        '—' => 0x97,
+        '\t' => b' ',
+        _ => b'?',
+    }
+}
+
+fn text_width(text: &str, style: InlineStyle, size: f64) -> f64 {
+    let widths = if style.bold {
+        &HELVETICA_BOLD_WIDTHS
+    } else {
+        &HELVETICA_WIDTHS
+    };
+    let units = text
+        .chars()
+        .map(|character| match win_ansi_byte(character) {
+            byte @ 32..=126 => widths[(byte - 32) as usize],
+            _ => FALLBACK_WIDTH,
+        })
+        .map(u32::from)
+        .sum::<u32>();
+    f64::from(units) * size / 1000.0
+}
+
+fn pdf_string(text: &str) -> Vec<u8> {
+    let mut bytes = vec![b'('];
+    for character in text.chars() {
+        let byte = win_ansi_byte(character);
+        if matches!(byte, b'(' | b')' | b'\\') {
+            bytes.push(b'\\');
+        }
+        bytes.push(byte);
+    }
+    bytes.push(b')');
+    bytes
+}
+
+/// A run of same-styled text placed on a line.
+struct Fragment {
+    x: f64,
+    text: String,
+    style: InlineStyle,
+    size: f64,
+    width: f64,
+}
+
+#[derive(Default)]
+struct Line {
+    fragments: Vec<Fragment>,
+    width: f64,
+    size: f64,
+}
+
+impl Line {
+    fn push(&mut self, text: &str, style: InlineStyle, size: f64) {
+        let width = text_width(text, style, size);
+        self.size = self.size.max(size);
+        if let Some(last) = self.fragments.last_mut() {
+            if last.style == style && last.size == size {
+                last.text.push_str(text);
+                last.width += width;
+                self.width += width;
+                return;
+            }
+        }
+        self.fragments.push(Fragment {
+            x: self.width,
+            text: text.to_string(),
+            style,
+            size,
+            width,
+        });
+        self.width += width;
+    }
+}
+
+/// Splits text into alternating whitespace and word tokens, keeping both so
+/// spacing inside underlined or highlighted runs survives.
+fn tokens(text: &str) -> Vec<&str> {
+    let mut tokens = Vec::new();
+    let mut start = 0;
+    let mut previous_space = None;
+    for (index, character) in text.char_indices() {
+        let is_space = character.is_whitespace();
+        if previous_space.is_some_and(|previous| previous != is_space) {
+            tokens.push(&text[start..index]);
+            start = index;
+        }
+        previous_space = Some(is_space);
+    }
+    if start < text.len() {
+        tokens.push(&text[start..]);
+    }
+    tokens
+}
+
+/// Greedy line breaking over the paragraph's styled segments.
+fn layout_paragraph(paragraph: &StyledParagraph, max_width: f64) -> Vec<Line> {
+    let heading_size = paragraph.heading_level.map(heading_font_size);
+    let mut lines = Vec::new();
+    let mut line = Line::default();
+    for segment in &paragraph.segments {
+        let mut style = segment.style;
+        if heading_size.is_some() {
+            style.bold = true;
+        }
+        let size = heading_size.unwrap_or(BODY_FONT_SIZE);
+        for token in tokens(&segment.text) {
+            if token.contains('\n') {
+                lines.push(std::mem::take(&mut line));
+                continue;
+            }
+            if token.trim().is_empty() {
+                if !line.fragments.is_empty() {
+                    line.push(" ", style, size);
+                }
+                continue;
+            }
+            let width = text_width(token, style, size);
+            if line.width + width > max_width && !line.fragments.is_empty() {
+                trim_trailing_space(&mut line);
+                lines.push(std::mem::take(&mut line));
+            }
+            if width <= max_width {
+                line.push(token, style, size);
+                continue;
+            }
+            // A word wider than the page is broken wherever it runs out.
+            for character in token.chars() {
+                let mut buffer = [0; 4];
+                let piece = character.encode_utf8(&mut buffer);
+                if line.width + text_width(piece, style, size) > max_width
+                    && !line.fragments.is_empty()
+                {
+                    lines.push(std::mem::take(&mut line));
+                }
+                line.push(piece, style, size);
+            }
+        }
+    }
+    trim_trailing_space(&mut line);
+    lines.push(line);
+    for line in &mut lines {
+        if line.size == 0.0 {
+            line.size = heading_size.unwrap_or(BODY_FONT_SIZE);
+        }
+    }
+    lines
+}
+
+fn trim_trailing_space(line: &mut Line) {
+    if let Some(last) = line.fragments.last_mut() {
+        let trimmed = last.text.trim_end().len();
+        if trimmed < last.text.len() {
+            let removed = text_width(&last.text[trimmed..], last.style, last.size);
+            last.text.truncate(trimmed);
+            last.width -= removed;
+            line.width -= removed;
+        }
+    }
+}
+
+fn draw_line(content: &mut Vec<u8>, line: &Line, baseline: f64) {
+    for fragment in &line.fragments {
+        let x = MARGIN + fragment.x;
+        if fragment.style.highlight {
+            content.extend_from_slice(
+                format!(
+                    "1 1 0 rg {x:.2} {:.2} {:.2} {:.2} re f 0 g\n",
+                    baseline - fragment.size * 0.22,
+                    fragment.width,
+                    fragment.size * 1.12
+                )
+                .as_bytes(),
+            );
+        }
+        content.extend_from_slice(
+            format!(
+                "BT /F{} {:.1} Tf {x:.2} {baseline:.2} Td ",
+                font_index(fragment.style) + 1,
+                fragment.size
+            )
+            .as_bytes(),
+        );
+        content.extend_from_slice(&pdf_string(&fragment.text));
+        content.extend_from_slice(b" Tj ET\n");
+        if fragment.style.underline {
+            let y = baseline - fragment.size * 0.12;
+            content.extend_from_slice(
+                format!(
+                    "{:.2} w {x:.2} {y:.2} m {:.2} {y:.2} l S\n",
+                    fragment.size * 0.06,
+                    x + fragment.width
+                )
+                .as_bytes(),
+            );
+        }
+    }
+}
+
+/// Lays the paragraphs out onto as many pages as they need and returns each
+/// page's content stream.
+fn render_pages(paragraphs: &[StyledParagraph]) -> Vec<Vec<u8>> {
+    let max_width = PAGE_WIDTH - 2.0 * MARGIN;
+    let mut pages = Vec::new();
+    let mut content = Vec::new();
+    let mut cursor = PAGE_HEIGHT - MARGIN;
+    for paragraph in paragraphs {
+        if paragraph.heading_level.is_some() && cursor < PAGE_HEIGHT - MARGIN {
+            cursor -= PARAGRAPH_GAP;
+        }
+        for line in layout_paragraph(paragraph, max_width) {
+            let height = line.size * LINE_SPACING;
+            if cursor - height < MARGIN && !content.is_empty() {
+                pages.push(std::mem::take(&mut content));
+                cursor = PAGE_HEIGHT - MARGIN;
+            }
+            cursor -= height;
+            draw_line(&mut content, &line, cursor + line.size * 0.25);
+        }
+        cursor -= PARAGRAPH_GAP;
+    }
+    pages.push(content);
+    pages
+}
+
+/// Assembles a PDF from numbered objects, computing the cross-reference table
+/// from their byte offsets.
+fn assemble_pdf(objects: &[Vec<u8>]) -> Vec<u8> {
+    let mut output = b"%PDF-1.4\n%\xe2\xe3\xcf\xd3\n".to_vec();
+    let mut offsets = Vec::with_capacity(objects.len());
+    for (index, object) in objects.iter().enumerate() {
+        offsets.push(output.len());
+        output.extend_from_slice(format!("{} 0 obj\n", index + 1).as_bytes());
+        output.extend_from_slice(object);
+        output.extend_from_slice(b"\nendobj\n");
+    }
+    let xref_offset = output.len();
+    output.extend_from_slice(format!("xref\n0 {}\n", objects.len() + 1).as_bytes());
+    output.extend_from_slice(b"0000000000 65535 f \n");
+    for offset in offsets {
+        output.extend_from_slice(format!("{offset:010} 00000 n \n").as_bytes());
+    }
+    output.extend_from_slice(
+        format!(
+            "trailer\n<< /Size {} /Root 1 0 R /Info 2 0 R >>\nstartxref\n{xref_offset}\n%%EOF\n",
+            objects.len() + 1
+        )
+        .as_bytes(),
+    );
+    output
+}
+
+/// Writes the paragraphs to a standalone PDF using the standard Helvetica
+/// family, with bold, italic, underline and highlight drawn as in the docx.
+pub(crate) fn write_section_pdf(
+    output_path: &Path,
+    title: &str,
+    paragraphs: &[StyledParagraph],
+) -> CommandResult<PdfExportCounts> {
+    let pages = render_pages(paragraphs);
+
+    // 1 catalog, 2 info, 3 page tree, 4..=7 fonts, then page/content pairs.
+    let first_page_object = 4 + FONT_NAMES.len();
+    let page_references = (0..pages.len())
+        .map(|index| format!("{} 0 R", first_page_object + index * 2))
+        .collect::<Vec<String>>()
+        .join(" ");
+    let font_resources = (0..FONT_NAMES.len())
+        .map(|index| format!("/F{} {} 0 R", index + 1, 4 + index))
+        .collect::<Vec<String>>()
+        .join(" ");
+
+    let mut objects = vec![b"<< /Type /Catalog /Pages 3 0 R >>".to_vec()];
+    let mut info = b"<< /Producer (BlockFile) /Title ".to_vec();
+    info.extend_from_slice(&pdf_string(title.trim()));
+    info.extend_from_slice(b" >>");
+    objects.push(info);
+    objects.push(
+        format!(
+            "<< /Type /Pages /Kids [{page_references}] /Count {} >>",
+            pages.len()
+        )
+        .into_bytes(),
+    );
+    for name in FONT_NAMES {
+        objects.push(
+            format!(
+                "<< /Type /Font /Subtype /Type1 /BaseFont /{name} /Encoding /WinAnsiEncoding >>"
+            )
+            .into_bytes(),
+        );
+    }
+    for (index, content) in pages.iter().enumerate() {
+        objects.push(
+            format!(
+                "<< /Type /Page /Parent 3 0 R /MediaBox [0 0 {PAGE_WIDTH} {PAGE_HEIGHT}] /Resources << /Font << {font_resources} >> >> /Contents {} 0 R >>",
+                first_page_object + index * 2 + 1
+            )
+            .into_bytes(),
+        );
+        let mut stream = format!("<< /Length {} >>\nstream\n", content.len()).into_bytes();
+        stream.extend_from_slice(content);
+        stream.extend_from_slice(b"\nendstream");
+        objects.push(stream);
+    }
+
+    if let Some(parent) = output_path.parent() {
+        fs::create_dir_all(parent).map_err(|error| {
+            CommandError::io(format!(
+                "Could not create export folder '{}': {error}",
+                path_display(parent)
+            ))
+        })?;
+    }
+    fs::write(output_path, assemble_pdf(&objects)).map_err(|error| {
+        CommandError::io(format!(
+            "Could not write PDF '{}': {error}",
+            path_display(output_path)
+        ))
+    })?;
+    Ok(PdfExportCounts {
+        page_count: pages.len(),
+        paragraph_count: paragraphs.len(),
+    })
+}
@@ -0,0 +1,1420 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io::Read;
+use std::path::Path;
+
+use flate2::read::ZlibDecoder;
+
+use crate::docx_build::ParagraphBuilder;
+use crate::errors::CommandError;
+use crate::rtf_import::cp1252_char;
+use crate::types::{GeneratedParagraph, GeneratedRun};
+use crate::util::path_display;
+use crate::CommandResult;
+
+/// Cap on a decoded stream, so a crafted one cannot inflate without bound.
+const MAX_DECODED_STREAM_BYTES: u64 = 64 * 1024 * 1024;
+const MAX_REFERENCE_DEPTH: usize = 16;
+const MAX_NESTING_DEPTH: usize = 64;
+const MAX_XOBJECT_DEPTH: usize = 8;
+const MAX_CMAP_RANGE: u32 = 0xFFFF;
+const FORCE_BOLD_FLAG: u32 = 1 << 18;
+/// `TJ` adjustments are in thousandths of an em.
+const TJ_SPACE_ADJUSTMENT: f64 = 200.0;
+/// Glyph widths are not read; a run is guessed at half an em per character.
+const AVERAGE_GLYPH_WIDTH: f64 = 0.5;
+const WORD_GAP_RATIO: f64 = 0.2;
+const HEADING_SIZE_RATIO: f64 = 1.15;
+const MAX_SIZE_TIERS: usize = 3;
+const MAX_BOLD_HEADING_CHARS: usize = 150;
+const PARAGRAPH_GAP_RATIO: f64 = 1.8;
+const FONT_WEIGHT_WORDS: [&str; 5] = ["bold", "black", "heavy", "semibold", "demi"];
+const GLYPH_NAMES: [(&str, &str); 57] = [
+    ("space", " "),
+    ("exclam", "!"),
+    ("quotedbl", "\""),
+    ("numbersign", "#"),
+    ("dollar", "$"),
+    ("percent", "%"),
+    ("ampersand", "&"),
+    ("quotesingle", "'"),
+    ("quoteleft", "‘"),
+    ("quoteright", "’"),
+    ("quotedblleft", "“"),
+    ("quotedblright", "”"),
+    ("parenleft", "("),
+    ("parenright", ")"),
+    ("asterisk", "*"),
+    ("plus", "+"),
+    ("comma", ","),
+    ("hyphen", "-"),
+    ("minus", "−"),
+    ("period", "."),
+    ("slash", "/"),
+    ("zero", "0"),
+    ("one", "1"),
+    ("two", "2"),
+    ("three", "3"),
+    ("four", "4"),
+    ("five", "5"),
+    ("six", "6"),
+    ("seven", "7"),
+    ("eight", "8"),
+    ("nine", "9"),
+    ("colon", ":"),
+    ("semicolon", ";"),
+    ("less", "<"),
+    ("equal", "="),
+    ("greater", ">"),
+    ("question", "?"),
+    ("at", "@"),
+    ("bracketleft", "["),
+    ("backslash", "\\"),
+    ("bracketright", "]"),
+    ("asciicircum", "^"),
+    ("underscore", "_"),
+    ("grave", "`"),
+    ("braceleft", "{"),
+    ("bar", "|"),
+    ("braceright", "}"),
+    ("asciitilde", "~"),
+    ("endash", "–"),
+    ("emdash", "—"),
+    ("bullet", "•"),
+    ("ellipsis", "…"),
+    ("section", "§"),
+    ("fi", "fi"),
+    ("fl", "fl"),
+    ("ff", "ff"),
+    ("ffi", "ffi"),
+];
+
+static NULL: PdfObject = PdfObject::Null;
+
+/// Streams keep their data still encoded; booleans are read as `Null`.
+enum PdfObject {
+    Null,
+    Number(f64),
+    String(Vec<u8>),
+    Name(String),
+    Array(Vec<PdfObject>),
+    Dictionary(HashMap<String, PdfObject>),
+    Stream(HashMap<String, PdfObject>, Vec<u8>),
+    Reference(u32),
+}
+
+impl PdfObject {
+    fn as_number(&self) -> Option<f64> {
+        match self {
+            Self::Number(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    fn as_name(&self) -> Option<&str> {
+        match self {
+            Self::Name(name) => Some(name),
+            _ => None,
+        }
+    }
+
+    fn as_array(&self) -> Option<&[PdfObject]> {
+        match self {
+            Self::Array(items) => Some(items),
+            _ => None,
+        }
+    }
+
+    fn as_dictionary(&self) -> Option<&HashMap<String, PdfObject>> {
+        match self {
+            Self::Dictionary(dictionary) | Self::Stream(dictionary, _) => Some(dictionary),
+            _ => None,
+        }
+    }
+}
+
+fn is_whitespace(byte: u8) -> bool {
+    matches!(byte, 0 | b'\t' | b'\n' | 0x0C | b'\r' | b' ')
+}
+
+fn is_delimiter(byte: u8) -> bool {
+    matches!(
+        byte,
+        b'(' | b')' | b'<' | b'>' | b'[' | b']' | b'{' | b'}' | b'/' | b'%'
+    )
+}
+
+fn is_regular(byte: u8) -> bool {
+    !is_whitespace(byte) && !is_delimiter(byte)
+}
+
+fn find(haystack: &[u8], needle: &[u8], from: usize) -> Option<usize> {
+    haystack
+        .get(from..)?
+        .windows(needle.len())
+        .position(|window| window == needle)
+        .map(|index| index + from)
+}
+
+fn hex_value(byte: u8) -> Option<u8> {
+    char::from(byte)
+        .to_digit(16)
+        .and_then(|digit| u8::try_from(digit).ok())
+}
+
+enum Token {
+    Operand(PdfObject),
+    Operator(Vec<u8>),
+}
+
+struct Lexer<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Lexer<'a> {
+    fn new(bytes: &'a [u8], position: usize) -> Self {
+        Self { bytes, position }
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(&byte) = self.bytes.get(self.position) {
+            if is_whitespace(byte) {
+                self.position += 1;
+            } else if byte == b'%' {
+                while self
+                    .bytes
+                    .get(self.position)
+                    .is_some_and(|byte| *byte != b'\n' && *byte != b'\r')
+                {
+                    self.position += 1;
+                }
+            } else {
+                break;
+            }
+        }
+    }
+
+    fn regular_token(&mut self) -> &'a [u8] {
+        let start = self.position;
+        while self
+            .bytes
+            .get(self.position)
+            .is_some_and(|byte| is_regular(*byte))
+        {
+            self.position += 1;
+        }
+        &self.bytes[start..self.position]
+    }
+
+    fn next_token(&mut self) -> Option<Token> {
+        self.skip_whitespace();
+        let byte = *self.bytes.get(self.position)?;
+        if matches!(byte, b'(' | b'<' | b'[' | b'/' | b'+' | b'-' | b'.') || byte.is_ascii_digit() {
+            return Some(Token::Operand(
+                self.object(false, 0).unwrap_or(PdfObject::Null),
+            ));
+        }
+        if !is_regular(byte) {
+            self.position += 1;
+            return Some(Token::Operator(vec![byte]));
+        }
+        Some(match self.regular_token() {
+            b"true" | b"false" | b"null" => Token::Operand(PdfObject::Null),
+            operator => Token::Operator(operator.to_vec()),
+        })
+    }
+
+    /// `references` enables `n g R`, which content streams never contain.
+    fn object(&mut self, references: bool, depth: usize) -> Option<PdfObject> {
+        self.skip_whitespace();
+        let byte = *self.bytes.get(self.position)?;
+        if depth > MAX_NESTING_DEPTH {
+            self.position += 1;
+            return None;
+        }
+        match byte {
+            b'(' => {
+                self.position += 1;
+                Some(PdfObject::String(self.literal_string()))
+            }
+            b'<' if self.bytes.get(self.position + 1) == Some(&b'<') => {
+                self.position += 2;
+                Some(self.dictionary(references, depth))
+            }
+            b'<' => {
+                self.position += 1;
+                Some(PdfObject::String(self.hex_string()))
+            }
+            b'[' => {
+                self.position += 1;
+                let mut items = Vec::new();
+                loop {
+                    self.skip_whitespace();
+                    match self.bytes.get(self.position) {
+                        None => break,
+                        Some(b']') => {
+                            self.position += 1;
+                            break;
+                        }
+                        Some(_) => items.extend(self.object(references, depth + 1)),
+                    }
+                }
+                Some(PdfObject::Array(items))
+            }
+            b'/' => {
+                self.position += 1;
+                Some(PdfObject::Name(self.name()))
+            }
+            _ if is_regular(byte) => match self.regular_token() {
+                b"true" | b"false" | b"null" => Some(PdfObject::Null),
+                token => {
+                    let number = std::str::from_utf8(token).ok()?.parse::<f64>().ok()?;
+                    if references && token.iter().all(u8::is_ascii_digit) {
+                        if let Some(reference) = self.reference_tail(number) {
+                            return Some(reference);
+                        }
+                    }
+                    Some(PdfObject::Number(number))
+                }
+            },
+            _ => {
+                self.position += 1;
+                None
+            }
+        }
+    }
+
+    fn reference_tail(&mut self, number: f64) -> Option<PdfObject> {
+        let start = self.position;
+        self.skip_whitespace();
+        let generation = self.regular_token();
+        self.skip_whitespace();
+        if !generation.is_empty()
+            && generation.iter().all(u8::is_ascii_digit)
+            && self.regular_token() == b"R"
+        {
+            return Some(PdfObject::Reference(number as u32));
+        }
+        self.position = start;
+        None
+    }
+
+    fn dictionary(&mut self, references: bool, depth: usize) -> PdfObject {
+        let mut dictionary = HashMap::new();
+        loop {
+            self.skip_whitespace();
+            match self.bytes.get(self.position) {
+                None => break,
+                Some(b'>') => {
+                    self.position += 1;
+                    if self.bytes.get(self.position) == Some(&b'>') {
+                        self.position += 1;
+                    }
+                    break;
+                }
+                Some(b'/') => {
+                    self.position += 1;
+                    let key = self.name();
+                    if let Some(value) = self.object(references, depth + 1) {
+                        dictionary.insert(key, value);
+                    }
+                }
+                Some(_) => {
+                    self.object(references, depth + 1);
+                }
+            }
+        }
+        PdfObject::Dictionary(dictionary)
+    }
+
+    fn name(&mut self) -> String {
+        let token = self.regular_token();
+        let mut name = Vec::with_capacity(token.len());
+        let mut index = 0;
+        while index < token.len() {
+            let escaped = if token[index] == b'#' {
+                token
+                    .get(index + 1)
+                    .zip(token.get(index + 2))
+                    .and_then(|(high, low)| Some((hex_value(*high)? << 4) | hex_value(*low)?))
+            } else {
+                None
+            };
+            match escaped {
+                Some(byte) => {
+                    name.push(byte);
+                    index += 3;
+                }
+                None => {
+                    name.push(token[index]);
+                    index += 1;
+                }
+            }
+        }
+        String::from_utf8_lossy(&name).into_owned()
+    }
+
+    fn literal_string(&mut self) -> Vec<u8> {
+        let mut value = Vec::new();
+        let mut depth = 1;
+        while let Some(&byte) = self.bytes.get(self.position) {
+            self.position += 1;
+            match byte {
+                b'(' => {
+                    depth += 1;
+                    value.push(byte);
+                }
+                b')' => {
+                    depth -= 1;
+                    if depth == 0 {
+                        break;
+                    }
+                    value.push(byte);
+                }
+                b'\\' => self.string_escape(&mut value),
+                _ => value.push(byte),
+            }
+        }
+        value
+    }
+
+    fn string_escape(&mut self, value: &mut Vec<u8>) {
+        let Some(&byte) = self.bytes.get(self.position) else {
+            return;
+        };
+        self.position += 1;
+        match byte {
+            b'n' => value.push(b'\n'),
+            b'r' => value.push(b'\r'),
+            b't' => value.push(b'\t'),
+            b'b' => value.push(0x08),
+            b'f' => value.push(0x0C),
+            b'0'..=b'7' => {
+                let mut code = u32::from(byte - b'0');
+                for _ in 0..2 {
+                    match self.bytes.get(self.position) {
+                        Some(digit @ b'0'..=b'7') => {
+                            code = code * 8 + u32::from(digit - b'0');
+                            self.position += 1;
+                        }
+                        _ => break,
+                    }
+                }
+                value.push((code & 0xFF) as u8);
+            }
+            // A backslash before a line break continues the string.
+            b'\r' => {
+                if self.bytes.get(self.position) == Some(&b'\n') {
+                    self.position += 1;
+                }
+            }
+            b'\n' => {}
+            _ => value.push(byte),
+        }
+    }
+
+    fn hex_string(&mut self) -> Vec<u8> {
+        let mut digits = Vec::new();
+        while let Some(&byte) = self.bytes.get(self.position) {
+            self.position += 1;
+            if byte == b'>' {
+                break;
+            }
+            digits.extend(hex_value(byte));
+        }
+        digits
+            .chunks(2)
+            .map(|pair| (pair[0] << 4) | pair.get(1).copied().unwrap_or(0))
+            .collect()
+    }
+
+    fn skip_inline_image(&mut self) {
+        let mut position = self.position + 1;
+        while position + 2 <= self.bytes.len() {
+            if &self.bytes[position..position + 2] == b"EI"
+                && is_whitespace(self.bytes[position - 1])
+                && self
+                    .bytes
+                    .get(position + 2)
+                    .is_none_or(|byte| is_whitespace(*byte))
+            {
+                self.position = position + 2;
+                return;
+            }
+            position += 1;
+        }
+        self.position = self.bytes.len();
+    }
+}
+
+fn object_header(bytes: &[u8], keyword_start: usize) -> Option<u32> {
+    let skip_whitespace = |mut position: usize| {
+        while position > 0 && is_whitespace(bytes[position - 1]) {
+            position -= 1;
+        }
+        position
+    };
+    let skip_digits = |mut position: usize| {
+        while position > 0 && bytes[position - 1].is_ascii_digit() {
+            position -= 1;
+        }
+        position
+    };
+    let generation_end = skip_whitespace(keyword_start);
+    let generation_start = skip_digits(generation_end);
+    let number_end = skip_whitespace(generation_start);
+    let number_start = skip_digits(number_end);
+    if generation_end == keyword_start
+        || generation_start == generation_end
+        || number_end == generation_start
+        || number_start == number_end
+        || (number_start > 0 && is_regular(bytes[number_start - 1]))
+    {
+        return None;
+    }
+    std::str::from_utf8(&bytes[number_start..number_end])
+        .ok()?
+        .parse()
+        .ok()
+}
+
+/// An indirect or wrong `/Length` falls back to searching for `endstream`.
+fn stream_data(
+    bytes: &[u8],
+    lexer: &mut Lexer<'_>,
+    dictionary: &HashMap<String, PdfObject>,
+) -> Option<Vec<u8>> {
+    lexer.skip_whitespace();
+    if !bytes.get(lexer.position..)?.starts_with(b"stream") {
+        return None;
+    }
+    let mut start = lexer.position + b"stream".len();
+    if bytes.get(start) == Some(&b'\r') {
+        start += 1;
+    }
+    if bytes.get(start) == Some(&b'\n') {
+        start += 1;
+    }
+    let declared_end = dictionary
+        .get("Length")
+        .and_then(PdfObject::as_number)
+        .and_then(|length| start.checked_add(length as usize))
+        .filter(|end| {
+            let mut after = Lexer::new(bytes, *end);
+            after.skip_whitespace();
+            *end <= bytes.len() && bytes[after.position..].starts_with(b"endstream")
+        });
+    let end = match declared_end {
+        Some(end) => end,
+        None => {
+            let mut end = find(bytes, b"endstream", start)?;
+            if end > start && bytes[end - 1] == b'\n' {
+                end -= 1;
+            }
+            if end > start && bytes[end - 1] == b'\r' {
+                end -= 1;
+            }
+            end
+        }
+    };
+    lexer.position = find(bytes, b"endstream", end).map_or(bytes.len(), |index| index + 9);
+    Some(bytes[start..end].to_vec())
+}
+
+/// Scanned rather than read from the xref table, which is often wrong; a
+/// later definition wins, as in an incremental update.
+fn read_indirect_objects(bytes: &[u8]) -> HashMap<u32, PdfObject> {
+    let mut objects = HashMap::new();
+    let mut search_from = 0;
+    while let Some(keyword_start) = find(bytes, b"obj", search_from) {
+        search_from = keyword_start + 3;
+        if bytes.get(search_from).is_some_and(|byte| is_regular(*byte)) {
+            continue;
+        }
+        let Some(number) = object_header(bytes, keyword_start) else {
+            continue;
+        };
+        let mut lexer = Lexer::new(bytes, search_from);
+        let Some(object) = lexer.object(true, 0) else {
+            continue;
+        };
+        let object = match object {
+            PdfObject::Dictionary(dictionary) => {
+                match stream_data(bytes, &mut lexer, &dictionary) {
+                    Some(data) => PdfObject::Stream(dictionary, data),
+                    None => PdfObject::Dictionary(dictionary),
+                }
+            }
+            other => other,
+        };
+        search_from = lexer.position.max(search_from);
+        objects.insert(number, object);
+    }
+    objects
+}
+
+fn inflate(data: &[u8]) -> Option<Vec<u8>> {
+    let mut output = Vec::new();
+    // A damaged stream still yields what came before the damage.
+    let _ = ZlibDecoder::new(data)
+        .take(MAX_DECODED_STREAM_BYTES)
+        .read_to_end(&mut output);
+    (!output.is_empty()).then_some(output)
+}
+
+struct PdfFile {
+    objects: HashMap<u32, PdfObject>,
+}
+
+struct PageRef<'a> {
+    dictionary: &'a HashMap<String, PdfObject>,
+    resources: Option<&'a HashMap<String, PdfObject>>,
+}
+
+impl PdfFile {
+    fn resolve<'a>(&'a self, mut object: &'a PdfObject) -> &'a PdfObject {
+        for _ in 0..MAX_REFERENCE_DEPTH {
+            match object {
+                PdfObject::Reference(number) => {
+                    object = self.objects.get(number).unwrap_or(&NULL);
+                }
+                _ => return object,
+            }
+        }
+        &NULL
+    }
+
+    fn get<'a>(&'a self, dictionary: &'a HashMap<String, PdfObject>, key: &str) -> &'a PdfObject {
+        dictionary
+            .get(key)
+            .map_or(&NULL, |value| self.resolve(value))
+    }
+
+    fn dictionary<'a>(
+        &'a self,
+        dictionary: &'a HashMap<String, PdfObject>,
+        key: &str,
+    ) -> Option<&'a HashMap<String, PdfObject>> {
+        self.get(dictionary, key).as_dictionary()
+    }
+
+    /// Only Flate is supported.
+    fn stream_bytes(&self, object: &PdfObject) -> Option<Vec<u8>> {
+        let PdfObject::Stream(dictionary, data) = self.resolve(object) else {
+            return None;
+        };
+        let filters = match self.get(dictionary, "Filter") {
+            PdfObject::Name(name) => vec![name.as_str()],
+            PdfObject::Array(items) => items
+                .iter()
+                .filter_map(|item| self.resolve(item).as_name())
+                .collect(),
+            _ => Vec::new(),
+        };
+        let mut decoded = data.clone();
+        for filter in filters {
+            decoded = match filter {
+                "FlateDecode" | "Fl" => inflate(&decoded)?,
+                _ => return None,
+            };
+        }
+        Some(decoded)
+    }
+
+    /// An object defined directly in the file wins over a packed copy.
+    fn expand_object_streams(&mut self) {
+        let mut unpacked = Vec::new();
+        for object in self.objects.values() {
+            let PdfObject::Stream(dictionary, _) = object else {
+                continue;
+            };
+            if self.get(dictionary, "Type").as_name() != Some("ObjStm") {
+                continue;
+            }
+            let Some(decoded) = self.stream_bytes(object) else {
+                continue;
+            };
+            let count = self.get(dictionary, "N").as_number().unwrap_or(0.0) as usize;
+            let first = self.get(dictionary, "First").as_number().unwrap_or(0.0) as usize;
+            let mut header = Lexer::new(&decoded, 0);
+            let mut entries = Vec::new();
+            for _ in 0..count {
+                let (Some(PdfObject::Number(number)), Some(PdfObject::Number(offset))) =
+                    (header.object(false, 0), header.object(false, 0))
+                else {
+                    break;
+                };
+                entries.push((number as u32, first + offset as usize));
+            }
+            for (number, offset) in entries {
+                if offset >= decoded.len() {
+                    continue;
+                }
+                if let Some(object) = Lexer::new(&decoded, offset).object(true, 0) {
+                    unpacked.push((number, object));
+                }
+            }
+        }
+        for (number, object) in unpacked {
+            self.objects.entry(number).or_insert(object);
+        }
+    }
+
+    fn is_encrypted(&self, bytes: &[u8]) -> bool {
+        let mut search_from = 0;
+        while let Some(index) = find(bytes, b"trailer", search_from) {
+            search_from = index + b"trailer".len();
+            if let Some(PdfObject::Dictionary(trailer)) =
+                Lexer::new(bytes, search_from).object(true, 0)
+            {
+                if trailer.contains_key("Encrypt") {
+                    return true;
+                }
+            }
+        }
+        self.objects.values().any(|object| {
+            matches!(object, PdfObject::Stream(dictionary, _)
+                if dictionary.get("Type").and_then(PdfObject::as_name) == Some("XRef")
+                    && dictionary.contains_key("Encrypt"))
+        })
+    }
+
+    fn pages(&self) -> Vec<PageRef<'_>> {
+        let mut pages = Vec::new();
+        let catalog = self
+            .objects
+            .iter()
+            .filter_map(|(number, object)| Some((*number, object.as_dictionary()?)))
+            .filter(|(_, dictionary)| {
+                self.get(dictionary, "Type").as_name() == Some("Catalog")
+                    && dictionary.contains_key("Pages")
+            })
+            .max_by_key(|(number, _)| *number)
+            .map(|(_, dictionary)| dictionary);
+        if let Some(tree) = catalog.and_then(|catalog| self.dictionary(catalog, "Pages")) {
+            self.collect_pages(tree, None, 0, &mut pages);
+        }
+        if pages.is_empty() {
+            let mut numbers = self
+                .objects
+                .iter()
+                .filter(|(_, object)| {
+                    object.as_dictionary().is_some_and(|dictionary| {
+                        self.get(dictionary, "Type").as_name() == Some("Page")
+                    })
+                })
+                .map(|(number, _)| *number)
+                .collect::<Vec<u32>>();
+            numbers.sort_unstable();
+            pages = numbers
+                .into_iter()
+                .filter_map(|number| self.objects.get(&number)?.as_dictionary())
+                .map(|dictionary| PageRef {
+                    dictionary,
+                    resources: self.dictionary(dictionary, "Resources"),
+                })
+                .collect();
+        }
+        pages
+    }
+
+    fn collect_pages<'a>(
+        &'a self,
+        node: &'a HashMap<String, PdfObject>,
+        inherited_resources: Option<&'a HashMap<String, PdfObject>>,
+        depth: usize,
+        pages: &mut Vec<PageRef<'a>>,
+    ) {
+        if depth > MAX_NESTING_DEPTH {
+            return;
+        }
+        let resources = self.dictionary(node, "Resources").or(inherited_resources);
+        match self.get(node, "Kids").as_array() {
+            Some(kids) => {
+                for kid in kids {
+                    if let Some(kid) = self.resolve(kid).as_dictionary() {
+                        self.collect_pages(kid, resources, depth + 1, pages);
+                    }
+                }
+            }
+            None => pages.push(PageRef {
+                dictionary: node,
+                resources,
+            }),
+        }
+    }
+
+    fn page_content(&self, page: &HashMap<String, PdfObject>) -> Option<Vec<u8>> {
+        match self.get(page, "Contents") {
+            PdfObject::Array(items) => {
+                let mut content = Vec::new();
+                for data in items.iter().filter_map(|item| self.stream_bytes(item)) {
+                    content.extend(data);
+                    content.push(b'\n');
+                }
+                Some(content)
+            }
+            object => self.stream_bytes(object),
+        }
+    }
+}
+
+fn code_value(bytes: &[u8]) -> u32 {
+    bytes
+        .iter()
+        .fold(0_u32, |code, byte| (code << 8) | u32::from(*byte))
+}
+
+fn utf16_text(bytes: &[u8]) -> String {
+    let units = bytes
+        .chunks(2)
+        .map(|pair| {
+            pair.iter()
+                .fold(0_u16, |unit, byte| (unit << 8) | u16::from(*byte))
+        })
+        .collect::<Vec<u16>>();
+    String::from_utf16_lossy(&units)
+}
+
+fn offset_text(start: &[u8], offset: u32) -> String {
+    let mut units = start
+        .chunks(2)
+        .map(|pair| {
+            pair.iter()
+                .fold(0_u16, |unit, byte| (unit << 8) | u16::from(*byte))
+        })
+        .collect::<Vec<u16>>();
+    if let Some(last) = units.last_mut() {
+        *last = last.wrapping_add(offset as u16);
+    }
+    String::from_utf16_lossy(&units)
+}
+
+fn parse_to_unicode(data: &[u8]) -> (Option<usize>, HashMap<u32, String>) {
+    let mut lexer = Lexer::new(data, 0);
+    let mut operands = Vec::new();
+    let mut code_bytes = None;
+    let mut map = HashMap::new();
+    while let Some(token) = lexer.next_token() {
+        let operator = match token {
+            Token::Operand(operand) => {
+                operands.push(operand);
+                continue;
+            }
+            Token::Operator(operator) => operator,
+        };
+        match operator.as_slice() {
+            b"endcodespacerange" => {
+                if let (None, Some(PdfObject::String(low))) = (code_bytes, operands.first()) {
+                    code_bytes = Some(low.len());
+                }
+            }
+            b"endbfchar" => {
+                for pair in operands.chunks_exact(2) {
+                    if let (PdfObject::String(code), PdfObject::String(text)) = (&pair[0], &pair[1])
+                    {
+                        map.insert(code_value(code), utf16_text(text));
+                    }
+                }
+            }
+            b"endbfrange" => {
+                for triple in operands.chunks_exact(3) {
+                    let (PdfObject::String(low), PdfObject::String(high)) =
+                        (&triple[0], &triple[1])
+                    else {
+                        continue;
+                    };
+                    let (low, high) = (code_value(low), code_value(high));
+                    if high < low || high - low > MAX_CMAP_RANGE {
+                        continue;
+                    }
+                    match &triple[2] {
+                        PdfObject::String(start) => {
+                            for offset in 0..=high - low {
+                                map.insert(low + offset, offset_text(start, offset));
+                            }
+                        }
+                        PdfObject::Array(targets) => {
+                            for (code, target) in (low..=high).zip(targets) {
+                                if let PdfObject::String(text) = target {
+                                    map.insert(code, utf16_text(text));
+                                }
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+            }
+            _ => {}
+        }
+        operands.clear();
+    }
+    (code_bytes, map)
+}
+
+fn glyph_text(name: &str) -> Option<String> {
+    if name.len() == 1
+        && name
+            .chars()
+            .all(|character| character.is_ascii_alphanumeric())
+    {
+        return Some(name.to_string());
+    }
+    let code = name
+        .strip_prefix("uni")
+        .filter(|hex| hex.len() == 4)
+        .or_else(|| {
+            name.strip_prefix('u')
+                .filter(|hex| (4..=6).contains(&hex.len()))
+        });
+    if let Some(character) = code
+        .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+        .and_then(char::from_u32)
+    {
+        return Some(character.to_string());
+    }
+    GLYPH_NAMES
+        .iter()
+        .find(|(glyph, _)| *glyph == name)
+        .map(|(_, text)| text.to_string())
+}
+
+/// WinAnsiEncoding is Windows-1252, with `Differences` laid over it.
+fn simple_encoding(file: &PdfFile, encoding: &PdfObject) -> Vec<String> {
+    let mut table = (0..=255_u8)
+        .map(|byte| {
+            if byte < 0x20 {
+                String::new()
+            } else {
+                cp1252_char(byte).to_string()
+            }
+        })
+        .collect::<Vec<String>>();
+    let differences = encoding
+        .as_dictionary()
+        .and_then(|encoding| file.get(encoding, "Differences").as_array());
+    let mut code = 0_usize;
+    for item in differences.unwrap_or_default() {
+        match file.resolve(item) {
+            PdfObject::Number(value) => code = *value as usize,
+            PdfObject::Name(name) => {
+                if let (Some(slot), Some(text)) = (table.get_mut(code), glyph_text(name)) {
+                    *slot = text;
+                }
+                code += 1;
+            }
+            _ => {}
+        }
+    }
+    table
+}
+
+fn is_bold_font_name(base_font: &str) -> bool {
+    // Subset fonts carry a six-letter tag: ABCDEF+Arial-BoldMT.
+    let name = base_font
+        .split_once('+')
+        .map_or(base_font, |(_, name)| name)
+        .to_ascii_lowercase();
+    FONT_WEIGHT_WORDS.iter().any(|word| name.contains(word))
+}
+
+struct PdfFont {
+    bold: bool,
+    code_bytes: usize,
+    to_unicode: HashMap<u32, String>,
+    simple_encoding: Option<Vec<String>>,
+}
+
+impl PdfFont {
+    fn load(file: &PdfFile, font: &HashMap<String, PdfObject>) -> Self {
+        let composite = file.get(font, "Subtype").as_name() == Some("Type0");
+        let descendant = file
+            .get(font, "DescendantFonts")
+            .as_array()
+            .and_then(|fonts| fonts.first())
+            .and_then(|descendant| file.resolve(descendant).as_dictionary());
+        let descriptor = file.dictionary(font, "FontDescriptor").or_else(|| {
+            descendant.and_then(|descendant| file.dictionary(descendant, "FontDescriptor"))
+        });
+        let bold = is_bold_font_name(file.get(font, "BaseFont").as_name().unwrap_or_default())
+            || descriptor.is_some_and(|descriptor| {
+                file.get(descriptor, "FontWeight")
+                    .as_number()
+                    .is_some_and(|weight| weight >= 600.0)
+                    || file
+                        .get(descriptor, "Flags")
+                        .as_number()
+                        .is_some_and(|flags| (flags as u32) & FORCE_BOLD_FLAG != 0)
+            });
+        let (declared_code_bytes, to_unicode) = file
+            .stream_bytes(file.get(font, "ToUnicode"))
+            .map(|data| parse_to_unicode(&data))
+            .unwrap_or_default();
+        let default_code_bytes = if composite { 2 } else { 1 };
+        Self {
+            bold,
+            code_bytes: declared_code_bytes
+                .filter(|bytes| (1..=4).contains(bytes))
+                .unwrap_or(default_code_bytes),
+            to_unicode,
+            simple_encoding: (!composite)
+                .then(|| simple_encoding(file, file.get(font, "Encoding"))),
+        }
+    }
+
+    fn decode(&self, bytes: &[u8]) -> String {
+        let mut text = String::new();
+        for code in bytes.chunks(self.code_bytes).map(code_value) {
+            if let Some(mapped) = self.to_unicode.get(&code) {
+                text.push_str(mapped);
+            } else if let Some(encoding) = &self.simple_encoding {
+                text.push_str(encoding.get(code as usize).map_or("", String::as_str));
+            }
+        }
+        text
+    }
+}
+
+#[derive(Clone, Copy)]
+struct Matrix([f64; 6]);
+
+const IDENTITY: Matrix = Matrix([1.0, 0.0, 0.0, 1.0, 0.0, 0.0]);
+
+impl Matrix {
+    fn translation(x: f64, y: f64) -> Self {
+        Self([1.0, 0.0, 0.0, 1.0, x, y])
+    }
+
+    fn from_operands(operands: &[PdfObject]) -> Option<Self> {
+        let start = operands.len().checked_sub(6)?;
+        let mut values = [0.0; 6];
+        for (value, operand) in values.iter_mut().zip(&operands[start..]) {
+            *value = operand.as_number()?;
+        }
+        Some(Self(values))
+    }
+
+    /// `self` followed by `other`, in PDF's row-vector order.
+    fn then(self, other: Self) -> Self {
+        let [a, b, c, d, e, f] = self.0;
+        let [a2, b2, c2, d2, e2, f2] = other.0;
+        Self([
+            a * a2 + b * c2,
+            a * b2 + b * d2,
+            c * a2 + d * c2,
+            c * b2 + d * d2,
+            e * a2 + f * c2 + e2,
+            e * b2 + f * d2 + f2,
+        ])
+    }
+}
+
+#[derive(Clone, Copy)]
+struct GraphicsState {
+    ctm: Matrix,
+    font: Option<usize>,
+    font_size: f64,
+    leading: f64,
+}
+
+struct TextItem {
+    page: usize,
+    text: String,
+    size: f64,
+    bold: bool,
+    x: f64,
+    y: f64,
+}
+
+struct ContentReader<'a> {
+    file: &'a PdfFile,
+    fonts: HashMap<usize, PdfFont>,
+    items: Vec<TextItem>,
+    page: usize,
+}
+
+impl<'a> ContentReader<'a> {
+    fn font(
+        &mut self,
+        resources: Option<&'a HashMap<String, PdfObject>>,
+        name: &str,
+    ) -> Option<usize> {
+        let file = self.file;
+        let fonts = file.dictionary(resources?, "Font")?;
+        let font = file.get(fonts, name).as_dictionary()?;
+        let key = std::ptr::from_ref(font) as usize;
+        self.fonts
+            .entry(key)
+            .or_insert_with(|| PdfFont::load(file, font));
+        Some(key)
+    }
+
+    fn shown_text(&self, font: Option<usize>, operand: Option<&PdfObject>) -> String {
+        let Some(font) = font.and_then(|key| self.fonts.get(&key)) else {
+            return String::new();
+        };
+        match operand {
+            Some(PdfObject::String(bytes)) => font.decode(bytes),
+            Some(PdfObject::Array(items)) => {
+                let mut text = String::new();
+                for item in items {
+                    match item {
+                        PdfObject::String(bytes) => text.push_str(&font.decode(bytes)),
+                        PdfObject::Number(adjustment)
+                            if *adjustment < -TJ_SPACE_ADJUSTMENT && !text.ends_with(' ') =>
+                        {
+                            text.push(' ');
+                        }
+                        _ => {}
+                    }
+                }
+                text
+            }
+            _ => String::new(),
+        }
+    }
+
+    fn show(&mut self, state: &GraphicsState, text_matrix: Matrix, text: String) {
+        if text.is_empty() {
+            return;
+        }
+        let placed = text_matrix.then(state.ctm);
+        let bold = state
+            .font
+            .and_then(|key| self.fonts.get(&key))
+            .is_some_and(|font| font.bold);
+        self.items.push(TextItem {
+            page: self.page,
+            text,
+            size: (state.font_size * placed.0[2].hypot(placed.0[3]))
+                .abs()
+                .max(1.0),
+            bold,
+            x: placed.0[4],
+            y: placed.0[5],
+        });
+    }
+
+    fn draw_form(
+        &mut self,
+        resources: Option<&'a HashMap<String, PdfObject>>,
+        name: &str,
+        ctm: Matrix,
+        depth: usize,
+    ) {
+        let file = self.file;
+        if depth >= MAX_XOBJECT_DEPTH {
+            return;
+        }
+        let Some(xobjects) = resources.and_then(|resources| file.dictionary(resources, "XObject"))
+        else {
+            return;
+        };
+        let object = file.get(xobjects, name);
+        let PdfObject::Stream(dictionary, _) = object else {
+            return;
+        };
+        if file.get(dictionary, "Subtype").as_name() != Some("Form") {
+            return;
+        }
+        let Some(content) = file.stream_bytes(object) else {
+            return;
+        };
+        let matrix =
+            Matrix::from_operands(file.get(dictionary, "Matrix").as_array().unwrap_or(&[]))
+                .unwrap_or(IDENTITY);
+        let form_resources = file.dictionary(dictionary, "Resources").or(resources);
+        self.run(&content, form_resources, matrix.then(ctm), depth + 1);
+    }
+
+    fn run(
+        &mut self,
+        content: &[u8],
+        resources: Option<&'a HashMap<String, PdfObject>>,
+        ctm: Matrix,
+        depth: usize,
+    ) {
+        let mut lexer = Lexer::new(content, 0);
+        let mut operands = Vec::new();
+        let mut state = GraphicsState {
+            ctm,
+            font: None,
+            font_size: 0.0,
+            leading: 0.0,
+        };
+        let mut saved_states = Vec::new();
+        let mut text_matrix = IDENTITY;
+        let mut line_matrix = IDENTITY;
+        while let Some(token) = lexer.next_token() {
+            let operator = match token {
+                Token::Operand(operand) => {
+                    operands.push(operand);
+                    continue;
+                }
+                Token::Operator(operator) => operator,
+            };
+            match operator.as_slice() {
+                b"q" => saved_states.push(state),
+                b"Q" => {
+                    if let Some(saved) = saved_states.pop() {
+                        state = saved;
+                    }
+                }
+                b"cm" => {
+                    if let Some(matrix) = Matrix::from_operands(&operands) {
+                        state.ctm = matrix.then(state.ctm);
+                    }
+                }
+                b"BT" => {
+                    text_matrix = IDENTITY;
+                    line_matrix = IDENTITY;
+                }
+                b"Tf" => {
+                    if let [.., PdfObject::Name(name), size] = operands.as_slice() {
+                        state.font = self.font(resources, name);
+                        state.font_size = size.as_number().unwrap_or(0.0);
+                    }
+                }
+                b"TL" => {
+                    state.leading = operands
+                        .last()
+                        .and_then(PdfObject::as_number)
+                        .unwrap_or(0.0);
+                }
+                b"Td" | b"TD" => {
+                    if let [.., x, y] = operands.as_slice() {
+                        let (x, y) = (x.as_number().unwrap_or(0.0), y.as_number().unwrap_or(0.0));
+                        if operator == b"TD" {
+                            state.leading = -y;
+                        }
+                        line_matrix = Matrix::translation(x, y).then(line_matrix);
+                        text_matrix = line_matrix;
+                    }
+                }
+                b"Tm" => {
+                    if let Some(matrix) = Matrix::from_operands(&operands) {
+                        line_matrix = matrix;
+                        text_matrix = matrix;
+                    }
+                }
+                b"T*" | b"'" | b"\"" | b"Tj" | b"TJ" => {
+                    if operator != b"Tj" && operator != b"TJ" {
+                        line_matrix = Matrix::translation(0.0, -state.leading).then(line_matrix);
+                        text_matrix = line_matrix;
+                    }
+                    if operator != b"T*" {
+                        let text = self.shown_text(state.font, operands.last());
+                        self.show(&state, text_matrix, text);
+                    }
+                }
+                b"Do" => {
+                    if let Some(PdfObject::Name(name)) = operands.last() {
+                        self.draw_form(resources, name, state.ctm, depth);
+                    }
+                }
+                b"ID" => lexer.skip_inline_image(),
+                _ => {}
+            }
+            operands.clear();
+        }
+    }
+}
+
+struct TextLine {
+    page: usize,
+    y: f64,
+    size: f64,
+    runs: Vec<(String, bool)>,
+    last_x: f64,
+    last_end: f64,
+}
+
+impl TextLine {
+    fn char_count(&self) -> usize {
+        self.runs
+            .iter()
+            .map(|(text, _)| {
+                text.chars()
+                    .filter(|character| !character.is_whitespace())
+                    .count()
+            })
+            .sum()
+    }
+
+    fn is_bold(&self) -> bool {
+        let mut visible = self
+            .runs
+            .iter()
+            .filter(|(text, _)| !text.trim().is_empty())
+            .peekable();
+        visible.peek().is_some() && visible.all(|(_, bold)| *bold)
+    }
+}
+
+fn estimated_end(item: &TextItem) -> f64 {
+    item.x + item.text.chars().count() as f64 * item.size * AVERAGE_GLYPH_WIDTH
+}
+
+fn assemble_lines(items: Vec<TextItem>) -> Vec<TextLine> {
+    let mut lines: Vec<TextLine> = Vec::new();
+    for item in items {
+        let current = lines.last_mut().filter(|line| {
+            line.page == item.page
+                && (line.y - item.y).abs() <= line.size.max(item.size) * 0.5
+                && item.x + item.size >= line.last_x
+        });
+        match current {
+            Some(line) => {
+                let gap = item.x - line.last_end;
+                let needs_space = gap > item.size * WORD_GAP_RATIO
+                    && !item.text.starts_with(char::is_whitespace)
+                    && !line
+                        .runs
+                        .last()
+                        .is_some_and(|(text, _)| text.ends_with(char::is_whitespace));
+                if needs_space {
+                    if let Some((text, _)) = line.runs.last_mut() {
+                        text.push(' ');
+                    }
+                }
+                line.last_x = item.x;
+                line.last_end = estimated_end(&item).max(line.last_end);
+                if !item.text.trim().is_empty() {
+                    line.size = line.size.max(item.size);
+                }
+                line.runs.push((item.text, item.bold));
+            }
+            None if item.text.trim().is_empty() => {}
+            None => lines.push(TextLine {
+                page: item.page,
+                y: item.y,
+                size: item.size,
+                last_x: item.x,
+                last_end: estimated_end(&item),
+                runs: vec![(item.text, item.bold)],
+            }),
+        }
+    }
+    lines
+}
+
+/// Bold body-size lines rank below every size tier, as tags sit under blocks.
+fn heading_levels(lines: &[TextLine]) -> Vec<Option<i64>> {
+    // Sizes are compared in half points.
+    let size_key = |size: f64| (size * 2.0).round() as i64;
+    let mut chars_by_size = HashMap::<i64, usize>::new();
+    for line in lines {
+        *chars_by_size.entry(size_key(line.size)).or_default() += line.char_count();
+    }
+    let Some(body_key) = chars_by_size
+        .iter()
+        .max_by_key(|(key, count)| (**count, -**key))
+        .map(|(key, _)| *key)
+    else {
+        return Vec::new();
+    };
+    let is_heading_size = |key: i64| key as f64 >= body_key as f64 * HEADING_SIZE_RATIO;
+    let mut tiers = chars_by_size
+        .keys()
+        .copied()
+        .filter(|key| is_heading_size(*key))
+        .collect::<Vec<i64>>();
+    tiers.sort_unstable_by(|left, right| right.cmp(left));
+    let bold_level = tiers.len().min(MAX_SIZE_TIERS) + 1;
+
+    lines
+        .iter()
+        .map(|line| {
+            let key = size_key(line.size);
+            let level = if is_heading_size(key) {
+                tiers
+                    .iter()
+                    .position(|tier| *tier == key)?
+                    .min(MAX_SIZE_TIERS - 1)
+                    + 1
+            } else if line.is_bold() && line.char_count() <= MAX_BOLD_HEADING_CHARS {
+                bold_level
+            } else {
+                return None;
+            };
+            Some(level as i64)
+        })
+        .collect()
+}
+
+fn paragraphs_from_lines(lines: &[TextLine]) -> Vec<GeneratedParagraph> {
+    let levels = heading_levels(lines);
+    let mut builder = ParagraphBuilder::default();
+    let mut previous: Option<(&TextLine, Option<i64>)> = None;
+    for (line, level) in lines.iter().zip(levels) {
+        let continues = previous.is_some_and(|(above, above_level)| {
+            above_level == level
+                && above.page == line.page
+                && above.y > line.y
+                && above.y - line.y <= above.size.max(line.size) * PARAGRAPH_GAP_RATIO
+        });
+        if !continues {
+            builder.flush();
+            builder.set_heading_level(level);
+        } else if !builder.at_word_boundary() {
+            builder.push_run(GeneratedRun {
+                text: " ".to_string(),
+                bold: line.runs.first().is_some_and(|(_, bold)| *bold),
+                ..GeneratedRun::default()
+            });
+        }
+        for (text, bold) in &line.runs {
+            builder.push_run(GeneratedRun {
+                text: text.clone(),
+                bold: *bold,
+                ..GeneratedRun::default()
+            });
+        }
+        previous = Some((line, level));
+    }
+    builder.finish()
+}
+
+/// Paragraphs of a PDF's text, with headings guessed from font size and
+/// weight since PDFs rarely carry structure. Only what is drawn as text is
+/// read: a scan without an OCR layer has none, and an encrypted file is
+/// refused.
+pub(crate) fn parse_pdf_paragraphs(file_path: &Path) -> CommandResult<Vec<GeneratedParagraph>> {
+    let bytes = fs::read(file_path).map_err(|error| {
+        CommandError::io(format!(
+            "Could not read '{}': {error}",
+            path_display(file_path)
+        ))
+    })?;
+    if find(&bytes[..bytes.len().min(1024)], b"%PDF-", 0).is_none() {
+        return Err(CommandError::parse(format!(
+            "'{}' is not a PDF document.",
+            path_display(file_path)
+        )));
+    }
+    let mut file = PdfFile {
+        objects: read_indirect_objects(&bytes),
+    };
+    file.expand_object_streams();
+    if file.is_encrypted(&bytes) {
+        return Err(CommandError::parse(format!(
+            "'{}' is encrypted; only unencrypted PDFs can be read.",
+            path_display(file_path)
+        )));
+    }
+
+    let mut reader = ContentReader {
+        file: &file,
+        fonts: HashMap::new(),
+        items: Vec::new(),
+        page: 0,
+    };
+    for (index, page) in file.pages().into_iter().enumerate() {
+        reader.page = index;
+        if let Some(content) = file.page_content(page.dictionary) {
+            reader.run(&content, page.resources, IDENTITY, 0);
+        }
+    }
+    Ok(paragraphs_from_lines(&assemble_lines(reader.items)))
+}
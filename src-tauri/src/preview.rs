@@ -1,14 +1,20 @@
-use std::fs::File;
+use std::collections::HashMap;
+use std::ops::RangeInclusive;
 use std::path::Path;
 
+use rayon::prelude::*;
 use roxmltree::{Document, Node};
-use zip::ZipArchive;
 
+use crate::docx_cache::cached_parsed_docx;
 use crate::docx_parse::{
-    build_heading_ranges, has_tag, html_escape, parse_docx_paragraphs, read_zip_file,
-    run_has_active_underline, run_has_property, run_highlight_class,
+    attribute_value, build_heading_ranges, has_tag, html_escape, paragraph_byte_ranges,
+    paragraph_shading_fill, read_parsed_docx, root_namespace_declarations,
+    run_has_active_underline, run_has_property, run_highlight_class, run_shading_fill,
+    run_text_color, table_byte_ranges, AltChunk,
 };
-use crate::types::{FileHeading, TaggedBlock};
+use crate::errors::CommandError;
+use crate::file_stats::heading_word_counts;
+use crate::types::{FileHeading, ParsedParagraph, TaggedBlock};
 use crate::util::{is_probable_author_line, path_display};
 use crate::CommandResult;
 
@@ -57,7 +63,33 @@ pub(crate) fn render_preview_run(run: Node<'_, '_>) -> String {
         classes.push(format!("bf-hl-{highlight_class}"));
     }
 
-    format!("<span class=\"{}\">{body}</span>", classes.join(" "))
+    // Shading and text colours are passed through as CSS variables rather
+    // than fixed colours, so the stylesheet can adapt them to a dark theme.
+    let mut style = Vec::new();
+    if let Some(fill) = run_shading_fill(run) {
+        classes.push("bf-run-shaded".to_string());
+        style.push(format!("--bf-shade: {fill}"));
+    }
+    if let Some(color) = run_text_color(run) {
+        classes.push("bf-run-colored".to_string());
+        style.push(format!("--bf-color: {color}"));
+    }
+
+    format!(
+        "<span class=\"{}\"{}>{body}</span>",
+        classes.join(" "),
+        style_attribute(&style)
+    )
+}
+
+/// A ` style="..."` attribute for CSS declarations built from validated
+/// colours, or nothing when there are none.
+fn style_attribute(declarations: &[String]) -> String {
+    if declarations.is_empty() {
+        String::new()
+    } else {
+        format!(" style=\"{}\"", declarations.join("; "))
+    }
 }
 
 pub(crate) fn render_preview_inline_nodes(node: Node<'_, '_>, output: &mut String) {
@@ -132,18 +164,120 @@ pub(crate) fn render_preview_paragraph(
         body.push_str("&nbsp;");
     }
 
-    format!(
-        "<p class=\"{}\">{body}</p>",
-        preview_paragraph_class(heading_level)
-    )
+    match paragraph_shading_fill(paragraph_node) {
+        Some(fill) => format!(
+            "<p class=\"{} bf-preview-shaded\"{}>{body}</p>",
+            preview_paragraph_class(heading_level),
+            style_attribute(&[format!("--bf-shade: {fill}")])
+        ),
+        None => format!(
+            "<p class=\"{}\">{body}</p>",
+            preview_paragraph_class(heading_level)
+        ),
+    }
+}
+
+/// Renders a `w:tbl` as a `bf-preview-table`, each cell's paragraphs
+/// rendered as elsewhere in the preview. `paragraphs` starts at the table's
+/// first paragraph and `next` counts through it, so nested tables share the
+/// count. Horizontal merges become `colspan`; vertically merged cells after
+/// the first are left empty.
+fn render_preview_table(
+    table_node: Node<'_, '_>,
+    paragraphs: &[ParsedParagraph],
+    next: &mut usize,
+) -> String {
+    let mut html = String::from("<table class=\"bf-preview-table\"><tbody>");
+    for row in table_node.children().filter(|node| has_tag(*node, "tr")) {
+        html.push_str("<tr>");
+        for cell in row.children().filter(|node| has_tag(*node, "tc")) {
+            let span = cell
+                .children()
+                .find(|node| has_tag(*node, "tcPr"))
+                .and_then(|properties| {
+                    properties
+                        .children()
+                        .find(|node| has_tag(*node, "gridSpan"))
+                })
+                .and_then(|grid_span| attribute_value(grid_span, "val")?.parse::<u32>().ok())
+                .filter(|span| *span > 1);
+            match span {
+                Some(span) => html.push_str(&format!("<td colspan=\"{span}\">")),
+                None => html.push_str("<td>"),
+            }
+            for child in cell.children() {
+                if has_tag(child, "p") {
+                    let meta = paragraphs.get(*next);
+                    *next += 1;
+                    html.push_str(&render_preview_paragraph(
+                        child,
+                        meta.and_then(|meta| meta.heading_level),
+                        meta.map(|meta| meta.text.as_str()).unwrap_or_default(),
+                    ));
+                } else if has_tag(child, "tbl") {
+                    html.push_str(&render_preview_table(child, paragraphs, next));
+                } else {
+                    // Content controls and the like still hold paragraphs
+                    // that count towards the index.
+                    *next += child
+                        .descendants()
+                        .filter(|node| has_tag(*node, "p"))
+                        .count();
+                }
+            }
+            html.push_str("</td>");
+        }
+        html.push_str("</tr>");
+    }
+    html.push_str("</tbody></table>");
+    html
+}
+
+/// Renders the paragraphs of an embedded altChunk document, as body text
+/// the way they are indexed.
+fn render_alt_chunk(chunk: &AltChunk) -> String {
+    let Ok(document) = Document::parse(&chunk.document_xml) else {
+        return String::new();
+    };
+    let mut html = String::from("<div class=\"bf-preview-embedded\">");
+    for paragraph_node in document.descendants().filter(|node| has_tag(*node, "p")) {
+        html.push_str(&render_preview_paragraph(paragraph_node, None, ""));
+    }
+    html.push_str("</div>");
+    html
+}
+
+/// Renders the altChunks that follow one of the host paragraphs counted by
+/// `after_paragraphs`.
+fn push_alt_chunks(
+    html: &mut String,
+    chunks: &[AltChunk],
+    after_paragraphs: RangeInclusive<usize>,
+) {
+    for chunk in chunks
+        .iter()
+        .filter(|chunk| after_paragraphs.contains(&chunk.after_paragraphs))
+    {
+        html.push_str(&render_alt_chunk(chunk));
+    }
+}
+
+fn parse_preview_fragment<'a>(fragment: &'a str, file_path: &Path) -> CommandResult<Document<'a>> {
+    Document::parse(fragment).map_err(|error| {
+        CommandError::parse(format!(
+            "Could not parse preview XML '{}': {error}",
+            path_display(file_path)
+        ))
+    })
 }
 
 pub(crate) fn extract_heading_preview_html(
     file_path: &Path,
     heading_order: i64,
 ) -> CommandResult<String> {
-    let paragraphs = parse_docx_paragraphs(file_path)?;
-    let heading_ranges = build_heading_ranges(&paragraphs);
+    let parsed = cached_parsed_docx(file_path)?;
+    let paragraphs = &parsed.paragraphs;
+    let heading_ranges = build_heading_ranges(paragraphs);
     let Some(target_range) = heading_ranges
         .iter()
         .find(|range| range.order == heading_order)
@@ -151,131 +285,232 @@ pub(crate) fn extract_heading_preview_html(
         return Ok(String::new());
     };
 
-    let file = File::open(file_path)
-        .map_err(|error| format!("Could not open '{}': {error}", path_display(file_path)))?;
-    let mut archive = ZipArchive::new(file)
-        .map_err(|error| format!("Could not read '{}': {error}", path_display(file_path)))?;
-    let document_xml = read_zip_file(&mut archive, "word/document.xml").ok_or_else(|| {
-        format!(
-            "Missing word/document.xml in '{}'. Is this a valid docx file?",
-            path_display(file_path)
-        )
-    })?;
-    let document = Document::parse(&document_xml).map_err(|error| {
-        format!(
-            "Could not parse preview XML '{}': {error}",
-            path_display(file_path)
-        )
-    })?;
-
-    let paragraph_nodes = document
-        .descendants()
-        .filter(|node| has_tag(*node, "p"))
-        .collect::<Vec<Node<'_, '_>>>();
-
     let start = target_range.start_index;
-    let end = target_range
-        .end_index
-        .min(paragraph_nodes.len())
-        .min(paragraphs.len());
+    let end = target_range.end_index.min(paragraphs.len());
     if start >= end {
         return Ok(String::new());
     }
 
+    // Each paragraph of the section is sliced out and parsed on its own, so a
+    // huge document never gets a full DOM just to render one card. Tables are
+    // sliced out whole, once, where their first paragraph falls.
+    let document_xml = parsed.parts.document_xml.as_str();
+    let namespaces = root_namespace_declarations(document_xml);
+    let tables = table_byte_ranges(document_xml, start..end);
+    let fragment_xml = |range: std::ops::Range<usize>| {
+        format!("<fragment {namespaces}>{}</fragment>", &document_xml[range])
+    };
     let mut html = String::new();
-    for index in start..end {
-        let paragraph_node = paragraph_nodes[index];
+    let mut rendered_through = 0_usize;
+    for (index, range) in paragraph_byte_ranges(document_xml, start..end) {
+        if index < rendered_through {
+            continue;
+        }
+        if let Some((paragraph_indices, table_range)) = tables
+            .iter()
+            .find(|(paragraph_indices, _)| paragraph_indices.contains(&index))
+        {
+            let fragment = fragment_xml(table_range.clone());
+            let document = parse_preview_fragment(&fragment, file_path)?;
+            if let Some(table_node) = document.descendants().find(|node| has_tag(*node, "tbl")) {
+                html.push_str(&render_preview_table(
+                    table_node,
+                    &paragraphs[paragraph_indices.start..],
+                    &mut 0,
+                ));
+            }
+            rendered_through = paragraph_indices.end;
+            push_alt_chunks(&mut html, &parsed.alt_chunks, index + 1..=rendered_through);
+            continue;
+        }
+
+        let fragment = fragment_xml(range);
+        let document = parse_preview_fragment(&fragment, file_path)?;
+        let Some(paragraph_node) = document.descendants().find(|node| has_tag(*node, "p")) else {
+            continue;
+        };
         let paragraph_meta = &paragraphs[index];
         html.push_str(&render_preview_paragraph(
             paragraph_node,
             paragraph_meta.heading_level,
             &paragraph_meta.text,
         ));
+        push_alt_chunks(&mut html, &parsed.alt_chunks, index + 1..=index + 1);
     }
 
     Ok(html)
 }
 
-pub(crate) fn extract_preview_content(
-    file_path: &Path,
-) -> CommandResult<(Vec<FileHeading>, Vec<TaggedBlock>)> {
-    let paragraphs = parse_docx_paragraphs(file_path)?;
+/// `(order, level, text)` of a heading rendered by `extract_file_preview_html`.
+pub(crate) type PreviewHeadingAnchor = (i64, i64, String);
 
-    let mut heading_indices = Vec::new();
-    for (index, paragraph) in paragraphs.iter().enumerate() {
-        if paragraph.heading_level.is_some() {
-            heading_indices.push(index);
-        }
-    }
+/// Renders a whole file as preview HTML. Heading paragraphs are preceded by
+/// an `h-<order>` anchor so pages can link into them.
+pub(crate) fn extract_file_preview_html(
+    file_path: &Path,
+) -> CommandResult<(String, Vec<PreviewHeadingAnchor>)> {
+    let parsed = read_parsed_docx(file_path)?;
+    let paragraphs = &parsed.paragraphs;
+    let document = Document::parse(&parsed.parts.document_xml).map_err(|error| {
+        CommandError::parse(format!(
+            "Could not parse preview XML '{}': {error}",
+            path_display(file_path)
+        ))
+    })?;
 
+    let mut html = String::new();
     let mut headings = Vec::new();
-    for (heading_position, start_index) in heading_indices.iter().enumerate() {
-        let paragraph = &paragraphs[*start_index];
-        let Some(level) = paragraph.heading_level else {
+    let mut rendered_through = 0_usize;
+    push_alt_chunks(&mut html, &parsed.alt_chunks, 0..=0);
+    for (index, paragraph_node) in document
+        .descendants()
+        .filter(|node| has_tag(*node, "p"))
+        .enumerate()
+        .take(paragraphs.len())
+    {
+        if index < rendered_through {
             continue;
+        }
+        // A paragraph inside a table brings the whole outermost table, with
+        // the anchors of any headings in it placed just before.
+        let (span_end, table_node) = match paragraph_node
+            .ancestors()
+            .filter(|node| has_tag(*node, "tbl"))
+            .last()
+        {
+            Some(table_node) => (
+                index
+                    + table_node
+                        .descendants()
+                        .filter(|node| has_tag(*node, "p"))
+                        .count(),
+                Some(table_node),
+            ),
+            None => (index + 1, None),
         };
-
-        let mut end_index = paragraphs.len();
-        for candidate_index in heading_indices.iter().skip(heading_position + 1) {
-            if let Some(candidate_level) = paragraphs[*candidate_index].heading_level {
-                if is_probable_author_line(&paragraphs[*candidate_index].text) {
-                    continue;
-                }
-                if candidate_level <= level {
-                    end_index = *candidate_index;
-                    break;
-                }
+        for paragraph_meta in &paragraphs[index..span_end.min(paragraphs.len())] {
+            if let Some(level) = paragraph_meta.heading_level {
+                html.push_str(&format!("<a id=\"h-{}\"></a>", paragraph_meta.order));
+                headings.push((paragraph_meta.order, level, paragraph_meta.text.clone()));
             }
         }
-
-        let section_lines = paragraphs[*start_index..end_index]
-            .iter()
-            .map(|entry| entry.text.as_str())
-            .collect::<Vec<&str>>();
-        let copy_text = section_lines.join("\n");
-
-        headings.push(FileHeading {
-            id: paragraph.order,
-            order: paragraph.order,
-            level,
-            text: paragraph.text.clone(),
-            copy_text,
-        });
+        match table_node {
+            Some(table_node) => {
+                html.push_str(&render_preview_table(
+                    table_node,
+                    &paragraphs[index..],
+                    &mut 0,
+                ));
+            }
+            None => {
+                let paragraph_meta = &paragraphs[index];
+                html.push_str(&render_preview_paragraph(
+                    paragraph_node,
+                    paragraph_meta.heading_level,
+                    &paragraph_meta.text,
+                ));
+            }
+        }
+        rendered_through = span_end;
+        push_alt_chunks(&mut html, &parsed.alt_chunks, index + 1..=span_end);
     }
 
-    let mut f8_cites = Vec::new();
-    let mut cursor = 0_usize;
-    while cursor < paragraphs.len() {
-        let paragraph = &paragraphs[cursor];
-        if !paragraph.is_f8_cite {
-            cursor += 1;
-            continue;
-        }
+    Ok((html, headings))
+}
 
-        let start_order = paragraph.order;
-        let style_label = paragraph
-            .style_label
-            .clone()
-            .unwrap_or_else(|| "F8 Cite".to_string());
-        let mut lines = vec![paragraph.text.clone()];
-
-        cursor += 1;
-        while cursor < paragraphs.len() && paragraphs[cursor].is_f8_cite {
-            lines.push(paragraphs[cursor].text.clone());
-            cursor += 1;
-        }
+/// The jump list of an already parsed paragraph list.
+pub(crate) fn preview_headings(paragraphs: &[ParsedParagraph]) -> Vec<FileHeading> {
+    let heading_indices = paragraphs
+        .iter()
+        .enumerate()
+        .filter(|(_, paragraph)| paragraph.heading_level.is_some())
+        .map(|(index, _)| index)
+        .collect::<Vec<usize>>();
+    let word_counts = heading_word_counts(paragraphs)
+        .into_iter()
+        .map(|count| (count.heading_order, count))
+        .collect::<HashMap<i64, _>>();
+
+    heading_indices
+        .par_iter()
+        .enumerate()
+        .filter_map(|(heading_position, start_index)| {
+            let paragraph = &paragraphs[*start_index];
+            let level = paragraph.heading_level?;
+
+            let mut end_index = paragraphs.len();
+            for candidate_index in heading_indices.iter().skip(heading_position + 1) {
+                if let Some(candidate_level) = paragraphs[*candidate_index].heading_level {
+                    if is_probable_author_line(&paragraphs[*candidate_index].text) {
+                        continue;
+                    }
+                    if candidate_level <= level {
+                        end_index = *candidate_index;
+                        break;
+                    }
+                }
+            }
 
-        let text = lines.join("\n");
-        if text.trim().is_empty() {
-            continue;
-        }
+            let copy_text = paragraphs[*start_index..end_index]
+                .iter()
+                .map(|entry| entry.text.as_str())
+                .collect::<Vec<&str>>()
+                .join("\n");
+            let word_count = word_counts.get(&paragraph.order);
+
+            Some(FileHeading {
+                id: paragraph.order,
+                order: paragraph.order,
+                level,
+                text: paragraph.text.clone(),
+                copy_text,
+                word_count: word_count.map_or(0, |count| count.word_count),
+                underlined_word_count: word_count.map_or(0, |count| count.underlined_word_count),
+            })
+        })
+        .collect::<Vec<FileHeading>>()
+}
 
-        f8_cites.push(TaggedBlock {
-            order: start_order,
-            style_label,
-            text,
-        });
-    }
+/// Builds the jump list and F8 cite blocks for a file. Both are assembled in
+/// parallel: each heading's section text, and each run of cite paragraphs,
+/// depends only on the shared paragraph list.
+pub(crate) fn extract_preview_content(
+    file_path: &Path,
+) -> CommandResult<(Vec<FileHeading>, Vec<TaggedBlock>)> {
+    let parsed = cached_parsed_docx(file_path)?;
+    let paragraphs = &parsed.paragraphs;
+    let headings = preview_headings(paragraphs);
+
+    let cite_run_starts = (0..paragraphs.len())
+        .filter(|index| {
+            paragraphs[*index].is_f8_cite && (*index == 0 || !paragraphs[index - 1].is_f8_cite)
+        })
+        .collect::<Vec<usize>>();
+
+    let f8_cites = cite_run_starts
+        .par_iter()
+        .filter_map(|start| {
+            let paragraph = &paragraphs[*start];
+            let lines = paragraphs[*start..]
+                .iter()
+                .take_while(|entry| entry.is_f8_cite)
+                .map(|entry| entry.text.as_str())
+                .collect::<Vec<&str>>();
+            let text = lines.join("\n");
+            if text.trim().is_empty() {
+                return None;
+            }
+
+            Some(TaggedBlock {
+                order: paragraph.order,
+                style_label: paragraph
+                    .style_label
+                    .clone()
+                    .unwrap_or_else(|| "F8 Cite".to_string()),
+                text,
+            })
+        })
+        .collect::<Vec<TaggedBlock>>();
 
     Ok((headings, f8_cites))
 }
@@ -0,0 +1,220 @@
+use std::fs;
+use std::path::PathBuf;
+use std::sync::{Mutex, OnceLock};
+
+use serde::{Deserialize, Serialize};
+use tauri::AppHandle;
+
+use crate::db::app_data_dir;
+use crate::errors::CommandError;
+use crate::types::ProfileInfo;
+use crate::util::{now_ms, path_display};
+use crate::CommandResult;
+
+/// The profile every install starts with. Its data stays directly in the app
+/// data dir, where it lived before profiles existed.
+pub(crate) const DEFAULT_PROFILE_ID: &str = "default";
+const DEFAULT_PROFILE_NAME: &str = "Default";
+const PROFILES_FILE_NAME: &str = "profiles.json";
+const PROFILES_DIR_NAME: &str = "profiles";
+
+#[derive(Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ProfileRegistry {
+    #[serde(default)]
+    active: Option<String>,
+    #[serde(default)]
+    profiles: Vec<StoredProfile>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct StoredProfile {
+    id: String,
+    name: String,
+    created_at_ms: i64,
+}
+
+/// The active profile id, read from the registry on first use. Every
+/// `open_database` resolves paths through it.
+static ACTIVE_PROFILE: OnceLock<Mutex<Option<String>>> = OnceLock::new();
+
+fn active_profile_cache() -> &'static Mutex<Option<String>> {
+    ACTIVE_PROFILE.get_or_init(|| Mutex::new(None))
+}
+
+fn registry_path(app: &AppHandle) -> CommandResult<PathBuf> {
+    Ok(app_data_dir(app)?.join(PROFILES_FILE_NAME))
+}
+
+fn load_registry(app: &AppHandle) -> CommandResult<ProfileRegistry> {
+    let path = registry_path(app)?;
+    let raw = match fs::read_to_string(&path) {
+        Ok(raw) => raw,
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => {
+            return Ok(ProfileRegistry::default())
+        }
+        Err(error) => {
+            return Err(CommandError::io(format!(
+                "Could not read profiles '{}': {error}",
+                path_display(&path)
+            )))
+        }
+    };
+    serde_json::from_str(&raw).map_err(|error| {
+        CommandError::parse(format!(
+            "Could not parse profiles '{}': {error}",
+            path_display(&path)
+        ))
+    })
+}
+
+fn store_registry(app: &AppHandle, registry: &ProfileRegistry) -> CommandResult<()> {
+    let path = registry_path(app)?;
+    let raw = serde_json::to_string_pretty(registry)
+        .map_err(|error| CommandError::parse(format!("Could not serialize profiles: {error}")))?;
+    fs::write(&path, raw).map_err(|error| {
+        CommandError::io(format!(
+            "Could not write profiles '{}': {error}",
+            path_display(&path)
+        ))
+    })
+}
+
+/// Every profile, the default one first.
+fn all_profiles(registry: &ProfileRegistry) -> Vec<StoredProfile> {
+    let mut profiles = vec![StoredProfile {
+        id: DEFAULT_PROFILE_ID.to_string(),
+        name: DEFAULT_PROFILE_NAME.to_string(),
+        created_at_ms: 0,
+    }];
+    profiles.extend(registry.profiles.iter().cloned());
+    profiles
+}
+
+fn profile_info(profile: StoredProfile, active_id: &str) -> ProfileInfo {
+    ProfileInfo {
+        active: profile.id == active_id,
+        id: profile.id,
+        name: profile.name,
+        created_at_ms: profile.created_at_ms,
+    }
+}
+
+/// The active profile id. A registry naming a profile that no longer exists
+/// falls back to the default.
+pub(crate) fn active_profile_id(app: &AppHandle) -> CommandResult<String> {
+    let mut cache = active_profile_cache()
+        .lock()
+        .map_err(|_| CommandError::internal("Could not lock active profile"))?;
+    if let Some(active) = cache.as_ref() {
+        return Ok(active.clone());
+    }
+    let registry = load_registry(app)?;
+    let profiles = all_profiles(&registry);
+    let active = registry
+        .active
+        .filter(|active| profiles.iter().any(|profile| &profile.id == active))
+        .unwrap_or_else(|| DEFAULT_PROFILE_ID.to_string());
+    *cache = Some(active.clone());
+    Ok(active)
+}
+
+/// The folder holding the active profile's index layout.
+pub(crate) fn profile_data_dir(app: &AppHandle) -> CommandResult<PathBuf> {
+    let app_data = app_data_dir(app)?;
+    let active = active_profile_id(app)?;
+    if active == DEFAULT_PROFILE_ID {
+        return Ok(app_data);
+    }
+    let dir = app_data.join(PROFILES_DIR_NAME).join(&active);
+    fs::create_dir_all(&dir).map_err(|error| {
+        CommandError::io(format!(
+            "Could not create profile dir '{}': {error}",
+            path_display(&dir)
+        ))
+    })?;
+    Ok(dir)
+}
+
+pub(crate) fn list_profiles(app: &AppHandle) -> CommandResult<Vec<ProfileInfo>> {
+    let active = active_profile_id(app)?;
+    let registry = load_registry(app)?;
+    Ok(all_profiles(&registry)
+        .into_iter()
+        .map(|profile| profile_info(profile, &active))
+        .collect())
+}
+
+/// A folder-safe id for `name`: lowercase letters and digits joined by `-`.
+fn profile_slug(name: &str) -> String {
+    let mut slug = String::new();
+    for character in name.chars().flat_map(char::to_lowercase) {
+        if character.is_ascii_alphanumeric() {
+            slug.push(character);
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+    slug.trim_end_matches('-').to_string()
+}
+
+pub(crate) fn create_profile(app: &AppHandle, name: &str) -> CommandResult<ProfileInfo> {
+    let name = name.trim();
+    let base_id = profile_slug(name);
+    if base_id.is_empty() {
+        return Err(CommandError::validation_field(
+            "name",
+            "Give the profile a name with at least one letter or digit.",
+        ));
+    }
+
+    let mut registry = load_registry(app)?;
+    let existing = all_profiles(&registry);
+    if existing
+        .iter()
+        .any(|profile| profile.name.eq_ignore_ascii_case(name))
+    {
+        return Err(CommandError::validation_field(
+            "name",
+            format!("A profile named '{name}' already exists."),
+        ));
+    }
+    let mut id = base_id.clone();
+    let mut suffix = 2;
+    while existing.iter().any(|profile| profile.id == id) {
+        id = format!("{base_id}-{suffix}");
+        suffix += 1;
+    }
+
+    let profile = StoredProfile {
+        id,
+        name: name.to_string(),
+        created_at_ms: now_ms(),
+    };
+    registry.profiles.push(profile.clone());
+    store_registry(app, &registry)?;
+    Ok(profile_info(profile, &active_profile_id(app)?))
+}
+
+/// Makes `profile_id` the active profile. Callers reset whatever caches
+/// depend on the index location.
+pub(crate) fn set_active_profile(app: &AppHandle, profile_id: &str) -> CommandResult<ProfileInfo> {
+    let mut registry = load_registry(app)?;
+    let profile = all_profiles(&registry)
+        .into_iter()
+        .find(|profile| profile.id == profile_id)
+        .ok_or_else(|| {
+            CommandError::validation_field(
+                "profileId",
+                format!("There is no profile '{profile_id}'."),
+            )
+        })?;
+    registry.active = Some(profile.id.clone());
+    store_registry(app, &registry)?;
+    let mut cache = active_profile_cache()
+        .lock()
+        .map_err(|_| CommandError::internal("Could not lock active profile"))?;
+    *cache = Some(profile.id.clone());
+    Ok(profile_info(profile, profile_id))
+}
@@ -0,0 +1,84 @@
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, OnceLock};
+use std::time::Duration;
+
+use crate::types::{IndexPhaseTimings, PerformanceReport, SearchStageTimings};
+
+const MAX_INDEX_RUNS: usize = 20;
+const MAX_SEARCHES: usize = 200;
+
+static PROFILING_ENABLED: AtomicBool = AtomicBool::new(false);
+
+#[derive(Default)]
+struct PerformanceLog {
+    index_runs: VecDeque<IndexPhaseTimings>,
+    searches: VecDeque<SearchStageTimings>,
+}
+
+static PERFORMANCE_LOG: OnceLock<Mutex<PerformanceLog>> = OnceLock::new();
+
+fn performance_log() -> &'static Mutex<PerformanceLog> {
+    PERFORMANCE_LOG.get_or_init(|| Mutex::new(PerformanceLog::default()))
+}
+
+pub(crate) fn duration_ms(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+pub(crate) fn profiling_enabled() -> bool {
+    PROFILING_ENABLED.load(Ordering::Relaxed)
+}
+
+/// Turns timing capture on or off. Switching it on starts a fresh log so a
+/// report only covers the runs the user asked to diagnose.
+pub(crate) fn set_profiling_enabled(enabled: bool) {
+    let was_enabled = PROFILING_ENABLED.swap(enabled, Ordering::Relaxed);
+    if enabled && !was_enabled {
+        if let Ok(mut log) = performance_log().lock() {
+            log.index_runs.clear();
+            log.searches.clear();
+        }
+    }
+}
+
+pub(crate) fn record_index_run(timings: IndexPhaseTimings) {
+    if !profiling_enabled() {
+        return;
+    }
+    if let Ok(mut log) = performance_log().lock() {
+        log.index_runs.push_back(timings);
+        while log.index_runs.len() > MAX_INDEX_RUNS {
+            log.index_runs.pop_front();
+        }
+    }
+}
+
+pub(crate) fn record_search(timings: SearchStageTimings) {
+    if !profiling_enabled() {
+        return;
+    }
+    if let Ok(mut log) = performance_log().lock() {
+        log.searches.push_back(timings);
+        while log.searches.len() > MAX_SEARCHES {
+            log.searches.pop_front();
+        }
+    }
+}
+
+pub(crate) fn performance_report() -> PerformanceReport {
+    let (index_runs, searches) = performance_log()
+        .lock()
+        .map(|log| {
+            (
+                log.index_runs.iter().cloned().collect(),
+                log.searches.iter().cloned().collect(),
+            )
+        })
+        .unwrap_or_default();
+    PerformanceReport {
+        enabled: profiling_enabled(),
+        index_runs,
+        searches,
+    }
+}
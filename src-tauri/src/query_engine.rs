@@ -4,10 +4,20 @@ use std::time::{Duration, Instant};
 use futures::future;
 use tauri::AppHandle;
 
+use crate::body_index;
+use crate::cite_index;
 use crate::db::{open_database, root_id};
+use crate::errors::CommandError;
+use crate::file_stats::attach_heading_word_counts;
 use crate::lexical;
-use crate::search::{normalize_for_search, MAX_QUERY_CHARS};
-use crate::types::SearchHit;
+use crate::profiling::{duration_ms, record_search};
+use crate::query_syntax::parse_query;
+use crate::search::{
+    ensure_current_search, normalize_for_search, SearchFilters, SearchGeneration, MAX_QUERY_CHARS,
+};
+use crate::search_settings::load_search_settings;
+use crate::tags::{load_tag_filter, tagged_hits};
+use crate::types::{SearchHit, SearchPage, SearchStageTimings};
 use crate::util::{canonicalize_folder, now_ms, path_display};
 use crate::vector::{self, VECTOR_MIN_QUERY_CHARS};
 use crate::CommandResult;
@@ -23,6 +33,10 @@ const RRF_K: f64 = 55.0;
 const RRF_LEXICAL_WEIGHT: f64 = 1.25;
 const RRF_SEMANTIC_WEIGHT: f64 = 1.0;
 const RRF_BOTH_MODALITIES_BONUS: f64 = 0.08;
+/// Hits gathered at a time when paging; see `search_lexical_page`.
+const PAGE_WINDOW: usize = 400;
+/// Hits fetched per requested hit when `tag:` filters will drop some.
+const TAG_FILTER_FETCH_MULTIPLIER: usize = 4;
 
 #[derive(Clone)]
 struct CacheEntry {
@@ -173,12 +187,14 @@ fn resolve_requested_root_id(
 }
 
 fn cache_key(mode: &str, query: &str, root_id: Option<i64>, limit: usize) -> String {
-    format!(
-        "{mode}|{}|{}|{}",
-        normalize_for_search(query),
-        root_id.unwrap_or(0),
-        limit
-    )
+    // Normalizing would drop quotes, `OR` and `-`, so queries using them key
+    // on their exact text.
+    let query_key = if parse_query(query).has_syntax {
+        query.trim().to_string()
+    } else {
+        normalize_for_search(query)
+    };
+    format!("{mode}|{}|{}|{}", query_key, root_id.unwrap_or(0), limit)
 }
 
 fn dedupe_key(hit: &SearchHit) -> String {
@@ -192,18 +208,113 @@ fn dedupe_key(hit: &SearchHit) -> String {
     )
 }
 
+/// Tantivy hits merged with paragraph matches from `body_fts` and cite
+/// matches from `cites_fts`, ordered by score, with heading hits carrying
+/// their section preview as a snippet. File-name-only searches skip both.
+/// With `tag:` filters, more hits are fetched and the untagged ones dropped.
+/// Ranking follows the stored search settings.
+fn lexical_with_body_hits(
+    app: &AppHandle,
+    query: &str,
+    requested_root_id: Option<i64>,
+    limit: usize,
+    file_name_only: bool,
+    filters: &SearchFilters,
+    generation: Option<SearchGeneration>,
+) -> CommandResult<Vec<SearchHit>> {
+    let fetch_limit = if filters.tags.is_empty() {
+        limit
+    } else {
+        limit.saturating_mul(TAG_FILTER_FETCH_MULTIPLIER)
+    };
+    let connection = open_database(app)?;
+    let settings = load_search_settings(&connection)?;
+    let mut hits = lexical::search(
+        app,
+        query,
+        requested_root_id,
+        fetch_limit,
+        file_name_only,
+        &lexical::LexicalSearchOptions {
+            filters,
+            settings: &settings,
+            generation,
+        },
+    )?;
+    ensure_current_search(generation)?;
+    if file_name_only {
+        return Ok(hits);
+    }
+
+    hits.extend(body_index::search(
+        &connection,
+        query,
+        requested_root_id,
+        fetch_limit,
+        filters,
+        &settings,
+    )?);
+    hits.extend(cite_index::search(
+        &connection,
+        query,
+        requested_root_id,
+        fetch_limit,
+        filters,
+        &settings,
+    )?);
+    if !filters.tags.is_empty() {
+        let tag_filter = load_tag_filter(&connection, &filters.tags)?;
+        hits.retain(|hit| tag_filter.allows(hit));
+    }
+    hits.sort_by(|left, right| {
+        left.score
+            .partial_cmp(&right.score)
+            .unwrap_or(std::cmp::Ordering::Equal)
+    });
+    hits.truncate(limit);
+    body_index::attach_heading_previews(&connection, &mut hits)?;
+    attach_heading_word_counts(&connection, &mut hits)?;
+    Ok(hits)
+}
+
 async fn run_lexical_search_task(
     app: AppHandle,
     query: String,
     requested_root_id: Option<i64>,
     limit: usize,
     file_name_only: bool,
+    generation: Option<SearchGeneration>,
 ) -> CommandResult<Vec<SearchHit>> {
     tauri::async_runtime::spawn_blocking(move || {
-        lexical::search(&app, &query, requested_root_id, limit, file_name_only)
+        lexical_with_body_hits(
+            &app,
+            &query,
+            requested_root_id,
+            limit,
+            file_name_only,
+            &SearchFilters::default(),
+            generation,
+        )
     })
     .await
-    .map_err(|error| format!("Lexical search task failed: {error}"))?
+    .map_err(|error| CommandError::internal(format!("Lexical search task failed: {error}")))?
+}
+
+/// Timings for a search that started at `started`, with every stage zeroed
+/// for the caller to fill in.
+fn search_timings(mode: &str, started: Instant, hit_count: usize) -> SearchStageTimings {
+    let elapsed = started.elapsed();
+    SearchStageTimings {
+        mode: mode.to_string(),
+        started_at_ms: now_ms() - i64::try_from(elapsed.as_millis()).unwrap_or(0),
+        cached: false,
+        hit_count,
+        resolve_ms: 0.0,
+        lexical_ms: 0.0,
+        semantic_ms: 0.0,
+        fuse_ms: 0.0,
+        total_ms: duration_ms(elapsed),
+    }
 }
 
 fn fuse_rrf(
@@ -275,15 +386,101 @@ fn fuse_rrf(
     ranked
 }
 
+/// Runs an unfiltered lexical search; see `search_lexical_filtered`.
 pub(crate) fn search_lexical(
     app: &AppHandle,
     query: &str,
     root_path: Option<String>,
     limit: Option<usize>,
+    generation: Option<SearchGeneration>,
+) -> CommandResult<Vec<SearchHit>> {
+    search_lexical_filtered(
+        app,
+        query,
+        root_path,
+        limit,
+        &SearchFilters::default(),
+        generation,
+    )
+}
+
+/// Runs a lexical search narrowed by `filters`. When `generation` is set and
+/// a newer search claims a generation before this one finishes, it stops
+/// early with a cancelled error and caches nothing.
+pub(crate) fn search_lexical_filtered(
+    app: &AppHandle,
+    query: &str,
+    root_path: Option<String>,
+    limit: Option<usize>,
+    filters: &SearchFilters,
+    generation: Option<SearchGeneration>,
+) -> CommandResult<Vec<SearchHit>> {
+    search_lexical_up_to(
+        app,
+        query,
+        root_path,
+        effective_limit(limit),
+        filters,
+        generation,
+    )
+}
+
+/// One page of a lexical search starting `offset` hits in. Hits are gathered
+/// in windows of `PAGE_WINDOW`, so pages within a window come from the query
+/// cache; crossing into the next window searches again for the larger set,
+/// which can shift a few lower-ranked hits across the boundary.
+pub(crate) fn search_lexical_page(
+    app: &AppHandle,
+    query: &str,
+    root_path: Option<String>,
+    page_size: Option<usize>,
+    offset: usize,
+    filters: &SearchFilters,
+    generation: Option<SearchGeneration>,
+) -> CommandResult<SearchPage> {
+    let page_size = effective_limit(page_size);
+    let window = offset
+        .saturating_add(page_size)
+        .div_ceil(PAGE_WINDOW)
+        .saturating_mul(PAGE_WINDOW)
+        .clamp(PAGE_WINDOW, lexical::MAX_LEXICAL_HITS);
+    let hits = search_lexical_up_to(app, query, root_path, window, filters, generation)?;
+    // A full window means more hits may lie beyond it.
+    let exhausted = hits.len() < window || window == lexical::MAX_LEXICAL_HITS;
+    let page = hits
+        .iter()
+        .skip(offset)
+        .take(page_size)
+        .cloned()
+        .collect::<Vec<SearchHit>>();
+    let next_offset = offset.saturating_add(page.len());
+    let has_more = !page.is_empty() && (next_offset < hits.len() || !exhausted);
+    Ok(SearchPage {
+        hits: page,
+        next_cursor: has_more.then(|| next_offset.to_string()),
+        total_estimate: hits.len(),
+    })
+}
+
+fn search_lexical_up_to(
+    app: &AppHandle,
+    query: &str,
+    root_path: Option<String>,
+    limit: usize,
+    filters: &SearchFilters,
+    generation: Option<SearchGeneration>,
 ) -> CommandResult<Vec<SearchHit>> {
     let started = Instant::now();
     let capped_query = normalize_query(query);
     let cleaned_query = capped_query.trim();
+    if normalize_for_search(cleaned_query).is_empty() && !filters.tags.is_empty() {
+        let requested_root_id = resolve_requested_root_id(app, root_path)?;
+        let connection = open_database(app)?;
+        let mut hits = tagged_hits(&connection, requested_root_id, &filters.tags, limit)?;
+        body_index::attach_heading_previews(&connection, &mut hits)?;
+        attach_heading_word_counts(&connection, &mut hits)?;
+        return Ok(hits);
+    }
     if cleaned_query.len() < 2 {
         return Ok(Vec::new());
     }
@@ -292,18 +489,43 @@ pub(crate) fn search_lexical(
     }
 
     let requested_root_id = resolve_requested_root_id(app, root_path)?;
-    let limit = effective_limit(limit);
-    let key = cache_key("lexical", cleaned_query, requested_root_id, limit);
+    let resolve_ms = duration_ms(started.elapsed());
+    let key = format!(
+        "{}|{}",
+        cache_key("lexical", cleaned_query, requested_root_id, limit),
+        filters.cache_key()
+    );
     if let Ok(cache) = query_cache().lock() {
         if let Some(cached) = cache.get(&key) {
+            record_search(SearchStageTimings {
+                cached: true,
+                resolve_ms,
+                ..search_timings("lexical", started, cached.len())
+            });
             return Ok(cached);
         }
     }
 
-    let results = lexical::search(app, cleaned_query, requested_root_id, limit, false)?;
+    let lexical_started = Instant::now();
+    let results = lexical_with_body_hits(
+        app,
+        cleaned_query,
+        requested_root_id,
+        limit,
+        false,
+        filters,
+        generation,
+    )?;
+    ensure_current_search(generation)?;
+    let lexical_ms = duration_ms(lexical_started.elapsed());
     if let Ok(mut cache) = query_cache().lock() {
         cache.put(key, results.clone());
     }
+    record_search(SearchStageTimings {
+        resolve_ms,
+        lexical_ms,
+        ..search_timings("lexical", started, results.len())
+    });
 
     if started.elapsed().as_millis() > u128::from(LEXICAL_SOFT_BUDGET_MS) {
         eprintln!(
@@ -349,6 +571,7 @@ pub(crate) async fn search_hybrid(
     limit: Option<usize>,
     file_name_only: bool,
     semantic_enabled: bool,
+    generation: Option<SearchGeneration>,
 ) -> CommandResult<Vec<SearchHit>> {
     let started = Instant::now();
     let capped_query = normalize_query(query);
@@ -361,6 +584,7 @@ pub(crate) async fn search_hybrid(
     }
 
     let requested_root_id = resolve_requested_root_id(app, root_path)?;
+    let resolve_ms = duration_ms(started.elapsed());
     let limit = effective_limit(limit);
     let mode_key = if file_name_only {
         "hybrid_file_name_only"
@@ -372,59 +596,103 @@ pub(crate) async fn search_hybrid(
     let key = cache_key(mode_key, cleaned_query, requested_root_id, limit);
     if let Ok(cache) = query_cache().lock() {
         if let Some(cached) = cache.get(&key) {
+            record_search(SearchStageTimings {
+                cached: true,
+                resolve_ms,
+                ..search_timings(mode_key, started, cached.len())
+            });
             return Ok(cached);
         }
     }
 
     if file_name_only {
+        let lexical_started = Instant::now();
         let lexical_hits = run_lexical_search_task(
             app.clone(),
             cleaned_query.to_string(),
             requested_root_id,
             limit,
             true,
+            generation,
         )
         .await?;
+        ensure_current_search(generation)?;
+        let lexical_ms = duration_ms(lexical_started.elapsed());
         if let Ok(mut cache) = query_cache().lock() {
             cache.put(key, lexical_hits.clone());
         }
+        record_search(SearchStageTimings {
+            resolve_ms,
+            lexical_ms,
+            ..search_timings(mode_key, started, lexical_hits.len())
+        });
         return Ok(lexical_hits);
     }
 
     if !semantic_enabled {
+        let lexical_started = Instant::now();
         let lexical_hits = run_lexical_search_task(
             app.clone(),
             cleaned_query.to_string(),
             requested_root_id,
             limit,
             false,
+            generation,
         )
         .await?;
+        ensure_current_search(generation)?;
+        let lexical_ms = duration_ms(lexical_started.elapsed());
         if let Ok(mut cache) = query_cache().lock() {
             cache.put(key, lexical_hits.clone());
         }
+        record_search(SearchStageTimings {
+            resolve_ms,
+            lexical_ms,
+            ..search_timings(mode_key, started, lexical_hits.len())
+        });
         return Ok(lexical_hits);
     }
 
     vector::trigger_rebuild(app.clone(), false);
 
-    let lexical_task = run_lexical_search_task(
-        app.clone(),
-        cleaned_query.to_string(),
-        requested_root_id,
-        limit,
-        false,
-    );
-    let semantic_task = vector::search(app, cleaned_query, requested_root_id, limit);
-    let (lexical_result, semantic_result) = future::join(lexical_task, semantic_task).await;
+    let lexical_task = async {
+        let lexical_started = Instant::now();
+        let result = run_lexical_search_task(
+            app.clone(),
+            cleaned_query.to_string(),
+            requested_root_id,
+            limit,
+            false,
+            generation,
+        )
+        .await;
+        (result, lexical_started.elapsed())
+    };
+    let semantic_task = async {
+        let semantic_started = Instant::now();
+        let result = vector::search(app, cleaned_query, requested_root_id, limit).await;
+        (result, semantic_started.elapsed())
+    };
+    let ((lexical_result, lexical_time), (semantic_result, semantic_time)) =
+        future::join(lexical_task, semantic_task).await;
 
     let lexical_hits = lexical_result?;
+    ensure_current_search(generation)?;
     let semantic_hits = semantic_result.unwrap_or_default();
+    let fuse_started = Instant::now();
     let fused = fuse_rrf(&lexical_hits, &semantic_hits, limit);
+    let fuse_ms = duration_ms(fuse_started.elapsed());
 
     if let Ok(mut cache) = query_cache().lock() {
         cache.put(key, fused.clone());
     }
+    record_search(SearchStageTimings {
+        resolve_ms,
+        lexical_ms: duration_ms(lexical_time),
+        semantic_ms: duration_ms(semantic_time),
+        fuse_ms,
+        ..search_timings(mode_key, started, fused.len())
+    });
 
     if started.elapsed() > Duration::from_millis(HYBRID_SOFT_BUDGET_MS) {
         eprintln!(
@@ -0,0 +1,277 @@
+use crate::search::normalize_for_search;
+
+/// Field a term is scoped to with an `author:` or `file:` prefix.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub(crate) enum QueryField {
+    Author,
+    File,
+}
+
+/// One word or quoted phrase, already normalized for search. Words that
+/// normalize to several tokens ("anti-war") are matched as phrases.
+#[derive(Clone)]
+pub(crate) struct QueryTerm {
+    pub field: Option<QueryField>,
+    pub text: String,
+    pub phrase: bool,
+}
+
+impl QueryTerm {
+    fn is_bare_word(&self) -> bool {
+        self.field.is_none() && !self.phrase
+    }
+}
+
+/// A search box query: alternatives separated by `OR`, each a list of terms
+/// that must all match, plus `-` exclusions. Field-scoped terms and
+/// exclusions narrow the whole query rather than one alternative.
+#[derive(Default)]
+pub(crate) struct ParsedQuery {
+    pub groups: Vec<Vec<QueryTerm>>,
+    pub excluded: Vec<QueryTerm>,
+    /// Whether the query used quotes, `OR`, `-` or a field prefix. Plain
+    /// queries keep the tiered prefix and fuzzy matching.
+    pub has_syntax: bool,
+}
+
+fn field_prefix(token: &str) -> (Option<QueryField>, &str) {
+    let lower = token.to_ascii_lowercase();
+    if lower.starts_with("author:") {
+        (Some(QueryField::Author), &token["author:".len()..])
+    } else if lower.starts_with("file:") {
+        (Some(QueryField::File), &token["file:".len()..])
+    } else {
+        (None, token)
+    }
+}
+
+/// Splits `tag:` filters out of a search box query: `tag:politics` or
+/// `tag:"K answers"`. Returns the rest of the query and the tags, matched
+/// later without regard to case. Quoted phrases are left whole, so a phrase
+/// containing `tag:` is still searched as text.
+pub(crate) fn split_tag_filters(query: &str) -> (String, Vec<String>) {
+    let mut rest = Vec::new();
+    let mut tags = Vec::<String>::new();
+    let mut remaining = query;
+    loop {
+        remaining = remaining.trim_start();
+        if remaining.is_empty() {
+            break;
+        }
+        let is_tag = remaining
+            .get(..4)
+            .is_some_and(|prefix| prefix.eq_ignore_ascii_case("tag:"));
+        if !is_tag {
+            let mut in_quote = false;
+            let mut end = 0;
+            for (index, character) in remaining.char_indices() {
+                if character == '"' {
+                    in_quote = !in_quote;
+                } else if character.is_whitespace() && !in_quote {
+                    break;
+                }
+                end = index + character.len_utf8();
+            }
+            rest.push(&remaining[..end]);
+            remaining = &remaining[end..];
+            continue;
+        }
+
+        let after = &remaining[4..];
+        let tag = if let Some(quoted) = after.strip_prefix('"') {
+            let end = quoted.find('"').unwrap_or(quoted.len());
+            remaining = quoted.get(end + 1..).unwrap_or_default();
+            &quoted[..end]
+        } else {
+            let end = after.find(char::is_whitespace).unwrap_or(after.len());
+            remaining = &after[end..];
+            &after[..end]
+        };
+        let tag = tag.split_whitespace().collect::<Vec<&str>>().join(" ");
+        if !tag.is_empty() && !tags.iter().any(|known| known.eq_ignore_ascii_case(&tag)) {
+            tags.push(tag);
+        }
+    }
+    (rest.join(" "), tags)
+}
+
+pub(crate) fn parse_query(query: &str) -> ParsedQuery {
+    let mut parsed = ParsedQuery::default();
+    let mut group = Vec::new();
+    let mut chars = query.chars().peekable();
+
+    loop {
+        while chars
+            .next_if(|character| character.is_whitespace())
+            .is_some()
+        {}
+        let Some(&first) = chars.peek() else {
+            break;
+        };
+
+        let excluded = first == '-';
+        if excluded {
+            chars.next();
+        }
+        // Read up to a quote or whitespace; this is the field prefix, or the
+        // whole word when the term is not quoted.
+        let mut raw = String::new();
+        while let Some(character) =
+            chars.next_if(|character| !character.is_whitespace() && *character != '"')
+        {
+            raw.push(character);
+        }
+        let (field, rest) = field_prefix(&raw);
+        let quoted = rest.is_empty() && chars.peek() == Some(&'"');
+        let text = if quoted {
+            chars.next();
+            let phrase = chars
+                .by_ref()
+                .take_while(|character| *character != '"')
+                .collect::<String>();
+            normalize_for_search(&phrase)
+        } else if field.is_none() && !excluded && raw == "OR" {
+            parsed.has_syntax = true;
+            if !group.is_empty() {
+                parsed.groups.push(std::mem::take(&mut group));
+            }
+            continue;
+        } else {
+            // A stray quote inside a word ends it; skip past it.
+            if raw.is_empty() {
+                chars.next();
+            }
+            normalize_for_search(rest)
+        };
+        if text.is_empty() {
+            continue;
+        }
+
+        parsed.has_syntax |= quoted || excluded || field.is_some();
+        let term = QueryTerm {
+            field,
+            phrase: quoted || text.contains(' '),
+            text,
+        };
+        if excluded {
+            parsed.excluded.push(term);
+        } else {
+            group.push(term);
+        }
+    }
+    if !group.is_empty() {
+        parsed.groups.push(group);
+    }
+    parsed
+}
+
+impl ParsedQuery {
+    /// Positive terms scoped to a field, across every alternative.
+    pub(crate) fn field_terms(&self) -> impl Iterator<Item = &QueryTerm> {
+        self.groups
+            .iter()
+            .flatten()
+            .filter(|term| term.field.is_some())
+    }
+
+    fn unscoped_groups(&self) -> Vec<Vec<&QueryTerm>> {
+        self.groups
+            .iter()
+            .map(|group| {
+                group
+                    .iter()
+                    .filter(|term| term.field.is_none())
+                    .collect::<Vec<_>>()
+            })
+            .filter(|group| !group.is_empty())
+            .collect()
+    }
+
+    /// An FTS5 MATCH expression over the unscoped terms, or `None` when there
+    /// are none. The query's last bare word matches as a prefix so results
+    /// keep up while the user types.
+    pub(crate) fn to_fts5(&self) -> Option<String> {
+        let groups = self.unscoped_groups();
+        let last_group = groups.len().checked_sub(1)?;
+        let alternatives = groups
+            .iter()
+            .enumerate()
+            .map(|(group_index, group)| {
+                let terms = group
+                    .iter()
+                    .enumerate()
+                    .map(|(term_index, term)| {
+                        let is_last = group_index == last_group && term_index + 1 == group.len();
+                        if is_last && term.is_bare_word() {
+                            format!("\"{}\"*", term.text)
+                        } else {
+                            format!("\"{}\"", term.text)
+                        }
+                    })
+                    .collect::<Vec<String>>();
+                format!("({})", terms.join(" AND "))
+            })
+            .collect::<Vec<String>>();
+
+        let mut expression = format!("({})", alternatives.join(" OR "));
+        for term in self.excluded.iter().filter(|term| term.field.is_none()) {
+            expression.push_str(&format!(" NOT \"{}\"", term.text));
+        }
+        Some(expression)
+    }
+
+    /// The query in tantivy's query language, with scoped terms mapped onto
+    /// `author_fields` and `file_fields`. `None` when nothing is required,
+    /// since a query of exclusions alone matches nothing useful.
+    pub(crate) fn to_tantivy(
+        &self,
+        author_fields: &[&str],
+        file_fields: &[&str],
+    ) -> Option<String> {
+        fn quoted(term: &QueryTerm) -> String {
+            format!("\"{}\"", term.text)
+        }
+        fn scoped(term: &QueryTerm, fields: &[&str]) -> String {
+            let alternatives = fields
+                .iter()
+                .map(|field| format!("{field}:{}", quoted(term)))
+                .collect::<Vec<String>>();
+            format!("({})", alternatives.join(" OR "))
+        }
+        let fields_for = |field: QueryField| match field {
+            QueryField::Author => author_fields,
+            QueryField::File => file_fields,
+        };
+
+        let mut clauses = Vec::new();
+        let groups = self.unscoped_groups();
+        if !groups.is_empty() {
+            let alternatives = groups
+                .iter()
+                .map(|group| {
+                    let terms = group
+                        .iter()
+                        .map(|term| quoted(term))
+                        .collect::<Vec<String>>();
+                    format!("({})", terms.join(" AND "))
+                })
+                .collect::<Vec<String>>();
+            clauses.push(format!("+({})", alternatives.join(" OR ")));
+        }
+        for term in self.field_terms() {
+            if let Some(field) = term.field {
+                clauses.push(format!("+{}", scoped(term, fields_for(field))));
+            }
+        }
+        if clauses.is_empty() {
+            return None;
+        }
+        for term in &self.excluded {
+            match term.field {
+                Some(field) => clauses.push(format!("-{}", scoped(term, fields_for(field)))),
+                None => clauses.push(format!("-{}", quoted(term))),
+            }
+        }
+        Some(clauses.join(" "))
+    }
+}
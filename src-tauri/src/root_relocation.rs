@@ -0,0 +1,173 @@
+use std::path::Path;
+
+use rusqlite::{params, Connection, OptionalExtension};
+
+use crate::errors::CommandError;
+use crate::shards::schema_for_root;
+use crate::shared_roots::read_root_identity;
+use crate::CommandResult;
+
+/// Files checked under the new path before a root is moved there.
+const RELOCATION_SAMPLE_SIZE: i64 = 20;
+
+/// How many of a random sample of the root's indexed files exist under
+/// `new_root`, as `(sampled, found)`.
+pub(crate) fn sample_relocated_files(
+    connection: &Connection,
+    root_id: i64,
+    new_root: &Path,
+) -> CommandResult<(usize, usize)> {
+    let mut statement = connection
+        .prepare("SELECT relative_path FROM files WHERE root_id = ?1 ORDER BY RANDOM() LIMIT ?2")
+        .map_err(|error| {
+            CommandError::database(format!(
+                "Could not prepare relocation sample query: {error}"
+            ))
+        })?;
+    let rows = statement
+        .query_map(params![root_id, RELOCATION_SAMPLE_SIZE], |row| {
+            row.get::<_, String>(0)
+        })
+        .map_err(|error| {
+            CommandError::database(format!("Could not run relocation sample query: {error}"))
+        })?;
+    let mut sampled = 0;
+    let mut found = 0;
+    for row in rows {
+        let relative_path = row.map_err(|error| {
+            CommandError::database(format!("Could not parse relocation sample row: {error}"))
+        })?;
+        sampled += 1;
+        if new_root.join(&relative_path).is_file() {
+            found += 1;
+        }
+    }
+    Ok((sampled, found))
+}
+
+/// Refuses a new path that carries another root's identity file; a folder
+/// without one, or a root never given one, proves nothing either way.
+pub(crate) fn ensure_same_root_identity(
+    connection: &Connection,
+    root_id: i64,
+    new_root: &Path,
+) -> CommandResult<()> {
+    let stored = connection
+        .query_row(
+            "SELECT root_uid FROM roots WHERE id = ?1",
+            params![root_id],
+            |row| row.get::<_, Option<String>>(0),
+        )
+        .optional()
+        .map_err(|error| CommandError::database(format!("Could not load root identity: {error}")))?
+        .flatten();
+    match (stored, read_root_identity(new_root)) {
+        (Some(stored), Some(found)) if stored != found => Err(CommandError::validation(
+            "The new folder belongs to a different root. Add it as a root of its own instead.",
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// Rewrites `column` of `table` from `old_root` to `new_root` wherever it is
+/// the root itself or a path under it. `root_filter` narrows the rows to one
+/// root for tables that have a `root_id`.
+fn rewrite_path_prefix(
+    connection: &Connection,
+    table: &str,
+    column: &str,
+    root_filter: Option<i64>,
+    old_root: &str,
+    new_root: &str,
+) -> CommandResult<usize> {
+    let root_clause = if root_filter.is_some() {
+        "root_id = ?4 AND"
+    } else {
+        "?4 IS NULL AND"
+    };
+    connection
+        .execute(
+            &format!(
+                "UPDATE {table} SET {column} = ?1 || substr({column}, ?3 + 1)
+                 WHERE {root_clause} substr({column}, 1, ?3) = ?2
+                   AND (length({column}) = ?3 OR substr({column}, ?3 + 1, 1) IN ('/', '\\'))"
+            ),
+            params![
+                new_root,
+                old_root,
+                i64::try_from(old_root.chars().count()).unwrap_or(i64::MAX),
+                root_filter
+            ],
+        )
+        .map_err(|error| {
+            CommandError::database(format!("Could not relocate {table}.{column}: {error}"))
+        })
+}
+
+/// Points a root and every stored path under it at `new_root`, returning how
+/// many indexed files were moved. Relative paths are unchanged, so headings,
+/// tags, history and captures carry over as they are.
+pub(crate) fn relocate_root_rows(
+    connection: &Connection,
+    root_id: i64,
+    old_root: &str,
+    new_root: &str,
+) -> CommandResult<usize> {
+    connection
+        .execute(
+            "UPDATE roots SET path = ?1 WHERE id = ?2",
+            params![new_root, root_id],
+        )
+        .map_err(|error| CommandError::database(format!("Could not relocate root: {error}")))?;
+
+    let schema = schema_for_root(connection, root_id)?;
+    let file_count = rewrite_path_prefix(
+        connection,
+        &format!("{schema}.files"),
+        "absolute_path",
+        Some(root_id),
+        old_root,
+        new_root,
+    )?;
+    rewrite_path_prefix(
+        connection,
+        &format!("{schema}.chunks"),
+        "absolute_path",
+        Some(root_id),
+        old_root,
+        new_root,
+    )?;
+    rewrite_path_prefix(
+        connection,
+        "captures",
+        "source_path",
+        Some(root_id),
+        old_root,
+        new_root,
+    )?;
+    rewrite_path_prefix(
+        connection,
+        "root_intake",
+        "intake_path",
+        Some(root_id),
+        old_root,
+        new_root,
+    )?;
+    rewrite_path_prefix(
+        connection,
+        "capture_outline_cache",
+        "capture_path",
+        None,
+        old_root,
+        new_root,
+    )?;
+    rewrite_path_prefix(
+        connection,
+        "session_state",
+        "last_root_path",
+        None,
+        old_root,
+        new_root,
+    )?;
+    Ok(file_count)
+}
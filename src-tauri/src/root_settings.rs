@@ -0,0 +1,136 @@
+use rusqlite::{params, Connection, OptionalExtension};
+
+use crate::errors::CommandError;
+use crate::types::RootSettings;
+use crate::util::now_ms;
+use crate::validation::normalize_capture_target_path;
+use crate::CommandResult;
+use crate::DEFAULT_CAPTURE_TARGET;
+
+/// Word caps style ids at 253 characters.
+const MAX_STYLE_ID_CHARS: usize = 253;
+/// A shift past this would push every heading onto Heading 9.
+const MAX_HEADING_LEVEL_SHIFT: i64 = 8;
+
+/// The layout captures had before settings existed: one shared target, a
+/// title line in a fresh file and a blank paragraph after each capture, with
+/// headings kept at their source level.
+pub(crate) fn default_root_settings() -> RootSettings {
+    RootSettings {
+        default_capture_target: DEFAULT_CAPTURE_TARGET.to_string(),
+        insert_title: true,
+        insert_separator: true,
+        separator_style: None,
+        heading_level_shift: 0,
+        nest_child_captures: false,
+    }
+}
+
+/// Checks settings before they are stored: the default target goes through
+/// the same rules as any capture target and the separator style has to be a
+/// usable style id. The heading shift is clamped rather than rejected.
+pub(crate) fn normalize_root_settings(settings: RootSettings) -> CommandResult<RootSettings> {
+    let default_capture_target =
+        normalize_capture_target_path(Some(&settings.default_capture_target))?;
+    let separator_style = settings
+        .separator_style
+        .map(|style| style.trim().to_string())
+        .filter(|style| !style.is_empty());
+    if let Some(style) = &separator_style {
+        if style.chars().count() > MAX_STYLE_ID_CHARS {
+            return Err(CommandError::validation_field(
+                "separatorStyle",
+                format!("Style ids are at most {MAX_STYLE_ID_CHARS} characters."),
+            ));
+        }
+    }
+    Ok(RootSettings {
+        default_capture_target,
+        insert_title: settings.insert_title,
+        insert_separator: settings.insert_separator,
+        separator_style,
+        heading_level_shift: settings
+            .heading_level_shift
+            .clamp(0, MAX_HEADING_LEVEL_SHIFT),
+        nest_child_captures: settings.nest_child_captures,
+    })
+}
+
+pub(crate) fn load_root_settings(
+    connection: &Connection,
+    root_id: i64,
+) -> CommandResult<RootSettings> {
+    let stored = connection
+        .query_row(
+            "SELECT default_capture_target, insert_title, insert_separator, separator_style,
+                    heading_level_shift, nest_child_captures
+             FROM root_settings WHERE root_id = ?1",
+            params![root_id],
+            |row| {
+                Ok(RootSettings {
+                    default_capture_target: row.get(0)?,
+                    insert_title: row.get::<_, i64>(1)? != 0,
+                    insert_separator: row.get::<_, i64>(2)? != 0,
+                    separator_style: row.get(3)?,
+                    heading_level_shift: row.get(4)?,
+                    nest_child_captures: row.get::<_, i64>(5)? != 0,
+                })
+            },
+        )
+        .optional()
+        .map_err(|error| {
+            CommandError::database(format!("Could not load root settings: {error}"))
+        })?;
+    Ok(stored.unwrap_or_else(default_root_settings))
+}
+
+pub(crate) fn store_root_settings(
+    connection: &Connection,
+    root_id: i64,
+    settings: &RootSettings,
+) -> CommandResult<()> {
+    connection
+        .execute(
+            "INSERT INTO root_settings(
+               root_id, default_capture_target, insert_title, insert_separator,
+               separator_style, heading_level_shift, nest_child_captures, updated_at_ms
+             ) VALUES(?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)
+             ON CONFLICT(root_id) DO UPDATE SET
+               default_capture_target = excluded.default_capture_target,
+               insert_title = excluded.insert_title,
+               insert_separator = excluded.insert_separator,
+               separator_style = excluded.separator_style,
+               heading_level_shift = excluded.heading_level_shift,
+               nest_child_captures = excluded.nest_child_captures,
+               updated_at_ms = excluded.updated_at_ms",
+            params![
+                root_id,
+                settings.default_capture_target,
+                i64::from(settings.insert_title),
+                i64::from(settings.insert_separator),
+                settings.separator_style,
+                settings.heading_level_shift,
+                i64::from(settings.nest_child_captures),
+                now_ms(),
+            ],
+        )
+        .map_err(|error| {
+            CommandError::database(format!("Could not save root settings: {error}"))
+        })?;
+    Ok(())
+}
+
+/// The capture target a command means: the one it names, or the root's
+/// default when it names none.
+pub(crate) fn capture_target_for_root(
+    connection: &Connection,
+    root_id: i64,
+    target_path: Option<&str>,
+) -> CommandResult<String> {
+    match target_path.map(str::trim).filter(|value| !value.is_empty()) {
+        Some(target) => normalize_capture_target_path(Some(target)),
+        None => normalize_capture_target_path(Some(
+            &load_root_settings(connection, root_id)?.default_capture_target,
+        )),
+    }
+}
@@ -0,0 +1,330 @@
+use std::collections::HashMap;
+
+use crate::docx_build::ParagraphBuilder;
+use crate::types::{ConvertedDocument, GeneratedRun};
+
+/// Destinations that hold tables, metadata or binary data rather than text.
+const SKIPPED_DESTINATIONS: [&str; 22] = [
+    "fonttbl",
+    "colortbl",
+    "info",
+    "pict",
+    "header",
+    "headerl",
+    "headerr",
+    "headerf",
+    "footer",
+    "footerl",
+    "footerr",
+    "footerf",
+    "footnote",
+    "listtable",
+    "listoverridetable",
+    "rsidtbl",
+    "generator",
+    "xmlnstbl",
+    "themedata",
+    "colorschememapping",
+    "latentstyles",
+    "datastore",
+];
+
+/// Windows-1252 characters for bytes 0x80..=0x9F; the rest of the upper half
+/// matches Latin-1.
+const CP1252_HIGH: [char; 32] = [
+    '€', '\u{81}', '‚', 'ƒ', '„', '…', '†', '‡', 'ˆ', '‰', 'Š', '‹', 'Œ', '\u{8d}', 'Ž', '\u{8f}',
+    '\u{90}', '‘', '’', '“', '”', '•', '–', '—', '˜', '™', 'š', '›', 'œ', '\u{9d}', 'ž', 'Ÿ',
+];
+
+#[derive(Clone, Copy, Default, PartialEq)]
+enum Destination {
+    #[default]
+    Text,
+    Skip,
+    StyleSheet,
+    StyleEntry,
+    FieldInstruction,
+}
+
+#[derive(Clone, Default)]
+struct GroupState {
+    destination: Destination,
+    bold: bool,
+    italic: bool,
+    underline: bool,
+    link: Option<String>,
+    unicode_skip: usize,
+}
+
+#[derive(Default)]
+struct RtfConverter {
+    builder: ParagraphBuilder,
+    stack: Vec<GroupState>,
+    state: GroupState,
+    ignorable_pending: bool,
+    skip_chars: usize,
+    style_levels: HashMap<i64, i64>,
+    style_entry: Option<(i64, String)>,
+    field_instruction: String,
+    pending_link: Option<String>,
+}
+
+impl RtfConverter {
+    fn push_char(&mut self, character: char) {
+        if self.skip_chars > 0 {
+            self.skip_chars -= 1;
+            return;
+        }
+        match self.state.destination {
+            Destination::Text => {
+                let text = if character == ' ' && self.builder.at_word_boundary() {
+                    String::new()
+                } else {
+                    character.to_string()
+                };
+                self.builder.push_run(GeneratedRun {
+                    text,
+                    bold: self.state.bold,
+                    italic: self.state.italic,
+                    underline: self.state.underline,
+                    link: self.state.link.clone(),
+                });
+            }
+            Destination::StyleEntry => {
+                if let Some((_, name)) = self.style_entry.as_mut() {
+                    name.push(character);
+                }
+            }
+            Destination::FieldInstruction => self.field_instruction.push(character),
+            Destination::Skip | Destination::StyleSheet => {}
+        }
+    }
+
+    fn open_group(&mut self) {
+        self.stack.push(self.state.clone());
+        if self.state.destination == Destination::StyleSheet {
+            self.state.destination = Destination::StyleEntry;
+            self.style_entry = Some((0, String::new()));
+        }
+    }
+
+    fn close_group(&mut self) {
+        self.ignorable_pending = false;
+        let closed = self.state.destination;
+        if let Some(previous) = self.stack.pop() {
+            self.state = previous;
+        }
+        // Nested groups inherit the destination, so only the outermost group
+        // of a style entry or field instruction completes it.
+        if closed == self.state.destination {
+            return;
+        }
+        match closed {
+            Destination::StyleEntry => {
+                if let Some((number, name)) = self.style_entry.take() {
+                    if let Some(level) = heading_level_from_style_name(&name) {
+                        self.style_levels.entry(number).or_insert(level);
+                    }
+                }
+            }
+            Destination::FieldInstruction => {
+                let instruction = std::mem::take(&mut self.field_instruction);
+                self.pending_link = hyperlink_target(&instruction);
+            }
+            _ => {}
+        }
+    }
+
+    fn control_word(&mut self, word: &str, parameter: Option<i32>) {
+        let ignorable = std::mem::take(&mut self.ignorable_pending);
+        if self.state.destination == Destination::Skip {
+            return;
+        }
+        if self.state.destination == Destination::StyleEntry {
+            match word {
+                "s" => {
+                    if let Some((number, _)) = self.style_entry.as_mut() {
+                        *number = i64::from(parameter.unwrap_or(0));
+                    }
+                }
+                "outlinelevel" => {
+                    if let Some((number, _)) = self.style_entry {
+                        self.style_levels
+                            .insert(number, outline_heading_level(parameter.unwrap_or(0)));
+                    }
+                }
+                _ => {}
+            }
+            return;
+        }
+
+        match word {
+            "stylesheet" => self.state.destination = Destination::StyleSheet,
+            "fldinst" => {
+                self.state.destination = Destination::FieldInstruction;
+                self.field_instruction.clear();
+            }
+            "fldrslt" => self.state.link = self.pending_link.take(),
+            _ if SKIPPED_DESTINATIONS.contains(&word) => self.state.destination = Destination::Skip,
+            _ if ignorable => self.state.destination = Destination::Skip,
+            "par" | "sect" | "page" | "row" => self.builder.flush(),
+            "line" => self.builder.push_line_break(),
+            "tab" | "cell" => self.push_char(' '),
+            "pard" => self.builder.set_heading_level(None),
+            "s" => {
+                let style = i64::from(parameter.unwrap_or(0));
+                if let Some(level) = self.style_levels.get(&style) {
+                    self.builder.set_heading_level(Some(*level));
+                }
+            }
+            "outlinelevel" => {
+                let level = parameter.unwrap_or(0);
+                self.builder.set_heading_level(
+                    (0..9)
+                        .contains(&level)
+                        .then(|| outline_heading_level(level)),
+                );
+            }
+            "b" => self.state.bold = parameter != Some(0),
+            "i" => self.state.italic = parameter != Some(0),
+            "ul" => self.state.underline = parameter != Some(0),
+            "ulnone" => self.state.underline = false,
+            "plain" => {
+                self.state.bold = false;
+                self.state.italic = false;
+                self.state.underline = false;
+            }
+            "uc" => self.state.unicode_skip = parameter.unwrap_or(1).max(0) as usize,
+            "u" => {
+                let code = parameter.unwrap_or(0);
+                let code = if code < 0 { code + 65536 } else { code };
+                if let Some(character) = char::from_u32(code as u32) {
+                    self.push_char(character);
+                }
+                self.skip_chars = self.state.unicode_skip;
+            }
+            "emdash" => self.push_char('—'),
+            "endash" => self.push_char('–'),
+            "bullet" => self.push_char('•'),
+            "lquote" => self.push_char('‘'),
+            "rquote" => self.push_char('’'),
+            "ldblquote" => self.push_char('“'),
+            "rdblquote" => self.push_char('”'),
+            _ => {}
+        }
+    }
+
+    fn control_symbol(&mut self, symbol: char) {
+        match symbol {
+            '*' => self.ignorable_pending = true,
+            '~' => self.push_char(' '),
+            '_' => self.push_char('-'),
+            '\\' | '{' | '}' => self.push_char(symbol),
+            '\n' | '\r' if self.state.destination == Destination::Text => self.builder.flush(),
+            _ => {}
+        }
+    }
+}
+
+/// Maps a zero-based outline level onto the four heading levels the indexer uses.
+fn outline_heading_level(outline_level: i32) -> i64 {
+    i64::from(outline_level.clamp(0, 3)) + 1
+}
+
+fn heading_level_from_style_name(name: &str) -> Option<i64> {
+    let name = name
+        .trim()
+        .trim_end_matches(';')
+        .trim()
+        .to_ascii_lowercase();
+    let level = name.strip_prefix("heading")?.trim().parse::<i32>().ok()?;
+    (1..=9)
+        .contains(&level)
+        .then(|| outline_heading_level(level - 1))
+}
+
+/// Pulls the target out of a `HYPERLINK "https://..."` field instruction.
+fn hyperlink_target(instruction: &str) -> Option<String> {
+    let rest = instruction.trim().strip_prefix("HYPERLINK")?.trim();
+    let target = match rest.strip_prefix('"') {
+        Some(quoted) => quoted.split('"').next().unwrap_or_default(),
+        None => rest.split_whitespace().next().unwrap_or_default(),
+    };
+    let lower = target.to_ascii_lowercase();
+    (lower.starts_with("http://") || lower.starts_with("https://") || lower.starts_with("mailto:"))
+        .then(|| target.to_string())
+}
+
+pub(crate) fn cp1252_char(byte: u8) -> char {
+    match byte {
+        0x80..=0x9F => CP1252_HIGH[usize::from(byte - 0x80)],
+        _ => char::from(byte),
+    }
+}
+
+/// Converts RTF (as put on the clipboard by Word, Pages or a browser) into
+/// paragraphs. Formatting beyond bold, italic, underline, heading levels and
+/// hyperlinks is dropped, and unknown control words are ignored.
+pub(crate) fn convert_rtf_document(rtf: &str) -> ConvertedDocument {
+    let mut converter = RtfConverter {
+        state: GroupState {
+            unicode_skip: 1,
+            ..GroupState::default()
+        },
+        ..RtfConverter::default()
+    };
+    let mut characters = rtf.chars().peekable();
+    while let Some(character) = characters.next() {
+        match character {
+            '{' => converter.open_group(),
+            '}' => converter.close_group(),
+            '\r' | '\n' => {}
+            '\\' => {
+                let Some(&next) = characters.peek() else {
+                    break;
+                };
+                if next.is_ascii_alphabetic() {
+                    let mut word = String::new();
+                    while let Some(&letter) = characters.peek() {
+                        if !letter.is_ascii_alphabetic() {
+                            break;
+                        }
+                        word.push(letter);
+                        characters.next();
+                    }
+                    let mut digits = String::new();
+                    if characters.peek() == Some(&'-') {
+                        digits.push('-');
+                        characters.next();
+                    }
+                    while let Some(&digit) = characters.peek() {
+                        if !digit.is_ascii_digit() {
+                            break;
+                        }
+                        digits.push(digit);
+                        characters.next();
+                    }
+                    if characters.peek() == Some(&' ') {
+                        characters.next();
+                    }
+                    converter.control_word(&word, digits.parse::<i32>().ok());
+                } else if next == '\'' {
+                    characters.next();
+                    let hex = characters.by_ref().take(2).collect::<String>();
+                    if let Ok(byte) = u8::from_str_radix(&hex, 16) {
+                        converter.push_char(cp1252_char(byte));
+                    }
+                } else {
+                    characters.next();
+                    converter.control_symbol(next);
+                }
+            }
+            _ => converter.push_char(character),
+        }
+    }
+
+    ConvertedDocument {
+        paragraphs: converter.builder.finish(),
+        ..ConvertedDocument::default()
+    }
+}
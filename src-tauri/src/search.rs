@@ -1,5 +1,110 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::errors::CommandError;
+use crate::CommandResult;
+
 pub(crate) const MAX_QUERY_CHARS: usize = 512;
 
+static LEXICAL_SEARCH_GENERATION: AtomicU64 = AtomicU64::new(0);
+static HYBRID_SEARCH_GENERATION: AtomicU64 = AtomicU64::new(0);
+
+/// The search command a generation belongs to. Each command only supersedes
+/// its own older searches, so a lexical and a hybrid search running side by
+/// side do not cancel each other.
+#[derive(Clone, Copy)]
+pub(crate) enum SearchChannel {
+    Lexical,
+    Hybrid,
+}
+
+impl SearchChannel {
+    fn counter(self) -> &'static AtomicU64 {
+        match self {
+            Self::Lexical => &LEXICAL_SEARCH_GENERATION,
+            Self::Hybrid => &HYBRID_SEARCH_GENERATION,
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+pub(crate) struct SearchGeneration {
+    channel: SearchChannel,
+    value: u64,
+}
+
+/// Claims the next interactive search generation on `channel`. Any search
+/// still running there under an older generation has been superseded by a
+/// newer keystroke.
+pub(crate) fn next_search_generation(channel: SearchChannel) -> SearchGeneration {
+    SearchGeneration {
+        channel,
+        value: channel.counter().fetch_add(1, Ordering::SeqCst) + 1,
+    }
+}
+
+pub(crate) fn is_superseded(generation: Option<SearchGeneration>) -> bool {
+    generation
+        .map(|generation| generation.channel.counter().load(Ordering::SeqCst) != generation.value)
+        .unwrap_or(false)
+}
+
+/// Stops a superseded search with a cancelled error, so the caller can tell
+/// a dropped response from a search that found nothing.
+pub(crate) fn ensure_current_search(generation: Option<SearchGeneration>) -> CommandResult<()> {
+    if is_superseded(generation) {
+        return Err(CommandError::cancelled("search"));
+    }
+    Ok(())
+}
+
+/// Optional narrowing of a lexical search by heading level, hit kind
+/// (`file`, `heading`, `author`, `body`, `cite`) and tags. A hit without a
+/// heading level, such as a file-name or author hit, never satisfies a level
+/// bound. A hit passes the tags when each is on its file or its heading.
+#[derive(Clone, Default)]
+pub(crate) struct SearchFilters {
+    pub min_level: Option<i64>,
+    pub max_level: Option<i64>,
+    pub kinds: Option<Vec<String>>,
+    pub tags: Vec<String>,
+}
+
+impl SearchFilters {
+    pub(crate) fn has_level_bounds(&self) -> bool {
+        self.min_level.is_some() || self.max_level.is_some()
+    }
+
+    pub(crate) fn allows_kind(&self, kind: &str) -> bool {
+        self.kinds
+            .as_ref()
+            .map(|kinds| kinds.iter().any(|allowed| allowed == kind))
+            .unwrap_or(true)
+    }
+
+    /// Distinguishes filtered searches in the query cache.
+    pub(crate) fn cache_key(&self) -> String {
+        let mut kinds = self.kinds.clone().unwrap_or_default();
+        kinds.sort();
+        let mut tags = self
+            .tags
+            .iter()
+            .map(|tag| tag.to_lowercase())
+            .collect::<Vec<String>>();
+        tags.sort();
+        format!(
+            "{}-{}-{}-{}",
+            self.min_level
+                .map(|level| level.to_string())
+                .unwrap_or_default(),
+            self.max_level
+                .map(|level| level.to_string())
+                .unwrap_or_default(),
+            kinds.join(","),
+            tags.join(",")
+        )
+    }
+}
+
 pub(crate) fn normalize_for_search(text: &str) -> String {
     let mut normalized = String::with_capacity(text.len());
     let mut previous_space = false;
@@ -0,0 +1,237 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use rusqlite::{params, Connection};
+
+use crate::capture_writer::write_parts;
+use crate::docx_capture::{
+    create_blank_docx, extract_styled_section, insert_fragment_into_document_xml,
+    merge_section_into_docx_parts, paragraph_xml_bold, paragraph_xml_heading,
+};
+use crate::docx_parse::read_docx_parts;
+use crate::errors::CommandError;
+use crate::operations::OperationHandle;
+use crate::types::{SearchExportFilters, SearchHit};
+use crate::util::path_display;
+use crate::CommandResult;
+
+/// Level used for per-file group headings when they are headings, i.e. a
+/// pocket.
+const FILE_GROUP_HEADING_LEVEL: i64 = 1;
+
+/// How each source file's group of sections is introduced.
+#[derive(Clone, Copy)]
+pub(crate) enum SourceHeader {
+    /// A Heading 1, so every source becomes a pocket.
+    Pocket,
+    /// A bold line that leaves the heading outline to the sections.
+    Bold,
+}
+
+/// Matching sections from one source file, in document order.
+pub(crate) struct CompiledFile {
+    pub file_id: i64,
+    pub file_name: String,
+    pub absolute_path: String,
+    pub sections: Vec<(i64, String)>,
+}
+
+pub(crate) struct SearchCompileCounts {
+    pub file_count: usize,
+    pub section_count: usize,
+    pub nested_count: usize,
+}
+
+fn matches_filters(hit: &SearchHit, filters: &SearchExportFilters) -> bool {
+    if let Some(levels) = filters
+        .heading_levels
+        .as_ref()
+        .filter(|levels| !levels.is_empty())
+    {
+        if !hit
+            .heading_level
+            .map(|level| levels.contains(&level))
+            .unwrap_or(false)
+        {
+            return false;
+        }
+    }
+    if let Some(prefix) = filters
+        .path_prefix
+        .as_deref()
+        .map(|prefix| prefix.trim().trim_matches('/'))
+        .filter(|prefix| !prefix.is_empty())
+    {
+        let relative = hit.relative_path.as_str();
+        if relative != prefix && !relative.starts_with(&format!("{prefix}/")) {
+            return false;
+        }
+    }
+    true
+}
+
+/// Groups heading hits by file, keeping files in order of their best hit so
+/// the most relevant sources come first.
+pub(crate) fn group_search_hits(
+    hits: Vec<SearchHit>,
+    filters: &SearchExportFilters,
+) -> Vec<CompiledFile> {
+    let mut files: Vec<CompiledFile> = Vec::new();
+    let mut positions = HashMap::new();
+    for hit in hits {
+        let Some(heading_order) = hit.heading_order else {
+            continue;
+        };
+        if !matches_filters(&hit, filters) {
+            continue;
+        }
+        let position = *positions.entry(hit.file_id).or_insert_with(|| {
+            files.push(CompiledFile {
+                file_id: hit.file_id,
+                file_name: hit.file_name.clone(),
+                absolute_path: hit.absolute_path.clone(),
+                sections: Vec::new(),
+            });
+            files.len() - 1
+        });
+        let sections = &mut files[position].sections;
+        if !sections.iter().any(|(order, _)| *order == heading_order) {
+            sections.push((heading_order, hit.heading_text.unwrap_or_default()));
+        }
+    }
+    for file in &mut files {
+        file.sections.sort_by_key(|(order, _)| *order);
+    }
+    files
+}
+
+fn file_heading_levels(connection: &Connection, file_id: i64) -> CommandResult<Vec<(i64, i64)>> {
+    let mut statement = connection
+        .prepare(
+            "SELECT heading_order, level FROM headings WHERE file_id = ?1 ORDER BY heading_order",
+        )
+        .map_err(|error| {
+            CommandError::database(format!("Could not prepare heading level query: {error}"))
+        })?;
+    let rows = statement
+        .query_map(params![file_id], |row| Ok((row.get(0)?, row.get(1)?)))
+        .map_err(|error| {
+            CommandError::database(format!("Could not run heading level query: {error}"))
+        })?;
+    let mut levels = Vec::new();
+    for row in rows {
+        levels.push(row.map_err(|error| {
+            CommandError::database(format!("Could not parse heading level row: {error}"))
+        })?);
+    }
+    Ok(levels)
+}
+
+/// Drops sections that sit inside another selected section of the same file,
+/// since the outer section already carries their text.
+fn outermost_sections(
+    sections: &[(i64, String)],
+    heading_levels: &[(i64, i64)],
+) -> Vec<(i64, String)> {
+    let level_of = |order: i64| {
+        heading_levels
+            .iter()
+            .find(|(heading_order, _)| *heading_order == order)
+            .map(|(_, level)| *level)
+    };
+    let mut kept: Vec<(i64, String)> = Vec::new();
+    for (order, text) in sections {
+        let nested = kept.last().is_some_and(|(outer_order, _)| {
+            let Some(outer_level) = level_of(*outer_order) else {
+                return false;
+            };
+            !heading_levels.iter().any(|(heading_order, level)| {
+                *heading_order > *outer_order && *heading_order <= *order && *level <= outer_level
+            })
+        });
+        if !nested {
+            kept.push((*order, text.clone()));
+        }
+    }
+    kept
+}
+
+/// Writes one document holding each matching section under a header for its
+/// source file, styled per `source_header`. Styles,
+/// relationships, lists, notes and images are merged per source, as captures
+/// do.
+pub(crate) fn write_search_compilation(
+    connection: &Connection,
+    query: &str,
+    files: &[CompiledFile],
+    source_header: SourceHeader,
+    output_path: &Path,
+    operation: &mut OperationHandle,
+) -> CommandResult<SearchCompileCounts> {
+    if let Some(parent) = output_path.parent() {
+        fs::create_dir_all(parent).map_err(|error| {
+            CommandError::io(format!(
+                "Could not create export folder '{}': {error}",
+                path_display(parent)
+            ))
+        })?;
+    }
+    create_blank_docx(output_path)?;
+    let mut parts = read_docx_parts(output_path)?.ok_or_else(|| {
+        CommandError::invalid_docx(
+            output_path,
+            format!(
+                "Missing word/document.xml in '{}' after initialization",
+                path_display(output_path)
+            ),
+        )
+    })?;
+
+    let mut fragment = paragraph_xml_bold(&format!("Search: {}", query.trim()));
+    let mut counts = SearchCompileCounts {
+        file_count: 0,
+        section_count: 0,
+        nested_count: 0,
+    };
+    let total = files.len();
+    for (index, file) in files.iter().enumerate() {
+        operation.ensure_not_cancelled()?;
+        operation.update(
+            "compiling",
+            index,
+            Some(total),
+            Some(file.file_name.clone()),
+        );
+
+        let heading_levels = file_heading_levels(connection, file.file_id)?;
+        let sections = outermost_sections(&file.sections, &heading_levels);
+        counts.nested_count += file.sections.len() - sections.len();
+        let source_path = Path::new(&file.absolute_path);
+        fragment.push_str(&match source_header {
+            SourceHeader::Pocket => {
+                paragraph_xml_heading(FILE_GROUP_HEADING_LEVEL, &file.file_name)
+            }
+            SourceHeader::Bold => paragraph_xml_bold(&file.file_name),
+        });
+        for (order, heading_text) in sections {
+            let mut section = extract_styled_section(source_path, Some(order), &heading_text);
+            // The group heading uses the source's own Heading1 when it has one.
+            if matches!(source_header, SourceHeader::Pocket) && section.used_source_xml {
+                section
+                    .style_ids
+                    .insert(format!("Heading{FILE_GROUP_HEADING_LEVEL}"));
+            }
+            for paragraph in merge_section_into_docx_parts(&mut parts, &section) {
+                fragment.push_str(&paragraph);
+            }
+            fragment.push_str("<w:p/>");
+            counts.section_count += 1;
+        }
+        counts.file_count += 1;
+    }
+
+    parts.document_xml = insert_fragment_into_document_xml(&parts.document_xml, &fragment, None)?;
+    write_parts(output_path, &parts)?;
+    Ok(counts)
+}
@@ -0,0 +1,141 @@
+use rusqlite::{params, Connection, OptionalExtension};
+
+use crate::errors::CommandError;
+use crate::types::SearchSettings;
+use crate::util::now_ms;
+use crate::CommandResult;
+
+/// Field boosts outside this range either mute a field or drown out every
+/// other one, neither of which anyone means.
+const MIN_FIELD_WEIGHT: f64 = 0.1;
+const MAX_FIELD_WEIGHT: f64 = 10.0;
+const MAX_MIN_CHARS: i64 = 20;
+/// Score bases stay above zero and well clear of the hybrid search's RRF
+/// scores, which it maps onto 0-1000 on its own.
+const MIN_SCORE_BASE: f64 = 1.0;
+const MAX_SCORE_BASE: f64 = 100_000.0;
+
+/// The ranking shipped before settings existed: recall matches just behind
+/// exact ones, cites ahead of body text (a match in the cite names the card's
+/// source), then prefix and n-gram matches.
+pub(crate) fn default_search_settings() -> SearchSettings {
+    SearchSettings {
+        heading_weight: 1.0,
+        author_weight: 1.0,
+        file_weight: 1.0,
+        prefix_min_chars: 0,
+        fuzzy_min_chars: 0,
+        recall_score_base: 1_450.0,
+        cite_score_base: 1_400.0,
+        body_score_base: 1_500.0,
+        prefix_score_base: 2_000.0,
+        fuzzy_score_base: 3_000.0,
+    }
+}
+
+fn clamp_weight(value: f64) -> f64 {
+    if value.is_finite() {
+        value.clamp(MIN_FIELD_WEIGHT, MAX_FIELD_WEIGHT)
+    } else {
+        1.0
+    }
+}
+
+fn clamp_score_base(value: f64, fallback: f64) -> f64 {
+    if value.is_finite() {
+        value.clamp(MIN_SCORE_BASE, MAX_SCORE_BASE)
+    } else {
+        fallback
+    }
+}
+
+/// Pulls out-of-range values back into range, the way search limits are
+/// clamped rather than rejected.
+pub(crate) fn clamp_search_settings(settings: SearchSettings) -> SearchSettings {
+    let defaults = default_search_settings();
+    SearchSettings {
+        heading_weight: clamp_weight(settings.heading_weight),
+        author_weight: clamp_weight(settings.author_weight),
+        file_weight: clamp_weight(settings.file_weight),
+        prefix_min_chars: settings.prefix_min_chars.clamp(0, MAX_MIN_CHARS),
+        fuzzy_min_chars: settings.fuzzy_min_chars.clamp(0, MAX_MIN_CHARS),
+        recall_score_base: clamp_score_base(settings.recall_score_base, defaults.recall_score_base),
+        cite_score_base: clamp_score_base(settings.cite_score_base, defaults.cite_score_base),
+        body_score_base: clamp_score_base(settings.body_score_base, defaults.body_score_base),
+        prefix_score_base: clamp_score_base(settings.prefix_score_base, defaults.prefix_score_base),
+        fuzzy_score_base: clamp_score_base(settings.fuzzy_score_base, defaults.fuzzy_score_base),
+    }
+}
+
+pub(crate) fn load_search_settings(connection: &Connection) -> CommandResult<SearchSettings> {
+    let stored = connection
+        .query_row(
+            "SELECT heading_weight, author_weight, file_weight, prefix_min_chars,
+                    fuzzy_min_chars, recall_score_base, cite_score_base, body_score_base,
+                    prefix_score_base, fuzzy_score_base
+             FROM search_settings WHERE id = 1",
+            [],
+            |row| {
+                Ok(SearchSettings {
+                    heading_weight: row.get(0)?,
+                    author_weight: row.get(1)?,
+                    file_weight: row.get(2)?,
+                    prefix_min_chars: row.get(3)?,
+                    fuzzy_min_chars: row.get(4)?,
+                    recall_score_base: row.get(5)?,
+                    cite_score_base: row.get(6)?,
+                    body_score_base: row.get(7)?,
+                    prefix_score_base: row.get(8)?,
+                    fuzzy_score_base: row.get(9)?,
+                })
+            },
+        )
+        .optional()
+        .map_err(|error| {
+            CommandError::database(format!("Could not load search settings: {error}"))
+        })?;
+    Ok(stored.unwrap_or_else(default_search_settings))
+}
+
+pub(crate) fn store_search_settings(
+    connection: &Connection,
+    settings: &SearchSettings,
+) -> CommandResult<()> {
+    connection
+        .execute(
+            "INSERT INTO search_settings(
+               id, heading_weight, author_weight, file_weight, prefix_min_chars,
+               fuzzy_min_chars, recall_score_base, cite_score_base, body_score_base,
+               prefix_score_base, fuzzy_score_base, updated_at_ms
+             ) VALUES(1, ?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)
+             ON CONFLICT(id) DO UPDATE SET
+               heading_weight = excluded.heading_weight,
+               author_weight = excluded.author_weight,
+               file_weight = excluded.file_weight,
+               prefix_min_chars = excluded.prefix_min_chars,
+               fuzzy_min_chars = excluded.fuzzy_min_chars,
+               recall_score_base = excluded.recall_score_base,
+               cite_score_base = excluded.cite_score_base,
+               body_score_base = excluded.body_score_base,
+               prefix_score_base = excluded.prefix_score_base,
+               fuzzy_score_base = excluded.fuzzy_score_base,
+               updated_at_ms = excluded.updated_at_ms",
+            params![
+                settings.heading_weight,
+                settings.author_weight,
+                settings.file_weight,
+                settings.prefix_min_chars,
+                settings.fuzzy_min_chars,
+                settings.recall_score_base,
+                settings.cite_score_base,
+                settings.body_score_base,
+                settings.prefix_score_base,
+                settings.fuzzy_score_base,
+                now_ms(),
+            ],
+        )
+        .map_err(|error| {
+            CommandError::database(format!("Could not save search settings: {error}"))
+        })?;
+    Ok(())
+}
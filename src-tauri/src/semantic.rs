@@ -22,6 +22,7 @@ use tauri::{AppHandle, Manager};
 use tokenizers::Tokenizer;
 
 use crate::db::{index_meta_dir, index_vector_dir, open_database};
+use crate::errors::CommandError;
 use crate::types::{SearchHit, SemanticCandidate, SemanticIndexMeta, SemanticRuntime};
 use crate::util::{file_name_from_relative, now_ms, path_display};
 use crate::CommandResult;
@@ -105,10 +106,9 @@ async fn load_semantic_table(app: &AppHandle) -> CommandResult<Option<LanceTable
         }
     }
 
-    let db = connect_lancedb(&uri)
-        .execute()
-        .await
-        .map_err(|error| format!("Could not open semantic LanceDB: {error}"))?;
+    let db = connect_lancedb(&uri).execute().await.map_err(|error| {
+        CommandError::database(format!("Could not open semantic LanceDB: {error}"))
+    })?;
     let table = match db.open_table(SEMANTIC_TABLE_NAME).execute().await {
         Ok(table) => table,
         Err(_) => return Ok(None),
@@ -146,44 +146,44 @@ fn resolve_semantic_resource_path(app: &AppHandle, file_name: &str) -> CommandRe
         }
     }
 
-    Err(format!(
+    Err(CommandError::io(format!(
         "Missing semantic resource '{file_name}'. Expected it under the app resource directory or '{}'",
         path_display(&manifest_resources)
-    ))
+    )))
 }
 
 fn build_semantic_runtime(app: &AppHandle) -> CommandResult<SemanticRuntime> {
     let model_path = resolve_semantic_resource_path(app, "model.onnx")?;
     let tokenizer_path = resolve_semantic_resource_path(app, "tokenizer.json")?;
     let tokenizer = Tokenizer::from_file(&tokenizer_path).map_err(|error| {
-        format!(
+        CommandError::io(format!(
             "Could not load tokenizer '{}': {error}",
             path_display(&tokenizer_path)
-        )
+        ))
     })?;
     let mut builder = OrtSession::builder().map_err(|error| {
-        format!(
+        CommandError::internal(format!(
             "Could not create ONNX session builder for '{}': {error}",
             path_display(&model_path)
-        )
+        ))
     })?;
     if let Ok(parallelism) = std::thread::available_parallelism() {
         let threads = parallelism.get().clamp(1, 8);
-        builder = builder
-            .with_intra_threads(threads)
-            .map_err(|error| format!("Could not set ONNX thread count: {error}"))?;
+        builder = builder.with_intra_threads(threads).map_err(|error| {
+            CommandError::internal(format!("Could not set ONNX thread count: {error}"))
+        })?;
     }
     let session = builder.commit_from_file(&model_path).map_err(|error| {
-        format!(
+        CommandError::io(format!(
             "Could not load ONNX model '{}': {error}",
             path_display(&model_path)
-        )
+        ))
     })?;
     let output_name = session
         .outputs()
         .first()
         .map(|entry| entry.name().to_string())
-        .ok_or_else(|| "ONNX model has no outputs".to_string())?;
+        .ok_or_else(|| CommandError::internal("ONNX model has no outputs"))?;
 
     Ok(SemanticRuntime {
         tokenizer,
@@ -201,7 +201,7 @@ fn load_semantic_runtime(app: &AppHandle) -> CommandResult<&'static Mutex<Semant
     let _ = SEMANTIC_RUNTIME.set(Mutex::new(runtime));
     SEMANTIC_RUNTIME
         .get()
-        .ok_or_else(|| "Could not initialize semantic runtime".to_string())
+        .ok_or_else(|| CommandError::internal("Could not initialize semantic runtime"))
 }
 
 fn semantic_root_fingerprint_ms(connection: &rusqlite::Connection) -> CommandResult<i64> {
@@ -211,7 +211,11 @@ fn semantic_root_fingerprint_ms(connection: &rusqlite::Connection) -> CommandRes
             [],
             |row| row.get::<_, i64>(0),
         )
-        .map_err(|error| format!("Could not read semantic index fingerprint: {error}"))
+        .map_err(|error| {
+            CommandError::database(format!(
+                "Could not read semantic index fingerprint: {error}"
+            ))
+        })
 }
 
 fn read_semantic_meta(app: &AppHandle) -> CommandResult<SemanticIndexMeta> {
@@ -220,28 +224,29 @@ fn read_semantic_meta(app: &AppHandle) -> CommandResult<SemanticIndexMeta> {
         return Ok(SemanticIndexMeta::default());
     }
     let raw = fs::read_to_string(&path).map_err(|error| {
-        format!(
+        CommandError::io(format!(
             "Could not read semantic metadata '{}': {error}",
             path_display(&path)
-        )
+        ))
     })?;
     serde_json::from_str::<SemanticIndexMeta>(&raw).map_err(|error| {
-        format!(
+        CommandError::parse(format!(
             "Could not parse semantic metadata '{}': {error}",
             path_display(&path)
-        )
+        ))
     })
 }
 
 fn write_semantic_meta(app: &AppHandle, meta: &SemanticIndexMeta) -> CommandResult<()> {
     let path = semantic_meta_path(app)?;
-    let raw = serde_json::to_vec_pretty(meta)
-        .map_err(|error| format!("Could not serialize semantic metadata: {error}"))?;
+    let raw = serde_json::to_vec_pretty(meta).map_err(|error| {
+        CommandError::parse(format!("Could not serialize semantic metadata: {error}"))
+    })?;
     fs::write(&path, raw).map_err(|error| {
-        format!(
+        CommandError::io(format!(
             "Could not write semantic metadata '{}': {error}",
             path_display(&path)
-        )
+        ))
     })
 }
 
@@ -295,7 +300,9 @@ fn load_semantic_candidates(
                 ",
             )
             .map_err(|error| {
-                format!("Could not prepare semantic chunk candidates query: {error}")
+                CommandError::database(format!(
+                    "Could not prepare semantic chunk candidates query: {error}"
+                ))
             })?;
 
         let rows = statement
@@ -313,7 +320,11 @@ fn load_semantic_candidates(
                     row.get::<_, String>(9)?,
                 ))
             })
-            .map_err(|error| format!("Could not run semantic chunk candidates query: {error}"))?;
+            .map_err(|error| {
+                CommandError::database(format!(
+                    "Could not run semantic chunk candidates query: {error}"
+                ))
+            })?;
 
         for row in rows {
             if candidates.len() >= max_documents {
@@ -330,8 +341,9 @@ fn load_semantic_candidates(
                 heading_order,
                 author_text,
                 chunk_text,
-            ) =
-                row.map_err(|error| format!("Could not parse semantic chunk candidate: {error}"))?;
+            ) = row.map_err(|error| {
+                CommandError::database(format!("Could not parse semantic chunk candidate: {error}"))
+            })?;
 
             let trimmed_chunk = chunk_text.trim();
             if trimmed_chunk.is_empty() {
@@ -384,7 +396,11 @@ fn load_semantic_candidates(
             LIMIT ?1
             ",
         )
-        .map_err(|error| format!("Could not prepare semantic fallback file query: {error}"))?;
+        .map_err(|error| {
+            CommandError::database(format!(
+                "Could not prepare semantic fallback file query: {error}"
+            ))
+        })?;
     let rows = statement
         .query_map(params![max_documents_i64], |row| {
             Ok((
@@ -394,13 +410,20 @@ fn load_semantic_candidates(
                 row.get::<_, String>(3)?,
             ))
         })
-        .map_err(|error| format!("Could not run semantic fallback file query: {error}"))?;
+        .map_err(|error| {
+            CommandError::database(format!(
+                "Could not run semantic fallback file query: {error}"
+            ))
+        })?;
     for row in rows {
         if candidates.len() >= max_documents {
             break;
         }
-        let (root_id, file_id, relative_path, absolute_path) =
-            row.map_err(|error| format!("Could not parse semantic fallback file row: {error}"))?;
+        let (root_id, file_id, relative_path, absolute_path) = row.map_err(|error| {
+            CommandError::database(format!(
+                "Could not parse semantic fallback file row: {error}"
+            ))
+        })?;
         let file_name = file_name_from_relative(&relative_path);
         let semantic_text =
             semantic_embedding_text(&format!("file: {}\npath: {}", file_name, relative_path));
@@ -451,9 +474,9 @@ fn encode_semantic_batch(
     let mut token_type_ids = Vec::with_capacity(batch_size.saturating_mul(seq_len));
 
     for text in texts {
-        let encoding = tokenizer
-            .encode(text.as_str(), true)
-            .map_err(|error| format!("Could not tokenize semantic input: {error}"))?;
+        let encoding = tokenizer.encode(text.as_str(), true).map_err(|error| {
+            CommandError::parse(format!("Could not tokenize semantic input: {error}"))
+        })?;
         let mut ids = encoding
             .get_ids()
             .iter()
@@ -513,7 +536,7 @@ pub(crate) fn embed_semantic_texts(
     let runtime = load_semantic_runtime(app)?;
     let mut runtime = runtime
         .lock()
-        .map_err(|_| "Could not lock semantic runtime".to_string())?;
+        .map_err(|_| CommandError::internal("Could not lock semantic runtime"))?;
     let output_name = runtime.output_name.clone();
     let expects_token_type_ids = runtime
         .session
@@ -531,13 +554,25 @@ pub(crate) fn embed_semantic_texts(
         i64::try_from(batch_size).unwrap_or(0),
         i64::try_from(seq_len).unwrap_or(0),
     ];
-    let primary_input_ids = OrtTensor::from_array((shape.clone(), input_ids.clone()))
-        .map_err(|error| format!("Could not create semantic input_ids tensor: {error}"))?;
+    let primary_input_ids =
+        OrtTensor::from_array((shape.clone(), input_ids.clone())).map_err(|error| {
+            CommandError::internal(format!(
+                "Could not create semantic input_ids tensor: {error}"
+            ))
+        })?;
     let primary_attention_mask = OrtTensor::from_array((shape.clone(), attention_mask.clone()))
-        .map_err(|error| format!("Could not create semantic attention_mask tensor: {error}"))?;
+        .map_err(|error| {
+            CommandError::internal(format!(
+                "Could not create semantic attention_mask tensor: {error}"
+            ))
+        })?;
     let outputs = if expects_token_type_ids {
         let primary_token_type_ids = OrtTensor::from_array((shape.clone(), token_type_ids.clone()))
-            .map_err(|error| format!("Could not create semantic token_type_ids tensor: {error}"))?;
+            .map_err(|error| {
+                CommandError::internal(format!(
+                    "Could not create semantic token_type_ids tensor: {error}"
+                ))
+            })?;
         runtime.session.run(ort::inputs! {
             "input_ids" => primary_input_ids,
             "attention_mask" => primary_attention_mask,
@@ -549,29 +584,31 @@ pub(crate) fn embed_semantic_texts(
             "attention_mask" => primary_attention_mask
         })
     }
-    .map_err(|error| format!("Semantic model inference failed: {error}"))?;
+    .map_err(|error| CommandError::internal(format!("Semantic model inference failed: {error}")))?;
 
     let output = if outputs.contains_key(output_name.as_str()) {
         &outputs[output_name.as_str()]
     } else {
         &outputs[0]
     };
-    let output = output
-        .try_extract_array::<f32>()
-        .map_err(|error| format!("Could not extract semantic output tensor: {error}"))?;
+    let output = output.try_extract_array::<f32>().map_err(|error| {
+        CommandError::internal(format!("Could not extract semantic output tensor: {error}"))
+    })?;
 
     if output.ndim() != 3 {
-        return Err(format!(
+        return Err(CommandError::internal(format!(
             "Semantic model output rank {} is unsupported (expected 3)",
             output.ndim()
-        ));
+        )));
     }
     let output_shape = output.shape();
     let output_batch = output_shape[0];
     let output_seq = output_shape[1];
     let embedding_dim = output_shape[2];
     if output_batch != batch_size || embedding_dim == 0 {
-        return Err("Semantic model output shape does not match request".to_string());
+        return Err(CommandError::internal(
+            "Semantic model output shape does not match request",
+        ));
     }
 
     let mut vectors = Vec::with_capacity(batch_size);
@@ -636,7 +673,9 @@ fn semantic_record_batch(
     embedding_dim: usize,
 ) -> CommandResult<RecordBatch> {
     if candidates.len() != embeddings.len() {
-        return Err("Semantic candidate/embedding batch size mismatch".to_string());
+        return Err(CommandError::internal(
+            "Semantic candidate/embedding batch size mismatch",
+        ));
     }
     let semantic_ids =
         Int64Array::from_iter_values(candidates.iter().map(|candidate| candidate.semantic_id));
@@ -709,7 +748,9 @@ fn semantic_record_batch(
             Arc::new(vectors),
         ],
     )
-    .map_err(|error| format!("Could not build semantic record batch: {error}"))
+    .map_err(|error| {
+        CommandError::internal(format!("Could not build semantic record batch: {error}"))
+    })
 }
 
 async fn rebuild_semantic_index(app: AppHandle, force: bool) -> CommandResult<()> {
@@ -753,7 +794,9 @@ async fn rebuild_semantic_index(app: AppHandle, force: bool) -> CommandResult<()
             embed_semantic_texts(&app_for_embedding, &texts)
         })
         .await
-        .map_err(|error| format!("Semantic embedding task failed: {error}"))??;
+        .map_err(|error| {
+            CommandError::internal(format!("Semantic embedding task failed: {error}"))
+        })??;
         if embeddings.is_empty() {
             continue;
         }
@@ -771,7 +814,7 @@ async fn rebuild_semantic_index(app: AppHandle, force: bool) -> CommandResult<()
         let batch = semantic_record_batch(
             schema
                 .clone()
-                .ok_or_else(|| "Semantic schema was not initialized".to_string())?,
+                .ok_or_else(|| CommandError::database("Semantic schema was not initialized"))?,
             chunk,
             &embeddings,
             embedding_dim,
@@ -785,25 +828,26 @@ async fn rebuild_semantic_index(app: AppHandle, force: bool) -> CommandResult<()
 
     let semantic_dir = semantic_db_dir(&app)?;
     fs::create_dir_all(&semantic_dir).map_err(|error| {
-        format!(
+        CommandError::io(format!(
             "Could not create semantic DB directory '{}': {error}",
             path_display(&semantic_dir)
-        )
+        ))
     })?;
     let uri = path_display(&semantic_dir);
-    let db = connect_lancedb(&uri)
-        .execute()
-        .await
-        .map_err(|error| format!("Could not open LanceDB at '{}': {error}", uri))?;
+    let db = connect_lancedb(&uri).execute().await.map_err(|error| {
+        CommandError::database(format!("Could not open LanceDB at '{}': {error}", uri))
+    })?;
 
-    let schema = schema.ok_or_else(|| "Semantic schema was not created".to_string())?;
+    let schema = schema.ok_or_else(|| CommandError::database("Semantic schema was not created"))?;
     let reader = RecordBatchIterator::new(batches.into_iter().map(Ok), schema.clone());
     let table = db
         .create_table(SEMANTIC_TABLE_NAME, Box::new(reader))
         .mode(CreateTableMode::Overwrite)
         .execute()
         .await
-        .map_err(|error| format!("Could not write semantic LanceDB table: {error}"))?;
+        .map_err(|error| {
+            CommandError::database(format!("Could not write semantic LanceDB table: {error}"))
+        })?;
 
     if candidates.len() >= SEMANTIC_INDEX_MIN_ROWS {
         let vector_index = LanceIndex::IvfHnswSq(
@@ -820,7 +864,9 @@ async fn rebuild_semantic_index(app: AppHandle, force: bool) -> CommandResult<()
             .create_index(&["vector"], vector_index)
             .execute()
             .await
-            .map_err(|error| format!("Could not create semantic vector index: {error}"))?;
+            .map_err(|error| {
+                CommandError::database(format!("Could not create semantic vector index: {error}"))
+            })?;
     }
 
     let meta = SemanticIndexMeta {
@@ -863,23 +909,31 @@ pub(crate) fn semantic_hits_from_batches(
         let file_id_col = batch
             .column_by_name("file_id")
             .and_then(|column| column.as_any().downcast_ref::<Int64Array>())
-            .ok_or_else(|| "Semantic result batch missing file_id column".to_string())?;
+            .ok_or_else(|| {
+                CommandError::database("Semantic result batch missing file_id column")
+            })?;
         let kind_col = batch
             .column_by_name("kind")
             .and_then(|column| column.as_any().downcast_ref::<StringArray>())
-            .ok_or_else(|| "Semantic result batch missing kind column".to_string())?;
+            .ok_or_else(|| CommandError::database("Semantic result batch missing kind column"))?;
         let file_name_col = batch
             .column_by_name("file_name")
             .and_then(|column| column.as_any().downcast_ref::<StringArray>())
-            .ok_or_else(|| "Semantic result batch missing file_name column".to_string())?;
+            .ok_or_else(|| {
+                CommandError::database("Semantic result batch missing file_name column")
+            })?;
         let relative_path_col = batch
             .column_by_name("relative_path")
             .and_then(|column| column.as_any().downcast_ref::<StringArray>())
-            .ok_or_else(|| "Semantic result batch missing relative_path column".to_string())?;
+            .ok_or_else(|| {
+                CommandError::database("Semantic result batch missing relative_path column")
+            })?;
         let absolute_path_col = batch
             .column_by_name("absolute_path")
             .and_then(|column| column.as_any().downcast_ref::<StringArray>())
-            .ok_or_else(|| "Semantic result batch missing absolute_path column".to_string())?;
+            .ok_or_else(|| {
+                CommandError::database("Semantic result batch missing absolute_path column")
+            })?;
         let heading_level_col = batch
             .column_by_name("heading_level")
             .and_then(|column| column.as_any().downcast_ref::<Int64Array>());
@@ -933,6 +987,9 @@ pub(crate) fn semantic_hits_from_batches(
                 heading_text,
                 heading_order,
                 score: 7000.0 + (distance * 1000.0),
+                snippet: None,
+                word_count: None,
+                underlined_word_count: None,
             });
         }
     }
@@ -955,7 +1012,9 @@ pub(crate) async fn semantic_search(
         embed_semantic_texts(&app_for_embedding, &[query_text])
     })
     .await
-    .map_err(|error| format!("Semantic query embedding task failed: {error}"))??;
+    .map_err(|error| {
+        CommandError::internal(format!("Semantic query embedding task failed: {error}"))
+    })??;
     if query_embedding.is_empty() || query_embedding[0].is_empty() {
         return Ok(Vec::new());
     }
@@ -963,7 +1022,9 @@ pub(crate) async fn semantic_search(
     let mut vector_query = table
         .query()
         .nearest_to(query_embedding[0].as_slice())
-        .map_err(|error| format!("Could not build semantic vector query: {error}"))?
+        .map_err(|error| {
+            CommandError::database(format!("Could not build semantic vector query: {error}"))
+        })?
         .distance_type(DistanceType::Cosine)
         .limit(semantic_fetch_limit(limit))
         .select(Select::columns(&[
@@ -987,10 +1048,14 @@ pub(crate) async fn semantic_search(
     let batches = vector_query
         .execute()
         .await
-        .map_err(|error| format!("Semantic search execution failed: {error}"))?
+        .map_err(|error| {
+            CommandError::database(format!("Semantic search execution failed: {error}"))
+        })?
         .try_collect::<Vec<RecordBatch>>()
         .await
-        .map_err(|error| format!("Semantic search result stream failed: {error}"))?;
+        .map_err(|error| {
+            CommandError::database(format!("Semantic search result stream failed: {error}"))
+        })?;
 
     semantic_hits_from_batches(&batches, limit)
 }
@@ -0,0 +1,139 @@
+use rusqlite::{params, Connection, OptionalExtension};
+
+use crate::errors::CommandError;
+use crate::types::{RootCaptureTarget, SessionState};
+use crate::util::now_ms;
+use crate::CommandResult;
+
+/// Loads the persisted session. References that no longer resolve (a removed
+/// root, a file dropped by a reindex) come back as `None` rather than stale ids.
+pub(crate) fn load_session_state(connection: &Connection) -> CommandResult<SessionState> {
+    let stored = connection
+        .query_row(
+            "
+            SELECT
+              r.path,
+              f.id,
+              f.relative_path,
+              CASE WHEN f.id IS NULL THEN NULL ELSE s.last_heading_order END,
+              s.updated_at_ms
+            FROM session_state s
+            LEFT JOIN roots r ON r.path = s.last_root_path
+            LEFT JOIN files f ON f.id = s.last_file_id AND f.root_id = r.id
+            WHERE s.id = 1
+            ",
+            [],
+            |row| {
+                Ok((
+                    row.get::<_, Option<String>>(0)?,
+                    row.get::<_, Option<i64>>(1)?,
+                    row.get::<_, Option<String>>(2)?,
+                    row.get::<_, Option<i64>>(3)?,
+                    row.get::<_, i64>(4)?,
+                ))
+            },
+        )
+        .optional()
+        .map_err(|error| {
+            CommandError::database(format!("Could not load session state: {error}"))
+        })?;
+
+    let mut statement = connection
+        .prepare(
+            "SELECT path, last_capture_target FROM roots WHERE last_capture_target IS NOT NULL ORDER BY path",
+        )
+        .map_err(|error| {
+            CommandError::database(format!(
+                "Could not prepare capture target session query: {error}"
+            ))
+        })?;
+    let rows = statement
+        .query_map([], |row| {
+            Ok(RootCaptureTarget {
+                root_path: row.get(0)?,
+                target_relative_path: row.get(1)?,
+            })
+        })
+        .map_err(|error| {
+            CommandError::database(format!(
+                "Could not run capture target session query: {error}"
+            ))
+        })?;
+    let mut capture_targets = Vec::new();
+    for row in rows {
+        capture_targets.push(row.map_err(|error| {
+            CommandError::database(format!(
+                "Could not parse capture target session row: {error}"
+            ))
+        })?);
+    }
+
+    let (last_root_path, last_file_id, last_relative_path, last_heading_order, updated_at_ms) =
+        stored.unwrap_or((None, None, None, None, 0));
+    Ok(SessionState {
+        last_root_path,
+        last_file_id,
+        last_relative_path,
+        last_heading_order,
+        capture_targets,
+        updated_at_ms,
+    })
+}
+
+pub(crate) fn store_last_location(
+    connection: &Connection,
+    root_path: Option<&str>,
+    file_id: Option<i64>,
+    heading_order: Option<i64>,
+) -> CommandResult<()> {
+    connection
+        .execute(
+            "
+            INSERT INTO session_state(id, last_root_path, last_file_id, last_heading_order, updated_at_ms)
+            VALUES(1, ?1, ?2, ?3, ?4)
+            ON CONFLICT(id) DO UPDATE SET
+              last_root_path = excluded.last_root_path,
+              last_file_id = excluded.last_file_id,
+              last_heading_order = excluded.last_heading_order,
+              updated_at_ms = excluded.updated_at_ms
+            ",
+            params![root_path, file_id, heading_order, now_ms()],
+        )
+        .map_err(|error| {
+            CommandError::database(format!("Could not store session location: {error}"))
+        })?;
+    Ok(())
+}
+
+pub(crate) fn store_last_capture_target(
+    connection: &Connection,
+    root_id: i64,
+    target_relative_path: &str,
+) -> CommandResult<()> {
+    connection
+        .execute(
+            "UPDATE roots SET last_capture_target = ?1 WHERE id = ?2",
+            params![target_relative_path, root_id],
+        )
+        .map_err(|error| {
+            CommandError::database(format!("Could not store last capture target: {error}"))
+        })?;
+    Ok(())
+}
+
+pub(crate) fn load_last_capture_target(
+    connection: &Connection,
+    root_id: i64,
+) -> CommandResult<Option<String>> {
+    connection
+        .query_row(
+            "SELECT last_capture_target FROM roots WHERE id = ?1",
+            params![root_id],
+            |row| row.get::<_, Option<String>>(0),
+        )
+        .optional()
+        .map(Option::flatten)
+        .map_err(|error| {
+            CommandError::database(format!("Could not load last capture target: {error}"))
+        })
+}
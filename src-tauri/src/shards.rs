@@ -0,0 +1,515 @@
+use std::fs;
+use std::path::PathBuf;
+
+use rusqlite::{params, Connection, OptionalExtension};
+use tauri::AppHandle;
+
+use crate::db::index_meta_dir;
+use crate::errors::CommandError;
+use crate::util::path_display;
+use crate::CommandResult;
+
+const SHARD_DIR_NAME: &str = "shards";
+
+/// Row ids in a root's shard start at `root_id * SHARD_ID_STRIDE`, so ids stay
+/// unique across the main database and every shard.
+const SHARD_ID_STRIDE: i64 = 1 << 40;
+
+/// SQLite's default attach limit; one slot per sharded root.
+pub(crate) const MAX_ROOT_SHARDS: usize = 10;
+
+/// Per-file index tables, with the column order their union views expose.
+const SHARDED_TABLES: &[(&str, &str)] = &[
+    (
+        "files",
+        "id, root_id, relative_path, absolute_path, modified_ms, size, file_hash, heading_count, authors_hash, cites_hash",
+    ),
+    (
+        "headings",
+        "id, file_id, heading_order, level, text, normalized, file_name, relative_path, body_preview, word_count, underlined_word_count",
+    ),
+    (
+        "authors",
+        "id, file_id, author_order, text, normalized, file_name, relative_path, surnames, year",
+    ),
+    (
+        "chunks",
+        "id, chunk_id, root_id, file_id, chunk_order, heading_order, heading_level, heading_text, author_text, chunk_text, file_name, relative_path, absolute_path",
+    ),
+    ("file_text", "file_id, paragraph_count, text_zstd"),
+    (
+        "file_stats",
+        "file_id, word_count, card_count, card_word_count, cite_count",
+    ),
+    (
+        "body_paragraphs",
+        "id, file_id, paragraph_order, heading_order, text",
+    ),
+    ("cites", "id, file_id, paragraph_order, heading_order, text"),
+    (
+        "sync_conflicts",
+        "id, root_id, file_id, original_relative_path, detected_at_ms",
+    ),
+    (
+        "heading_history",
+        "id, file_id, indexed_at_ms, change_kind, level, heading_order, previous_text, text",
+    ),
+    (
+        "file_links",
+        "id, root_id, file_id, paragraph_order, url, link_text",
+    ),
+];
+
+/// Same tables as the main database, minus the foreign keys to `roots`,
+/// which lives in the catalog and cannot be referenced across files.
+const SHARD_SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS {schema}.files (
+      id INTEGER PRIMARY KEY AUTOINCREMENT,
+      root_id INTEGER NOT NULL,
+      relative_path TEXT NOT NULL,
+      absolute_path TEXT NOT NULL,
+      modified_ms INTEGER NOT NULL,
+      size INTEGER NOT NULL,
+      file_hash TEXT NOT NULL DEFAULT '',
+      heading_count INTEGER NOT NULL DEFAULT 0,
+      authors_hash TEXT NOT NULL DEFAULT '',
+      cites_hash TEXT NOT NULL DEFAULT '',
+      UNIQUE(root_id, relative_path)
+    );
+
+    CREATE TABLE IF NOT EXISTS {schema}.headings (
+      id INTEGER PRIMARY KEY AUTOINCREMENT,
+      file_id INTEGER NOT NULL,
+      heading_order INTEGER NOT NULL,
+      level INTEGER NOT NULL,
+      text TEXT NOT NULL,
+      normalized TEXT NOT NULL,
+      file_name TEXT NOT NULL,
+      relative_path TEXT NOT NULL,
+      body_preview TEXT,
+      word_count INTEGER,
+      underlined_word_count INTEGER,
+      FOREIGN KEY(file_id) REFERENCES files(id) ON DELETE CASCADE
+    );
+
+    CREATE TABLE IF NOT EXISTS {schema}.authors (
+      id INTEGER PRIMARY KEY AUTOINCREMENT,
+      file_id INTEGER NOT NULL,
+      author_order INTEGER NOT NULL,
+      text TEXT NOT NULL,
+      normalized TEXT NOT NULL,
+      file_name TEXT NOT NULL,
+      relative_path TEXT NOT NULL,
+      surnames TEXT,
+      year INTEGER,
+      FOREIGN KEY(file_id) REFERENCES files(id) ON DELETE CASCADE
+    );
+
+    CREATE TABLE IF NOT EXISTS {schema}.chunks (
+      id INTEGER PRIMARY KEY AUTOINCREMENT,
+      chunk_id TEXT NOT NULL UNIQUE,
+      root_id INTEGER NOT NULL,
+      file_id INTEGER NOT NULL,
+      chunk_order INTEGER NOT NULL,
+      heading_order INTEGER,
+      heading_level INTEGER,
+      heading_text TEXT,
+      author_text TEXT,
+      chunk_text TEXT NOT NULL,
+      file_name TEXT NOT NULL,
+      relative_path TEXT NOT NULL,
+      absolute_path TEXT NOT NULL,
+      FOREIGN KEY(file_id) REFERENCES files(id) ON DELETE CASCADE
+    );
+
+    CREATE TABLE IF NOT EXISTS {schema}.file_text (
+      file_id INTEGER PRIMARY KEY,
+      paragraph_count INTEGER NOT NULL,
+      text_zstd BLOB NOT NULL,
+      FOREIGN KEY(file_id) REFERENCES files(id) ON DELETE CASCADE
+    );
+
+    CREATE TABLE IF NOT EXISTS {schema}.file_stats (
+      file_id INTEGER PRIMARY KEY,
+      word_count INTEGER NOT NULL,
+      card_count INTEGER NOT NULL,
+      card_word_count INTEGER NOT NULL,
+      cite_count INTEGER NOT NULL,
+      FOREIGN KEY(file_id) REFERENCES files(id) ON DELETE CASCADE
+    );
+
+    CREATE TABLE IF NOT EXISTS {schema}.body_paragraphs (
+      id INTEGER PRIMARY KEY AUTOINCREMENT,
+      file_id INTEGER NOT NULL,
+      paragraph_order INTEGER NOT NULL,
+      heading_order INTEGER,
+      text TEXT NOT NULL,
+      FOREIGN KEY(file_id) REFERENCES files(id) ON DELETE CASCADE
+    );
+
+    CREATE VIRTUAL TABLE IF NOT EXISTS {schema}.body_fts USING fts5(
+      text,
+      content = 'body_paragraphs',
+      content_rowid = 'id',
+      tokenize = 'unicode61 remove_diacritics 2'
+    );
+
+    CREATE TRIGGER IF NOT EXISTS {schema}.body_paragraphs_insert AFTER INSERT ON body_paragraphs BEGIN
+      INSERT INTO body_fts(rowid, text) VALUES(new.id, new.text);
+    END;
+
+    CREATE TRIGGER IF NOT EXISTS {schema}.body_paragraphs_delete AFTER DELETE ON body_paragraphs BEGIN
+      INSERT INTO body_fts(body_fts, rowid, text) VALUES('delete', old.id, old.text);
+    END;
+
+    CREATE TABLE IF NOT EXISTS {schema}.cites (
+      id INTEGER PRIMARY KEY AUTOINCREMENT,
+      file_id INTEGER NOT NULL,
+      paragraph_order INTEGER NOT NULL,
+      heading_order INTEGER,
+      text TEXT NOT NULL,
+      FOREIGN KEY(file_id) REFERENCES files(id) ON DELETE CASCADE
+    );
+
+    CREATE VIRTUAL TABLE IF NOT EXISTS {schema}.cites_fts USING fts5(
+      text,
+      content = 'cites',
+      content_rowid = 'id',
+      tokenize = 'unicode61 remove_diacritics 2'
+    );
+
+    CREATE TRIGGER IF NOT EXISTS {schema}.cites_insert AFTER INSERT ON cites BEGIN
+      INSERT INTO cites_fts(rowid, text) VALUES(new.id, new.text);
+    END;
+
+    CREATE TRIGGER IF NOT EXISTS {schema}.cites_delete AFTER DELETE ON cites BEGIN
+      INSERT INTO cites_fts(cites_fts, rowid, text) VALUES('delete', old.id, old.text);
+    END;
+
+    CREATE TABLE IF NOT EXISTS {schema}.sync_conflicts (
+      id INTEGER PRIMARY KEY AUTOINCREMENT,
+      root_id INTEGER NOT NULL,
+      file_id INTEGER NOT NULL UNIQUE,
+      original_relative_path TEXT NOT NULL,
+      detected_at_ms INTEGER NOT NULL,
+      FOREIGN KEY(file_id) REFERENCES files(id) ON DELETE CASCADE
+    );
+
+    CREATE TABLE IF NOT EXISTS {schema}.heading_history (
+      id INTEGER PRIMARY KEY AUTOINCREMENT,
+      file_id INTEGER NOT NULL,
+      indexed_at_ms INTEGER NOT NULL,
+      change_kind TEXT NOT NULL,
+      level INTEGER NOT NULL,
+      heading_order INTEGER NOT NULL,
+      previous_text TEXT,
+      text TEXT,
+      FOREIGN KEY(file_id) REFERENCES files(id) ON DELETE CASCADE
+    );
+
+    CREATE TABLE IF NOT EXISTS {schema}.file_links (
+      id INTEGER PRIMARY KEY AUTOINCREMENT,
+      root_id INTEGER NOT NULL,
+      file_id INTEGER NOT NULL,
+      paragraph_order INTEGER NOT NULL,
+      url TEXT NOT NULL,
+      link_text TEXT NOT NULL DEFAULT '',
+      FOREIGN KEY(file_id) REFERENCES files(id) ON DELETE CASCADE
+    );
+
+    CREATE INDEX IF NOT EXISTS {schema}.idx_files_root_relative ON files(root_id, relative_path);
+    CREATE INDEX IF NOT EXISTS {schema}.idx_files_root_modified ON files(root_id, modified_ms DESC, id DESC);
+    CREATE INDEX IF NOT EXISTS {schema}.idx_files_relative_length ON files(length(relative_path));
+    CREATE INDEX IF NOT EXISTS {schema}.idx_headings_file ON headings(file_id);
+    CREATE INDEX IF NOT EXISTS {schema}.idx_headings_file_order ON headings(file_id, heading_order);
+    CREATE INDEX IF NOT EXISTS {schema}.idx_headings_normalized_length ON headings(length(normalized));
+    CREATE INDEX IF NOT EXISTS {schema}.idx_authors_file ON authors(file_id);
+    CREATE INDEX IF NOT EXISTS {schema}.idx_authors_file_order ON authors(file_id, author_order);
+    CREATE INDEX IF NOT EXISTS {schema}.idx_authors_normalized_length ON authors(length(normalized));
+    CREATE INDEX IF NOT EXISTS {schema}.idx_chunks_file_order ON chunks(file_id, chunk_order);
+    CREATE INDEX IF NOT EXISTS {schema}.idx_chunks_root_file ON chunks(root_id, file_id);
+    CREATE INDEX IF NOT EXISTS {schema}.idx_chunks_root_file_order ON chunks(root_id, file_id, chunk_order);
+    CREATE INDEX IF NOT EXISTS {schema}.idx_body_paragraphs_file ON body_paragraphs(file_id, paragraph_order);
+    CREATE INDEX IF NOT EXISTS {schema}.idx_cites_file ON cites(file_id, paragraph_order);
+    CREATE INDEX IF NOT EXISTS {schema}.idx_heading_history_file ON heading_history(file_id, indexed_at_ms DESC);
+    CREATE INDEX IF NOT EXISTS {schema}.idx_sync_conflicts_root ON sync_conflicts(root_id, original_relative_path);
+    CREATE INDEX IF NOT EXISTS {schema}.idx_file_links_root_url ON file_links(root_id, url);
+    CREATE INDEX IF NOT EXISTS {schema}.idx_file_links_file ON file_links(file_id, paragraph_order);
+";
+
+/// Columns added to per-file tables since sharding, as `(table, column,
+/// definition)`, for shards created before them.
+const SHARD_COLUMN_MIGRATIONS: &[(&str, &str, &str)] = &[
+    ("headings", "body_preview", "TEXT"),
+    ("files", "cites_hash", "TEXT NOT NULL DEFAULT ''"),
+    ("authors", "surnames", "TEXT"),
+    ("authors", "year", "INTEGER"),
+    ("headings", "word_count", "INTEGER"),
+    ("headings", "underlined_word_count", "INTEGER"),
+];
+
+fn shard_schema_name(root_id: i64) -> String {
+    format!("shard_{root_id}")
+}
+
+pub(crate) fn shard_path(app: &AppHandle, root_id: i64) -> CommandResult<PathBuf> {
+    Ok(index_meta_dir(app)?
+        .join(SHARD_DIR_NAME)
+        .join(format!("root-{root_id}.sqlite3")))
+}
+
+fn is_attached(connection: &Connection, schema: &str) -> CommandResult<bool> {
+    connection
+        .query_row(
+            "SELECT EXISTS(SELECT 1 FROM pragma_database_list WHERE name = ?1)",
+            params![schema],
+            |row| row.get::<_, i64>(0),
+        )
+        .map(|exists| exists != 0)
+        .map_err(|error| {
+            CommandError::database(format!("Could not inspect attached databases: {error}"))
+        })
+}
+
+fn attach_shard(app: &AppHandle, connection: &Connection, root_id: i64) -> CommandResult<()> {
+    let path = shard_path(app, root_id)?;
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).map_err(|error| {
+            CommandError::io(format!(
+                "Could not create shard dir '{}': {error}",
+                path_display(parent)
+            ))
+        })?;
+    }
+
+    let schema = shard_schema_name(root_id);
+    connection
+        .execute(
+            &format!("ATTACH DATABASE ?1 AS {schema}"),
+            params![path_display(&path)],
+        )
+        .map_err(|error| {
+            CommandError::database(format!(
+                "Could not attach index shard '{}': {error}",
+                path_display(&path)
+            ))
+        })?;
+
+    let prepared = connection
+        .query_row(&format!("PRAGMA {schema}.journal_mode = WAL"), [], |row| {
+            row.get::<_, String>(0)
+        })
+        .map_err(|error| {
+            CommandError::database(format!("Could not set shard journal mode: {error}"))
+        })
+        .and_then(|_| {
+            connection
+                .execute_batch(&SHARD_SCHEMA.replace("{schema}", &schema))
+                .map_err(|error| {
+                    CommandError::database(format!("Could not initialize index shard: {error}"))
+                })
+        })
+        .and_then(|_| migrate_shard_columns(connection, &schema))
+        .and_then(|_| seed_shard_ids(connection, &schema, root_id));
+
+    if let Err(error) = prepared {
+        let _ = connection.execute(&format!("DETACH DATABASE {schema}"), []);
+        return Err(CommandError::database(format!(
+            "{error} ('{}')",
+            path_display(&path)
+        )));
+    }
+    Ok(())
+}
+
+fn migrate_shard_columns(connection: &Connection, schema: &str) -> CommandResult<()> {
+    for (table, column, definition) in SHARD_COLUMN_MIGRATIONS {
+        let exists = connection
+            .query_row(
+                "SELECT EXISTS(SELECT 1 FROM pragma_table_info(?1, ?2) WHERE name = ?3)",
+                params![table, schema, column],
+                |row| row.get::<_, i64>(0),
+            )
+            .map_err(|error| {
+                CommandError::database(format!("Could not inspect shard table '{table}': {error}"))
+            })?;
+        if exists == 0 {
+            connection
+                .execute(
+                    &format!("ALTER TABLE {schema}.{table} ADD COLUMN {column} {definition}"),
+                    [],
+                )
+                .map_err(|error| {
+                    CommandError::database(format!(
+                        "Could not add shard column {table}.{column}: {error}"
+                    ))
+                })?;
+        }
+    }
+    Ok(())
+}
+
+/// Starts every AUTOINCREMENT table of a new shard at the root's id range.
+fn seed_shard_ids(connection: &Connection, schema: &str, root_id: i64) -> CommandResult<()> {
+    let base = root_id.saturating_mul(SHARD_ID_STRIDE);
+    for (table, _) in SHARDED_TABLES {
+        // Keyed by file id, so there is no sequence to seed.
+        if matches!(*table, "file_text" | "file_stats") {
+            continue;
+        }
+        connection
+            .execute(
+                &format!(
+                    "INSERT INTO {schema}.sqlite_sequence(name, seq)
+                     SELECT ?1, ?2
+                     WHERE NOT EXISTS(SELECT 1 FROM {schema}.sqlite_sequence WHERE name = ?1)"
+                ),
+                params![table, base],
+            )
+            .map_err(|error| {
+                CommandError::database(format!("Could not seed shard ids for '{table}': {error}"))
+            })?;
+    }
+    Ok(())
+}
+
+/// Attaches the shard of every sharded root and shadows the per-file tables
+/// with temporary views over the main database and all shards, so readers
+/// keep querying `files`, `headings`, etc. unchanged. A shard that cannot be
+/// opened is skipped; its root reads as empty until it is rebuilt.
+pub(crate) fn attach_root_shards(app: &AppHandle, connection: &Connection) -> CommandResult<()> {
+    let mut statement = connection
+        .prepare("SELECT id FROM roots WHERE index_shard = 1 ORDER BY id")
+        .map_err(|error| {
+            CommandError::database(format!("Could not prepare sharded roots query: {error}"))
+        })?;
+    let rows = statement
+        .query_map([], |row| row.get::<_, i64>(0))
+        .map_err(|error| {
+            CommandError::database(format!("Could not query sharded roots: {error}"))
+        })?;
+    let mut root_ids = Vec::new();
+    for row in rows {
+        root_ids.push(row.map_err(|error| {
+            CommandError::database(format!("Could not parse sharded root row: {error}"))
+        })?);
+    }
+
+    let mut schemas = Vec::new();
+    for root_id in root_ids {
+        match attach_shard(app, connection, root_id) {
+            Ok(()) => schemas.push(shard_schema_name(root_id)),
+            Err(error) => eprintln!("Index shard for root {root_id} is unavailable: {error}"),
+        }
+    }
+    if schemas.is_empty() {
+        return Ok(());
+    }
+
+    let mut views = String::new();
+    for (table, columns) in SHARDED_TABLES {
+        views.push_str(&format!(
+            "CREATE TEMP VIEW {table} AS SELECT {columns} FROM main.{table}"
+        ));
+        for schema in &schemas {
+            views.push_str(&format!(
+                " UNION ALL SELECT {columns} FROM {schema}.{table}"
+            ));
+        }
+        views.push_str(";\n");
+    }
+    connection
+        .execute_batch(&views)
+        .map_err(|error| CommandError::database(format!("Could not create shard views: {error}")))
+}
+
+pub(crate) fn root_is_sharded(connection: &Connection, root_id: i64) -> CommandResult<bool> {
+    connection
+        .query_row(
+            "SELECT index_shard FROM roots WHERE id = ?1",
+            params![root_id],
+            |row| row.get::<_, i64>(0),
+        )
+        .optional()
+        .map(|value| value == Some(1))
+        .map_err(|error| {
+            CommandError::database(format!("Could not load root shard setting: {error}"))
+        })
+}
+
+/// The schema holding a root's per-file rows: `main`, or the root's shard.
+/// Writes to per-file tables must name it, since the unqualified names are
+/// read-only views while any shard is attached.
+pub(crate) fn schema_for_root(connection: &Connection, root_id: i64) -> CommandResult<String> {
+    if !root_is_sharded(connection, root_id)? {
+        return Ok("main".to_string());
+    }
+    let schema = shard_schema_name(root_id);
+    if !is_attached(connection, &schema)? {
+        return Err(CommandError::not_indexed(format!(
+            "Index shard for root {root_id} is unavailable; rebuild the root's index"
+        )));
+    }
+    Ok(schema)
+}
+
+/// The schema holding a file's rows, read from the id range it was given.
+pub(crate) fn schema_for_file(file_id: i64) -> String {
+    if file_id >= SHARD_ID_STRIDE {
+        shard_schema_name(file_id / SHARD_ID_STRIDE)
+    } else {
+        "main".to_string()
+    }
+}
+
+/// `main` plus every attached shard.
+pub(crate) fn index_schemas(connection: &Connection) -> CommandResult<Vec<String>> {
+    let mut statement = connection
+        .prepare("SELECT name FROM pragma_database_list WHERE name = 'main' OR name LIKE 'shard\\_%' ESCAPE '\\' ORDER BY seq")
+        .map_err(|error| {
+            CommandError::database(format!("Could not prepare attached databases query: {error}"))
+        })?;
+    let rows = statement
+        .query_map([], |row| row.get::<_, String>(0))
+        .map_err(|error| {
+            CommandError::database(format!("Could not list attached databases: {error}"))
+        })?;
+    let mut schemas = Vec::new();
+    for row in rows {
+        schemas.push(row.map_err(|error| {
+            CommandError::database(format!("Could not parse attached database: {error}"))
+        })?);
+    }
+    Ok(schemas)
+}
+
+pub(crate) fn sharded_root_count(connection: &Connection) -> CommandResult<usize> {
+    connection
+        .query_row(
+            "SELECT COUNT(*) FROM roots WHERE index_shard = 1",
+            [],
+            |row| row.get::<_, i64>(0),
+        )
+        .map(|count| usize::try_from(count).unwrap_or(0))
+        .map_err(|error| CommandError::database(format!("Could not count sharded roots: {error}")))
+}
+
+/// Deletes a root's shard file with its WAL and shared-memory files. The
+/// caller must have dropped connections that attached it.
+pub(crate) fn remove_shard_files(app: &AppHandle, root_id: i64) -> CommandResult<()> {
+    let path = shard_path(app, root_id)?;
+    for suffix in ["", "-wal", "-shm"] {
+        let file = PathBuf::from(format!("{}{suffix}", path_display(&path)));
+        match fs::remove_file(&file) {
+            Ok(()) => {}
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => {}
+            Err(error) => {
+                return Err(CommandError::io(format!(
+                    "Could not remove index shard '{}': {error}",
+                    path_display(&file)
+                )))
+            }
+        }
+    }
+    Ok(())
+}
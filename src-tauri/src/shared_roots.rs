@@ -0,0 +1,374 @@
+use std::fs;
+use std::path::Path;
+
+use rusqlite::{params, Connection, OptionalExtension};
+
+use crate::errors::CommandError;
+use crate::types::{PathMapping, ResolvedSharedPath};
+use crate::util::{form_encode, now_ms, path_display, query_param, random_token};
+use crate::CommandResult;
+
+/// Lives in the root itself so it syncs along with the documents. Unlike the
+/// index marker it is kept when a root is removed, since the identity belongs
+/// to the folder rather than to this machine's index.
+const ROOT_IDENTITY_FILE: &str = ".blockfile-root.json";
+const PORTABLE_LINK_PREFIX: &str = "blockfile://open?";
+
+/// Compares paths from any OS: separators become `/`, trailing slashes go,
+/// and Windows-looking paths are compared case-insensitively.
+fn portable_key(path: &str) -> String {
+    let normalized = path.trim().replace('\\', "/");
+    let normalized = normalized.trim_end_matches('/');
+    let is_windows = normalized.as_bytes().get(1) == Some(&b':') || path.contains('\\');
+    if is_windows {
+        normalized.to_lowercase()
+    } else {
+        normalized.to_string()
+    }
+}
+
+pub(crate) fn read_root_identity(root: &Path) -> Option<String> {
+    let raw = fs::read_to_string(root.join(ROOT_IDENTITY_FILE)).ok()?;
+    let value = serde_json::from_str::<serde_json::Value>(&raw).ok()?;
+    value
+        .get("rootId")
+        .and_then(|field| field.as_str())
+        .map(str::trim)
+        .filter(|id| !id.is_empty())
+        .map(str::to_string)
+}
+
+/// Returns the root's portable id, creating the identity file on first use,
+/// and records it on the `roots` row so other artifacts can be matched to it.
+pub(crate) fn ensure_root_identity(
+    connection: &Connection,
+    root_id: i64,
+    root: &Path,
+) -> CommandResult<String> {
+    let root_uid = match read_root_identity(root) {
+        Some(existing) => existing,
+        None => {
+            let created = random_token()?[..32].to_string();
+            let identity = serde_json::json!({
+                "version": 1,
+                "rootId": created,
+                "createdAtMs": now_ms(),
+            });
+            let content = serde_json::to_string_pretty(&identity).map_err(|error| {
+                CommandError::parse(format!("Could not serialize root identity JSON: {error}"))
+            })?;
+            let identity_path = root.join(ROOT_IDENTITY_FILE);
+            fs::write(&identity_path, content).map_err(|error| {
+                CommandError::io(format!(
+                    "Could not write root identity '{}': {error}",
+                    path_display(&identity_path)
+                ))
+            })?;
+            created
+        }
+    };
+
+    connection
+        .execute(
+            "UPDATE roots SET root_uid = ?1 WHERE id = ?2",
+            params![root_uid, root_id],
+        )
+        .map_err(|error| {
+            CommandError::database(format!("Could not store root identity: {error}"))
+        })?;
+    Ok(root_uid)
+}
+
+pub(crate) fn list_path_mappings(connection: &Connection) -> CommandResult<Vec<PathMapping>> {
+    let mut statement = connection
+        .prepare(
+            "
+            SELECT m.foreign_root_path, r.path, r.root_uid, m.created_at_ms
+            FROM path_mappings m
+            JOIN roots r ON r.id = m.root_id
+            ORDER BY r.path, m.foreign_root_path
+            ",
+        )
+        .map_err(|error| {
+            CommandError::database(format!("Could not prepare path mapping query: {error}"))
+        })?;
+    let rows = statement
+        .query_map([], |row| {
+            Ok(PathMapping {
+                foreign_root_path: row.get(0)?,
+                root_path: row.get(1)?,
+                root_uid: row.get(2)?,
+                created_at_ms: row.get(3)?,
+            })
+        })
+        .map_err(|error| {
+            CommandError::database(format!("Could not run path mapping query: {error}"))
+        })?;
+    let mut mappings = Vec::new();
+    for row in rows {
+        mappings.push(row.map_err(|error| {
+            CommandError::database(format!("Could not parse path mapping row: {error}"))
+        })?);
+    }
+    Ok(mappings)
+}
+
+pub(crate) fn upsert_path_mapping(
+    connection: &Connection,
+    foreign_root_path: &str,
+    root_id: i64,
+) -> CommandResult<()> {
+    let foreign_key = portable_key(foreign_root_path);
+    if foreign_key.is_empty() {
+        return Err(CommandError::validation(
+            "Enter the root's path on the other machine.",
+        ));
+    }
+    // Replace any spelling of the same path so lookups stay unambiguous.
+    for mapping in list_path_mappings(connection)? {
+        if portable_key(&mapping.foreign_root_path) == foreign_key {
+            delete_path_mapping(connection, &mapping.foreign_root_path)?;
+        }
+    }
+    connection
+        .execute(
+            "INSERT INTO path_mappings(foreign_root_path, root_id, created_at_ms) VALUES(?1, ?2, ?3)",
+            params![foreign_root_path.trim(), root_id, now_ms()],
+        )
+        .map_err(|error| CommandError::database(format!("Could not store path mapping: {error}")))?;
+    Ok(())
+}
+
+pub(crate) fn delete_path_mapping(
+    connection: &Connection,
+    foreign_root_path: &str,
+) -> CommandResult<bool> {
+    let removed = connection
+        .execute(
+            "DELETE FROM path_mappings WHERE foreign_root_path = ?1",
+            params![foreign_root_path],
+        )
+        .map_err(|error| {
+            CommandError::database(format!("Could not remove path mapping: {error}"))
+        })?;
+    Ok(removed > 0)
+}
+
+/// One known spelling of a root: its local path, or its path on another
+/// machine taken from a mapping.
+struct RootPrefix {
+    prefix: String,
+    root_id: i64,
+    root_path: String,
+    root_uid: Option<String>,
+}
+
+fn root_prefixes(connection: &Connection) -> CommandResult<Vec<RootPrefix>> {
+    let mut statement = connection
+        .prepare(
+            "
+            SELECT r.path, r.id, r.path, r.root_uid FROM roots r
+            UNION ALL
+            SELECT m.foreign_root_path, r.id, r.path, r.root_uid
+            FROM path_mappings m
+            JOIN roots r ON r.id = m.root_id
+            ",
+        )
+        .map_err(|error| {
+            CommandError::database(format!("Could not prepare root prefix query: {error}"))
+        })?;
+    let rows = statement
+        .query_map([], |row| {
+            Ok(RootPrefix {
+                prefix: row.get(0)?,
+                root_id: row.get(1)?,
+                root_path: row.get(2)?,
+                root_uid: row.get(3)?,
+            })
+        })
+        .map_err(|error| {
+            CommandError::database(format!("Could not run root prefix query: {error}"))
+        })?;
+    let mut prefixes = Vec::new();
+    for row in rows {
+        prefixes.push(row.map_err(|error| {
+            CommandError::database(format!("Could not parse root prefix row: {error}"))
+        })?);
+    }
+    Ok(prefixes)
+}
+
+fn root_by_identity(
+    connection: &Connection,
+    root_uid: &str,
+) -> CommandResult<Option<(i64, String)>> {
+    connection
+        .query_row(
+            "SELECT id, path FROM roots WHERE root_uid = ?1 ORDER BY id LIMIT 1",
+            params![root_uid],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .optional()
+        .map_err(|error| {
+            CommandError::database(format!("Could not look up root identity: {error}"))
+        })
+}
+
+/// Finds the local root for a root path recorded on another machine. Known
+/// paths and mappings win; failing those, a matching portable id identifies
+/// the root and the mapping is remembered for next time.
+pub(crate) fn resolve_shared_root(
+    connection: &Connection,
+    foreign_root_path: &str,
+    root_uid: Option<&str>,
+) -> CommandResult<Option<(i64, String)>> {
+    let foreign_key = portable_key(foreign_root_path);
+    if let Some(known) = root_prefixes(connection)?
+        .into_iter()
+        .find(|known| portable_key(&known.prefix) == foreign_key)
+    {
+        return Ok(Some((known.root_id, known.root_path)));
+    }
+
+    let Some(root_uid) = root_uid.filter(|uid| !uid.trim().is_empty()) else {
+        return Ok(None);
+    };
+    let matched = root_by_identity(connection, root_uid)?;
+    if let Some((root_id, _)) = &matched {
+        if !foreign_key.is_empty() {
+            upsert_path_mapping(connection, foreign_root_path, *root_id)?;
+        }
+    }
+    Ok(matched)
+}
+
+fn indexed_file_id(
+    connection: &Connection,
+    root_id: i64,
+    relative_path: &str,
+) -> CommandResult<Option<i64>> {
+    connection
+        .query_row(
+            "SELECT id FROM files WHERE root_id = ?1 AND relative_path = ?2",
+            params![root_id, relative_path],
+            |row| row.get(0),
+        )
+        .optional()
+        .map_err(|error| {
+            CommandError::database(format!("Could not look up '{relative_path}': {error}"))
+        })
+}
+
+fn resolved_location(
+    connection: &Connection,
+    root_id: i64,
+    root_path: String,
+    root_uid: Option<String>,
+    relative_path: String,
+    heading_order: Option<i64>,
+) -> CommandResult<ResolvedSharedPath> {
+    let absolute_path = if relative_path.is_empty() {
+        Path::new(&root_path).to_path_buf()
+    } else {
+        Path::new(&root_path).join(&relative_path)
+    };
+    Ok(ResolvedSharedPath {
+        file_id: indexed_file_id(connection, root_id, &relative_path)?,
+        exists: absolute_path.exists(),
+        absolute_path: path_display(&absolute_path),
+        root_path,
+        root_uid,
+        relative_path,
+        heading_order,
+    })
+}
+
+/// Rewrites an absolute path from any machine onto the matching local root,
+/// using the longest known root prefix. Returns `None` when no root matches.
+pub(crate) fn resolve_shared_path(
+    connection: &Connection,
+    foreign_path: &str,
+) -> CommandResult<Option<ResolvedSharedPath>> {
+    let normalized = foreign_path.trim().replace('\\', "/");
+    let path_key = portable_key(foreign_path);
+    let best = root_prefixes(connection)?
+        .into_iter()
+        .filter_map(|known| {
+            let prefix_key = portable_key(&known.prefix);
+            let matches = path_key == prefix_key
+                || path_key
+                    .strip_prefix(&prefix_key)
+                    .is_some_and(|rest| rest.starts_with('/'));
+            matches.then_some((prefix_key.chars().count(), known))
+        })
+        .max_by_key(|(prefix_length, _)| *prefix_length);
+    let Some((prefix_length, known)) = best else {
+        return Ok(None);
+    };
+
+    let relative_path = normalized
+        .trim_end_matches('/')
+        .chars()
+        .skip(prefix_length)
+        .collect::<String>()
+        .trim_start_matches('/')
+        .to_string();
+    resolved_location(
+        connection,
+        known.root_id,
+        known.root_path,
+        known.root_uid,
+        relative_path,
+        None,
+    )
+    .map(Some)
+}
+
+/// Builds a link that names the root by its portable id instead of a local
+/// path, so it opens the same document on every machine sharing the folder.
+pub(crate) fn portable_link(
+    root_uid: &str,
+    relative_path: &str,
+    heading_order: Option<i64>,
+) -> String {
+    let mut link = format!(
+        "{PORTABLE_LINK_PREFIX}root={}&path={}",
+        form_encode(root_uid),
+        form_encode(relative_path)
+    );
+    if let Some(order) = heading_order {
+        link.push_str(&format!("&heading={order}"));
+    }
+    link
+}
+
+pub(crate) fn resolve_portable_link(
+    connection: &Connection,
+    link: &str,
+) -> CommandResult<ResolvedSharedPath> {
+    let query = link
+        .trim()
+        .strip_prefix(PORTABLE_LINK_PREFIX)
+        .ok_or_else(|| {
+            CommandError::validation(format!("'{}' is not a BlockFile link.", link.trim()))
+        })?;
+    let root_uid = query_param(query, "root")
+        .filter(|uid| !uid.is_empty())
+        .ok_or_else(|| CommandError::validation("BlockFile link is missing its root."))?;
+    let relative_path = query_param(query, "path").unwrap_or_default();
+    let heading_order = query_param(query, "heading").and_then(|order| order.parse::<i64>().ok());
+
+    let (root_id, root_path) = root_by_identity(connection, &root_uid)?.ok_or_else(|| {
+        CommandError::not_indexed(
+            "This link points to a root that is not added on this machine. Add the shared folder first.",
+        )
+    })?;
+    resolved_location(
+        connection,
+        root_id,
+        root_path,
+        Some(root_uid),
+        relative_path,
+        heading_order,
+    )
+}
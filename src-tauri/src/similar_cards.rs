@@ -0,0 +1,226 @@
+use std::collections::{HashMap, HashSet};
+
+use rusqlite::{params, Connection, OptionalExtension};
+
+use crate::errors::CommandError;
+use crate::messages;
+use crate::search::normalize_for_search;
+use crate::shards::index_schemas;
+use crate::types::SimilarCard;
+use crate::util::file_name_from_relative;
+use crate::CommandResult;
+
+pub(crate) const DEFAULT_SIMILAR_LIMIT: usize = 10;
+pub(crate) const MAX_SIMILAR_LIMIT: usize = 50;
+const MIN_TERM_CHARS: usize = 3;
+/// The card's most frequent terms go into the full-text query; bm25 weighs
+/// them by rarity across the index.
+const QUERY_TERMS: usize = 32;
+/// Paragraph matches fetched from each index schema.
+const CANDIDATE_PARAGRAPHS: i64 = 600;
+/// Cards re-scored by trigram overlap after the full-text pass.
+const CANDIDATE_CARDS: usize = 200;
+const MIN_SIMILARITY: f64 = 0.05;
+/// Copies of the same card are the near-duplicate report's business, and
+/// would otherwise crowd out the related cards asked for.
+const COPY_SIMILARITY: f64 = 0.95;
+
+struct Card {
+    relative_path: String,
+    absolute_path: String,
+    level: i64,
+    heading_text: String,
+    preview: Option<String>,
+    /// Heading and section text, normalized for comparison.
+    normalized: String,
+}
+
+fn load_card(
+    connection: &Connection,
+    file_id: i64,
+    heading_order: i64,
+) -> CommandResult<Option<Card>> {
+    let mut statement = connection
+        .prepare_cached(
+            "
+            SELECT f.relative_path, f.absolute_path, h.level, h.text, h.body_preview,
+              (SELECT group_concat(text, ' ') FROM (
+                 SELECT b.text FROM body_paragraphs b
+                 WHERE b.file_id = h.file_id AND b.heading_order = h.heading_order
+                 ORDER BY b.paragraph_order
+               ))
+            FROM headings h
+            JOIN files f ON f.id = h.file_id
+            WHERE h.file_id = ?1 AND h.heading_order = ?2
+            ",
+        )
+        .map_err(|error| {
+            CommandError::database(format!("Could not prepare similar card query: {error}"))
+        })?;
+    statement
+        .query_row(params![file_id, heading_order], |row| {
+            let heading_text = row.get::<_, String>(3)?;
+            let body = row.get::<_, Option<String>>(5)?.unwrap_or_default();
+            Ok(Card {
+                relative_path: row.get(0)?,
+                absolute_path: row.get(1)?,
+                level: row.get(2)?,
+                normalized: normalize_for_search(&format!("{heading_text} {body}")),
+                heading_text,
+                preview: row
+                    .get::<_, Option<String>>(4)?
+                    .filter(|preview| !preview.is_empty()),
+            })
+        })
+        .optional()
+        .map_err(|error| CommandError::database(format!("Could not load card text: {error}")))
+}
+
+/// An FTS5 query matching any of the card's most frequent terms. Normalized
+/// text is letters, digits and single spaces, so every term quotes safely.
+fn terms_query(normalized: &str) -> Option<String> {
+    let mut counts = HashMap::<&str, usize>::new();
+    for term in normalized.split_whitespace().filter(|term| {
+        term.chars().count() >= MIN_TERM_CHARS
+            && !term.chars().all(|character| character.is_ascii_digit())
+    }) {
+        *counts.entry(term).or_default() += 1;
+    }
+    let mut terms = counts.into_iter().collect::<Vec<(&str, usize)>>();
+    terms.sort_by(|left, right| {
+        right
+            .1
+            .cmp(&left.1)
+            .then_with(|| right.0.len().cmp(&left.0.len()))
+            .then_with(|| left.0.cmp(right.0))
+    });
+    let quoted = terms
+        .into_iter()
+        .take(QUERY_TERMS)
+        .map(|(term, _)| format!("\"{term}\""))
+        .collect::<Vec<String>>();
+    (!quoted.is_empty()).then(|| quoted.join(" OR "))
+}
+
+/// Cards with paragraphs matching `match_query`, best bm25 first, as
+/// `(file_id, heading_order)`.
+fn candidate_cards(
+    connection: &Connection,
+    match_query: &str,
+    exclude: (i64, i64),
+) -> CommandResult<Vec<(i64, i64)>> {
+    let mut ranked = Vec::new();
+    for schema in index_schemas(connection)? {
+        let mut statement = connection
+            .prepare_cached(&format!(
+                "
+                SELECT b.file_id, b.heading_order, bm25(body_fts)
+                FROM {schema}.body_fts
+                JOIN {schema}.body_paragraphs b ON b.id = body_fts.rowid
+                WHERE body_fts MATCH ?1 AND b.heading_order IS NOT NULL
+                ORDER BY bm25(body_fts)
+                LIMIT ?2
+                "
+            ))
+            .map_err(|error| {
+                CommandError::database(format!("Could not prepare similar card search: {error}"))
+            })?;
+        let rows = statement
+            .query_map(params![match_query, CANDIDATE_PARAGRAPHS], |row| {
+                Ok((
+                    row.get::<_, f64>(2)?,
+                    (row.get::<_, i64>(0)?, row.get::<_, i64>(1)?),
+                ))
+            })
+            .map_err(|error| {
+                CommandError::database(format!("Similar card search failed: {error}"))
+            })?;
+        for row in rows {
+            ranked.push(row.map_err(|error| {
+                CommandError::database(format!("Could not parse similar card row: {error}"))
+            })?);
+        }
+    }
+
+    // bm25() is lower for better matches.
+    ranked.sort_by(|left, right| {
+        left.0
+            .partial_cmp(&right.0)
+            .unwrap_or(std::cmp::Ordering::Equal)
+    });
+    let mut seen = HashSet::from([exclude]);
+    Ok(ranked
+        .into_iter()
+        .map(|(_, card)| card)
+        .filter(|card| seen.insert(*card))
+        .take(CANDIDATE_CARDS)
+        .collect())
+}
+
+pub(crate) fn trigrams(normalized: &str) -> HashSet<[char; 3]> {
+    let chars = normalized.chars().collect::<Vec<char>>();
+    chars
+        .windows(3)
+        .map(|window| [window[0], window[1], window[2]])
+        .collect()
+}
+
+pub(crate) fn jaccard(left: &HashSet<[char; 3]>, right: &HashSet<[char; 3]>) -> f64 {
+    let union = left.union(right).count();
+    if union == 0 {
+        return 0.0;
+    }
+    left.intersection(right).count() as f64 / union as f64
+}
+
+/// Cards across the whole index whose text overlaps the card under
+/// `heading_order`, most similar first. Candidates come from a full-text
+/// search for the card's terms and are ranked by character trigram overlap,
+/// which tolerates the rewording and tags that separate an extension or an
+/// answer from the original.
+pub(crate) fn find_similar_cards(
+    connection: &Connection,
+    file_id: i64,
+    heading_order: i64,
+    limit: usize,
+) -> CommandResult<Vec<SimilarCard>> {
+    let source = load_card(connection, file_id, heading_order)?
+        .ok_or_else(|| CommandError::not_indexed(messages::message("error.headingGone")))?;
+    let Some(match_query) = terms_query(&source.normalized) else {
+        return Ok(Vec::new());
+    };
+    let source_trigrams = trigrams(&source.normalized);
+
+    let mut similar = Vec::new();
+    for (candidate_file_id, candidate_order) in
+        candidate_cards(connection, &match_query, (file_id, heading_order))?
+    {
+        let Some(card) = load_card(connection, candidate_file_id, candidate_order)? else {
+            continue;
+        };
+        let similarity = jaccard(&source_trigrams, &trigrams(&card.normalized));
+        if !(MIN_SIMILARITY..COPY_SIMILARITY).contains(&similarity) {
+            continue;
+        }
+        similar.push(SimilarCard {
+            file_id: candidate_file_id,
+            file_name: file_name_from_relative(&card.relative_path),
+            relative_path: card.relative_path,
+            absolute_path: card.absolute_path,
+            heading_order: candidate_order,
+            heading_level: card.level,
+            heading_text: card.heading_text,
+            similarity,
+            preview: card.preview,
+        });
+    }
+
+    similar.sort_by(|left, right| {
+        right
+            .similarity
+            .partial_cmp(&left.similarity)
+            .unwrap_or(std::cmp::Ordering::Equal)
+    });
+    similar.truncate(limit);
+    Ok(similar)
+}
@@ -0,0 +1,314 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::Path;
+
+use rusqlite::{params, Connection};
+use serde_json::json;
+
+use crate::docx_parse::html_escape;
+use crate::errors::CommandError;
+use crate::operations::OperationHandle;
+use crate::preview::extract_file_preview_html;
+use crate::util::{file_name_from_relative, folder_from_relative, write_export_file};
+use crate::CommandResult;
+
+const ASSET_FOLDER: &str = "_site";
+
+const SITE_STYLE: &str = r#"body { margin: 0; font-family: -apple-system, BlinkMacSystemFont, "Segoe UI", sans-serif; background: #0a0a0a; color: #d4d4d4; }
+main { max-width: 880px; margin: 0 auto; padding: 32px 24px 64px; }
+a { color: #7dd3fc; }
+nav.bf-crumbs { font-size: 0.9rem; margin-bottom: 24px; color: #a3a3a3; }
+ul.bf-listing { list-style: none; padding: 0; }
+ul.bf-listing li { padding: 4px 0; }
+ul.bf-toc { font-size: 0.92rem; border-left: 2px solid #262626; padding-left: 16px; margin-bottom: 40px; }
+ul.bf-toc li { list-style: none; padding: 2px 0; }
+ul.bf-toc .bf-toc-2 { margin-left: 16px; } ul.bf-toc .bf-toc-3 { margin-left: 32px; } ul.bf-toc .bf-toc-4 { margin-left: 48px; }
+input.bf-search { width: 100%; box-sizing: border-box; padding: 10px 12px; font-size: 1rem; background: #171717; color: #f5f5f5; border: 1px solid #404040; border-radius: 6px; }
+ul.bf-results li { padding: 4px 0; }
+ul.bf-results small { color: #737373; margin-left: 8px; }
+.bf-preview-p { margin: 0 0 1.25rem; white-space: pre-wrap; }
+.bf-preview-h1 { font-size: 1.6rem; font-weight: 600; color: #fafafa; }
+.bf-preview-h2 { font-size: 1.32rem; font-weight: 600; color: #f5f5f5; }
+.bf-preview-h3 { font-size: 1.14rem; font-weight: 500; color: #f5f5f5; }
+.bf-preview-h4 { font-size: 1.03rem; font-weight: 500; text-transform: uppercase; letter-spacing: 0.05em; color: #e5e5e5; }
+.bf-run-bold { font-weight: 600; color: #f5f5f5; }
+.bf-run-italic { font-style: italic; }
+.bf-run-underline { text-decoration: underline; text-underline-offset: 2px; }
+.bf-run-smallcaps { font-variant: small-caps; letter-spacing: 0.05em; }
+.bf-run-highlight { border-radius: 3px; padding: 0 2px; }
+.bf-hl-yellow { background: rgba(250, 204, 21, 0.26); }
+.bf-hl-green { background: rgba(74, 222, 128, 0.23); }
+.bf-hl-cyan { background: rgba(103, 232, 249, 0.22); }
+.bf-hl-magenta, .bf-hl-pink { background: rgba(244, 114, 182, 0.24); }
+.bf-hl-blue { background: rgba(96, 165, 250, 0.24); }
+.bf-hl-gray { background: rgba(148, 163, 184, 0.2); }
+.bf-run-shaded { border-radius: 3px; padding: 0 2px; background: color-mix(in srgb, var(--bf-shade) 35%, transparent); }
+.bf-run-colored { color: color-mix(in srgb, var(--bf-color) 70%, #e5e5e5); }
+.bf-preview-shaded { border-radius: 4px; padding: 4px 8px; background: color-mix(in srgb, var(--bf-shade) 22%, transparent); }
+.bf-preview-link { color: #7dd3fc; text-decoration: underline; }
+.bf-preview-table { border-collapse: collapse; margin: 0 0 1.25rem; width: 100%; }
+.bf-preview-table td { border: 1px solid #404040; padding: 6px 10px; vertical-align: top; }
+.bf-preview-table .bf-preview-p { margin: 0; }
+.bf-preview-embedded { border-left: 2px solid #404040; margin: 0 0 1.25rem; padding-left: 1rem; }
+"#;
+
+const SITE_SEARCH_SCRIPT: &str = r#"(function () {
+  var input = document.getElementById("bf-search");
+  var results = document.getElementById("bf-results");
+  var entries = window.BF_SITE_INDEX || [];
+  if (!input || !results) return;
+  input.addEventListener("input", function () {
+    var terms = input.value.toLowerCase().split(/\s+/).filter(Boolean);
+    results.innerHTML = "";
+    if (!terms.length) return;
+    var shown = 0;
+    for (var i = 0; i < entries.length && shown < 200; i++) {
+      var entry = entries[i];
+      var haystack = (entry.text + " " + entry.file).toLowerCase();
+      if (!terms.every(function (term) { return haystack.indexOf(term) !== -1; })) continue;
+      var item = document.createElement("li");
+      var link = document.createElement("a");
+      link.href = entry.href;
+      link.textContent = entry.text;
+      var file = document.createElement("small");
+      file.textContent = entry.file;
+      item.appendChild(link);
+      item.appendChild(file);
+      results.appendChild(item);
+      shown++;
+    }
+  });
+})();
+"#;
+
+#[derive(Default)]
+pub(crate) struct SiteExportCounts {
+    pub page_count: usize,
+    pub folder_count: usize,
+    pub heading_count: usize,
+    pub skipped_files: Vec<String>,
+}
+
+fn encode_href_path(relative_path: &str) -> String {
+    let mut encoded = String::with_capacity(relative_path.len());
+    for byte in relative_path.bytes() {
+        if byte.is_ascii_alphanumeric() || matches!(byte, b'/' | b'-' | b'_' | b'.' | b'~') {
+            encoded.push(byte as char);
+        } else {
+            encoded.push_str(&format!("%{byte:02X}"));
+        }
+    }
+    encoded
+}
+
+fn prefix_for_depth(depth: usize) -> String {
+    "../".repeat(depth)
+}
+
+fn page_shell(title: &str, depth: usize, body: &str, extra_scripts: &[&str]) -> String {
+    let prefix = prefix_for_depth(depth);
+    let mut scripts = String::new();
+    for script in extra_scripts {
+        scripts.push_str(&format!(
+            "<script src=\"{prefix}{ASSET_FOLDER}/{script}\"></script>\n"
+        ));
+    }
+    format!(
+        "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n<meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">\n<title>{}</title>\n<link rel=\"stylesheet\" href=\"{prefix}{ASSET_FOLDER}/style.css\">\n</head>\n<body>\n<main>\n{body}\n</main>\n{scripts}</body>\n</html>\n",
+        html_escape(title)
+    )
+}
+
+/// Breadcrumb links from the site root down to `folder` (exclusive of any file).
+fn breadcrumbs(root_title: &str, folder: &str, depth: usize) -> String {
+    let prefix = prefix_for_depth(depth);
+    let mut crumbs = vec![format!(
+        "<a href=\"{prefix}index.html\">{}</a>",
+        html_escape(root_title)
+    )];
+    let mut current = String::new();
+    for segment in folder.split('/').filter(|segment| !segment.is_empty()) {
+        if !current.is_empty() {
+            current.push('/');
+        }
+        current.push_str(segment);
+        crumbs.push(format!(
+            "<a href=\"{prefix}{}/index.html\">{}</a>",
+            encode_href_path(&current),
+            html_escape(segment)
+        ));
+    }
+    format!("<nav class=\"bf-crumbs\">{}</nav>", crumbs.join(" / "))
+}
+
+fn folder_depth(folder: &str) -> usize {
+    folder
+        .split('/')
+        .filter(|segment| !segment.is_empty())
+        .count()
+}
+
+fn load_site_files(connection: &Connection, root_id: i64) -> CommandResult<Vec<(String, String)>> {
+    let mut statement = connection
+        .prepare(
+            "SELECT relative_path, absolute_path FROM files WHERE root_id = ?1 ORDER BY relative_path",
+        )
+        .map_err(|error| {
+            CommandError::database(format!("Could not prepare site export file query: {error}"))
+        })?;
+    let rows = statement
+        .query_map(params![root_id], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+        })
+        .map_err(|error| {
+            CommandError::database(format!("Could not run site export file query: {error}"))
+        })?;
+    let mut files = Vec::new();
+    for row in rows {
+        files.push(row.map_err(|error| {
+            CommandError::database(format!("Could not parse site export file row: {error}"))
+        })?);
+    }
+    Ok(files)
+}
+
+/// Writes a read-only HTML bundle for a root: one page per indexed file with
+/// its rendered headings, an `index.html` per folder, and a client-side
+/// heading search on the root page. Files that fail to render are listed in
+/// `skipped_files` instead of aborting the export.
+pub(crate) fn write_static_site(
+    connection: &Connection,
+    root_id: i64,
+    root_path: &str,
+    output_dir: &Path,
+    operation: &mut OperationHandle,
+) -> CommandResult<SiteExportCounts> {
+    let files = load_site_files(connection, root_id)?;
+    let root_title = Path::new(root_path)
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_else(|| root_path.to_string());
+
+    let mut folders: BTreeMap<String, (BTreeSet<String>, Vec<String>)> = BTreeMap::new();
+    folders.entry(String::new()).or_default();
+    let mut search_entries = Vec::new();
+    let mut counts = SiteExportCounts::default();
+
+    let total = files.len();
+    for (index, (relative_path, absolute_path)) in files.iter().enumerate() {
+        operation.ensure_not_cancelled()?;
+        operation.update("rendering", index, Some(total), Some(relative_path.clone()));
+
+        let (preview_html, headings) = match extract_file_preview_html(Path::new(absolute_path)) {
+            Ok(rendered) => rendered,
+            Err(_) => {
+                counts.skipped_files.push(relative_path.clone());
+                continue;
+            }
+        };
+
+        let folder = folder_from_relative(relative_path);
+        let mut child = folder.clone();
+        while !child.is_empty() {
+            let parent = folder_from_relative(&child);
+            folders.entry(parent.clone()).or_default().0.insert(child);
+            child = parent;
+        }
+        folders
+            .entry(folder.clone())
+            .or_default()
+            .1
+            .push(relative_path.clone());
+
+        let file_name = file_name_from_relative(relative_path);
+        let page_href = format!("{}.html", encode_href_path(relative_path));
+        let mut toc = String::new();
+        for (order, level, text) in &headings {
+            toc.push_str(&format!(
+                "<li class=\"bf-toc-{}\"><a href=\"#h-{order}\">{}</a></li>",
+                level.clamp(&1, &4),
+                html_escape(text)
+            ));
+            search_entries.push(json!({
+                "text": text,
+                "file": relative_path,
+                "href": format!("{page_href}#h-{order}"),
+            }));
+        }
+        counts.heading_count += headings.len();
+
+        let depth = folder_depth(&folder);
+        let body = format!(
+            "{}\n<h1>{}</h1>\n<ul class=\"bf-toc\">{toc}</ul>\n<article>{preview_html}</article>",
+            breadcrumbs(&root_title, &folder, depth),
+            html_escape(&file_name)
+        );
+        write_export_file(
+            &output_dir.join(format!("{relative_path}.html")),
+            &page_shell(&file_name, depth, &body, &[]),
+        )?;
+        counts.page_count += 1;
+    }
+
+    operation.update("indexes", 0, Some(folders.len()), None);
+    for (folder, (subfolders, folder_files)) in &folders {
+        let depth = folder_depth(folder);
+        let mut listing = String::new();
+        for subfolder in subfolders {
+            let name = file_name_from_relative(subfolder);
+            listing.push_str(&format!(
+                "<li><a href=\"{}/index.html\">{}/</a></li>",
+                encode_href_path(&name),
+                html_escape(&name)
+            ));
+        }
+        for relative_path in folder_files {
+            let name = file_name_from_relative(relative_path);
+            listing.push_str(&format!(
+                "<li><a href=\"{}.html\">{}</a></li>",
+                encode_href_path(&name),
+                html_escape(&name)
+            ));
+        }
+
+        let title = if folder.is_empty() {
+            root_title.clone()
+        } else {
+            file_name_from_relative(folder)
+        };
+        let search = if folder.is_empty() {
+            "<input id=\"bf-search\" class=\"bf-search\" type=\"search\" placeholder=\"Search headings\" autofocus>\n<ul id=\"bf-results\" class=\"bf-results\"></ul>\n"
+        } else {
+            ""
+        };
+        let body = format!(
+            "{}\n<h1>{}</h1>\n{search}<ul class=\"bf-listing\">{listing}</ul>",
+            breadcrumbs(&root_title, folder, depth),
+            html_escape(&title)
+        );
+        let scripts: &[&str] = if folder.is_empty() {
+            &["search-index.js", "search.js"]
+        } else {
+            &[]
+        };
+        write_export_file(
+            &output_dir.join(folder).join("index.html"),
+            &page_shell(&title, depth, &body, scripts),
+        )?;
+        if !folder.is_empty() {
+            counts.folder_count += 1;
+        }
+    }
+
+    let assets = output_dir.join(ASSET_FOLDER);
+    write_export_file(&assets.join("style.css"), SITE_STYLE)?;
+    write_export_file(&assets.join("search.js"), SITE_SEARCH_SCRIPT)?;
+    let index_raw = serde_json::to_string(&search_entries).map_err(|error| {
+        CommandError::parse(format!("Could not serialize site search index: {error}"))
+    })?;
+    write_export_file(
+        &assets.join("search-index.js"),
+        &format!("window.BF_SITE_INDEX = {index_raw};\n"),
+    )?;
+
+    Ok(counts)
+}
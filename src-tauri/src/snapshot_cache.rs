@@ -0,0 +1,64 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex, OnceLock};
+
+use crate::types::IndexSnapshot;
+
+/// Roots whose file browser snapshot is kept; people switch between a few.
+const SNAPSHOT_CACHE_CAPACITY: usize = 8;
+
+#[derive(Default)]
+struct SnapshotCache {
+    order: VecDeque<i64>,
+    entries: HashMap<i64, Arc<IndexSnapshot>>,
+}
+
+static SNAPSHOT_CACHE: OnceLock<Mutex<SnapshotCache>> = OnceLock::new();
+
+fn snapshot_cache() -> &'static Mutex<SnapshotCache> {
+    SNAPSHOT_CACHE.get_or_init(|| Mutex::new(SnapshotCache::default()))
+}
+
+/// The cached snapshot of a root, if it was built from the index run that
+/// finished at `indexed_at_ms`.
+pub(crate) fn cached_snapshot(root_id: i64, indexed_at_ms: i64) -> Option<Arc<IndexSnapshot>> {
+    let mut cache = snapshot_cache().lock().ok()?;
+    let snapshot = cache.entries.get(&root_id)?;
+    if snapshot.indexed_at_ms != indexed_at_ms {
+        return None;
+    }
+    let snapshot = Arc::clone(snapshot);
+    cache.order.retain(|item| *item != root_id);
+    cache.order.push_back(root_id);
+    Some(snapshot)
+}
+
+pub(crate) fn store_snapshot(root_id: i64, snapshot: Arc<IndexSnapshot>) {
+    let Ok(mut cache) = snapshot_cache().lock() else {
+        return;
+    };
+    cache.order.retain(|item| *item != root_id);
+    cache.order.push_back(root_id);
+    cache.entries.insert(root_id, snapshot);
+    while cache.order.len() > SNAPSHOT_CACHE_CAPACITY {
+        if let Some(oldest) = cache.order.pop_front() {
+            cache.entries.remove(&oldest);
+        }
+    }
+}
+
+/// Drops a root's snapshot. Index runs call this before touching file rows,
+/// so a run that fails halfway cannot leave a stale snapshot behind.
+pub(crate) fn invalidate_snapshot(root_id: i64) {
+    if let Ok(mut cache) = snapshot_cache().lock() {
+        cache.order.retain(|item| *item != root_id);
+        cache.entries.remove(&root_id);
+    }
+}
+
+/// Drops every snapshot, for when the index they came from goes away.
+pub(crate) fn clear_snapshots() {
+    if let Ok(mut cache) = snapshot_cache().lock() {
+        cache.order.clear();
+        cache.entries.clear();
+    }
+}
@@ -0,0 +1,538 @@
+use std::collections::HashSet;
+use std::fs;
+use std::path::Path;
+
+use rusqlite::{params, Connection};
+
+use crate::csv_export::parse_csv;
+use crate::errors::CommandError;
+use crate::search::normalize_for_search;
+use crate::types::{ItemTag, SearchHit, TagImportResult, TagImportSkip, TagSummary};
+use crate::util::{file_name_from_relative, now_ms, path_display, wildcard_match};
+use crate::CommandResult;
+
+const PATH_COLUMNS: [&str; 4] = ["path", "relative_path", "file", "folder"];
+const HEADING_COLUMNS: [&str; 3] = ["heading", "pattern", "heading_pattern"];
+const TAG_COLUMNS: [&str; 2] = ["tags", "tag"];
+
+/// Tag-only searches list tagged files and headings at the score of an exact
+/// heading match.
+const TAGGED_SCORE_BASE: f64 = 1_000.0;
+
+/// One row of a tag mapping: a path pattern, a heading pattern, or both (the
+/// heading pattern then only applies inside the matched files).
+pub(crate) struct TagMappingRow {
+    pub row: usize,
+    pub path_pattern: Option<String>,
+    pub heading_pattern: Option<String>,
+    pub tags: Vec<String>,
+}
+
+struct TaggableHeading {
+    relative_path: String,
+    text: String,
+    normalized: String,
+}
+
+fn find_column(header: &[String], names: &[&str]) -> Option<usize> {
+    header.iter().position(|field| {
+        let field = field.trim().to_lowercase().replace([' ', '-'], "_");
+        names.contains(&field.as_str())
+    })
+}
+
+/// A tag with its whitespace collapsed, the form tags are stored in.
+pub(crate) fn clean_tag(tag: &str) -> String {
+    tag.split_whitespace().collect::<Vec<&str>>().join(" ")
+}
+
+/// Tags in one cell may be separated by `;`, `|` or `,`.
+fn split_tags(value: &str) -> Vec<String> {
+    let mut tags = Vec::<String>::new();
+    for tag in value.split([';', '|', ',']) {
+        let tag = clean_tag(tag);
+        if !tag.is_empty() && !tags.iter().any(|known| known.eq_ignore_ascii_case(&tag)) {
+            tags.push(tag);
+        }
+    }
+    tags
+}
+
+pub(crate) fn read_tag_mapping(path: &Path) -> CommandResult<Vec<TagMappingRow>> {
+    let raw = fs::read_to_string(path).map_err(|error| {
+        CommandError::io(format!(
+            "Could not read tag mapping '{}': {error}",
+            path_display(path)
+        ))
+    })?;
+    let mut rows = parse_csv(&raw).into_iter();
+    let header = rows
+        .next()
+        .ok_or_else(|| CommandError::parse("The tag mapping is empty."))?;
+    let path_column = find_column(&header, &PATH_COLUMNS);
+    let heading_column = find_column(&header, &HEADING_COLUMNS);
+    let Some(tag_column) = find_column(&header, &TAG_COLUMNS) else {
+        return Err(
+            CommandError::parse(
+                "This CSV has no tags column (expected a header row with 'path' or 'heading', and 'tags').",
+            )
+        );
+    };
+    if path_column.is_none() && heading_column.is_none() {
+        return Err(
+            CommandError::parse(
+                "This CSV has no path or heading column (expected a header row with 'path' or 'heading', and 'tags').",
+            )
+        );
+    }
+
+    let cell = |fields: &[String], column: Option<usize>| {
+        column
+            .and_then(|index| fields.get(index))
+            .map(|value| value.trim().to_string())
+            .filter(|value| !value.is_empty())
+    };
+    Ok(rows
+        .enumerate()
+        .map(|(index, fields)| TagMappingRow {
+            // Row 1 is the header.
+            row: index + 2,
+            path_pattern: cell(&fields, path_column),
+            heading_pattern: cell(&fields, heading_column),
+            tags: split_tags(&cell(&fields, Some(tag_column)).unwrap_or_default()),
+        })
+        .collect())
+}
+
+/// A path pattern matches a file exactly, every file under it when it names a
+/// folder, or by glob when it contains `*`. Case and slash direction are
+/// ignored.
+fn path_matches(pattern: &str, relative_path: &str) -> bool {
+    let pattern = pattern.replace('\\', "/").trim_matches('/').to_lowercase();
+    let relative_path = relative_path.replace('\\', "/").to_lowercase();
+    if pattern.contains('*') {
+        return wildcard_match(&pattern, &relative_path);
+    }
+    relative_path == pattern || relative_path.starts_with(&format!("{pattern}/"))
+}
+
+/// Heading patterns compare normalized text: a plain pattern matches headings
+/// containing it, a pattern with `*` must match the whole heading.
+fn heading_matches(pattern: &str, normalized_heading: &str) -> bool {
+    if pattern.contains('*') {
+        let pattern = pattern
+            .split('*')
+            .map(normalize_for_search)
+            .collect::<Vec<String>>()
+            .join("*");
+        return wildcard_match(&pattern, normalized_heading);
+    }
+    let pattern = normalize_for_search(pattern);
+    !pattern.is_empty() && normalized_heading.contains(&pattern)
+}
+
+fn load_root_files(connection: &Connection, root_id: i64) -> CommandResult<Vec<String>> {
+    let mut statement = connection
+        .prepare("SELECT relative_path FROM files WHERE root_id = ?1 ORDER BY relative_path")
+        .map_err(|error| {
+            CommandError::database(format!("Could not prepare tag file query: {error}"))
+        })?;
+    let rows = statement
+        .query_map(params![root_id], |row| row.get::<_, String>(0))
+        .map_err(|error| {
+            CommandError::database(format!("Could not run tag file query: {error}"))
+        })?;
+    let mut files = Vec::new();
+    for row in rows {
+        files.push(row.map_err(|error| {
+            CommandError::database(format!("Could not parse tag file row: {error}"))
+        })?);
+    }
+    Ok(files)
+}
+
+fn load_root_headings(
+    connection: &Connection,
+    root_id: i64,
+) -> CommandResult<Vec<TaggableHeading>> {
+    let mut statement = connection
+        .prepare(
+            "
+            SELECT f.relative_path, h.text, h.normalized
+            FROM headings h
+            JOIN files f ON f.id = h.file_id
+            WHERE f.root_id = ?1
+            ORDER BY f.relative_path, h.heading_order
+            ",
+        )
+        .map_err(|error| {
+            CommandError::database(format!("Could not prepare tag heading query: {error}"))
+        })?;
+    let rows = statement
+        .query_map(params![root_id], |row| {
+            Ok(TaggableHeading {
+                relative_path: row.get(0)?,
+                text: row.get(1)?,
+                normalized: row.get(2)?,
+            })
+        })
+        .map_err(|error| {
+            CommandError::database(format!("Could not run tag heading query: {error}"))
+        })?;
+    let mut headings = Vec::new();
+    for row in rows {
+        headings.push(row.map_err(|error| {
+            CommandError::database(format!("Could not parse tag heading row: {error}"))
+        })?);
+    }
+    Ok(headings)
+}
+
+/// Adds a tag to a file, or to one of its headings when `heading` is given as
+/// `(text, normalized)`. Returns whether the tag was new.
+pub(crate) fn add_tag(
+    connection: &Connection,
+    root_id: i64,
+    relative_path: &str,
+    heading: Option<(&str, &str)>,
+    tag: &str,
+) -> CommandResult<bool> {
+    let inserted = connection
+        .execute(
+            "INSERT OR IGNORE INTO tags(root_id, relative_path, heading_normalized, heading_text, tag, created_at_ms)
+             VALUES(?1, ?2, ?3, ?4, ?5, ?6)",
+            params![
+                root_id,
+                relative_path,
+                heading.map(|(_, normalized)| normalized).unwrap_or_default(),
+                heading.map(|(text, _)| text),
+                tag,
+                now_ms()
+            ],
+        )
+        .map_err(|error| CommandError::database(format!("Could not add tag '{tag}': {error}")))?;
+    Ok(inserted > 0)
+}
+
+/// Applies a tag mapping to a root. Rows that match nothing or carry no tags
+/// are reported back rather than failing the import.
+pub(crate) fn apply_tag_mapping(
+    connection: &Connection,
+    root_id: i64,
+    rows: &[TagMappingRow],
+) -> CommandResult<TagImportResult> {
+    let files = load_root_files(connection, root_id)?;
+    let headings = if rows.iter().any(|row| row.heading_pattern.is_some()) {
+        load_root_headings(connection, root_id)?
+    } else {
+        Vec::new()
+    };
+    let mut result = TagImportResult {
+        row_count: rows.len(),
+        file_tag_count: 0,
+        heading_tag_count: 0,
+        skipped: Vec::new(),
+    };
+
+    for row in rows {
+        let skip = |reason: &str| TagImportSkip {
+            row: row.row,
+            reason: reason.to_string(),
+        };
+        if row.tags.is_empty() {
+            result.skipped.push(skip("No tags given."));
+            continue;
+        }
+        let mut matched = false;
+        match (&row.path_pattern, &row.heading_pattern) {
+            (path_pattern, Some(heading_pattern)) => {
+                for heading in headings.iter().filter(|heading| {
+                    path_pattern
+                        .as_deref()
+                        .is_none_or(|pattern| path_matches(pattern, &heading.relative_path))
+                        && heading_matches(heading_pattern, &heading.normalized)
+                }) {
+                    matched = true;
+                    for tag in &row.tags {
+                        if add_tag(
+                            connection,
+                            root_id,
+                            &heading.relative_path,
+                            Some((&heading.text, &heading.normalized)),
+                            tag,
+                        )? {
+                            result.heading_tag_count += 1;
+                        }
+                    }
+                }
+            }
+            (Some(path_pattern), None) => {
+                for relative_path in files
+                    .iter()
+                    .filter(|relative_path| path_matches(path_pattern, relative_path))
+                {
+                    matched = true;
+                    for tag in &row.tags {
+                        if add_tag(connection, root_id, relative_path, None, tag)? {
+                            result.file_tag_count += 1;
+                        }
+                    }
+                }
+            }
+            (None, None) => {
+                result.skipped.push(skip("No path or heading given."));
+                continue;
+            }
+        }
+        if !matched {
+            result
+                .skipped
+                .push(skip("Nothing in this root matches the row."));
+        }
+    }
+    Ok(result)
+}
+
+/// Tags on a file and its headings; file-level tags come first.
+pub(crate) fn load_file_tags(connection: &Connection, file_id: i64) -> CommandResult<Vec<ItemTag>> {
+    let mut statement = connection
+        .prepare(
+            "
+            SELECT t.tag, t.heading_text
+            FROM tags t
+            JOIN files f ON f.root_id = t.root_id AND f.relative_path = t.relative_path
+            WHERE f.id = ?1
+            ORDER BY t.heading_normalized <> '', t.heading_normalized, t.tag COLLATE NOCASE
+            ",
+        )
+        .map_err(|error| {
+            CommandError::database(format!("Could not prepare file tag query: {error}"))
+        })?;
+    let rows = statement
+        .query_map(params![file_id], |row| {
+            Ok(ItemTag {
+                tag: row.get(0)?,
+                heading_text: row.get(1)?,
+            })
+        })
+        .map_err(|error| {
+            CommandError::database(format!("Could not run file tag query: {error}"))
+        })?;
+    let mut tags = Vec::new();
+    for row in rows {
+        tags.push(row.map_err(|error| {
+            CommandError::database(format!("Could not parse file tag row: {error}"))
+        })?);
+    }
+    Ok(tags)
+}
+
+/// Takes a tag off a file, or off one of its headings when
+/// `heading_normalized` is given. Returns whether the tag was there.
+pub(crate) fn remove_tag(
+    connection: &Connection,
+    root_id: i64,
+    relative_path: &str,
+    heading_normalized: Option<&str>,
+    tag: &str,
+) -> CommandResult<bool> {
+    let removed = connection
+        .execute(
+            "DELETE FROM tags
+             WHERE root_id = ?1 AND relative_path = ?2 AND heading_normalized = ?3 AND tag = ?4",
+            params![
+                root_id,
+                relative_path,
+                heading_normalized.unwrap_or_default(),
+                tag
+            ],
+        )
+        .map_err(|error| {
+            CommandError::database(format!("Could not remove tag '{tag}': {error}"))
+        })?;
+    Ok(removed > 0)
+}
+
+/// Every tag in use, with how many files and headings carry it, for a tag
+/// picker. `root_id` narrows to one root.
+pub(crate) fn list_tag_summaries(
+    connection: &Connection,
+    root_id: Option<i64>,
+) -> CommandResult<Vec<TagSummary>> {
+    let mut statement = connection
+        .prepare_cached(
+            "
+            SELECT
+              MIN(tag),
+              SUM(CASE WHEN heading_normalized = '' THEN 1 ELSE 0 END),
+              SUM(CASE WHEN heading_normalized <> '' THEN 1 ELSE 0 END)
+            FROM tags
+            WHERE ?1 IS NULL OR root_id = ?1
+            GROUP BY tag
+            ORDER BY tag
+            ",
+        )
+        .map_err(|error| {
+            CommandError::database(format!("Could not prepare tag list query: {error}"))
+        })?;
+    let rows = statement
+        .query_map(params![root_id], |row| {
+            Ok(TagSummary {
+                tag: row.get(0)?,
+                file_count: row.get(1)?,
+                heading_count: row.get(2)?,
+            })
+        })
+        .map_err(|error| CommandError::database(format!("Could not list tags: {error}")))?;
+    let mut tags = Vec::new();
+    for row in rows {
+        tags.push(row.map_err(|error| {
+            CommandError::database(format!("Could not parse tag list row: {error}"))
+        })?);
+    }
+    Ok(tags)
+}
+
+/// The files and headings carrying one tag.
+#[derive(Default)]
+struct TaggedItems {
+    files: HashSet<i64>,
+    headings: HashSet<(i64, String)>,
+}
+
+/// The `tag:` filters of a search, loaded once per search so hits can be
+/// checked without a query each.
+pub(crate) struct TagFilter {
+    tags: Vec<TaggedItems>,
+}
+
+impl TagFilter {
+    /// A hit passes when every tag is on its file or on its heading.
+    pub(crate) fn allows(&self, hit: &SearchHit) -> bool {
+        let heading = hit
+            .heading_text
+            .as_deref()
+            .map(|text| (hit.file_id, normalize_for_search(text)));
+        self.tags.iter().all(|items| {
+            items.files.contains(&hit.file_id)
+                || heading
+                    .as_ref()
+                    .is_some_and(|heading| items.headings.contains(heading))
+        })
+    }
+}
+
+pub(crate) fn load_tag_filter(
+    connection: &Connection,
+    tags: &[String],
+) -> CommandResult<TagFilter> {
+    let mut statement = connection
+        .prepare_cached(
+            "
+            SELECT f.id, t.heading_normalized
+            FROM tags t
+            JOIN files f ON f.root_id = t.root_id AND f.relative_path = t.relative_path
+            WHERE t.tag = ?1
+            ",
+        )
+        .map_err(|error| {
+            CommandError::database(format!("Could not prepare tag filter query: {error}"))
+        })?;
+    let mut filter = TagFilter { tags: Vec::new() };
+    for tag in tags {
+        let rows = statement
+            .query_map(params![tag], |row| {
+                Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?))
+            })
+            .map_err(|error| {
+                CommandError::database(format!("Could not load tag '{tag}': {error}"))
+            })?;
+        let mut items = TaggedItems::default();
+        for row in rows {
+            let (file_id, heading_normalized) = row.map_err(|error| {
+                CommandError::database(format!("Could not parse tag filter row: {error}"))
+            })?;
+            if heading_normalized.is_empty() {
+                items.files.insert(file_id);
+            } else {
+                items.headings.insert((file_id, heading_normalized));
+            }
+        }
+        filter.tags.push(items);
+    }
+    Ok(filter)
+}
+
+/// The files and headings carrying every one of `tags`, for a search made of
+/// `tag:` filters alone. Headings tagged by text that no longer appear in
+/// their file are left out.
+pub(crate) fn tagged_hits(
+    connection: &Connection,
+    requested_root_id: Option<i64>,
+    tags: &[String],
+    limit: usize,
+) -> CommandResult<Vec<SearchHit>> {
+    let Some(first_tag) = tags.first() else {
+        return Ok(Vec::new());
+    };
+    let filter = load_tag_filter(connection, tags)?;
+    let mut statement = connection
+        .prepare_cached(
+            "
+            SELECT f.id, f.relative_path, f.absolute_path, h.level, h.text, h.heading_order
+            FROM tags t
+            JOIN files f ON f.root_id = t.root_id AND f.relative_path = t.relative_path
+            LEFT JOIN headings h
+              ON t.heading_normalized <> ''
+             AND h.file_id = f.id
+             AND h.normalized = t.heading_normalized
+            WHERE t.tag = ?1
+              AND (?2 IS NULL OR f.root_id = ?2)
+              AND (t.heading_normalized = '' OR h.id IS NOT NULL)
+            ORDER BY f.relative_path, h.heading_order
+            ",
+        )
+        .map_err(|error| {
+            CommandError::database(format!("Could not prepare tagged item query: {error}"))
+        })?;
+    let rows = statement
+        .query_map(params![first_tag, requested_root_id], |row| {
+            let relative_path = row.get::<_, String>(1)?;
+            let heading_order = row.get::<_, Option<i64>>(5)?;
+            Ok(SearchHit {
+                source: "lexical".to_string(),
+                kind: if heading_order.is_some() {
+                    "heading"
+                } else {
+                    "file"
+                }
+                .to_string(),
+                file_id: row.get(0)?,
+                file_name: file_name_from_relative(&relative_path),
+                relative_path,
+                absolute_path: row.get(2)?,
+                heading_level: row.get(3)?,
+                heading_text: row.get(4)?,
+                heading_order,
+                score: 0.0,
+                snippet: None,
+                word_count: None,
+                underlined_word_count: None,
+            })
+        })
+        .map_err(|error| CommandError::database(format!("Could not load tagged items: {error}")))?;
+    let mut hits = Vec::new();
+    for row in rows {
+        let hit = row.map_err(|error| {
+            CommandError::database(format!("Could not parse tagged item row: {error}"))
+        })?;
+        if filter.allows(&hit) {
+            hits.push(hit);
+        }
+    }
+    hits.truncate(limit);
+    for (rank, hit) in hits.iter_mut().enumerate() {
+        hit.score = TAGGED_SCORE_BASE + f64::from(rank as u32);
+    }
+    Ok(hits)
+}
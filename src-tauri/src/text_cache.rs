@@ -0,0 +1,170 @@
+use std::fs;
+use std::path::Path;
+
+use rusqlite::{params, Connection, OptionalExtension};
+
+use crate::errors::CommandError;
+use crate::shards::schema_for_file;
+use crate::types::ParsedParagraph;
+use crate::util::epoch_ms;
+use crate::CommandResult;
+
+/// Joins paragraphs in the stored text. XML text cannot contain U+001E, so
+/// it never collides with a line break inside a paragraph.
+const PARAGRAPH_SEPARATOR: char = '\u{1e}';
+const TEXT_ZSTD_LEVEL: i32 = 3;
+
+/// A file's paragraph text, zstd-compressed for the `file_text` table.
+pub(crate) fn compress_paragraph_text(paragraphs: &[ParsedParagraph]) -> CommandResult<Vec<u8>> {
+    let text = paragraphs
+        .iter()
+        .map(|paragraph| paragraph.text.as_str())
+        .collect::<Vec<&str>>()
+        .join(&PARAGRAPH_SEPARATOR.to_string());
+    zstd::encode_all(text.as_bytes(), TEXT_ZSTD_LEVEL)
+        .map_err(|error| CommandError::internal(format!("Could not compress file text: {error}")))
+}
+
+pub(crate) fn store_file_text(
+    connection: &Connection,
+    file_id: i64,
+    paragraph_count: usize,
+    text_zstd: &[u8],
+) -> CommandResult<()> {
+    let schema = schema_for_file(file_id);
+    connection
+        .prepare_cached(&format!(
+            "INSERT INTO {schema}.file_text(file_id, paragraph_count, text_zstd) VALUES(?1, ?2, ?3)
+             ON CONFLICT(file_id) DO UPDATE SET
+               paragraph_count = excluded.paragraph_count,
+               text_zstd = excluded.text_zstd"
+        ))
+        .and_then(|mut statement| {
+            statement.execute(params![
+                file_id,
+                i64::try_from(paragraph_count).unwrap_or(0),
+                text_zstd
+            ])
+        })
+        .map_err(|error| {
+            CommandError::database(format!(
+                "Could not store cached text for file {file_id}: {error}"
+            ))
+        })?;
+    Ok(())
+}
+
+/// The indexed paragraph text of a file, or `None` when nothing is cached or
+/// the file on disk has changed since it was indexed.
+pub(crate) fn load_file_paragraphs(
+    connection: &Connection,
+    file_id: i64,
+) -> CommandResult<Option<Vec<String>>> {
+    let row = connection
+        .query_row(
+            "
+            SELECT f.absolute_path, f.modified_ms, f.size, t.text_zstd
+            FROM file_text t
+            JOIN files f ON f.id = t.file_id
+            WHERE t.file_id = ?1
+            ",
+            params![file_id],
+            |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, i64>(1)?,
+                    row.get::<_, i64>(2)?,
+                    row.get::<_, Vec<u8>>(3)?,
+                ))
+            },
+        )
+        .optional()
+        .map_err(|error| {
+            CommandError::database(format!(
+                "Could not load cached text for file {file_id}: {error}"
+            ))
+        })?;
+    let Some((absolute_path, modified_ms, size, text_zstd)) = row else {
+        return Ok(None);
+    };
+
+    let Ok(metadata) = fs::metadata(Path::new(&absolute_path)) else {
+        return Ok(None);
+    };
+    let current_modified_ms = metadata.modified().map(epoch_ms).unwrap_or(0);
+    if current_modified_ms != modified_ms || i64::try_from(metadata.len()).unwrap_or(0) != size {
+        return Ok(None);
+    }
+
+    let bytes = zstd::decode_all(text_zstd.as_slice()).map_err(|error| {
+        CommandError::parse(format!(
+            "Could not decompress cached text for file {file_id}: {error}"
+        ))
+    })?;
+    let text = String::from_utf8(bytes).map_err(|error| {
+        CommandError::parse(format!(
+            "Cached text for file {file_id} is not UTF-8: {error}"
+        ))
+    })?;
+    Ok(Some(
+        text.split(PARAGRAPH_SEPARATOR)
+            .map(str::to_string)
+            .collect(),
+    ))
+}
+
+/// A heading's section text from the cache, matching the preview's copy
+/// text: the heading paragraph through the paragraph before the next heading
+/// at the same or a higher level.
+pub(crate) fn cached_section_text(
+    connection: &Connection,
+    file_id: i64,
+    heading_order: i64,
+) -> CommandResult<Option<String>> {
+    let Some(paragraphs) = load_file_paragraphs(connection, file_id)? else {
+        return Ok(None);
+    };
+
+    let mut statement = connection
+        .prepare_cached(
+            "SELECT heading_order, level FROM headings WHERE file_id = ?1 ORDER BY heading_order",
+        )
+        .map_err(|error| {
+            CommandError::database(format!("Could not prepare cached section query: {error}"))
+        })?;
+    let rows = statement
+        .query_map(params![file_id], |row| {
+            Ok((row.get::<_, i64>(0)?, row.get::<_, i64>(1)?))
+        })
+        .map_err(|error| {
+            CommandError::database(format!("Could not load cached section headings: {error}"))
+        })?;
+    let mut headings = Vec::new();
+    for row in rows {
+        headings.push(row.map_err(|error| {
+            CommandError::database(format!("Could not parse cached section heading: {error}"))
+        })?);
+    }
+
+    let Some(level) = headings
+        .iter()
+        .find(|(order, _)| *order == heading_order)
+        .map(|(_, level)| *level)
+    else {
+        return Ok(None);
+    };
+    let end_order = headings
+        .iter()
+        .find(|(order, candidate_level)| *order > heading_order && *candidate_level <= level)
+        .map(|(order, _)| *order)
+        .unwrap_or(i64::try_from(paragraphs.len()).unwrap_or(0) + 1);
+
+    let start = usize::try_from(heading_order - 1).unwrap_or(0);
+    let end = usize::try_from(end_order - 1)
+        .unwrap_or(0)
+        .min(paragraphs.len());
+    if start >= end {
+        return Ok(None);
+    }
+    Ok(Some(paragraphs[start..end].join("\n")))
+}
@@ -5,134 +5,1092 @@ use ort::session::Session as OrtSession;
 use serde::{Deserialize, Serialize};
 use tokenizers::Tokenizer;
 
+use crate::errors::CommandError;
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct RootSummary {
+    pub path: String,
+    pub file_count: i64,
+    pub heading_count: i64,
+    pub added_at_ms: i64,
+    pub last_indexed_ms: i64,
+    pub root_uid: Option<String>,
+    pub sharded: bool,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct AddRootResult {
+    pub canonical_path: String,
+    pub should_index: bool,
+}
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct IndexStats {
+    pub scanned: usize,
+    pub updated: usize,
+    pub skipped: usize,
+    pub removed: usize,
+    pub headings_extracted: usize,
+    pub conflicts_detected: usize,
+    /// Files whose author lines were (re)extracted by the author phase.
+    pub author_files_updated: usize,
+    /// Folders and files the root's exclusion rules kept out of the walk.
+    pub excluded: usize,
+    pub elapsed_ms: i64,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct FileStatsEntry {
+    pub file_id: i64,
+    pub file_name: String,
+    pub relative_path: String,
+    pub word_count: i64,
+    pub card_count: i64,
+    pub cite_count: i64,
+}
+
+/// Totals over a root's files. Words count body text only; a card is a
+/// heading with body text directly under it.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct RootStats {
+    pub root_path: String,
+    pub file_count: i64,
+    pub word_count: i64,
+    pub card_count: i64,
+    pub cite_count: i64,
+    pub average_card_words: f64,
+    /// The files with the most words, largest first.
+    pub largest_files: Vec<FileStatsEntry>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct IndexQueueStatus {
+    /// The root being indexed, if any.
+    pub running: Option<String>,
+    pub pending: Vec<String>,
+    /// Roots finished so far in the current batch.
+    pub completed: usize,
+}
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct IndexQueueProgress {
+    pub root_path: String,
+    pub error: Option<CommandError>,
+    pub completed: usize,
+    pub total: usize,
+}
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct IndexQueueRootResult {
+    pub root_path: String,
+    pub stats: Option<IndexStats>,
+    pub error: Option<CommandError>,
+}
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct IndexQueueSummary {
+    pub indexed: usize,
+    pub failed: usize,
+    pub elapsed_ms: i64,
+    pub results: Vec<IndexQueueRootResult>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct IndexSettings {
+    pub extract_authors: bool,
+}
+
+/// Tuning for lexical ranking. Scores are lower-is-better, so a result
+/// type's score base sets where its hits land: exact heading, author and
+/// path matches start at 1000 and every other type is placed around them.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct SearchSettings {
+    pub heading_weight: f64,
+    pub author_weight: f64,
+    pub file_weight: f64,
+    /// Query words shorter than this are matched whole, not as prefixes.
+    pub prefix_min_chars: i64,
+    /// Queries shorter than this (spaces aside) skip the n-gram tier.
+    pub fuzzy_min_chars: i64,
+    pub recall_score_base: f64,
+    pub cite_score_base: f64,
+    pub body_score_base: f64,
+    pub prefix_score_base: f64,
+    pub fuzzy_score_base: f64,
+}
+
+/// How captures into one root are laid out. `separator_style` is the style id
+/// given to the empty paragraph after each capture; without one it is a plain
+/// paragraph. Captured headings are demoted `heading_level_shift` levels, and
+/// with `nest_child_captures` a capture inserted inside a heading is demoted
+/// far enough to sit below it.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct RootSettings {
+    pub default_capture_target: String,
+    pub insert_title: bool,
+    pub insert_separator: bool,
+    pub separator_style: Option<String>,
+    pub heading_level_shift: i64,
+    pub nest_child_captures: bool,
+}
+
+/// Options for one capture file. With `table_of_contents` the file keeps an
+/// index of its Heading 1 and 2 paragraphs at the top.
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct CaptureTargetSettings {
+    pub table_of_contents: bool,
+}
+
+/// One author as parsed from author lines: co-authors' surnames joined with
+/// ` & `, the cards and files citing them and the years those cards span.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct AuthorEntry {
+    pub surnames: String,
+    pub card_count: i64,
+    pub file_count: i64,
+    pub first_year: Option<i64>,
+    pub last_year: Option<i64>,
+}
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct FolderEntry {
+    pub path: String,
+    pub name: String,
+    pub parent_path: Option<String>,
+    pub depth: usize,
+    pub file_count: usize,
+}
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct IndexedFile {
+    pub id: i64,
+    pub file_name: String,
+    pub relative_path: String,
+    pub folder_path: String,
+    pub modified_ms: i64,
+    pub heading_count: i64,
+}
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct IndexSnapshot {
+    pub root_path: String,
+    pub indexed_at_ms: i64,
+    pub folders: Vec<FolderEntry>,
+    pub files: Vec<IndexedFile>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct FileHeading {
+    pub id: i64,
+    pub order: i64,
+    pub level: i64,
+    pub text: String,
+    pub copy_text: String,
+    /// Words in the paragraphs directly under the heading, up to the next
+    /// heading of any level.
+    pub word_count: usize,
+    pub underlined_word_count: usize,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct TaggedBlock {
+    pub order: i64,
+    pub style_label: String,
+    pub text: String,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct FilePreview {
+    pub file_id: i64,
+    pub file_name: String,
+    pub relative_path: String,
+    pub absolute_path: String,
+    pub heading_count: i64,
+    pub headings: Vec<FileHeading>,
+    pub f8_cites: Vec<TaggedBlock>,
+}
+
+/// A heading with the headings nested under it. Counts cover the whole
+/// section, nested headings' bodies included, but not heading paragraphs.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct OutlineNode {
+    pub order: i64,
+    pub level: i64,
+    pub text: String,
+    pub word_count: usize,
+    pub paragraph_count: usize,
+    pub children: Vec<OutlineNode>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct FileOutline {
+    pub file_id: i64,
+    pub file_name: String,
+    pub relative_path: String,
+    pub headings: Vec<OutlineNode>,
+}
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct SearchHit {
+    pub source: String,
+    pub kind: String,
+    pub file_id: i64,
+    pub file_name: String,
+    pub relative_path: String,
+    pub absolute_path: String,
+    pub heading_level: Option<i64>,
+    pub heading_text: Option<String>,
+    pub heading_order: Option<i64>,
+    pub score: f64,
+    /// For heading hits, the opening text of the section. For body and cite
+    /// hits, the matching text trimmed around the match, with matches in
+    /// `<mark>`.
+    pub snippet: Option<String>,
+    /// Word counts of the heading's card; `None` for hits without a
+    /// heading and for files not reindexed since the counts were added.
+    pub word_count: Option<i64>,
+    pub underlined_word_count: Option<i64>,
+}
+
+/// A page of search hits. `next_cursor` resumes after this page and is
+/// `None` on the last one; `total_estimate` counts the hits gathered so
+/// far, which is a lower bound while more pages remain.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct SearchPage {
+    pub hits: Vec<SearchHit>,
+    pub next_cursor: Option<String>,
+    pub total_estimate: usize,
+}
+
+/// Filters and paging for `search_index`. `kind` lists the hit kinds to keep
+/// (empty keeps all); `cursor` is a previous page's `next_cursor` and takes
+/// precedence over `offset`.
+#[derive(Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct SearchPageOptions {
+    pub min_level: Option<i64>,
+    pub max_level: Option<i64>,
+    pub kind: Option<Vec<String>>,
+    pub offset: Option<usize>,
+    pub cursor: Option<String>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct CaptureInsertResult {
+    pub capture_path: String,
+    pub marker: String,
+    pub target_relative_path: String,
+}
+
+/// One section of a batch capture: an indexed heading in a source file.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct CaptureBatchItem {
+    pub source_path: String,
+    pub heading_order: i64,
+}
+
+/// The target a batch was captured into and one marker per section, in the
+/// order the sections were given.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct CaptureBatchResult {
+    pub capture_path: String,
+    pub markers: Vec<String>,
+    pub target_relative_path: String,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct CaptureTarget {
+    pub relative_path: String,
+    pub absolute_path: String,
+    pub exists: bool,
+    pub entry_count: i64,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct CaptureTargetPreview {
+    pub relative_path: String,
+    pub absolute_path: String,
+    pub exists: bool,
+    pub heading_count: i64,
+    pub headings: Vec<FileHeading>,
+}
+
+/// One row of the capture history, with the marker written into its target.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct CaptureRecord {
+    pub id: i64,
+    pub source_path: String,
+    pub source_file_name: String,
+    pub section_title: String,
+    pub target_relative_path: String,
+    pub heading_level: Option<i64>,
+    pub created_at_ms: i64,
+    pub marker: String,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct CaptureTrashEntry {
+    pub id: i64,
+    pub target_relative_path: String,
+    pub heading_level: i64,
+    pub heading_text: String,
+    pub deleted_at_ms: i64,
+}
+
+pub(crate) struct CaptureTrashFragment {
+    pub paragraph_offset: usize,
+    pub fragment_xml: String,
+    pub styles_xml: String,
+    pub relationships_xml: String,
+}
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct SyncConflictCopy {
+    pub file_id: i64,
+    pub relative_path: String,
+    pub modified_ms: i64,
+    pub size: i64,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct SyncConflictGroup {
+    pub original_relative_path: String,
+    pub original: Option<SyncConflictCopy>,
+    pub copies: Vec<SyncConflictCopy>,
+    pub newest_relative_path: String,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct SyncConflictResolution {
+    pub original_relative_path: String,
+    pub kept_from_relative_path: String,
+    pub archived_paths: Vec<String>,
+}
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct HeadingChange {
+    pub kind: String,
+    pub level: i64,
+    pub heading_order: i64,
+    pub previous_text: Option<String>,
+    pub text: Option<String>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct HeadingHistoryRun {
+    pub indexed_at_ms: i64,
+    pub changes: Vec<HeadingChange>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct FileHistory {
+    pub file_id: i64,
+    pub file_name: String,
+    pub relative_path: String,
+    pub runs: Vec<HeadingHistoryRun>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct BlockPackSelection {
+    pub root_path: String,
+    #[serde(default)]
+    pub file_ids: Vec<i64>,
+    #[serde(default)]
+    pub folder_paths: Vec<String>,
+    /// Capture target to bundle alongside the sources, relative to the root.
+    #[serde(default)]
+    pub capture_target: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct BlockPackHeading {
+    pub order: i64,
+    pub level: i64,
+    pub text: String,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct BlockPackAuthor {
+    pub order: i64,
+    pub text: String,
+}
+
+/// A tag on a packed file, or on one of its headings when
+/// `heading_normalized` is not empty.
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct BlockPackTag {
+    pub heading_normalized: String,
+    pub heading_text: Option<String>,
+    pub tag: String,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct BlockPackFile {
+    pub file_name: String,
+    pub relative_path: String,
+    pub modified_ms: i64,
+    pub file_hash: String,
+    pub headings: Vec<BlockPackHeading>,
+    pub authors: Vec<BlockPackAuthor>,
+    #[serde(default)]
+    pub tags: Vec<BlockPackTag>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct BlockPackManifest {
+    pub version: i64,
+    pub exported_at_ms: i64,
+    pub source_root_path: String,
+    #[serde(default)]
+    pub source_root_uid: Option<String>,
+    /// Which of `files` is the capture target, when one was bundled.
+    #[serde(default)]
+    pub capture_target: Option<String>,
+    pub files: Vec<BlockPackFile>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct BlockPackExportResult {
+    pub pack_path: String,
+    pub file_count: usize,
+    pub heading_count: usize,
+    pub capture_target: Option<String>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct BlockPackImportResult {
+    pub root_path: String,
+    pub written_paths: Vec<String>,
+    pub skipped_paths: Vec<String>,
+    pub index: IndexStats,
+    pub tag_count: usize,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct CutQueueItem {
+    pub id: i64,
+    pub file_id: Option<i64>,
+    pub file_name: String,
+    pub relative_path: String,
+    pub heading_order: Option<i64>,
+    pub heading_text: Option<String>,
+    pub status: String,
+    pub assignee: String,
+    pub note: String,
+    pub created_at_ms: i64,
+    pub updated_at_ms: i64,
+}
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct NearDuplicateHeading {
+    pub file_id: i64,
+    pub file_name: String,
+    pub relative_path: String,
+    pub heading_order: i64,
+    pub level: i64,
+    pub text: String,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct SpeechLengthEstimate {
+    pub words_per_minute: f64,
+    pub word_count: usize,
+    pub underlined_word_count: usize,
+    pub seconds: f64,
+    pub underlined_seconds: f64,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct SimilarCard {
+    pub file_id: i64,
+    pub file_name: String,
+    pub relative_path: String,
+    pub absolute_path: String,
+    pub heading_order: i64,
+    pub heading_level: i64,
+    pub heading_text: String,
+    /// Character trigram overlap with the card asked about, `0.0..1.0`.
+    pub similarity: f64,
+    pub preview: Option<String>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct NearDuplicateGroup {
+    pub variant_count: usize,
+    pub file_count: usize,
+    pub min_similarity: f64,
+    pub variants: Vec<String>,
+    pub headings: Vec<NearDuplicateHeading>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct NearDuplicateReport {
+    pub root_path: String,
+    pub threshold: f64,
+    pub headings_compared: usize,
+    pub groups: Vec<NearDuplicateGroup>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct CitationAuditItem {
+    pub file_id: i64,
+    pub file_name: String,
+    pub relative_path: String,
+    pub heading_order: i64,
+    pub heading_level: i64,
+    pub heading_text: String,
+    pub cite_text: Option<String>,
+    pub missing: Vec<String>,
+    pub is_capture_file: bool,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct CitationAuditReport {
+    pub root_path: String,
+    pub files_checked: usize,
+    pub sections_checked: usize,
+    pub items: Vec<CitationAuditItem>,
+}
+
+pub(crate) struct ExtractedLink {
+    pub paragraph_order: i64,
+    pub url: String,
+    pub link_text: String,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct LinkStatusEntry {
+    pub url: String,
+    pub link_text: String,
+    pub paragraph_order: i64,
+    pub status: Option<String>,
+    pub status_code: Option<i64>,
+    pub error: Option<String>,
+    pub checked_at_ms: Option<i64>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct FileLinkReport {
+    pub file_id: i64,
+    pub file_name: String,
+    pub relative_path: String,
+    pub links: Vec<LinkStatusEntry>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct LinkReport {
+    pub root_path: String,
+    pub running: bool,
+    pub total_links: i64,
+    pub checked_links: i64,
+    pub broken_links: i64,
+    pub files: Vec<FileLinkReport>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct RootCaptureTarget {
+    pub root_path: String,
+    pub target_relative_path: String,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct SessionState {
+    pub last_root_path: Option<String>,
+    pub last_file_id: Option<i64>,
+    pub last_relative_path: Option<String>,
+    pub last_heading_order: Option<i64>,
+    pub capture_targets: Vec<RootCaptureTarget>,
+    pub updated_at_ms: i64,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct OutlineExportResult {
+    pub output_path: String,
+    pub folder_count: usize,
+    pub file_count: usize,
+    pub heading_count: usize,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct MarkdownExportResult {
+    pub output_path: String,
+    pub heading_count: usize,
+    pub paragraph_count: usize,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct PdfExportResult {
+    pub output_path: String,
+    pub page_count: usize,
+    pub paragraph_count: usize,
+}
+
+/// Markers for `export_marked_text`; `None` keeps the default and an empty
+/// string drops that formatting.
+#[derive(Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct TextMarkerOptions {
+    pub underline: Option<String>,
+    pub highlight: Option<String>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct MarkedTextExportResult {
+    pub output_path: String,
+    pub paragraph_count: usize,
+    pub text: String,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct SiteExportResult {
+    pub output_path: String,
+    pub page_count: usize,
+    pub folder_count: usize,
+    pub heading_count: usize,
+    pub skipped_files: Vec<String>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct VaultExportResult {
+    pub output_path: String,
+    pub note_count: usize,
+    pub link_count: usize,
+    pub skipped_files: Vec<String>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct CsvExportResult {
+    pub output_path: String,
+    pub row_count: usize,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct SnapshotXlsxResult {
+    pub output_path: String,
+    pub folder_count: usize,
+    pub file_count: usize,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct IndexDumpHeading {
+    pub order: i64,
+    pub level: i64,
+    pub text: String,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct IndexDumpAuthor {
+    pub order: i64,
+    pub text: String,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct IndexDumpCite {
+    pub paragraph_order: i64,
+    #[serde(default)]
+    pub heading_order: Option<i64>,
+    pub text: String,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct IndexDumpFile {
+    pub relative_path: String,
+    pub modified_ms: i64,
+    pub size: i64,
+    pub file_hash: String,
+    #[serde(default)]
+    pub headings: Vec<IndexDumpHeading>,
+    #[serde(default)]
+    pub authors: Vec<IndexDumpAuthor>,
+    #[serde(default)]
+    pub cites: Vec<IndexDumpCite>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct IndexDump {
+    pub version: i64,
+    pub exported_at_ms: i64,
+    pub files: Vec<IndexDumpFile>,
+}
+
 #[derive(Serialize)]
 #[serde(rename_all = "camelCase")]
-pub(crate) struct RootSummary {
-    pub path: String,
+pub(crate) struct IndexExportResult {
+    pub output_path: String,
+    pub format: String,
+    pub file_count: usize,
+    pub heading_count: usize,
+    pub author_count: usize,
+    pub cite_count: usize,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct IndexImportResult {
+    pub root_path: String,
+    pub file_count: usize,
+    pub heading_count: usize,
+    pub author_count: usize,
+    pub cite_count: usize,
+    /// Paths in the dump that point outside the root.
+    pub skipped_paths: Vec<String>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct IndexFeedResult {
+    pub output_path: String,
+    pub format: String,
+    pub run_count: usize,
+    pub entry_count: usize,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct TagImportSkip {
+    pub row: usize,
+    pub reason: String,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct TagImportResult {
+    pub row_count: usize,
+    pub file_tag_count: usize,
+    pub heading_tag_count: usize,
+    pub skipped: Vec<TagImportSkip>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct ItemTag {
+    pub tag: String,
+    pub heading_text: Option<String>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct TagSummary {
+    pub tag: String,
     pub file_count: i64,
     pub heading_count: i64,
-    pub added_at_ms: i64,
-    pub last_indexed_ms: i64,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct HeadingRef {
+    pub file_id: i64,
+    pub heading_order: i64,
 }
 
 #[derive(Serialize)]
 #[serde(rename_all = "camelCase")]
-pub(crate) struct AddRootResult {
-    pub canonical_path: String,
-    pub should_index: bool,
+pub(crate) struct MergeDocumentSkip {
+    pub file_id: i64,
+    pub heading_order: i64,
+    pub reason: String,
 }
 
 #[derive(Serialize)]
 #[serde(rename_all = "camelCase")]
-pub(crate) struct IndexStats {
-    pub scanned: usize,
-    pub updated: usize,
-    pub skipped: usize,
-    pub removed: usize,
-    pub headings_extracted: usize,
-    pub elapsed_ms: i64,
+pub(crate) struct MergeDocumentResult {
+    pub output_path: String,
+    pub section_count: usize,
+    pub skipped: Vec<MergeDocumentSkip>,
 }
 
 #[derive(Serialize)]
 #[serde(rename_all = "camelCase")]
-pub(crate) struct FolderEntry {
-    pub path: String,
+pub(crate) struct CaptureReplaySkip {
+    pub source_path: String,
+    pub section_title: String,
+    pub reason: String,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct CaptureReplayResult {
+    pub replayed_count: usize,
+    pub duplicate_count: usize,
+    pub skipped: Vec<CaptureReplaySkip>,
+    pub target_paths: Vec<String>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct GoogleDriveStatus {
+    pub configured: bool,
+    pub connected: bool,
+    pub client_id: Option<String>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct DriveUploadResult {
+    pub drive_file_id: String,
     pub name: String,
-    pub parent_path: Option<String>,
-    pub depth: usize,
-    pub file_count: usize,
+    pub web_view_link: Option<String>,
+    pub converted: bool,
 }
 
 #[derive(Serialize)]
 #[serde(rename_all = "camelCase")]
-pub(crate) struct IndexedFile {
-    pub id: i64,
-    pub file_name: String,
-    pub relative_path: String,
-    pub folder_path: String,
-    pub modified_ms: i64,
-    pub heading_count: i64,
+pub(crate) struct LocalApiStatus {
+    pub running: bool,
+    pub enabled: bool,
+    pub port: u16,
+    pub base_url: String,
+    pub events_url: String,
+    pub token: Option<String>,
 }
 
 #[derive(Serialize)]
 #[serde(rename_all = "camelCase")]
-pub(crate) struct IndexSnapshot {
-    pub root_path: String,
-    pub indexed_at_ms: i64,
-    pub folders: Vec<FolderEntry>,
-    pub files: Vec<IndexedFile>,
+pub(crate) struct PandocStatus {
+    pub available: bool,
+    pub binary_path: Option<String>,
+    pub version: Option<String>,
+    pub configured_path: Option<String>,
+    pub export_formats: Vec<String>,
 }
 
 #[derive(Serialize)]
 #[serde(rename_all = "camelCase")]
-pub(crate) struct FileHeading {
-    pub id: i64,
-    pub order: i64,
-    pub level: i64,
-    pub text: String,
-    pub copy_text: String,
+pub(crate) struct PandocExportResult {
+    pub output_path: String,
+    pub format: String,
 }
 
 #[derive(Serialize)]
 #[serde(rename_all = "camelCase")]
-pub(crate) struct TaggedBlock {
-    pub order: i64,
-    pub style_label: String,
-    pub text: String,
+pub(crate) struct VerbatimExportResult {
+    pub output_path: String,
+    pub pocket_count: usize,
+    pub hat_count: usize,
+    pub block_count: usize,
+    pub tag_count: usize,
 }
 
 #[derive(Serialize)]
 #[serde(rename_all = "camelCase")]
-pub(crate) struct FilePreview {
-    pub file_id: i64,
-    pub file_name: String,
-    pub relative_path: String,
+pub(crate) struct ProfileInfo {
+    pub id: String,
+    pub name: String,
+    pub created_at_ms: i64,
+    pub active: bool,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct ExternalOpenResult {
     pub absolute_path: String,
-    pub heading_count: i64,
-    pub headings: Vec<FileHeading>,
-    pub f8_cites: Vec<TaggedBlock>,
+    /// The requested heading's text. Word cannot be told to scroll to a
+    /// heading from outside, so the UI offers this for its Find box.
+    pub heading_text: Option<String>,
 }
 
-#[derive(Clone, Serialize)]
+#[derive(Deserialize)]
 #[serde(rename_all = "camelCase")]
-pub(crate) struct SearchHit {
-    pub source: String,
-    pub kind: String,
+pub(crate) struct LocalApiSearchRequest {
+    pub query: String,
+    pub root_path: Option<String>,
+    pub limit: Option<usize>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct LocalApiPreviewRequest {
     pub file_id: i64,
-    pub file_name: String,
-    pub relative_path: String,
-    pub absolute_path: String,
+    pub heading_order: Option<i64>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct CaptureRequest {
+    pub root_path: String,
+    pub source_path: String,
+    pub section_title: String,
+    pub content: String,
+    pub paragraph_xml: Option<Vec<String>>,
+    pub target_path: Option<String>,
     pub heading_level: Option<i64>,
-    pub heading_text: Option<String>,
     pub heading_order: Option<i64>,
-    pub score: f64,
+    pub selected_target_heading_order: Option<i64>,
+    /// `after`, `as-child-end`, `as-child-start` or `before` the selected
+    /// target heading. Defaults to `after`.
+    pub insert_mode: Option<String>,
 }
 
 #[derive(Serialize)]
 #[serde(rename_all = "camelCase")]
-pub(crate) struct CaptureInsertResult {
-    pub capture_path: String,
-    pub marker: String,
-    pub target_relative_path: String,
+pub(crate) struct RootIntake {
+    pub root_path: String,
+    pub intake_path: String,
+    pub target_folder: String,
+    pub last_moved_at_ms: Option<i64>,
+}
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct IntakeMovedEvent {
+    pub root_path: String,
+    pub moved_paths: Vec<String>,
 }
 
 #[derive(Serialize)]
 #[serde(rename_all = "camelCase")]
-pub(crate) struct CaptureTarget {
+pub(crate) struct WatchedRoot {
+    pub root_path: String,
+    pub enabled_at_ms: i64,
+    /// False when the watcher could not be started this session.
+    pub active: bool,
+}
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct RootFilesChangedEvent {
+    pub root_path: String,
+    pub changed_paths: Vec<String>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct DocumentImportResult {
+    pub docx_path: String,
     pub relative_path: String,
-    pub absolute_path: String,
-    pub exists: bool,
-    pub entry_count: i64,
+    pub title: String,
+    pub heading_count: usize,
+    pub paragraph_count: usize,
+    pub link_count: usize,
+    pub index: IndexStats,
 }
 
 #[derive(Serialize)]
 #[serde(rename_all = "camelCase")]
-pub(crate) struct CaptureTargetPreview {
+pub(crate) struct AllowedLocation {
+    pub path: String,
+    pub added_at_ms: i64,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct RootRelocationResult {
+    pub root_path: String,
+    pub previous_path: String,
+    pub file_count: usize,
+    pub sampled_count: usize,
+    pub found_count: usize,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct PathMapping {
+    pub foreign_root_path: String,
+    pub root_path: String,
+    pub root_uid: Option<String>,
+    pub created_at_ms: i64,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct ResolvedSharedPath {
+    pub root_path: String,
+    pub root_uid: Option<String>,
     pub relative_path: String,
     pub absolute_path: String,
     pub exists: bool,
-    pub heading_count: i64,
-    pub headings: Vec<FileHeading>,
+    pub file_id: Option<i64>,
+    pub heading_order: Option<i64>,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct SearchExportFilters {
+    pub root_path: Option<String>,
+    pub limit: Option<usize>,
+    #[serde(default)]
+    pub heading_levels: Option<Vec<i64>>,
+    pub path_prefix: Option<String>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct SearchCompileResult {
+    pub output_path: String,
+    pub file_count: usize,
+    pub section_count: usize,
+    pub nested_count: usize,
 }
 
 #[derive(Clone)]
@@ -157,6 +1115,9 @@ pub(crate) struct ParsedParagraph {
     pub heading_level: Option<i64>,
     pub style_label: Option<String>,
     pub is_f8_cite: bool,
+    /// Words with at least one underlined character, from direct run
+    /// formatting.
+    pub underlined_word_count: usize,
 }
 
 #[derive(Clone)]
@@ -189,6 +1150,36 @@ pub(crate) struct ParsedIndexCandidate {
     pub headings: Vec<ParsedHeading>,
     pub authors: Vec<(i64, String)>,
     pub chunks: Vec<ParsedChunk>,
+    pub paragraph_count: usize,
+    /// The file's paragraph text, zstd-compressed for the `file_text` cache.
+    pub text_zstd: Vec<u8>,
+    pub body: Vec<BodyParagraph>,
+    pub cites: Vec<CiteBlock>,
+    pub heading_word_counts: Vec<HeadingWordCount>,
+}
+
+/// Word counts of the body paragraphs under one heading, up to the next
+/// heading of any level.
+pub(crate) struct HeadingWordCount {
+    pub heading_order: i64,
+    pub word_count: usize,
+    pub underlined_word_count: usize,
+}
+
+/// A non-heading paragraph for the `body_fts` index, with the order of the
+/// heading it sits under.
+pub(crate) struct BodyParagraph {
+    pub paragraph_order: i64,
+    pub heading_order: Option<i64>,
+    pub text: String,
+}
+
+/// A run of consecutive F8 cite paragraphs for the `cites_fts` index, keyed
+/// by its first paragraph and the heading it sits under.
+pub(crate) struct CiteBlock {
+    pub paragraph_order: i64,
+    pub heading_order: Option<i64>,
+    pub text: String,
 }
 
 #[derive(Clone)]
@@ -231,6 +1222,25 @@ pub(crate) struct SemanticRuntime {
     pub output_name: String,
 }
 
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct OperationProgress {
+    pub operation_id: String,
+    pub kind: String,
+    pub root_path: Option<String>,
+    pub phase: String,
+    /// `phase` worded in the active locale.
+    pub phase_label: String,
+    pub status: String,
+    pub percent: Option<f64>,
+    pub processed: usize,
+    pub total: Option<usize>,
+    pub message: Option<String>,
+    pub cancellable: bool,
+    pub elapsed_ms: i64,
+    pub error: Option<CommandError>,
+}
+
 #[derive(Clone, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub(crate) struct IndexProgress {
@@ -299,11 +1309,107 @@ pub(crate) struct BenchmarkReport {
     pub elapsed_ms: i64,
 }
 
+/// Where one index run spent its time. `walk_ms` is the parallel directory
+/// listing; stat and hash calls are counted under `metadata_ms`.
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct IndexPhaseTimings {
+    pub root_path: String,
+    pub started_at_ms: i64,
+    pub files_scanned: usize,
+    pub files_parsed: usize,
+    pub walk_ms: f64,
+    pub metadata_ms: f64,
+    pub parse_ms: f64,
+    pub db_write_ms: f64,
+    pub fts_ms: f64,
+    pub total_ms: f64,
+}
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct SearchStageTimings {
+    pub mode: String,
+    pub started_at_ms: i64,
+    pub cached: bool,
+    pub hit_count: usize,
+    pub resolve_ms: f64,
+    pub lexical_ms: f64,
+    pub semantic_ms: f64,
+    pub fuse_ms: f64,
+    pub total_ms: f64,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct PerformanceReport {
+    pub enabled: bool,
+    pub index_runs: Vec<IndexPhaseTimings>,
+    pub searches: Vec<SearchStageTimings>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct LocaleOption {
+    pub code: String,
+    pub name: String,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct LocaleSettings {
+    pub locale: String,
+    pub available: Vec<LocaleOption>,
+}
+
+/// One local usage counter. `total_ms` and `max_ms` stay 0 for events that
+/// are counted but not timed.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct UsageCounter {
+    pub metric: String,
+    pub count: i64,
+    pub total_ms: i64,
+    pub max_ms: i64,
+    pub first_at_ms: i64,
+    pub last_at_ms: i64,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct UsageMetrics {
+    pub enabled: bool,
+    pub counters: Vec<UsageCounter>,
+}
+
+#[derive(Clone)]
 pub(crate) struct StyledSection {
     pub paragraph_xml: Vec<String>,
     pub style_ids: HashSet<String>,
     pub relationship_ids: HashSet<String>,
     pub used_source_xml: bool,
+    /// The source's styles and relationships, read alongside the paragraphs
+    /// so merging them into a target does not reopen the source.
+    pub source_styles_xml: Option<String>,
+    pub source_relationships_xml: Option<String>,
+    /// List instances the paragraphs are numbered with, and the source's
+    /// numbering part that defines them.
+    pub numbering_ids: HashSet<String>,
+    pub source_numbering_xml: Option<String>,
+    /// The source's notes parts, for footnote and endnote references.
+    pub source_footnotes_xml: Option<String>,
+    pub source_endnotes_xml: Option<String>,
+    /// Images the paragraphs show, already renamed to the part names the
+    /// relationships above point at.
+    pub media_parts: Vec<MediaPart>,
+}
+
+/// An image copied out of a source docx, named by a hash of its bytes.
+#[derive(Clone)]
+pub(crate) struct MediaPart {
+    pub part_name: String,
+    pub content_type: String,
+    pub bytes: Vec<u8>,
 }
 
 pub(crate) struct SourceStyleDefinition {
@@ -317,3 +1423,39 @@ pub(crate) struct RelationshipDef {
     pub target: String,
     pub target_mode: Option<String>,
 }
+
+#[derive(Clone, Default)]
+pub(crate) struct GeneratedRun {
+    pub text: String,
+    pub bold: bool,
+    pub italic: bool,
+    pub underline: bool,
+    pub link: Option<String>,
+}
+
+#[derive(Default)]
+pub(crate) struct GeneratedParagraph {
+    pub heading_level: Option<i64>,
+    pub runs: Vec<GeneratedRun>,
+}
+
+#[derive(Default)]
+pub(crate) struct ConvertedDocument {
+    pub title: Option<String>,
+    pub source_url: Option<String>,
+    pub paragraphs: Vec<GeneratedParagraph>,
+}
+
+/// A file the user opened or pinned, for the recent and pinned lists.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct ActivityFile {
+    pub file_id: i64,
+    pub file_name: String,
+    pub relative_path: String,
+    pub absolute_path: String,
+    pub root_path: String,
+    pub open_count: i64,
+    pub last_opened_ms: Option<i64>,
+    pub pinned: bool,
+}
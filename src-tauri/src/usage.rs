@@ -0,0 +1,139 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::OnceLock;
+
+use rusqlite::{params, Connection, OptionalExtension};
+use tauri::AppHandle;
+
+use crate::db::open_database;
+use crate::errors::CommandError;
+use crate::types::{UsageCounter, UsageMetrics};
+use crate::util::now_ms;
+use crate::CommandResult;
+
+pub(crate) const USAGE_SEARCH_LEXICAL: &str = "search.lexical";
+pub(crate) const USAGE_SEARCH_SEMANTIC: &str = "search.semantic";
+pub(crate) const USAGE_SEARCH_HYBRID: &str = "search.hybrid";
+pub(crate) const USAGE_CAPTURE: &str = "capture";
+pub(crate) const USAGE_INDEX: &str = "index";
+
+/// The stored opt-in flag, loaded on first use so a disabled install skips
+/// opening the database for every search.
+static USAGE_ENABLED: OnceLock<AtomicBool> = OnceLock::new();
+
+fn load_enabled(connection: &Connection) -> CommandResult<bool> {
+    connection
+        .query_row(
+            "SELECT enabled FROM usage_settings WHERE id = 1",
+            [],
+            |row| row.get::<_, i64>(0),
+        )
+        .optional()
+        .map(|enabled| enabled.unwrap_or(0) != 0)
+        .map_err(|error| CommandError::database(format!("Could not load usage settings: {error}")))
+}
+
+fn usage_enabled(connection: &Connection) -> bool {
+    USAGE_ENABLED
+        .get_or_init(|| AtomicBool::new(load_enabled(connection).unwrap_or(false)))
+        .load(Ordering::Relaxed)
+}
+
+pub(crate) fn store_usage_enabled(connection: &Connection, enabled: bool) -> CommandResult<()> {
+    connection
+        .execute(
+            "
+            INSERT INTO usage_settings(id, enabled, updated_at_ms)
+            VALUES(1, ?1, ?2)
+            ON CONFLICT(id) DO UPDATE SET
+              enabled = excluded.enabled,
+              updated_at_ms = excluded.updated_at_ms
+            ",
+            params![i64::from(enabled), now_ms()],
+        )
+        .map_err(|error| {
+            CommandError::database(format!("Could not store usage settings: {error}"))
+        })?;
+    USAGE_ENABLED
+        .get_or_init(|| AtomicBool::new(enabled))
+        .store(enabled, Ordering::Relaxed);
+    Ok(())
+}
+
+/// Bumps `metric` when the user has opted in. Counters never leave this
+/// machine, and failing to record one never fails the command being measured.
+pub(crate) fn record_usage(app: &AppHandle, metric: &str, duration_ms: Option<i64>) {
+    if USAGE_ENABLED
+        .get()
+        .is_some_and(|enabled| !enabled.load(Ordering::Relaxed))
+    {
+        return;
+    }
+    let Ok(connection) = open_database(app) else {
+        return;
+    };
+    if !usage_enabled(&connection) {
+        return;
+    }
+    let duration_ms = duration_ms.unwrap_or(0).max(0);
+    let now = now_ms();
+    let _ = connection.execute(
+        "
+        INSERT INTO usage_counters(metric, count, total_ms, max_ms, first_at_ms, last_at_ms)
+        VALUES(?1, 1, ?2, ?2, ?3, ?3)
+        ON CONFLICT(metric) DO UPDATE SET
+          count = count + 1,
+          total_ms = total_ms + excluded.total_ms,
+          max_ms = max(max_ms, excluded.max_ms),
+          last_at_ms = excluded.last_at_ms
+        ",
+        params![metric, duration_ms, now],
+    );
+}
+
+pub(crate) fn load_usage_metrics(connection: &Connection) -> CommandResult<UsageMetrics> {
+    let mut statement = connection
+        .prepare(
+            "
+            SELECT metric, count, total_ms, max_ms, first_at_ms, last_at_ms
+            FROM usage_counters
+            ORDER BY metric
+            ",
+        )
+        .map_err(|error| {
+            CommandError::database(format!("Could not prepare usage metrics query: {error}"))
+        })?;
+    let rows = statement
+        .query_map([], |row| {
+            Ok(UsageCounter {
+                metric: row.get(0)?,
+                count: row.get(1)?,
+                total_ms: row.get(2)?,
+                max_ms: row.get(3)?,
+                first_at_ms: row.get(4)?,
+                last_at_ms: row.get(5)?,
+            })
+        })
+        .map_err(|error| {
+            CommandError::database(format!("Could not run usage metrics query: {error}"))
+        })?;
+    let mut counters = Vec::new();
+    for row in rows {
+        counters.push(row.map_err(|error| {
+            CommandError::database(format!("Could not parse usage metrics row: {error}"))
+        })?);
+    }
+
+    Ok(UsageMetrics {
+        enabled: usage_enabled(connection),
+        counters,
+    })
+}
+
+pub(crate) fn purge_usage_counters(connection: &Connection) -> CommandResult<()> {
+    connection
+        .execute("DELETE FROM usage_counters", [])
+        .map_err(|error| {
+            CommandError::database(format!("Could not purge usage metrics: {error}"))
+        })?;
+    Ok(())
+}
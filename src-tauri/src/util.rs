@@ -1,16 +1,16 @@
 use std::collections::HashSet;
 use std::fs;
 use std::io::{Read, Seek, SeekFrom};
-use std::path::{Component, Path, PathBuf};
+use std::path::{Path, PathBuf};
 use std::time::{SystemTime, UNIX_EPOCH};
 
 use tauri::{AppHandle, Emitter};
-use walkdir::DirEntry;
 
+use crate::errors::CommandError;
+use crate::operations::OperationHandle;
 use crate::search::normalize_for_search;
 use crate::types::{IndexProgress, ParsedParagraph};
 use crate::CommandResult;
-use crate::DEFAULT_CAPTURE_TARGET;
 
 pub(crate) const INDEX_PROGRESS_EVENT: &str = "index-progress";
 pub(crate) const INDEX_PROGRESS_EMIT_INTERVAL_MS: i64 = 120;
@@ -26,6 +26,89 @@ pub(crate) fn epoch_ms(time: SystemTime) -> i64 {
         .unwrap_or(0)
 }
 
+/// 256-bit token from the OS random source, as 64 hex characters.
+pub(crate) fn random_token() -> CommandResult<String> {
+    let mut bytes = [0_u8; 32];
+    getrandom::fill(&mut bytes).map_err(|error| {
+        CommandError::internal(format!("Could not generate a random token: {error}"))
+    })?;
+    Ok(bytes.iter().map(|byte| format!("{byte:02x}")).collect())
+}
+
+pub(crate) fn form_encode(value: &str) -> String {
+    let mut encoded = String::with_capacity(value.len());
+    for byte in value.bytes() {
+        if byte.is_ascii_alphanumeric() || matches!(byte, b'-' | b'_' | b'.' | b'~') {
+            encoded.push(byte as char);
+        } else {
+            encoded.push_str(&format!("%{byte:02X}"));
+        }
+    }
+    encoded
+}
+
+pub(crate) fn form_decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut index = 0;
+    while index < bytes.len() {
+        match bytes[index] {
+            b'+' => decoded.push(b' '),
+            b'%' => match value
+                .get(index + 1..index + 3)
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+            {
+                Some(byte) => {
+                    decoded.push(byte);
+                    index += 2;
+                }
+                None => decoded.push(b'%'),
+            },
+            byte => decoded.push(byte),
+        }
+        index += 1;
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+pub(crate) fn query_param(query: &str, key: &str) -> Option<String> {
+    query.split('&').find_map(|pair| {
+        let (name, value) = pair.split_once('=')?;
+        (name == key).then(|| form_decode(value))
+    })
+}
+
+/// Formats epoch milliseconds as an ISO 8601 UTC timestamp
+/// (`2024-03-09T14:05:00Z`), using the days-to-civil conversion so no date
+/// crate is needed.
+pub(crate) fn format_utc_timestamp(epoch_ms: i64) -> String {
+    let seconds = epoch_ms.div_euclid(1_000);
+    let days = seconds.div_euclid(86_400);
+    let second_of_day = seconds.rem_euclid(86_400);
+
+    let shifted = days + 719_468;
+    let era = shifted.div_euclid(146_097);
+    let day_of_era = shifted.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}Z",
+        second_of_day / 3_600,
+        second_of_day % 3_600 / 60,
+        second_of_day % 60
+    )
+}
+
 pub(crate) fn path_display(path: &Path) -> String {
     path.to_string_lossy().into_owned()
 }
@@ -42,11 +125,26 @@ pub(crate) fn suggested_parse_chunk_size() -> usize {
 
 pub(crate) fn emit_index_progress(
     app: &AppHandle,
+    operation: &mut OperationHandle,
     started_at: i64,
     progress: &IndexProgress,
     last_emitted_ms: &mut i64,
     force: bool,
 ) {
+    let (processed, total) = match progress.phase.as_str() {
+        "indexing" => (progress.processed, Some(progress.changed)),
+        "cleaning" => (progress.removed, None),
+        _ => (progress.discovered, None),
+    };
+    if progress.phase != "complete" {
+        operation.update(
+            &progress.phase,
+            processed,
+            total,
+            progress.current_file.clone(),
+        );
+    }
+
     let now = now_ms();
     if !force && now - *last_emitted_ms < INDEX_PROGRESS_EMIT_INTERVAL_MS {
         return;
@@ -60,9 +158,11 @@ pub(crate) fn emit_index_progress(
 
 pub(crate) fn canonicalize_folder(path: &str) -> CommandResult<PathBuf> {
     let canonical = fs::canonicalize(path)
-        .map_err(|error| format!("Could not access folder '{path}': {error}"))?;
+        .map_err(|error| CommandError::io(format!("Could not access folder '{path}': {error}")))?;
     if !canonical.is_dir() {
-        return Err(format!("Path is not a folder: {path}"));
+        return Err(CommandError::validation(format!(
+            "Path is not a folder: {path}"
+        )));
     }
     Ok(canonical)
 }
@@ -71,44 +171,46 @@ pub(crate) fn root_index_marker_path(root: &Path) -> PathBuf {
     root.join(".blockfile-index.json")
 }
 
-pub(crate) fn normalize_capture_target_path(target_path: Option<&str>) -> CommandResult<String> {
-    let raw = target_path
-        .map(str::trim)
-        .filter(|value| !value.is_empty())
-        .unwrap_or(DEFAULT_CAPTURE_TARGET);
-
-    let candidate = Path::new(raw);
-    let mut normalized = if candidate.is_absolute() {
-        PathBuf::from(candidate)
-    } else {
-        let mut value = PathBuf::new();
-        for component in candidate.components() {
-            match component {
-                Component::Normal(part) => value.push(part),
-                Component::CurDir => {}
-                Component::ParentDir | Component::RootDir | Component::Prefix(_) => return Err(
-                    "Capture target path cannot use '..' or root-prefix components when relative."
-                        .to_string(),
-                ),
-            }
+/// Returns `destination`, or "Name (2).docx", "Name (3).docx", ... when taken.
+pub(crate) fn unique_docx_path(destination: PathBuf) -> PathBuf {
+    if !destination.exists() {
+        return destination;
+    }
+    let stem = destination
+        .file_stem()
+        .map(|value| value.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let mut attempt = 2_u32;
+    loop {
+        let candidate = destination.with_file_name(format!("{stem} ({attempt}).docx"));
+        if !candidate.exists() {
+            return candidate;
         }
-        value
-    };
-
-    if normalized.as_os_str().is_empty() {
-        return Err("Capture target path cannot be empty.".to_string());
+        attempt += 1;
     }
+}
 
-    if normalized
-        .extension()
-        .and_then(|ext| ext.to_str())
-        .map(|ext| ext.eq_ignore_ascii_case("docx"))
-        != Some(true)
-    {
-        normalized.set_extension("docx");
+/// Turns a document title into a file stem that is valid on every platform.
+pub(crate) fn file_stem_from_title(title: &str) -> String {
+    let cleaned = title
+        .chars()
+        .map(|character| match character {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => ' ',
+            character if character.is_control() => ' ',
+            character => character,
+        })
+        .collect::<String>();
+    let collapsed = cleaned.split_whitespace().collect::<Vec<&str>>().join(" ");
+    let trimmed = collapsed
+        .trim_matches(['.', ' '])
+        .chars()
+        .take(120)
+        .collect::<String>();
+    if trimmed.is_empty() {
+        "Untitled".to_string()
+    } else {
+        trimmed.trim_end().to_string()
     }
-
-    Ok(path_display(&normalized))
 }
 
 pub(crate) fn capture_docx_path(root: &Path, target_relative_path: &str) -> PathBuf {
@@ -126,25 +228,27 @@ pub(crate) fn write_root_index_marker(root: &Path, last_indexed_ms: i64) -> Comm
         "rootPath": path_display(root),
         "lastIndexedMs": last_indexed_ms,
     });
-    let content = serde_json::to_string_pretty(&marker)
-        .map_err(|error| format!("Could not serialize index marker JSON: {error}"))?;
+    let content = serde_json::to_string_pretty(&marker).map_err(|error| {
+        CommandError::parse(format!("Could not serialize index marker JSON: {error}"))
+    })?;
     fs::write(&marker_path, content).map_err(|error| {
-        format!(
+        CommandError::io(format!(
             "Could not write index marker '{}': {error}",
             path_display(&marker_path)
-        )
+        ))
     })
 }
 
 pub(crate) fn fast_file_hash(path: &Path) -> CommandResult<String> {
     const WINDOW_BYTES: usize = 64 * 1024;
-    let mut file = fs::File::open(path)
-        .map_err(|error| format!("Could not open '{}': {error}", path_display(path)))?;
+    let mut file = fs::File::open(path).map_err(|error| {
+        CommandError::io(format!("Could not open '{}': {error}", path_display(path)))
+    })?;
     let metadata = file.metadata().map_err(|error| {
-        format!(
+        CommandError::io(format!(
             "Could not read metadata for '{}': {error}",
             path_display(path)
-        )
+        ))
     })?;
     let file_len = metadata.len();
 
@@ -153,26 +257,26 @@ pub(crate) fn fast_file_hash(path: &Path) -> CommandResult<String> {
 
     let mut buffer = vec![0_u8; WINDOW_BYTES];
     let front_bytes = file.read(&mut buffer).map_err(|error| {
-        format!(
+        CommandError::io(format!(
             "Could not read hash prefix for '{}': {error}",
             path_display(path)
-        )
+        ))
     })?;
     hasher.update(&buffer[..front_bytes]);
 
     if file_len > u64::try_from(WINDOW_BYTES).unwrap_or(0) {
         let start = file_len.saturating_sub(u64::try_from(WINDOW_BYTES).unwrap_or(0));
         file.seek(SeekFrom::Start(start)).map_err(|error| {
-            format!(
+            CommandError::io(format!(
                 "Could not seek hash suffix for '{}': {error}",
                 path_display(path)
-            )
+            ))
         })?;
         let tail_bytes = file.read(&mut buffer).map_err(|error| {
-            format!(
+            CommandError::io(format!(
                 "Could not read hash suffix for '{}': {error}",
                 path_display(path)
-            )
+            ))
         })?;
         hasher.update(&buffer[..tail_bytes]);
     }
@@ -194,30 +298,49 @@ pub(crate) fn folder_from_relative(relative_path: &str) -> String {
         .unwrap_or_default()
 }
 
-pub(crate) fn is_visible_entry(entry: &DirEntry) -> bool {
-    let name = entry.file_name().to_string_lossy();
-    !name.starts_with('.')
-}
-
 pub(crate) fn relative_path(root: &Path, file_path: &Path) -> CommandResult<String> {
     let relative = file_path
         .strip_prefix(root)
-        .map_err(|error| format!("Failed to strip root prefix: {error}"))?;
+        .map_err(|error| CommandError::internal(format!("Failed to strip root prefix: {error}")))?;
     Ok(relative.to_string_lossy().replace('\\', "/"))
 }
 
-pub(crate) fn contains_year_token(text: &str) -> bool {
-    for token in text
-        .split(|character: char| !character.is_ascii_digit())
-        .filter(|token| token.len() == 4)
-    {
-        if let Ok(year) = token.parse::<i32>() {
-            if (1900..=2099).contains(&year) {
-                return true;
-            }
+/// Glob match where `*` spans any run of characters, including `/`.
+pub(crate) fn wildcard_match(pattern: &str, text: &str) -> bool {
+    let pattern = pattern.chars().collect::<Vec<char>>();
+    let text = text.chars().collect::<Vec<char>>();
+    let (mut pattern_index, mut text_index) = (0, 0);
+    let mut backtrack: Option<(usize, usize)> = None;
+    while text_index < text.len() {
+        if pattern_index < pattern.len() && pattern[pattern_index] == '*' {
+            backtrack = Some((pattern_index, text_index));
+            pattern_index += 1;
+        } else if pattern_index < pattern.len() && pattern[pattern_index] == text[text_index] {
+            pattern_index += 1;
+            text_index += 1;
+        } else if let Some((star, matched)) = backtrack {
+            pattern_index = star + 1;
+            text_index = matched + 1;
+            backtrack = Some((star, matched + 1));
+        } else {
+            return false;
         }
     }
-    false
+    pattern[pattern_index..]
+        .iter()
+        .all(|character| *character == '*')
+}
+
+pub(crate) fn contains_year_token(text: &str) -> bool {
+    first_year_token(text).is_some()
+}
+
+/// The first standalone four-digit year from 1900 to 2099 in `text`.
+pub(crate) fn first_year_token(text: &str) -> Option<i64> {
+    text.split(|character: char| !character.is_ascii_digit())
+        .filter(|token| token.len() == 4)
+        .filter_map(|token| token.parse::<i64>().ok())
+        .find(|year| (1900..=2099).contains(year))
 }
 
 pub(crate) fn is_probable_author_line(text: &str) -> bool {
@@ -251,20 +374,32 @@ pub(crate) fn is_probable_author_line(text: &str) -> bool {
 }
 
 pub(crate) fn extract_author_candidates(paragraphs: &[ParsedParagraph]) -> Vec<(i64, String)> {
+    author_candidates_from_text(
+        paragraphs
+            .iter()
+            .map(|paragraph| (paragraph.order, paragraph.text.as_str())),
+    )
+}
+
+/// Author lines among `(order, text)` paragraphs, deduplicated by normalized
+/// text and capped at 120 per file.
+pub(crate) fn author_candidates_from_text<'a>(
+    paragraphs: impl IntoIterator<Item = (i64, &'a str)>,
+) -> Vec<(i64, String)> {
     let mut seen = HashSet::new();
     let mut authors = Vec::new();
 
-    for paragraph in paragraphs {
-        if !is_probable_author_line(&paragraph.text) {
+    for (order, text) in paragraphs {
+        if !is_probable_author_line(text) {
             continue;
         }
 
-        let normalized = normalize_for_search(&paragraph.text);
+        let normalized = normalize_for_search(text);
         if normalized.is_empty() || !seen.insert(normalized) {
             continue;
         }
 
-        authors.push((paragraph.order, paragraph.text.clone()));
+        authors.push((order, text.to_string()));
         if authors.len() >= 120 {
             break;
         }
@@ -272,3 +407,20 @@ pub(crate) fn extract_author_candidates(paragraphs: &[ParsedParagraph]) -> Vec<(
 
     authors
 }
+
+pub(crate) fn write_export_file(output_path: &Path, contents: &str) -> CommandResult<()> {
+    if let Some(parent) = output_path.parent() {
+        fs::create_dir_all(parent).map_err(|error| {
+            CommandError::io(format!(
+                "Could not create export folder '{}': {error}",
+                path_display(parent)
+            ))
+        })?;
+    }
+    fs::write(output_path, contents).map_err(|error| {
+        CommandError::io(format!(
+            "Could not write export '{}': {error}",
+            path_display(output_path)
+        ))
+    })
+}
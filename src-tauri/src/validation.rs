@@ -0,0 +1,292 @@
+use std::path::{Component, Path, PathBuf};
+
+use rusqlite::Connection;
+
+use crate::access::guard_path;
+use crate::document_parser::is_indexable_path;
+use crate::errors::CommandError;
+use crate::messages::{format_message, message};
+use crate::util::path_display;
+use crate::CommandResult;
+use crate::DEFAULT_CAPTURE_TARGET;
+
+/// A command argument: `name` is the argument's name on the frontend side
+/// (reported as the error's `field`), `label` is the message catalog key of
+/// its user-facing label.
+#[derive(Clone, Copy)]
+pub(crate) struct Field {
+    pub name: &'static str,
+    pub label: &'static str,
+}
+
+pub(crate) const ROOT_PATH: Field = Field {
+    name: "rootPath",
+    label: "field.rootPath",
+};
+pub(crate) const SOURCE_PATH: Field = Field {
+    name: "sourcePath",
+    label: "field.sourcePath",
+};
+pub(crate) const TARGET_PATH: Field = Field {
+    name: "targetPath",
+    label: "field.targetPath",
+};
+pub(crate) const SECTION_TITLE: Field = Field {
+    name: "sectionTitle",
+    label: "field.sectionTitle",
+};
+pub(crate) const CONTENT: Field = Field {
+    name: "content",
+    label: "field.content",
+};
+pub(crate) const HEADING_LEVEL: Field = Field {
+    name: "headingLevel",
+    label: "field.headingLevel",
+};
+pub(crate) const HEADING_TEXT: Field = Field {
+    name: "headingText",
+    label: "field.headingText",
+};
+pub(crate) const TITLE: Field = Field {
+    name: "title",
+    label: "field.title",
+};
+pub(crate) const FOLDER: Field = Field {
+    name: "folder",
+    label: "field.folder",
+};
+pub(crate) const TAG: Field = Field {
+    name: "tag",
+    label: "field.tag",
+};
+
+pub(crate) const MAX_PATH_CHARS: usize = 4_096;
+/// Most filesystems cap a single path component at 255 bytes.
+pub(crate) const MAX_FILE_NAME_BYTES: usize = 255;
+pub(crate) const MAX_TITLE_CHARS: usize = 1_000;
+pub(crate) const MAX_TAG_CHARS: usize = 100;
+pub(crate) const MAX_CAPTURE_CONTENT_BYTES: usize = 8 * 1024 * 1024;
+/// Capture headings map onto Word's built-in Heading 1-9 styles.
+pub(crate) const MAX_CAPTURE_HEADING_LEVEL: i64 = 9;
+/// Headings added from the capture panel are the pocket/hat/block/tag levels.
+pub(crate) const MAX_ADDED_HEADING_LEVEL: i64 = 4;
+
+/// Characters Windows rejects in file names; the app's capture targets have
+/// to open on every platform a root is synced to.
+const FORBIDDEN_FILE_NAME_CHARS: [char; 9] = ['<', '>', ':', '"', '/', '\\', '|', '?', '*'];
+const RESERVED_FILE_STEMS: [&str; 22] = [
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+    "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+fn invalid(field: Field, key: &str, args: &[(&str, &str)]) -> CommandError {
+    CommandError::validation_field(field.name, format_message(key, args))
+}
+
+/// Like `invalid`, for messages that start with the field's label.
+fn invalid_value(field: Field, key: &str, args: &[(&str, &str)]) -> CommandError {
+    let label = message(field.label);
+    let mut args = args.to_vec();
+    args.push(("label", &label));
+    invalid(field, key, &args)
+}
+
+/// Trims `value` and rejects it when empty or longer than `max_chars`.
+pub(crate) fn required_text(field: Field, value: &str, max_chars: usize) -> CommandResult<&str> {
+    let trimmed = value.trim();
+    if trimmed.is_empty() {
+        return Err(invalid_value(field, "validation.empty", &[]));
+    }
+    optional_text(field, trimmed, max_chars)?;
+    Ok(trimmed)
+}
+
+pub(crate) fn optional_text(field: Field, value: &str, max_chars: usize) -> CommandResult<()> {
+    if value.chars().count() > max_chars {
+        return Err(invalid_value(
+            field,
+            "validation.tooLong",
+            &[("max", &max_chars.to_string())],
+        ));
+    }
+    Ok(())
+}
+
+/// Like `required_text`, for large bodies measured in bytes.
+pub(crate) fn required_body(field: Field, value: &str, max_bytes: usize) -> CommandResult<()> {
+    if value.trim().is_empty() {
+        return Err(invalid_value(field, "validation.empty", &[]));
+    }
+    if value.len() > max_bytes {
+        return Err(invalid_value(
+            field,
+            "validation.tooLarge",
+            &[("max", &(max_bytes / (1024 * 1024)).to_string())],
+        ));
+    }
+    Ok(())
+}
+
+pub(crate) fn heading_level(field: Field, level: i64, max_level: i64) -> CommandResult<i64> {
+    if !(1..=max_level).contains(&level) {
+        return Err(invalid_value(
+            field,
+            "validation.levelRange",
+            &[("max", &max_level.to_string())],
+        ));
+    }
+    Ok(level)
+}
+
+/// Rejects paths no filesystem accepts before they reach `fs` calls.
+pub(crate) fn input_path(field: Field, value: &str) -> CommandResult<()> {
+    if value.trim().is_empty() {
+        return Err(invalid_value(field, "validation.empty", &[]));
+    }
+    if value.contains('\0') {
+        return Err(invalid_value(field, "validation.nul", &[]));
+    }
+    optional_text(field, value, MAX_PATH_CHARS)
+}
+
+fn file_name_rules(field: Field, name: &str) -> CommandResult<()> {
+    if name.len() > MAX_FILE_NAME_BYTES {
+        return Err(invalid(
+            field,
+            "validation.fileNameTooLong",
+            &[("name", name), ("max", &MAX_FILE_NAME_BYTES.to_string())],
+        ));
+    }
+    if let Some(character) = name
+        .chars()
+        .find(|character| character.is_control() || FORBIDDEN_FILE_NAME_CHARS.contains(character))
+    {
+        return Err(invalid(
+            field,
+            "validation.fileNameCharacter",
+            &[("name", name), ("character", &format!("{character:?}"))],
+        ));
+    }
+    if name.ends_with('.') || name.ends_with(' ') {
+        return Err(invalid(
+            field,
+            "validation.fileNameEnding",
+            &[("name", name)],
+        ));
+    }
+    let stem = name.split('.').next().unwrap_or_default();
+    if RESERVED_FILE_STEMS
+        .iter()
+        .any(|reserved| reserved.eq_ignore_ascii_case(stem))
+    {
+        return Err(invalid(
+            field,
+            "validation.fileNameReserved",
+            &[("name", name)],
+        ));
+    }
+    Ok(())
+}
+
+/// Normalizes a capture target: relative paths stay inside the root (no `..`
+/// or root-prefix components), every component must be a valid file name on
+/// all platforms, and the result always ends in `.docx`.
+pub(crate) fn normalize_capture_target_path(target_path: Option<&str>) -> CommandResult<String> {
+    let raw = target_path
+        .map(str::trim)
+        .filter(|value| !value.is_empty())
+        .unwrap_or(DEFAULT_CAPTURE_TARGET);
+    input_path(TARGET_PATH, raw)?;
+
+    let candidate = Path::new(raw);
+    let mut normalized = if candidate.is_absolute() {
+        PathBuf::from(candidate)
+    } else {
+        let mut value = PathBuf::new();
+        for component in candidate.components() {
+            match component {
+                Component::Normal(part) => {
+                    file_name_rules(TARGET_PATH, &part.to_string_lossy())?;
+                    value.push(part);
+                }
+                Component::CurDir => {}
+                Component::ParentDir | Component::RootDir | Component::Prefix(_) => {
+                    return Err(invalid(TARGET_PATH, "validation.targetOutsideRoot", &[]))
+                }
+            }
+        }
+        value
+    };
+
+    let Some(file_name) = normalized.file_name() else {
+        return Err(invalid(TARGET_PATH, "validation.targetEmpty", &[]));
+    };
+    file_name_rules(TARGET_PATH, &file_name.to_string_lossy())?;
+
+    if normalized
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| ext.eq_ignore_ascii_case("docx"))
+        != Some(true)
+    {
+        normalized.set_extension("docx");
+    }
+
+    Ok(path_display(&normalized))
+}
+
+/// Normalizes a folder inside a root ("" is the root itself), rejecting
+/// absolute paths and `..` so writes cannot escape the root.
+pub(crate) fn normalize_root_subfolder(
+    folder: Option<&str>,
+    default: &str,
+) -> CommandResult<String> {
+    let raw = folder
+        .map(|value| value.trim().replace('\\', "/"))
+        .filter(|value| !value.is_empty())
+        .unwrap_or_else(|| default.to_string());
+    optional_text(FOLDER, &raw, MAX_PATH_CHARS)?;
+    let mut parts = Vec::new();
+    for component in Path::new(raw.trim_matches('/')).components() {
+        match component {
+            Component::Normal(part) => {
+                let part = part.to_string_lossy().into_owned();
+                file_name_rules(FOLDER, &part)?;
+                parts.push(part);
+            }
+            Component::CurDir => {}
+            Component::ParentDir | Component::RootDir | Component::Prefix(_) => {
+                return Err(invalid(
+                    FOLDER,
+                    "validation.folderOutsideRoot",
+                    &[("folder", &raw)],
+                ))
+            }
+        }
+    }
+    Ok(parts.join("/"))
+}
+
+/// The source file of a capture, when it may be read. A source that exists
+/// must be an indexable document that passes `guard_path`, so a capture
+/// request cannot pull arbitrary files into a target. A source that does not
+/// exist on this machine is only recorded, never read, and yields `None`.
+pub(crate) fn readable_source_docx(
+    connection: &Connection,
+    source_path: &str,
+) -> CommandResult<Option<PathBuf>> {
+    input_path(SOURCE_PATH, source_path)?;
+    let path = Path::new(source_path);
+    if !path.exists() {
+        return Ok(None);
+    }
+    let guarded = guard_path(connection, path)?;
+    if !guarded.is_file() || !is_indexable_path(&guarded) {
+        return Err(invalid(
+            SOURCE_PATH,
+            "validation.sourceNotDocx",
+            &[("path", source_path)],
+        ));
+    }
+    Ok(Some(guarded))
+}
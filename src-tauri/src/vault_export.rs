@@ -0,0 +1,328 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::path::Path;
+
+use rusqlite::{params, Connection};
+
+use crate::errors::CommandError;
+use crate::markdown::render_docx_markdown;
+use crate::operations::OperationHandle;
+use crate::shared_roots::resolve_portable_link;
+use crate::util::{file_name_from_relative, folder_from_relative, write_export_file};
+use crate::CommandResult;
+
+/// Tag added to every note so the export can be found (or removed) as a set.
+const VAULT_TAG: &str = "blockfile";
+
+#[derive(Default)]
+pub(crate) struct VaultExportCounts {
+    pub note_count: usize,
+    pub link_count: usize,
+    pub skipped_files: Vec<String>,
+}
+
+struct VaultFile {
+    file_id: i64,
+    relative_path: String,
+    absolute_path: String,
+}
+
+/// Links between two files of the root, by relative path: `outgoing` for the
+/// file the link was made from, `incoming` for the file it points at.
+#[derive(Default)]
+struct FileConnections {
+    outgoing: BTreeMap<String, BTreeSet<String>>,
+    incoming: BTreeMap<String, BTreeSet<String>>,
+}
+
+impl FileConnections {
+    fn add(&mut self, from: &str, to: &str) {
+        if from == to {
+            return;
+        }
+        self.outgoing
+            .entry(from.to_string())
+            .or_default()
+            .insert(to.to_string());
+        self.incoming
+            .entry(to.to_string())
+            .or_default()
+            .insert(from.to_string());
+    }
+}
+
+fn load_vault_files(connection: &Connection, root_id: i64) -> CommandResult<Vec<VaultFile>> {
+    let mut statement = connection
+        .prepare(
+            "SELECT id, relative_path, absolute_path FROM files WHERE root_id = ?1 ORDER BY relative_path",
+        )
+        .map_err(|error| {
+            CommandError::database(format!("Could not prepare vault export file query: {error}"))
+        })?;
+    let rows = statement
+        .query_map(params![root_id], |row| {
+            Ok(VaultFile {
+                file_id: row.get(0)?,
+                relative_path: row.get(1)?,
+                absolute_path: row.get(2)?,
+            })
+        })
+        .map_err(|error| {
+            CommandError::database(format!("Could not run vault export file query: {error}"))
+        })?;
+    let mut files = Vec::new();
+    for row in rows {
+        files.push(row.map_err(|error| {
+            CommandError::database(format!("Could not parse vault export file row: {error}"))
+        })?);
+    }
+    Ok(files)
+}
+
+fn load_file_authors(connection: &Connection, file_id: i64) -> CommandResult<Vec<String>> {
+    let mut statement = connection
+        .prepare("SELECT text FROM authors WHERE file_id = ?1 ORDER BY author_order")
+        .map_err(|error| {
+            CommandError::database(format!(
+                "Could not prepare vault export author query: {error}"
+            ))
+        })?;
+    let rows = statement
+        .query_map(params![file_id], |row| row.get::<_, String>(0))
+        .map_err(|error| {
+            CommandError::database(format!("Could not run vault export author query: {error}"))
+        })?;
+    let mut authors = Vec::new();
+    for row in rows {
+        let author = row.map_err(|error| {
+            CommandError::database(format!("Could not parse vault export author row: {error}"))
+        })?;
+        if !authors.contains(&author) {
+            authors.push(author);
+        }
+    }
+    Ok(authors)
+}
+
+/// Collects file-to-file links: captures (source file -> capture target) and
+/// portable `blockfile://` links typed into documents.
+fn load_file_connections(
+    connection: &Connection,
+    root_id: i64,
+    files: &[VaultFile],
+) -> CommandResult<FileConnections> {
+    let by_absolute = files
+        .iter()
+        .map(|file| (file.absolute_path.as_str(), file.relative_path.as_str()))
+        .collect::<HashMap<&str, &str>>();
+    let by_relative = files
+        .iter()
+        .map(|file| file.relative_path.as_str())
+        .collect::<BTreeSet<&str>>();
+    let by_id = files
+        .iter()
+        .map(|file| (file.file_id, file.relative_path.as_str()))
+        .collect::<HashMap<i64, &str>>();
+    let mut connections = FileConnections::default();
+
+    let mut statement = connection
+        .prepare(
+            "SELECT DISTINCT source_path, target_relative_path FROM captures WHERE root_id = ?1",
+        )
+        .map_err(|error| {
+            CommandError::database(format!(
+                "Could not prepare vault export capture query: {error}"
+            ))
+        })?;
+    let rows = statement
+        .query_map(params![root_id], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+        })
+        .map_err(|error| {
+            CommandError::database(format!("Could not run vault export capture query: {error}"))
+        })?;
+    for row in rows {
+        let (source_path, target_relative_path) = row.map_err(|error| {
+            CommandError::database(format!("Could not parse vault export capture row: {error}"))
+        })?;
+        let target = target_relative_path.replace('\\', "/");
+        if let Some(source) = by_absolute.get(source_path.as_str()) {
+            if by_relative.contains(target.as_str()) {
+                connections.add(source, &target);
+            }
+        }
+    }
+
+    let mut statement = connection
+        .prepare(
+            "SELECT file_id, url FROM file_links WHERE root_id = ?1 AND url LIKE 'blockfile://%'",
+        )
+        .map_err(|error| {
+            CommandError::database(format!(
+                "Could not prepare vault export link query: {error}"
+            ))
+        })?;
+    let rows = statement
+        .query_map(params![root_id], |row| {
+            Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?))
+        })
+        .map_err(|error| {
+            CommandError::database(format!("Could not run vault export link query: {error}"))
+        })?;
+    for row in rows {
+        let (file_id, url) = row.map_err(|error| {
+            CommandError::database(format!("Could not parse vault export link row: {error}"))
+        })?;
+        let (Some(from), Ok(resolved)) =
+            (by_id.get(&file_id), resolve_portable_link(connection, &url))
+        else {
+            continue;
+        };
+        if by_relative.contains(resolved.relative_path.as_str()) {
+            connections.add(from, &resolved.relative_path);
+        }
+    }
+    Ok(connections)
+}
+
+/// Vault path of a file's note without the `.md`, which is what wikilinks use.
+fn note_link_path(relative_path: &str) -> String {
+    let relative_path = relative_path.replace('\\', "/");
+    match relative_path.rsplit_once('.') {
+        Some((stem, _)) if !stem.is_empty() && !stem.ends_with('/') => stem.to_string(),
+        _ => relative_path,
+    }
+}
+
+fn wikilink(relative_path: &str) -> String {
+    let target = note_link_path(relative_path);
+    let label = note_link_path(&file_name_from_relative(relative_path));
+    if target == label {
+        format!("[[{target}]]")
+    } else {
+        format!("[[{target}|{label}]]")
+    }
+}
+
+/// Obsidian tags cannot contain spaces or be purely numeric, so folder names
+/// are hyphenated and year folders are left out.
+fn folder_tags(relative_path: &str) -> Vec<String> {
+    let mut tags = vec![VAULT_TAG.to_string()];
+    for segment in folder_from_relative(relative_path)
+        .split('/')
+        .filter(|segment| !segment.trim().is_empty())
+    {
+        let tag = segment
+            .trim()
+            .to_lowercase()
+            .split(|character: char| !character.is_alphanumeric() && character != '_')
+            .filter(|part| !part.is_empty())
+            .collect::<Vec<&str>>()
+            .join("-");
+        let numeric = tag.chars().all(|character| character.is_ascii_digit());
+        if !tag.is_empty() && !numeric && !tags.contains(&tag) {
+            tags.push(tag);
+        }
+    }
+    tags
+}
+
+/// A JSON string is a valid double-quoted YAML scalar.
+fn yaml_string(value: &str) -> String {
+    serde_json::Value::String(value.to_string()).to_string()
+}
+
+fn yaml_list(key: &str, values: &[String]) -> String {
+    if values.is_empty() {
+        return format!("{key}: []\n");
+    }
+    let mut list = format!("{key}:\n");
+    for value in values {
+        list.push_str(&format!("  - {}\n", yaml_string(value)));
+    }
+    list
+}
+
+fn front_matter(title: &str, relative_path: &str, authors: &[String], tags: &[String]) -> String {
+    format!(
+        "---\ntitle: {}\nsource: {}\n{}{}---\n\n",
+        yaml_string(title),
+        yaml_string(relative_path),
+        yaml_list("authors", authors),
+        yaml_list("tags", tags)
+    )
+}
+
+fn links_section(connections: &FileConnections, relative_path: &str) -> (String, usize) {
+    let mut section = String::new();
+    let mut count = 0;
+    for (label, links) in [
+        ("Captured into", connections.outgoing.get(relative_path)),
+        ("Linked from", connections.incoming.get(relative_path)),
+    ] {
+        let Some(links) = links.filter(|links| !links.is_empty()) else {
+            continue;
+        };
+        section.push_str(&format!("\n**{label}:** "));
+        section.push_str(
+            &links
+                .iter()
+                .map(|link| wikilink(link))
+                .collect::<Vec<String>>()
+                .join(", "),
+        );
+        section.push('\n');
+        count += links.len();
+    }
+    if section.is_empty() {
+        return (section, 0);
+    }
+    (format!("\n---\n{section}"), count)
+}
+
+/// Writes one Markdown note per indexed file, mirroring the root's folders,
+/// with YAML front matter (authors and folder tags) and wikilinks to the files
+/// it is connected to. Files that fail to render are listed in
+/// `skipped_files` instead of aborting the export.
+pub(crate) fn write_obsidian_vault(
+    connection: &Connection,
+    root_id: i64,
+    output_dir: &Path,
+    operation: &mut OperationHandle,
+) -> CommandResult<VaultExportCounts> {
+    let files = load_vault_files(connection, root_id)?;
+    let connections = load_file_connections(connection, root_id, &files)?;
+    let mut counts = VaultExportCounts::default();
+
+    let total = files.len();
+    for (index, file) in files.iter().enumerate() {
+        operation.ensure_not_cancelled()?;
+        operation.update(
+            "writing",
+            index,
+            Some(total),
+            Some(file.relative_path.clone()),
+        );
+
+        let Ok(document) = render_docx_markdown(Path::new(&file.absolute_path)) else {
+            counts.skipped_files.push(file.relative_path.clone());
+            continue;
+        };
+        let title = note_link_path(&file_name_from_relative(&file.relative_path));
+        let authors = load_file_authors(connection, file.file_id)?;
+        let (links, link_count) = links_section(&connections, &file.relative_path);
+
+        let mut note = front_matter(
+            &title,
+            &file.relative_path,
+            &authors,
+            &folder_tags(&file.relative_path),
+        );
+        note.push_str(&document.markdown);
+        note.push_str(&links);
+        let note_path = output_dir.join(format!("{}.md", note_link_path(&file.relative_path)));
+        write_export_file(&note_path, &note)?;
+        counts.note_count += 1;
+        counts.link_count += link_count;
+    }
+    Ok(counts)
+}
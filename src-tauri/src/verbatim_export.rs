@@ -0,0 +1,204 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use roxmltree::Document;
+
+use crate::docx_capture::rewrite_docx_with_parts;
+use crate::docx_parse::{
+    attribute_value, has_tag, parse_document_paragraphs, read_docx_parts, read_style_map,
+};
+use crate::errors::CommandError;
+use crate::util::path_display;
+use crate::xml_write::apply_replacements;
+use crate::CommandResult;
+
+/// Verbatim's outline styles by heading level. Verbatim keeps Word's built-in
+/// heading style ids and names and gives them these aliases, so speech files
+/// recognise pasted paragraphs by style id alone.
+const VERBATIM_STYLES: [(&str, &str); 4] = [
+    ("Heading1", "Pocket"),
+    ("Heading2", "Hat"),
+    ("Heading3", "Block"),
+    ("Heading4", "Tag"),
+];
+
+/// Paragraph and run properties for each outline style, close to the
+/// defaults in Verbatim's template. A speech file's own definitions win when
+/// blocks are pasted into it; these only shape the exported file itself.
+const VERBATIM_STYLE_PROPERTIES: [(&str, &str); 4] = [
+    (
+        "<w:keepNext/><w:keepLines/><w:pageBreakBefore/><w:pBdr><w:top w:val=\"single\" w:sz=\"18\" w:space=\"1\" w:color=\"auto\"/><w:left w:val=\"single\" w:sz=\"18\" w:space=\"4\" w:color=\"auto\"/><w:bottom w:val=\"single\" w:sz=\"18\" w:space=\"1\" w:color=\"auto\"/><w:right w:val=\"single\" w:sz=\"18\" w:space=\"4\" w:color=\"auto\"/></w:pBdr><w:jc w:val=\"center\"/><w:outlineLvl w:val=\"0\"/>",
+        "<w:b/><w:bCs/><w:sz w:val=\"52\"/><w:szCs w:val=\"52\"/>",
+    ),
+    (
+        "<w:keepNext/><w:keepLines/><w:pageBreakBefore/><w:pBdr><w:top w:val=\"double\" w:sz=\"4\" w:space=\"1\" w:color=\"auto\"/><w:bottom w:val=\"double\" w:sz=\"4\" w:space=\"1\" w:color=\"auto\"/></w:pBdr><w:jc w:val=\"center\"/><w:outlineLvl w:val=\"1\"/>",
+        "<w:b/><w:bCs/><w:sz w:val=\"44\"/><w:szCs w:val=\"44\"/>",
+    ),
+    (
+        "<w:keepNext/><w:keepLines/><w:pageBreakBefore/><w:jc w:val=\"center\"/><w:outlineLvl w:val=\"2\"/>",
+        "<w:b/><w:bCs/><w:caps/><w:sz w:val=\"32\"/><w:szCs w:val=\"32\"/><w:u w:val=\"double\"/>",
+    ),
+    (
+        "<w:keepNext/><w:keepLines/><w:spacing w:before=\"40\" w:after=\"0\"/><w:outlineLvl w:val=\"3\"/>",
+        "<w:b/><w:bCs/><w:sz w:val=\"26\"/><w:szCs w:val=\"26\"/>",
+    ),
+];
+
+/// How many paragraphs went to each Verbatim outline level.
+#[derive(Default)]
+pub(crate) struct VerbatimOutlineCounts {
+    pub pocket_count: usize,
+    pub hat_count: usize,
+    pub block_count: usize,
+    pub tag_count: usize,
+}
+
+/// Index into `VERBATIM_STYLES` for a heading level. Levels deeper than 4
+/// have no Verbatim equivalent and become tags, the level cards are read at.
+fn verbatim_level_index(level: i64) -> usize {
+    usize::try_from(level.clamp(1, 4) - 1).unwrap_or(3)
+}
+
+fn verbatim_style_xml(style_id: &str, alias: &str, level_index: usize) -> String {
+    let (paragraph_properties, run_properties) = VERBATIM_STYLE_PROPERTIES[level_index];
+    format!(
+        "<w:style w:type=\"paragraph\" w:styleId=\"{style_id}\"><w:name w:val=\"heading {level}\"/><w:aliases w:val=\"{alias}\"/><w:basedOn w:val=\"Normal\"/><w:next w:val=\"Normal\"/><w:uiPriority w:val=\"9\"/><w:qFormat/><w:pPr>{paragraph_properties}</w:pPr><w:rPr>{run_properties}</w:rPr></w:style>",
+        level = level_index + 1
+    )
+}
+
+/// Points every heading paragraph at its Verbatim style. The paragraph's other
+/// properties are kept; its own style and outline level are dropped so the
+/// Verbatim style decides both.
+fn restyle_document(
+    document_xml: &str,
+    style_map: &HashMap<String, String>,
+) -> CommandResult<(String, VerbatimOutlineCounts)> {
+    let document = Document::parse(document_xml).map_err(|error| {
+        CommandError::parse(format!("Could not parse capture document.xml: {error}"))
+    })?;
+    let parsed = parse_document_paragraphs(&document, style_map);
+    let paragraph_nodes = document
+        .descendants()
+        .filter(|node| has_tag(*node, "p"))
+        .collect::<Vec<_>>();
+
+    let mut counts = VerbatimOutlineCounts::default();
+    let mut replacements = Vec::new();
+    for (paragraph, node) in parsed.iter().zip(paragraph_nodes) {
+        let Some(level) = paragraph.heading_level else {
+            continue;
+        };
+        let level_index = verbatim_level_index(level);
+        match level_index {
+            0 => counts.pocket_count += 1,
+            1 => counts.hat_count += 1,
+            2 => counts.block_count += 1,
+            _ => counts.tag_count += 1,
+        }
+
+        let style_id = VERBATIM_STYLES[level_index].0;
+        let mut properties = format!("<w:pPr><w:pStyle w:val=\"{style_id}\"/>");
+        match node.children().find(|child| has_tag(*child, "pPr")) {
+            Some(existing) => {
+                for child in existing.children().filter(|child| {
+                    child.is_element()
+                        && !has_tag(*child, "pStyle")
+                        && !has_tag(*child, "outlineLvl")
+                }) {
+                    properties.push_str(&document_xml[child.range()]);
+                }
+                properties.push_str("</w:pPr>");
+                replacements.push((existing.range(), properties));
+            }
+            None => {
+                // A heading always has runs, so its first child marks the end
+                // of the opening tag.
+                let Some(first_child) = node.first_child() else {
+                    continue;
+                };
+                properties.push_str("</w:pPr>");
+                let start = first_child.range().start;
+                replacements.push((start..start, properties));
+            }
+        }
+    }
+
+    Ok((apply_replacements(document_xml, replacements), counts))
+}
+
+/// Replaces any existing definitions of the heading styles with Verbatim's.
+fn restyle_styles(styles_xml: &str) -> CommandResult<String> {
+    let document = Document::parse(styles_xml).map_err(|error| {
+        CommandError::parse(format!("Could not parse capture styles.xml: {error}"))
+    })?;
+    let mut replacements = document
+        .descendants()
+        .filter(|node| has_tag(*node, "style"))
+        .filter(|node| {
+            attribute_value(*node, "styleId")
+                .map(|style_id| {
+                    VERBATIM_STYLES
+                        .iter()
+                        .any(|(verbatim_id, _)| *verbatim_id == style_id)
+                })
+                .unwrap_or(false)
+        })
+        .map(|node| (node.range(), String::new()))
+        .collect::<Vec<_>>();
+
+    let styles_close = styles_xml
+        .rfind("</w:styles>")
+        .ok_or_else(|| CommandError::parse("Could not find </w:styles> in capture styles.xml"))?;
+    let definitions = VERBATIM_STYLES
+        .iter()
+        .enumerate()
+        .map(|(level_index, (style_id, alias))| verbatim_style_xml(style_id, alias, level_index))
+        .collect::<String>();
+    replacements.push((styles_close..styles_close, definitions));
+    Ok(apply_replacements(styles_xml, replacements))
+}
+
+/// Copies the capture docx at `capture_path` to `output_path` with its
+/// headings restyled as Verbatim pockets, hats, blocks and tags.
+pub(crate) fn write_verbatim_outline(
+    capture_path: &Path,
+    output_path: &Path,
+) -> CommandResult<VerbatimOutlineCounts> {
+    let parts = read_docx_parts(capture_path)?.ok_or_else(|| {
+        CommandError::parse(format!(
+            "Capture docx '{}' has no document.xml",
+            path_display(capture_path)
+        ))
+    })?;
+    let styles_xml = parts.styles_xml.as_deref().ok_or_else(|| {
+        CommandError::parse(format!(
+            "Capture docx '{}' has no styles.xml",
+            path_display(capture_path)
+        ))
+    })?;
+    let style_map = read_style_map(Some(styles_xml));
+    let (document_xml, counts) = restyle_document(&parts.document_xml, &style_map)?;
+    let styles_xml = restyle_styles(styles_xml)?;
+
+    if let Some(parent) = output_path.parent() {
+        fs::create_dir_all(parent).map_err(|error| {
+            CommandError::io(format!(
+                "Could not create export folder '{}': {error}",
+                path_display(parent)
+            ))
+        })?;
+    }
+    fs::copy(capture_path, output_path).map_err(|error| {
+        CommandError::io(format!(
+            "Could not write export '{}': {error}",
+            path_display(output_path)
+        ))
+    })?;
+    let mut replacements = HashMap::new();
+    replacements.insert("word/document.xml".to_string(), document_xml.into_bytes());
+    replacements.insert("word/styles.xml".to_string(), styles_xml.into_bytes());
+    rewrite_docx_with_parts(output_path, &replacements)?;
+    Ok(counts)
+}
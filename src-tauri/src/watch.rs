@@ -0,0 +1,217 @@
+use std::collections::{BTreeSet, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::sync::{Mutex, OnceLock};
+use std::time::Duration;
+
+use notify::event::ModifyKind;
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use rusqlite::{params, Connection};
+use tauri::{AppHandle, Emitter};
+
+use crate::commands;
+use crate::db::open_database;
+use crate::document_parser::is_indexable_path;
+use crate::errors::CommandError;
+use crate::types::{RootFilesChangedEvent, WatchedRoot};
+use crate::util::{now_ms, path_display};
+use crate::CommandResult;
+
+pub(crate) const ROOT_FILES_CHANGED_EVENT: &str = "root-files-changed";
+
+/// Quiet period after the last change before re-indexing. Word saves through
+/// temp files and renames, so one save arrives as a burst of events.
+const WATCH_DEBOUNCE_MS: u64 = 1_500;
+
+/// Live watchers by canonical root path. Dropping a watcher closes its event
+/// channel, which ends the root's worker thread.
+static ROOT_WATCHERS: OnceLock<Mutex<HashMap<String, RecommendedWatcher>>> = OnceLock::new();
+
+fn root_watchers() -> &'static Mutex<HashMap<String, RecommendedWatcher>> {
+    ROOT_WATCHERS.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+fn is_active(root_path: &str) -> bool {
+    root_watchers()
+        .lock()
+        .map(|watchers| watchers.contains_key(root_path))
+        .unwrap_or(false)
+}
+
+/// Whether a change to `path` can affect the index: an indexable file that is
+/// not a Word lock file, or an extensionless path removed or renamed, which
+/// may be a folder of them.
+fn is_index_relevant(kind: &EventKind, path: &Path) -> bool {
+    let is_lock_file = path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .map(|name| name.starts_with("~$"))
+        .unwrap_or(false);
+    if is_lock_file {
+        return false;
+    }
+    match path.extension().and_then(|extension| extension.to_str()) {
+        Some(_) => is_indexable_path(path),
+        None => matches!(
+            kind,
+            EventKind::Remove(_) | EventKind::Modify(ModifyKind::Name(_))
+        ),
+    }
+}
+
+fn collect_changes(event: notify::Result<Event>, changed: &mut BTreeSet<PathBuf>) {
+    let Ok(event) = event else {
+        return;
+    };
+    if matches!(event.kind, EventKind::Access(_)) {
+        return;
+    }
+    for path in event.paths {
+        if is_index_relevant(&event.kind, &path) {
+            changed.insert(path);
+        }
+    }
+}
+
+/// Waits for a burst of changes to settle, then re-indexes the root. The
+/// index pass only reparses files whose size, time or hash moved, and emits
+/// the usual `index-progress` events.
+fn run_watch_worker(app: AppHandle, root_path: String, receiver: Receiver<notify::Result<Event>>) {
+    loop {
+        let Ok(first) = receiver.recv() else {
+            return;
+        };
+        let mut changed = BTreeSet::new();
+        collect_changes(first, &mut changed);
+        loop {
+            match receiver.recv_timeout(Duration::from_millis(WATCH_DEBOUNCE_MS)) {
+                Ok(event) => collect_changes(event, &mut changed),
+                Err(RecvTimeoutError::Timeout) => break,
+                Err(RecvTimeoutError::Disconnected) => return,
+            }
+        }
+        if changed.is_empty() {
+            continue;
+        }
+
+        let _ = app.emit(
+            ROOT_FILES_CHANGED_EVENT,
+            RootFilesChangedEvent {
+                root_path: root_path.clone(),
+                changed_paths: changed.iter().map(|path| path_display(path)).collect(),
+            },
+        );
+        if let Err(error) = commands::index_root_blocking(app.clone(), root_path.clone()) {
+            eprintln!("Indexing after changes in '{root_path}' failed: {error}");
+        }
+    }
+}
+
+/// Starts watching `root_path` unless a watcher is already running for it.
+pub(crate) fn start_watch(app: &AppHandle, root_path: &str) -> CommandResult<()> {
+    let mut watchers = root_watchers()
+        .lock()
+        .map_err(|_| CommandError::internal("Could not lock root watchers"))?;
+    if watchers.contains_key(root_path) {
+        return Ok(());
+    }
+
+    let (sender, receiver) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(sender)
+        .map_err(|error| CommandError::io(format!("Could not start a file watcher: {error}")))?;
+    watcher
+        .watch(Path::new(root_path), RecursiveMode::Recursive)
+        .map_err(|error| CommandError::io(format!("Could not watch '{root_path}': {error}")))?;
+
+    let worker_app = app.clone();
+    let worker_root = root_path.to_string();
+    std::thread::spawn(move || run_watch_worker(worker_app, worker_root, receiver));
+    watchers.insert(root_path.to_string(), watcher);
+    Ok(())
+}
+
+pub(crate) fn stop_watch(root_path: &str) {
+    if let Ok(mut watchers) = root_watchers().lock() {
+        watchers.remove(root_path);
+    }
+}
+
+/// Stops every live watcher, for when the watched roots change wholesale.
+pub(crate) fn stop_all_watches() {
+    if let Ok(mut watchers) = root_watchers().lock() {
+        watchers.clear();
+    }
+}
+
+pub(crate) fn load_watched_roots(connection: &Connection) -> CommandResult<Vec<WatchedRoot>> {
+    let mut statement = connection
+        .prepare(
+            "
+            SELECT r.path, w.enabled_at_ms
+            FROM root_watches w
+            JOIN roots r ON r.id = w.root_id
+            ORDER BY r.path
+            ",
+        )
+        .map_err(|error| {
+            CommandError::database(format!("Could not prepare watched roots query: {error}"))
+        })?;
+    let rows = statement
+        .query_map([], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)?))
+        })
+        .map_err(|error| {
+            CommandError::database(format!("Could not run watched roots query: {error}"))
+        })?;
+    let mut roots = Vec::new();
+    for row in rows {
+        let (root_path, enabled_at_ms) = row.map_err(|error| {
+            CommandError::database(format!("Could not parse watched root row: {error}"))
+        })?;
+        roots.push(WatchedRoot {
+            active: is_active(&root_path),
+            root_path,
+            enabled_at_ms,
+        });
+    }
+    Ok(roots)
+}
+
+pub(crate) fn store_root_watch(connection: &Connection, root_id: i64) -> CommandResult<()> {
+    connection
+        .execute(
+            "INSERT INTO root_watches(root_id, enabled_at_ms) VALUES(?1, ?2)
+             ON CONFLICT(root_id) DO NOTHING",
+            params![root_id, now_ms()],
+        )
+        .map_err(|error| CommandError::database(format!("Could not store root watch: {error}")))?;
+    Ok(())
+}
+
+pub(crate) fn delete_root_watch(connection: &Connection, root_id: i64) -> CommandResult<()> {
+    connection
+        .execute(
+            "DELETE FROM root_watches WHERE root_id = ?1",
+            params![root_id],
+        )
+        .map_err(|error| CommandError::database(format!("Could not remove root watch: {error}")))?;
+    Ok(())
+}
+
+/// Restarts the watchers saved by `watch_root`. A root that is gone or cannot
+/// be watched is logged and left for the user to re-enable.
+pub(crate) fn restore_root_watches(app: &AppHandle) {
+    let watched = open_database(app).and_then(|connection| load_watched_roots(&connection));
+    let watched = match watched {
+        Ok(watched) => watched,
+        Err(error) => {
+            eprintln!("Could not load watched roots: {error}");
+            return;
+        }
+    };
+    for root in watched {
+        if let Err(error) = start_watch(app, &root.root_path) {
+            eprintln!("Could not resume watching '{}': {error}", root.root_path);
+        }
+    }
+}
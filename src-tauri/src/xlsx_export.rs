@@ -0,0 +1,328 @@
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::Path;
+
+use rusqlite::{params, Connection};
+
+use crate::docx_parse::html_escape;
+use crate::errors::CommandError;
+use crate::util::{file_name_from_relative, folder_from_relative, path_display};
+use crate::CommandResult;
+
+/// Days between the spreadsheet epoch (1899-12-30) and the Unix epoch.
+const SPREADSHEET_UNIX_EPOCH_DAYS: f64 = 25_569.0;
+const MS_PER_DAY: f64 = 86_400_000.0;
+
+// Cell style indexes into `STYLES_XML`'s `cellXfs`.
+const STYLE_HEADER: usize = 1;
+const STYLE_DATE: usize = 2;
+const STYLE_COUNT: usize = 3;
+
+const STYLES_XML: &str = r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<styleSheet xmlns="http://schemas.openxmlformats.org/spreadsheetml/2006/main">
+<numFmts count="1"><numFmt numFmtId="164" formatCode="yyyy-mm-dd hh:mm"/></numFmts>
+<fonts count="2"><font><sz val="11"/><name val="Calibri"/></font><font><b/><sz val="11"/><name val="Calibri"/></font></fonts>
+<fills count="3"><fill><patternFill patternType="none"/></fill><fill><patternFill patternType="gray125"/></fill><fill><patternFill patternType="solid"><fgColor rgb="FFD9E1F2"/><bgColor indexed="64"/></patternFill></fill></fills>
+<borders count="2"><border><left/><right/><top/><bottom/><diagonal/></border><border><left/><right/><top/><bottom style="thin"><color auto="1"/></bottom><diagonal/></border></borders>
+<cellStyleXfs count="1"><xf numFmtId="0" fontId="0" fillId="0" borderId="0"/></cellStyleXfs>
+<cellXfs count="4"><xf numFmtId="0" fontId="0" fillId="0" borderId="0" xfId="0"/><xf numFmtId="0" fontId="1" fillId="2" borderId="1" xfId="0" applyFont="1" applyFill="1" applyBorder="1"/><xf numFmtId="164" fontId="0" fillId="0" borderId="0" xfId="0" applyNumberFormat="1"/><xf numFmtId="3" fontId="0" fillId="0" borderId="0" xfId="0" applyNumberFormat="1"/></cellXfs>
+<cellStyles count="1"><cellStyle name="Normal" xfId="0" builtinId="0"/></cellStyles>
+</styleSheet>"#;
+
+pub(crate) enum XlsxCell {
+    Text(String),
+    Count(i64),
+    Timestamp(i64),
+}
+
+pub(crate) struct XlsxSheet {
+    pub name: String,
+    pub headers: Vec<&'static str>,
+    pub column_widths: Vec<f64>,
+    pub rows: Vec<Vec<XlsxCell>>,
+}
+
+pub(crate) struct SnapshotCounts {
+    pub folder_count: usize,
+    pub file_count: usize,
+}
+
+/// `0` -> `A`, `26` -> `AA`.
+fn column_name(index: usize) -> String {
+    let mut name = String::new();
+    let mut remaining = index + 1;
+    while remaining > 0 {
+        let digit = (remaining - 1) % 26;
+        name.insert(0, (b'A' + digit as u8) as char);
+        remaining = (remaining - 1) / 26;
+    }
+    name
+}
+
+/// Escapes text for a cell, dropping control characters XML cannot carry.
+fn cell_text(value: &str) -> String {
+    html_escape(
+        &value
+            .chars()
+            .filter(|character| !character.is_control() || matches!(character, '\t' | '\n'))
+            .collect::<String>(),
+    )
+}
+
+fn cell_xml(reference: &str, cell: &XlsxCell) -> String {
+    match cell {
+        XlsxCell::Text(value) => format!(
+            "<c r=\"{reference}\" t=\"inlineStr\"><is><t xml:space=\"preserve\">{}</t></is></c>",
+            cell_text(value)
+        ),
+        XlsxCell::Count(value) => {
+            format!("<c r=\"{reference}\" s=\"{STYLE_COUNT}\"><v>{value}</v></c>")
+        }
+        XlsxCell::Timestamp(epoch_ms) if *epoch_ms > 0 => format!(
+            "<c r=\"{reference}\" s=\"{STYLE_DATE}\"><v>{}</v></c>",
+            SPREADSHEET_UNIX_EPOCH_DAYS + *epoch_ms as f64 / MS_PER_DAY
+        ),
+        XlsxCell::Timestamp(_) => String::new(),
+    }
+}
+
+/// Worksheet with a bold, frozen, filterable header row.
+fn sheet_xml(sheet: &XlsxSheet) -> String {
+    let last_column = column_name(sheet.headers.len().saturating_sub(1));
+    let mut xml = String::from(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?>\n<worksheet xmlns=\"http://schemas.openxmlformats.org/spreadsheetml/2006/main\">",
+    );
+    xml.push_str("<sheetViews><sheetView workbookViewId=\"0\"><pane ySplit=\"1\" topLeftCell=\"A2\" activePane=\"bottomLeft\" state=\"frozen\"/></sheetView></sheetViews>");
+    xml.push_str("<cols>");
+    for (index, width) in sheet.column_widths.iter().enumerate() {
+        xml.push_str(&format!(
+            "<col min=\"{0}\" max=\"{0}\" width=\"{width}\" customWidth=\"1\"/>",
+            index + 1
+        ));
+    }
+    xml.push_str("</cols><sheetData>");
+
+    xml.push_str("<row r=\"1\">");
+    for (index, header) in sheet.headers.iter().enumerate() {
+        xml.push_str(&format!(
+            "<c r=\"{}1\" t=\"inlineStr\" s=\"{STYLE_HEADER}\"><is><t>{}</t></is></c>",
+            column_name(index),
+            cell_text(header)
+        ));
+    }
+    xml.push_str("</row>");
+    for (row_index, row) in sheet.rows.iter().enumerate() {
+        let row_number = row_index + 2;
+        xml.push_str(&format!("<row r=\"{row_number}\">"));
+        for (column_index, cell) in row.iter().enumerate() {
+            xml.push_str(&cell_xml(
+                &format!("{}{row_number}", column_name(column_index)),
+                cell,
+            ));
+        }
+        xml.push_str("</row>");
+    }
+    xml.push_str(&format!(
+        "</sheetData><autoFilter ref=\"A1:{last_column}{}\"/></worksheet>",
+        sheet.rows.len() + 1
+    ));
+    xml
+}
+
+/// Writes a minimal XLSX package: inline strings, one shared stylesheet, and
+/// one worksheet per sheet in order.
+pub(crate) fn write_xlsx(output_path: &Path, sheets: &[XlsxSheet]) -> CommandResult<()> {
+    let mut content_types = String::from(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?>\n<Types xmlns=\"http://schemas.openxmlformats.org/package/2006/content-types\"><Default Extension=\"rels\" ContentType=\"application/vnd.openxmlformats-package.relationships+xml\"/><Default Extension=\"xml\" ContentType=\"application/xml\"/><Override PartName=\"/xl/workbook.xml\" ContentType=\"application/vnd.openxmlformats-officedocument.spreadsheetml.sheet.main+xml\"/><Override PartName=\"/xl/styles.xml\" ContentType=\"application/vnd.openxmlformats-officedocument.spreadsheetml.styles+xml\"/>",
+    );
+    let mut workbook = String::from(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?>\n<workbook xmlns=\"http://schemas.openxmlformats.org/spreadsheetml/2006/main\" xmlns:r=\"http://schemas.openxmlformats.org/officeDocument/2006/relationships\"><sheets>",
+    );
+    let mut workbook_relationships = String::from(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?>\n<Relationships xmlns=\"http://schemas.openxmlformats.org/package/2006/relationships\">",
+    );
+    for (index, sheet) in sheets.iter().enumerate() {
+        let number = index + 1;
+        content_types.push_str(&format!(
+            "<Override PartName=\"/xl/worksheets/sheet{number}.xml\" ContentType=\"application/vnd.openxmlformats-officedocument.spreadsheetml.worksheet+xml\"/>"
+        ));
+        workbook.push_str(&format!(
+            "<sheet name=\"{}\" sheetId=\"{number}\" r:id=\"rId{number}\"/>",
+            cell_text(&sheet.name)
+        ));
+        workbook_relationships.push_str(&format!(
+            "<Relationship Id=\"rId{number}\" Type=\"http://schemas.openxmlformats.org/officeDocument/2006/relationships/worksheet\" Target=\"worksheets/sheet{number}.xml\"/>"
+        ));
+    }
+    content_types.push_str("</Types>");
+    workbook.push_str("</sheets></workbook>");
+    workbook_relationships.push_str(&format!(
+        "<Relationship Id=\"rId{}\" Type=\"http://schemas.openxmlformats.org/officeDocument/2006/relationships/styles\" Target=\"styles.xml\"/></Relationships>",
+        sheets.len() + 1
+    ));
+
+    let mut parts = vec![
+        ("[Content_Types].xml".to_string(), content_types),
+        (
+            "_rels/.rels".to_string(),
+            "<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?>\n<Relationships xmlns=\"http://schemas.openxmlformats.org/package/2006/relationships\"><Relationship Id=\"rId1\" Type=\"http://schemas.openxmlformats.org/officeDocument/2006/relationships/officeDocument\" Target=\"xl/workbook.xml\"/></Relationships>".to_string(),
+        ),
+        ("xl/workbook.xml".to_string(), workbook),
+        ("xl/_rels/workbook.xml.rels".to_string(), workbook_relationships),
+        ("xl/styles.xml".to_string(), STYLES_XML.to_string()),
+    ];
+    for (index, sheet) in sheets.iter().enumerate() {
+        parts.push((
+            format!("xl/worksheets/sheet{}.xml", index + 1),
+            sheet_xml(sheet),
+        ));
+    }
+
+    if let Some(parent) = output_path.parent() {
+        fs::create_dir_all(parent).map_err(|error| {
+            CommandError::io(format!(
+                "Could not create export folder '{}': {error}",
+                path_display(parent)
+            ))
+        })?;
+    }
+    let file = File::create(output_path).map_err(|error| {
+        CommandError::io(format!(
+            "Could not create spreadsheet '{}': {error}",
+            path_display(output_path)
+        ))
+    })?;
+    let mut writer = zip::ZipWriter::new(file);
+    let options = zip::write::SimpleFileOptions::default()
+        .compression_method(zip::CompressionMethod::Deflated);
+    for (name, content) in parts {
+        writer.start_file(name.as_str(), options).map_err(|error| {
+            CommandError::io(format!("Could not add '{name}' to spreadsheet: {error}"))
+        })?;
+        writer.write_all(content.as_bytes()).map_err(|error| {
+            CommandError::io(format!("Could not write '{name}' to spreadsheet: {error}"))
+        })?;
+    }
+    writer
+        .finish()
+        .map_err(|error| CommandError::io(format!("Could not finish spreadsheet: {error}")))?;
+    Ok(())
+}
+
+/// Per-folder totals over the direct files of each folder.
+#[derive(Default)]
+struct FolderTotals {
+    file_count: i64,
+    heading_count: i64,
+    size: i64,
+    last_modified_ms: i64,
+}
+
+/// Builds the coverage-audit workbook for a root: a Folders sheet with
+/// per-folder totals and a Files sheet with one row per indexed file.
+pub(crate) fn snapshot_sheets(
+    connection: &Connection,
+    root_id: i64,
+) -> CommandResult<(Vec<XlsxSheet>, SnapshotCounts)> {
+    let mut statement = connection
+        .prepare(
+            "
+            SELECT
+              f.relative_path,
+              f.heading_count,
+              (SELECT COUNT(*) FROM authors a WHERE a.file_id = f.id),
+              f.size,
+              f.modified_ms
+            FROM files f
+            WHERE f.root_id = ?1
+            ORDER BY f.relative_path
+            ",
+        )
+        .map_err(|error| {
+            CommandError::database(format!("Could not prepare snapshot query: {error}"))
+        })?;
+    let rows = statement
+        .query_map(params![root_id], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, i64>(1)?,
+                row.get::<_, i64>(2)?,
+                row.get::<_, i64>(3)?,
+                row.get::<_, i64>(4)?,
+            ))
+        })
+        .map_err(|error| {
+            CommandError::database(format!("Could not run snapshot query: {error}"))
+        })?;
+
+    let mut folders: BTreeMap<String, FolderTotals> = BTreeMap::new();
+    let mut file_rows = Vec::new();
+    for row in rows {
+        let (relative_path, heading_count, author_count, size, modified_ms) =
+            row.map_err(|error| {
+                CommandError::database(format!("Could not parse snapshot row: {error}"))
+            })?;
+        let folder = folder_from_relative(&relative_path);
+        let totals = folders.entry(folder.clone()).or_default();
+        totals.file_count += 1;
+        totals.heading_count += heading_count;
+        totals.size += size;
+        totals.last_modified_ms = totals.last_modified_ms.max(modified_ms);
+
+        file_rows.push(vec![
+            XlsxCell::Text(folder),
+            XlsxCell::Text(file_name_from_relative(&relative_path)),
+            XlsxCell::Count(heading_count),
+            XlsxCell::Count(author_count),
+            XlsxCell::Count(size / 1024),
+            XlsxCell::Timestamp(modified_ms),
+            XlsxCell::Text(relative_path),
+        ]);
+    }
+
+    let counts = SnapshotCounts {
+        folder_count: folders.len(),
+        file_count: file_rows.len(),
+    };
+    let folder_rows = folders
+        .into_iter()
+        .map(|(folder, totals)| {
+            vec![
+                XlsxCell::Text(if folder.is_empty() {
+                    "(root)".to_string()
+                } else {
+                    folder
+                }),
+                XlsxCell::Count(totals.file_count),
+                XlsxCell::Count(totals.heading_count),
+                XlsxCell::Count(totals.size / 1024),
+                XlsxCell::Timestamp(totals.last_modified_ms),
+            ]
+        })
+        .collect();
+
+    let sheets = vec![
+        XlsxSheet {
+            name: "Folders".to_string(),
+            headers: vec!["Folder", "Files", "Headings", "Size (KB)", "Last modified"],
+            column_widths: vec![48.0, 10.0, 12.0, 12.0, 18.0],
+            rows: folder_rows,
+        },
+        XlsxSheet {
+            name: "Files".to_string(),
+            headers: vec![
+                "Folder",
+                "File",
+                "Headings",
+                "Authors",
+                "Size (KB)",
+                "Modified",
+                "Path",
+            ],
+            column_widths: vec![36.0, 40.0, 10.0, 10.0, 12.0, 18.0, 60.0],
+            rows: file_rows,
+        },
+    ];
+    Ok((sheets, counts))
+}
@@ -0,0 +1,348 @@
+use std::collections::{BTreeMap, HashSet};
+use std::ops::Range;
+
+use roxmltree::{Document, Node, NodeId, NodeType, NS_XML_URI};
+
+use crate::docx_parse::has_tag;
+
+const XML_DECLARATION: &str = "<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?>";
+const MARKUP_COMPATIBILITY_URI: &str =
+    "http://schemas.openxmlformats.org/markup-compatibility/2006";
+
+/// Markup-compatibility attributes whose values name namespace prefixes, so a
+/// fragment must keep those prefixes declared even if no element uses them.
+const PREFIX_LIST_ATTRIBUTES: [&str; 4] =
+    ["Ignorable", "Requires", "ProcessContent", "MustUnderstand"];
+
+fn escape_text(value: &str, output: &mut String) {
+    for character in value.chars() {
+        match character {
+            '&' => output.push_str("&amp;"),
+            '<' => output.push_str("&lt;"),
+            '>' => output.push_str("&gt;"),
+            '\r' => output.push_str("&#13;"),
+            _ => output.push(character),
+        }
+    }
+}
+
+fn escape_attribute(value: &str, output: &mut String) {
+    for character in value.chars() {
+        match character {
+            '&' => output.push_str("&amp;"),
+            '<' => output.push_str("&lt;"),
+            '"' => output.push_str("&quot;"),
+            '\t' => output.push_str("&#9;"),
+            '\n' => output.push_str("&#10;"),
+            '\r' => output.push_str("&#13;"),
+            _ => output.push(character),
+        }
+    }
+}
+
+/// The prefix to write for `uri` on `node`: `Some("")` for the default
+/// namespace, `None` when nothing in scope binds it.
+fn prefix_for<'a>(node: Node<'a, '_>, uri: &str, is_attribute: bool) -> Option<&'a str> {
+    if uri == NS_XML_URI {
+        return Some("xml");
+    }
+    if let Some(prefix) = node
+        .namespaces()
+        .find(|namespace| namespace.uri() == uri && namespace.name().is_some())
+        .and_then(|namespace| namespace.name())
+    {
+        return Some(prefix);
+    }
+    // Attributes never take the default namespace.
+    (!is_attribute && node.default_namespace() == Some(uri)).then_some("")
+}
+
+fn push_qualified_name(output: &mut String, prefix: Option<&str>, local: &str) {
+    if let Some(prefix) = prefix.filter(|prefix| !prefix.is_empty()) {
+        output.push_str(prefix);
+        output.push(':');
+    }
+    output.push_str(local);
+}
+
+/// Namespace declarations made on `node` itself rather than inherited.
+fn declared_here<'a>(node: Node<'a, '_>) -> BTreeMap<String, String> {
+    let parent = node.parent_element();
+    if let Some(parent) = parent {
+        // An element that declares nothing shares its parent's namespace list.
+        let (mut own, mut inherited) = (node.namespaces(), parent.namespaces());
+        if own.len() == inherited.len()
+            && own.next().map(|namespace| namespace as *const _)
+                == inherited.next().map(|namespace| namespace as *const _)
+        {
+            return BTreeMap::new();
+        }
+    }
+    node.namespaces()
+        .filter(|namespace| namespace.uri() != NS_XML_URI)
+        .filter(|namespace| {
+            parent.is_none_or(|parent| {
+                parent.lookup_namespace_uri(namespace.name()) != Some(namespace.uri())
+            })
+        })
+        .map(|namespace| {
+            (
+                namespace.name().unwrap_or_default().to_string(),
+                namespace.uri().to_string(),
+            )
+        })
+        .collect()
+}
+
+/// Every in-scope binding of `top` that its subtree relies on, so the subtree
+/// can be written out on its own.
+fn bindings_used_below(top: Node<'_, '_>) -> BTreeMap<String, String> {
+    let mut prefixes = HashSet::<String>::new();
+    for node in top.descendants().filter(|node| node.is_element()) {
+        if let Some(uri) = node.tag_name().namespace() {
+            if let Some(prefix) = prefix_for(node, uri, false) {
+                prefixes.insert(prefix.to_string());
+            }
+        }
+        for attribute in node.attributes() {
+            if let Some(uri) = attribute.namespace() {
+                if let Some(prefix) = prefix_for(node, uri, true) {
+                    prefixes.insert(prefix.to_string());
+                }
+                if uri == MARKUP_COMPATIBILITY_URI
+                    && PREFIX_LIST_ATTRIBUTES.contains(&attribute.name())
+                {
+                    prefixes.extend(attribute.value().split_whitespace().map(str::to_string));
+                }
+            }
+        }
+    }
+
+    prefixes
+        .into_iter()
+        .filter(|prefix| prefix != "xml")
+        .filter_map(|prefix| {
+            let lookup = (!prefix.is_empty()).then_some(prefix.as_str());
+            top.lookup_namespace_uri(lookup)
+                .map(|uri| (prefix.clone(), uri.to_string()))
+        })
+        .collect()
+}
+
+fn write_declarations(output: &mut String, declarations: &BTreeMap<String, String>) {
+    for (prefix, uri) in declarations {
+        if prefix.is_empty() {
+            output.push_str(" xmlns=\"");
+        } else {
+            output.push_str(" xmlns:");
+            output.push_str(prefix);
+            output.push_str("=\"");
+        }
+        escape_attribute(uri, output);
+        output.push('"');
+    }
+}
+
+fn write_node(
+    node: Node<'_, '_>,
+    declarations: &BTreeMap<String, String>,
+    skip: &HashSet<NodeId>,
+    output: &mut String,
+) {
+    if skip.contains(&node.id()) {
+        return;
+    }
+
+    match node.node_type() {
+        NodeType::Root => {
+            for child in node.children() {
+                write_node(child, &declared_here(child), skip, output);
+            }
+        }
+        NodeType::Text => escape_text(node.text().unwrap_or_default(), output),
+        NodeType::Comment => {
+            output.push_str("<!--");
+            output.push_str(node.text().unwrap_or_default());
+            output.push_str("-->");
+        }
+        NodeType::PI => {
+            if let Some(pi) = node.pi() {
+                output.push_str("<?");
+                output.push_str(pi.target);
+                if let Some(value) = pi.value {
+                    output.push(' ');
+                    output.push_str(value);
+                }
+                output.push_str("?>");
+            }
+        }
+        NodeType::Element => {
+            let tag_name = node.tag_name();
+            let prefix = tag_name
+                .namespace()
+                .and_then(|uri| prefix_for(node, uri, false));
+            let mut name = String::new();
+            push_qualified_name(&mut name, prefix, tag_name.name());
+
+            output.push('<');
+            output.push_str(&name);
+            write_declarations(output, declarations);
+            for attribute in node.attributes() {
+                output.push(' ');
+                let prefix = attribute
+                    .namespace()
+                    .and_then(|uri| prefix_for(node, uri, true));
+                push_qualified_name(output, prefix, attribute.name());
+                output.push_str("=\"");
+                escape_attribute(attribute.value(), output);
+                output.push('"');
+            }
+
+            if !node.has_children() {
+                output.push_str("/>");
+                return;
+            }
+            output.push('>');
+            for child in node.children() {
+                let child_declarations = if child.is_element() {
+                    declared_here(child)
+                } else {
+                    BTreeMap::new()
+                };
+                write_node(child, &child_declarations, skip, output);
+            }
+            output.push_str("</");
+            output.push_str(&name);
+            output.push('>');
+        }
+    }
+}
+
+/// Re-serializes a whole document, leaving out the `skip` nodes and their
+/// subtrees. Namespace declarations stay on the elements that made them.
+pub(crate) fn write_document(document: &Document<'_>, skip: &HashSet<NodeId>) -> String {
+    let mut output = String::with_capacity(document.input_text().len());
+    output.push_str(XML_DECLARATION);
+    write_node(document.root(), &BTreeMap::new(), skip, &mut output);
+    output
+}
+
+/// Serializes one element as a standalone fragment: it declares every
+/// namespace its subtree uses, so it stays well-formed wherever it is
+/// inserted.
+pub(crate) fn write_fragment(node: Node<'_, '_>) -> String {
+    let mut declarations = bindings_used_below(node);
+    declarations.extend(declared_here(node));
+    let mut output = String::new();
+    write_node(node, &declarations, &HashSet::new(), &mut output);
+    output
+}
+
+/// Rewrites attribute values in `xml`. `rewrite` gets each element, the
+/// attribute's local name and its value, and returns a replacement value.
+/// Returns `xml` unchanged when it does not parse.
+pub(crate) fn rewrite_attribute_values(
+    xml: &str,
+    rewrite: impl Fn(Node<'_, '_>, &str, &str) -> Option<String>,
+) -> String {
+    let Ok(document) = Document::parse(xml) else {
+        return xml.to_string();
+    };
+    let replacements = document
+        .descendants()
+        .filter(|node| node.is_element())
+        .flat_map(|node| {
+            node.attributes()
+                .filter_map(|attribute| {
+                    rewrite(node, attribute.name(), attribute.value())
+                        .map(|value| (attribute.range_value(), value))
+                })
+                .collect::<Vec<(Range<usize>, String)>>()
+        })
+        .collect::<Vec<_>>();
+    if replacements.is_empty() {
+        return xml.to_string();
+    }
+    apply_replacements(xml, replacements)
+}
+
+/// Swaps `source[range]` for each replacement, which must not overlap.
+pub(crate) fn apply_replacements(
+    source: &str,
+    mut replacements: Vec<(Range<usize>, String)>,
+) -> String {
+    replacements.sort_by_key(|(range, _)| range.start);
+    let mut output = String::with_capacity(source.len());
+    let mut cursor = 0;
+    for (range, replacement) in replacements {
+        output.push_str(&source[cursor..range.start]);
+        output.push_str(&replacement);
+        cursor = range.end;
+    }
+    output.push_str(&source[cursor..]);
+    output
+}
+
+/// The `w:body` children covering paragraphs `start..end` (indices into the
+/// document's `w:p` descendants), from the first block through the last. A
+/// heading range that starts or ends inside a table takes the whole table, so
+/// removing the blocks never splits an element.
+pub(crate) fn body_blocks_for_paragraphs<'a, 'input>(
+    paragraph_nodes: &[Node<'a, 'input>],
+    start: usize,
+    end: usize,
+) -> Vec<Node<'a, 'input>> {
+    let block_of = |node: Node<'a, 'input>| {
+        node.ancestors().find(|ancestor| {
+            ancestor
+                .parent_element()
+                .is_some_and(|parent| has_tag(parent, "body"))
+        })
+    };
+    let (Some(first), Some(last)) = (
+        paragraph_nodes.get(start).copied().and_then(block_of),
+        end.checked_sub(1)
+            .and_then(|index| paragraph_nodes.get(index))
+            .copied()
+            .and_then(block_of),
+    ) else {
+        return Vec::new();
+    };
+
+    let mut blocks = Vec::new();
+    let mut current = Some(first);
+    while let Some(node) = current {
+        if node.is_element() && !has_tag(node, "sectPr") {
+            blocks.push(node);
+        }
+        if node == last {
+            break;
+        }
+        current = node.next_sibling();
+    }
+    blocks
+}
+
+/// Paragraphs `start..end` written as standalone fragments, skipping any that
+/// sit inside another paragraph of the range (text boxes) so nothing is
+/// copied twice.
+pub(crate) fn paragraph_fragments(
+    paragraph_nodes: &[Node<'_, '_>],
+    start: usize,
+    end: usize,
+) -> Vec<String> {
+    let selected = paragraph_nodes
+        .get(start..end.min(paragraph_nodes.len()))
+        .unwrap_or_default();
+    let selected_ids = selected.iter().map(Node::id).collect::<HashSet<NodeId>>();
+    selected
+        .iter()
+        .filter(|node| {
+            !node
+                .ancestors()
+                .skip(1)
+                .any(|ancestor| selected_ids.contains(&ancestor.id()))
+        })
+        .map(|node| write_fragment(*node))
+        .collect()
+}
@@ -27,6 +27,7 @@ import type {
 } from "./lib/types";
 import {
   basename,
+  errorMessage,
   fallbackCopy,
   folderFromRelativePath,
   headingLevelLabel,
@@ -734,7 +735,7 @@ function App() {
         syncTreeViewportState();
       }, 0);
     } catch (error) {
-      setStatus(`Could not load index snapshot: ${String(error)}`);
+      setStatus(`Could not load index snapshot: ${errorMessage(error)}`);
       setSnapshot(null);
     } finally {
       setIsLoadingSnapshot(false);
@@ -785,7 +786,7 @@ function App() {
         syncTreeViewportState();
       }, 0);
     } catch (error) {
-      setStatus(`Could not load snapshots for all roots: ${String(error)}`);
+      setStatus(`Could not load snapshots for all roots: ${errorMessage(error)}`);
       setAllRootSnapshots([]);
     } finally {
       setIsLoadingSnapshot(false);
@@ -844,7 +845,7 @@ function App() {
       });
     } catch (error) {
       if (requestId === captureTargetsSeq) {
-        setStatus(`Could not load capture target files: ${String(error)}`);
+        setStatus(`Could not load capture target files: ${errorMessage(error)}`);
       }
     } finally {
       if (requestId === captureTargetsSeq) {
@@ -870,7 +871,7 @@ function App() {
       setCaptureTargetPreview(preview);
     } catch (error) {
       if (requestId === capturePreviewSeq) {
-        setStatus(`Could not load target preview: ${String(error)}`);
+        setStatus(`Could not load target preview: ${errorMessage(error)}`);
         setCaptureTargetPreview(null);
       }
     } finally {
@@ -906,7 +907,7 @@ function App() {
       setStatus(`Deleted heading from ${basename(preview.absolutePath)}`);
     } catch (error) {
       if (requestId === capturePreviewSeq) {
-        setStatus(`Could not delete heading: ${String(error)}`);
+        setStatus(`Could not delete heading: ${errorMessage(error)}`);
       }
     } finally {
       if (requestId === capturePreviewSeq) {
@@ -940,7 +941,7 @@ function App() {
       setStatus(`Moved heading under target in ${basename(preview.absolutePath)}`);
     } catch (error) {
       if (requestId === capturePreviewSeq) {
-        setStatus(`Could not move heading: ${String(error)}`);
+        setStatus(`Could not move heading: ${errorMessage(error)}`);
       }
     } finally {
       if (requestId === capturePreviewSeq) {
@@ -990,7 +991,7 @@ function App() {
       return true;
     } catch (error) {
       if (requestId === capturePreviewSeq) {
-        setStatus(`Could not add heading: ${String(error)}`);
+        setStatus(`Could not add heading: ${errorMessage(error)}`);
       }
       return false;
     } finally {
@@ -1047,7 +1048,7 @@ function App() {
         selectedPath = selected.find((entry: unknown): entry is string => typeof entry === "string") ?? null;
       }
     } catch (error) {
-      setStatus(`File picker failed: ${String(error)}`);
+      setStatus(`File picker failed: ${errorMessage(error)}`);
       return;
     }
 
@@ -1077,7 +1078,7 @@ function App() {
       await loadRoots();
       await loadSnapshot(rootPath);
     } catch (error) {
-      setStatus(`Indexing failed: ${String(error)}`);
+      setStatus(`Indexing failed: ${errorMessage(error)}`);
     } finally {
       setIndexProgress(null);
       setIsIndexing(false);
@@ -1108,7 +1109,7 @@ function App() {
       await loadRoots();
       await loadAllSnapshots();
     } catch (error) {
-      setStatus(`Bulk reindex failed: ${String(error)}`);
+      setStatus(`Bulk reindex failed: ${errorMessage(error)}`);
     } finally {
       setIndexProgress(null);
       setIsIndexing(false);
@@ -1148,7 +1149,7 @@ function App() {
       });
       selectedPaths = parseSelectedFolders(selected);
     } catch (error) {
-      setStatus(`Folder picker failed: ${String(error)}`);
+      setStatus(`Folder picker failed: ${errorMessage(error)}`);
     }
 
     if (selectedPaths.length === 0) {
@@ -1465,7 +1466,7 @@ function App() {
       setStatus(`Inserted into ${basename(inserted.capturePath)} [${inserted.marker}]`);
       return inserted;
     } catch (error) {
-      setStatus(`Insert failed: ${String(error)}`);
+      setStatus(`Insert failed: ${errorMessage(error)}`);
       return null;
     }
   };
@@ -1504,6 +1505,13 @@ function App() {
     }
     if (!inserted) return;
 
+    const rootPath = captureRootPath();
+    if (rootPath) {
+      await invokeTyped<void>("flush_capture_target", {
+        rootPath,
+        targetPath: inserted.targetRelativePath,
+      });
+    }
     await openPath(inserted.capturePath);
     setStatus(`Opened capture file: ${basename(inserted.capturePath)}`);
   };
@@ -1714,7 +1722,7 @@ function App() {
       })
         .catch((error) => {
           if (requestId === searchRequestSeq) {
-            setStatus(`Search failed: ${String(error)}`);
+            setStatus(`Search failed: ${errorMessage(error)}`);
           }
           return [] as SearchHit[];
         })
@@ -1762,7 +1770,7 @@ function App() {
       const invocation = searchDebatifyTags(query, { signal: controller.signal })
         .catch((error) => {
           if (requestId === remoteSearchRequestSeq && !isAbortError(error)) {
-            setStatus(`Debatify tag search failed: ${String(error)}`);
+            setStatus(`Debatify tag search failed: ${errorMessage(error)}`);
           }
           return [] as DebatifyTagHit[];
         })
@@ -1920,7 +1928,7 @@ function App() {
         stopIndexProgressListener = unlisten;
       })
       .catch((error) => {
-        setStatus(`Could not subscribe to indexing progress: ${String(error)}`);
+        setStatus(`Could not subscribe to indexing progress: ${errorMessage(error)}`);
       });
 
     window.addEventListener("resize", onResize);
@@ -1962,7 +1970,7 @@ function App() {
     queueMicrotask(syncTreeViewportState);
 
     void loadRoots().catch((error) => {
-      setStatus(`Failed to load roots: ${String(error)}`);
+      setStatus(`Failed to load roots: ${errorMessage(error)}`);
     });
   });
 
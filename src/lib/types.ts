@@ -1,9 +1,34 @@
+export type CommandErrorCode =
+  | "io"
+  | "parse"
+  | "invalid_docx"
+  | "not_found"
+  | "not_indexed"
+  | "target_locked"
+  | "access_denied"
+  | "validation"
+  | "database"
+  | "cancelled"
+  | "internal";
+
+export type CommandError = {
+  code: CommandErrorCode;
+  title: string;
+  message: string;
+  retryable: boolean;
+  path?: string | null;
+  field?: string | null;
+  operation?: string | null;
+};
+
 export type RootSummary = {
   path: string;
   fileCount: number;
   headingCount: number;
   addedAtMs: number;
   lastIndexedMs: number;
+  rootUid: string | null;
+  sharded: boolean;
 };
 
 export type AddRootResult = {
@@ -41,6 +66,8 @@ export type FileHeading = {
   level: number;
   text: string;
   copyText: string;
+  wordCount: number;
+  underlinedWordCount: number;
 };
 
 export type TaggedBlock = {
@@ -59,9 +86,45 @@ export type FilePreview = {
   f8Cites: TaggedBlock[];
 };
 
+export type OutlineNode = {
+  order: number;
+  level: number;
+  text: string;
+  wordCount: number;
+  paragraphCount: number;
+  children: OutlineNode[];
+};
+
+export type FileOutline = {
+  fileId: number;
+  fileName: string;
+  relativePath: string;
+  headings: OutlineNode[];
+};
+
+export type HeadingChange = {
+  kind: "added" | "removed" | "renamed";
+  level: number;
+  headingOrder: number;
+  previousText: string | null;
+  text: string | null;
+};
+
+export type HeadingHistoryRun = {
+  indexedAtMs: number;
+  changes: HeadingChange[];
+};
+
+export type FileHistory = {
+  fileId: number;
+  fileName: string;
+  relativePath: string;
+  runs: HeadingHistoryRun[];
+};
+
 export type SearchHit = {
   source: "lexical" | "semantic" | "hybrid";
-  kind: "heading" | "file" | "author";
+  kind: "heading" | "file" | "author" | "body" | "cite";
   fileId: number;
   fileName: string;
   relativePath: string;
@@ -70,6 +133,23 @@ export type SearchHit = {
   headingText: string | null;
   headingOrder: number | null;
   score: number;
+  snippet: string | null;
+  wordCount: number | null;
+  underlinedWordCount: number | null;
+};
+
+export type SearchPage = {
+  hits: SearchHit[];
+  nextCursor: string | null;
+  totalEstimate: number;
+};
+
+export type SearchPageOptions = {
+  minLevel?: number | null;
+  maxLevel?: number | null;
+  kind?: string[] | null;
+  offset?: number | null;
+  cursor?: string | null;
 };
 
 export type IndexStats = {
@@ -78,12 +158,562 @@ export type IndexStats = {
   skipped: number;
   removed: number;
   headingsExtracted: number;
+  conflictsDetected: number;
+  excluded: number;
+  elapsedMs: number;
+};
+
+export type FileStatsEntry = {
+  fileId: number;
+  fileName: string;
+  relativePath: string;
+  wordCount: number;
+  cardCount: number;
+  citeCount: number;
+};
+
+export type SearchSettings = {
+  headingWeight: number;
+  authorWeight: number;
+  fileWeight: number;
+  prefixMinChars: number;
+  fuzzyMinChars: number;
+  recallScoreBase: number;
+  citeScoreBase: number;
+  bodyScoreBase: number;
+  prefixScoreBase: number;
+  fuzzyScoreBase: number;
+};
+
+export type RootSettings = {
+  defaultCaptureTarget: string;
+  insertTitle: boolean;
+  insertSeparator: boolean;
+  separatorStyle: string | null;
+  headingLevelShift: number;
+  nestChildCaptures: boolean;
+};
+
+export type CaptureTargetSettings = {
+  tableOfContents: boolean;
+};
+
+export type AuthorEntry = {
+  surnames: string;
+  cardCount: number;
+  fileCount: number;
+  firstYear: number | null;
+  lastYear: number | null;
+};
+
+export type RootStats = {
+  rootPath: string;
+  fileCount: number;
+  wordCount: number;
+  cardCount: number;
+  citeCount: number;
+  averageCardWords: number;
+  largestFiles: FileStatsEntry[];
+};
+
+export type IndexQueueStatus = {
+  running: string | null;
+  pending: string[];
+  completed: number;
+};
+
+export type IndexQueueProgress = {
+  rootPath: string;
+  error: CommandError | null;
+  completed: number;
+  total: number;
+};
+
+export type IndexQueueRootResult = {
+  rootPath: string;
+  stats: IndexStats | null;
+  error: CommandError | null;
+};
+
+export type IndexQueueSummary = {
+  indexed: number;
+  failed: number;
   elapsedMs: number;
+  results: IndexQueueRootResult[];
+};
+
+export type SyncConflictCopy = {
+  fileId: number;
+  relativePath: string;
+  modifiedMs: number;
+  size: number;
+};
+
+export type SyncConflictGroup = {
+  originalRelativePath: string;
+  original: SyncConflictCopy | null;
+  copies: SyncConflictCopy[];
+  newestRelativePath: string;
+};
+
+export type SyncConflictResolution = {
+  originalRelativePath: string;
+  keptFromRelativePath: string;
+  archivedPaths: string[];
+};
+
+export type BlockPackSelection = {
+  rootPath: string;
+  fileIds?: number[];
+  folderPaths?: string[];
+  captureTarget?: string | null;
+};
+
+export type BlockPackExportResult = {
+  packPath: string;
+  fileCount: number;
+  headingCount: number;
+  captureTarget: string | null;
+};
+
+export type BlockPackImportResult = {
+  rootPath: string;
+  writtenPaths: string[];
+  skippedPaths: string[];
+  index: IndexStats;
+  tagCount: number;
+};
+
+export type CutQueueStatus = "todo" | "in-progress" | "done";
+
+export type CutQueueItem = {
+  id: number;
+  fileId: number | null;
+  fileName: string;
+  relativePath: string;
+  headingOrder: number | null;
+  headingText: string | null;
+  status: CutQueueStatus;
+  assignee: string;
+  note: string;
+  createdAtMs: number;
+  updatedAtMs: number;
+};
+
+export type NearDuplicateHeading = {
+  fileId: number;
+  fileName: string;
+  relativePath: string;
+  headingOrder: number;
+  level: number;
+  text: string;
+};
+
+export type SpeechLengthEstimate = {
+  wordsPerMinute: number;
+  wordCount: number;
+  underlinedWordCount: number;
+  seconds: number;
+  underlinedSeconds: number;
+};
+
+export type SimilarCard = {
+  fileId: number;
+  fileName: string;
+  relativePath: string;
+  absolutePath: string;
+  headingOrder: number;
+  headingLevel: number;
+  headingText: string;
+  similarity: number;
+  preview: string | null;
+};
+
+export type NearDuplicateGroup = {
+  variantCount: number;
+  fileCount: number;
+  minSimilarity: number;
+  variants: string[];
+  headings: NearDuplicateHeading[];
+};
+
+export type NearDuplicateReport = {
+  rootPath: string;
+  threshold: number;
+  headingsCompared: number;
+  groups: NearDuplicateGroup[];
+};
+
+export type CitationAuditItem = {
+  fileId: number;
+  fileName: string;
+  relativePath: string;
+  headingOrder: number;
+  headingLevel: number;
+  headingText: string;
+  citeText: string | null;
+  missing: Array<"author" | "year" | "url">;
+  isCaptureFile: boolean;
+};
+
+export type CitationAuditReport = {
+  rootPath: string;
+  filesChecked: number;
+  sectionsChecked: number;
+  items: CitationAuditItem[];
+};
+
+export type LinkStatus = "ok" | "broken" | "unreachable";
+
+export type LinkStatusEntry = {
+  url: string;
+  linkText: string;
+  paragraphOrder: number;
+  status: LinkStatus | null;
+  statusCode: number | null;
+  error: string | null;
+  checkedAtMs: number | null;
+};
+
+export type FileLinkReport = {
+  fileId: number;
+  fileName: string;
+  relativePath: string;
+  links: LinkStatusEntry[];
+};
+
+export type LinkReport = {
+  rootPath: string;
+  running: boolean;
+  totalLinks: number;
+  checkedLinks: number;
+  brokenLinks: number;
+  files: FileLinkReport[];
+};
+
+export type OperationProgress = {
+  operationId: string;
+  kind: "index" | "lexical-rebuild" | "block-pack-export" | "block-pack-import" | "link-check";
+  rootPath: string | null;
+  phase: string;
+  phaseLabel: string;
+  status: "running" | "complete" | "failed" | "cancelled";
+  percent: number | null;
+  processed: number;
+  total: number | null;
+  message: string | null;
+  cancellable: boolean;
+  elapsedMs: number;
+  error: CommandError | null;
+};
+
+export type RootCaptureTarget = {
+  rootPath: string;
+  targetRelativePath: string;
+};
+
+export type SessionState = {
+  lastRootPath: string | null;
+  lastFileId: number | null;
+  lastRelativePath: string | null;
+  lastHeadingOrder: number | null;
+  captureTargets: RootCaptureTarget[];
+  updatedAtMs: number;
+};
+
+export type OutlineExportResult = {
+  outputPath: string;
+  folderCount: number;
+  fileCount: number;
+  headingCount: number;
+};
+
+export type MarkdownExportResult = {
+  outputPath: string;
+  headingCount: number;
+  paragraphCount: number;
+};
+
+export type PdfExportResult = {
+  outputPath: string;
+  pageCount: number;
+  paragraphCount: number;
+};
+
+export type TextMarkerOptions = {
+  underline?: string | null;
+  highlight?: string | null;
+};
+
+export type MarkedTextExportResult = {
+  outputPath: string;
+  paragraphCount: number;
+  text: string;
+};
+
+export type SiteExportResult = {
+  outputPath: string;
+  pageCount: number;
+  folderCount: number;
+  headingCount: number;
+  skippedFiles: string[];
+};
+
+export type VaultExportResult = {
+  outputPath: string;
+  noteCount: number;
+  linkCount: number;
+  skippedFiles: string[];
+};
+
+export type CsvExportResult = {
+  outputPath: string;
+  rowCount: number;
+};
+
+export type SnapshotXlsxResult = {
+  outputPath: string;
+  folderCount: number;
+  fileCount: number;
+};
+
+export type IndexExportResult = {
+  outputPath: string;
+  format: "json" | "csv";
+  fileCount: number;
+  headingCount: number;
+  authorCount: number;
+  citeCount: number;
+};
+
+export type IndexImportResult = {
+  rootPath: string;
+  fileCount: number;
+  headingCount: number;
+  authorCount: number;
+  citeCount: number;
+  skippedPaths: string[];
+};
+
+export type IndexFeedResult = {
+  outputPath: string;
+  format: "atom" | "json";
+  runCount: number;
+  entryCount: number;
+};
+
+export type TagImportSkip = {
+  row: number;
+  reason: string;
+};
+
+export type TagImportResult = {
+  rowCount: number;
+  fileTagCount: number;
+  headingTagCount: number;
+  skipped: TagImportSkip[];
+};
+
+export type ItemTag = {
+  tag: string;
+  headingText: string | null;
+};
+
+export type TagSummary = {
+  tag: string;
+  fileCount: number;
+  headingCount: number;
+};
+
+export type HeadingRef = {
+  fileId: number;
+  headingOrder: number;
+};
+
+export type MergeDocumentSkip = {
+  fileId: number;
+  headingOrder: number;
+  reason: string;
+};
+
+export type MergeDocumentResult = {
+  outputPath: string;
+  sectionCount: number;
+  skipped: MergeDocumentSkip[];
+};
+
+export type CaptureReplaySkip = {
+  sourcePath: string;
+  sectionTitle: string;
+  reason: string;
+};
+
+export type CaptureReplayResult = {
+  replayedCount: number;
+  duplicateCount: number;
+  skipped: CaptureReplaySkip[];
+  targetPaths: string[];
+};
+
+export type GoogleDriveStatus = {
+  configured: boolean;
+  connected: boolean;
+  clientId: string | null;
+};
+
+export type DriveUploadResult = {
+  driveFileId: string;
+  name: string;
+  webViewLink: string | null;
+  converted: boolean;
+};
+
+export type LocalApiStatus = {
+  running: boolean;
+  enabled: boolean;
+  port: number;
+  baseUrl: string;
+  eventsUrl: string;
+  token: string | null;
+};
+
+export type PandocStatus = {
+  available: boolean;
+  binaryPath: string | null;
+  version: string | null;
+  configuredPath: string | null;
+  exportFormats: string[];
+};
+
+export type LocaleOption = {
+  code: string;
+  name: string;
+};
+
+export type LocaleSettings = {
+  locale: string;
+  available: LocaleOption[];
+};
+
+export type UsageCounter = {
+  metric: string;
+  count: number;
+  totalMs: number;
+  maxMs: number;
+  firstAtMs: number;
+  lastAtMs: number;
+};
+
+export type UsageMetrics = {
+  enabled: boolean;
+  counters: UsageCounter[];
+};
+
+export type PandocExportResult = {
+  outputPath: string;
+  format: string;
+};
+
+export type VerbatimExportResult = {
+  outputPath: string;
+  pocketCount: number;
+  hatCount: number;
+  blockCount: number;
+  tagCount: number;
+};
+
+export type ProfileInfo = {
+  id: string;
+  name: string;
+  createdAtMs: number;
+  active: boolean;
+};
+
+export type ExternalOpenResult = {
+  absolutePath: string;
+  headingText: string | null;
+};
+
+export type RootIntake = {
+  rootPath: string;
+  intakePath: string;
+  targetFolder: string;
+  lastMovedAtMs: number | null;
+};
+
+export type IntakeMovedEvent = {
+  rootPath: string;
+  movedPaths: string[];
+};
+
+export type WatchedRoot = {
+  rootPath: string;
+  enabledAtMs: number;
+  active: boolean;
+};
+
+export type RootFilesChangedEvent = {
+  rootPath: string;
+  changedPaths: string[];
+};
+
+export type DocumentImportResult = {
+  docxPath: string;
+  relativePath: string;
+  title: string;
+  headingCount: number;
+  paragraphCount: number;
+  linkCount: number;
+  index: IndexStats;
+};
+
+export type AllowedLocation = {
+  path: string;
+  addedAtMs: number;
+};
+
+export type RootRelocationResult = {
+  rootPath: string;
+  previousPath: string;
+  fileCount: number;
+  sampledCount: number;
+  foundCount: number;
+};
+
+export type PathMapping = {
+  foreignRootPath: string;
+  rootPath: string;
+  rootUid: string | null;
+  createdAtMs: number;
+};
+
+export type ResolvedSharedPath = {
+  rootPath: string;
+  rootUid: string | null;
+  relativePath: string;
+  absolutePath: string;
+  exists: boolean;
+  fileId: number | null;
+  headingOrder: number | null;
+};
+
+export type SearchExportFilters = {
+  rootPath?: string | null;
+  limit?: number | null;
+  headingLevels?: number[] | null;
+  pathPrefix?: string | null;
+};
+
+export type SearchCompileResult = {
+  outputPath: string;
+  fileCount: number;
+  sectionCount: number;
+  nestedCount: number;
 };
 
 export type IndexProgress = {
   rootPath: string;
-  phase: "discovering" | "indexing" | "cleaning" | "complete";
+  phase: "discovering" | "indexing" | "cleaning" | "complete" | "cancelled";
   discovered: number;
   changed: number;
   processed: number;
@@ -118,6 +748,17 @@ export type CaptureInsertResult = {
   targetRelativePath: string;
 };
 
+export type CaptureBatchItem = {
+  sourcePath: string;
+  headingOrder: number;
+};
+
+export type CaptureBatchResult = {
+  capturePath: string;
+  markers: string[];
+  targetRelativePath: string;
+};
+
 export type CaptureTarget = {
   relativePath: string;
   absolutePath: string;
@@ -133,6 +774,25 @@ export type CaptureTargetPreview = {
   headings: FileHeading[];
 };
 
+export type CaptureRecord = {
+  id: number;
+  sourcePath: string;
+  sourceFileName: string;
+  sectionTitle: string;
+  targetRelativePath: string;
+  headingLevel: number | null;
+  createdAtMs: number;
+  marker: string;
+};
+
+export type CaptureTrashEntry = {
+  id: number;
+  targetRelativePath: string;
+  headingLevel: number;
+  headingText: string;
+  deletedAtMs: number;
+};
+
 export type SidePreview = {
   title: string;
   subTitle?: string;
@@ -141,3 +801,14 @@ export type SidePreview = {
   headingLevel?: number | null;
   kind?: "heading" | "f8" | "author";
 };
+
+export type ActivityFile = {
+  fileId: number;
+  fileName: string;
+  relativePath: string;
+  absolutePath: string;
+  rootPath: string;
+  openCount: number;
+  lastOpenedMs: number | null;
+  pinned: boolean;
+};
@@ -1,5 +1,6 @@
 import { invoke } from "@tauri-apps/api/core";
 import type {
+  CommandError,
   FileHeading,
   FolderEntry,
   IndexSnapshot,
@@ -10,6 +11,15 @@ import { HEADING_LEVEL_LABELS } from "./constants";
 export const invokeTyped = <T,>(command: string, args?: Record<string, unknown>) =>
   invoke<T>(command, args);
 
+export const isCommandError = (error: unknown): error is CommandError =>
+  typeof error === "object" &&
+  error !== null &&
+  typeof (error as CommandError).code === "string" &&
+  typeof (error as CommandError).message === "string";
+
+export const errorMessage = (error: unknown) =>
+  isCommandError(error) ? error.message : String(error);
+
 export const basename = (path: string) => {
   const segments = path.split(/[/\\]/).filter(Boolean);
   return segments.length > 0 ? segments[segments.length - 1] : path;